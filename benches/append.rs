@@ -0,0 +1,63 @@
+// vim: set ts=4 sw=4 et :
+
+//! Loading rows into a `Table`: row at a time through the `TableAppender` and a column at a time.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::allocator;
+use dbkit::schema::Schema;
+use dbkit::table::{Table, TableAppender};
+use dbkit::types::{Int64, UInt32};
+
+const ROWS: usize = 10000;
+
+const STATUS: [&'static str; 4] = ["pending", "shipped", "delivered", "returned"];
+
+fn orders() -> Schema {
+    Schema::parse_ddl("id INT64 NOT NULL, customer UINT32 NOT NULL, price FLOAT64, status TEXT").unwrap()
+}
+
+#[bench]
+fn appender_rows(b: &mut Bencher) {
+    let schema = orders();
+    b.bytes = (ROWS * (8 + 4 + 8)) as u64;
+
+    b.iter(|| {
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            for row in 0 .. ROWS {
+                let price = if row % 10 == 0 { None } else { Some(row as f64 * 0.25) };
+                appender.add_row().unwrap()
+                    .set(row as i64).unwrap()
+                    .set((row % 1000) as u32).unwrap()
+                    .set(price).unwrap()
+                    .set(STATUS[row % STATUS.len()]).unwrap();
+            }
+            appender.done().unwrap();
+        }
+
+        black_box(table)
+    });
+}
+
+#[bench]
+fn append_column_slice(b: &mut Bencher) {
+    let schema = Schema::parse_ddl("id INT64 NOT NULL, customer UINT32 NOT NULL").unwrap();
+    let ids: Vec<i64> = (0 .. ROWS as i64).collect();
+    let customers: Vec<u32> = (0 .. ROWS as u32).map(|v| v % 1000).collect();
+    b.bytes = (ROWS * (8 + 4)) as u64;
+
+    b.iter(|| {
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        table.append_column_slice::<Int64>(0, &ids).unwrap();
+        table.append_column_slice::<UInt32>(1, &customers).unwrap();
+        black_box(table)
+    });
+}
@@ -0,0 +1,97 @@
+// vim: set ts=4 sw=4 et :
+
+//! Predicate and expression evaluation over an in memory block.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::allocator;
+use dbkit::block::{Block, View};
+use dbkit::exec::ExecContext;
+use dbkit::expression::Expr;
+use dbkit::expression::arithmetic::Arithmetic;
+use dbkit::expression::comparison::CompareOp;
+use dbkit::expression::literal::Literal;
+use dbkit::operation::{CursorChunk, Filter, Operation, ScanPredicate, ScanView};
+use dbkit::schema::Schema;
+use dbkit::types::Value;
+use dbkit::util::collation::Collation;
+use dbkit::util::copy_value::set_column_value;
+use dbkit::util::math::{ArithOp, OverflowPolicy};
+
+const ROWS: usize = 65536;
+
+const STATUS: [&'static str; 4] = ["pending", "shipped", "Delivered", "returned"];
+
+fn orders() -> Block<'static> {
+    let schema = Schema::parse_ddl("id INT64 NOT NULL, quantity INT64, status TEXT NOT NULL").unwrap();
+    let mut block = Block::new(&allocator::GLOBAL, &schema);
+    block.add_rows(ROWS).unwrap();
+
+    for row in 0 .. ROWS {
+        let quantity = if row % 10 == 0 { None } else { Some((row * 7 % 100) as i64) };
+        set_column_value(&mut block, 0, row, &(row as i64)).unwrap();
+        set_column_value(&mut block, 1, row, &quantity).unwrap();
+        set_column_value(&mut block, 2, row, &STATUS[row % STATUS.len()]).unwrap();
+    }
+
+    block
+}
+
+/// Drain the operation, returns the number of output rows
+fn run<'a>(op: &Operation<'a>) -> usize {
+    let mut cursor = op.bind(&ExecContext::default()).unwrap();
+    let mut rows = 0;
+    while let CursorChunk::Next(view) = cursor.next(1024).unwrap() {
+        rows += view.rows();
+    }
+    rows
+}
+
+#[bench]
+fn filter_int64(b: &mut Bencher) {
+    let block = orders();
+    let op = Filter::new(ScanPredicate::new(1, CompareOp::LT, 20i64), ScanView::new(&block, None));
+
+    b.iter(|| black_box(run(&op)));
+}
+
+#[bench]
+fn filter_text(b: &mut Bencher) {
+    let block = orders();
+    let op = Filter::new(ScanPredicate::new(2, CompareOp::EQ, "shipped"), ScanView::new(&block, None));
+
+    b.iter(|| black_box(run(&op)));
+}
+
+#[bench]
+fn filter_text_nocase(b: &mut Bencher) {
+    let block = orders();
+    let predicate = ScanPredicate::new(2, CompareOp::EQ, "delivered").with_collation(Collation::NOCASE);
+    let op = Filter::new(predicate, ScanView::new(&block, None));
+
+    b.iter(|| black_box(run(&op)));
+}
+
+#[bench]
+fn arithmetic_add(b: &mut Bencher) {
+    let schema = Schema::parse_ddl("a INT64 NOT NULL, b INT64").unwrap();
+    let mut block = Block::new(&allocator::GLOBAL, &schema);
+    block.add_rows(ROWS).unwrap();
+    for row in 0 .. ROWS {
+        let v = if row % 10 == 0 { None } else { Some(row as i64) };
+        set_column_value(&mut block, 0, row, &(row as i64)).unwrap();
+        set_column_value(&mut block, 1, row, &v).unwrap();
+    }
+
+    // Expressions are bound against the input schema directly; the inputs are placeholders
+    let expr = Arithmetic::new(ArithOp::ADD, Literal::new("a", Value::INT64(0)), Literal::new("b", Value::INT64(0)))
+        .with_overflow(OverflowPolicy::NULL);
+    let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+
+    b.iter(|| black_box(bound.evaluate(&block, ROWS).unwrap()));
+}
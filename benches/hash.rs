@@ -0,0 +1,107 @@
+// vim: set ts=4 sw=4 et :
+
+//! Hash aggregation and hash join: the `HashAggregate` and `HashJoin` operators over in memory
+//! blocks, and the key hashing kernel they share.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::allocator;
+use dbkit::block::{Block, View};
+use dbkit::exec::ExecContext;
+use dbkit::operation::{CursorChunk, HashAggregate, HashJoin, Operation, ScanView};
+use dbkit::plan::{Aggregate, AggregateFunc, JoinKind};
+use dbkit::schema::Schema;
+use dbkit::util::copy_value::set_column_value;
+use dbkit::util::row_hash::hash_rows;
+
+const ROWS: usize = 65536;
+const CUSTOMERS: usize = 1000;
+
+const ORDERS: &'static str = "customer INT64 NOT NULL, region TEXT NOT NULL, price FLOAT64 NOT NULL";
+
+fn orders() -> Block<'static> {
+    let schema = Schema::parse_ddl(ORDERS).unwrap();
+    let mut block = Block::new(&allocator::GLOBAL, &schema);
+    block.add_rows(ROWS).unwrap();
+
+    for row in 0 .. ROWS {
+        let customer = (row * 7919 % CUSTOMERS) as i64;
+        set_column_value(&mut block, 0, row, &customer).unwrap();
+        set_column_value(&mut block, 1, row, &format!("region-{}", customer % 16)).unwrap();
+        set_column_value(&mut block, 2, row, &(row as f64 * 0.5)).unwrap();
+    }
+
+    block
+}
+
+fn customers() -> Block<'static> {
+    let schema = Schema::parse_ddl("id INT64 NOT NULL, customer_region TEXT NOT NULL").unwrap();
+    let mut block = Block::new(&allocator::GLOBAL, &schema);
+    block.add_rows(CUSTOMERS).unwrap();
+
+    for row in 0 .. CUSTOMERS {
+        set_column_value(&mut block, 0, row, &(row as i64)).unwrap();
+        set_column_value(&mut block, 1, row, &format!("region-{}", row % 16)).unwrap();
+    }
+
+    block
+}
+
+/// Drain the operation, returns the number of output rows
+fn run<'a>(op: &Operation<'a>) -> usize {
+    let mut cursor = op.bind(&ExecContext::default()).unwrap();
+    let mut rows = 0;
+    while let CursorChunk::Next(view) = cursor.next(1024).unwrap() {
+        rows += view.rows();
+    }
+    rows
+}
+
+/// `SELECT <keys>, SUM(price) GROUP BY <keys>`
+fn aggregate<'a>(block: &'a Block<'a>, keys: Vec<usize>) -> HashAggregate<'a> {
+    HashAggregate::new(keys, vec![Aggregate::new(AggregateFunc::SUM, 2, "total")], ScanView::new(block, None))
+}
+
+#[bench]
+fn hash_keys(b: &mut Bencher) {
+    let block = orders();
+    b.iter(|| black_box(hash_rows(&block, &[0, 1]).unwrap()));
+}
+
+#[bench]
+fn aggregate_int64(b: &mut Bencher) {
+    let block = orders();
+    let op = aggregate(&block, vec![0]);
+    assert_eq!(run(&op), CUSTOMERS);
+    b.iter(|| black_box(run(&op)));
+}
+
+#[bench]
+fn aggregate_int64_text(b: &mut Bencher) {
+    let block = orders();
+    let op = aggregate(&block, vec![0, 1]);
+    b.iter(|| black_box(run(&op)));
+}
+
+/// Orders joined with their customer; the customers are the build (right) side
+#[bench]
+fn join(b: &mut Bencher) {
+    let (orders, customers) = (orders(), customers());
+    let op = HashJoin::new(JoinKind::INNER, vec![(0, 0), (1, 1)], ScanView::new(&orders, None), ScanView::new(&customers, None));
+    assert_eq!(run(&op), ROWS);
+    b.iter(|| black_box(run(&op)));
+}
+
+/// Build side only: none of the probe rows match
+#[bench]
+fn join_build(b: &mut Bencher) {
+    let customers = customers();
+    let empty = Block::new(&allocator::GLOBAL, &Schema::parse_ddl(ORDERS).unwrap());
+    let op = HashJoin::new(JoinKind::INNER, vec![(0, 0), (1, 1)], ScanView::new(&empty, None), ScanView::new(&customers, None));
+    b.iter(|| black_box(run(&op)));
+}
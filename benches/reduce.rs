@@ -0,0 +1,116 @@
+// vim: set ts=4 sw=4 et :
+
+//! `util::simd` reductions versus the naive loops they replace.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::block::Bitmap;
+use dbkit::util::simd;
+
+const ROWS: usize = 65536;
+
+fn int64s() -> Vec<i64> {
+    (0 .. ROWS as i64).map(|v| v * 7919 % 100003 - 50000).collect()
+}
+
+fn float64s() -> Vec<f64> {
+    (0 .. ROWS).map(|v| (v * 7919 % 100003) as f64 * 0.25).collect()
+}
+
+/// Every 10th row is NULL
+fn null_bits() -> Vec<u8> {
+    let mut bits = vec![0u8; ROWS / 8];
+    for row in (0 .. ROWS).filter(|r| r % 10 == 0) {
+        bits[row / 8] |= 1 << (row % 8);
+    }
+    bits
+}
+
+#[bench]
+fn sum_int64_naive(b: &mut Bencher) {
+    let values = int64s();
+    b.iter(|| black_box(values.iter().fold(Some(0i64), |acc, &v| acc.and_then(|a| a.checked_add(v)))));
+}
+
+#[bench]
+fn sum_int64(b: &mut Bencher) {
+    let values = int64s();
+    b.iter(|| black_box(simd::sum(&values, None).unwrap()));
+}
+
+#[bench]
+fn sum_int64_nulls_naive(b: &mut Bencher) {
+    let (values, bits) = (int64s(), null_bits());
+    let nulls = Bitmap::new(&bits, 0, ROWS);
+
+    b.iter(|| {
+        let mut sum = Some(0i64);
+        for (row, &v) in values.iter().enumerate() {
+            if !nulls.get(row) {
+                sum = sum.and_then(|s| s.checked_add(v));
+            }
+        }
+        black_box(sum)
+    });
+}
+
+#[bench]
+fn sum_int64_nulls(b: &mut Bencher) {
+    let (values, bits) = (int64s(), null_bits());
+    let nulls = Bitmap::new(&bits, 0, ROWS);
+
+    b.iter(|| black_box(simd::sum(&values, Some(&nulls)).unwrap()));
+}
+
+#[bench]
+fn sum_float64_naive(b: &mut Bencher) {
+    let values = float64s();
+    b.iter(|| black_box(values.iter().fold(0.0, |acc, &v| acc + v)));
+}
+
+#[bench]
+fn sum_float64(b: &mut Bencher) {
+    let values = float64s();
+    b.iter(|| black_box(simd::sum(&values, None).unwrap()));
+}
+
+#[bench]
+fn min_float64_naive(b: &mut Bencher) {
+    let values = float64s();
+    b.iter(|| black_box(values.iter().fold(::std::f64::INFINITY, |acc, &v| acc.min(v))));
+}
+
+#[bench]
+fn min_float64(b: &mut Bencher) {
+    let values = float64s();
+    b.iter(|| black_box(simd::min(&values, None)));
+}
+
+#[bench]
+fn max_int64_nulls_naive(b: &mut Bencher) {
+    let (values, bits) = (int64s(), null_bits());
+    let nulls = Bitmap::new(&bits, 0, ROWS);
+
+    b.iter(|| black_box(values.iter().enumerate().filter(|&(r, _)| !nulls.get(r)).map(|(_, &v)| v).max()));
+}
+
+#[bench]
+fn max_int64_nulls(b: &mut Bencher) {
+    let (values, bits) = (int64s(), null_bits());
+    let nulls = Bitmap::new(&bits, 0, ROWS);
+
+    b.iter(|| black_box(simd::max(&values, Some(&nulls))));
+}
+
+#[bench]
+fn count_valid(b: &mut Bencher) {
+    let bits = null_bits();
+    let nulls = Bitmap::new(&bits, 0, ROWS);
+
+    b.iter(|| black_box(simd::count_valid(ROWS, Some(&nulls))));
+}
@@ -0,0 +1,62 @@
+// vim: set ts=4 sw=4 et :
+
+//! Name resolution on wide schemas. `linear_scan` is the baseline `Schema::exists` used to be.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::projector::{BuildSingleSourceProjector, project_by_name};
+use dbkit::schema::{Attribute, Schema};
+use dbkit::types::Type;
+
+const COLUMNS: usize = 200;
+
+fn wide_schema() -> Schema {
+    let attrs = (0 .. COLUMNS)
+        .map(|pos| Attribute::new(format!("column_{}", pos), true, Type::INT64))
+        .collect();
+
+    Schema::from_vec(attrs).unwrap()
+}
+
+fn names() -> Vec<String> {
+    (0 .. COLUMNS).rev().map(|pos| format!("column_{}", pos)).collect()
+}
+
+#[bench]
+fn linear_scan(b: &mut Bencher) {
+    let schema = wide_schema();
+    let names = names();
+
+    b.iter(|| {
+        for name in &names {
+            black_box(schema.iter().position(|a| a.name == *name));
+        }
+    });
+}
+
+#[bench]
+fn exists(b: &mut Bencher) {
+    let schema = wide_schema();
+    let names = names();
+
+    b.iter(|| {
+        for name in &names {
+            black_box(schema.exists(name));
+        }
+    });
+}
+
+#[bench]
+fn bind_by_name(b: &mut Bencher) {
+    let schema = wide_schema();
+    let proj = names().into_iter()
+        .fold(BuildSingleSourceProjector::new(), |p, name| p.add(project_by_name(name)))
+        .done();
+
+    b.iter(|| black_box(proj.bind(&schema).unwrap()));
+}
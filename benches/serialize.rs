@@ -0,0 +1,78 @@
+// vim: set ts=4 sw=4 et :
+
+//! Native block serialization round trip, plain and with column codecs.
+
+#![feature(test)]
+
+extern crate dbkit_engine as dbkit;
+extern crate test;
+
+use test::{Bencher, black_box};
+
+use dbkit::allocator;
+use dbkit::block::Block;
+use dbkit::schema::Schema;
+use dbkit::util::codec::{Codec, Lz4};
+use dbkit::util::copy_value::set_column_value;
+
+const ROWS: usize = 65536;
+
+fn events() -> Block<'static> {
+    let schema = Schema::parse_ddl("ts INT64 NOT NULL, user UINT32 NOT NULL, value FLOAT64, name TEXT").unwrap();
+    let mut block = Block::new(&allocator::GLOBAL, &schema);
+    block.add_rows(ROWS).unwrap();
+
+    for row in 0 .. ROWS {
+        let value = if row % 10 == 0 { None } else { Some(row as f64 * 0.125) };
+        set_column_value(&mut block, 0, row, &(1500000000000i64 + row as i64 * 10)).unwrap();
+        set_column_value(&mut block, 1, row, &((row % 5000) as u32)).unwrap();
+        set_column_value(&mut block, 2, row, &value).unwrap();
+        set_column_value(&mut block, 3, row, &format!("event-{}", row % 64)).unwrap();
+    }
+
+    block
+}
+
+#[bench]
+fn serialize(b: &mut Bencher) {
+    let block = events();
+    let mut out = Vec::new();
+    b.bytes = block.serialize(&mut out).unwrap() as u64;
+
+    b.iter(|| {
+        out.clear();
+        black_box(block.serialize(&mut out).unwrap())
+    });
+}
+
+#[bench]
+fn deserialize(b: &mut Bencher) {
+    let mut data = Vec::new();
+    b.bytes = events().serialize(&mut data).unwrap() as u64;
+
+    b.iter(|| black_box(Block::deserialize(&mut &data[..], &allocator::GLOBAL).unwrap()));
+}
+
+#[bench]
+fn serialize_lz4(b: &mut Bencher) {
+    let block = events();
+    let codecs: Vec<Option<&Codec>> = vec![Some(&Lz4); 4];
+    let mut out = Vec::new();
+    b.bytes = block.serialize(&mut out).unwrap() as u64;
+
+    b.iter(|| {
+        out.clear();
+        black_box(block.serialize_with(&mut out, &codecs).unwrap())
+    });
+}
+
+#[bench]
+fn deserialize_lz4(b: &mut Bencher) {
+    let block = events();
+    let codecs: Vec<Option<&Codec>> = vec![Some(&Lz4); 4];
+    let mut data = Vec::new();
+    b.bytes = block.serialize(&mut Vec::new()).unwrap() as u64;
+    block.serialize_with(&mut data, &codecs).unwrap();
+
+    b.iter(|| black_box(Block::deserialize(&mut &data[..], &allocator::GLOBAL).unwrap()));
+}
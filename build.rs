@@ -0,0 +1,63 @@
+// vim : set ts=4 sw=4 et :
+
+//! With the `icu` feature, links the system ICU (found with pkg-config) and generates the
+//! declarations of the ICU C functions used; their symbols carry the ICU major version
+//! (eg. `ucol_open_72`).
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// (name, arguments, return type) of the ICU functions
+const FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("ucol_open", "loc: *const c_char, status: *mut UErrorCode", "*mut UCollator"),
+    ("ucol_close", "coll: *mut UCollator", "()"),
+    ("ucol_setStrength", "coll: *mut UCollator, strength: c_int", "()"),
+    ("ucol_strcollUTF8", "coll: *const UCollator, source: *const c_char, source_len: i32, \
+                          target: *const c_char, target_len: i32, status: *mut UErrorCode", "c_int"),
+    ("ucol_getSortKey", "coll: *const UCollator, source: *const u16, source_len: i32, \
+                         result: *mut u8, result_len: i32", "i32"),
+];
+
+fn pkg_config(args: &[&str]) -> String {
+    let out = Command::new("pkg-config").args(args).arg("icu-i18n").output()
+        .expect("icu feature: failed to run pkg-config");
+
+    if !out.status.success() {
+        panic!("icu feature: pkg-config can't find icu-i18n: {}", String::from_utf8_lossy(&out.stderr));
+    }
+
+    String::from_utf8(out.stdout).unwrap().trim().to_string()
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if env::var_os("CARGO_FEATURE_ICU").is_none() {
+        return
+    }
+
+    let version = pkg_config(&["--modversion"]);
+    let major = version.split('.').next().unwrap();
+
+    for flag in pkg_config(&["--libs"]).split_whitespace() {
+        if let Some(path) = flag.strip_prefix("-L") {
+            println!("cargo:rustc-link-search=native={}", path);
+        } else if let Some(lib) = flag.strip_prefix("-l") {
+            println!("cargo:rustc-link-lib={}", lib);
+        }
+    }
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("icu_sys.rs");
+    let mut out = File::create(&path).unwrap();
+
+    writeln!(out, "// ICU {}", version).unwrap();
+    writeln!(out, "extern \"C\" {{").unwrap();
+    for &(name, args, ret) in FUNCTIONS {
+        writeln!(out, "    #[link_name = \"{}_{}\"]", name, major).unwrap();
+        writeln!(out, "    pub fn {}({}) -> {};", name, args, ret).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+}
@@ -1,10 +1,18 @@
 // vim : set ts=4 sw=4 et :
 
-use alloc::heap::{Alloc, AllocErr, Heap, Layout};
+use std::alloc::{alloc, dealloc, realloc, Layout};
+use std::fmt;
 use std::mem;
 use std::ptr;
 use std::slice;
 use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use super::error::DBError;
 
@@ -17,6 +25,26 @@ use super::error::DBError;
 // const MIN_ALIGN: usize = mem::size_of::<usize>();
 pub const MIN_ALIGN: usize = 32;
 
+/// Memory allocation failure
+#[derive(Clone, Debug, PartialEq)]
+pub enum AllocErr {
+    /// Out of memory
+    Exhausted { request: Layout },
+    /// Request the allocator can't serve, eg. an invalid alignment
+    Unsupported { details: &'static str },
+}
+
+impl fmt::Display for AllocErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AllocErr::Exhausted { request } =>
+                write!(f, "out of memory ({} bytes, {} aligned)", request.size(), request.align()),
+            AllocErr::Unsupported { details } =>
+                write!(f, "unsupported request: {}", details),
+        }
+    }
+}
+
 /// Allocator trait, used through out the operations in dbkit.
 ///
 /// Allocators have to maintain their own synchronization
@@ -25,11 +53,22 @@ pub trait Allocator : Send + Sync {
     fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError>;
 
     /// Resize; will try to resize in place if possible
-    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Option<DBError>;
+    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Result<(), DBError>;
 
     fn putback(&self, data: &mut OwnedChunk);
 
     fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize);
+
+    /// Take over the data of a chunk that's not going to be accessed until `pin`. The allocator
+    /// may move it out of memory; by default it stays put.
+    fn unpin(&self, _chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    /// Make the data of an unpinned chunk accessible again
+    fn pin(&self, _chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        Ok(())
+    }
 }
 
 pub type RefChunk<'a> = &'a mut [u8];
@@ -39,6 +78,10 @@ pub struct OwnedChunk<'a> {
     parent: Option<&'a Allocator>,
     pub data: Option<&'a mut[u8]>,
     pub align: usize,
+    /// Allocator's handle of the data while unpinned
+    handle: Option<usize>,
+    /// Length of the data while unpinned
+    unpinned_len: usize,
 }
 
 impl<'a> OwnedChunk<'a> {
@@ -47,6 +90,8 @@ impl<'a> OwnedChunk<'a> {
             parent: None,
             data: None,
             align: MIN_ALIGN,
+            handle: None,
+            unpinned_len: 0,
         }
     }
 
@@ -54,9 +99,10 @@ impl<'a> OwnedChunk<'a> {
         self.data.is_none()
     }
 
+    /// Length of the data, pinned or not
     pub fn len(&self) -> usize {
         self.data.as_ref()
-            .map_or(0, |slice| slice.len())
+            .map_or(self.unpinned_len, |slice| slice.len())
     }
 
     pub unsafe fn as_ptr(&self) -> *const u8 {
@@ -71,13 +117,34 @@ impl<'a> OwnedChunk<'a> {
 
     /// Attempt to resize the chunk. If possible it will attempt to resize in-place, if not possible
     /// it will create new alloc and copy the old data.
-    pub fn resize(&mut self, size: usize) -> Option<DBError> {
+    pub fn resize(&mut self, size: usize) -> Result<(), DBError> {
         unsafe {
             if let Some(allocator) = self.parent {
                 return allocator.resize(self, size);
             }
 
-            Some(DBError::Memory(AllocErr::Unsupported{details: "Unkown parent"}))
+            Err(DBError::Memory(AllocErr::Unsupported{details: "Unkown parent"}))
+        }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    /// Let the allocator spill the data under memory pressure. Depending on the allocator `data`
+    /// can be None until the chunk is pinned again.
+    pub fn unpin(&mut self) -> Result<(), DBError> {
+        match self.parent {
+            Some(allocator) if self.is_pinned() => allocator.unpin(self),
+            _                                   => Ok(()),
+        }
+    }
+
+    /// Make the data accessible again, reading it back in if it was spilled
+    pub fn pin(&mut self) -> Result<(), DBError> {
+        match self.parent {
+            Some(allocator) if !self.is_pinned() => allocator.pin(self),
+            _                                    => Ok(()),
         }
     }
 }
@@ -101,7 +168,32 @@ unsafe impl Sync for HeapAllocator{}
 /// A instance of default allocator when you don't care memory accounting, limitation
 pub static GLOBAL: HeapAllocator = HeapAllocator{};
 
-/// Simple heap allocator that delegates to `alloc::heap`
+fn heap_layout(size: usize, align: usize) -> Result<Layout, DBError> {
+    Layout::from_size_align(size, align)
+        .map_err(|_| DBError::Memory(AllocErr::Unsupported { details: "Invalid size or alignment" }))
+}
+
+/// Allocate from the global heap. Zero sized allocations don't touch the heap, they get a dangling
+/// (aligned) pointer.
+unsafe fn heap_alloc(layout: Layout) -> Result<*mut u8, DBError> {
+    if layout.size() == 0 {
+        return Ok(layout.align() as *mut u8)
+    }
+
+    let data = alloc(layout);
+    if data.is_null() {
+        return Err(DBError::Memory(AllocErr::Exhausted { request: layout }))
+    }
+    Ok(data)
+}
+
+unsafe fn heap_dealloc(data: *mut u8, layout: Layout) {
+    if layout.size() != 0 {
+        dealloc(data, layout)
+    }
+}
+
+/// Simple heap allocator that delegates to `std::alloc`
 impl Allocator for HeapAllocator {
     fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
         self.allocate_aligned(size, MIN_ALIGN)
@@ -109,29 +201,31 @@ impl Allocator for HeapAllocator {
 
     fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
         unsafe {
-            let layout = Layout::from_size_align_unchecked(size, align);
-            
-            Heap.alloc(layout)
-                .map_err(|err| DBError::Memory(err))
-                .map(|data| slice::from_raw_parts_mut::<u8>(data, size))
-                .map(|slice| OwnedChunk { parent: Some(self), data: Some(slice), align: align })
+            let data = heap_alloc(heap_layout(size, align)?)?;
+            let slice = slice::from_raw_parts_mut::<u8>(data, size);
+            Ok(OwnedChunk { parent: Some(self), data: Some(slice), align: align, handle: None, unpinned_len: 0 })
         }
     }
 
-    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Option<DBError> {
-        let old_layout = Layout::from_size_align_unchecked(prev.len(), prev.align);
-        let new_layout = Layout::from_size_align_unchecked(size, prev.align);
+    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Result<(), DBError> {
+        let old_layout = heap_layout(prev.len(), prev.align)?;
+        let new_layout = heap_layout(size, prev.align)?;
+        let old = prev.as_mut_ptr();
 
-        let data = prev.as_mut_ptr();
-        let status = Heap
-            .realloc(data, old_layout, new_layout)
-            .map_err(|err| DBError::Memory(err));
-
-        if let Ok(v) = status {
-            prev.data = Some(slice::from_raw_parts_mut::<u8>(v, size));
+        let data = if old_layout.size() == 0 || new_layout.size() == 0 {
+            let data = heap_alloc(new_layout)?;
+            heap_dealloc(old, old_layout);
+            data
+        } else {
+            let data = realloc(old, old_layout, size);
+            if data.is_null() {
+                return Err(DBError::Memory(AllocErr::Exhausted { request: new_layout }))
+            }
+            data
         };
 
-        status.err()
+        prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+        Ok(())
     }
 
     fn putback(&self, c: &mut OwnedChunk) {
@@ -143,91 +237,1516 @@ impl Allocator for HeapAllocator {
     fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
         // Just deallocate, no heap tracking
         unsafe {
-            let layout = Layout::from_size_align_unchecked(size, align);
-            Heap.dealloc(ptr, layout)
+            heap_dealloc(ptr, Layout::from_size_align_unchecked(size, align))
         }
     }
 }
 
-/// Result of arena append
-/// Chunk offset & pointer
-pub struct ArenaAppend(pub usize, pub *mut u8);
+/// Allocation event reported to an `AllocObserver`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AllocEvent {
+    ALLOCATE { size: usize },
+    /// Successful resize
+    RESIZE { old: usize, new: usize },
+    FREE { size: usize },
+}
 
-/// Arena styled allocator. Stores data in non-relocatable/non-movable arenas.
+/// Receives the allocation events of an `ObservedAllocator`. The tag identifies who allocated
+/// (operator, column, query stage...).
+pub trait AllocObserver : Send + Sync {
+    fn event(&self, tag: &str, event: AllocEvent);
+}
+
+/// Allocator wrapper reporting allocation events, tagged, to an observer.
 ///
-/// Policy is to increase allocation blocks 2X compare to previous block.
-pub struct ChainedArena<'a> {
-    parent: &'a Allocator,
-    chunks: Vec<&'a mut [u8]>,
-    min_size: usize,
-    max_size: usize,
-    pos: usize,
+/// Create a differently tagged allocator for every part of the query (`tagged`) and bind them
+/// with it to attribute memory usage to each part.
+pub struct ObservedAllocator<'a> {
+    inner: &'a Allocator,
+    observer: &'a AllocObserver,
+    tag: String,
 }
 
-/// Helper for creating the next Arena using allocator. Unwraps from `OwnedChunk` since
-/// `ChainedArena` managed deallocation for the whole container.
-unsafe fn make_arena<'a>(alloc: &'a Allocator, size: usize) -> Result<&'a mut [u8], DBError> {
-    alloc.allocate_aligned(size, MIN_ALIGN)
-        .map(|mut c| {
-            let mut out: &'a mut [u8] = mem::uninitialized();
-            mem::swap(&mut out, c.data.as_mut().unwrap());
-            mem::forget(c);
-            out
-        })
+impl<'a> ObservedAllocator<'a> {
+    pub fn new<T: Into<String>>(inner: &'a Allocator, observer: &'a AllocObserver, tag: T) -> ObservedAllocator<'a> {
+        ObservedAllocator { inner: inner, observer: observer, tag: tag.into() }
+    }
+
+    /// Allocator with the same inner allocator and observer but a different tag
+    pub fn tagged<T: Into<String>>(&self, tag: T) -> ObservedAllocator<'a> {
+        ObservedAllocator::new(self.inner, self.observer, tag)
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
 }
 
-impl<'a> ChainedArena<'a> {
+impl<'a> Allocator for ObservedAllocator<'a> {
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, MIN_ALIGN)
+    }
 
-    pub fn new(alloc: &'a Allocator, min_size: usize, max_size: usize) -> ChainedArena<'a> {
-        ChainedArena {
-            parent: alloc,
-            chunks: Vec::new(),
-            min_size: min_size,
-            max_size: max_size,
-            pos: 0,
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        let mut chunk = self.inner.allocate_aligned(size, align)?;
+        chunk.parent = Some(self);
+        self.observer.event(&self.tag, AllocEvent::ALLOCATE { size: size });
+        Ok(chunk)
+    }
+
+    unsafe fn resize<'b>(&self, prev: &mut OwnedChunk<'b>, size: usize) -> Result<(), DBError> {
+        let old = prev.len();
+        let status = self.inner.resize(prev, size);
+        if status.is_ok() {
+            self.observer.event(&self.tag, AllocEvent::RESIZE { old: old, new: size });
         }
+        status
     }
 
-    pub unsafe fn allocate(&mut self, size: usize) -> Result<*mut u8, DBError> {
-        if size > self.max_size {
-            return Err(DBError::MemoryLimit);
+    fn putback(&self, c: &mut OwnedChunk) {
+        self.observer.event(&self.tag, AllocEvent::FREE { size: c.len() });
+        self.inner.putback(c)
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        self.observer.event(&self.tag, AllocEvent::FREE { size: size });
+        self.inner.putback_raw(ptr, size, align)
+    }
+
+    fn unpin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        self.inner.unpin(chunk)
+    }
+
+    fn pin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        self.inner.pin(chunk)
+    }
+}
+
+/// Allocation counters of a tag
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllocStats {
+    pub allocations: usize,
+    pub resizes: usize,
+    pub frees: usize,
+    /// Currently allocated bytes
+    pub used: usize,
+    /// Highest allocated bytes
+    pub peak: usize,
+    /// Bytes allocated over time (including growth by resize)
+    pub total: usize,
+}
+
+/// Observer aggregating the allocation counters per tag
+#[derive(Default)]
+pub struct AllocMetrics {
+    tags: Mutex<HashMap<String, AllocStats>>,
+}
+
+impl AllocMetrics {
+    pub fn new() -> AllocMetrics {
+        AllocMetrics::default()
+    }
+
+    /// Counters of a tag (zero if it didn't allocate)
+    pub fn get(&self, tag: &str) -> AllocStats {
+        self.tags.lock().unwrap()
+            .get(tag)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Counters of all the tags, sorted by tag
+    pub fn all(&self) -> Vec<(String, AllocStats)> {
+        let mut out: Vec<_> = self.tags.lock().unwrap()
+            .iter()
+            .map(|(tag, stats)| (tag.clone(), *stats))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+
+    pub fn clear(&self) {
+        self.tags.lock().unwrap().clear()
+    }
+}
+
+impl AllocObserver for AllocMetrics {
+    fn event(&self, tag: &str, event: AllocEvent) {
+        let mut tags = self.tags.lock().unwrap();
+        if !tags.contains_key(tag) {
+            tags.insert(tag.to_string(), AllocStats::default());
         }
 
-        let new_size = if let Some(ref mut arena) = self.chunks.last_mut() {
-            if arena.len() - self.pos >= size {
-                let ptr = arena.as_mut_ptr().offset(size as isize);
-                self.pos += size;
-                return Ok(ptr);
+        let stats = tags.get_mut(tag).unwrap();
+        match event {
+            AllocEvent::ALLOCATE { size } => {
+                stats.allocations += 1;
+                stats.used += size;
+                stats.total += size;
             }
+            AllocEvent::RESIZE { old, new } => {
+                stats.resizes += 1;
+                stats.used = stats.used + new - old;
+                stats.total += new.saturating_sub(old);
+            }
+            AllocEvent::FREE { size } => {
+                stats.frees += 1;
+                stats.used -= size;
+            }
+        }
+
+        stats.peak = stats.peak.max(stats.used);
+    }
+}
+
+/// Huge page policy of `MmapAllocator`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HugePages {
+    NONE,
+    /// Advise the kernel to back the mapping with transparent huge pages (Linux only; ignored
+    /// elsewhere). Only helps mappings of at least `HUGE_PAGE_SIZE`.
+    TRANSPARENT,
+    /// Map from the reserved huge page pool (`MAP_HUGETLB`, Linux only). Allocations fail if the
+    /// pool is exhausted or not configured.
+    EXPLICIT,
+}
+
+/// Assumed huge page size (the x86-64 default)
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// Allocator mapping every allocation as anonymous memory straight from the OS.
+///
+/// Meant for large column buffers: it doesn't fragment the heap, memory is returned to the OS on
+/// putback and buffers are page aligned (usable for direct IO). Sizes are rounded up to the page
+/// size, so it's wasteful for small allocations.
+#[cfg(unix)]
+pub struct MmapAllocator {
+    huge_pages: HugePages,
+}
+
+/// Mmap allocator without huge pages
+#[cfg(unix)]
+pub static MMAP: MmapAllocator = MmapAllocator { huge_pages: HugePages::NONE };
+
+#[cfg(unix)]
+impl MmapAllocator {
+    pub fn new(huge_pages: HugePages) -> MmapAllocator {
+        MmapAllocator { huge_pages: huge_pages }
+    }
+
+    pub fn huge_pages(&self) -> HugePages {
+        self.huge_pages
+    }
+
+    /// Mapping granularity
+    pub fn page_size(&self) -> usize {
+        if self.huge_pages == HugePages::EXPLICIT {
+            HUGE_PAGE_SIZE
+        } else {
+            unsafe { ::libc::sysconf(::libc::_SC_PAGESIZE) as usize }
+        }
+    }
+
+    /// Mapped length for an allocation of `size`; always at least a page
+    fn map_len(&self, size: usize) -> usize {
+        let page = self.page_size();
+        (size.max(1) + page - 1) / page * page
+    }
+
+    fn error(size: usize, align: usize) -> DBError {
+        DBError::Memory(AllocErr::Exhausted { request: unsafe { Layout::from_size_align_unchecked(size, align) } })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn map_flags(&self) -> ::libc::c_int {
+        let flags = ::libc::MAP_PRIVATE | ::libc::MAP_ANONYMOUS;
+        if self.huge_pages == HugePages::EXPLICIT { flags | ::libc::MAP_HUGETLB } else { flags }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn map_flags(&self) -> ::libc::c_int {
+        ::libc::MAP_PRIVATE | ::libc::MAP_ANON
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn advise(&self, ptr: *mut ::libc::c_void, len: usize) {
+        // Only advisory, failure is not an error
+        if self.huge_pages == HugePages::TRANSPARENT {
+            ::libc::madvise(ptr, len, ::libc::MADV_HUGEPAGE);
+        }
+    }
 
-            min(arena.len() * 2, self.max_size)
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn advise(&self, _: *mut ::libc::c_void, _: usize) { }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn remap(&self, ptr: *mut u8, old_len: usize, len: usize) -> Option<*mut u8> {
+        let out = ::libc::mremap(ptr as *mut ::libc::c_void, old_len, len, ::libc::MREMAP_MAYMOVE);
+        if out == ::libc::MAP_FAILED {
+            return None
+        }
+
+        self.advise(out, len);
+        Some(out as *mut u8)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn remap(&self, ptr: *mut u8, old_len: usize, len: usize) -> Option<*mut u8> {
+        let out = self.map(len)?;
+        ptr::copy_nonoverlapping(ptr, out, min(old_len, len));
+        ::libc::munmap(ptr as *mut ::libc::c_void, old_len);
+        Some(out)
+    }
+
+    unsafe fn map(&self, len: usize) -> Option<*mut u8> {
+        let prot = ::libc::PROT_READ | ::libc::PROT_WRITE;
+        let ptr = ::libc::mmap(ptr::null_mut(), len, prot, self.map_flags(), -1, 0);
+        if ptr == ::libc::MAP_FAILED {
+            return None
+        }
+
+        self.advise(ptr, len);
+        Some(ptr as *mut u8)
+    }
+}
+
+#[cfg(unix)]
+impl Default for MmapAllocator {
+    fn default() -> MmapAllocator {
+        MmapAllocator::new(HugePages::NONE)
+    }
+}
+
+#[cfg(unix)]
+impl Allocator for MmapAllocator {
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, MIN_ALIGN)
+    }
+
+    /// Alignments up to the page size are supported
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        if align > self.page_size() {
+            return Err(DBError::Memory(AllocErr::Unsupported { details: "Alignment larger than page size" }))
+        }
+
+        unsafe {
+            self.map(self.map_len(size))
+                .ok_or_else(|| MmapAllocator::error(size, align))
+                .map(|data| OwnedChunk {
+                    parent: Some(self),
+                    data: Some(slice::from_raw_parts_mut::<u8>(data, size)),
+                    align: align,
+                    handle: None,
+                    unpinned_len: 0,
+                })
+        }
+    }
+
+    unsafe fn resize<'a>(&self, prev: &mut OwnedChunk<'a>, size: usize) -> Result<(), DBError> {
+        let old_len = self.map_len(prev.len());
+        let len = self.map_len(size);
+
+        let data = if old_len == len {
+            prev.as_mut_ptr()
         } else {
-            self.min_size
+            match self.remap(prev.as_mut_ptr(), old_len, len) {
+                Some(data)  => data,
+                None        => return Err(MmapAllocator::error(size, prev.align)),
+            }
         };
 
-        let new_arena = make_arena(self.parent, new_size)?;
-        let ptr = new_arena.as_mut_ptr();
+        prev.data = Some(slice::from_raw_parts_mut::<u8>(data, size));
+        Ok(())
+    }
 
-        self.chunks.push(new_arena);
-        Ok(ptr)
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
     }
 
-    pub fn append(&mut self, data: &[u8]) -> Result<ArenaAppend, DBError> {
+    fn putback_raw(&self, ptr: *mut u8, size: usize, _: usize) {
         unsafe {
-            let ptr = self.allocate(data.len())?;
-            ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
-            Ok(ArenaAppend(self.chunks.len(), ptr))
+            ::libc::munmap(ptr as *mut ::libc::c_void, self.map_len(size));
         }
     }
 }
 
-impl<'a> Drop for ChainedArena<'a> {
+/// Allocator wrapper that keeps track of the allocated bytes and enforces a byte budget.
+///
+/// Allocations over the budget fail with `DBError::MemoryLimit`. Chunks allocated by the tracking
+/// allocator are returned to it, so the usage goes down when they're dropped.
+///
+/// Tracking allocators form a hierarchy of pools: a sub pool's budget is carved out of its parent
+/// (counted as used in the parent) and returned to the parent when the sub pool is dropped. A
+/// query level pool can hand out a sub pool per operator (bind the operator with it), so each
+/// operator has its own limit.
+pub struct TrackingAllocator<'a> {
+    inner: &'a Allocator,
+    /// Pool the budget is reserved from
+    parent: Option<&'a TrackingAllocator<'a>>,
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl<'a> TrackingAllocator<'a> {
+    /// Allocator with a `limit` byte budget
+    pub fn new(inner: &'a Allocator, limit: usize) -> TrackingAllocator<'a> {
+        TrackingAllocator {
+            inner: inner,
+            parent: None,
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Only accounting, no budget
+    pub fn unlimited(inner: &'a Allocator) -> TrackingAllocator<'a> {
+        TrackingAllocator::new(inner, usize::max_value())
+    }
+
+    /// Currently allocated bytes
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Highest allocated bytes
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Bytes left in the budget
+    pub fn available(&self) -> usize {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Change the budget. Lowering it under the current usage only fails future allocations.
+    ///
+    /// For sub pools the difference is reserved from (or returned to) the parent; fails with
+    /// `DBError::MemoryLimit` if the parent doesn't have enough budget left.
+    pub fn set_limit(&self, limit: usize) -> Result<(), DBError> {
+        if let Some(parent) = self.parent {
+            let old = self.limit();
+            if limit > old {
+                parent.reserve(limit - old)?;
+            } else {
+                parent.release(old - limit);
+            }
+        }
+
+        self.limit.store(limit, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Create a sub pool with a `budget` byte budget reserved from this pool
+    pub fn sub_pool<'p>(&'p self, budget: usize) -> Result<TrackingAllocator<'p>, DBError> {
+        self.reserve(budget)?;
+
+        Ok(TrackingAllocator {
+            inner: self.inner,
+            parent: Some(self),
+            limit: AtomicUsize::new(budget),
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        })
+    }
+
+    /// Reset the peak to the current usage
+    pub fn reset_peak(&self) {
+        self.peak.store(self.used(), Ordering::Relaxed)
+    }
+
+    fn reserve(&self, size: usize) -> Result<(), DBError> {
+        let mut used = self.used.load(Ordering::Relaxed);
+
+        loop {
+            let next = match used.checked_add(size) {
+                Some(next) if next <= self.limit() => next,
+                _ => return Err(DBError::MemoryLimit),
+            };
+
+            match self.used.compare_exchange_weak(used, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    let mut peak = self.peak.load(Ordering::Relaxed);
+                    while next > peak {
+                        match self.peak.compare_exchange_weak(peak, next, Ordering::Relaxed, Ordering::Relaxed) {
+                            Ok(_) => break,
+                            Err(current) => peak = current,
+                        }
+                    }
+                    return Ok(())
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+impl<'a> Allocator for TrackingAllocator<'a> {
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, MIN_ALIGN)
+    }
+
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        self.reserve(size)?;
+
+        match self.inner.allocate_aligned(size, align) {
+            Ok(mut chunk) => {
+                // Chunks are returned through us
+                chunk.parent = Some(self);
+                Ok(chunk)
+            }
+            Err(e) => {
+                self.release(size);
+                Err(e)
+            }
+        }
+    }
+
+    unsafe fn resize<'b>(&self, prev: &mut OwnedChunk<'b>, size: usize) -> Result<(), DBError> {
+        let old_size = prev.len();
+
+        if size > old_size {
+            self.reserve(size - old_size)?;
+        }
+
+        let status = self.inner.resize(prev, size);
+
+        if size > old_size && status.is_err() {
+            self.release(size - old_size);
+        } else if size < old_size && status.is_ok() {
+            self.release(old_size - size);
+        }
+
+        status
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        self.release(c.len());
+        self.inner.putback(c)
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        self.release(size);
+        self.inner.putback_raw(ptr, size, align)
+    }
+
+    fn unpin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        self.inner.unpin(chunk)
+    }
+
+    fn pin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        self.inner.pin(chunk)
+    }
+}
+
+impl<'a> Drop for TrackingAllocator<'a> {
     fn drop(&mut self) {
-        let mut arenas = Vec::new();
-        mem::swap(&mut arenas, &mut self.chunks);
-        for ref mut a in arenas {
-            self.parent.putback_raw(a.as_mut_ptr(), a.len(), MIN_ALIGN);
+        if let Some(parent) = self.parent {
+            parent.release(self.limit());
         }
     }
 }
 
+/// Slab style allocator recycling chunks through per size class free lists.
+///
+/// Sizes between `min_size` and `max_size` are rounded up to a power of two size class; chunks of
+/// a class are kept on putback (up to `max_free` per class) and handed out again instead of going
+/// to the `inner` allocator every batch. Larger sizes, and alignments over `MIN_ALIGN`, go
+/// straight to `inner`.
+pub struct PoolAllocator<'a> {
+    inner: &'a Allocator,
+    min_size: usize,
+    max_size: usize,
+    max_free: usize,
+    /// Free chunk addresses for every size class
+    free: Mutex<Vec<Vec<usize>>>,
+}
+
+impl<'a> PoolAllocator<'a> {
+    /// `min_size` and `max_size` are rounded up to a power of two
+    pub fn new(inner: &'a Allocator, min_size: usize, max_size: usize, max_free: usize) -> PoolAllocator<'a> {
+        let min_size = min_size.max(1).next_power_of_two();
+        let max_size = max_size.max(min_size).next_power_of_two();
+        let classes = (max_size.trailing_zeros() - min_size.trailing_zeros()) as usize + 1;
+
+        PoolAllocator {
+            inner: inner,
+            min_size: min_size,
+            max_size: max_size,
+            max_free: max_free,
+            free: Mutex::new(vec![Vec::new(); classes]),
+        }
+    }
+
+    /// Size class of an allocation; None if it's not pooled
+    fn class(&self, size: usize, align: usize) -> Option<(usize, usize)> {
+        if size > self.max_size || align > MIN_ALIGN {
+            return None
+        }
+
+        let class_size = size.max(self.min_size).next_power_of_two();
+        let idx = (class_size.trailing_zeros() - self.min_size.trailing_zeros()) as usize;
+        Some((idx, class_size))
+    }
+
+    /// Bytes held in the free lists
+    pub fn free_bytes(&self) -> usize {
+        let free = self.free.lock().unwrap();
+        free.iter()
+            .enumerate()
+            .map(|(idx, list)| list.len() * (self.min_size << idx))
+            .sum()
+    }
+
+    /// Return all the free chunks to the inner allocator
+    pub fn trim(&self) {
+        let mut free = self.free.lock().unwrap();
+        for (idx, list) in free.iter_mut().enumerate() {
+            for ptr in list.drain(..) {
+                self.inner.putback_raw(ptr as *mut u8, self.min_size << idx, MIN_ALIGN);
+            }
+        }
+    }
+
+    fn take(&self, size: usize, align: usize) -> Result<*mut u8, DBError> {
+        let (idx, class_size) = match self.class(size, align) {
+            Some(class) => class,
+            None        => return self.inner_alloc(size, align),
+        };
+
+        if let Some(ptr) = self.free.lock().unwrap()[idx].pop() {
+            return Ok(ptr as *mut u8)
+        }
+
+        self.inner_alloc(class_size, MIN_ALIGN)
+    }
+
+    fn inner_alloc(&self, size: usize, align: usize) -> Result<*mut u8, DBError> {
+        let mut chunk = self.inner.allocate_aligned(size, align)?;
+        // Ownership moves to the pool
+        chunk.parent = None;
+        Ok(unsafe { chunk.as_mut_ptr() })
+    }
+}
+
+impl<'a> Allocator for PoolAllocator<'a> {
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, MIN_ALIGN)
+    }
+
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        let ptr = self.take(size, align)?;
+
+        Ok(OwnedChunk {
+            parent: Some(self),
+            data: Some(unsafe { slice::from_raw_parts_mut::<u8>(ptr, size) }),
+            align: align,
+            handle: None,
+            unpinned_len: 0,
+        })
+    }
+
+    unsafe fn resize<'b>(&self, prev: &mut OwnedChunk<'b>, size: usize) -> Result<(), DBError> {
+        let old_size = prev.len();
+
+        // Still fits the same size class
+        if let (Some(old), Some(new)) = (self.class(old_size, prev.align), self.class(size, prev.align)) {
+            if old.0 == new.0 {
+                prev.data = Some(slice::from_raw_parts_mut::<u8>(prev.as_mut_ptr(), size));
+                return Ok(())
+            }
+        }
+
+        let ptr = self.take(size, prev.align)?;
+
+        ptr::copy_nonoverlapping(prev.as_ptr(), ptr, min(old_size, size));
+        self.putback_raw(prev.as_mut_ptr(), old_size, prev.align);
+        prev.data = Some(slice::from_raw_parts_mut::<u8>(ptr, size));
+        Ok(())
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        let (idx, class_size) = match self.class(size, align) {
+            Some(class) => class,
+            None        => return self.inner.putback_raw(ptr, size, align),
+        };
+
+        {
+            let mut free = self.free.lock().unwrap();
+            if free[idx].len() < self.max_free {
+                free[idx].push(ptr as usize);
+                return
+            }
+        }
+
+        self.inner.putback_raw(ptr, class_size, MIN_ALIGN)
+    }
+}
+
+impl<'a> Drop for PoolAllocator<'a> {
+    fn drop(&mut self) {
+        self.trim()
+    }
+}
+
+/// Unpinned chunk data
+enum Unpinned {
+    /// Still in memory, can be spilled
+    MEMORY { ptr: usize, len: usize, align: usize },
+    /// Written to the spill file
+    SPILLED { offset: u64, len: usize, align: usize },
+}
+
+struct SpillState {
+    /// Bytes in memory, pinned or not
+    used: usize,
+    /// Unpinned chunks by handle; handles increase so the least recently unpinned comes first
+    unpinned: BTreeMap<usize, Unpinned>,
+    next_handle: usize,
+    /// Spill file, created on the first spill
+    file: Option<(File, PathBuf)>,
+    file_len: u64,
+    spilled: usize,
+}
+
+/// Allocator with a byte budget that spills unpinned chunks to a temporary file.
+///
+/// When an allocation (or `pin`) would go over the budget the least recently unpinned chunks are
+/// written out and their memory released. They're read back in when pinned. Pinned chunks are
+/// never spilled; if there's nothing left to spill allocations fail with `DBError::MemoryLimit`.
+///
+/// Space in the spill file is not reused; the file is removed when the allocator is dropped.
+pub struct SpillableAllocator<'a> {
+    inner: &'a Allocator,
+    limit: usize,
+    dir: PathBuf,
+    state: Mutex<SpillState>,
+}
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl<'a> SpillableAllocator<'a> {
+    /// Spill into a file created in `dir`
+    pub fn new<P: AsRef<Path>>(inner: &'a Allocator, limit: usize, dir: P) -> SpillableAllocator<'a> {
+        SpillableAllocator {
+            inner: inner,
+            limit: limit,
+            dir: dir.as_ref().to_path_buf(),
+            state: Mutex::new(SpillState {
+                used: 0,
+                unpinned: BTreeMap::new(),
+                next_handle: 0,
+                file: None,
+                file_len: 0,
+                spilled: 0,
+            }),
+        }
+    }
+
+    /// Spill into the system temporary directory
+    pub fn temp(inner: &'a Allocator, limit: usize) -> SpillableAllocator<'a> {
+        SpillableAllocator::new(inner, limit, env::temp_dir())
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Bytes in memory
+    pub fn used(&self) -> usize {
+        self.state.lock().unwrap().used
+    }
+
+    /// Bytes of unpinned chunks currently spilled
+    pub fn spilled(&self) -> usize {
+        self.state.lock().unwrap().spilled
+    }
+
+    /// Make room for `size` bytes, spilling unpinned chunks as needed
+    fn reserve(&self, state: &mut SpillState, size: usize) -> Result<(), DBError> {
+        while state.used + size > self.limit {
+            let victim = state.unpinned.iter()
+                .find(|&(_, u)| match *u { Unpinned::MEMORY { .. } => true, _ => false })
+                .map(|(handle, _)| *handle);
+
+            match victim {
+                Some(handle) => self.spill(state, handle)?,
+                None         => return Err(DBError::MemoryLimit),
+            }
+        }
+
+        state.used += size;
+        Ok(())
+    }
+
+    fn spill(&self, state: &mut SpillState, handle: usize) -> Result<(), DBError> {
+        let (ptr, len, align) = match state.unpinned[&handle] {
+            Unpinned::MEMORY { ptr, len, align } => (ptr, len, align),
+            _ => return Ok(()),
+        };
+
+        if state.file.is_none() {
+            let id = SPILL_FILES.fetch_add(1, Ordering::Relaxed);
+            let path = self.dir.join(format!("dbkit-spill-{}-{}", ::std::process::id(), id));
+            let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+            state.file = Some((file, path));
+        }
+
+        let offset = state.file_len;
+        {
+            let file = &mut state.file.as_mut().unwrap().0;
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(unsafe { slice::from_raw_parts(ptr as *const u8, len) })?;
+        }
+
+        self.inner.putback_raw(ptr as *mut u8, len, align);
+        state.unpinned.insert(handle, Unpinned::SPILLED { offset: offset, len: len, align: align });
+        state.file_len += len as u64;
+        state.used -= len;
+        state.spilled += len;
+        Ok(())
+    }
+}
+
+impl<'a> Allocator for SpillableAllocator<'a> {
+    fn allocate(&self, size: usize) -> Result<OwnedChunk, DBError> {
+        self.allocate_aligned(size, MIN_ALIGN)
+    }
+
+    fn allocate_aligned(&self, size: usize, align: usize) -> Result<OwnedChunk, DBError> {
+        let mut state = self.state.lock().unwrap();
+        self.reserve(&mut state, size)?;
+
+        match self.inner.allocate_aligned(size, align) {
+            Ok(mut chunk) => {
+                chunk.parent = Some(self);
+                Ok(chunk)
+            }
+            Err(e) => {
+                state.used -= size;
+                Err(e)
+            }
+        }
+    }
+
+    unsafe fn resize<'b>(&self, prev: &mut OwnedChunk<'b>, size: usize) -> Result<(), DBError> {
+        if !prev.is_pinned() {
+            return Err(DBError::Memory(AllocErr::Unsupported { details: "Resize of unpinned chunk" }))
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let old_size = prev.len();
+
+        if size > old_size {
+            self.reserve(&mut state, size - old_size)?;
+        }
+
+        let status = self.inner.resize(prev, size);
+
+        if size > old_size && status.is_err() {
+            state.used -= size - old_size;
+        } else if size < old_size && status.is_ok() {
+            state.used -= old_size - size;
+        }
+
+        status
+    }
+
+    fn putback(&self, c: &mut OwnedChunk) {
+        if let Some(handle) = c.handle.take() {
+            let mut state = self.state.lock().unwrap();
+            match state.unpinned.remove(&handle) {
+                Some(Unpinned::MEMORY { ptr, len, align }) => {
+                    state.used -= len;
+                    self.inner.putback_raw(ptr as *mut u8, len, align)
+                }
+                Some(Unpinned::SPILLED { len, .. }) => state.spilled -= len,
+                None => (),
+            }
+        } else if let Some(ref mut data) = c.data {
+            self.putback_raw(data.as_mut_ptr(), data.len(), c.align)
+        }
+    }
+
+    fn putback_raw(&self, ptr: *mut u8, size: usize, align: usize) {
+        self.state.lock().unwrap().used -= size;
+        self.inner.putback_raw(ptr, size, align)
+    }
+
+    fn unpin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        let (ptr, len) = match chunk.data.take() {
+            Some(data)  => (data.as_mut_ptr() as usize, data.len()),
+            None        => return Ok(()),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let handle = state.next_handle;
+        state.next_handle += 1;
+        state.unpinned.insert(handle, Unpinned::MEMORY { ptr: ptr, len: len, align: chunk.align });
+        chunk.handle = Some(handle);
+        chunk.unpinned_len = len;
+        Ok(())
+    }
+
+    fn pin(&self, chunk: &mut OwnedChunk) -> Result<(), DBError> {
+        let handle = match chunk.handle {
+            Some(handle)    => handle,
+            None            => return Ok(()),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let (ptr, len) = match state.unpinned[&handle] {
+            Unpinned::MEMORY { ptr, len, .. } => (ptr as *mut u8, len),
+            Unpinned::SPILLED { offset, len, align } => {
+                self.reserve(&mut state, len)?;
+
+                let ptr = match self.inner.allocate_aligned(len, align) {
+                    Ok(mut data) => {
+                        // Ownership moves to the chunk being pinned
+                        data.parent = None;
+                        unsafe { data.as_mut_ptr() }
+                    }
+                    Err(e) => {
+                        state.used -= len;
+                        return Err(e)
+                    }
+                };
+
+                let read = {
+                    let file = &mut state.file.as_mut().unwrap().0;
+                    file.seek(SeekFrom::Start(offset))
+                        .and_then(|_| file.read_exact(unsafe { slice::from_raw_parts_mut(ptr, len) }))
+                };
+
+                if let Err(e) = read {
+                    state.used -= len;
+                    self.inner.putback_raw(ptr, len, align);
+                    return Err(e.into())
+                }
+
+                state.spilled -= len;
+                (ptr, len)
+            }
+        };
+
+        state.unpinned.remove(&handle);
+        chunk.handle = None;
+        chunk.unpinned_len = 0;
+        chunk.data = Some(unsafe { slice::from_raw_parts_mut(ptr, len) });
+        Ok(())
+    }
+}
+
+impl<'a> Drop for SpillableAllocator<'a> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        if let Some((file, path)) = state.file.take() {
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Result of arena append
+/// Chunk index & pointer
+pub struct ArenaAppend(pub usize, pub *mut u8);
+
+/// Arena styled allocator. Stores data in non-relocatable/non-movable arenas.
+///
+/// Policy is to increase allocation blocks 2X compare to previous block. Chunks are kept on
+/// `reset`, so the arena can be reused without allocating.
+pub struct ChainedArena<'a> {
+    parent: &'a Allocator,
+    chunks: Vec<&'a mut [u8]>,
+    /// Used bytes of each chunk
+    used: Vec<usize>,
+    /// Chunk appended to
+    current: usize,
+    min_size: usize,
+    max_size: usize,
+}
+
+/// Helper for creating the next Arena using allocator. Unwraps from `OwnedChunk` since
+/// `ChainedArena` managed deallocation for the whole container.
+unsafe fn make_arena<'a>(alloc: &'a Allocator, size: usize) -> Result<&'a mut [u8], DBError> {
+    alloc.allocate_aligned(size, MIN_ALIGN)
+        .map(|mut c| {
+            let mut out: &'a mut [u8] = mem::uninitialized();
+            mem::swap(&mut out, c.data.as_mut().unwrap());
+            mem::forget(c);
+            out
+        })
+}
+
+impl<'a> ChainedArena<'a> {
+
+    pub fn new(alloc: &'a Allocator, min_size: usize, max_size: usize) -> ChainedArena<'a> {
+        ChainedArena {
+            parent: alloc,
+            chunks: Vec::new(),
+            used: Vec::new(),
+            current: 0,
+            min_size: min_size,
+            max_size: max_size,
+        }
+    }
+
+    /// Allocate `size` bytes without alignment
+    pub unsafe fn allocate(&mut self, size: usize) -> Result<*mut u8, DBError> {
+        self.allocate_aligned(size, 1)
+    }
+
+    /// Allocate `size` bytes aligned to `align` (a power of two)
+    pub unsafe fn allocate_aligned(&mut self, size: usize, align: usize) -> Result<*mut u8, DBError> {
+        if !align.is_power_of_two() {
+            return Err(DBError::Memory(AllocErr::Unsupported { details: "Alignment not a power of two" }))
+        }
+
+        // Padding to align the next free byte
+        let padding = |ptr: *const u8| (align - (ptr as usize) % align) % align;
+
+        // Current chunk or the next retained (after reset) chunk with enough space
+        while self.current < self.chunks.len() {
+            let idx = self.current;
+            let used = self.used[idx];
+            let ptr = self.chunks[idx].as_mut_ptr().offset(used as isize);
+            let pad = padding(ptr);
+
+            if self.chunks[idx].len() - used >= size + pad {
+                self.used[idx] += pad + size;
+                return Ok(ptr.offset(pad as isize));
+            }
+
+            if idx + 1 == self.chunks.len() {
+                break
+            }
+            self.current += 1;
+        }
+
+        // Chunks are MIN_ALIGN aligned, so larger alignments might need padding
+        let needed = size + align.saturating_sub(MIN_ALIGN);
+        if needed > self.max_size {
+            return Err(DBError::MemoryLimit);
+        }
+
+        let mut new_size = self.chunks.last()
+            .map_or(self.min_size, |c| min(c.len() * 2, self.max_size));
+        while new_size < needed {
+            new_size = min(new_size * 2, self.max_size);
+        }
+
+        let new_arena = make_arena(self.parent, new_size)?;
+        let pad = padding(new_arena.as_ptr());
+        let ptr = new_arena.as_mut_ptr().offset(pad as isize);
+
+        self.chunks.push(new_arena);
+        self.used.push(pad + size);
+        self.current = self.chunks.len() - 1;
+        Ok(ptr)
+    }
+
+    /// Forget all the allocations, keeping the chunks for reuse. Previously returned pointers must
+    /// not be used afterwards.
+    pub fn reset(&mut self) {
+        for used in &mut self.used {
+            *used = 0;
+        }
+        self.current = 0;
+    }
+
+    /// Bytes used by allocations
+    pub fn used_bytes(&self) -> usize {
+        self.used.iter().sum()
+    }
+
+    /// Bytes allocated for all the arena chunks
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.iter()
+            .map(|c| c.len())
+            .sum()
+    }
+
+    /// Copy `data` into the arena
+    pub fn append(&mut self, data: &[u8]) -> Result<ArenaAppend, DBError> {
+        self.append_aligned(data, 1)
+    }
+
+    /// Copy `data` into the arena at an `align` aligned address
+    pub fn append_aligned(&mut self, data: &[u8], align: usize) -> Result<ArenaAppend, DBError> {
+        unsafe {
+            let ptr = self.allocate_aligned(data.len(), align)?;
+            ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+            Ok(ArenaAppend(self.current, ptr))
+        }
+    }
+}
+
+impl<'a> Drop for ChainedArena<'a> {
+    fn drop(&mut self) {
+        let mut arenas = Vec::new();
+        mem::swap(&mut arenas, &mut self.chunks);
+        for ref mut a in arenas {
+            self.parent.putback_raw(a.as_mut_ptr(), a.len(), MIN_ALIGN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::quickcheck::{Arbitrary, Gen, QuickCheck};
+
+    #[test]
+    fn heap_resize() {
+        let mut chunk = GLOBAL.allocate(0).unwrap();
+        assert_eq!(chunk.len(), 0);
+
+        chunk.resize(64).unwrap();
+        chunk.data.as_mut().unwrap()[63] = 7;
+        chunk.resize(4096).unwrap();
+        assert_eq!(chunk.data.as_ref().unwrap()[63], 7);
+        assert_eq!(unsafe { chunk.as_ptr() } as usize % MIN_ALIGN, 0);
+
+        chunk.resize(0).unwrap();
+        assert_eq!(chunk.len(), 0);
+
+        match GLOBAL.allocate_aligned(16, 3) {
+            Err(DBError::Memory(AllocErr::Unsupported { .. }))  => (),
+            _                                                   => panic!("Expected invalid alignment"),
+        }
+    }
+
+    #[test]
+    fn tracking_limits() {
+        let alloc = TrackingAllocator::new(&GLOBAL, 1000);
+
+        {
+            let mut chunk = alloc.allocate(600).unwrap();
+            assert_eq!(alloc.used(), 600);
+
+            match alloc.allocate(500) {
+                Err(DBError::MemoryLimit)   => (),
+                _                           => panic!("Expected memory limit"),
+            }
+            assert_eq!(alloc.used(), 600);
+
+            assert!(chunk.resize(2000).is_err());
+            assert!(chunk.resize(200).is_ok());
+            assert_eq!(alloc.used(), 200);
+
+            let mut arena = ChainedArena::new(&alloc, 256, 256);
+            arena.append(b"abc").unwrap();
+            assert_eq!(alloc.used(), 456);
+        }
+
+        assert_eq!(alloc.used(), 0);
+        assert_eq!(alloc.peak(), 600);
+
+        alloc.reset_peak();
+        alloc.set_limit(10).unwrap();
+        assert!(alloc.allocate(11).is_err());
+        assert_eq!(alloc.peak(), 0);
+    }
+
+    #[test]
+    fn sub_pools() {
+        let query = TrackingAllocator::new(&GLOBAL, 1000);
+
+        {
+            let scan = query.sub_pool(600).unwrap();
+            assert_eq!(query.used(), 600);
+            assert!(query.sub_pool(500).is_err());
+
+            let _chunk = scan.allocate(500).unwrap();
+            assert_eq!((scan.used(), scan.available()), (500, 100));
+            assert!(scan.allocate(200).is_err());
+
+            // Growing the budget takes it from the parent
+            assert!(scan.set_limit(1100).is_err());
+            scan.set_limit(800).unwrap();
+            assert_eq!(query.available(), 200);
+
+            let join = query.sub_pool(200).unwrap();
+            assert!(join.sub_pool(100).unwrap().allocate(100).is_ok());
+            assert_eq!(join.used(), 0);
+        }
+
+        // Dropped pools return their budget
+        assert_eq!(query.used(), 0);
+        assert_eq!(query.peak(), 1000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_allocator() {
+        let page = MMAP.page_size();
+        let mut chunk = MMAP.allocate(100).unwrap();
+        assert_eq!(chunk.len(), 100);
+        assert_eq!(unsafe { chunk.as_ptr() } as usize % page, 0);
+
+        chunk.data.as_mut().unwrap()[99] = 7;
+        assert!(chunk.resize(page + 1).is_ok());
+        assert!(chunk.resize(10 * page).is_ok());
+        assert_eq!(chunk.data.as_ref().unwrap()[99], 7);
+        assert!(MMAP.allocate_aligned(10, page * 2).is_err());
+
+        // Transparent huge pages are only a hint
+        let thp = MmapAllocator::new(HugePages::TRANSPARENT);
+        let tracking = TrackingAllocator::unlimited(&thp);
+        {
+            let mut arena = ChainedArena::new(&tracking, HUGE_PAGE_SIZE, HUGE_PAGE_SIZE);
+            assert!(arena.append(b"abc").is_ok());
+            assert_eq!(tracking.used(), HUGE_PAGE_SIZE);
+        }
+        assert_eq!(tracking.used(), 0);
+
+        // Whether explicit huge pages are available depends on the system configuration
+        let huge = MmapAllocator::new(HugePages::EXPLICIT);
+        if let Ok(chunk) = huge.allocate(10) {
+            assert_eq!(unsafe { chunk.as_ptr() } as usize % HUGE_PAGE_SIZE, 0);
+        };
+    }
+
+    #[test]
+    fn pool_reuse() {
+        let tracking = TrackingAllocator::unlimited(&GLOBAL);
+        let pool = PoolAllocator::new(&tracking, 1000, 4096, 2);
+
+        let ptr = {
+            let chunk = pool.allocate(1000).unwrap();
+            assert_eq!(chunk.len(), 1000);
+            assert_eq!(tracking.used(), 1024);
+            unsafe { chunk.as_ptr() }
+        };
+        assert_eq!(pool.free_bytes(), 1024);
+
+        // Same size class comes from the free list
+        let mut chunk = pool.allocate(600).unwrap();
+        assert_eq!(unsafe { chunk.as_ptr() }, ptr);
+        assert_eq!(pool.free_bytes(), 0);
+
+        chunk.data.as_mut().unwrap()[0] = 42;
+        assert!(chunk.resize(1024).is_ok());
+        assert_eq!(unsafe { chunk.as_ptr() }, ptr);
+        assert!(chunk.resize(3000).is_ok());
+        assert_eq!(chunk.data.as_ref().unwrap()[0], 42);
+        assert_eq!(pool.free_bytes(), 1024);
+
+        // Not pooled
+        drop(pool.allocate(10000).unwrap());
+        drop(pool.allocate_aligned(100, 4096).unwrap());
+        drop(chunk);
+        assert_eq!(pool.free_bytes(), 1024 + 4096);
+
+        // Free lists are bounded
+        {
+            let _chunks: Vec<_> = (0 .. 3).map(|_| pool.allocate(1024).unwrap()).collect();
+        }
+        assert_eq!(pool.free_bytes(), 2 * 1024 + 4096);
+
+        pool.trim();
+        assert_eq!(tracking.used(), 0);
+    }
+
+    #[test]
+    fn spill_unpinned() {
+        let alloc = SpillableAllocator::temp(&GLOBAL, 3000);
+        let mut a = alloc.allocate(1000).unwrap();
+        let mut b = alloc.allocate(1000).unwrap();
+        for (v, chunk) in vec![&mut a, &mut b].into_iter().enumerate() {
+            for byte in chunk.data.as_mut().unwrap().iter_mut() {
+                *byte = v as u8 + 1;
+            }
+        }
+
+        a.unpin().unwrap();
+        b.unpin().unwrap();
+        assert!(a.data.is_none() && !a.is_pinned());
+
+        // The least recently unpinned chunk is spilled first
+        let c = alloc.allocate(2000).unwrap();
+        assert_eq!((alloc.used(), alloc.spilled()), (3000, 1000));
+
+        a.pin().unwrap();
+        assert!(a.data.as_ref().unwrap().iter().all(|v| *v == 1));
+        assert_eq!((alloc.used(), alloc.spilled()), (3000, 1000));
+
+        // Nothing left to spill
+        match b.pin() {
+            Err(DBError::MemoryLimit)   => (),
+            _                           => panic!("Expected memory limit"),
+        }
+        assert!(alloc.allocate(1).is_err());
+
+        drop(c);
+        b.pin().unwrap();
+        assert!(b.is_pinned());
+        assert!(b.data.as_ref().unwrap().iter().all(|v| *v == 2));
+
+        a.unpin().unwrap();
+        drop(a);
+        drop(b);
+        assert_eq!((alloc.used(), alloc.spilled()), (0, 0));
+
+        let path = alloc.state.lock().unwrap().file.as_ref().unwrap().1.clone();
+        assert!(path.exists());
+        drop(alloc);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn arena_alignment() {
+        let mut arena = ChainedArena::new(&GLOBAL, 64, 4096);
+        let mut values = Vec::new();
+
+        for (idx, align) in vec![1, 8, 2, 16, 4, 64, 8, 128, 1, 32].into_iter().cycle().take(60).enumerate() {
+            let data = vec![idx as u8; 1 + idx % 23];
+            let ArenaAppend(chunk, ptr) = arena.append_aligned(&data, align).unwrap();
+            assert_eq!(ptr as usize % align, 0);
+            values.push((chunk, ptr, data));
+        }
+
+        // Filled multiple chunks without values overlapping
+        assert!(values.last().unwrap().0 > 1);
+        for &(_, ptr, ref data) in &values {
+            assert_eq!(unsafe { ::std::slice::from_raw_parts(ptr, data.len()) }, &data[..]);
+        }
+
+        // Padding for large alignments is accounted for
+        assert!(arena.used_bytes() >= values.iter().map(|v| v.2.len()).sum::<usize>());
+        assert!(arena.append_aligned(&[0; 4096], 64).is_err());
+        assert!(arena.append_aligned(&[0; 4000], 64).is_ok());
+        assert!(arena.append_aligned(b"x", 3).is_err());
+    }
+
+    #[test]
+    fn observed_metrics() {
+        let metrics = AllocMetrics::new();
+        let scan = ObservedAllocator::new(&GLOBAL, &metrics, "scan");
+        let sort = scan.tagged("sort");
+
+        {
+            let mut chunk = scan.allocate(100).unwrap();
+            assert!(chunk.resize(300).is_ok());
+            let _other = scan.allocate(50).unwrap();
+
+            let mut arena = ChainedArena::new(&sort, 64, 64);
+            arena.append(b"abc").unwrap();
+
+            assert_eq!(metrics.get("scan").used, 350);
+            assert_eq!(metrics.get("sort").used, 64);
+        }
+
+        assert_eq!(metrics.get("scan"), AllocStats {
+            allocations: 2, resizes: 1, frees: 2, used: 0, peak: 350, total: 350,
+        });
+        assert_eq!(metrics.all().iter().map(|t| t.0.as_str()).collect::<Vec<_>>(), vec!["scan", "sort"]);
+        assert_eq!(metrics.get("join"), AllocStats::default());
+    }
+
+    #[test]
+    fn observed_unpinned_free() {
+        let metrics = AllocMetrics::new();
+        let spill = SpillableAllocator::temp(&GLOBAL, 1000);
+        let alloc = ObservedAllocator::new(&spill, &metrics, "sort");
+
+        let mut a = alloc.allocate(600).unwrap();
+        let mut b = alloc.allocate(300).unwrap();
+        a.unpin().unwrap();
+        b.unpin().unwrap();
+        assert_eq!((a.len(), b.len()), (600, 300));
+
+        // Spills `a`
+        let c = alloc.allocate(700).unwrap();
+        assert_eq!(spill.spilled(), 600);
+        assert_eq!(metrics.get("sort").used, 1600);
+
+        drop(a);
+        drop(b);
+        drop(c);
+        assert_eq!((spill.used(), spill.spilled()), (0, 0));
+        assert_eq!(metrics.get("sort").used, 0);
+        assert_eq!(metrics.get("sort").frees, 3);
+    }
+
+    #[test]
+    fn arena_reset() {
+        let alloc = TrackingAllocator::unlimited(&GLOBAL);
+        let mut arena = ChainedArena::new(&alloc, 64, 1024);
+
+        let values: Vec<Vec<u8>> = (0 .. 20u8).map(|v| vec![v; 10 + v as usize]).collect();
+        let mut ptrs = Vec::new();
+        for v in &values {
+            ptrs.push(arena.append(v).unwrap().1);
+        }
+
+        // Values spanning chunks don't overlap
+        for (v, ptr) in values.iter().zip(&ptrs) {
+            assert_eq!(unsafe { ::std::slice::from_raw_parts(*ptr, v.len()) }, &v[..]);
+        }
+        assert_eq!(arena.used_bytes(), values.iter().map(|v| v.len()).sum::<usize>());
+
+        // Reuse without allocating
+        let allocated = alloc.used();
+        arena.reset();
+        assert_eq!(arena.used_bytes(), 0);
+
+        for v in &values {
+            let ArenaAppend(_, ptr) = arena.append(v).unwrap();
+            assert_eq!(unsafe { ::std::slice::from_raw_parts(ptr, v.len()) }, &v[..]);
+        }
+        assert_eq!(alloc.used(), allocated);
+
+        // Larger than the next chunk size
+        assert!(arena.append(&[1; 1000]).is_ok());
+        assert!(arena.append(&[1; 2000]).is_err());
+    }
+
+    /// Random chunk life cycles
+    #[derive(Clone, Debug)]
+    enum ChunkOp {
+        Allocate(usize),
+        /// Resize the (index modulo live chunks) chunk
+        Resize(usize, usize),
+        Free(usize),
+    }
+
+    impl Arbitrary for ChunkOp {
+        fn arbitrary<G: Gen>(g: &mut G) -> ChunkOp {
+            let size = g.gen_range(1, 10001);
+            match g.gen_range(0, 3) {
+                0 => ChunkOp::Allocate(size),
+                1 => ChunkOp::Resize(g.gen(), size),
+                _ => ChunkOp::Free(g.gen()),
+            }
+        }
+    }
+
+    fn filled(chunk: &OwnedChunk, byte: u8) -> bool {
+        chunk.data.as_ref().map_or(false, |d| d.iter().all(|b| *b == byte))
+    }
+
+    /// Every live chunk keeps its contents; resizes keep the common prefix
+    fn chunk_ops(alloc: &Allocator, ops: &[ChunkOp]) -> bool {
+        let mut chunks: Vec<(u8, OwnedChunk)> = Vec::new();
+
+        for (idx, op) in ops.iter().enumerate() {
+            let byte = idx as u8;
+
+            match *op {
+                ChunkOp::Allocate(size) => {
+                    let mut chunk = alloc.allocate(size).unwrap();
+                    for b in chunk.data.as_mut().unwrap().iter_mut() {
+                        *b = byte;
+                    }
+                    chunks.push((byte, chunk));
+                }
+                ChunkOp::Resize(pos, size) if !chunks.is_empty() => {
+                    let pos = pos % chunks.len();
+                    let (fill, ref mut chunk) = chunks[pos];
+                    let prev = chunk.len();
+                    chunk.resize(size).unwrap();
+
+                    if chunk.len() != size || chunk.data.as_ref().unwrap()[.. min(prev, size)].iter().any(|b| *b != fill) {
+                        return false
+                    }
+                    for b in chunk.data.as_mut().unwrap()[min(prev, size) ..].iter_mut() {
+                        *b = fill;
+                    }
+                }
+                ChunkOp::Free(pos) if !chunks.is_empty() => {
+                    let pos = pos % chunks.len();
+                    chunks.swap_remove(pos);
+                }
+                _ => (),
+            }
+
+            if !chunks.iter().all(|&(fill, ref chunk)| filled(chunk, fill)) {
+                return false
+            }
+        }
+
+        true
+    }
+
+    fn allocator_ops(ops: Vec<ChunkOp>) -> bool {
+        let tracking = TrackingAllocator::unlimited(&GLOBAL);
+        if !chunk_ops(&tracking, &ops) || tracking.used() != 0 {
+            return false
+        }
+
+        let pool = PoolAllocator::new(&tracking, 64, 4096, 2);
+        if !chunk_ops(&pool, &ops) {
+            return false
+        }
+        pool.trim();
+
+        tracking.used() == 0
+    }
+
+    #[test]
+    fn allocator_properties() {
+        QuickCheck::new().tests(100).quickcheck(allocator_ops as fn(Vec<ChunkOp>) -> bool);
+    }
+
+    /// Arena appends of (length, alignment shift), `None` resets the arena
+    fn arena_ops(ops: Vec<Option<(u16, u8)>>) -> bool {
+        let tracking = TrackingAllocator::unlimited(&GLOBAL);
+        let mut arena = ChainedArena::new(&tracking, 64, 4096);
+        let mut values: Vec<(*mut u8, Vec<u8>)> = Vec::new();
+
+        for (idx, op) in ops.into_iter().enumerate() {
+            match op {
+                Some((len, shift)) => {
+                    let align = 1 << (shift % 8);
+                    let data = vec![idx as u8; len as usize % 3000];
+                    let ArenaAppend(_, ptr) = arena.append_aligned(&data, align).unwrap();
+                    if ptr as usize % align != 0 {
+                        return false
+                    }
+                    values.push((ptr, data));
+                }
+                None => {
+                    arena.reset();
+                    values.clear();
+                }
+            }
+
+            let intact = values.iter()
+                .all(|&(ptr, ref data)| unsafe { slice::from_raw_parts(ptr, data.len()) } == &data[..]);
+
+            if !intact
+                || arena.used_bytes() < values.iter().map(|v| v.1.len()).sum::<usize>()
+                || arena.used_bytes() > arena.allocated_bytes()
+                || tracking.used() != arena.allocated_bytes()
+            {
+                return false
+            }
+        }
+
+        drop(arena);
+        tracking.used() == 0
+    }
+
+    #[test]
+    fn arena_properties() {
+        QuickCheck::new().tests(100).quickcheck(arena_ops as fn(Vec<Option<(u16, u8)>>) -> bool);
+    }
+}
@@ -1,6 +1,305 @@
 // vim : set ts=4 sw=4 et :
 
-type BoolBitmap = &[u8];
+use ::util::bitmap::{self, get_bit, set_bit};
 
+pub use ::util::bitmap::bytes_for;
 
+/// Read only bit-packed bitmap (one bit per row, least significant bit first).
+///
+/// Used for `Column` null vectors where a set bit means the row is NULL. A bitmap may start at a
+/// bit offset into its first byte; this happens when aliasing a range of rows.
+#[derive(Clone, Copy)]
+pub struct Bitmap<'a> {
+    data: &'a [u8],
+    offset: usize,
+    len: usize,
+}
 
+/// Mutable bit-packed bitmap. Always starts at the beginning of the first byte.
+pub struct MutBitmap<'a> {
+    data: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Bitmap<'a> {
+    /// Bitmap of `len` bits starting at bit `offset` of `data`
+    pub fn new(data: &'a [u8], offset: usize, len: usize) -> Bitmap<'a> {
+        assert!(bytes_for(offset + len) <= data.len(), "Bitmap data too short");
+        Bitmap { data: data, offset: offset, len: len }
+    }
+
+    pub fn empty() -> Bitmap<'static> {
+        Bitmap { data: &[], offset: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bit offset into the first byte of `raw()`
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Underlying bytes
+    pub fn raw(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Panics on out of bounds access
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "Bitmap index out of bounds");
+        get_bit(self.data, self.offset + idx)
+    }
+
+    /// Sub-range of the bitmap
+    pub fn slice(&self, offset: usize, len: usize) -> Bitmap<'a> {
+        assert!(offset + len <= self.len, "Bitmap slice out of bounds");
+        let start = self.offset + offset;
+        Bitmap { data: &self.data[start >> 3 ..], offset: start & 7, len: len }
+    }
+
+    /// Number of set bits
+    pub fn count_ones(&self) -> usize {
+        let mut count = 0;
+        let mut idx = 0;
+
+        // Unaligned head
+        while idx < self.len && (self.offset + idx) & 7 != 0 {
+            count += self.get(idx) as usize;
+            idx += 1;
+        }
+
+        count + bitmap::count_ones(&self.data[(self.offset + idx) >> 3 ..], self.len - idx)
+    }
+
+    /// Any bit set
+    pub fn any(&self) -> bool {
+        self.first_set(0).is_some()
+    }
+
+    /// Position of the first set bit at or after `from`
+    pub fn first_set(&self, from: usize) -> Option<usize> {
+        bitmap::find_first_set(self.data, self.offset + from, self.offset + self.len)
+            .map(|pos| pos - self.offset)
+    }
+
+    /// Runs of consecutive set bits as (start, len)
+    pub fn set_runs(&self) -> Box<Iterator<Item=(usize, usize)> + 'a> {
+        let offset = self.offset;
+        Box::new(bitmap::set_runs(self.data, offset, offset + self.len)
+            .map(move |(start, len)| (start - offset, len)))
+    }
+
+    pub fn iter(&self) -> BitmapIter<'a> {
+        BitmapIter { bitmap: *self, pos: 0 }
+    }
+}
+
+pub struct BitmapIter<'a> {
+    bitmap: Bitmap<'a>,
+    pos: usize,
+}
+
+impl<'a> Iterator for BitmapIter<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.pos >= self.bitmap.len {
+            return None
+        }
+
+        self.pos += 1;
+        Some(self.bitmap.get(self.pos - 1))
+    }
+}
+
+impl<'a> MutBitmap<'a> {
+    pub fn new(data: &'a mut [u8], len: usize) -> MutBitmap<'a> {
+        assert!(bytes_for(len) <= data.len(), "Bitmap data too short");
+        MutBitmap { data: data, len: len }
+    }
+
+    pub fn empty() -> MutBitmap<'static> {
+        MutBitmap { data: &mut [], len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bitmap(&self) -> Bitmap {
+        Bitmap { data: self.data, offset: 0, len: self.len }
+    }
+
+    /// Panics on out of bounds access
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len, "Bitmap index out of bounds");
+        get_bit(self.data, idx)
+    }
+
+    /// Panics on out of bounds access
+    #[inline]
+    pub fn set(&mut self, idx: usize, value: bool) {
+        assert!(idx < self.len, "Bitmap index out of bounds");
+        set_bit(self.data, idx, value)
+    }
+
+    /// Set bits [offset, offset + len) to `value`
+    pub fn fill(&mut self, offset: usize, len: usize, value: bool) {
+        assert!(offset + len <= self.len, "Bitmap fill out of bounds");
+
+        let mut idx = offset;
+        let end = offset + len;
+
+        while idx < end && idx & 7 != 0 {
+            set_bit(self.data, idx, value);
+            idx += 1;
+        }
+
+        let whole = (end - idx) >> 3;
+        for byte in &mut self.data[idx >> 3 .. (idx >> 3) + whole] {
+            *byte = if value { 0xFF } else { 0 };
+        }
+        idx += whole << 3;
+
+        while idx < end {
+            set_bit(self.data, idx, value);
+            idx += 1;
+        }
+    }
+
+    /// Copy `len` bits from `src` (starting at `src_offset`) to `offset`
+    pub fn copy_from(&mut self, offset: usize, src: &Bitmap, src_offset: usize, len: usize) {
+        assert!(offset + len <= self.len, "Bitmap copy out of bounds");
+        let src = src.slice(src_offset, len);
+
+        // Fast path for byte aligned copies
+        if offset & 7 == 0 && src.offset == 0 {
+            let whole = len >> 3;
+            let dst_start = offset >> 3;
+            self.data[dst_start .. dst_start + whole].copy_from_slice(&src.data[.. whole]);
+
+            for idx in whole << 3 .. len {
+                set_bit(self.data, offset + idx, src.get(idx));
+            }
+        } else {
+            for idx in 0 .. len {
+                set_bit(self.data, offset + idx, src.get(idx));
+            }
+        }
+    }
+
+    /// self |= other. Bitmaps have to be the same length.
+    pub fn or(&mut self, other: &Bitmap) {
+        self.combine(other, bitmap::or, |l, r| l | r)
+    }
+
+    /// self &= other. Bitmaps have to be the same length.
+    pub fn and(&mut self, other: &Bitmap) {
+        self.combine(other, bitmap::and, |l, r| l & r)
+    }
+
+    /// self &= !other. Bitmaps have to be the same length.
+    pub fn and_not(&mut self, other: &Bitmap) {
+        self.combine(other, bitmap::and_not, |l, r| l & !r)
+    }
+
+    /// Flip all bits
+    pub fn not(&mut self) {
+        let bytes = bytes_for(self.len);
+        bitmap::not(&mut self.data[.. bytes]);
+        self.clear_tail()
+    }
+
+    /// Clear the bits of the last byte past `len`; the byte kernels work on whole bytes
+    fn clear_tail(&mut self) {
+        let tail = self.len & 7;
+        if tail != 0 {
+            self.data[self.len >> 3] &= (1u8 << tail) - 1;
+        }
+    }
+
+    /// Uses the word level kernel when both bitmaps are byte aligned, falls back on bit at a time
+    /// otherwise.
+    #[inline]
+    fn combine<W, B>(&mut self, other: &Bitmap, word_op: W, bit_op: B)
+        where W: Fn(&mut [u8], &[u8]), B: Fn(bool, bool) -> bool
+    {
+        assert_eq!(self.len, other.len, "Bitmap length mismatch");
+
+        if other.offset == 0 {
+            let bytes = bytes_for(self.len);
+            word_op(&mut self.data[.. bytes], &other.data[.. bytes]);
+            return self.clear_tail()
+        }
+
+        for idx in 0 .. self.len {
+            let value = bit_op(get_bit(self.data, idx), other.get(idx));
+            set_bit(self.data, idx, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_ops() {
+        let mut data = [0u8; 24];
+        let mut bits = MutBitmap::new(&mut data, 190);
+
+        bits.fill(3, 150, true);
+        bits.set(100, false);
+        assert_eq!(bits.as_bitmap().count_ones(), 149);
+        assert!(!bits.get(2) && bits.get(3) && !bits.get(100) && !bits.get(153));
+
+        let slice = bits.as_bitmap().slice(99, 3);
+        assert_eq!(slice.iter().collect::<Vec<_>>(), vec![true, false, true]);
+
+        let mut other_data = [0u8; 24];
+        let mut other = MutBitmap::new(&mut other_data, 190);
+        other.fill(0, 190, true);
+        other.and(&bits.as_bitmap());
+        assert_eq!(other.as_bitmap().count_ones(), 149);
+
+        other.fill(0, 190, false);
+        other.copy_from(5, &bits.as_bitmap(), 99, 3);
+        assert_eq!(other.as_bitmap().slice(5, 3).iter().collect::<Vec<_>>(), vec![true, false, true]);
+
+        let runs: Vec<_> = bits.as_bitmap().slice(1, 150).set_runs().collect();
+        assert_eq!(runs, vec![(2, 97), (100, 50)]);
+        assert_eq!(bits.as_bitmap().slice(1, 150).first_set(3), Some(3));
+
+        other.not();
+        assert_eq!(other.as_bitmap().count_ones(), 188);
+        // No bits set past the length
+        assert_eq!(other_data[23], 0x3F);
+    }
+
+    #[test]
+    fn tail_bits() {
+        let mut data = [0u8; 2];
+        let mut bits = MutBitmap::new(&mut data, 11);
+        bits.not();
+        assert_eq!(bits.as_bitmap().count_ones(), 11);
+
+        // The other bitmap has garbage past its length
+        let garbage = [0u8, 0xFF];
+        bits.fill(0, 11, false);
+        bits.or(&Bitmap::new(&garbage, 0, 11));
+        assert_eq!(bits.as_bitmap().count_ones(), 3);
+        assert_eq!(data, [0, 0x07]);
+    }
+}
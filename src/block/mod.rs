@@ -0,0 +1,2683 @@
+// vim : set ts=4 sw=4 et :
+
+// libstd
+use std::borrow::Cow;
+use std::cell::UnsafeCell;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::ops::{Add, AddAssign, Index, IndexMut};
+
+// DBKit
+use ::allocator::{Allocator, OwnedChunk, ChainedArena, MIN_ALIGN};
+use ::bitmaps::bytes_for;
+use ::types::{self, ListEntry, Type, Value, ValueInfo};
+use ::schema::{Attribute, Schema};
+use ::error::DBError;
+use ::record::{Record, read_records};
+use ::row::{RowGetter, RowOffset, RowRange, RowRef};
+use ::util::copy_value::ValueSetter;
+use ::util::math::*;
+use ::util::selection::selected_rows;
+
+pub use ::bitmaps::{Bitmap, MutBitmap};
+pub use self::shared::{SharedBlock, SharedView};
+pub use self::stats::{ColumnStats, ZoneMap};
+
+/// Reference counted, copy-on-write blocks
+pub mod shared;
+/// Native binary serialization of Blocks
+pub mod serialize;
+/// Column data statistics
+pub mod stats;
+
+/// Width in bytes of the widest SIMD registers (AVX-512) used by vectorized kernels.
+///
+/// Column value vectors and null bitmaps are padded to a multiple of it; see `RefColumn`.
+pub const SIMD_WIDTH: usize = 64;
+
+/// Starting size for the VARLEN arena
+const ARENA_MIN_SIZE : usize = MIN_ALIGN;
+
+/// Limit on arena chunk size. This is also on the largest VARLEN value in Columns.
+/// Currently the limit for large blobs / text is up to 16MB.
+const ARENA_MAX_SIZE : usize = 16 * 1024 * 1024;
+
+/// Memory used by column data, in bytes
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct MemoryUsage {
+    /// Value vectors (including nested type entries)
+    pub values: usize,
+    /// Null bitmaps
+    pub nulls: usize,
+    /// VARLEN data arena chunks
+    pub arena: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.values + self.nulls + self.arena
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            values: self.values + other.values,
+            nulls: self.nulls + other.nulls,
+            arena: self.arena + other.arena,
+        }
+    }
+}
+
+impl AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: MemoryUsage) {
+        *self = *self + other;
+    }
+}
+
+pub struct ColumnRows<'a, T: ValueInfo>
+    where <T as ValueInfo>::Store: 'a
+{
+    pub values: &'a [T::Store],
+    pub nulls: Bitmap<'a>,
+}
+
+pub struct ColumnRowsMut<'a, T: ValueInfo>
+    where <T as ValueInfo>::Store: 'a
+{
+    pub values: &'a mut [T::Store],
+    pub nulls: MutBitmap<'a>,
+}
+
+/// Trait representing a reference to column data.
+/// Data can be owned by current object or references from another one.
+///
+/// Columns owning their data (`Column`) are SIMD padded: the value vector is `SIMD_WIDTH`
+/// aligned and `capacity() * size_of` is a multiple of `SIMD_WIDTH` (`set_capacity` rounds the
+/// capacity up), likewise the null bitmap. Rows past the block's row count are initialized, so
+/// vectorized kernels can read full lanes up to `padded_rows(dtype, rows)` without a scalar tail.
+/// Views of other data (aliases, windows) make no such guarantee.
+pub trait RefColumn<'re> {
+    fn attribute(&self) -> &Attribute;
+    fn capacity(&self) -> usize;
+
+    /// Will panic if there's no row data
+    fn rows_raw_slice(&'re self) -> &'re [u8];
+    /// Will panic if there's no null data
+    fn nulls_raw_slice(&'re self) -> &'re [u8];
+
+    /// Pointer to the beginning of the raw row data.
+    /// ptr can be nil
+    unsafe fn rows_ptr(&self) -> *const u8;
+    /// Pointer to the beginning of the raw null bitmap.
+    /// ptr can be nil
+    unsafe fn nulls_ptr(&self) -> *const u8;
+    /// Bit offset of the first row in the null bitmap
+    fn nulls_offset(&self) -> usize {
+        0
+    }
+
+    /// Child column of a nested type (LIST element column or STRUCT field column).
+    fn child(&'re self, pos: usize) -> Option<&'re RefColumn<'re>>;
+
+    /// Dictionary encoding of the rows, if the column has one
+    fn dictionary(&'re self) -> Option<Dictionary<'re>> {
+        None
+    }
+
+    /// Run-length encoding of the rows, if the column has one
+    fn runs(&'re self) -> Option<Runs<'re>> {
+        None
+    }
+
+    /// Decode lazily encoded row data (`RleColumn`, `DictColumn`) so the raw accessors can return
+    /// it. Encoded columns return null / empty raw data if decoding failed.
+    fn materialize(&self) -> Result<(), DBError> {
+        Ok(())
+    }
+}
+
+/// Dictionary encoded rows; row `i` has the value of `values` row `codes[i]`. NULL is stored as a
+/// dictionary value.
+#[derive(Clone, Copy)]
+pub struct Dictionary<'a> {
+    pub values: &'a RefColumn<'a>,
+    pub codes: &'a [u32],
+}
+
+/// Run-length encoded rows; run `i` has the value of `values` row `i` and ends (exclusive) at row
+/// `ends[i] - offset`. Aliases of a row range keep the source's run ends, the last run is cut
+/// short at `rows`.
+#[derive(Clone, Copy)]
+pub struct Runs<'a> {
+    pub values: &'a RefColumn<'a>,
+    pub ends: &'a [RowOffset],
+    /// Source row of the first row
+    pub offset: RowOffset,
+    pub rows: RowOffset,
+}
+
+impl<'a> Runs<'a> {
+    pub fn count(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Rows covered by the run
+    pub fn range(&self, run: usize) -> RowRange {
+        let start = if run == 0 { 0 } else { self.ends[run - 1] - self.offset };
+        let end = min(self.ends[run] - self.offset, self.rows);
+        RowRange { offset: start, rows: end - start }
+    }
+
+    /// Run containing the row
+    pub fn run_of(&self, row: RowOffset) -> Result<usize, DBError> {
+        if row >= self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok(match self.ends.binary_search(&(self.offset + row)) {
+            Ok(pos)     => pos + 1,
+            Err(pos)    => pos,
+        })
+    }
+}
+
+/// Rows rounded up so their values fill whole `SIMD_WIDTH` lanes
+pub fn padded_rows(dtype: Type, rows: RowOffset) -> RowOffset {
+    let size = dtype.size_of();
+    round_up(rows * size, SIMD_WIDTH) / size
+}
+
+/// Helper badness for converting raw column data into a typed slice of rows.
+// It's not really 'static, but we don't have enough context in thi
+#[inline]
+unsafe fn rows_from_rawptr<'a, T>(ptr: *mut u8, elems: usize) -> &'a mut [T] {
+    let typed_ptr: *mut T = mem::transmute(ptr);
+    if !typed_ptr.is_null() {
+        slice::from_raw_parts_mut(typed_ptr, elems)
+    } else {
+        &mut []
+    }
+}
+
+/// Helper badness for converting raw column data into a slice of row's null bitmap.
+// It's not really 'static, but we don't have enough context in thi
+#[inline]
+unsafe fn rows_from_rawptr_const<'a, T>(ptr: *const u8, elems: usize) -> &'a [T] {
+    let typed_ptr: *const T = mem::transmute(ptr);
+    if !typed_ptr.is_null() {
+        slice::from_raw_parts(typed_ptr, elems)
+    } else {
+        &[]
+    }
+}
+
+/// Zero length VARLEN value. Points at a valid (empty) location rather than null.
+fn empty_raw_data() -> types::RawData {
+    types::RawData { data: b"".as_ptr() as *mut u8, size: 0 }
+}
+
+/// Two slices. One representing the column value vector (row data). Second representing the column
+/// null vector (row data).
+// RUST FRUSTRATION: wish this could be part of `RefColumn`.
+// Can't have generics methods be part of the trait... even if there's default trait implementation.
+//
+// Inline: so we can optimize away a bunch of code (like we're not using nulls in context)
+#[inline]
+pub fn column_row_data<'c, T: ValueInfo>(col: &'c RefColumn) -> Result<ColumnRows<'c, T>, DBError> {
+    let attr = col.attribute();
+    let rows = col.capacity();
+
+    if attr.dtype != T::ENUM {
+        return Err(DBError::AttributeType(attr.name.clone()))
+    }
+
+    col.materialize()?;
+
+    unsafe {
+        Ok(ColumnRows{
+            values: rows_from_rawptr_const::<T::Store>(col.rows_ptr(), rows),
+            nulls: column_nulls(col),
+        })
+    }
+}
+
+/// Null bitmap of the column. Empty for columns that are not nullable.
+#[inline]
+pub fn column_nulls<'c>(col: &'c RefColumn) -> Bitmap<'c> {
+    unsafe {
+        let ptr = col.nulls_ptr();
+        if ptr.is_null() {
+            return Bitmap::empty()
+        }
+
+        let offset = col.nulls_offset();
+        let rows = col.capacity();
+        Bitmap::new(rows_from_rawptr_const::<u8>(ptr, bytes_for(offset + rows)), offset, rows)
+    }
+}
+
+/// Read a single column row as a `Value`. Nested values are read recursively.
+pub fn column_value<'c>(col: &'c RefColumn<'c>, row: RowOffset) -> Result<Value<'c>, DBError> {
+    if row >= col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    col.materialize()?;
+
+    let attr = col.attribute();
+    if attr.nullable && column_nulls(col).get(row) {
+        return Ok(Value::NULL)
+    }
+
+    let value = match attr.dtype {
+        Type::UINT32    => Value::UINT32(column_row_data::<types::UInt32>(col)?.values[row]),
+        Type::UINT64    => Value::UINT64(column_row_data::<types::UInt64>(col)?.values[row]),
+        Type::INT32     => Value::INT32(column_row_data::<types::Int32>(col)?.values[row]),
+        Type::INT64     => Value::INT64(column_row_data::<types::Int64>(col)?.values[row]),
+        Type::FLOAT32   => Value::FLOAT32(column_row_data::<types::Float32>(col)?.values[row]),
+        Type::FLOAT64   => Value::FLOAT64(column_row_data::<types::Float64>(col)?.values[row]),
+        Type::BOOLEAN   => Value::BOOLEAN(column_row_data::<types::Boolean>(col)?.values[row]),
+        Type::TIMESTAMP     => Value::TIMESTAMP(column_row_data::<types::Timestamp>(col)?.values[row]),
+        Type::TIMESTAMP_TZ  => Value::TIMESTAMP_TZ(column_row_data::<types::TimestampTz>(col)?.values[row]),
+        Type::TEXT      => {
+            let raw: &'c types::RawData = &column_row_data::<types::Text>(col)?.values[row];
+            Value::TEXT(Cow::Borrowed(raw.as_ref()))
+        }
+        Type::JSON      => {
+            let raw: &'c types::RawData = &column_row_data::<types::Json>(col)?.values[row];
+            Value::JSON(Cow::Borrowed(raw.as_ref()))
+        }
+        Type::BLOB      => {
+            let raw: &'c types::RawData = &column_row_data::<types::Blob>(col)?.values[row];
+            Value::BLOB(Cow::Borrowed(raw.as_ref()))
+        }
+        Type::LIST      => {
+            let entry = column_row_data::<types::List>(col)?.values[row];
+            let elems = col.child(0).ok_or(DBError::make_column_unknown_pos(0))?;
+            let mut out = Vec::with_capacity(entry.len);
+            for idx in entry.offset .. entry.offset + entry.len {
+                out.push(column_value(elems, idx)?);
+            }
+            Value::LIST(out)
+        }
+        Type::STRUCT    => {
+            let mut out = Vec::with_capacity(attr.children.len());
+            for pos in 0 .. attr.children.len() {
+                let field = col.child(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                out.push(column_value(field, row)?);
+            }
+            Value::STRUCT(out)
+        }
+        Type::MAP       => {
+            let entry = column_row_data::<types::Map>(col)?.values[row];
+            let keys = col.child(0).ok_or(DBError::make_column_unknown_pos(0))?;
+            let values = col.child(1).ok_or(DBError::make_column_unknown_pos(1))?;
+            let mut out = Vec::with_capacity(entry.len);
+            for idx in entry.offset .. entry.offset + entry.len {
+                out.push((column_value(keys, idx)?, column_value(values, idx)?));
+            }
+            Value::MAP(out)
+        }
+    };
+
+    Ok(value)
+}
+
+/// Typed Data Column. Contains a vector of column rows, and optionally a nul vector.
+///
+/// Knows its capacity but not size, has no concept of current. Those properties are fulfilled by
+/// it's parent container (types such as Block).
+pub struct Column<'alloc> {
+    allocator: &'alloc Allocator,
+    attr: Attribute,
+    raw_nulls: OwnedChunk<'alloc>,
+    raw: OwnedChunk<'alloc>,
+    /// Used to store varlen column values
+    arena: ChainedArena<'alloc>,
+    /// Nested type columns; LIST element column or STRUCT field columns
+    children: Vec<Column<'alloc>>,
+    /// Count of used rows in a LIST / MAP child columns
+    list_rows: RowOffset,
+}
+
+/// Typed Data Column that references another column
+#[derive(Clone)]
+pub struct AliasColumn<'parent> {
+    attr: Attribute,
+    raw_nulls: &'parent [u8],
+    /// Bit offset of the first row in `raw_nulls`
+    nulls_offset: usize,
+    raw: &'parent [u8],
+    children: Vec<AliasColumn<'parent>>,
+    /// Dictionary values and the codes of the aliased rows, if the source is dictionary encoded
+    dict: Option<(Box<AliasColumn<'parent>>, &'parent [u32])>,
+    /// Run values, ends and source row of the first row, if the source is run-length encoded
+    runs: Option<(Box<AliasColumn<'parent>>, &'parent [RowOffset], RowOffset)>,
+}
+
+/// Create another read only alias of a column
+///
+/// If no range is specified, aliases the whole column source column.
+pub fn alias_column<'a>(src: &'a RefColumn<'a>, range: Option<RowRange>)
+    -> Result<AliasColumn<'a>, DBError>
+{
+    let range = range.unwrap_or(RowRange { offset: 0, rows: src.capacity() });
+    alias_column_range(src, range)
+}
+
+/// Create a read only alias of a sub-range of rows of a column.
+///
+/// Validates that the range is within the column, that the source row & null data is large enough
+/// for the range and that the aliased row data is correctly aligned for the column type.
+pub fn alias_column_range<'a>(src: &'a RefColumn<'a>, range: RowRange)
+    -> Result<AliasColumn<'a>, DBError>
+{
+    let (offset, rows) = (range.offset, range.rows);
+    let dtype = src.attribute().dtype;
+
+    if offset + rows > src.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    src.materialize()?;
+
+    let size_of = dtype.size_of();
+    let start = offset * size_of;
+    let len = rows * size_of;
+
+    let raw = src.rows_raw_slice();
+    if start + len > raw.len() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let col = &raw[start .. start + len];
+    if !col.is_empty() && (col.as_ptr() as usize) % dtype.align_of() != 0 {
+        return Err(DBError::AttributeType(format!("{} (misaligned alias)", src.attribute().name)))
+    }
+
+    // Row ranges don't have to start on a byte boundary of the null bitmap
+    let (nulls, nulls_offset) = if src.attribute().nullable {
+        let raw = src.nulls_raw_slice();
+        let bit = src.nulls_offset() + offset;
+        let start = bit / 8;
+        let len = bytes_for(bit % 8 + rows);
+
+        if start + len > raw.len() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        (&raw[start .. start + len], bit % 8)
+    } else {
+        (&[] as &[u8], 0)
+    };
+
+    // STRUCT fields are row aligned with the parent. LIST & MAP entries point at absolute child rows
+    // so the whole child column is aliased.
+    let children_count = src.attribute().children.len();
+    let mut children = Vec::with_capacity(children_count);
+    for pos in 0 .. children_count {
+        let child = src.child(pos)
+            .ok_or(DBError::make_column_unknown_pos(pos))?;
+
+        let child_range = match dtype {
+            Type::STRUCT    => range,
+            _               => RowRange { offset: 0, rows: child.capacity() },
+        };
+
+        children.push(alias_column_range(child, child_range)?);
+    }
+
+    let dict = match src.dictionary() {
+        Some(d) => Some((Box::new(alias_column(d.values, None)?), &d.codes[offset .. offset + rows])),
+        None    => None,
+    };
+
+    // Only the runs overlapping the range
+    let runs = match src.runs() {
+        Some(r) if rows > 0 => {
+            let first = r.run_of(offset)?;
+            let last = r.run_of(offset + rows - 1)?;
+            let values = alias_column_range(r.values, RowRange { offset: first, rows: last + 1 - first })?;
+            Some((Box::new(values), &r.ends[first .. last + 1], r.offset + offset))
+        }
+        _                   => None,
+    };
+
+    Ok(AliasColumn {
+        attr: src.attribute().clone(),
+        raw: col,
+        raw_nulls: nulls,
+        nulls_offset: nulls_offset,
+        children: children,
+        dict: dict,
+        runs: runs,
+    })
+}
+
+impl<'parent> RefColumn<'parent> for AliasColumn<'parent> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        self.raw.len() / self.attr.dtype.size_of()
+    }
+
+    /// Pointer to the beginning of the raw row data
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.raw.as_ptr()
+    }
+
+    /// Pointer to the beginning of the raw null bitmap
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        if self.raw_nulls.is_empty() { ptr::null() } else { self.raw_nulls.as_ptr() }
+    }
+
+    fn nulls_offset(&self) -> usize {
+        self.nulls_offset
+    }
+
+    fn rows_raw_slice(&'parent self) -> &'parent [u8] {
+        self.raw
+    }
+
+    fn nulls_raw_slice(&'parent self) -> &'parent [u8] {
+        self.raw_nulls
+    }
+
+    fn child(&'parent self, pos: usize) -> Option<&'parent RefColumn<'parent>> {
+        self.children.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn dictionary(&'parent self) -> Option<Dictionary<'parent>> {
+        self.dict.as_ref()
+            .map(|&(ref values, codes)| Dictionary { values: &**values, codes: codes })
+    }
+
+    fn runs(&'parent self) -> Option<Runs<'parent>> {
+        let rows = self.capacity();
+        self.runs.as_ref()
+            .map(|&(ref values, ends, offset)| Runs { values: &**values, ends: ends, offset: offset, rows: rows })
+    }
+}
+
+impl<'alloc> RefColumn<'alloc> for Column<'alloc> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        self.raw.len() / self.attr.dtype.size_of()
+    }
+
+    /// Pointer to the beginning of the raw row data
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.raw.as_ptr()
+    }
+
+    /// Pointer to the beginning of the raw null bitmap
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        self.raw_nulls.as_ptr()
+    }
+
+    fn rows_raw_slice(&'alloc self) -> &'alloc [u8] {
+        self.raw.data.as_ref()
+            .map_or(&[], |f| f as &'alloc [u8])
+    }
+
+    fn nulls_raw_slice(&'alloc self) -> &'alloc [u8] {
+        self.raw_nulls.data.as_ref()
+            .map_or(&[], |f| f as &'alloc [u8])
+    }
+
+    fn child(&'alloc self, pos: usize) -> Option<&'alloc RefColumn<'alloc>> {
+        self.children.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+}
+
+impl<'alloc> Column<'alloc> {
+    fn new(a: &'alloc Allocator, attr: Attribute) -> Column<'alloc> {
+        let children = attr.children.iter()
+            .map(|c| Column::new(a, c.clone()))
+            .collect();
+
+        Column {
+            allocator: a,
+            attr: attr,
+            raw_nulls: OwnedChunk::empty(),
+            raw: OwnedChunk::empty(),
+            arena: ChainedArena::new(a, ARENA_MIN_SIZE, ARENA_MAX_SIZE),
+            children: children,
+            list_rows: 0,
+        }
+    }
+
+    /// Mutable reference to a nested type's child column
+    pub fn child_mut(&mut self, pos: usize) -> Option<&mut Column<'alloc>> {
+        self.children.get_mut(pos)
+    }
+
+    /// Reserve `count` entries in a LIST or MAP column's child columns for `row`.
+    fn reserve_entries(&mut self, row: RowOffset, count: usize) -> Result<RowOffset, DBError> {
+        let offset = self.list_rows;
+        let needed = offset + count;
+
+        {
+            let entries = match self.attr.dtype {
+                Type::LIST  => self.rows_mut::<types::List>()?,
+                Type::MAP   => self.rows_mut::<types::Map>()?,
+                _           => return Err(DBError::AttributeType(self.attr.name.clone())),
+            };
+
+            if row >= entries.len() {
+                return Err(DBError::RowOutOfBounds)
+            }
+            entries[row] = ListEntry { offset: offset, len: count };
+        }
+
+        for child in &mut self.children {
+            if child.capacity() < needed {
+                let new_cap = round_up(needed * 2, 1024);
+                child.set_capacity(new_cap)?;
+            }
+        }
+
+        self.list_rows = needed;
+        Ok(offset)
+    }
+
+    /// Reserve `count` elements in the LIST column's element column for `row`.
+    ///
+    /// Returns the element column and the row offset of first reserved element. The caller is
+    /// responsible for filling in the element values.
+    pub fn list_append(&mut self, row: RowOffset, count: usize)
+        -> Result<(&mut Column<'alloc>, RowOffset), DBError>
+    {
+        if self.attr.dtype != Type::LIST {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        let offset = self.reserve_entries(row, count)?;
+        Ok((&mut self.children[0], offset))
+    }
+
+    /// Reserve `count` key, value pairs in the MAP column for `row`.
+    ///
+    /// Returns the key column, value column and the row offset of the first reserved pair. The
+    /// caller is responsible for filling in the keys and values.
+    pub fn map_append(&mut self, row: RowOffset, count: usize)
+        -> Result<(&mut Column<'alloc>, &mut Column<'alloc>, RowOffset), DBError>
+    {
+        if self.attr.dtype != Type::MAP {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        let offset = self.reserve_entries(row, count)?;
+        let (keys, values) = self.children.split_at_mut(1);
+        Ok((&mut keys[0], &mut values[0], offset))
+    }
+
+    /// Copy `rows` rows starting at `src_row` of the `src` column into this column starting at
+    /// `row`. VARLEN values are copied into this column's arena.
+    ///
+    /// Both columns have to be of the same type. Nested values are copied recursively; LIST and MAP
+    /// elements are appended to this column's child columns.
+    pub fn copy_rows<'s>(&mut self, row: RowOffset, src: &'s RefColumn<'s>, src_row: RowOffset, rows: usize)
+        -> Result<(), DBError>
+    {
+        let dtype = self.attr.dtype;
+        if src.attribute().dtype != dtype {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        if row + rows > self.capacity() || src_row + rows > src.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let src_nullable = src.attribute().nullable;
+
+        let src_nulls = column_nulls(src);
+
+        if self.attr.nullable {
+            let mut nulls = self.nulls_mut()?;
+            if src_nullable {
+                nulls.copy_from(row, &src_nulls, src_row, rows);
+            } else {
+                nulls.fill(row, rows, false);
+            }
+        } else if src_nullable && src_nulls.slice(src_row, rows).any() {
+            return Err(DBError::AttributeNullability(self.attr.name.clone()))
+        }
+
+        match dtype {
+            Type::STRUCT => {
+                for pos in 0 .. self.children.len() {
+                    let field = src.child(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                    self.children[pos].copy_rows(row, field, src_row, rows)?;
+                }
+            }
+            Type::LIST | Type::MAP => {
+                let entries = unsafe { rows_from_rawptr_const::<ListEntry>(src.rows_ptr(), src.capacity()) };
+
+                for idx in 0 .. rows {
+                    // Entries of NULL rows aren't necessarily initialized
+                    let entry = if src_nullable && src_nulls.get(src_row + idx) {
+                        ListEntry::default()
+                    } else {
+                        entries[src_row + idx]
+                    };
+
+                    let offset = self.reserve_entries(row + idx, entry.len)?;
+                    for pos in 0 .. self.children.len() {
+                        let child = src.child(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                        self.children[pos].copy_rows(offset, child, entry.offset, entry.len)?;
+                    }
+                }
+            }
+            _ if dtype.is_varlen() => unsafe {
+                let src_values = rows_from_rawptr_const::<types::RawData>(src.rows_ptr(), src.capacity());
+
+                for idx in 0 .. rows {
+                    let value = if src_nullable && src_nulls.get(src_row + idx) {
+                        types::RawData { data: ptr::null_mut(), size: 0 }
+                    } else {
+                        let data: &[u8] = src_values[src_row + idx].as_ref();
+                        let ptr = self.arena.append(data)?.1;
+                        types::RawData { data: ptr, size: data.len() }
+                    };
+
+                    let values = rows_from_rawptr::<types::RawData>(self.raw.as_mut_ptr(), self.capacity());
+                    values[row + idx] = value;
+                }
+            },
+            _ => unsafe {
+                let size_of = dtype.size_of();
+                ptr::copy_nonoverlapping(
+                    src.rows_ptr().offset((src_row * size_of) as isize),
+                    self.raw.as_mut_ptr().offset((row * size_of) as isize),
+                    rows * size_of);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Gather rows of `src` at `indices` into this column starting at `row`.
+    pub fn take_rows<'s>(&mut self, row: RowOffset, src: &'s RefColumn<'s>, indices: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        if row + indices.len() > self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        self.copy_mapped(src, indices.len(), |idx| row + idx, |idx| indices[idx])
+    }
+
+    /// Scatter `indices.len()` rows of `src` starting at `src_row` into this column's rows at
+    /// `indices`.
+    pub fn scatter_rows<'s>(&mut self, src: &'s RefColumn<'s>, src_row: RowOffset, indices: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        if src_row + indices.len() > src.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        self.copy_mapped(src, indices.len(), |idx| indices[idx], |idx| src_row + idx)
+    }
+
+    /// Copy `count` rows; the i-th copied row is moved from `src_pos(i)` to `dst_pos(i)`.
+    fn copy_mapped<'s, D, S>(&mut self, src: &'s RefColumn<'s>, count: usize, dst_pos: D, src_pos: S)
+        -> Result<(), DBError>
+        where D: Fn(usize) -> RowOffset, S: Fn(usize) -> RowOffset
+    {
+        let dtype = self.attr.dtype;
+        if src.attribute().dtype != dtype {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        let (cap, src_cap) = (self.capacity(), src.capacity());
+        if (0 .. count).any(|idx| dst_pos(idx) >= cap || src_pos(idx) >= src_cap) {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        // Nested & varlen values need their data copied, one row at a time
+        if dtype.is_nested() || dtype.is_varlen() {
+            for idx in 0 .. count {
+                self.copy_rows(dst_pos(idx), src, src_pos(idx), 1)?;
+            }
+            return Ok(())
+        }
+
+        let src_nullable = src.attribute().nullable;
+        let src_nulls = column_nulls(src);
+
+        if self.attr.nullable {
+            let mut nulls = self.nulls_mut()?;
+            for idx in 0 .. count {
+                nulls.set(dst_pos(idx), src_nullable && src_nulls.get(src_pos(idx)));
+            }
+        } else if src_nullable && (0 .. count).any(|idx| src_nulls.get(src_pos(idx))) {
+            return Err(DBError::AttributeNullability(self.attr.name.clone()))
+        }
+
+        unsafe {
+            let (dst, src) = (self.raw.as_mut_ptr(), src.rows_ptr());
+
+            match dtype.size_of() {
+                1 => gather(rows_from_rawptr::<u8>(dst, cap), rows_from_rawptr_const::<u8>(src, src_cap),
+                            count, dst_pos, src_pos),
+                4 => gather(rows_from_rawptr::<u32>(dst, cap), rows_from_rawptr_const::<u32>(src, src_cap),
+                            count, dst_pos, src_pos),
+                8 => gather(rows_from_rawptr::<u64>(dst, cap), rows_from_rawptr_const::<u64>(src, src_cap),
+                            count, dst_pos, src_pos),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the first `rows` rows of the column (values, nulls, varlen data and nested children)
+    /// into a new column backed by `alloc`. The new column has the same capacity.
+    pub fn deep_copy<'a>(&'alloc self, alloc: &'a Allocator, rows: RowOffset) -> Result<Column<'a>, DBError> {
+        if rows > self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let mut out = Column::new(alloc, self.attr.clone());
+        if self.capacity() > 0 {
+            out.set_capacity(self.capacity())?;
+        }
+
+        out.copy_rows(0, self, 0, rows)?;
+        Ok(out)
+    }
+
+    /// Memory allocated for the column's values, nulls, arena and nested children
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let own = MemoryUsage {
+            values: self.raw.len(),
+            nulls: self.raw_nulls.len(),
+            arena: self.arena.allocated_bytes(),
+        };
+
+        self.children.iter()
+            .fold(own, |acc, c| acc + c.memory_usage())
+    }
+
+    pub fn arena(&mut self) -> &mut ChainedArena<'alloc> {
+        &mut self.arena
+    }
+
+    /// Reset all the rows (and nested entries) to their initial state, keeping the capacity and
+    /// the VARLEN arena memory for reuse.
+    pub fn clear(&mut self) {
+        let capacity = self.capacity();
+        self.arena.reset();
+        self.list_rows = 0;
+        self.init_rows(0, capacity);
+
+        for child in &mut self.children {
+            child.clear();
+        }
+    }
+
+    /// Initialize newly allocated rows [from, to): clear their null flags and make VARLEN values
+    /// empty, so never set rows don't expose uninitialized memory.
+    fn init_rows(&mut self, from: RowOffset, to: RowOffset) {
+        // Zero capacity columns might not have any memory
+        if from >= to {
+            return
+        }
+
+        if self.attr.nullable {
+            if let Ok(mut nulls) = self.nulls_mut() {
+                nulls.fill(from, to - from, false);
+            }
+        }
+
+        if self.attr.dtype.is_varlen() {
+            let values = unsafe { slice::from_raw_parts_mut(self.raw.as_mut_ptr() as *mut types::RawData, to) };
+            for value in &mut values[from ..] {
+                *value = empty_raw_data();
+            }
+        }
+    }
+
+    /// Reset the row's value to the type's default (zero / empty), so NULL rows don't hold stale
+    /// values.
+    pub fn set_default(&mut self, row: RowOffset) -> Result<(), DBError> {
+        if row >= self.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        if self.attr.dtype.is_varlen() {
+            unsafe { *(self.raw.as_mut_ptr() as *mut types::RawData).offset(row as isize) = empty_raw_data(); }
+        } else {
+            let size = self.attr.dtype.size_of();
+            unsafe { ptr::write_bytes(self.raw.as_mut_ptr().offset((row * size) as isize), 0, size); }
+        }
+
+        Ok(())
+    }
+
+    pub fn nulls_mut(&mut self) -> Result<MutBitmap, DBError> {
+        if !self.attr.nullable {
+            return Err(DBError::AttributeNullability(self.attr.name.clone()))
+        }
+
+        let rows = self.capacity();
+        let out = match self.raw_nulls.data {
+            Some(ref mut slice) => MutBitmap::new(slice, rows),
+            _ => MutBitmap::empty(),
+        };
+
+        Ok(out)
+    }
+
+    pub fn rows_mut<T: ValueInfo>(&mut self) -> Result<&mut [T::Store], DBError> {
+        if self.attr.dtype != T::ENUM {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        unsafe {
+            let ptr: *mut T::Store = mem::transmute(self.raw.as_mut_ptr());
+            let out = if ptr.is_null() {
+                &mut []
+            } else {
+                slice::from_raw_parts_mut(ptr, self.capacity())
+            };
+
+            Ok(out)
+        }
+    }
+
+    pub fn row_data_mut<T: ValueInfo>(&mut self) -> Result<ColumnRowsMut<T>, DBError> {
+        if self.attr.dtype != T::ENUM {
+            return Err(DBError::AttributeType(self.attr.name.clone()))
+        }
+
+        unsafe {
+            let ptr: *mut T::Store = mem::transmute(self.raw.as_mut_ptr());
+            let rows = if ptr.is_null() {
+                &mut []
+            } else {
+                slice::from_raw_parts_mut(ptr, self.capacity())
+            };
+
+            let nulls = match self.raw_nulls.data {
+                Some(ref mut slice) => MutBitmap::new(slice, rows.len()),
+                _ => MutBitmap::empty(),
+            };
+
+            Ok(ColumnRowsMut{ values: rows, nulls: nulls})
+        }
+    }
+
+    /// Change the capacity of the Column. The capacity is rounded up to `padded_rows`, so the
+    /// column is SIMD padded (see `RefColumn`).
+    pub fn set_capacity(&mut self, rows: RowOffset) -> Result<(), DBError> {
+        let rows = padded_rows(self.attr.dtype, rows);
+        let new_size = rows * self.attr.dtype.size_of();
+        let nulls_size = round_up(bytes_for(rows), SIMD_WIDTH);
+        let prev_rows = self.capacity();
+
+        if self.raw.is_null() {
+            self.raw = self.allocator.allocate_aligned(new_size, SIMD_WIDTH)?;
+
+            if self.attr.nullable {
+                self.raw_nulls = self.allocator.allocate_aligned(nulls_size, SIMD_WIDTH)?;
+            }
+        } else {
+            self.raw.resize(new_size)?;
+
+            if self.attr.nullable {
+                self.raw_nulls.resize(nulls_size)?;
+            }
+        }
+
+        if rows > prev_rows {
+            self.init_rows(prev_rows, rows);
+        }
+
+        // STRUCT fields are row aligned with the parent column
+        if self.attr.dtype == Type::STRUCT {
+            for child in &mut self.children {
+                child.set_capacity(rows)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run-length encoded column. Used for columns with long runs of repeated values (sorted keys,
+/// flags).
+///
+/// Stores a single value per run plus the end row of each run. Raw row access through `RefColumn`
+/// (eg. `column_row_data`) transparently decodes the column on first use.
+pub struct RleColumn<'alloc> {
+    allocator: &'alloc Allocator,
+    attr: Attribute,
+    rows: RowOffset,
+    /// Exclusive end row of each run
+    ends: Vec<RowOffset>,
+    /// One row per run
+    values: Column<'alloc>,
+    /// Lazily decoded column
+    decoded: UnsafeCell<Option<Column<'alloc>>>,
+}
+
+/// Fixed width inner loop of `take_rows` / `scatter_rows`
+#[inline]
+fn gather<T: Copy, D, S>(dst: &mut [T], src: &[T], count: usize, dst_pos: D, src_pos: S)
+    where D: Fn(usize) -> RowOffset, S: Fn(usize) -> RowOffset
+{
+    for idx in 0 .. count {
+        dst[dst_pos(idx)] = src[src_pos(idx)];
+    }
+}
+
+/// Compare two rows of the same column. NULLs are equal to each other, fixed width values are
+/// compared bitwise.
+fn rows_equal(col: &RefColumn, nulls: &Bitmap, lhs: RowOffset, rhs: RowOffset) -> bool {
+    if col.attribute().nullable {
+        let (lhs_null, rhs_null) = (nulls.get(lhs), nulls.get(rhs));
+        if lhs_null || rhs_null {
+            return lhs_null == rhs_null
+        }
+    }
+
+    let dtype = col.attribute().dtype;
+
+    unsafe {
+        if dtype.is_varlen() {
+            let values = rows_from_rawptr_const::<types::RawData>(col.rows_ptr(), col.capacity());
+            AsRef::<[u8]>::as_ref(&values[lhs]) == AsRef::<[u8]>::as_ref(&values[rhs])
+        } else {
+            let size_of = dtype.size_of();
+            let raw = rows_from_rawptr_const::<u8>(col.rows_ptr(), col.capacity() * size_of);
+            raw[lhs * size_of .. (lhs + 1) * size_of] == raw[rhs * size_of .. (rhs + 1) * size_of]
+        }
+    }
+}
+
+/// Count the number of runs in the first `rows` rows of the column. Useful for deciding if a
+/// column is worth RLE encoding.
+pub fn rle_run_count(src: &RefColumn, rows: RowOffset) -> Result<usize, DBError> {
+    if src.attribute().dtype.is_nested() {
+        return Err(DBError::AttributeType(src.attribute().name.clone()))
+    }
+
+    if rows > src.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let nulls = column_nulls(src);
+    let breaks = (1 .. rows)
+        .filter(|row| !rows_equal(src, &nulls, row - 1, *row))
+        .count();
+
+    Ok(if rows == 0 { 0 } else { breaks + 1 })
+}
+
+/// RLE encode the first `rows` rows of the `src` column. Nested types are not supported.
+pub fn rle_encode<'alloc, 's>(alloc: &'alloc Allocator, src: &'s RefColumn<'s>, rows: RowOffset)
+    -> Result<RleColumn<'alloc>, DBError>
+{
+    let runs = rle_run_count(src, rows)?;
+    let nulls = column_nulls(src);
+
+    let mut ends = Vec::with_capacity(runs);
+    for row in 1 .. rows {
+        if !rows_equal(src, &nulls, row - 1, row) {
+            ends.push(row);
+        }
+    }
+
+    if rows > 0 {
+        ends.push(rows);
+    }
+
+    let mut values = Column::new(alloc, src.attribute().clone());
+    if runs > 0 {
+        values.set_capacity(runs)?;
+    }
+
+    let mut start = 0;
+    for (run, end) in ends.iter().enumerate() {
+        values.copy_rows(run, src, start, 1)?;
+        start = *end;
+    }
+
+    Ok(RleColumn {
+        allocator: alloc,
+        attr: src.attribute().clone(),
+        rows: rows,
+        ends: ends,
+        values: values,
+        decoded: UnsafeCell::new(None),
+    })
+}
+
+impl<'alloc> RleColumn<'alloc> {
+    /// Number of (decoded) rows
+    pub fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    pub fn run_count(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Memory used by the encoded column, and the decoded copy if one was materialized
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut out = self.values.memory_usage();
+        out.values += self.ends.capacity() * mem::size_of::<RowOffset>();
+
+        if let Some(ref decoded) = *unsafe { &*self.decoded.get() } {
+            out += decoded.memory_usage();
+        }
+
+        out
+    }
+
+    /// Run values; one row per run.
+    pub fn values(&self) -> &Column<'alloc> {
+        &self.values
+    }
+
+    /// Rows covered by the run
+    pub fn run_range(&self, run: usize) -> RowRange {
+        let start = if run == 0 { 0 } else { self.ends[run - 1] };
+        RowRange { offset: start, rows: self.ends[run] - start }
+    }
+
+    /// Run containing the row
+    pub fn run_of(&self, row: RowOffset) -> Result<usize, DBError> {
+        if row >= self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok(match self.ends.binary_search(&row) {
+            Ok(pos)     => pos + 1,
+            Err(pos)    => pos,
+        })
+    }
+
+    /// Decode into a new plain column
+    pub fn decode(&self) -> Result<Column<'alloc>, DBError> {
+        let mut out = Column::new(self.allocator, self.attr.clone());
+        if self.rows > 0 {
+            out.set_capacity(self.rows)?;
+        }
+
+        for run in 0 .. self.run_count() {
+            let range = self.run_range(run);
+            for row in range.offset .. range.offset + range.rows {
+                out.copy_rows(row, &self.values, run, 1)?;
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Decoded copy of the column, decoded on first use
+    fn decoded(&self) -> Result<&Column<'alloc>, DBError> {
+        unsafe {
+            let slot = &mut *self.decoded.get();
+            if slot.is_none() {
+                *slot = Some(self.decode()?);
+            }
+
+            Ok(slot.as_ref().unwrap())
+        }
+    }
+}
+
+impl<'c, 'alloc: 'c> RefColumn<'c> for RleColumn<'alloc> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        self.rows
+    }
+
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.decoded().map_or(ptr::null(), |d| d.rows_ptr())
+    }
+
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        self.decoded().map_or(ptr::null(), |d| d.nulls_ptr())
+    }
+
+    fn rows_raw_slice(&'c self) -> &'c [u8] {
+        match self.decoded() {
+            Ok(decoded) => &decoded.rows_raw_slice()[.. self.rows * self.attr.dtype.size_of()],
+            Err(_)      => &[],
+        }
+    }
+
+    fn nulls_raw_slice(&'c self) -> &'c [u8] {
+        match self.decoded() {
+            Ok(decoded) => {
+                let nulls = decoded.nulls_raw_slice();
+                &nulls[.. bytes_for(self.rows).min(nulls.len())]
+            }
+            Err(_)      => &[],
+        }
+    }
+
+    fn child(&'c self, _: usize) -> Option<&'c RefColumn<'c>> {
+        None
+    }
+
+    fn runs(&'c self) -> Option<Runs<'c>> {
+        Some(Runs { values: &self.values, ends: &self.ends, offset: 0, rows: self.rows })
+    }
+
+    fn materialize(&self) -> Result<(), DBError> {
+        self.decoded().map(|_| ())
+    }
+}
+
+/// Column representing a single value repeated for N rows. The value is only stored once; raw
+/// row access through `RefColumn` materializes the rows on first use.
+pub struct ConstColumn<'alloc> {
+    /// Single run RLE column
+    inner: RleColumn<'alloc>,
+}
+
+impl<'alloc> ConstColumn<'alloc> {
+    pub fn new(alloc: &'alloc Allocator, attr: Attribute, value: &Value, rows: RowOffset)
+        -> Result<ConstColumn<'alloc>, DBError>
+    {
+        if attr.dtype.is_nested() {
+            return Err(DBError::AttributeType(attr.name.clone()))
+        }
+
+        let mut values = Column::new(alloc, attr.clone());
+        values.set_capacity(1)?;
+
+        value.set_row(&mut values, 0)?;
+
+        let inner = RleColumn {
+            allocator: alloc,
+            attr: attr,
+            rows: rows,
+            ends: if rows > 0 { vec![rows] } else { Vec::new() },
+            values: values,
+            decoded: UnsafeCell::new(None),
+        };
+
+        Ok(ConstColumn { inner: inner })
+    }
+
+    pub fn rows(&self) -> RowOffset {
+        self.inner.rows
+    }
+
+    /// The repeated value
+    pub fn value<'a>(&'a self) -> Result<Value<'a>, DBError> {
+        column_value(&self.inner.values, 0)
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.inner.memory_usage()
+    }
+
+    /// Materialize into a new plain column
+    pub fn expand(&self) -> Result<Column<'alloc>, DBError> {
+        self.inner.decode()
+    }
+}
+
+impl<'c, 'alloc: 'c> RefColumn<'c> for ConstColumn<'alloc> {
+    fn attribute(&self) -> &Attribute {
+        self.inner.attribute()
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.inner.rows_ptr()
+    }
+
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        self.inner.nulls_ptr()
+    }
+
+    fn rows_raw_slice(&'c self) -> &'c [u8] {
+        self.inner.rows_raw_slice()
+    }
+
+    fn nulls_raw_slice(&'c self) -> &'c [u8] {
+        self.inner.nulls_raw_slice()
+    }
+
+    fn child(&'c self, _: usize) -> Option<&'c RefColumn<'c>> {
+        None
+    }
+
+    fn runs(&'c self) -> Option<Runs<'c>> {
+        self.inner.runs()
+    }
+
+    fn materialize(&self) -> Result<(), DBError> {
+        self.inner.materialize()
+    }
+}
+
+/// Dictionary encoded column. Used for low cardinality columns (categories, country codes); group
+/// by and joins can work on the codes.
+///
+/// Stores each distinct value once plus a code per row. Raw row access through `RefColumn` (eg.
+/// `column_row_data`) transparently decodes the column on first use.
+pub struct DictColumn<'alloc> {
+    allocator: &'alloc Allocator,
+    attr: Attribute,
+    /// Dictionary row of every row
+    codes: Vec<u32>,
+    /// One row per distinct value
+    values: Column<'alloc>,
+    /// Lazily decoded column
+    decoded: UnsafeCell<Option<Column<'alloc>>>,
+}
+
+/// Dictionary encode the first `rows` rows of the `src` column. Nested types are not supported.
+pub fn dict_encode<'alloc, 's>(alloc: &'alloc Allocator, src: &'s RefColumn<'s>, rows: RowOffset)
+    -> Result<DictColumn<'alloc>, DBError>
+{
+    let dtype = src.attribute().dtype;
+    if dtype.is_nested() {
+        return Err(DBError::AttributeType(src.attribute().name.clone()))
+    }
+
+    if rows > src.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let nulls = column_nulls(src);
+    let nullable = src.attribute().nullable;
+
+    // First row of every distinct value, by the value's bytes (`None` for NULL)
+    let mut seen: HashMap<Option<&[u8]>, u32> = HashMap::new();
+    let mut distinct = Vec::new();
+    let mut codes = Vec::with_capacity(rows);
+
+    unsafe {
+        let size_of = dtype.size_of();
+        let varlen = rows_from_rawptr_const::<types::RawData>(src.rows_ptr(), if dtype.is_varlen() { rows } else { 0 });
+        let raw = rows_from_rawptr_const::<u8>(src.rows_ptr(), if dtype.is_varlen() { 0 } else { rows * size_of });
+
+        for row in 0 .. rows {
+            let key = if nullable && nulls.get(row) {
+                None
+            } else if dtype.is_varlen() {
+                Some(AsRef::<[u8]>::as_ref(&varlen[row]))
+            } else {
+                Some(&raw[row * size_of .. (row + 1) * size_of])
+            };
+
+            let next = distinct.len() as u32;
+            let code = *seen.entry(key).or_insert(next);
+            if code == next {
+                distinct.push(row);
+            }
+            codes.push(code);
+        }
+    }
+
+    let mut values = Column::new(alloc, src.attribute().clone());
+    if !distinct.is_empty() {
+        values.set_capacity(distinct.len())?;
+        values.take_rows(0, src, &distinct)?;
+    }
+
+    DictColumn::new(alloc, values, codes)
+}
+
+impl<'alloc> DictColumn<'alloc> {
+    /// Column of the `values` at `codes`. Columns can share a dictionary.
+    pub fn new(alloc: &'alloc Allocator, values: Column<'alloc>, codes: Vec<u32>) -> Result<DictColumn<'alloc>, DBError> {
+        if codes.iter().any(|c| *c as usize >= values.capacity()) {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok(DictColumn {
+            allocator: alloc,
+            attr: values.attribute().clone(),
+            codes: codes,
+            values: values,
+            decoded: UnsafeCell::new(None),
+        })
+    }
+
+    /// Number of (decoded) rows
+    pub fn rows(&self) -> RowOffset {
+        self.codes.len()
+    }
+
+    /// Memory used by the encoded column, and the decoded copy if one was materialized
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut out = self.values.memory_usage();
+        out.values += self.codes.capacity() * mem::size_of::<u32>();
+
+        if let Some(ref decoded) = *unsafe { &*self.decoded.get() } {
+            out += decoded.memory_usage();
+        }
+
+        out
+    }
+
+    /// Dictionary values; one row per distinct value.
+    pub fn values(&self) -> &Column<'alloc> {
+        &self.values
+    }
+
+    pub fn codes(&self) -> &[u32] {
+        &self.codes
+    }
+
+    /// Decode into a new plain column
+    pub fn decode(&self) -> Result<Column<'alloc>, DBError> {
+        let mut out = Column::new(self.allocator, self.attr.clone());
+        if !self.codes.is_empty() {
+            out.set_capacity(self.codes.len())?;
+            let rows: Vec<RowOffset> = self.codes.iter().map(|c| *c as RowOffset).collect();
+            out.take_rows(0, &self.values, &rows)?;
+        }
+
+        Ok(out)
+    }
+
+    /// Decoded copy of the column, decoded on first use
+    fn decoded(&self) -> Result<&Column<'alloc>, DBError> {
+        unsafe {
+            let slot = &mut *self.decoded.get();
+            if slot.is_none() {
+                *slot = Some(self.decode()?);
+            }
+
+            Ok(slot.as_ref().unwrap())
+        }
+    }
+}
+
+impl<'c, 'alloc: 'c> RefColumn<'c> for DictColumn<'alloc> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    /// Row capacity
+    fn capacity(&self) -> usize {
+        self.codes.len()
+    }
+
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        self.decoded().map_or(ptr::null(), |d| d.rows_ptr())
+    }
+
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        self.decoded().map_or(ptr::null(), |d| d.nulls_ptr())
+    }
+
+    fn rows_raw_slice(&'c self) -> &'c [u8] {
+        match self.decoded() {
+            Ok(decoded) => &decoded.rows_raw_slice()[.. self.codes.len() * self.attr.dtype.size_of()],
+            Err(_)      => &[],
+        }
+    }
+
+    fn nulls_raw_slice(&'c self) -> &'c [u8] {
+        match self.decoded() {
+            Ok(decoded) => {
+                let nulls = decoded.nulls_raw_slice();
+                &nulls[.. bytes_for(self.codes.len()).min(nulls.len())]
+            }
+            Err(_)      => &[],
+        }
+    }
+
+    fn child(&'c self, _: usize) -> Option<&'c RefColumn<'c>> {
+        None
+    }
+
+    fn dictionary(&'c self) -> Option<Dictionary<'c>> {
+        Some(Dictionary { values: &self.values, codes: &self.codes })
+    }
+
+    fn materialize(&self) -> Result<(), DBError> {
+        self.decoded().map(|_| ())
+    }
+}
+
+/// A read-only view into data conforming to a pre-defined schema. This view may be backed by a
+/// container that owns it data, borrows or aliases somebody elses data.
+pub trait View<'v> {
+    fn schema(&'v self) -> &'v Schema;
+    fn column(&'v self, pos: usize) -> Option<&'v RefColumn<'v>>;
+
+    /// Number of rows
+    fn rows(&self) -> RowOffset;
+
+    /// Statistics of the column data. Computed on demand unless the view keeps them around.
+    fn column_stats(&'v self, pos: usize) -> Result<ColumnStats, DBError> {
+        let col = self.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        ColumnStats::compute(col, self.rows())
+    }
+
+    /// Statistics of the column kept with the data (eg. `Block::update_stats`), never computed
+    fn stored_stats(&'v self, _: usize) -> Option<&'v ColumnStats> {
+        None
+    }
+
+    /// Zone map of the column kept with the data, never computed
+    fn zone_map(&'v self, _: usize) -> Option<&'v ZoneMap> {
+        None
+    }
+
+    /// Rows that are deleted but still in the columns (set bit means deleted; rows past the end of
+    /// the bitmap aren't), eg. the rows of a `Table` until it's compacted. Scans skip them.
+    fn deleted(&'v self) -> Option<&'v [u8]> {
+        None
+    }
+
+    /// Read a row into a Rust tuple, eg. `view.get_row::<(u32, &str, Option<f64>)>(0)`
+    fn get_row<R: RowGetter<'v>>(&'v self, row: RowOffset) -> Result<R, DBError>
+        where Self: Sized + 'v
+    {
+        RowRef::new(self, row)?.get_row()
+    }
+
+    /// Read all the rows as records
+    fn to_records<R: Record>(&'v self) -> Result<Vec<R>, DBError>
+        where Self: Sized + 'v
+    {
+        read_records(self)
+    }
+
+    /// Column aligned table of the first `limit` rows, for debugging and tests. Values are
+    /// formatted with `Value`'s `Display`, newlines and tabs are escaped.
+    ///
+    /// ```text
+    /// id | name
+    /// ---+-----
+    /// 1  | a
+    /// 2  | NULL
+    /// (2 rows)
+    /// ```
+    fn format_rows(&'v self, limit: RowOffset) -> String {
+        let schema = self.schema();
+        let shown = limit.min(self.rows());
+
+        let mut table: Vec<Vec<String>> = vec![schema.iter().map(|a| a.name.clone()).collect()];
+        for row in 0 .. shown {
+            let cells = (0 .. schema.count())
+                .map(|pos| {
+                    let value = self.column(pos)
+                        .ok_or(DBError::make_column_unknown_pos(pos))
+                        .and_then(|col| column_value(col, row));
+
+                    match value {
+                        Ok(v)   => v.to_string().replace('\n', "\\n").replace('\t', "\\t"),
+                        Err(e)  => format!("<{}>", e),
+                    }
+                })
+                .collect();
+            table.push(cells);
+        }
+
+        let mut widths = vec![0; schema.count()];
+        for cells in &table {
+            for (w, cell) in widths.iter_mut().zip(cells) {
+                *w = (*w).max(cell.chars().count());
+            }
+        }
+
+        let line = |cells: &[String]| -> String {
+            let padded: Vec<String> = cells.iter().zip(&widths)
+                .map(|(c, w)| format!("{:1$}", c, w))
+                .collect();
+            format!("{}\n", padded.join(" | ").trim_right())
+        };
+
+        let mut out = line(&table[0]);
+        let dashes: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        out.push_str(&dashes.join("-+-"));
+        out.push('\n');
+
+        for cells in &table[1 ..] {
+            out.push_str(&line(cells));
+        }
+
+        if shown < self.rows() {
+            out.push_str(&format!("({} of {} rows)\n", shown, self.rows()));
+        } else {
+            out.push_str(&format!("({} rows)\n", shown));
+        }
+
+        out
+    }
+}
+
+/// An implementation of a View that doesn't "own" the data but aliases it
+#[derive(Default)]
+pub struct RefView<'a> {
+    schema: Schema,
+    columns: Vec<AliasColumn<'a>>,
+    rows: RowOffset,
+}
+
+/// Take a view and create a vector of column aliases
+pub fn alias_columns<'a>(src: &'a View<'a>, range: Option<RowRange>)
+    -> Result<Vec<AliasColumn<'a>>, DBError>
+{
+    let count = src.schema().count();
+    let mut out: Vec<AliasColumn> = Vec::with_capacity(count);
+
+    for pos in 0 .. count {
+        let col = alias_column(src.column(pos).unwrap(), range)?;
+        out.push(col);
+    }
+
+    Ok(out)
+}
+
+impl<'a> View<'a> for RefView<'a> {
+    fn schema(&'a self) -> &'a Schema {
+        &self.schema
+    }
+
+    fn column(&'a self, pos: usize) -> Option<&RefColumn> {
+        self.columns.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn rows(&self) -> RowOffset {
+        self.rows
+    }
+}
+
+/// Create window into another view
+pub fn window_alias<'a>(src: &'a View<'a>, range: Option<RowRange>)
+    -> Result<RefView<'a>, DBError>
+{
+    let (offset, rows) = range.map_or((0, src.rows()), |r| (r.offset, r.rows));
+
+    if offset + rows > src.rows() {
+        Err(DBError::RowOutOfBounds)
+    } else {
+        let schema = src.schema();
+
+        Ok(RefView {
+            schema: schema.clone(),
+            rows: rows,
+            columns: alias_columns(src, range)?,
+        })
+    }
+}
+
+impl<'a> fmt::Display for RefView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let view: &View = self;
+        f.write_str(&view.format_rows(view.rows()))
+    }
+}
+
+impl<'a> fmt::Debug for RefView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let view: &View = self;
+        write!(f, "RefView({})\n{}", self.schema, view.format_rows(DEBUG_ROWS))
+    }
+}
+
+impl<'a> RefView<'a> {
+    pub fn new(schema: Schema, columns: Vec<AliasColumn<'a>>, rows: RowOffset) -> RefView<'a> {
+        RefView { schema: schema, columns: columns, rows: rows }
+    }
+
+    /// New view with the columns at `positions`, described by `schema`. Consumes this view.
+    pub fn select(self, positions: &[usize], schema: Schema) -> Result<RefView<'a>, DBError> {
+        if positions.len() != schema.count() {
+            return Err(DBError::ExpressionInputCount(format!("{} != {}", positions.len(), schema.count())))
+        }
+
+        let mut columns = Vec::with_capacity(positions.len());
+        for pos in positions {
+            let col = self.columns.get(*pos).ok_or(DBError::make_column_unknown_pos(*pos))?;
+            columns.push(col.clone());
+        }
+
+        Ok(RefView { schema: schema, columns: columns, rows: self.rows })
+    }
+
+    /// The first `rows` rows of this view. Consumes this view.
+    pub fn truncate(self, rows: RowOffset) -> Result<RefView<'a>, DBError> {
+        if rows > self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok(RefView { rows: rows, .. self })
+    }
+}
+
+/// A container for column data conforming to a pre-defined schema. This container is the owner of
+/// the columns (and their data)
+pub struct Block<'b> {
+    allocator: &'b Allocator,
+    schema: Schema,
+    columns: Vec<Column<'b>>,
+    rows: RowOffset,
+    capacity: RowOffset,
+    /// Column statistics, if computed. Cleared when the block is modified.
+    stats: Vec<ColumnStats>,
+    /// Column zone maps, computed along with the statistics
+    zones: Vec<ZoneMap>,
+}
+
+impl<'b> View<'b> for Block<'b> {
+    fn schema(&'b self) -> &'b Schema {
+        &self.schema
+    }
+
+    fn column(&'b self, pos: usize) -> Option<&RefColumn> {
+        self.columns.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    fn column_stats(&'b self, pos: usize) -> Result<ColumnStats, DBError> {
+        match self.stats(pos) {
+            Some(stats) => Ok(stats.clone()),
+            None        => {
+                let col = self.columns.get(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                ColumnStats::compute(col, self.rows)
+            }
+        }
+    }
+
+    fn stored_stats(&'b self, pos: usize) -> Option<&'b ColumnStats> {
+        self.stats(pos)
+    }
+
+    fn zone_map(&'b self, pos: usize) -> Option<&'b ZoneMap> {
+        self.zones.get(pos)
+    }
+}
+
+/// All the rows, see `View::format_rows`
+impl<'b> fmt::Display for Block<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let view: &View = self;
+        f.write_str(&view.format_rows(view.rows()))
+    }
+}
+
+impl<'b> fmt::Debug for Block<'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let view: &View = self;
+        write!(f, "Block({}), capacity {}\n{}", self.schema, self.capacity, view.format_rows(DEBUG_ROWS))
+    }
+}
+
+/// Rows included in the `Debug` output of blocks and views
+const DEBUG_ROWS: RowOffset = 20;
+
+impl<'b> Block<'b> {
+    pub fn new(alloc: &'b Allocator, schema: &Schema) -> Block<'b> {
+        let mut b = Block {
+            allocator: alloc,
+            schema: schema.clone(),
+            rows: 0,
+            capacity: 0,
+            columns: Vec::new(),
+            stats: Vec::new(),
+            zones: Vec::new(),
+        };
+
+        for attr in schema.iter() {
+            b.columns.push(Column::new(b.allocator, attr.clone()))
+        }
+
+        b
+    }
+
+    pub fn allocator(&self) -> &'b Allocator {
+        self.allocator
+    }
+
+    /// Number of rows the Block can currently grow to without re-allocating column data.
+    pub fn capacity(&self) -> RowOffset {
+        self.capacity
+    }
+
+    /// Compute and store statistics and zone maps (of `DEFAULT_ZONE_ROWS`) for all columns.
+    pub fn update_stats(&mut self) -> Result<(), DBError> {
+        self.update_stats_zoned(stats::DEFAULT_ZONE_ROWS)
+    }
+
+    /// Compute and store statistics and zone maps of `zone_rows` for all columns.
+    pub fn update_stats_zoned(&mut self, zone_rows: RowOffset) -> Result<(), DBError> {
+        let rows = self.rows;
+        self.clear_stats();
+
+        let stats = self.columns.iter()
+            .map(|c| ColumnStats::compute(c, rows))
+            .collect::<Result<Vec<_>, _>>()?;
+        let zones = self.columns.iter()
+            .map(|c| ZoneMap::compute(c, rows, zone_rows))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.stats = stats;
+        self.zones = zones;
+        Ok(())
+    }
+
+    fn clear_stats(&mut self) {
+        self.stats.clear();
+        self.zones.clear();
+    }
+
+    /// Memory allocated for the data of all columns
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.columns.iter()
+            .fold(MemoryUsage::default(), |acc, c| acc + c.memory_usage())
+    }
+
+    /// Stored column statistics
+    pub fn stats(&self, pos: usize) -> Option<&ColumnStats> {
+        self.stats.get(pos)
+    }
+
+    /// Grow possible row space for each column
+    pub fn set_capacity(&mut self, row_cap: RowOffset) -> Result<(), DBError> {
+        self.clear_stats();
+
+        for ref mut col in &mut self.columns {
+            col.set_capacity(row_cap)?;
+        }
+
+        self.capacity = row_cap;
+        if row_cap < self.rows {
+            self.rows = row_cap;
+        }
+
+        Ok(())
+    }
+
+    /// Returns rowid of the added row
+    pub fn add_row(&mut self) -> Result<RowOffset, DBError> {
+        self.clear_stats();
+
+        if self.capacity > self.rows {
+            let rowid = self.rows;
+            self.rows += 1;
+            Ok(rowid)
+        } else {
+            let rowid = self.rows;
+            let new_cap = self.capacity + 1024;
+
+            self.set_capacity(new_cap)?;
+            self.rows += 1;
+            Ok(rowid)
+        }
+    }
+
+    /// Add a slew of uninitialized rows
+    pub fn add_rows(&mut self, rows: RowOffset) -> Result<RowOffset, DBError> {
+        self.clear_stats();
+
+        if self.capacity >= self.rows + rows {
+            let rowid = self.rows;
+            self.rows += rows;
+            Ok(rowid)
+        } else {
+            let rowid = self.rows;
+            let mut new_cap = self.capacity + rows;
+            new_cap = round_up(new_cap, 1024);
+
+            self.set_capacity(new_cap)?;
+            self.rows += rows;
+            Ok(rowid)
+        }
+    }
+
+    /// Append all rows of `src` to the end of the block. Returns the row offset of the first
+    /// appended row.
+    ///
+    /// The view's columns have to match the block's column types. Nullable view columns can be
+    /// appended to non-nullable block columns as long as they contain no NULLs.
+    pub fn append_view<'v>(&mut self, src: &'v View<'v>) -> Result<RowOffset, DBError> {
+        check_append_schema(&self.schema, src.schema())?;
+
+        let start = self.rows;
+        let rows = src.rows();
+        if rows == 0 {
+            return Ok(start)
+        }
+
+        self.add_rows(rows)?;
+
+        for (pos, col) in self.columns.iter_mut().enumerate() {
+            let src_col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            if let Err(e) = col.copy_rows(start, src_col, 0, rows) {
+                self.rows = start;
+                return Err(e)
+            }
+        }
+
+        Ok(start)
+    }
+
+    /// Zero-copy view of a subset of the block's columns (in the order given) and optionally a
+    /// sub-range of its rows.
+    pub fn slice_view<'a>(&'a self, columns: &[usize], range: Option<RowRange>)
+        -> Result<RefView<'a>, DBError>
+    {
+        let (offset, rows) = range.map_or((0, self.rows), |r| (r.offset, r.rows));
+        if offset + rows > self.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let mut attrs = Vec::with_capacity(columns.len());
+        let mut aliases = Vec::with_capacity(columns.len());
+
+        for pos in columns {
+            let col = self.columns.get(*pos)
+                .ok_or(DBError::make_column_unknown_pos(*pos))?;
+
+            attrs.push(col.attribute().clone());
+            aliases.push(alias_column(col, Some(RowRange { offset: offset, rows: rows }))?);
+        }
+
+        Ok(RefView::new(Schema::from_vec(attrs)?, aliases, rows))
+    }
+
+    /// Copy the block (including varlen data) into a new block backed by `alloc`. The copy can
+    /// outlive the source's allocator.
+    pub fn deep_copy<'a>(&self, alloc: &'a Allocator) -> Result<Block<'a>, DBError> {
+        let columns = self.columns.iter()
+            .map(|c| c.deep_copy(alloc, self.rows))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Block {
+            allocator: alloc,
+            schema: self.schema.clone(),
+            columns: columns,
+            rows: self.rows,
+            capacity: self.capacity,
+            stats: self.stats.clone(),
+            zones: self.zones.clone(),
+        })
+    }
+
+    /// Drop rows past `rows`. Capacity (and column data) is kept.
+    pub fn truncate(&mut self, rows: RowOffset) {
+        if rows < self.rows {
+            self.rows = rows;
+            self.clear_stats();
+        }
+    }
+
+    /// Drop all the rows, keeping the capacity and column memory (including VARLEN data) for
+    /// reuse by the next batch.
+    pub fn clear(&mut self) {
+        for col in &mut self.columns {
+            col.clear();
+        }
+
+        self.rows = 0;
+        self.clear_stats();
+    }
+
+    /// Mutable reference to column and its data.
+    pub fn column_mut(&mut self, pos: usize) -> Option<&mut Column<'b>> {
+        self.clear_stats();
+        self.columns.get_mut(pos)
+    }
+}
+
+/// Columns of `src` can be appended to `dst`; same count and types.
+fn check_append_schema(dst: &Schema, src: &Schema) -> Result<(), DBError> {
+    if dst.count() != src.count() {
+        return Err(DBError::AttributeMissing(format!("column count {} != {}", src.count(), dst.count())))
+    }
+
+    for (d, s) in dst.iter().zip(src.iter()) {
+        if d.dtype != s.dtype {
+            return Err(DBError::AttributeType(s.name.clone()))
+        }
+    }
+
+    Ok(())
+}
+
+/// Concatenate the rows of multiple views into a new Block. The output schema is the first
+/// view's schema, with columns made nullable if they're nullable in any of the views.
+pub fn concat_blocks<'a, 'v>(alloc: &'a Allocator, srcs: &[&'v View<'v>]) -> Result<Block<'a>, DBError> {
+    let first = srcs.first()
+        .ok_or(DBError::AttributeMissing("no views to concatenate".to_string()))?;
+
+    let mut attrs: Vec<Attribute> = first.schema().iter().cloned().collect();
+    for src in srcs {
+        check_append_schema(first.schema(), src.schema())?;
+        for (attr, other) in attrs.iter_mut().zip(src.schema().iter()) {
+            attr.nullable |= other.nullable;
+        }
+    }
+
+    let mut out = Block::new(alloc, &Schema::from_vec(attrs)?);
+
+    let total: RowOffset = srcs.iter().map(|v| v.rows()).sum();
+    if total > 0 {
+        out.set_capacity(total)?;
+    }
+
+    for src in srcs {
+        out.append_view(*src)?;
+    }
+
+    Ok(out)
+}
+
+/// Copy the `src` rows at `indices` (in that order) into a new Block. Rows can be repeated.
+pub fn take<'a, 'v>(alloc: &'a Allocator, src: &'v View<'v>, indices: &[RowOffset])
+    -> Result<Block<'a>, DBError>
+{
+    if indices.iter().any(|idx| *idx >= src.rows()) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let mut out = Block::new(alloc, src.schema());
+    if indices.is_empty() {
+        return Ok(out)
+    }
+
+    out.add_rows(indices.len())?;
+
+    for (pos, col) in out.columns.iter_mut().enumerate() {
+        let src_col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        col.take_rows(0, src_col, indices)?;
+    }
+
+    Ok(out)
+}
+
+/// Copy the `src` rows where the BOOLEAN `mask` column is true into a new Block; NULL mask rows
+/// aren't selected. All / none selected skip the gather.
+pub fn filter_view<'a, 'v, 'm>(alloc: &'a Allocator, src: &'v View<'v>, mask: &'m RefColumn<'m>)
+    -> Result<Block<'a>, DBError>
+{
+    if mask.attribute().dtype != Type::BOOLEAN {
+        return Err(DBError::AttributeType(mask.attribute().name.clone()))
+    }
+
+    let selected = selected_rows(mask, src.rows())?;
+
+    if selected.len() == src.rows() {
+        let mut out = Block::new(alloc, src.schema());
+        out.append_view(src)?;
+        Ok(out)
+    } else {
+        take(alloc, src, &selected)
+    }
+}
+
+/// Copy the `src` rows into the `dst` rows at `indices`; row `i` of `src` is written to row
+/// `indices[i]`.
+pub fn scatter<'v>(dst: &mut Block, src: &'v View<'v>, indices: &[RowOffset]) -> Result<(), DBError> {
+    check_append_schema(&dst.schema, src.schema())?;
+
+    if indices.len() != src.rows() || indices.iter().any(|idx| *idx >= dst.rows) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    dst.clear_stats();
+    for (pos, col) in dst.columns.iter_mut().enumerate() {
+        let src_col = src.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        col.scatter_rows(src_col, 0, indices)?;
+    }
+
+    Ok(())
+}
+
+impl<'a> Index<usize> for Block<'a> {
+    type Output = Column<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.columns[index]
+    }
+}
+
+/// Address mutable column by its inde
+impl<'a> IndexMut<usize> for Block<'a> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.clear_stats();
+        &mut self.columns[index]
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::types::*;
+    use ::quickcheck::{Arbitrary, Gen, QuickCheck};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn nested_columns() {
+        let attrs = vec![
+            Attribute::list("list", false, Attribute::new("elem", false, Type::INT32)),
+            Attribute::structure("struct", false, vec![
+                Attribute::new("a", false, Type::UINT32),
+                Attribute::new("b", true, Type::INT64),
+            ]).unwrap(),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+
+        for row in 0 .. 2 {
+            let (elems, offset) = block[0].list_append(row, row + 1).unwrap();
+            let values = elems.rows_mut::<Int32>().unwrap();
+            for idx in 0 .. row + 1 {
+                values[offset + idx] = (row * 10 + idx) as i32;
+            }
+        }
+
+        block[1].child_mut(0).unwrap().rows_mut::<UInt32>().unwrap()[1] = 7;
+
+        let list = block.column(0).unwrap();
+        let entries = column_row_data::<List>(list).unwrap();
+        assert_eq!((entries.values[1].offset, entries.values[1].len), (1, 2));
+
+        let elems = column_row_data::<Int32>(list.child(0).unwrap()).unwrap();
+        assert_eq!(&elems.values[0 .. 3], &[0, 10, 11]);
+
+        let fields = block.column(1).unwrap();
+        assert_eq!(fields.child(0).unwrap().capacity(), block.capacity());
+        assert_eq!(column_row_data::<UInt32>(fields.child(0).unwrap()).unwrap().values[1], 7);
+    }
+
+    #[test]
+    fn nested_values() {
+        let attrs = vec![
+            Attribute::list("list", false, Attribute::new("elem", false, Type::INT32)),
+            Attribute::map("map", false, Attribute::new("k", false, Type::UINT32),
+                           Attribute::new("v", true, Type::BOOLEAN)).unwrap(),
+            Attribute::structure("struct", true, vec![Attribute::new("a", false, Type::INT64),
+                                                      Attribute::new("b", true, Type::TEXT)]).unwrap(),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+
+        let list = Value::LIST(vec![Value::INT32(1), Value::INT32(2)]);
+        let map = Value::MAP(vec![(Value::UINT32(3), Value::BOOLEAN(true)),
+                                  (Value::UINT32(4), Value::NULL)]);
+
+        list.set_row(&mut block[0], 0).unwrap();
+        map.set_row(&mut block[1], 0).unwrap();
+
+        assert_eq!(column_value(block.column(0).unwrap(), 0).unwrap(), list);
+        assert_eq!(column_value(block.column(1).unwrap(), 0).unwrap(), map);
+
+        let fields = Value::STRUCT(vec![Value::INT64(-1), Value::NULL]);
+        set_column_value(&mut block, 2, 0, &fields).unwrap();
+        set_column_value(&mut block, 2, 1, &Value::NULL).unwrap();
+        assert_eq!(column_value(block.column(2).unwrap(), 0).unwrap(), fields);
+        assert_eq!(column_value(block.column(2).unwrap(), 1).unwrap(), Value::NULL);
+
+        assert!(Value::STRUCT(vec![Value::INT64(1)]).set_row(&mut block[2], 0).is_err());
+        assert!(fields.set_row(&mut block[1], 0).is_err());
+    }
+
+    #[test]
+    fn deep_copy() {
+        let attrs = vec![
+            Attribute::new("id", false, Type::UINT64),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::list("list", false, Attribute::new("elem", false, Type::INT32)),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let copy = {
+            let mut block = Block::new(&allocator::GLOBAL, &schema);
+            block.add_rows(2).unwrap();
+
+            for row in 0 .. 2 {
+                Value::UINT64(row as u64 + 5).set_row(&mut block[0], row).unwrap();
+                Value::LIST(vec![Value::INT32(row as i32)]).set_row(&mut block[2], row).unwrap();
+            }
+
+            Value::from("abc").set_row(&mut block[1], 0).unwrap();
+            Value::NULL.set_row(&mut block[1], 1).unwrap();
+
+            block.deep_copy(&allocator::GLOBAL).unwrap()
+        };
+
+        assert_eq!(copy.rows(), 2);
+        assert_eq!(column_value(copy.column(0).unwrap(), 1).unwrap(), Value::UINT64(6));
+        assert_eq!(column_value(copy.column(1).unwrap(), 0).unwrap(), Value::from("abc"));
+        assert_eq!(column_value(copy.column(1).unwrap(), 1).unwrap(), Value::NULL);
+        assert_eq!(column_value(copy.column(2).unwrap(), 1).unwrap(), Value::LIST(vec![Value::INT32(1)]));
+    }
+
+    #[test]
+    fn append_and_concat() {
+        let list = Attribute::list("list", false, Attribute::new("elem", false, Type::INT32));
+
+        let mut a = Block::new(&allocator::GLOBAL,
+                               &Schema::from_vec(vec![Attribute::new("id", false, Type::UINT32), list.clone()]).unwrap());
+        a.add_row().unwrap();
+        Value::UINT32(1).set_row(&mut a[0], 0).unwrap();
+        Value::LIST(vec![Value::INT32(10)]).set_row(&mut a[1], 0).unwrap();
+
+        let mut b = Block::new(&allocator::GLOBAL,
+                               &Schema::from_vec(vec![Attribute::new("id", true, Type::UINT32), list]).unwrap());
+        b.add_rows(2).unwrap();
+        Value::NULL.set_row(&mut b[0], 0).unwrap();
+        Value::UINT32(3).set_row(&mut b[0], 1).unwrap();
+        for row in 0 .. 2 {
+            Value::LIST(vec![Value::INT32(20), Value::INT32(row as i32)]).set_row(&mut b[1], row).unwrap();
+        }
+
+        // Can't append NULLs into a non-nullable column
+        assert!(a.append_view(&b).is_err());
+        assert_eq!(a.rows(), 1);
+
+        let out = concat_blocks(&allocator::GLOBAL, &[&a, &b]).unwrap();
+        assert_eq!(out.rows(), 3);
+        assert!(out.schema().get(0).unwrap().nullable);
+
+        let ids: Vec<_> = (0 .. 3).map(|r| column_value(out.column(0).unwrap(), r).unwrap()).collect();
+        assert_eq!(ids, vec![Value::UINT32(1), Value::NULL, Value::UINT32(3)]);
+        assert_eq!(column_value(out.column(1).unwrap(), 2).unwrap(),
+                   Value::LIST(vec![Value::INT32(20), Value::INT32(1)]));
+    }
+
+    #[test]
+    fn take_and_scatter() {
+        let attrs = vec![
+            Attribute::new("id", true, Type::INT64),
+            Attribute::new("flag", false, Type::BOOLEAN),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(4).unwrap();
+        for row in 0 .. 4 {
+            let id = if row == 2 { None } else { Some(row as i64) };
+            Value::from(id).set_row(&mut block[0], row).unwrap();
+            Value::from(row % 2 == 0).set_row(&mut block[1], row).unwrap();
+        }
+
+        let out = take(&allocator::GLOBAL, &block, &[3, 2, 3, 0]).unwrap();
+        assert_eq!(out.rows(), 4);
+        let ids: Vec<_> = (0 .. 4).map(|r| column_value(out.column(0).unwrap(), r).unwrap()).collect();
+        assert_eq!(ids, vec![Value::INT64(3), Value::NULL, Value::INT64(3), Value::INT64(0)]);
+        assert_eq!(column_value(out.column(1).unwrap(), 1).unwrap(), Value::BOOLEAN(true));
+
+        assert!(take(&allocator::GLOBAL, &block, &[4]).is_err());
+
+        // Reverse the first two rows back into the block
+        let src = take(&allocator::GLOBAL, &block, &[0, 1]).unwrap();
+        scatter(&mut block, &src, &[1, 0]).unwrap();
+        assert_eq!(column_value(block.column(0).unwrap(), 0).unwrap(), Value::INT64(1));
+        assert_eq!(column_value(block.column(1).unwrap(), 1).unwrap(), Value::BOOLEAN(true));
+    }
+
+    #[test]
+    fn filter_view() {
+        let attrs = vec![
+            Attribute::new("id", true, Type::INT64),
+            Attribute::new("name", false, Type::TEXT),
+            Attribute::new("mask", true, Type::BOOLEAN),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(5).unwrap();
+        for row in 0 .. 5 {
+            let id = if row == 3 { None } else { Some(row as i64) };
+            let mask = if row == 4 { None } else { Some(row != 1) };
+            Value::from(id).set_row(&mut block[0], row).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from(format!("n{}", row).as_str())).unwrap();
+            Value::from(mask).set_row(&mut block[2], row).unwrap();
+        }
+
+        // NULL mask rows aren't selected
+        let out = super::filter_view(&allocator::GLOBAL, &block, block.column(2).unwrap()).unwrap();
+        assert_eq!(out.rows(), 3);
+        let ids: Vec<_> = (0 .. 3).map(|r| column_value(out.column(0).unwrap(), r).unwrap()).collect();
+        assert_eq!(ids, vec![Value::INT64(0), Value::INT64(2), Value::NULL]);
+        assert_eq!(column_value(out.column(1).unwrap(), 2).unwrap(), Value::from("n3"));
+
+        // All selected
+        let first = block.slice_view(&[0, 1, 2], Some(RowRange { offset: 2, rows: 2 })).unwrap();
+        let out = super::filter_view(&allocator::GLOBAL, &first, first.column(2).unwrap()).unwrap();
+        assert_eq!(out.rows(), 2);
+        assert_eq!(column_value(out.column(1).unwrap(), 0).unwrap(), Value::from("n2"));
+
+        // Mask has to be BOOLEAN
+        assert!(super::filter_view(&allocator::GLOBAL, &block, block.column(0).unwrap()).is_err());
+    }
+
+    #[test]
+    fn slice_view() {
+        let attrs = vec![
+            Attribute::new("a", false, Type::UINT32),
+            Attribute::new("b", false, Type::UINT32),
+            Attribute::new("c", false, Type::UINT32),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(3).unwrap();
+        for row in 0 .. 3 {
+            for pos in 0 .. 3 {
+                Value::UINT32((pos * 10 + row) as u32).set_row(&mut block[pos], row).unwrap();
+            }
+        }
+
+        let view = block.slice_view(&[2, 0], Some(RowRange { offset: 1, rows: 2 })).unwrap();
+        assert_eq!(view.rows(), 2);
+        assert_eq!(view.schema().get(0).unwrap().name, "c");
+        assert_eq!(column_value(view.column(0).unwrap(), 0).unwrap(), Value::UINT32(21));
+        assert_eq!(column_value(view.column(1).unwrap(), 1).unwrap(), Value::UINT32(2));
+
+        assert!(block.slice_view(&[3], None).is_err());
+        assert!(block.slice_view(&[0], Some(RowRange { offset: 2, rows: 2 })).is_err());
+    }
+
+    #[test]
+    fn alias_ranges() {
+        let attrs = vec![
+            Attribute::new("u32", false, Type::UINT32),
+            Attribute::new("i64", true, Type::INT64),
+            Attribute::new("bool", true, Type::BOOLEAN),
+            Attribute::new("f64", false, Type::FLOAT64),
+        ];
+
+        let rows = 300;
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(rows).unwrap();
+
+        for row in 0 .. rows {
+            Value::UINT32(row as u32).set_row(&mut block[0], row).unwrap();
+            let i = if row % 3 == 0 { None } else { Some(-(row as i64)) };
+            Value::from(i).set_row(&mut block[1], row).unwrap();
+            let b = if row % 7 == 0 { None } else { Some(row % 2 == 0) };
+            Value::from(b).set_row(&mut block[2], row).unwrap();
+            Value::FLOAT64(row as f64 / 2.0).set_row(&mut block[3], row).unwrap();
+        }
+
+        // Cheap LCG so the ranges are reproducible
+        let mut seed: u64 = 42;
+        let mut next = |max: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % max
+        };
+
+        for _ in 0 .. 200 {
+            let offset = next(rows);
+            let len = next(rows - offset + 1);
+            let range = RowRange { offset: offset, rows: len };
+
+            for pos in 0 .. 4 {
+                let col = block.column(pos).unwrap();
+                let alias = alias_column(col, Some(range)).unwrap();
+                assert_eq!(alias.capacity(), len);
+
+                // Alias of an alias; exercises null bitmap bit offsets
+                let inner = len / 3;
+                let nested = alias_column_range(&alias, RowRange { offset: inner, rows: len - inner }).unwrap();
+
+                for row in 0 .. len {
+                    assert_eq!(column_value(&alias, row).unwrap(), column_value(col, offset + row).unwrap());
+                }
+
+                for row in 0 .. len - inner {
+                    assert_eq!(column_value(&nested, row).unwrap(),
+                               column_value(col, offset + inner + row).unwrap());
+                }
+            }
+        }
+
+        let col = block.column(0).unwrap();
+        assert!(alias_column_range(col, RowRange { offset: block.capacity(), rows: 1 }).is_err());
+    }
+
+    #[test]
+    fn initialized_growth() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("t", true, Type::TEXT));
+
+        for _ in 0 .. 3 {
+            block.add_rows(1000).unwrap();
+            let last = block.rows() - 1;
+            NULL_VALUE.set_row(&mut block[0], last).unwrap();
+        }
+
+        let rows = column_row_data::<types::Text>(&block[0]).unwrap();
+        assert_eq!(rows.nulls.count_ones(), 3);
+        assert!(rows.values.iter().all(|v| v.to_string().is_empty()));
+    }
+
+    #[test]
+    fn simd_padding() {
+        let attrs = vec![
+            Attribute::new("flag", true, Type::BOOLEAN),
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::structure("s", false, vec![Attribute::new("v", false, Type::INT64)]).unwrap(),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.set_capacity(3).unwrap();
+
+        assert_eq!(padded_rows(Type::UINT32, 3), 16);
+        for pos in 0 .. 4 {
+            let col = &block[pos];
+            let size = col.attribute().dtype.size_of();
+            assert!(col.capacity() >= 3);
+            assert_eq!(col.capacity() * size % SIMD_WIDTH, 0);
+            assert_eq!(unsafe { col.rows_ptr() } as usize % SIMD_WIDTH, 0);
+            assert_eq!(col.nulls_raw_slice().len() % SIMD_WIDTH, 0);
+        }
+
+        // Padding is initialized
+        let names = column_row_data::<types::Text>(&block[2]).unwrap();
+        assert!(names.values.iter().all(|v| v.to_string().is_empty()));
+        assert_eq!(block[3].child(0).unwrap().capacity(), block[3].capacity());
+    }
+
+    #[test]
+    fn memory_usage() {
+        let attrs = vec![
+            Attribute::new("id", true, Type::UINT64),
+            Attribute::new("name", false, Type::TEXT),
+            Attribute::list("list", false, Attribute::new("elem", false, Type::INT32)),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        assert_eq!(block.memory_usage().total(), 0);
+
+        block.add_rows(10).unwrap();
+        Value::from("abc").set_row(&mut block[1], 0).unwrap();
+        block[2].list_append(0, 3).unwrap();
+
+        let usage = block.memory_usage();
+        let cap = block.capacity();
+        assert_eq!(usage.nulls, bytes_for(cap));
+        assert_eq!(usage.arena, ARENA_MIN_SIZE);
+        assert_eq!(usage.values, cap * (8 + Type::TEXT.size_of() + Type::LIST.size_of()) + 1024 * 4);
+        assert_eq!(usage.total(), usage.values + usage.nulls + usage.arena);
+    }
+
+    #[test]
+    fn clear_and_reuse() {
+        let attrs = vec![
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::list("list", false, Attribute::new("elem", false, Type::INT32)),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(2).unwrap();
+        Value::from("abc").set_row(&mut block[0], 0).unwrap();
+        NULL_VALUE.set_row(&mut block[0], 1).unwrap();
+        block[1].list_append(0, 3).unwrap();
+
+        let usage = block.memory_usage();
+        block.clear();
+        assert_eq!(block.rows(), 0);
+        assert_eq!(block.memory_usage().total(), usage.total());
+
+        block.add_rows(1).unwrap();
+        assert_eq!(column_value(&block[0], 1).unwrap(), Value::TEXT("".into()));
+        assert_eq!(block[1].list_append(0, 2).unwrap().1, 0);
+        Value::from("de").set_row(&mut block[0], 0).unwrap();
+        assert_eq!(column_value(&block[0], 0).unwrap(), Value::TEXT("de".into()));
+        assert_eq!(block.memory_usage().arena, usage.arena);
+    }
+
+    #[test]
+    fn rle_column() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("flag", true, Type::UINT32));
+        block.add_rows(7).unwrap();
+
+        let values = [Some(1u32), Some(1), Some(1), None, None, Some(2), Some(1)];
+        for (row, v) in values.iter().enumerate() {
+            Value::from(*v).set_row(&mut block[0], row).unwrap();
+        }
+
+        let rle = rle_encode(&allocator::GLOBAL, &block[0], 7).unwrap();
+        assert_eq!(rle.run_count(), 4);
+        assert_eq!(rle.run_of(4).unwrap(), 1);
+        assert_eq!(rle.run_of(5).unwrap(), 2);
+        assert_eq!((rle.run_range(1).offset, rle.run_range(1).rows), (3, 2));
+
+        // Transparent decode
+        let rows = column_row_data::<UInt32>(&rle).unwrap();
+        assert_eq!(rows.values.len(), 7);
+        assert_eq!((rows.values[2], rows.values[5], rows.values[6]), (1, 2, 1));
+        assert!(rows.nulls.get(3) && rows.nulls.get(4) && !rows.nulls.get(5));
+
+        // Aliases keep the runs overlapping the range, rows 2 - 5
+        let alias = alias_column(&rle, Some(RowRange { offset: 2, rows: 4 })).unwrap();
+        let runs = alias.runs().unwrap();
+        assert_eq!(runs.count(), 3);
+        let ranges: Vec<_> = (0 .. 3).map(|run| (runs.range(run).offset, runs.range(run).rows)).collect();
+        assert_eq!(ranges, vec![(0, 1), (1, 2), (3, 1)]);
+        assert_eq!(runs.run_of(2).unwrap(), 1);
+        assert_eq!(column_value(runs.values, 2).unwrap(), Value::UINT32(2));
+
+        // Of an alias
+        let inner = alias_column(&alias, Some(RowRange { offset: 2, rows: 2 })).unwrap();
+        let runs = inner.runs().unwrap();
+        assert_eq!((runs.count(), runs.range(0).rows, runs.range(1).rows), (2, 1, 1));
+        assert!(alias_column(&block[0], None).unwrap().runs().is_none());
+    }
+
+    #[test]
+    fn dict_column() {
+        let attr = Attribute::new("c", true, Type::TEXT);
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_attr(attr));
+        block.add_rows(6).unwrap();
+
+        let values = [Some("b"), Some("a"), None, Some("b"), None, Some("a")];
+        for (row, v) in values.iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::from(*v)).unwrap();
+        }
+
+        let dict = dict_encode(&allocator::GLOBAL, &block[0], 6).unwrap();
+        assert_eq!(dict.codes(), &[0, 1, 2, 0, 2, 1]);
+        assert_eq!(column_value(dict.values(), 2).unwrap(), Value::NULL);
+
+        // Transparent decode
+        for (row, v) in values.iter().enumerate() {
+            assert_eq!(column_value(&dict, row).unwrap(), Value::from(*v));
+        }
+
+        // Aliases keep the codes
+        let alias = alias_column(&dict, Some(RowRange { offset: 2, rows: 3 })).unwrap();
+        assert_eq!(alias.dictionary().unwrap().codes, &[2, 0, 2]);
+        assert!(alias_column(&block[0], None).unwrap().dictionary().is_none());
+
+        assert!(DictColumn::new(&allocator::GLOBAL, dict.decode().unwrap(), vec![1 << 20]).is_err());
+    }
+
+    #[test]
+    fn const_column() {
+        let attr = Attribute::new("c", false, Type::INT64);
+        let col = ConstColumn::new(&allocator::GLOBAL, attr, &Value::INT64(-3), 2000).unwrap();
+
+        assert_eq!(col.value().unwrap(), Value::INT64(-3));
+        assert_eq!(col.capacity(), 2000);
+
+        let rows = column_row_data::<Int64>(&col).unwrap();
+        assert!(rows.values.iter().all(|v| *v == -3));
+    }
+
+    #[test]
+    fn format_rows() {
+        use ::util::copy_value::set_column_value;
+
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, name TEXT, tags LIST<INT64>").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(3).unwrap();
+
+        let rows = vec![
+            (1, Value::from("first\nline"), Value::LIST(vec![Value::INT64(1), Value::NULL])),
+            (22, Value::NULL, Value::LIST(vec![])),
+            (333, Value::from("c"), Value::NULL),
+        ];
+        for (row, (id, name, tags)) in rows.into_iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::UINT32(id)).unwrap();
+            set_column_value(&mut block, 1, row, &name).unwrap();
+            set_column_value(&mut block, 2, row, &tags).unwrap();
+        }
+
+        assert_eq!(block.to_string(), "\
+id  | name        | tags
+----+-------------+----------
+1   | first\\nline | [1, NULL]
+22  | NULL        | []
+333 | c           | NULL
+(3 rows)
+");
+
+        assert_eq!(block.format_rows(1), "\
+id | name        | tags
+---+-------------+----------
+1  | first\\nline | [1, NULL]
+(1 of 3 rows)
+");
+
+        assert!(format!("{:?}", block).starts_with("Block(id UINT32 NOT NULL, name TEXT, tags LIST<INT64>)"));
+    }
+
+    type Row = (Option<i64>, Option<String>);
+
+    /// Random block mutations and reads, checked against a `Vec` of rows
+    #[derive(Clone, Debug)]
+    enum BlockOp {
+        Append(Vec<Row>),
+        SetCapacity(usize),
+        Truncate(usize),
+        Clear,
+        /// Read (and copy) a window of (offset, rows), both modulo the block rows
+        Window(usize, usize),
+    }
+
+    fn num<G: Gen>(g: &mut G, max: usize) -> usize {
+        g.gen_range(0, max)
+    }
+
+    impl Arbitrary for BlockOp {
+        fn arbitrary<G: Gen>(g: &mut G) -> BlockOp {
+            match num(g, 8) {
+                0 | 1 | 2   => {
+                    let rows = num(g, 600);
+                    BlockOp::Append((0 .. rows).map(|_| Row::arbitrary(g)).collect())
+                }
+                3           => BlockOp::SetCapacity(num(g, 2000)),
+                4           => BlockOp::Truncate(num(g, 2000)),
+                5           => BlockOp::Clear,
+                _           => BlockOp::Window(num(g, 2000), num(g, 2000)),
+            }
+        }
+    }
+
+    fn view_matches<'v>(view: &'v View<'v>, expected: &[Row]) -> bool {
+        let (ints, texts) = (view.column(0).unwrap(), view.column(1).unwrap());
+
+        view.rows() == expected.len() && expected.iter().enumerate().all(|(row, &(i, ref s))| {
+            column_value(ints, row).unwrap() == Value::from(i)
+                && column_value(texts, row).unwrap() == Value::from(s.clone())
+        })
+    }
+
+    fn block_ops(ops: Vec<BlockOp>) -> bool {
+        let schema = Schema::parse_ddl("i INT64, s TEXT").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        let mut copy = Block::new(&allocator::GLOBAL, &schema);
+        let (mut model, mut copy_model): (Vec<Row>, Vec<Row>) = (Vec::new(), Vec::new());
+
+        for op in ops {
+            match op {
+                BlockOp::Append(rows) => {
+                    let start = block.add_rows(rows.len()).unwrap();
+                    for (row, &(i, ref s)) in rows.iter().enumerate() {
+                        set_column_value(&mut block, 0, start + row, &i).unwrap();
+                        set_column_value(&mut block, 1, start + row, s).unwrap();
+                    }
+                    model.extend(rows);
+                }
+                BlockOp::SetCapacity(rows) => {
+                    block.set_capacity(rows).unwrap();
+                    model.truncate(rows);
+                }
+                BlockOp::Truncate(rows) => {
+                    block.truncate(rows);
+                    model.truncate(rows);
+                }
+                BlockOp::Clear => {
+                    block.clear();
+                    model.clear();
+                }
+                BlockOp::Window(offset, rows) => {
+                    let offset = offset % (model.len() + 1);
+                    let rows = rows % (model.len() - offset + 1);
+                    let expected = &model[offset .. offset + rows];
+                    let range = RowRange { offset: offset, rows: rows };
+
+                    let window = window_alias(&block, Some(range)).unwrap();
+                    let sliced = block.slice_view(&[0, 1], Some(range)).unwrap();
+                    if !view_matches(&window, expected) || !view_matches(&sliced, expected) {
+                        return false
+                    }
+
+                    // Ranges past the end are rejected, not clamped
+                    let past = RowRange { offset: offset, rows: model.len() - offset + 1 };
+                    if window_alias(&block, Some(past)).is_ok() || block.slice_view(&[0], Some(past)).is_ok() {
+                        return false
+                    }
+
+                    // Copy through the alias, null bitmaps at arbitrary bit offsets
+                    copy.append_view(&window).unwrap();
+                    copy_model.extend_from_slice(expected);
+                }
+            }
+
+            if block.capacity() < block.rows() || !view_matches(&block, &model) {
+                return false
+            }
+        }
+
+        view_matches(&copy, &copy_model)
+    }
+
+    #[test]
+    fn block_properties() {
+        QuickCheck::new().tests(50).quickcheck(block_ops as fn(Vec<BlockOp>) -> bool);
+    }
+}
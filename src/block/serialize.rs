@@ -0,0 +1,1044 @@
+// vim : set ts=4 sw=4 et :
+
+//! Native binary serialization of Blocks.
+//!
+//! Layout (all integers are little endian u64 unless noted):
+//!
+//! ```text
+//! header:  magic "DBKB", version (u16), flags (u16), schema length
+//! schema:  column count, then per attribute: name length, name, type (u8), nullable (u8),
+//!          children count, children... (padded to 8 bytes)
+//! rows
+//! columns: per column (children follow their parent):
+//!          rows, nulls length, values length, data length,
+//!          nulls bitmap, values, VARLEN data (each padded to 8 bytes)
+//! ```
+//!
+//! VARLEN values are (offset, length) pairs into the column's data. LIST & MAP entries are
+//! (offset, length) pairs into the child columns, which are compacted; STRUCT fields have the
+//! parent's rows. Sections start 8 byte aligned, so the data can be used in place (`map_block`).
+//! Values are stored in the host's byte order; only little endian hosts are supported.
+//!
+//! With the compressed header flag each top level column (with its children) is stored in a frame:
+//! codec id (0 for uncompressed), uncompressed length, stored length, stored data (padded to 8
+//! bytes). See `util::codec` for the codecs.
+
+use std::io::{Read, Write};
+use std::ptr;
+use std::slice;
+use std::str;
+
+use ::allocator::Allocator;
+use ::bitmaps::{Bitmap, bytes_for};
+use ::block::{Block, Column, RefColumn, View, column_nulls, rows_from_rawptr_const};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{self, ListEntry, Type};
+use ::util::codec::{Codec, Codecs};
+
+pub const MAGIC: &'static [u8] = b"DBKB";
+pub const VERSION: u16 = 1;
+
+/// Header flag: every column is stored in a (possibly) compressed frame
+pub const FLAG_COMPRESSED: u16 = 1;
+
+/// Size of a VARLEN value or LIST / MAP entry slot
+pub const SLOT_SIZE: usize = 16;
+
+/// Limit on schema nesting, so malformed input can't blow the stack
+const MAX_DEPTH: usize = 64;
+
+fn error<S: Into<String>>(msg: S) -> DBError {
+    DBError::Serialization(msg.into())
+}
+
+fn check_host() -> Result<(), DBError> {
+    if cfg!(target_endian = "big") {
+        return Err(error("big endian hosts are not supported"))
+    }
+    Ok(())
+}
+
+pub fn type_id(dtype: Type) -> u8 {
+    match dtype {
+        Type::UINT32    => 1,
+        Type::UINT64    => 2,
+        Type::INT32     => 3,
+        Type::INT64     => 4,
+        Type::FLOAT32   => 5,
+        Type::FLOAT64   => 6,
+        Type::BOOLEAN   => 7,
+        Type::TEXT      => 8,
+        Type::BLOB      => 9,
+        Type::JSON      => 10,
+        Type::LIST      => 11,
+        Type::STRUCT    => 12,
+        Type::MAP       => 13,
+        Type::TIMESTAMP     => 14,
+        Type::TIMESTAMP_TZ  => 15,
+    }
+}
+
+pub fn type_from_id(id: u8) -> Result<Type, DBError> {
+    let dtype = match id {
+        1   => Type::UINT32,
+        2   => Type::UINT64,
+        3   => Type::INT32,
+        4   => Type::INT64,
+        5   => Type::FLOAT32,
+        6   => Type::FLOAT64,
+        7   => Type::BOOLEAN,
+        8   => Type::TEXT,
+        9   => Type::BLOB,
+        10  => Type::JSON,
+        11  => Type::LIST,
+        12  => Type::STRUCT,
+        13  => Type::MAP,
+        14  => Type::TIMESTAMP,
+        15  => Type::TIMESTAMP_TZ,
+        _   => return Err(DBError::UnknownType(format!("serialized type id {}", id))),
+    };
+
+    Ok(dtype)
+}
+
+/// Size of a row in the serialized values section
+pub fn slot_size(dtype: Type) -> usize {
+    match dtype {
+        Type::STRUCT                => 0,
+        Type::LIST | Type::MAP      => SLOT_SIZE,
+        _ if dtype.is_varlen()      => SLOT_SIZE,
+        _                           => dtype.size_of(),
+    }
+}
+
+fn padding(pos: usize) -> usize {
+    (8 - pos % 8) % 8
+}
+
+/// Output stream that keeps track of the position for alignment
+struct Writer<'w, W: Write + 'w> {
+    out: &'w mut W,
+    pos: usize,
+}
+
+impl<'w, W: Write> Writer<'w, W> {
+    fn bytes(&mut self, data: &[u8]) -> Result<(), DBError> {
+        self.out.write_all(data)?;
+        self.pos += data.len();
+        Ok(())
+    }
+
+    fn u64(&mut self, value: u64) -> Result<(), DBError> {
+        let mut raw = [0u8; 8];
+        for (idx, b) in raw.iter_mut().enumerate() {
+            *b = (value >> (8 * idx)) as u8;
+        }
+        self.bytes(&raw)
+    }
+
+    fn pad(&mut self) -> Result<(), DBError> {
+        let zeros = [0u8; 8];
+        let len = padding(self.pos);
+        self.bytes(&zeros[.. len])
+    }
+}
+
+fn schema_bytes(schema: &Schema) -> Vec<u8> {
+    fn attr_bytes(out: &mut Vec<u8>, attr: &Attribute) {
+        put_u64(out, attr.name.len() as u64);
+        out.extend_from_slice(attr.name.as_bytes());
+        out.push(type_id(attr.dtype));
+        out.push(attr.nullable as u8);
+        put_u64(out, attr.children.len() as u64);
+        for child in &attr.children {
+            attr_bytes(out, child);
+        }
+    }
+
+    let mut out = Vec::new();
+    put_u64(&mut out, schema.count() as u64);
+    for attr in schema.iter() {
+        attr_bytes(&mut out, attr);
+    }
+
+    while out.len() % 8 != 0 {
+        out.push(0);
+    }
+    out
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend((0 .. 8).map(|idx| (value >> (8 * idx)) as u8));
+}
+
+fn get_u64(raw: &[u8]) -> u64 {
+    raw[.. 8].iter().rev().fold(0u64, |acc, b| acc << 8 | *b as u64)
+}
+
+/// Serialize the view's rows; see `Block::serialize`
+pub fn serialize_view<'v, W: Write>(view: &'v View<'v>, out: &mut W) -> Result<usize, DBError> {
+    serialize_view_with(view, out, &[])
+}
+
+/// Serialize the view's rows compressing each column with its codec (`None` for uncompressed).
+/// `codecs` is either empty or has an entry per column.
+pub fn serialize_view_with<'v, W: Write>(view: &'v View<'v>, out: &mut W, codecs: &[Option<&Codec>])
+    -> Result<usize, DBError>
+{
+    check_host()?;
+
+    let count = view.schema().count();
+    if !codecs.is_empty() && codecs.len() != count {
+        return Err(error(format!("{} codecs for {} columns", codecs.len(), count)))
+    }
+
+    let compressed = codecs.iter().any(|c| c.is_some());
+    let flags = if compressed { FLAG_COMPRESSED } else { 0 };
+
+    let schema = schema_bytes(view.schema());
+    let mut writer = Writer { out: out, pos: 0 };
+
+    writer.bytes(MAGIC)?;
+    writer.bytes(&[VERSION as u8, (VERSION >> 8) as u8])?;
+    writer.bytes(&[flags as u8, (flags >> 8) as u8])?;
+    writer.u64(schema.len() as u64)?;
+    writer.bytes(&schema)?;
+
+    let rows = view.rows();
+    writer.u64(rows as u64)?;
+
+    let indices: Vec<RowOffset> = (0 .. rows).collect();
+    for pos in 0 .. count {
+        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+
+        if !compressed {
+            write_column(&mut writer, col, &indices)?;
+            continue
+        }
+
+        // Compressed column frame: codec id, uncompressed length, stored length, data
+        let mut raw = Vec::new();
+        write_column(&mut Writer { out: &mut raw, pos: 0 }, col, &indices)?;
+
+        let packed = match codecs[pos] {
+            Some(codec) => Some((codec.id(), codec.compress(&raw)?)),
+            None        => None,
+        };
+
+        // Keep the data uncompressed if it doesn't help
+        let (id, data) = match packed {
+            Some((id, ref data)) if data.len() < raw.len()  => (id, &data[..]),
+            _                                               => (0, &raw[..]),
+        };
+
+        writer.u64(id as u64)?;
+        writer.u64(raw.len() as u64)?;
+        writer.u64(data.len() as u64)?;
+        writer.bytes(data)?;
+        writer.pad()?;
+    }
+
+    Ok(writer.pos)
+}
+
+/// Raw row data of the column
+unsafe fn raw_rows<'c>(col: &'c RefColumn<'c>) -> &'c [u8] {
+    let len = col.capacity() * col.attribute().dtype.size_of();
+    let ptr = col.rows_ptr();
+    if ptr.is_null() { &[] } else { slice::from_raw_parts(ptr, len) }
+}
+
+/// Write the column's rows at `indices` (and their children)
+fn write_column<'c, W: Write>(writer: &mut Writer<W>, col: &'c RefColumn<'c>, indices: &[RowOffset])
+    -> Result<(), DBError>
+{
+    let attr = col.attribute();
+    let dtype = attr.dtype;
+    let rows = indices.len();
+
+    if indices.iter().any(|idx| *idx >= col.capacity()) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let nulls = column_nulls(col);
+    let is_null = |idx: RowOffset| attr.nullable && nulls.get(idx);
+
+    let raw = unsafe { raw_rows(col) };
+    let varlen = if dtype.is_varlen() {
+        unsafe { rows_from_rawptr_const::<types::RawData>(col.rows_ptr(), col.capacity()) }
+    } else {
+        &[]
+    };
+    let entries = if dtype == Type::LIST || dtype == Type::MAP {
+        unsafe { rows_from_rawptr_const::<ListEntry>(col.rows_ptr(), col.capacity()) }
+    } else {
+        &[]
+    };
+
+    let data_len: usize = if dtype.is_varlen() {
+        indices.iter().filter(|idx| !is_null(**idx)).map(|idx| varlen[*idx].size).sum()
+    } else {
+        0
+    };
+
+    let nulls_len = if attr.nullable { bytes_for(rows) } else { 0 };
+    writer.u64(rows as u64)?;
+    writer.u64(nulls_len as u64)?;
+    writer.u64((rows * slot_size(dtype)) as u64)?;
+    writer.u64(data_len as u64)?;
+
+    if attr.nullable {
+        let mut bits = vec![0u8; nulls_len];
+        for (row, idx) in indices.iter().enumerate() {
+            if is_null(*idx) {
+                bits[row >> 3] |= 1 << (row & 7);
+            }
+        }
+        writer.bytes(&bits)?;
+        writer.pad()?;
+    }
+
+    // Child rows; STRUCT fields are row aligned, LIST & MAP elements are compacted
+    let mut child_indices = Vec::new();
+
+    match dtype {
+        Type::STRUCT            => (),
+        Type::LIST | Type::MAP  => {
+            for idx in indices {
+                let entry = if is_null(*idx) { ListEntry::default() } else { entries[*idx] };
+                writer.u64(child_indices.len() as u64)?;
+                writer.u64(entry.len as u64)?;
+                child_indices.extend(entry.offset .. entry.offset + entry.len);
+            }
+        }
+        _ if dtype.is_varlen()  => {
+            let mut offset = 0;
+            for idx in indices {
+                let size = if is_null(*idx) { 0 } else { varlen[*idx].size };
+                writer.u64(offset as u64)?;
+                writer.u64(size as u64)?;
+                offset += size;
+            }
+            writer.pad()?;
+
+            for idx in indices.iter().filter(|idx| !is_null(**idx)) {
+                let value: &[u8] = varlen[*idx].as_ref();
+                writer.bytes(value)?;
+            }
+        }
+        _                       => {
+            let size = dtype.size_of();
+            let contiguous = indices.windows(2).all(|w| w[1] == w[0] + 1);
+
+            if contiguous && rows > 0 {
+                writer.bytes(&raw[indices[0] * size .. (indices[0] + rows) * size])?;
+            } else {
+                for idx in indices {
+                    writer.bytes(&raw[idx * size .. (idx + 1) * size])?;
+                }
+            }
+        }
+    }
+    writer.pad()?;
+
+    let children: &[RowOffset] = if dtype == Type::STRUCT { indices } else { &child_indices };
+    for pos in 0 .. attr.children.len() {
+        let child = col.child(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        write_column(writer, child, children)?;
+    }
+
+    Ok(())
+}
+
+/// Input stream that keeps track of the position for alignment
+struct Reader<'r, R: Read + 'r> {
+    input: &'r mut R,
+    pos: usize,
+}
+
+impl<'r, R: Read> Reader<'r, R> {
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, DBError> {
+        // Don't trust the length up front; grow the buffer as data arrives
+        let mut out = Vec::new();
+        (&mut *self.input).take(len as u64).read_to_end(&mut out)?;
+        if out.len() != len {
+            return Err(error("unexpected end of input"))
+        }
+
+        self.pos += len;
+        Ok(out)
+    }
+
+    fn u64(&mut self) -> Result<u64, DBError> {
+        let mut raw = [0u8; 8];
+        self.input.read_exact(&mut raw)?;
+        self.pos += 8;
+        Ok(get_u64(&raw))
+    }
+
+    fn usize(&mut self) -> Result<usize, DBError> {
+        let value = self.u64()?;
+        if value > isize::max_value() as u64 {
+            return Err(error("length out of range"))
+        }
+        Ok(value as usize)
+    }
+
+    fn pad(&mut self) -> Result<(), DBError> {
+        let len = padding(self.pos);
+        self.bytes(len).map(|_| ())
+    }
+}
+
+/// Parser over in memory serialized data
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DBError> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| error("unexpected end of input"))?;
+        let out = &self.data[self.pos .. end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u64(&mut self) -> Result<usize, DBError> {
+        let value = get_u64(self.bytes(8)?);
+        if value > isize::max_value() as u64 {
+            return Err(error("length out of range"))
+        }
+        Ok(value as usize)
+    }
+
+    fn pad(&mut self) -> Result<(), DBError> {
+        let len = padding(self.pos);
+        self.bytes(len).map(|_| ())
+    }
+
+    fn attr(&mut self, depth: usize) -> Result<Attribute, DBError> {
+        if depth > MAX_DEPTH {
+            return Err(error("schema nested too deep"))
+        }
+
+        let len = self.u64()?;
+        let name = String::from_utf8(self.bytes(len)?.to_vec())
+            .map_err(|_| error("invalid attribute name"))?;
+        let flags = self.bytes(2)?;
+        let dtype = type_from_id(flags[0])?;
+        let nullable = flags[1] != 0;
+
+        let count = self.u64()?;
+        let mut children = Vec::new();
+        for _ in 0 .. count {
+            children.push(self.attr(depth + 1)?);
+        }
+
+        let expected = match dtype {
+            Type::LIST      => count == 1,
+            Type::MAP       => count == 2,
+            Type::STRUCT    => true,
+            _               => count == 0,
+        };
+        if !expected {
+            return Err(error(format!("{}: {} children for {}", name, count, dtype.name())))
+        }
+
+        Ok(Attribute::nested(name, nullable, dtype, children))
+    }
+}
+
+/// Parse the serialized schema
+pub fn parse_schema(data: &[u8]) -> Result<Schema, DBError> {
+    let mut parser = Parser { data: data, pos: 0 };
+    let count = parser.u64()?;
+
+    let mut attrs = Vec::new();
+    for _ in 0 .. count {
+        attrs.push(parser.attr(0)?);
+    }
+
+    Schema::from_vec(attrs)
+}
+
+/// Returns the header flags
+fn check_header(header: &[u8]) -> Result<u16, DBError> {
+    if &header[.. 4] != MAGIC {
+        return Err(error("missing magic number"))
+    }
+
+    let version = header[4] as u16 | (header[5] as u16) << 8;
+    if version != VERSION {
+        return Err(error(format!("unsupported version {}", version)))
+    }
+
+    let flags = header[6] as u16 | (header[7] as u16) << 8;
+    if flags & !FLAG_COMPRESSED != 0 {
+        return Err(error(format!("unsupported flags {:x}", flags)))
+    }
+
+    Ok(flags)
+}
+
+/// Deserialize a Block; see `Block::deserialize`
+pub fn deserialize_block<'a, R: Read>(input: &mut R, alloc: &'a Allocator) -> Result<Block<'a>, DBError> {
+    deserialize_block_with(input, alloc, &Codecs::default())
+}
+
+/// Deserialize a Block, decompressing columns with the `codecs`
+pub fn deserialize_block_with<'a, R: Read>(input: &mut R, alloc: &'a Allocator, codecs: &Codecs)
+    -> Result<Block<'a>, DBError>
+{
+    check_host()?;
+
+    let mut reader = Reader { input: input, pos: 0 };
+
+    let flags = check_header(&reader.bytes(8)?)?;
+
+    let schema_len = reader.usize()?;
+    let schema = parse_schema(&reader.bytes(schema_len)?)?;
+    reader.pad()?;
+
+    let rows = reader.usize()?;
+
+    let mut block = Block::new(alloc, &schema);
+    if rows > 0 {
+        block.add_rows(rows)?;
+    }
+
+    for col in &mut block.columns {
+        if flags & FLAG_COMPRESSED == 0 {
+            read_column(&mut reader, col, rows)?;
+            continue
+        }
+
+        let (id, len, stored) = (reader.u64()?, reader.usize()?, reader.usize()?);
+        let data = reader.bytes(stored)?;
+        reader.pad()?;
+
+        let raw = match id {
+            0   => data,
+            _   => {
+                let codec = codecs.get(id as u8).filter(|_| id <= u8::max_value() as u64)
+                    .ok_or_else(|| error(format!("unknown codec {}", id)))?;
+                codec.decompress(&data, len)?
+            }
+        };
+
+        if raw.len() != len {
+            return Err(error(format!("{}: unexpected column length", col.attribute().name)))
+        }
+
+        read_column(&mut Reader { input: &mut &raw[..], pos: 0 }, col, rows)?;
+    }
+
+    Ok(block)
+}
+
+/// TEXT & JSON values are later used as `str` without checks
+fn check_varlen(attr: &Attribute, value: &[u8]) -> Result<(), DBError> {
+    match attr.dtype {
+        Type::TEXT | Type::JSON => str::from_utf8(value).map(|_| ())
+            .map_err(|_| error(format!("{}: invalid UTF-8", attr.name))),
+        _                       => Ok(()),
+    }
+}
+
+fn check_booleans(attr: &Attribute, values: &[u8]) -> Result<(), DBError> {
+    if values.iter().any(|b| *b > 1) {
+        return Err(error(format!("{}: invalid boolean", attr.name)))
+    }
+    Ok(())
+}
+
+/// Read `rows` rows of the column, starting at row 0
+fn read_column<R: Read>(reader: &mut Reader<R>, col: &mut Column, rows: RowOffset) -> Result<(), DBError> {
+    let dtype = col.attribute().dtype;
+    let nullable = col.attribute().nullable;
+
+    let (col_rows, nulls_len, values_len, data_len) =
+        (reader.usize()?, reader.usize()?, reader.usize()?, reader.usize()?);
+
+    let nulls_expected = if nullable { bytes_for(rows) } else { 0 };
+    if col_rows != rows || nulls_len != nulls_expected || Some(values_len) != rows.checked_mul(slot_size(dtype)) {
+        return Err(error(format!("{}: unexpected column layout", col.attribute().name)))
+    }
+
+    if nullable {
+        let bits = reader.bytes(nulls_len)?;
+        reader.pad()?;
+        col.nulls_mut()?.copy_from(0, &Bitmap::new(&bits, 0, rows), 0, rows);
+    }
+
+    let values = reader.bytes(values_len)?;
+    reader.pad()?;
+
+    if dtype == Type::BOOLEAN {
+        check_booleans(col.attribute(), &values)?;
+    }
+
+    let slot = |row: usize| {
+        let pos = row * SLOT_SIZE;
+        (get_u64(&values[pos ..]) as usize, get_u64(&values[pos + 8 ..]) as usize)
+    };
+
+    let mut child_rows = rows;
+
+    match dtype {
+        Type::STRUCT            => (),
+        Type::LIST | Type::MAP  => {
+            // Compacted entries are appended in order, so they keep their offsets
+            child_rows = 0;
+            for row in 0 .. rows {
+                let (offset, len) = slot(row);
+                if offset != child_rows {
+                    return Err(error(format!("{}: entries out of order", col.attribute().name)))
+                }
+
+                child_rows = child_rows.checked_add(len)
+                    .ok_or_else(|| error(format!("{}: entries out of range", col.attribute().name)))?;
+                col.reserve_entries(row, len)?;
+            }
+        }
+        _ if dtype.is_varlen()  => {
+            let data = reader.bytes(data_len)?;
+            for row in 0 .. rows {
+                let (offset, len) = slot(row);
+                let end = offset.checked_add(len).filter(|end| *end <= data.len())
+                    .ok_or_else(|| error(format!("{}: value out of bounds", col.attribute().name)))?;
+                check_varlen(col.attribute(), &data[offset .. end])?;
+
+                if len > 0 {
+                    let ptr = col.arena.append(&data[offset .. end])?.1;
+                    unsafe {
+                        *(col.raw.as_mut_ptr() as *mut types::RawData).offset(row as isize) =
+                            types::RawData { data: ptr, size: len };
+                    }
+                }
+            }
+        }
+        _ if values_len > 0     => unsafe {
+            ::std::ptr::copy_nonoverlapping(values.as_ptr(), col.raw.as_mut_ptr(), values_len);
+        },
+        _                       => (),
+    }
+    reader.pad()?;
+
+    for child in &mut col.children {
+        read_column(reader, child, child_rows)?;
+    }
+
+    Ok(())
+}
+
+/// Column of a block used in place (see `map_block`).
+///
+/// Fixed size values, null bitmaps and LIST / MAP entries point at the serialized data. Only the
+/// VARLEN value slots (pointing at the serialized VARLEN data) and the STRUCT rows are built.
+pub struct MappedColumn<'d> {
+    attr: Attribute,
+    rows: RowOffset,
+    raw: &'d [u8],
+    raw_nulls: &'d [u8],
+    /// Built rows; u64 for alignment
+    owned: Vec<u64>,
+    children: Vec<MappedColumn<'d>>,
+}
+
+/// Block serialized in the native format, used in place (see `map_block`)
+pub struct MappedBlock<'d> {
+    schema: Schema,
+    columns: Vec<MappedColumn<'d>>,
+    rows: RowOffset,
+}
+
+impl<'d> MappedColumn<'d> {
+    fn is_built(&self) -> bool {
+        let dtype = self.attr.dtype;
+        dtype == Type::STRUCT || (dtype.is_varlen() && dtype != Type::LIST && dtype != Type::MAP)
+    }
+}
+
+impl<'d> RefColumn<'d> for MappedColumn<'d> {
+    fn attribute(&self) -> &Attribute {
+        &self.attr
+    }
+
+    fn capacity(&self) -> usize {
+        self.rows
+    }
+
+    unsafe fn rows_ptr(&self) -> *const u8 {
+        if self.is_built() {
+            self.owned.as_ptr() as *const u8
+        } else if self.raw.is_empty() {
+            ptr::null()
+        } else {
+            self.raw.as_ptr()
+        }
+    }
+
+    unsafe fn nulls_ptr(&self) -> *const u8 {
+        if self.raw_nulls.is_empty() { ptr::null() } else { self.raw_nulls.as_ptr() }
+    }
+
+    fn rows_raw_slice(&'d self) -> &'d [u8] {
+        if self.is_built() {
+            let len = self.rows * self.attr.dtype.size_of();
+            unsafe { slice::from_raw_parts(self.owned.as_ptr() as *const u8, len) }
+        } else {
+            self.raw
+        }
+    }
+
+    fn nulls_raw_slice(&'d self) -> &'d [u8] {
+        self.raw_nulls
+    }
+
+    fn child(&'d self, pos: usize) -> Option<&'d RefColumn<'d>> {
+        self.children.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+}
+
+impl<'d> View<'d> for MappedBlock<'d> {
+    fn schema(&'d self) -> &'d Schema {
+        &self.schema
+    }
+
+    fn column(&'d self, pos: usize) -> Option<&'d RefColumn<'d>> {
+        self.columns.get(pos)
+            .map(|c| c as &RefColumn)
+    }
+
+    fn rows(&self) -> RowOffset {
+        self.rows
+    }
+}
+
+/// Use the serialized block at the start of `data` in place, without copying the column data.
+///
+/// `data` has to be 8 byte aligned (true for memory maps). Returns the block and its size in
+/// bytes; serialized blocks can be concatenated.
+pub fn map_block<'d>(data: &'d [u8]) -> Result<(MappedBlock<'d>, usize), DBError> {
+    check_host()?;
+
+    // LIST & MAP entries are used as `ListEntry` in place
+    if cfg!(not(target_pointer_width = "64")) {
+        return Err(error("mapping requires a 64 bit host"))
+    }
+
+    if (data.as_ptr() as usize) % 8 != 0 {
+        return Err(error("misaligned data"))
+    }
+
+    let mut parser = Parser { data: data, pos: 0 };
+    if check_header(parser.bytes(8)?)? & FLAG_COMPRESSED != 0 {
+        return Err(error("compressed blocks can't be used in place"))
+    }
+
+    let schema_len = parser.u64()?;
+    let schema = parse_schema(parser.bytes(schema_len)?)?;
+    parser.pad()?;
+
+    let rows = parser.u64()?;
+
+    let mut columns = Vec::with_capacity(schema.count());
+    for attr in schema.iter() {
+        columns.push(map_column(&mut parser, attr, rows)?);
+    }
+
+    let block = MappedBlock { schema: schema, columns: columns, rows: rows };
+    Ok((block, parser.pos))
+}
+
+fn map_column<'d>(parser: &mut Parser<'d>, attr: &Attribute, rows: RowOffset) -> Result<MappedColumn<'d>, DBError> {
+    let dtype = attr.dtype;
+
+    let (col_rows, nulls_len, values_len, data_len) =
+        (parser.u64()?, parser.u64()?, parser.u64()?, parser.u64()?);
+
+    let nulls_expected = if attr.nullable { bytes_for(rows) } else { 0 };
+    if col_rows != rows || nulls_len != nulls_expected || Some(values_len) != rows.checked_mul(slot_size(dtype)) {
+        return Err(error(format!("{}: unexpected column layout", attr.name)))
+    }
+
+    let raw_nulls = parser.bytes(nulls_len)?;
+    parser.pad()?;
+
+    let values = parser.bytes(values_len)?;
+    parser.pad()?;
+
+    let slot = |row: usize| {
+        let pos = row * SLOT_SIZE;
+        (get_u64(&values[pos ..]) as usize, get_u64(&values[pos + 8 ..]) as usize)
+    };
+
+    let mut raw: &[u8] = &[];
+    let mut owned = Vec::new();
+    let mut child_rows = rows;
+
+    match dtype {
+        Type::STRUCT            => owned = vec![0u64; (rows + 7) / 8],
+        Type::LIST | Type::MAP  => {
+            child_rows = 0;
+            for row in 0 .. rows {
+                let (offset, len) = slot(row);
+                if offset != child_rows {
+                    return Err(error(format!("{}: entries out of order", attr.name)))
+                }
+                child_rows = child_rows.checked_add(len)
+                    .ok_or_else(|| error(format!("{}: entries out of range", attr.name)))?;
+            }
+            raw = values;
+        }
+        _ if dtype.is_varlen()  => {
+            let data = parser.bytes(data_len)?;
+            owned = vec![0u64; rows * 2];
+
+            {
+                let slots = unsafe { slice::from_raw_parts_mut(owned.as_mut_ptr() as *mut types::RawData, rows) };
+                for (row, value) in slots.iter_mut().enumerate() {
+                    let (offset, len) = slot(row);
+                    let end = offset.checked_add(len).filter(|end| *end <= data.len())
+                        .ok_or_else(|| error(format!("{}: value out of bounds", attr.name)))?;
+                    check_varlen(attr, &data[offset .. end])?;
+
+                    *value = types::RawData { data: data[offset .. end].as_ptr() as *mut u8, size: len };
+                }
+            }
+        }
+        Type::BOOLEAN           => {
+            check_booleans(attr, values)?;
+            raw = values;
+        }
+        _                       => raw = values,
+    }
+    parser.pad()?;
+
+    let mut children = Vec::with_capacity(attr.children.len());
+    for child in &attr.children {
+        children.push(map_column(parser, child, child_rows)?);
+    }
+
+    Ok(MappedColumn {
+        attr: attr.clone(),
+        rows: rows,
+        raw: raw,
+        raw_nulls: raw_nulls,
+        owned: owned,
+        children: children,
+    })
+}
+
+impl<'b> Block<'b> {
+    /// Write the block's schema & rows in the native binary format. Returns the number of bytes
+    /// written.
+    pub fn serialize<W: Write>(&self, out: &mut W) -> Result<usize, DBError> {
+        serialize_view(self, out)
+    }
+
+    /// Like `serialize`, compressing each column with its codec (`None` for uncompressed).
+    /// `codecs` is either empty or has an entry per column.
+    pub fn serialize_with<W: Write>(&self, out: &mut W, codecs: &[Option<&Codec>]) -> Result<usize, DBError> {
+        serialize_view_with(self, out, codecs)
+    }
+
+    /// Read a block written by `serialize` (or `serialize_view`). Compressed columns can use any
+    /// of the built-in codecs.
+    pub fn deserialize<'a, R: Read>(input: &mut R, alloc: &'a Allocator) -> Result<Block<'a>, DBError> {
+        deserialize_block(input, alloc)
+    }
+
+    /// Read a block, decompressing columns with the `codecs`
+    pub fn deserialize_with<'a, R: Read>(input: &mut R, alloc: &'a Allocator, codecs: &Codecs)
+        -> Result<Block<'a>, DBError>
+    {
+        deserialize_block_with(input, alloc, codecs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use ::allocator;
+    use ::block::{column_value, window_alias};
+    use ::row::RowRange;
+    use ::types::Value;
+    use ::util::codec;
+    use ::util::copy_value::set_column_value;
+
+    fn values<'v>(view: &'v View<'v>) -> Vec<Vec<Value<'static>>> {
+        (0 .. view.rows())
+            .map(|row| (0 .. view.schema().count())
+                .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                .collect())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let attrs = vec![
+            Attribute::new("u", false, Type::UINT32),
+            Attribute::new("f", true, Type::FLOAT64),
+            Attribute::new("b", false, Type::BOOLEAN),
+            Attribute::new("t", true, Type::TEXT),
+            Attribute::list("l", true, Attribute::new("e", false, Type::INT64)),
+            Attribute::structure("s", false, vec![
+                Attribute::new("x", true, Type::INT32),
+                Attribute::new("y", false, Type::BLOB),
+            ]).unwrap(),
+            Attribute::map("m", false, Attribute::new("k", false, Type::UINT64), Attribute::new("v", true, Type::INT32)).unwrap(),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(3).unwrap();
+
+        let text = |v: &str| Value::TEXT(Cow::Owned(v.to_string()));
+        let blob = |v: &[u8]| Value::BLOB(Cow::Owned(v.to_vec()));
+        let rows = vec![
+            vec![Value::UINT32(1), Value::FLOAT64(0.5), Value::BOOLEAN(true), text("a"),
+                 Value::LIST(vec![Value::INT64(1), Value::INT64(2)]),
+                 Value::STRUCT(vec![Value::INT32(1), blob(b"x")]),
+                 Value::MAP(vec![(Value::UINT64(1), Value::NULL)])],
+            vec![Value::UINT32(2), Value::NULL, Value::BOOLEAN(false), Value::NULL,
+                 Value::NULL,
+                 Value::STRUCT(vec![Value::NULL, blob(b"yz")]),
+                 Value::MAP(vec![])],
+            vec![Value::UINT32(3), Value::FLOAT64(-2.0), Value::BOOLEAN(true), text("bc"),
+                 Value::LIST(vec![Value::INT64(3)]),
+                 Value::STRUCT(vec![Value::INT32(3), blob(b"abcd")]),
+                 Value::MAP(vec![(Value::UINT64(2), Value::INT32(5)), (Value::UINT64(3), Value::INT32(6))])],
+        ];
+
+        for (row, values) in rows.iter().enumerate() {
+            for (pos, value) in values.iter().enumerate() {
+                set_column_value(&mut block, pos, row, value).unwrap();
+            }
+        }
+
+        let mut buf = Vec::new();
+        let written = block.serialize(&mut buf).unwrap();
+        assert_eq!(written, buf.len());
+        assert_eq!(buf.len() % 8, 0);
+
+        let copy = Block::deserialize(&mut &buf[..], &allocator::GLOBAL).unwrap();
+        assert_eq!(copy.schema().get(5).unwrap().children[1].name, "y");
+        assert_eq!(values(&copy), rows);
+
+        // Sub-range of a view; nested entries are compacted
+        let view = window_alias(&block, Some(RowRange { offset: 1, rows: 2 })).unwrap();
+        let mut buf = Vec::new();
+        serialize_view(&view, &mut buf).unwrap();
+
+        let copy = Block::deserialize(&mut &buf[..], &allocator::GLOBAL).unwrap();
+        assert_eq!(values(&copy), rows[1 ..].to_vec());
+
+        // Corrupt & truncated input
+        assert!(Block::deserialize(&mut &buf[.. buf.len() - 8], &allocator::GLOBAL).is_err());
+        buf[4] = 9;
+        assert!(Block::deserialize(&mut &buf[..], &allocator::GLOBAL).is_err());
+    }
+
+    /// Replace the first occurrence of `from` in `buf`
+    fn patch(buf: &mut [u8], from: &[u8], to: &[u8]) {
+        let pos = buf.windows(from.len()).position(|w| w == from).unwrap();
+        buf[pos .. pos + to.len()].copy_from_slice(to);
+    }
+
+    fn serialized(attr: Attribute, rows: &[Value]) -> Vec<u8> {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(vec![attr]).unwrap());
+        block.add_rows(rows.len()).unwrap();
+        for (row, value) in rows.iter().enumerate() {
+            set_column_value(&mut block, 0, row, value).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        block.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn corrupt_values() {
+        let deserialize = |buf: &[u8]| {
+            let block = Block::deserialize(&mut &buf[..], &allocator::GLOBAL).map(|b| values(&b));
+
+            // Mapped in place from 8 byte aligned memory, with the same checks
+            let mut aligned = vec![0u64; (buf.len() + 7) / 8];
+            let data = unsafe { slice::from_raw_parts_mut(aligned.as_mut_ptr() as *mut u8, buf.len()) };
+            data.copy_from_slice(buf);
+            let mapped = map_block(data).map(|(b, _)| values(&b));
+            assert_eq!(block.is_ok(), mapped.is_ok());
+            block
+        };
+
+        // TEXT & JSON must be UTF-8; BLOBs are not checked
+        for &dtype in &[Type::TEXT, Type::JSON, Type::BLOB] {
+            let value = match dtype {
+                Type::BLOB  => Value::BLOB(Cow::Borrowed(b"ok\xc3\xa9")),
+                Type::JSON  => Value::JSON(Cow::Borrowed("ok\u{e9}")),
+                _           => Value::TEXT(Cow::Borrowed("ok\u{e9}")),
+            };
+            let mut buf = serialized(Attribute::new("t", false, dtype), &[value]);
+            assert!(deserialize(&buf).is_ok());
+
+            patch(&mut buf, b"ok\xc3\xa9", b"ok\xc3\x28");
+            assert_eq!(deserialize(&buf).is_ok(), dtype == Type::BLOB);
+        }
+
+        let mut buf = serialized(Attribute::new("b", false, Type::BOOLEAN),
+            &[Value::BOOLEAN(true), Value::BOOLEAN(false)]);
+        patch(&mut buf, &[1, 0], &[2, 0]);
+        assert!(deserialize(&buf).is_err());
+
+        // Entry lengths that overflow
+        let list = |len| Value::LIST((0 .. len).map(|v| Value::INT64(100 + v)).collect());
+        let mut buf = serialized(Attribute::list("l", false, Attribute::new("e", false, Type::INT64)),
+            &[list(3), list(5)]);
+        let (mut from, mut to) = (Vec::new(), Vec::new());
+        put_u64(&mut from, 3);
+        put_u64(&mut from, 5);
+        put_u64(&mut to, 3);
+        put_u64(&mut to, u64::max_value());
+        patch(&mut buf, &from, &to);
+        assert!(deserialize(&buf).is_err());
+    }
+
+    #[test]
+    fn compressed_columns() {
+        let attrs = vec![
+            Attribute::new("id", false, Type::UINT64),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::new("score", false, Type::INT32),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(1000).unwrap();
+        for row in 0 .. 1000 {
+            set_column_value(&mut block, 0, row, &Value::UINT64(row as u64 % 10)).unwrap();
+            set_column_value(&mut block, 2, row, &Value::INT32(7)).unwrap();
+        }
+        set_column_value(&mut block, 1, 3, &Value::TEXT(Cow::Borrowed("name"))).unwrap();
+
+        let mut plain = Vec::new();
+        block.serialize(&mut plain).unwrap();
+
+        let (lz4, snappy) = (codec::Lz4, codec::Snappy);
+        let mut packed = Vec::new();
+        block.serialize_with(&mut packed, &[Some(&lz4), Some(&snappy), None]).unwrap();
+        assert!(packed.len() < plain.len() / 4);
+
+        let copy = Block::deserialize(&mut &packed[..], &allocator::GLOBAL).unwrap();
+        assert_eq!(values(&copy), values(&block));
+
+        // Compressed blocks can't be mapped; missing codecs are an error
+        assert!(map_block(&packed).is_err());
+        assert!(Block::deserialize_with(&mut &packed[..], &allocator::GLOBAL, &Codecs::empty()).is_err());
+        assert!(block.serialize_with(&mut Vec::new(), &[Some(&lz4)]).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+// vim : set ts=4 sw=4 et :
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use ::block::{Block, RefView, View};
+use ::error::DBError;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+/// Reference counted (atomically, so it can be shared between threads), copy-on-write Block.
+///
+/// Clones share the column data. Views created from a `SharedBlock` (`SharedView`) keep the data
+/// alive instead of borrowing it, so operators can retain chunks without deep copying them.
+#[derive(Clone)]
+pub struct SharedBlock<'alloc> {
+    inner: Arc<Block<'alloc>>,
+}
+
+/// Sub-range of rows & columns of a `SharedBlock`. Shares ownership of the block's data.
+#[derive(Clone)]
+pub struct SharedView<'alloc> {
+    block: SharedBlock<'alloc>,
+    schema: Schema,
+    columns: Vec<usize>,
+    range: RowRange,
+}
+
+impl<'alloc> SharedBlock<'alloc> {
+    pub fn new(block: Block<'alloc>) -> SharedBlock<'alloc> {
+        SharedBlock { inner: Arc::new(block) }
+    }
+
+    /// Number of handles (blocks & views) sharing the data
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// Mutable access to the block. Copies the data (using the block's allocator) if it's shared.
+    pub fn make_mut(&mut self) -> Result<&mut Block<'alloc>, DBError> {
+        if Arc::get_mut(&mut self.inner).is_none() {
+            let copy = self.inner.deep_copy(self.inner.allocator)?;
+            self.inner = Arc::new(copy);
+        }
+
+        Ok(Arc::get_mut(&mut self.inner).unwrap())
+    }
+
+    /// Take back ownership of the block, if it's not shared
+    pub fn try_unwrap(self) -> Result<Block<'alloc>, SharedBlock<'alloc>> {
+        Arc::try_unwrap(self.inner)
+            .map_err(|inner| SharedBlock { inner: inner })
+    }
+
+    /// Shared view of a subset of columns and rows. All columns / rows when not specified.
+    pub fn share(&self, columns: Option<&[usize]>, range: Option<RowRange>)
+        -> Result<SharedView<'alloc>, DBError>
+    {
+        let columns: Vec<usize> = columns.map_or((0 .. self.inner.schema.count()).collect(), |c| c.to_vec());
+        let range = range.unwrap_or(RowRange { offset: 0, rows: self.inner.rows() });
+
+        // Validates the columns & range
+        let schema = self.inner.slice_view(&columns, Some(range))?.schema().clone();
+
+        Ok(SharedView { block: self.clone(), schema: schema, columns: columns, range: range })
+    }
+}
+
+impl<'alloc> Deref for SharedBlock<'alloc> {
+    type Target = Block<'alloc>;
+
+    fn deref(&self) -> &Block<'alloc> {
+        &self.inner
+    }
+}
+
+impl<'alloc> From<Block<'alloc>> for SharedBlock<'alloc> {
+    fn from(block: Block<'alloc>) -> SharedBlock<'alloc> {
+        SharedBlock::new(block)
+    }
+}
+
+impl<'alloc> SharedView<'alloc> {
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn rows(&self) -> RowOffset {
+        self.range.rows
+    }
+
+    /// Zero-copy view of the data, borrowing from this shared view
+    pub fn view<'a>(&'a self) -> Result<RefView<'a>, DBError> {
+        self.block.slice_view(&self.columns, Some(self.range))
+    }
+
+    /// Sub-range of this view; `range` is relative to this view
+    pub fn slice(&self, range: RowRange) -> Result<SharedView<'alloc>, DBError> {
+        if range.offset + range.rows > self.range.rows {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let range = RowRange { offset: self.range.offset + range.offset, rows: range.rows };
+        Ok(SharedView { range: range, ..self.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use ::allocator;
+    use ::block::column_value;
+    use ::schema::Attribute;
+    use ::types::{Type, Value};
+    use ::util::copy_value::ValueSetter;
+
+    #[test]
+    fn shared_views() {
+        let attrs = vec![
+            Attribute::new("a", false, Type::UINT32),
+            Attribute::new("b", false, Type::INT64),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(4).unwrap();
+        for row in 0 .. 4 {
+            (row as u32).set_row(&mut block[0], row).unwrap();
+            (row as i64 * -1).set_row(&mut block[1], row).unwrap();
+        }
+
+        let mut shared = SharedBlock::new(block);
+        let retained = shared.share(Some(&[1]), Some(RowRange { offset: 1, rows: 3 })).unwrap()
+            .slice(RowRange { offset: 1, rows: 2 }).unwrap();
+        assert_eq!(shared.ref_count(), 2);
+
+        // Copy on write; the retained view keeps the original data
+        7u32.set_row(&mut shared.make_mut().unwrap()[0], 0).unwrap();
+        assert_eq!(shared.ref_count(), 1);
+        assert_eq!(column_value(shared.column(0).unwrap(), 0).unwrap(), Value::UINT32(7));
+
+        let view = retained.view().unwrap();
+        assert_eq!(view.rows(), 2);
+        assert_eq!(view.schema().get(0).unwrap().name, "b");
+        assert_eq!(column_value(view.column(0).unwrap(), 0).unwrap(), Value::INT64(-2));
+
+        // Views can be handed to other threads
+        let remote = retained.clone();
+        assert_eq!(thread::spawn(move || remote.rows()).join().unwrap(), 2);
+
+        assert!(shared.try_unwrap().is_ok());
+    }
+}
@@ -0,0 +1,284 @@
+// vim : set ts=4 sw=4 et :
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ::block::{RefColumn, RowRange, alias_column, column_nulls, column_value};
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::row::RowOffset;
+use ::types::Value;
+
+/// log2 of the number of HyperLogLog registers used for distinct estimates
+const HLL_BITS: u32 = 8;
+const HLL_REGISTERS: usize = 1 << HLL_BITS;
+
+/// Rows per zone of the zone maps computed by `Block::update_stats`
+pub const DEFAULT_ZONE_ROWS: RowOffset = 1024;
+
+/// Column data statistics. Used for pruning chunks of rows (min/max) and by planners.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnStats {
+    /// Number of rows the stats were computed on
+    pub rows: RowOffset,
+    pub null_count: usize,
+    /// Smallest non-NULL (and non-NaN) value. None if there's no such value or for nested types.
+    pub min: Option<Value<'static>>,
+    /// Largest non-NULL (and non-NaN) value. None if there's no such value or for nested types.
+    pub max: Option<Value<'static>>,
+    /// Approximate number of distinct non-NULL values (HyperLogLog)
+    pub distinct_estimate: u64,
+}
+
+impl ColumnStats {
+    /// Compute statistics over the first `rows` rows of the column.
+    pub fn compute<'c>(col: &'c RefColumn<'c>, rows: RowOffset) -> Result<ColumnStats, DBError> {
+        if rows > col.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let nulls = column_nulls(col);
+        let null_count = if col.attribute().nullable { nulls.slice(0, rows).count_ones() } else { 0 };
+
+        let mut out = ColumnStats { rows: rows, null_count: null_count, min: None, max: None, distinct_estimate: 0 };
+        if col.attribute().dtype.is_nested() {
+            return Ok(out)
+        }
+
+        let mut registers = [0u8; HLL_REGISTERS];
+
+        for row in 0 .. rows {
+            let value = column_value(col, row)?;
+            if value.is_null() {
+                continue
+            }
+
+            hll_add(&mut registers, hash_value(&value));
+
+            // NaN is unordered; min / max are of the other values
+            if is_nan(&value) {
+                continue
+            }
+
+            if out.min.as_ref().map_or(true, |m| value < *m) {
+                out.min = Some(value.clone().into_owned());
+            }
+
+            if out.max.as_ref().map_or(true, |m| value > *m) {
+                out.max = Some(value.into_owned());
+            }
+        }
+
+        out.distinct_estimate = hll_estimate(&registers);
+        Ok(out)
+    }
+
+    /// All rows are NULL
+    pub fn all_null(&self) -> bool {
+        self.null_count == self.rows
+    }
+
+    /// Can any row satisfy `column op value`? Only returns false when the min/max prove that no
+    /// row matches. Comparisons with NULL never match.
+    pub fn might_match(&self, op: CompareOp, value: &Value) -> bool {
+        if value.is_null() || self.all_null() {
+            return false
+        }
+
+        let (min, max) = match (&self.min, &self.max) {
+            (&Some(ref min), &Some(ref max))    => (min, max),
+            _                                   => return true,
+        };
+
+        // Can't reason about values of a different type
+        if min.dtype() != value.dtype() {
+            return true
+        }
+
+        match op {
+            CompareOp::EQ   => min <= value && value <= max,
+            CompareOp::NE   => !(min == value && max == value),
+            CompareOp::LT   => min < value,
+            CompareOp::LE   => min <= value,
+            CompareOp::GT   => max > value,
+            CompareOp::GE   => max >= value,
+        }
+    }
+}
+
+/// Statistics for consecutive fixed size zones of a column; lets scans skip chunks of rows
+/// without looking at the data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneMap {
+    pub zone_rows: RowOffset,
+    pub zones: Vec<ColumnStats>,
+}
+
+impl ZoneMap {
+    /// Compute statistics for every `zone_rows` rows of the first `rows` rows of the column.
+    pub fn compute<'c>(col: &'c RefColumn<'c>, rows: RowOffset, zone_rows: RowOffset)
+        -> Result<ZoneMap, DBError>
+    {
+        if rows > col.capacity() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let zone_rows = zone_rows.max(1);
+        let mut zones = Vec::with_capacity((rows + zone_rows - 1) / zone_rows);
+
+        let mut offset = 0;
+        while offset < rows {
+            let range = RowRange { offset: offset, rows: zone_rows.min(rows - offset) };
+            let zone = alias_column(col, Some(range))?;
+            zones.push(ColumnStats::compute(&zone, range.rows)?);
+            offset += range.rows;
+        }
+
+        Ok(ZoneMap { zone_rows: zone_rows, zones: zones })
+    }
+
+    /// Statistics of the zones overlapping the rows. None if the rows aren't all covered.
+    pub fn zones(&self, range: RowRange) -> Option<&[ColumnStats]> {
+        if range.rows == 0 || range.offset + range.rows > self.zones.iter().map(|z| z.rows).sum() {
+            return None
+        }
+
+        let first = range.offset / self.zone_rows;
+        let last = (range.offset + range.rows - 1) / self.zone_rows;
+        Some(&self.zones[first ..= last])
+    }
+}
+
+fn is_nan(value: &Value) -> bool {
+    match *value {
+        Value::FLOAT32(v)   => v.is_nan(),
+        Value::FLOAT64(v)   => v.is_nan(),
+        _                   => false,
+    }
+}
+
+fn hash_value(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    match *value {
+        Value::UINT32(v)        => v.hash(&mut hasher),
+        Value::UINT64(v)        => v.hash(&mut hasher),
+        Value::INT32(v)         => v.hash(&mut hasher),
+        Value::INT64(v)         => v.hash(&mut hasher),
+        Value::FLOAT32(v)       => v.to_bits().hash(&mut hasher),
+        Value::FLOAT64(v)       => v.to_bits().hash(&mut hasher),
+        Value::BOOLEAN(v)       => v.hash(&mut hasher),
+        Value::TEXT(ref v)      => v.hash(&mut hasher),
+        Value::JSON(ref v)      => v.hash(&mut hasher),
+        Value::BLOB(ref v)      => v.hash(&mut hasher),
+        Value::TIMESTAMP(v)     => v.hash(&mut hasher),
+        Value::TIMESTAMP_TZ(v)  => v.hash(&mut hasher),
+        _                       => (),
+    }
+
+    hasher.finish()
+}
+
+fn hll_add(registers: &mut [u8; HLL_REGISTERS], hash: u64) {
+    let idx = (hash >> (64 - HLL_BITS)) as usize;
+    let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() + 1;
+
+    if rank as u8 > registers[idx] {
+        registers[idx] = rank as u8;
+    }
+}
+
+fn hll_estimate(registers: &[u8; HLL_REGISTERS]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+    let sum: f64 = registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+    let zeros = registers.iter().filter(|r| **r == 0).count();
+
+    let raw = alpha * m * m / sum;
+
+    // Small range correction (linear counting)
+    let estimate = if raw <= 2.5 * m && zeros > 0 {
+        m * (m / zeros as f64).ln()
+    } else {
+        raw
+    };
+
+    estimate.round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::schema::Schema;
+    use ::types::Type;
+    use ::util::copy_value::ValueSetter;
+
+    #[test]
+    fn column_stats() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("v", true, Type::INT64));
+        block.add_rows(1000).unwrap();
+
+        for row in 0 .. 1000 {
+            let v = if row % 10 == 0 { None } else { Some((row % 100) as i64 - 50) };
+            Value::from(v).set_row(&mut block[0], row).unwrap();
+        }
+
+        let stats = block.column_stats(0).unwrap();
+        assert_eq!(stats.null_count, 100);
+        assert_eq!(stats.min, Some(Value::INT64(-49)));
+        assert_eq!(stats.max, Some(Value::INT64(49)));
+        assert!(stats.might_match(CompareOp::EQ, &Value::INT64(0)));
+        assert!(!stats.might_match(CompareOp::GT, &Value::INT64(49)));
+        assert!(!stats.might_match(CompareOp::LT, &Value::INT64(-49)));
+        assert!(stats.might_match(CompareOp::LE, &Value::INT64(-49)));
+
+        assert!(stats.distinct_estimate >= 81 && stats.distinct_estimate <= 99,
+                "distinct estimate {}", stats.distinct_estimate);
+
+        // Stored stats are dropped when the block is modified
+        block.update_stats().unwrap();
+        assert!(block.stats(0).is_some());
+        assert_eq!(block.zone_map(0).unwrap().zones.len(), 1);
+        Value::INT64(500).set_row(&mut block[0], 1).unwrap();
+        assert!(block.stats(0).is_none());
+        assert!(block.zone_map(0).is_none());
+        assert_eq!(block.column_stats(0).unwrap().max, Some(Value::INT64(500)));
+    }
+
+    #[test]
+    fn nan() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("v", false, Type::FLOAT64));
+        block.add_rows(4).unwrap();
+        for (row, v) in [::std::f64::NAN, 2.5, ::std::f64::NAN, -1.0].iter().enumerate() {
+            v.set_row(&mut block[0], row).unwrap();
+        }
+
+        let stats = block.column_stats(0).unwrap();
+        assert_eq!((stats.min, stats.max), (Some(Value::FLOAT64(-1.0)), Some(Value::FLOAT64(2.5))));
+
+        // Only NaNs: no min / max, so nothing is ruled out
+        let stats = ColumnStats::compute(&block[0], 1).unwrap();
+        assert!(stats.min.is_none() && stats.max.is_none());
+        assert!(stats.might_match(CompareOp::GT, &Value::FLOAT64(0.0)));
+    }
+
+    #[test]
+    fn zone_map() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("v", false, Type::INT64));
+        block.add_rows(25).unwrap();
+        for row in 0 .. 25 {
+            (row as i64).set_row(&mut block[0], row).unwrap();
+        }
+
+        let zones = ZoneMap::compute(&block[0], 25, 10).unwrap();
+        assert_eq!(zones.zones.iter().map(|z| (z.rows, z.min.clone())).collect::<Vec<_>>(),
+                   vec![(10, Some(Value::INT64(0))), (10, Some(Value::INT64(10))), (5, Some(Value::INT64(20)))]);
+
+        assert_eq!(zones.zones(RowRange { offset: 9, rows: 2 }).unwrap().len(), 2);
+        assert_eq!(zones.zones(RowRange { offset: 20, rows: 5 }).unwrap()[0].max, Some(Value::INT64(24)));
+        assert!(zones.zones(RowRange { offset: 20, rows: 6 }).is_none());
+    }
+}
@@ -0,0 +1,119 @@
+// vim: set ts=4 sw=4 et :
+
+//! In-memory catalog of named tables.
+//!
+//! Tables are immutable, reference counted blocks shared by all the queries (and threads) using
+//! the catalog. Dropping a table from the catalog doesn't affect queries already scanning it;
+//! the block is freed once the last one is done.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use ::block::{Block, SharedBlock};
+use ::error::DBError;
+
+#[derive(Default)]
+pub struct Catalog<'a> {
+    tables: RwLock<HashMap<String, SharedBlock<'a>>>,
+}
+
+impl<'a> Catalog<'a> {
+    pub fn new() -> Catalog<'a> {
+        Catalog::default()
+    }
+
+    /// Fails if there's already a table with the name. The table's column statistics are computed
+    /// (`Block::update_stats`).
+    pub fn register<S: Into<String>>(&self, name: S, mut block: Block<'a>) -> Result<SharedBlock<'a>, DBError> {
+        let name = name.into();
+        block.update_stats()?;
+        let mut tables = self.tables.write().unwrap();
+
+        if tables.contains_key(&name) {
+            return Err(DBError::TableDuplicate(name))
+        }
+
+        let block = SharedBlock::new(block);
+        tables.insert(name, block.clone());
+        Ok(block)
+    }
+
+    /// Register the table, returning the table it replaced
+    pub fn replace<S: Into<String>>(&self, name: S, mut block: Block<'a>) -> Result<Option<SharedBlock<'a>>, DBError> {
+        block.update_stats()?;
+        Ok(self.tables.write().unwrap().insert(name.into(), SharedBlock::new(block)))
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<SharedBlock<'a>> {
+        self.tables.read().unwrap().get(name).cloned()
+    }
+
+    pub fn lookup_ok(&self, name: &str) -> Result<SharedBlock<'a>, DBError> {
+        self.lookup(name)
+            .ok_or_else(|| DBError::TableMissing(name.to_string()))
+    }
+
+    /// Remove the table from the catalog. It stays alive while it's still in use.
+    pub fn drop_table(&self, name: &str) -> Result<SharedBlock<'a>, DBError> {
+        self.tables.write().unwrap().remove(name)
+            .ok_or_else(|| DBError::TableMissing(name.to_string()))
+    }
+
+    /// Table names, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use ::allocator;
+    use ::block::View;
+    use ::schema::Schema;
+
+    #[test]
+    fn register_lookup_drop() {
+        let catalog = Arc::new(Catalog::new());
+        let schema = Schema::parse_ddl("id UINT32").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(3).unwrap();
+
+        catalog.register("t", block).unwrap();
+        assert!(catalog.register("t", Block::new(&allocator::GLOBAL, &schema)).is_err());
+        catalog.register("empty", Block::new(&allocator::GLOBAL, &schema)).unwrap();
+        assert_eq!(catalog.names(), vec!["empty", "t"]);
+
+        let readers: Vec<_> = (0 .. 4)
+            .map(|_| {
+                let catalog = catalog.clone();
+                thread::spawn(move || catalog.lookup_ok("t").unwrap().rows())
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 3);
+        }
+
+        let table = catalog.lookup("t").unwrap();
+        catalog.drop_table("t").unwrap();
+        assert!(catalog.lookup("t").is_none());
+        assert!(catalog.drop_table("t").is_err());
+        assert_eq!(table.rows(), 3);
+
+        assert!(catalog.replace("empty", Block::new(&allocator::GLOBAL, &schema)).unwrap().is_some());
+        assert!(catalog.lookup("empty").unwrap().stats(0).is_some());
+        assert_eq!(catalog.len(), 1);
+    }
+}
@@ -1,9 +1,12 @@
 // vim: set ts=4 sw=4 et :
 
 use std::fmt;
-use std::heap::AllocErr;
 use std::io::{Error as IOError};
 
+use ::allocator::AllocErr;
+use ::row::RowOffset;
+use ::types::{Type, Value};
+
 
 /// Query execution errors
 pub enum DBError {
@@ -20,10 +23,56 @@ pub enum DBError {
     AttributeType(String),
     /// Duplicate attribute in result schema
     AttributeDuplicate(String),
-    ///
+    /// Attribute reference matching more than one attribute
+    AttributeAmbiguous(String),
+    /// Referencing a table missing from the catalog
+    TableMissing(String),
+    /// Registering a table under a name already in the catalog
+    TableDuplicate(String),
+    /// Expression input of a type the expression can't handle
     ExpressionInputType(String),
+    /// Wrong number of expression (or key) inputs
     ExpressionInputCount(String),
-    ExpressionNotCost,
+    /// Invalid argument of an accumulator or check, eg. a missing column
+    InvalidArgument(String),
+    /// Constant value requested from an expression that isn't constant
+    ExpressionNotConstant,
+    /// Failure compiling or running JIT generated expression code
+    JITEngine(String),
+    /// Malformed JSON document or path
+    JSON(String),
+    /// Malformed CSV (delimited text) input
+    CSV(String),
+    /// Malformed or unsupported Arrow IPC data
+    Arrow(String),
+    /// Malformed or unsupported Parquet file
+    Parquet(String),
+    /// Malformed or unsupported native serialized data
+    Serialization(String),
+    /// Malformed schema definition (JSON or DDL)
+    Schema(String),
+    /// Failure running a (parallel) operation tree, eg. a worker thread exited
+    Execution(String),
+    /// Query cancelled through its `CancelToken`
+    Cancelled,
+    /// Valid request for a feature (type, option, operator) that isn't implemented
+    Unsupported(String),
+    /// Inputs that are expected to have the same schema don't
+    SchemaMismatch(String),
+    /// Invalid logical plan, or one that can't be lowered to operations
+    Plan(String),
+    /// Malformed or unsupported SQL statement
+    SQL(String),
+    /// Arithmetic result out of range of its type
+    Overflow(String),
+    /// Value that can't be converted to the type of its column
+    Cast { column: String, row: RowOffset, from: Type, to: Type },
+    /// Malformed input of a reader (`format` eg. CSV); 1-based line and column, 0 when unknown
+    Parse { format: &'static str, line: usize, column: usize, msg: String },
+    /// Row failing a data quality check (`AssertOp`); `row` counts from the start of the input
+    Assertion { check: String, row: RowOffset, values: Vec<Value<'static>> },
+    /// Error binding or running an operator (named by its `describe()`)
+    Operator { name: String, cause: Box<DBError> },
     ///
     RowOutOfBounds,
     /// Unknown memory allocation error
@@ -40,6 +89,29 @@ impl DBError {
     pub fn make_column_unknown_pos(pos: usize) -> DBError {
         DBError::AttributeMissing(format!("(pos: {})", pos))
     }
+
+    /// Attach the operator the error happened in. Errors that already have one keep it (the
+    /// innermost operator is the one that failed), cancellation isn't attributed to operators.
+    pub fn in_operator<S: Into<String>>(self, name: S) -> DBError {
+        match self {
+            e @ DBError::Operator { .. } | e @ DBError::Cancelled => e,
+            e => DBError::Operator { name: name.into(), cause: Box::new(e) },
+        }
+    }
+
+    /// The error without the operator context
+    pub fn root_cause(&self) -> &DBError {
+        match *self {
+            DBError::Operator { ref cause, .. } => cause.root_cause(),
+            ref e                               => e,
+        }
+    }
+}
+
+impl From<IOError> for DBError {
+    fn from(e: IOError) -> DBError {
+        DBError::IO(e)
+    }
 }
 
 impl fmt::Display for DBError {
@@ -59,12 +131,60 @@ impl fmt::Display for DBError {
                 write!(f, "Attribute Type Mismatch {}", attr),
             DBError::AttributeDuplicate(ref attr) =>
                 write!(f, "Duplicate Attribute name {} in output schema", attr),
+            DBError::AttributeAmbiguous(ref attr) =>
+                write!(f, "Ambiguous Attribute reference {}", attr),
+            DBError::TableMissing(ref name) =>
+                write!(f, "Unknown Table {}", name),
+            DBError::TableDuplicate(ref name) =>
+                write!(f, "Duplicate Table name {} in catalog", name),
             DBError::ExpressionInputType(ref str) =>
                 write!(f, "Invalid expression input type: {}", str),
             DBError::ExpressionInputCount(ref str) =>
                 write!(f, "Invalid expression input count: {}", str),
-            DBError::ExpressionNotCost =>
-                write!(f, "Expression expected to be const"),
+            DBError::InvalidArgument(ref str) =>
+                write!(f, "Invalid argument: {}", str),
+            DBError::ExpressionNotConstant =>
+                write!(f, "Expression expected to be constant"),
+            DBError::JITEngine(ref str) =>
+                write!(f, "JIT engine error: {}", str),
+            DBError::JSON(ref str) =>
+                write!(f, "Invalid JSON: {}", str),
+            DBError::CSV(ref str) =>
+                write!(f, "Invalid CSV: {}", str),
+            DBError::Arrow(ref str) =>
+                write!(f, "Invalid Arrow data: {}", str),
+            DBError::Parquet(ref str) =>
+                write!(f, "Invalid Parquet file: {}", str),
+            DBError::Serialization(ref str) =>
+                write!(f, "Invalid serialized data: {}", str),
+            DBError::Schema(ref str) =>
+                write!(f, "Invalid schema definition: {}", str),
+            DBError::Execution(ref str) =>
+                write!(f, "Execution error: {}", str),
+            DBError::Cancelled =>
+                write!(f, "Query cancelled"),
+            DBError::Unsupported(ref str) =>
+                write!(f, "Unsupported: {}", str),
+            DBError::SchemaMismatch(ref str) =>
+                write!(f, "Schema mismatch: {}", str),
+            DBError::Plan(ref str) =>
+                write!(f, "Invalid plan: {}", str),
+            DBError::SQL(ref str) =>
+                write!(f, "Invalid SQL: {}", str),
+            DBError::Overflow(ref str) =>
+                write!(f, "Arithmetic overflow: {}", str),
+            DBError::Cast { ref column, row, from, to } =>
+                write!(f, "Can't convert {} to {}: column {} row {}", from.name(), to.name(), column, row),
+            DBError::Parse { format, line, column: 0, ref msg } =>
+                write!(f, "Invalid {}: {} (line {})", format, msg, line),
+            DBError::Parse { format, line, column, ref msg } =>
+                write!(f, "Invalid {}: {} (line {}, column {})", format, msg, line, column),
+            DBError::Assertion { ref check, row, ref values } => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "Assertion {} failed: row {} ({})", check, row, values.join(", "))
+            }
+            DBError::Operator { ref name, ref cause } =>
+                write!(f, "{} (in {})", cause, name),
             DBError::RowOutOfBounds =>
                 write!(f, "Row out of bounds"),
             DBError::Memory(ref e) =>
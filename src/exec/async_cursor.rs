@@ -0,0 +1,302 @@
+// vim: set ts=4 sw=4 et :
+
+//! Non-blocking (poll based) cursors.
+//!
+//! An `AsyncCursor` never blocks the calling thread. When no data is available yet `poll_ready`
+//! returns `PENDING` and the cursor calls the given `Notify` once it might be, so IO bound sources
+//! can be driven from an event loop or async executor. Adapters convert to and from `Cursor`.
+
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+
+use ::block::{Block, View, window_alias};
+use ::error::DBError;
+use ::exec::channel::{Message, Waiting};
+use ::operation::{Cursor, CursorChunk};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+/// Result of polling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Async<T> {
+    READY(T),
+    PENDING,
+}
+
+/// Wakes up whoever is waiting on a `PENDING` cursor (eg. an executor task)
+pub trait Notify: Send + Sync {
+    fn notify(&self);
+}
+
+/// Notify unparking a thread
+pub struct ThreadNotify(pub thread::Thread);
+
+impl Notify for ThreadNotify {
+    fn notify(&self) {
+        self.0.unpark()
+    }
+}
+
+pub trait AsyncCursor<'a> {
+    /// Output schema, None until the source is bound (the first `READY`)
+    fn schema(&self) -> Option<&Schema>;
+
+    /// `READY` once `take_next` won't block: the next chunk or the end of the stream is there.
+    /// Otherwise `PENDING`, `notify` is called when the cursor might be ready.
+    fn poll_ready(&mut self, notify: &Arc<Notify>) -> Result<Async<()>, DBError>;
+
+    /// Next chunk of up to `rows` rows. Only valid after `poll_ready` returned `READY`, errors
+    /// otherwise.
+    fn take_next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError>;
+
+    fn poll_next<'c>(&'c mut self, rows: RowOffset, notify: &Arc<Notify>) -> Result<Async<CursorChunk<'c>>, DBError> {
+        match self.poll_ready(notify)? {
+            Async::READY(())    => self.take_next(rows).map(Async::READY),
+            Async::PENDING      => Ok(Async::PENDING),
+        }
+    }
+}
+
+/// `Cursor` that never blocks (eg. over in-memory data) as an `AsyncCursor`
+pub struct ReadyCursor<C>(pub C);
+
+impl<'a, C: Cursor<'a>> AsyncCursor<'a> for ReadyCursor<C> {
+    fn schema(&self) -> Option<&Schema> {
+        Some(self.0.schema())
+    }
+
+    fn poll_ready(&mut self, _: &Arc<Notify>) -> Result<Async<()>, DBError> {
+        Ok(Async::READY(()))
+    }
+
+    fn take_next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.0.next(rows)
+    }
+}
+
+/// `AsyncCursor` as a `Cursor`, parking the calling thread while the cursor is pending
+pub struct BlockingCursor<A> {
+    inner: A,
+    /// Unparks the thread that last waited
+    notify: Arc<Notify>,
+    thread: thread::ThreadId,
+}
+
+impl<'a, A: AsyncCursor<'a>> BlockingCursor<A> {
+    /// Waits for the schema
+    pub fn new(inner: A) -> Result<BlockingCursor<A>, DBError> {
+        let current = thread::current();
+        let mut out = BlockingCursor { inner: inner, thread: current.id(), notify: Arc::new(ThreadNotify(current)) };
+        out.wait()?;
+        Ok(out)
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    fn wait(&mut self) -> Result<(), DBError> {
+        let current = thread::current();
+        if current.id() != self.thread {
+            self.thread = current.id();
+            self.notify = Arc::new(ThreadNotify(current));
+        }
+
+        // A stale unpark only costs an extra poll
+        while self.inner.poll_ready(&self.notify)? == Async::PENDING {
+            thread::park();
+        }
+        Ok(())
+    }
+}
+
+impl<'a, A: AsyncCursor<'a>> Cursor<'a> for BlockingCursor<A> {
+    fn schema(&self) -> &Schema {
+        self.inner.schema().expect("schema of a ready cursor")
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.wait()?;
+        self.inner.take_next(rows)
+    }
+}
+
+/// Non-blocking cursor over the blocks of a producer on another thread (`Driver::spawn_async`)
+pub struct AsyncChannelCursor {
+    rx: Receiver<Message>,
+    waiting: Waiting,
+    schema: Option<Schema>,
+    block: Option<Block<'static>>,
+    /// Next row of the current block
+    offset: RowOffset,
+    finished: bool,
+}
+
+impl AsyncChannelCursor {
+    pub fn new(rx: Receiver<Message>, waiting: Waiting) -> AsyncChannelCursor {
+        AsyncChannelCursor { rx: rx, waiting: waiting, schema: None, block: None, offset: 0, finished: false }
+    }
+
+    fn has_rows(&self) -> bool {
+        self.block.as_ref().map_or(false, |b| self.offset < b.rows())
+    }
+}
+
+impl<'a> AsyncCursor<'a> for AsyncChannelCursor {
+    fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    fn poll_ready(&mut self, notify: &Arc<Notify>) -> Result<Async<()>, DBError> {
+        while !(self.finished || (self.schema.is_some() && self.has_rows())) {
+            // Register before checking, so a message sent in between isn't missed
+            *self.waiting.lock().unwrap() = Some(notify.clone());
+
+            let msg = match self.rx.try_recv() {
+                Ok(msg)                             => msg,
+                Err(TryRecvError::Empty)            => return Ok(Async::PENDING),
+                Err(TryRecvError::Disconnected)     =>
+                    return Err(DBError::Execution("producer exited without finishing".to_string())),
+            };
+
+            self.waiting.lock().unwrap().take();
+            match msg {
+                Message::SCHEMA(s)  => self.schema = Some(s),
+                Message::BLOCK(b)   => { self.block = Some(b); self.offset = 0; }
+                Message::END        => self.finished = true,
+                Message::ERROR(e)   => return Err(e),
+            }
+        }
+
+        Ok(Async::READY(()))
+    }
+
+    fn take_next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        if !self.has_rows() {
+            return match self.finished {
+                true    => Ok(CursorChunk::End),
+                false   => Err(DBError::Execution("cursor is not ready".to_string())),
+            }
+        }
+
+        let block = self.block.as_ref().unwrap();
+        let range = RowRange { offset: self.offset, rows: min(rows, block.rows() - self.offset) };
+        self.offset += range.rows;
+
+        Ok(CursorChunk::Next(window_alias(block, Some(range))?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use ::allocator;
+    use ::block::{SharedBlock, column_row_data};
+    use ::exec::{Driver, ExecContext};
+    use ::exec::channel::notify_channel;
+    use ::operation::{Operation, ParallelScanView};
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    struct Counter(AtomicUsize);
+
+    impl Notify for Counter {
+        fn notify(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn numbers(rows: usize) -> ParallelScanView<'static> {
+        let schema = Schema::parse_ddl("v UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(rows).unwrap();
+        for row in 0 .. rows {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+        ParallelScanView::new(SharedBlock::new(block), 1, rows).unwrap()
+    }
+
+    #[test]
+    fn async_channel() {
+        let driver = Driver::new(1, &allocator::GLOBAL).unwrap().with_fetch(40);
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate_rx);
+        let op = numbers(100).worker(0).unwrap();
+
+        let mut cursor = driver.spawn_async_fn(Box::new(move |ctx| {
+            gate.lock().unwrap().recv().unwrap();
+            op.bind(ctx)
+        }));
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let notify: Arc<Notify> = counter.clone();
+        assert_eq!(cursor.poll_ready(&notify).unwrap(), Async::PENDING);
+        assert!(cursor.schema().is_none());
+        assert!(cursor.take_next(10).is_err());
+
+        gate_tx.send(()).unwrap();
+        while counter.0.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+
+        let mut blocking = BlockingCursor::new(cursor).unwrap();
+        assert_eq!(blocking.schema().count(), 1);
+
+        let mut values = Vec::new();
+        while let CursorChunk::Next(view) = blocking.next(1000).unwrap() {
+            assert!(view.rows() <= 40);
+            values.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        assert_eq!(values, (0 .. 100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn producer_gone() {
+        // Panics once the consumer is waiting
+        let driver = Driver::new(1, &allocator::GLOBAL).unwrap();
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate_rx);
+        let mut cursor = driver.spawn_async_fn(Box::new(move |_| {
+            gate.lock().unwrap().recv().unwrap();
+            panic!("producer failed")
+        }));
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let notify: Arc<Notify> = counter.clone();
+        assert_eq!(cursor.poll_ready(&notify).unwrap(), Async::PENDING);
+
+        gate_tx.send(()).unwrap();
+        while counter.0.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        assert!(cursor.poll_ready(&notify).is_err());
+
+        // Exits after the schema, without rows or END, while the consumer is parked
+        let (tx, rx, waiting) = notify_channel(1);
+        let producer = thread::spawn(move || {
+            tx.send(Message::SCHEMA(Schema::parse_ddl("v UINT32 NOT NULL").unwrap()));
+            thread::sleep(Duration::from_millis(20));
+        });
+
+        assert!(BlockingCursor::new(AsyncChannelCursor::new(rx, waiting)).is_err());
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn ready_cursor() {
+        let notify: Arc<Notify> = Arc::new(Counter(AtomicUsize::new(0)));
+        let mut cursor = ReadyCursor(numbers(5).worker(0).unwrap().bind(&ExecContext::default()).unwrap());
+
+        match cursor.poll_next(3, &notify).unwrap() {
+            Async::READY(CursorChunk::Next(view))   => assert_eq!(view.rows(), 3),
+            _                                       => panic!("expected rows"),
+        }
+        assert_eq!(cursor.schema().unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,132 @@
+// vim: set ts=4 sw=4 et :
+
+//! Cooperative query cancellation.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::operation::{Cursor, CursorChunk, Operation, Pipelining, ScanPredicate};
+use ::plan::Aggregate;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::util::bloom::KeyFilter;
+use ::types::Value;
+
+/// Shared cancellation flag. Clones refer to the same flag; cancelling from any thread stops every
+/// cursor checking it, at the next chunk, with `DBError::Cancelled`.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// `DBError::Cancelled` once cancelled
+    pub fn check(&self) -> Result<(), DBError> {
+        match self.is_cancelled() {
+            true    => Err(DBError::Cancelled),
+            false   => Ok(()),
+        }
+    }
+}
+
+/// Operation checking the token before binding and before every chunk. For cancelling a subtree
+/// independently of the rest of the query (`ExecContext::cancel_token`).
+pub struct Cancellable<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub token: CancelToken,
+}
+
+impl<'a> Cancellable<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T, token: CancelToken) -> Cancellable<'a> {
+        Cancellable { src: Box::new(src), token: token }
+    }
+}
+
+impl<'a> Operation<'a> for Cancellable<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        self.token.check()?;
+        Ok(Box::new(CancellableCursor { input: ctx.bind(&*self.src)?, token: self.token.clone() }))
+    }
+
+    fn describe(&self) -> String {
+        "Cancellable".to_string()
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+
+    /// Passes through; the rows and columns are the input's
+    fn push_predicate(&mut self, predicate: &ScanPredicate) -> bool {
+        self.src.push_predicate(predicate)
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.src.pipelining()
+    }
+}
+
+/// Implementation of the `Cancellable` operation
+struct CancellableCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    token: CancelToken,
+}
+
+impl<'a> Cursor<'a> for CancellableCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.token.check()?;
+        self.input.next(rows)
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.input.rewind()
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        self.input.push_key_filter(filter)
+    }
+
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        if self.token.is_cancelled() {
+            return None
+        }
+
+        self.input.aggregates_from_metadata(aggregates)
+    }
+}
@@ -0,0 +1,182 @@
+// vim: set ts=4 sw=4 et :
+
+//! Cursors reading the output of operations running on other threads.
+
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+use ::block::{Block, View, window_alias};
+use ::error::DBError;
+use ::exec::async_cursor::Notify;
+use ::operation::{Cursor, CursorChunk};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+/// Producer to `ChannelCursor` messages
+pub enum Message {
+    /// Output schema, sent first (once bound)
+    SCHEMA(Schema),
+    BLOCK(Block<'static>),
+    END,
+    ERROR(DBError),
+}
+
+/// Consumer waiting for the next message
+pub type Waiting = Arc<Mutex<Option<Arc<Notify>>>>;
+
+/// Wakes up the consumer; again when dropped, so it sees a producer going away
+#[derive(Clone)]
+struct Waker(Waiting);
+
+impl Waker {
+    fn wake(&self) {
+        if let Some(notify) = self.0.lock().unwrap().take() {
+            notify.notify();
+        }
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        self.wake()
+    }
+}
+
+/// Producer side of a `ChannelCursor` (or `AsyncChannelCursor`). Dropping it (also when the
+/// producer panics) disconnects the channel and wakes up the consumer.
+#[derive(Clone)]
+pub struct ChannelSender {
+    tx: SyncSender<Message>,
+    /// Dropped after `tx`, so the woken up consumer finds the channel disconnected
+    waker: Option<Waker>,
+}
+
+impl ChannelSender {
+    /// Blocks while the channel is full. False if the consumer is gone.
+    pub fn send(&self, msg: Message) -> bool {
+        if self.tx.send(msg).is_err() {
+            return false
+        }
+
+        if let Some(ref waker) = self.waker {
+            waker.wake();
+        }
+
+        true
+    }
+}
+
+/// Channel buffering up to `buffer` messages
+pub fn channel(buffer: usize) -> (ChannelSender, Receiver<Message>) {
+    let (tx, rx) = mpsc::sync_channel(buffer);
+    (ChannelSender { tx: tx, waker: None }, rx)
+}
+
+/// Channel with a consumer that's notified about new messages
+pub fn notify_channel(buffer: usize) -> (ChannelSender, Receiver<Message>, Waiting) {
+    let (tx, rx) = mpsc::sync_channel(buffer);
+    let waiting: Waiting = Arc::new(Mutex::new(None));
+    (ChannelSender { tx: tx, waker: Some(Waker(waiting.clone())) }, rx, waiting)
+}
+
+/// Cursor over blocks sent by one or more producers (the union of their output, in arrival order).
+///
+/// Every producer first sends its schema, then blocks and finally `END` or `ERROR`. The schemas
+/// have to be the same. A producer going away without `END` is an error.
+pub struct ChannelCursor {
+    rx: Receiver<Message>,
+    schema: Schema,
+    /// Producers that haven't finished
+    producers: usize,
+    /// Blocks received while waiting for all the schemas
+    pending: VecDeque<Block<'static>>,
+    block: Option<Block<'static>>,
+    /// Next row of the current block
+    offset: RowOffset,
+}
+
+impl ChannelCursor {
+    /// Waits for all the `producers` to bind and send their schema
+    pub fn new(rx: Receiver<Message>, producers: usize) -> Result<ChannelCursor, DBError> {
+        let mut schema: Option<Schema> = None;
+        let mut pending = VecDeque::new();
+        let mut bound = 0;
+        let mut finished = 0;
+
+        while bound < producers {
+            match recv(&rx)? {
+                Message::SCHEMA(s)  => {
+                    if schema.as_ref().map_or(false, |prev| *prev != s) {
+                        return Err(DBError::SchemaMismatch(format!("producer schemas ({}) and ({})",
+                                                                   schema.unwrap(), s)))
+                    }
+                    schema = Some(s);
+                    bound += 1;
+                }
+                Message::BLOCK(b)   => pending.push_back(b),
+                Message::END        => finished += 1,
+                Message::ERROR(e)   => return Err(e),
+            }
+        }
+
+        let schema = schema
+            .ok_or_else(|| DBError::Execution("no producers".to_string()))?;
+
+        Ok(ChannelCursor {
+            rx: rx,
+            schema: schema,
+            producers: producers - finished,
+            pending: pending,
+            block: None,
+            offset: 0,
+        })
+    }
+
+    /// Next non empty block, None once all producers are done
+    fn next_block(&mut self) -> Result<Option<Block<'static>>, DBError> {
+        if let Some(block) = self.pending.pop_front() {
+            return Ok(Some(block))
+        }
+
+        while self.producers > 0 {
+            match recv(&self.rx)? {
+                Message::BLOCK(b)   => return Ok(Some(b)),
+                Message::END        => self.producers -= 1,
+                Message::ERROR(e)   => return Err(e),
+                Message::SCHEMA(_)  => return Err(DBError::Execution("unexpected producer schema".to_string())),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn recv(rx: &Receiver<Message>) -> Result<Message, DBError> {
+    rx.recv()
+        .map_err(|_| DBError::Execution("producer exited without finishing".to_string()))
+}
+
+impl<'a> Cursor<'a> for ChannelCursor {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        while self.block.as_ref().map_or(true, |b| self.offset >= b.rows()) {
+            self.block = self.next_block()?;
+            self.offset = 0;
+
+            if self.block.is_none() {
+                return Ok(CursorChunk::End)
+            }
+        }
+
+        let block = self.block.as_ref().unwrap();
+        let range = RowRange { offset: self.offset, rows: min(rows, block.rows() - self.offset) };
+        self.offset += range.rows;
+
+        Ok(CursorChunk::Next(window_alias(block, Some(range))?))
+    }
+}
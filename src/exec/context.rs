@@ -0,0 +1,401 @@
+// vim: set ts=4 sw=4 et :
+
+//! Execution-wide state handed to operations when they're bound.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use ::allocator::{self, Allocator};
+use ::error::DBError;
+use ::exec::{CancelToken, ThreadPool, DEFAULT_BUFFER};
+use ::exec::explain::{CursorMetrics, InstrumentedCursor};
+use ::operation::{Cursor, CursorChunk, Operation, Pipelining, DEFAULT_CURSOR_FETCH};
+use ::operation::scan_parallel::DEFAULT_MORSEL_ROWS;
+use ::plan::Aggregate;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::Value;
+use ::util::bloom::KeyFilter;
+use ::util::math::OverflowPolicy;
+
+/// Blocks in flight between the producers of a channel and its consumer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferPolicy {
+    /// Per producer, for streaming operations
+    pub streaming: usize,
+    /// Per producer, for pipeline breakers. They emit their output in one burst once the input is
+    /// consumed; buffering more lets them finish (and free their state) sooner.
+    pub blocking: usize,
+    /// Cap per channel, however many producers feed it
+    pub max_in_flight: usize,
+}
+
+impl Default for BufferPolicy {
+    fn default() -> BufferPolicy {
+        BufferPolicy {
+            streaming: DEFAULT_BUFFER,
+            blocking: DEFAULT_BUFFER * 4,
+            max_in_flight: 64,
+        }
+    }
+}
+
+impl BufferPolicy {
+    /// Same bound for every operation
+    pub fn fixed(blocks: usize) -> BufferPolicy {
+        BufferPolicy { streaming: blocks, blocking: blocks, max_in_flight: blocks }
+    }
+
+    /// Channel capacity for `producers` operations of the given kind. At least one block.
+    pub fn buffer(&self, pipelining: Pipelining, producers: usize) -> usize {
+        let base = match pipelining {
+            Pipelining::STREAMING   => self.streaming,
+            Pipelining::BLOCKING    => self.blocking,
+        };
+
+        base.saturating_mul(producers.max(1)).min(self.max_in_flight).max(1)
+    }
+}
+
+/// Adaptive fetch size: as many rows as fit `target_bytes` (eg. in L2 cache) at the schema's
+/// estimated row width, unless the cursor prefers a chunk size of its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchSizing {
+    pub target_bytes: usize,
+    pub min_rows: RowOffset,
+    pub max_rows: RowOffset,
+}
+
+/// Assumed average length of variable length (TEXT, BLOB, JSON) values
+const VARLEN_WIDTH: usize = 16;
+
+impl Default for BatchSizing {
+    fn default() -> BatchSizing {
+        BatchSizing { target_bytes: 256 * 1024, min_rows: 64, max_rows: 64 * 1024 }
+    }
+}
+
+impl BatchSizing {
+    /// Rows to fetch at a time; multiple of 64 (whole null bitmap words) unless clamped to a
+    /// smaller `max_rows`
+    pub fn rows(&self, schema: &Schema, preferred: Option<RowOffset>) -> RowOffset {
+        let rows = match preferred {
+            Some(rows)  => rows,
+            None        => (self.target_bytes / row_width(schema.iter())) & !63,
+        };
+
+        rows.max(self.min_rows).min(self.max_rows).max(1)
+    }
+}
+
+/// Estimated bytes per row: row data, nested children, null bitmap and variable length data
+fn row_width<'s, I: Iterator<Item=&'s Attribute>>(attrs: I) -> usize {
+    let width: usize = attrs
+        .map(|attr| {
+            let data = attr.dtype.size_of() + row_width(attr.children.iter());
+            let nulls = if attr.nullable { 1 } else { 0 };
+            let varlen = if attr.dtype.is_varlen() { VARLEN_WIDTH } else { 0 };
+            data + nulls + varlen
+        })
+        .sum();
+
+    width.max(1)
+}
+
+/// Tuning knobs
+#[derive(Clone, Debug)]
+pub struct ExecConfig {
+    /// Rows requested from cursors at a time (by drivers and operators pulling input)
+    pub fetch_rows: RowOffset,
+    /// Size the fetches by row width instead of always `fetch_rows`
+    pub batch: Option<BatchSizing>,
+    /// Rows in a parallel scan morsel
+    pub morsel_rows: RowOffset,
+    /// Blocks buffered between the workers and their consumer
+    pub buffering: BufferPolicy,
+    /// Instrument the bound cursors with runtime metrics (`explain::analyze`)
+    pub analyze: bool,
+    /// Integer SUMs that don't fit their result type
+    pub sum_overflow: OverflowPolicy,
+}
+
+impl Default for ExecConfig {
+    fn default() -> ExecConfig {
+        ExecConfig {
+            fetch_rows: DEFAULT_CURSOR_FETCH,
+            batch: None,
+            morsel_rows: DEFAULT_MORSEL_ROWS,
+            buffering: BufferPolicy::default(),
+            analyze: false,
+            sum_overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// Named counters shared by all the operators (and threads) of a query
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn add(&self, name: &str, value: u64) {
+        let mut counters = self.counters.lock().unwrap();
+        if let Some(v) = counters.get_mut(name) {
+            *v += value;
+            return
+        }
+        counters.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> u64 {
+        self.counters.lock().unwrap().get(name).cloned().unwrap_or(0)
+    }
+
+    /// Copy of all the counters
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counters.lock().unwrap().clone()
+    }
+}
+
+/// Allocator, thread pool, cancellation, metrics and configuration of a query.
+///
+/// Passed to `Operation::bind`; operations pass it on to their inputs. Clones share the thread
+/// pool, cancel token and metrics.
+#[derive(Clone)]
+pub struct ExecContext<'a> {
+    alloc: &'a Allocator,
+    pool: Option<Arc<ThreadPool>>,
+    cancel: CancelToken,
+    metrics: Arc<Metrics>,
+    config: ExecConfig,
+}
+
+/// Global allocator, no thread pool
+impl Default for ExecContext<'static> {
+    fn default() -> ExecContext<'static> {
+        ExecContext::new(&allocator::GLOBAL)
+    }
+}
+
+impl<'a> ExecContext<'a> {
+    pub fn new(alloc: &'a Allocator) -> ExecContext<'a> {
+        ExecContext {
+            alloc: alloc,
+            pool: None,
+            cancel: CancelToken::new(),
+            metrics: Arc::new(Metrics::new()),
+            config: ExecConfig::default(),
+        }
+    }
+
+    pub fn with_pool(self, pool: Arc<ThreadPool>) -> ExecContext<'a> {
+        ExecContext { pool: Some(pool), .. self }
+    }
+
+    pub fn with_cancel(self, token: CancelToken) -> ExecContext<'a> {
+        ExecContext { cancel: token, .. self }
+    }
+
+    pub fn with_metrics(self, metrics: Arc<Metrics>) -> ExecContext<'a> {
+        ExecContext { metrics: metrics, .. self }
+    }
+
+    pub fn with_config(self, config: ExecConfig) -> ExecContext<'a> {
+        ExecContext { config: config, .. self }
+    }
+
+    /// Same context with a different allocator
+    pub fn with_allocator<'b>(&self, alloc: &'b Allocator) -> ExecContext<'b> {
+        ExecContext {
+            alloc: alloc,
+            pool: self.pool.clone(),
+            cancel: self.cancel.clone(),
+            metrics: self.metrics.clone(),
+            config: self.config.clone(),
+        }
+    }
+
+    pub fn allocator(&self) -> &'a Allocator {
+        self.alloc
+    }
+
+    pub fn pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.pool.as_ref()
+    }
+
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn config(&self) -> &ExecConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut ExecConfig {
+        &mut self.config
+    }
+
+    /// Rows to request from the cursor at a time: `fetch_rows`, or sized by `batch`
+    pub fn fetch_rows<'c>(&self, cursor: &(Cursor<'c> + 'c)) -> RowOffset {
+        match self.config.batch {
+            Some(ref batch) => batch.rows(cursor.schema(), cursor.preferred_rows()),
+            None            => self.config.fetch_rows,
+        }
+    }
+
+    /// Bind an (input) operation with this context; operations bind their inputs through this.
+    /// The cursor is instrumented when `analyze` is set. Errors binding or running the cursor
+    /// carry the operator (`DBError::Operator`). The cursor reports the operation's pipelining.
+    pub fn bind<'o>(&self, op: &(Operation<'o> + 'o)) -> Result<Box<Cursor<'o> + 'o>, DBError>
+        where 'a: 'o
+    {
+        let name = op.describe();
+        let pipelining = op.pipelining();
+        let cursor = op.bind(self).map_err(|e| e.in_operator(name.as_str()))?;
+
+        if !self.config.analyze {
+            return Ok(Box::new(OperatorCursor { input: cursor, name: name, pipelining: pipelining }))
+        }
+
+        let instrumented: Box<Cursor<'o> + 'o> = Box::new(InstrumentedCursor::new(name.clone(), cursor));
+        Ok(Box::new(OperatorCursor { input: instrumented, name: name, pipelining: pipelining }))
+    }
+}
+
+/// Attaches the operator to errors of the cursor
+struct OperatorCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    name: String,
+    pipelining: Pipelining,
+}
+
+impl<'a> Cursor<'a> for OperatorCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let name = &self.name;
+        self.input.next(rows).map_err(|e| e.in_operator(name.as_str()))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.input.memory_usage()
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        self.input.inputs()
+    }
+
+    fn metrics(&self) -> Option<(&str, &CursorMetrics)> {
+        self.input.metrics()
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.pipelining
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.input.rewind()
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        self.input.push_key_filter(filter)
+    }
+
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        self.input.aggregates_from_metadata(aggregates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::block::Block;
+    use ::expression::comparison::CompareOp;
+    use ::operation::{CursorChunk, Filter, ScanPredicate, ScanView};
+    use ::schema::Schema;
+
+    #[test]
+    fn context() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+
+        let ctx = ExecContext::default();
+        let child = ctx.clone();
+        child.metrics().add("rows", 4);
+        child.metrics().add("rows", 6);
+        assert_eq!(ctx.metrics().get("rows"), 10);
+        assert_eq!(ctx.metrics().get("missing"), 0);
+        assert!(ctx.pool().is_none());
+
+        let scan = ScanView::new(&block, None);
+        let mut cursor = scan.bind(&child).unwrap();
+        assert!(match cursor.next(4) { Ok(CursorChunk::Next(_)) => true, _ => false });
+
+        // Cancelling any clone stops the bound scans
+        ctx.cancel_token().cancel();
+        assert!(match cursor.next(4) { Err(DBError::Cancelled) => true, _ => false });
+    }
+
+    #[test]
+    fn batch_sizing() {
+        let narrow = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let wide = Schema::parse_ddl("id UINT64 NOT NULL, name TEXT, tags LIST<TEXT>").unwrap();
+
+        let batch = BatchSizing { target_bytes: 64 * 1024, min_rows: 64, max_rows: 8192 };
+        assert_eq!(batch.rows(&narrow, None), 8192);
+        let rows = batch.rows(&wide, None);
+        assert!(rows < 8192 && rows >= 64 && rows % 64 == 0);
+
+        // Preferred chunk size, within the bounds
+        assert_eq!(batch.rows(&wide, Some(1000)), 1000);
+        assert_eq!(batch.rows(&wide, Some(1)), 64);
+        assert_eq!(BatchSizing { target_bytes: 1, .. batch }.rows(&wide, None), 64);
+
+        let mut block = Block::new(&allocator::GLOBAL, &narrow);
+        block.add_rows(10).unwrap();
+        let scan = ScanView::new(&block, None);
+
+        let mut ctx = ExecContext::default();
+        assert_eq!(ctx.fetch_rows(&*ctx.bind(&scan).unwrap()), DEFAULT_CURSOR_FETCH);
+        ctx.config_mut().batch = Some(batch);
+        assert_eq!(ctx.fetch_rows(&*ctx.bind(&scan).unwrap()), 8192);
+    }
+
+    #[test]
+    fn operator_errors() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let block = Block::new(&allocator::GLOBAL, &schema);
+
+        let filter = Filter::new(ScanPredicate::new(0, CompareOp::EQ, "x"), ScanView::new(&block, None));
+        let err = ExecContext::default().bind(&filter).err().unwrap();
+        match err {
+            DBError::Operator { ref name, .. } => assert_eq!(name, "Filter #0 EQ x"),
+            _ => panic!("{}", err),
+        }
+        assert!(match *err.root_cause() { DBError::ExpressionInputType(_) => true, _ => false });
+
+        // Innermost operator is kept
+        let err = err.in_operator("Limit");
+        assert_eq!(err.to_string(), "Invalid expression input type: UINT32 (in Filter #0 EQ x)");
+    }
+}
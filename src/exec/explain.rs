@@ -0,0 +1,214 @@
+// vim: set ts=4 sw=4 et :
+
+//! Operator tree rendering (EXPLAIN) and per-operator runtime metrics (EXPLAIN ANALYZE).
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use ::block::View;
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::operation::{Cursor, CursorChunk, Operation, Pipelining};
+use ::plan::Aggregate;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Value;
+use ::util::bloom::KeyFilter;
+
+/// Runtime metrics of an instrumented cursor
+#[derive(Default)]
+pub struct CursorMetrics {
+    /// Rows returned
+    pub rows: Cell<usize>,
+    /// Chunks returned (excluding the end of stream)
+    pub chunks: Cell<usize>,
+    /// Time spent in `next`, including the inputs
+    pub time: Cell<Duration>,
+    /// Highest `Cursor::memory_usage`, sampled before every `next` call
+    pub peak_memory: Cell<usize>,
+}
+
+/// Wraps a cursor, recording its `CursorMetrics`. See `ExecContext::bind`.
+pub struct InstrumentedCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    name: String,
+    metrics: CursorMetrics,
+}
+
+impl<'a> InstrumentedCursor<'a> {
+    pub fn new(name: String, input: Box<Cursor<'a> + 'a>) -> InstrumentedCursor<'a> {
+        InstrumentedCursor { input: input, name: name, metrics: CursorMetrics::default() }
+    }
+}
+
+impl<'a> Cursor<'a> for InstrumentedCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let metrics = &self.metrics;
+        metrics.peak_memory.set(metrics.peak_memory.get().max(self.input.memory_usage()));
+
+        let start = Instant::now();
+        let out = self.input.next(rows);
+        metrics.time.set(metrics.time.get() + start.elapsed());
+
+        if let Ok(CursorChunk::Next(ref view)) = out {
+            metrics.rows.set(metrics.rows.get() + view.rows());
+            metrics.chunks.set(metrics.chunks.get() + 1);
+        }
+
+        out
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.input.memory_usage()
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        self.input.inputs()
+    }
+
+    fn metrics(&self) -> Option<(&str, &CursorMetrics)> {
+        Some((&self.name, &self.metrics))
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.input.rewind()
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        self.input.push_key_filter(filter)
+    }
+
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        self.input.aggregates_from_metadata(aggregates)
+    }
+}
+
+/// Operator tree, one operator per line, inputs indented under their parent. Pipeline breakers
+/// are marked `[blocking]`.
+pub fn explain<'a>(op: &Operation<'a>) -> String {
+    fn walk<'a>(op: &Operation<'a>, depth: usize, out: &mut String) {
+        let blocking = if op.pipelining() == Pipelining::BLOCKING { " [blocking]" } else { "" };
+        let _ = writeln!(out, "{:indent$}{}{}", "", op.describe(), blocking, indent = depth * 2);
+        for input in op.inputs() {
+            walk(input, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    walk(op, 0, &mut out);
+    out
+}
+
+/// Cursor tree with the runtime metrics of the instrumented cursors (bound with `analyze` set).
+/// Cursors that aren't instrumented are left out, their inputs are shown in their place.
+///
+/// `time` includes the inputs, `self` doesn't.
+pub fn explain_analyze<'a>(cursor: &Cursor<'a>) -> String {
+    fn instrumented<'s, 'a>(cursor: &'s Cursor<'a>, out: &mut Vec<&'s Cursor<'a>>) {
+        for input in cursor.inputs() {
+            match input.metrics() {
+                Some(_) => out.push(input),
+                None    => instrumented(input, out),
+            }
+        }
+    }
+
+    fn walk<'a>(cursor: &Cursor<'a>, depth: usize, out: &mut String) {
+        let mut inputs = Vec::new();
+        instrumented(cursor, &mut inputs);
+
+        if let Some((name, metrics)) = cursor.metrics() {
+            let time = metrics.time.get();
+            let own = inputs.iter()
+                .filter_map(|c| c.metrics())
+                .fold(time, |acc, (_, m)| acc.checked_sub(m.time.get()).unwrap_or_default());
+
+            let _ = writeln!(out, "{:indent$}{}  (rows={} chunks={} time={} self={} memory={})", "", name,
+                             metrics.rows.get(), metrics.chunks.get(), millis(time), millis(own),
+                             metrics.peak_memory.get(), indent = depth * 2);
+        }
+
+        let depth = if cursor.metrics().is_some() { depth + 1 } else { depth };
+        for input in inputs {
+            walk(input, depth, out);
+        }
+    }
+
+    let mut out = String::new();
+    walk(cursor, 0, &mut out);
+    out
+}
+
+/// Bind the operation with instrumented cursors, run it to completion and render it with its
+/// runtime metrics (`explain_analyze`)
+pub fn analyze<'a, 'b: 'a>(op: &(Operation<'a> + 'a), ctx: &ExecContext<'b>) -> Result<String, DBError> {
+    let mut ctx = ctx.clone();
+    ctx.config_mut().analyze = true;
+
+    let mut cursor = ctx.bind(op)?;
+    let fetch = ctx.fetch_rows(&*cursor);
+    while let CursorChunk::Next(_) = cursor.next(fetch)? {}
+
+    Ok(explain_analyze(&*cursor))
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.3}ms", d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::exec::{CancelToken, Cancellable};
+    use ::operation::{Project, ScanView};
+    use ::projector::*;
+
+    #[test]
+    fn explain_tree() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, name TEXT").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+
+        let proj = BuildSingleSourceProjector::new()
+            .add_as(project_by_name("name"), "n")
+            .add(project_by_position(0))
+            .done();
+        let op = Project::new(proj, Cancellable::new(ScanView::new(&block, None), CancelToken::new()));
+
+        assert_eq!(explain(&op), "Project [name AS n, #0]\n  Cancellable\n    ScanView rows=10\n");
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 4;
+        let out = analyze(&op, &ctx).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Project [name AS n, #0]  (rows=10 chunks=3 time="));
+        assert!(lines[1].starts_with("  Cancellable  (rows=10 chunks=3 "));
+        assert!(lines[2].starts_with("    ScanView rows=10  (rows=10 chunks=3 "));
+        assert!(lines[2].ends_with(" memory=0)"));
+
+        // Not instrumented
+        let cursor = op.bind(&ExecContext::default()).unwrap();
+        assert_eq!(explain_analyze(&*cursor), "");
+    }
+}
@@ -0,0 +1,422 @@
+// vim: set ts=4 sw=4 et :
+
+//! Parallel execution of operation trees.
+//!
+//! The `Driver` runs independent subtrees of a query (eg. both inputs of a join, partitions of a
+//! scan) concurrently on a `ThreadPool`. Each subtree is built and bound on a worker thread, so
+//! only its inputs have to be `Send`. The output chunks are copied into owned `Block`s and read
+//! back through a `ChannelCursor`.
+
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+
+use ::allocator::Allocator;
+use ::block::{Block, View};
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk, Operation, Pipelining};
+use ::row::RowOffset;
+
+pub mod async_cursor;
+pub mod cancel;
+pub mod channel;
+pub mod context;
+pub mod explain;
+pub mod pool;
+pub mod sink;
+
+pub use self::async_cursor::{Async, AsyncChannelCursor, AsyncCursor, BlockingCursor, Notify, ReadyCursor};
+pub use self::cancel::{Cancellable, CancelToken};
+pub use self::channel::{ChannelCursor, ChannelSender, Message};
+pub use self::context::{BatchSizing, BufferPolicy, ExecConfig, ExecContext, Metrics};
+pub use self::pool::ThreadPool;
+pub use self::sink::{Sink, collect_block, collect_rows, count, for_each_row};
+
+/// Builds and binds an operation tree on a worker thread
+pub type BindFn = Box<FnMut(&ExecContext<'static>) -> Result<Box<Cursor<'static>>, DBError> + Send>;
+
+/// Default number of blocks buffered between a producer and its cursor
+pub const DEFAULT_BUFFER: usize = 4;
+
+/// Runs subtrees on the context's thread pool. The workers bind with (a clone of) the context,
+/// output blocks are allocated from its allocator.
+pub struct Driver {
+    pool: Arc<ThreadPool>,
+    ctx: ExecContext<'static>,
+}
+
+impl Driver {
+    /// Driver with a new thread pool
+    pub fn new(threads: usize, alloc: &'static Allocator) -> Result<Driver, DBError> {
+        let pool = Arc::new(ThreadPool::new(threads)?);
+        Driver::from_context(ExecContext::new(alloc).with_pool(pool))
+    }
+
+    /// The context has to have a thread pool
+    pub fn from_context(ctx: ExecContext<'static>) -> Result<Driver, DBError> {
+        let pool = ctx.pool().cloned()
+            .ok_or_else(|| DBError::Execution("context without a thread pool".to_string()))?;
+
+        Ok(Driver { pool: pool, ctx: ctx })
+    }
+
+    pub fn context(&self) -> &ExecContext<'static> {
+        &self.ctx
+    }
+
+    /// Producers stop with `DBError::Cancelled` once the token is cancelled
+    pub fn with_cancel(self, token: CancelToken) -> Driver {
+        Driver { ctx: self.ctx.with_cancel(token), .. self }
+    }
+
+    pub fn cancel_token(&self) -> &CancelToken {
+        self.ctx.cancel_token()
+    }
+
+    /// Blocks in flight per streaming producer
+    pub fn with_buffer(mut self, blocks: usize) -> Driver {
+        self.ctx.config_mut().buffering.streaming = blocks;
+        self
+    }
+
+    pub fn with_buffering(mut self, policy: BufferPolicy) -> Driver {
+        self.ctx.config_mut().buffering = policy;
+        self
+    }
+
+    /// Rows requested from the producer cursors at a time
+    pub fn with_fetch(mut self, rows: RowOffset) -> Driver {
+        self.ctx.config_mut().fetch_rows = rows;
+        self
+    }
+
+    /// Size the producer fetches by row width (`ExecContext::fetch_rows`)
+    pub fn with_batch_sizing(mut self, batch: BatchSizing) -> Driver {
+        self.ctx.config_mut().batch = Some(batch);
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.threads()
+    }
+
+    /// Bind an operation on a worker; its output is read through the returned cursor
+    pub fn spawn<O: Operation<'static> + Send + 'static>(&self, op: O) -> Result<ChannelCursor, DBError> {
+        self.union(vec![op])
+    }
+
+    /// Run a subtree built by `bind` on a worker
+    pub fn spawn_fn(&self, bind: BindFn) -> Result<ChannelCursor, DBError> {
+        self.union_fn(vec![bind])
+    }
+
+    /// Run all the operations concurrently, a cursor for each (eg. the inputs of a join)
+    pub fn spawn_all<O: Operation<'static> + Send + 'static>(&self, ops: Vec<O>) -> Result<Vec<ChannelCursor>, DBError> {
+        let receivers: Vec<_> = ops.into_iter()
+            .map(|op| {
+                let pipelining = op.pipelining();
+                self.start(vec![bind_op(op)], pipelining)
+            })
+            .collect();
+
+        receivers.into_iter()
+            .map(|rx| ChannelCursor::new(rx, 1))
+            .collect()
+    }
+
+    /// Run all the operations concurrently, one cursor over the union of their output (in no
+    /// particular order). The operations have to have the same output schema.
+    pub fn union<O: Operation<'static> + Send + 'static>(&self, ops: Vec<O>) -> Result<ChannelCursor, DBError> {
+        let pipelining = if ops.iter().any(|op| op.pipelining() == Pipelining::BLOCKING) {
+            Pipelining::BLOCKING
+        } else {
+            Pipelining::STREAMING
+        };
+
+        let producers = ops.len();
+        ChannelCursor::new(self.start(ops.into_iter().map(bind_op).collect(), pipelining), producers)
+    }
+
+    /// The subtrees built by `binds` are opaque until bound, they're buffered as streaming
+    pub fn union_fn(&self, binds: Vec<BindFn>) -> Result<ChannelCursor, DBError> {
+        let producers = binds.len();
+        ChannelCursor::new(self.start(binds, Pipelining::STREAMING), producers)
+    }
+
+    /// Bind an operation on a worker, without blocking the caller on the output
+    pub fn spawn_async<O: Operation<'static> + Send + 'static>(&self, op: O) -> AsyncChannelCursor {
+        let pipelining = op.pipelining();
+        self.start_async(bind_op(op), pipelining)
+    }
+
+    pub fn spawn_async_fn(&self, bind: BindFn) -> AsyncChannelCursor {
+        self.start_async(bind, Pipelining::STREAMING)
+    }
+
+    fn start_async(&self, bind: BindFn, pipelining: Pipelining) -> AsyncChannelCursor {
+        let buffer = self.ctx.config().buffering.buffer(pipelining, 1);
+        let (tx, rx, waiting) = channel::notify_channel(buffer);
+        self.produce_on_worker(bind, tx);
+        AsyncChannelCursor::new(rx, waiting)
+    }
+
+    fn start(&self, binds: Vec<BindFn>, pipelining: Pipelining) -> Receiver<Message> {
+        let buffer = self.ctx.config().buffering.buffer(pipelining, binds.len());
+        let (tx, rx) = channel::channel(buffer);
+
+        for bind in binds {
+            self.produce_on_worker(bind, tx.clone());
+        }
+
+        rx
+    }
+
+    fn produce_on_worker(&self, mut bind: BindFn, tx: ChannelSender) {
+        let ctx = self.ctx.clone();
+
+        self.pool.spawn(move || {
+            if let Err(e) = produce(&mut bind, &ctx, &tx) {
+                tx.send(Message::ERROR(e));
+            }
+        });
+    }
+}
+
+fn bind_op<O: Operation<'static> + Send + 'static>(op: O) -> BindFn {
+    Box::new(move |ctx| op.bind(ctx))
+}
+
+/// Send the cursor's output. Stops early, without an error, if the consumer went away.
+fn produce(bind: &mut BindFn, ctx: &ExecContext<'static>, tx: &ChannelSender) -> Result<(), DBError> {
+    let cancel = ctx.cancel_token();
+
+    cancel.check()?;
+    let mut cursor = bind(ctx)?;
+    let fetch = ctx.fetch_rows(&*cursor);
+
+    if !tx.send(Message::SCHEMA(cursor.schema().clone())) {
+        return Ok(())
+    }
+
+    loop {
+        cancel.check()?;
+        let block = match cursor.next(fetch)? {
+            CursorChunk::Next(ref view) if view.rows() == 0 => continue,
+            CursorChunk::Next(view)                         => {
+                let mut block = Block::new(ctx.allocator(), view.schema());
+                block.append_view(&view)?;
+                block
+            }
+            CursorChunk::End                                => break,
+        };
+
+        if !tx.send(Message::BLOCK(block)) {
+            return Ok(())
+        }
+    }
+
+    tx.send(Message::END);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::schema::Schema;
+    use ::types::{UInt64, Value};
+    use ::util::copy_value::set_column_value;
+
+    /// `rows` sequential values starting at `first`, produced from a worker thread
+    struct Sequence {
+        first: u64,
+        rows: usize,
+        fail: bool,
+    }
+
+    struct SequenceCursor {
+        block: Block<'static>,
+        next: usize,
+    }
+
+    impl<'a> Operation<'a> for Sequence {
+        fn bind<'b: 'a>(&self, _: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+            if self.fail {
+                return Err(DBError::Execution("bind failed".to_string()))
+            }
+
+            let schema = Schema::parse_ddl("v UINT64 NOT NULL, thread TEXT").unwrap();
+            let mut block = Block::new(&allocator::GLOBAL, &schema);
+            let name = thread::current().name().unwrap_or("").to_string();
+            block.add_rows(self.rows)?;
+            for row in 0 .. self.rows {
+                set_column_value(&mut block, 0, row, &Value::UINT64(self.first + row as u64))?;
+                set_column_value(&mut block, 1, row, &Value::from(name.clone()))?;
+            }
+
+            Ok(Box::new(SequenceCursor { block: block, next: 0 }))
+        }
+
+        fn describe(&self) -> String {
+            format!("Sequence first={} rows={}", self.first, self.rows)
+        }
+    }
+
+    impl<'a> Cursor<'a> for SequenceCursor {
+        fn schema(&self) -> &Schema {
+            self.block.schema()
+        }
+
+        fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+            if self.next >= self.block.rows() {
+                return Ok(CursorChunk::End)
+            }
+
+            let range = ::row::RowRange { offset: self.next, rows: rows.min(self.block.rows() - self.next) };
+            self.next += range.rows;
+            Ok(CursorChunk::Next(::block::window_alias(&self.block, Some(range))?))
+        }
+    }
+
+    /// Pipeline breaker over its input (in name only, the rows are passed through)
+    struct Breaker<O>(O);
+
+    impl<'a, O: Operation<'a> + 'a> Operation<'a> for Breaker<O> {
+        fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+            self.0.bind(ctx)
+        }
+
+        fn describe(&self) -> String {
+            "Breaker".to_string()
+        }
+
+        fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+            vec![&self.0]
+        }
+
+        fn pipelining(&self) -> Pipelining {
+            Pipelining::BLOCKING
+        }
+    }
+
+    fn seq(first: u64, rows: usize) -> Sequence {
+        Sequence { first: first, rows: rows, fail: false }
+    }
+
+    fn drain(cursor: &mut ChannelCursor) -> Vec<u64> {
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(7).unwrap() {
+            assert!(view.rows() <= 7);
+            out.extend_from_slice(&column_row_data::<UInt64>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        out
+    }
+
+    #[test]
+    fn parallel_union() {
+        let driver = Driver::new(3, &allocator::GLOBAL).unwrap().with_fetch(10).with_buffer(1);
+
+        let mut cursor = driver.union((0 .. 4).map(|p| seq(p * 100, 100)).collect()).unwrap();
+        assert_eq!(cursor.schema().count(), 2);
+
+        let mut values = drain(&mut cursor);
+        values.sort();
+        assert_eq!(values, (0 .. 400).collect::<Vec<u64>>());
+
+        let mut cursors = driver.spawn_all(vec![seq(0, 5), seq(10, 0)]).unwrap();
+        assert_eq!(drain(&mut cursors[0]), vec![0, 1, 2, 3, 4]);
+        assert_eq!(drain(&mut cursors[1]), Vec::<u64>::new());
+
+        // File scans can run on the workers
+        fn is_send<T: Send>() {}
+        is_send::<::operation::ScanMmap>();
+
+        // Ran on the workers
+        let mut cursor = driver.spawn(seq(0, 1)).unwrap();
+        match cursor.next(1).unwrap() {
+            CursorChunk::Next(view) => {
+                let name = ::block::column_value(view.column(1).unwrap(), 0).unwrap();
+                assert!(name.as_str().unwrap().starts_with("dbkit-worker-"));
+            }
+            CursorChunk::End        => panic!("expected a row"),
+        }
+    }
+
+    #[test]
+    fn pipelining() {
+        let policy = BufferPolicy::default();
+        assert_eq!(policy.buffer(Pipelining::STREAMING, 1), DEFAULT_BUFFER);
+        assert!(policy.buffer(Pipelining::BLOCKING, 1) > DEFAULT_BUFFER);
+        assert_eq!(policy.buffer(Pipelining::STREAMING, 1000), policy.max_in_flight);
+        assert_eq!(BufferPolicy::fixed(0).buffer(Pipelining::BLOCKING, 3), 1);
+
+        let op = Cancellable::new(Breaker(Breaker(seq(0, 50))), CancelToken::new());
+        assert_eq!(op.pipelining(), Pipelining::BLOCKING);
+        assert_eq!(::operation::pipeline_breakers(&op).len(), 3);
+        assert!(::operation::pipeline_breakers(&seq(0, 1)).is_empty());
+        assert_eq!(explain::explain(&Breaker(seq(0, 1))), "Breaker [blocking]\n  Sequence first=0 rows=1\n");
+
+        // Bound cursors report the operation's
+        let cursor = ExecContext::default().bind(&op).unwrap();
+        assert_eq!(cursor.pipelining(), Pipelining::BLOCKING);
+
+        let driver = Driver::new(2, &allocator::GLOBAL).unwrap().with_fetch(10)
+            .with_buffering(BufferPolicy::fixed(1));
+        let mut cursor = driver.union(vec![Breaker(seq(0, 50)), Breaker(seq(50, 50))]).unwrap();
+        let mut values = drain(&mut cursor);
+        values.sort();
+        assert_eq!(values, (0 .. 100).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn producer_errors() {
+        let driver = Driver::new(2, &allocator::GLOBAL).unwrap();
+
+        let failing = Sequence { first: 0, rows: 1, fail: true };
+        assert!(driver.union(vec![seq(0, 10), failing]).is_err());
+
+        // Producer panics
+        match driver.spawn_fn(Box::new(|_| panic!("boom"))) {
+            Err(DBError::Execution(_))  => (),
+            _                           => panic!("expected an execution error"),
+        }
+
+        // Consumer dropping the cursor early doesn't block the workers
+        drop(driver.spawn(seq(0, 100000)).unwrap());
+        match driver.spawn_fn(Box::new(|_| Err(DBError::RowOutOfBounds))) {
+            Err(DBError::RowOutOfBounds)    => (),
+            _                               => panic!("expected the bind error"),
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let token = CancelToken::new();
+        let driver = Driver::new(2, &allocator::GLOBAL).unwrap().with_fetch(10).with_buffer(1)
+            .with_cancel(token.clone());
+
+        let mut cursor = driver.spawn(seq(0, 1000)).unwrap();
+        assert!(match cursor.next(10).unwrap() { CursorChunk::Next(_) => true, _ => false });
+
+        token.cancel();
+        let mut result = Ok(());
+        for _ in 0 .. 1000 {
+            if let Err(e) = cursor.next(10) {
+                result = Err(e);
+                break
+            }
+        }
+        assert!(match result { Err(DBError::Cancelled) => true, _ => false });
+
+        // Checked by the cursor as well as the driver
+        let mut op = Cancellable::new(seq(0, 10), token.clone());
+        assert!(op.bind(&ExecContext::default()).is_err());
+        op.token = CancelToken::new();
+
+        let mut bound = op.bind(&ExecContext::default()).unwrap();
+        assert!(bound.next(5).is_ok());
+        op.token.cancel();
+        assert!(match bound.next(5) { Err(DBError::Cancelled) => true, _ => false });
+    }
+}
@@ -0,0 +1,67 @@
+// vim: set ts=4 sw=4 et :
+
+//! Fixed size worker thread pool.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use ::error::DBError;
+
+type Job = Box<FnMut() + Send>;
+
+/// Runs jobs on a fixed set of threads, in submission order.
+///
+/// Dropping the pool doesn't wait for the jobs; the queued jobs still run and the threads exit
+/// once the queue is empty. A panicking job doesn't take its worker thread down.
+pub struct ThreadPool {
+    queue: Mutex<Sender<Job>>,
+    threads: usize,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> Result<ThreadPool, DBError> {
+        if threads == 0 {
+            return Err(DBError::Execution("thread pool needs at least one thread".to_string()))
+        }
+
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for id in 0 .. threads {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("dbkit-worker-{}", id))
+                .spawn(move || worker(rx))?;
+        }
+
+        Ok(ThreadPool { queue: Mutex::new(tx), threads: threads })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, job: F) {
+        let mut job = Some(job);
+        let job: Job = Box::new(move || if let Some(job) = job.take() { job() });
+
+        // Workers only exit once the sender is gone
+        self.queue.lock().unwrap().send(job).unwrap();
+    }
+}
+
+fn worker(queue: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = queue.lock().unwrap().recv();
+        match job {
+            Ok(mut job) => {
+                if panic::catch_unwind(AssertUnwindSafe(|| job())).is_err() {
+                    error!("dbkit worker job panicked");
+                }
+            }
+            Err(_)      => return,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// vim: set ts=4 sw=4 et :
+
+//! Consuming cursors: a `Sink` receives every chunk of a cursor (`drain`), plus helpers for the
+//! usual cases (all the rows in a block, row by row, counting).
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk, DEFAULT_CURSOR_FETCH};
+use ::row::RowOffset;
+use ::types::Value;
+
+/// Consumer of the chunks of a cursor
+pub trait Sink {
+    /// Called with every non empty chunk, in order
+    fn consume<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError>;
+
+    /// Called once after the last chunk
+    fn finish(&mut self) -> Result<(), DBError> {
+        Ok(())
+    }
+}
+
+/// Appends the chunks to the block
+impl<'a> Sink for Block<'a> {
+    fn consume<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        self.append_view(view).map(|_| ())
+    }
+}
+
+/// Copies of the rows' values
+impl Sink for Vec<Vec<Value<'static>>> {
+    fn consume<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        for row in 0 .. view.rows() {
+            self.push(row_values(view, row)?);
+        }
+        Ok(())
+    }
+}
+
+/// Counts the rows
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RowCount(pub usize);
+
+impl Sink for RowCount {
+    fn consume<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        self.0 += view.rows();
+        Ok(())
+    }
+}
+
+/// Calls the function with the values of every row
+pub struct ForEachRow<F>(pub F);
+
+impl<F: FnMut(&[Value]) -> Result<(), DBError>> Sink for ForEachRow<F> {
+    fn consume<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        for row in 0 .. view.rows() {
+            (self.0)(&row_values(view, row)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn row_values<'v>(view: &'v View<'v>, row: RowOffset) -> Result<Vec<Value<'static>>, DBError> {
+    (0 .. view.schema().count())
+        .map(|pos| {
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            column_value(col, row).map(|v| v.into_owned())
+        })
+        .collect()
+}
+
+/// Read the cursor to the end into the sink, in chunks of the cursor's preferred size (or
+/// `DEFAULT_CURSOR_FETCH` rows)
+pub fn drain<'a, S: Sink + ?Sized>(cursor: &mut (Cursor<'a> + 'a), sink: &mut S) -> Result<(), DBError> {
+    let rows = cursor.preferred_rows().unwrap_or(DEFAULT_CURSOR_FETCH);
+    while let CursorChunk::Next(view) = cursor.next(rows)? {
+        if view.rows() > 0 {
+            sink.consume(&view)?;
+        }
+    }
+    sink.finish()
+}
+
+/// All the rows of the cursor, copied into a block
+pub fn collect_block<'a, 'b>(cursor: &mut (Cursor<'a> + 'a), alloc: &'b Allocator) -> Result<Block<'b>, DBError> {
+    let mut block = Block::new(alloc, cursor.schema());
+    drain(cursor, &mut block)?;
+    Ok(block)
+}
+
+/// Values of all the rows of the cursor, eg. for comparing results in tests
+pub fn collect_rows<'a>(cursor: &mut (Cursor<'a> + 'a)) -> Result<Vec<Vec<Value<'static>>>, DBError> {
+    let mut rows = Vec::new();
+    drain(cursor, &mut rows)?;
+    Ok(rows)
+}
+
+/// Call `f` with the values of every row of the cursor; stops at the first error
+pub fn for_each_row<'a, F>(cursor: &mut (Cursor<'a> + 'a), f: F) -> Result<(), DBError>
+    where F: FnMut(&[Value]) -> Result<(), DBError>
+{
+    drain(cursor, &mut ForEachRow(f))
+}
+
+/// Number of rows left in the cursor
+pub fn count<'a>(cursor: &mut (Cursor<'a> + 'a)) -> Result<usize, DBError> {
+    let mut count = RowCount::default();
+    drain(cursor, &mut count)?;
+    Ok(count.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::exec::ExecContext;
+    use ::operation::ScanView;
+    use ::schema::Schema;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn sinks() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id UINT32 NOT NULL, name TEXT").unwrap());
+        block.add_rows(3).unwrap();
+        for (row, &(id, name)) in [(1u32, Some("a")), (2, None), (3, Some("c"))].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::UINT32(id)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from(name)).unwrap();
+        }
+
+        let ctx = ExecContext::default();
+        let op = ScanView::new(&block, None);
+
+        assert_eq!(count(&mut *ctx.bind(&op).unwrap()).unwrap(), 3);
+
+        let rows = collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap();
+        assert_eq!(rows, vec![vec![Value::UINT32(1), Value::from("a")],
+                              vec![Value::UINT32(2), Value::NULL],
+                              vec![Value::UINT32(3), Value::from("c")]]);
+
+        let copy = collect_block(&mut *ctx.bind(&op).unwrap(), &allocator::GLOBAL).unwrap();
+        assert_eq!(copy.rows(), 3);
+        assert_eq!(column_value(copy.column(1).unwrap(), 2).unwrap(), Value::from("c"));
+
+        // Errors from the function stop the drain
+        let mut seen = 0;
+        let err = for_each_row(&mut *ctx.bind(&op).unwrap(), |row| {
+            seen += 1;
+            if row[1].is_null() { Err(DBError::Unknown) } else { Ok(()) }
+        });
+        assert!(err.is_err());
+        assert_eq!(seen, 2);
+    }
+}
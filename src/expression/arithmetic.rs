@@ -0,0 +1,195 @@
+use std::marker::PhantomData;
+
+use ::allocator::Allocator;
+use ::block::{Bitmap, Block, MutBitmap, RefColumn, View, column_row_data};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::bitmap::bytes_for;
+use ::util::math::{self, ArithOp, CheckedArith, OverflowPolicy};
+use ::util::selection::bits_to_indices;
+
+/// Binary arithmetic (`+`, `-`, `*`) of two numeric inputs of the same type. NULL inputs produce
+/// NULL; overflow is handled according to the `OverflowPolicy` (default `ERROR`).
+pub struct Arithmetic<'b> {
+    pub op: ArithOp,
+    pub lhs: Box<Expr<'b> + 'b>,
+    pub rhs: Box<Expr<'b> + 'b>,
+    pub overflow: OverflowPolicy,
+}
+
+struct ArithmeticBound<'alloc, T> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    op: ArithOp,
+    overflow: OverflowPolicy,
+    pt: PhantomData<T>,
+}
+
+impl<'a> Arithmetic<'a> {
+    pub fn new<L: Expr<'a> + 'a, R: Expr<'a> + 'a>(op: ArithOp, lhs: L, rhs: R) -> Arithmetic<'a> {
+        Arithmetic { op: op, lhs: Box::new(lhs), rhs: Box::new(rhs), overflow: OverflowPolicy::ERROR }
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Arithmetic<'a> {
+        self.overflow = overflow;
+        self
+    }
+}
+
+impl<'b> Expr<'b> for Arithmetic<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        if input_schema.count() != 2 {
+            return Err(DBError::ExpressionInputCount(format!("{} != 2", input_schema.count())))
+        }
+
+        let (lhs, rhs) = (input_schema.get(0)?, input_schema.get(1)?);
+        if lhs.dtype != rhs.dtype {
+            return Err(DBError::ExpressionInputType(format!("{} {} {}", lhs.dtype.name(), self.op, rhs.dtype.name())))
+        }
+
+        let nullable = lhs.nullable || rhs.nullable || self.overflow == OverflowPolicy::NULL;
+        let name = format!("{} {} {}", lhs.name, self.op, rhs.name);
+        let schema = Schema::from_attr(Attribute::new(name, nullable, lhs.dtype));
+        let (op, overflow) = (self.op, self.overflow);
+
+        let out: Box<BoundExpr<'a> + 'b> = match lhs.dtype {
+            Type::UINT32 =>
+                Box::new(ArithmeticBound::<UInt32>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            Type::UINT64 =>
+                Box::new(ArithmeticBound::<UInt64>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            Type::INT32 =>
+                Box::new(ArithmeticBound::<Int32>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            Type::INT64 =>
+                Box::new(ArithmeticBound::<Int64>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            Type::FLOAT32 =>
+                Box::new(ArithmeticBound::<Float32>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            Type::FLOAT64 =>
+                Box::new(ArithmeticBound::<Float64>{alloc: alloc, schema: schema, op: op, overflow: overflow, pt: PhantomData}),
+            dtype =>
+                return Err(DBError::ExpressionInputType(dtype.name().to_string())),
+        };
+
+        Ok(out)
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.lhs, &mut self.rhs]
+    }
+}
+
+impl<'alloc, T: ValueInfo> BoundExpr<'alloc> for ArithmeticBound<'alloc, T>
+    where T::Store: CheckedArith
+{
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let (lcol, rcol) = (view.column(0).unwrap(), view.column(1).unwrap());
+        let (lhs, rhs) = (column_row_data::<T>(lcol)?, column_row_data::<T>(rcol)?);
+        let (lnullable, rnullable) = (lcol.attribute().nullable, rcol.attribute().nullable);
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        // NULL if either input is, a word at a time
+        let mut input_nulls = vec![0u8; bytes_for(rows)];
+        {
+            let mut bits = MutBitmap::new(&mut input_nulls, rows);
+            if lnullable {
+                bits.copy_from(0, &lhs.nulls, 0, rows);
+            }
+            if rnullable {
+                bits.or(&rhs.nulls.slice(0, rows));
+            }
+        }
+
+        {
+            let col = out.column_mut(0).unwrap();
+            let mut nulls = Vec::new();
+
+            {
+                let values = col.rows_mut::<T>()?;
+                for row in bits_to_indices(&input_nulls, 0, rows, false) {
+                    match math::apply(self.op, self.overflow, lhs.values[row], rhs.values[row])? {
+                        Some(v) => values[row] = v,
+                        None    => nulls.push(row),
+                    }
+                }
+            }
+
+            if col.attribute().nullable {
+                let mut bitmap = col.nulls_mut()?;
+                bitmap.copy_from(0, &Bitmap::new(&input_nulls, 0, rows), 0, rows);
+                for row in nulls {
+                    bitmap.set(row, true);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::util::copy_value::set_column_value;
+
+    fn make_block(values: &[(Option<i32>, i32)]) -> Block<'static> {
+        let schema = Schema::parse_ddl("a INT32, b INT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(values.len()).unwrap();
+        for (row, &(a, b)) in values.iter().enumerate() {
+            set_column_value(&mut block, 0, row, &a).unwrap();
+            set_column_value(&mut block, 1, row, &b).unwrap();
+        }
+        block
+    }
+
+    fn eval(block: &Block<'static>, op: ArithOp, overflow: OverflowPolicy) -> Result<Vec<Option<i32>>, DBError> {
+        let expr = Arithmetic::new(op, TestInput, TestInput).with_overflow(overflow);
+        let bound = expr.bind(&allocator::GLOBAL, block.schema())?;
+        let out = bound.evaluate(block, block.rows())?;
+        let rows = column_row_data::<Int32>(&out[0])?;
+        Ok((0 .. block.rows()).map(|r| if rows.nulls.get(r) { None } else { Some(rows.values[r]) }).collect())
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let block = make_block(&[(Some(2), 3), (None, 1), (Some(::std::i32::MAX), 1)]);
+
+        assert!(eval(&block, ArithOp::ADD, OverflowPolicy::ERROR).is_err());
+        assert_eq!(eval(&block, ArithOp::ADD, OverflowPolicy::NULL).unwrap(), vec![Some(5), None, None]);
+        assert_eq!(eval(&block, ArithOp::ADD, OverflowPolicy::WRAP).unwrap(),
+                   vec![Some(5), None, Some(::std::i32::MIN)]);
+        assert_eq!(eval(&block, ArithOp::ADD, OverflowPolicy::SATURATE).unwrap(),
+                   vec![Some(5), None, Some(::std::i32::MAX)]);
+        assert_eq!(eval(&block, ArithOp::SUB, OverflowPolicy::ERROR).unwrap(),
+                   vec![Some(-1), None, Some(::std::i32::MAX - 1)]);
+        assert_eq!(eval(&block, ArithOp::MUL, OverflowPolicy::ERROR).unwrap()[0], Some(6));
+
+        let schema = Schema::parse_ddl("a INT32, b INT64").unwrap();
+        assert!(Arithmetic::new(ArithOp::ADD, TestInput, TestInput).bind(&allocator::GLOBAL, &schema).is_err());
+    }
+
+    #[test]
+    fn helpers() {
+        assert_eq!(200u32.saturating(ArithOp::SUB, 300), 0);
+        assert_eq!(::std::i64::MIN.saturating(ArithOp::MUL, 2), ::std::i64::MIN);
+        assert_eq!(::std::f64::MAX.checked(ArithOp::MUL, 2.0), None);
+        assert_eq!(::std::f64::MAX.saturating(ArithOp::ADD, ::std::f64::MAX), ::std::f64::MAX);
+        assert_eq!(::std::f32::INFINITY.checked(ArithOp::ADD, 1.0), Some(::std::f32::INFINITY));
+        assert!(math::apply(ArithOp::ADD, OverflowPolicy::ERROR, 1u64, ::std::u64::MAX).is_err());
+    }
+}
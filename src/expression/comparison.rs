@@ -1,9 +1,46 @@
-use std::cmp::Eq;
+use std::cmp::{Eq, Ordering};
 use std::marker::PhantomData;
 
 use ::expression::*;
 use ::error::DBError;
-use ::types::ValueInfo;
+use ::types::{Value, ValueInfo};
+use ::util::collation::Collator;
+
+/// Comparison operators
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompareOp {
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+}
+
+impl CompareOp {
+    /// `lhs op rhs`. False for values that can't be compared.
+    pub fn eval<T: PartialOrd>(self, lhs: &T, rhs: &T) -> bool {
+        match self {
+            CompareOp::EQ   => lhs == rhs,
+            CompareOp::NE   => lhs != rhs,
+            CompareOp::LT   => lhs < rhs,
+            CompareOp::LE   => lhs <= rhs,
+            CompareOp::GT   => lhs > rhs,
+            CompareOp::GE   => lhs >= rhs,
+        }
+    }
+
+    /// `eval` with TEXT (and JSON) values compared under the collation
+    pub fn eval_collated(self, collator: &Collator, lhs: &Value, rhs: &Value) -> bool {
+        match (lhs, rhs) {
+            (&Value::TEXT(ref l), &Value::TEXT(ref r)) | (&Value::JSON(ref l), &Value::JSON(ref r))
+                if !collator.is_binary() =>
+                self.eval(&collator.compare(l.as_bytes(), r.as_bytes()), &Ordering::Equal),
+            _ =>
+                self.eval(lhs, rhs),
+        }
+    }
+}
 
 pub struct EqaulsExpr<'a> {
     pub lhs: Box<Expr<'a> + 'a>,
@@ -18,7 +55,7 @@ struct EqualsBound<'a, T: 'a + ValueInfo> {
 
 impl<'a> EqaulsExpr<'a> {
     pub fn new<T: Expr<'a> + 'a>(lhs: T, rhs: T) -> EqaulsExpr<'a> {
-        EqaulsExpr { lhs: box lhs, rhs: box rhs }
+        EqaulsExpr { lhs: Box::new(lhs), rhs: Box::new(rhs) }
     }
 }
 
@@ -28,13 +65,23 @@ impl<'b> Expr<'b> for EqaulsExpr<'b> {
     {
         Err(DBError::Unknown)
     }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.lhs, &*self.rhs]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.lhs, &mut self.rhs]
+    }
 }
 
 impl<'alloc, T: ValueInfo, V: Eq> BoundExpr<'alloc> for EqualsBound<'alloc, T>
     where T: ValueInfo<Store=V>
 {
-    default fn schema(&self) -> &Schema {
-        &self.schema
+    specializable! {
+        fn schema(&self) -> &Schema {
+            &self.schema
+        }
     }
 
     fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
@@ -2,13 +2,15 @@ use std::marker::PhantomData;
 use std::string::ToString;
 
 use ::allocator::Allocator;
-use ::block::{Block, View, column_row_data};
+use ::block::{Block, View, column_row_data, column_value};
 use ::error::DBError;
 use ::expression::*;
 use ::row::RowOffset;
 use ::schema::Schema;
 use ::types::*;
-use ::util::copy_value::ValueSetter;
+use ::util::copy_value::{ValueSetter, set_column_value};
+use ::util::datetime;
+use ::util::json;
 
 pub struct CastExpr<'b> {
     pub to: Type,
@@ -25,11 +27,69 @@ struct ToStrBound<'alloc, T> {
     pt: PhantomData<T>,
 }
 
+/// Row at a time cast of the input column, see `cast_value`
+struct CastBound<'alloc> {
+    alloc: &'alloc Allocator,
+    from: Type,
+    schema: Schema,
+}
+
+/// Value cast to the `to` type, None if it can't be converted. NULLs stay NULL, TEXT cast to JSON
+/// has to be a valid document. TIMESTAMP values are taken to be in UTC when cast to and from
+/// TIMESTAMP_TZ.
+fn cast_value<'v>(value: Value<'v>, to: Type) -> Option<Value<'v>> {
+    match (value, to) {
+        (Value::NULL, _)                        => Some(Value::NULL),
+        (v, to) if v.dtype() == Some(to)        => Some(v),
+        (Value::JSON(v), Type::TEXT)            => Some(Value::TEXT(v)),
+        (Value::TEXT(v), Type::JSON)            => json::parse(&v).ok().map(|_| Value::JSON(v)),
+        (Value::TEXT(v), Type::TIMESTAMP)       => datetime::parse_timestamp(&v).map(Value::TIMESTAMP),
+        (Value::TEXT(v), Type::TIMESTAMP_TZ)    => datetime::parse_timestamp_tz(&v).map(Value::TIMESTAMP_TZ),
+        (Value::TIMESTAMP(v), Type::TIMESTAMP_TZ) => Some(Value::TIMESTAMP_TZ(v)),
+        (Value::TIMESTAMP_TZ(v), Type::TIMESTAMP) => Some(Value::TIMESTAMP(v)),
+        (v @ Value::TIMESTAMP(_), Type::TEXT)   |
+        (v @ Value::TIMESTAMP_TZ(_), Type::TEXT) => Some(Value::TEXT(v.to_string().into())),
+        _                                       => None,
+    }
+}
+
+/// `cast_value` supports casts from `from` to `to`
+fn can_cast(from: Type, to: Type) -> bool {
+    match (from, to) {
+        (Type::TEXT, Type::JSON) | (Type::JSON, Type::TEXT) => true,
+        (Type::TEXT, Type::TIMESTAMP) | (Type::TIMESTAMP, Type::TEXT) => true,
+        (Type::TEXT, Type::TIMESTAMP_TZ) | (Type::TIMESTAMP_TZ, Type::TEXT) => true,
+        (Type::TIMESTAMP, Type::TIMESTAMP_TZ) | (Type::TIMESTAMP_TZ, Type::TIMESTAMP) => true,
+        (from, to)                                          => from == to,
+    }
+}
+
 impl<'b> Expr<'b> for CastExpr<'b> {
     fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
         -> Result<Box<BoundExpr<'a> + 'b>, DBError>
     {
-        unimplemented!()
+        if input_schema.count() != 1 {
+            return Err(DBError::ExpressionInputCount(format!("{} != 1", input_schema.count())))
+        }
+
+        let attr = input_schema.get(0)?;
+        if !can_cast(attr.dtype, self.to) {
+            return Err(DBError::Unsupported(format!("cast from {} to {}", attr.dtype.name(), self.to.name())))
+        }
+
+        Ok(Box::new(CastBound { alloc: alloc, from: attr.dtype, schema: Schema::from_attr(attr.cast(self.to)) }))
+    }
+
+    fn describe(&self) -> String {
+        format!("CAST({} AS {})", self.input.describe(), self.to.name())
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
     }
 }
 
@@ -37,14 +97,14 @@ impl<'a> CastExpr<'a> {
     pub fn new<T: Expr<'a> + 'a>(to: Type, input: T) -> CastExpr<'a> {
         CastExpr {
             to: to,
-            input: box input,
+            input: Box::new(input),
         }
     }
 }
 
 impl<'a> ToStr<'a> {
     pub fn new<T: Expr<'a> + 'a>(to: Type, input: T) -> ToStr<'a> {
-        ToStr { input: box input }
+        ToStr { input: Box::new(input) }
     }
 }
 
@@ -61,45 +121,47 @@ impl<'b> Expr<'b> for ToStr<'b> {
 
         let out: Box<BoundExpr<'a> + 'a> = match input_schema.get(0)?.dtype {
             Type::UINT32 =>
-                box ToStrBound::<UInt32>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<UInt32>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::UINT64 =>
-                box ToStrBound::<UInt64>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<UInt64>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::INT32 =>
-                box ToStrBound::<Int32>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<Int32>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::INT64 =>
-                box ToStrBound::<Int64>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<Int64>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::FLOAT32 =>
-                box ToStrBound::<Float32>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<Float32>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::FLOAT64 =>
-                box ToStrBound::<Float64>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                Box::new(ToStrBound::<Float64>{alloc: alloc, schema: out_schema, pt: PhantomData}),
             Type::BOOLEAN =>
-                box ToStrBound::<Float32>{alloc: alloc, schema: out_schema, pt: PhantomData},
-            Type::TEXT =>
-                // TODO: Just copy
-                unimplemented!(),
+                Box::new(ToStrBound::<Float32>{alloc: alloc, schema: out_schema, pt: PhantomData}),
+            Type::TEXT | Type::JSON | Type::TIMESTAMP | Type::TIMESTAMP_TZ =>
+                Box::new(CastBound { alloc: alloc, from: input_schema.get(0)?.dtype, schema: out_schema }),
             Type::BLOB =>
-                box ToStrBound::<Blob>{alloc: alloc, schema: out_schema, pt: PhantomData},
+                return Err(DBError::Unsupported("BLOB to TEXT".to_string())),
+            Type::LIST | Type::STRUCT | Type::MAP =>
+                return Err(DBError::ExpressionInputType(input_schema.get(0)?.dtype.name().to_string())),
         };
 
         Ok(out)
     }
-}
 
-impl<'alloc> BoundExpr<'alloc> for ToStrBound<'alloc, Blob>
-{
-    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
-        unimplemented!()
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
     }
 }
 
 impl<'alloc, T: ValueInfo, V: ToString> BoundExpr<'alloc> for ToStrBound<'alloc, T>
     where T: ValueInfo<Store=V>
 {
-    default fn schema(&self) -> &Schema {
+    fn schema(&self) -> &Schema {
         &self.schema
     }
 
-    default fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
         let mut out = Block::new(self.alloc, &self.schema);
         out.add_rows(rows)?;
 
@@ -121,7 +183,7 @@ impl<'alloc, T: ValueInfo, V: ToString> BoundExpr<'alloc> for ToStrBound<'alloc,
 
                 // TODO: Make sure we're not bounds checking
                 for idx in 0 .. rows {
-                    if src_rows.nulls[idx] != 0 {
+                    if src_rows.nulls.get(idx) {
                         NULL_VALUE.set_row(col, idx);
                     } else {
                         src_rows.values[idx].to_string()
@@ -135,3 +197,109 @@ impl<'alloc, T: ValueInfo, V: ToString> BoundExpr<'alloc> for ToStrBound<'alloc,
     }
 }
 
+
+impl<'alloc> BoundExpr<'alloc> for CastBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let attr = &self.schema[0];
+        let src = view.column(0).ok_or(DBError::make_column_unknown_pos(0))?;
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        for row in 0 .. rows {
+            let value = cast_value(column_value(src, row)?, attr.dtype)
+                .ok_or_else(|| DBError::Cast { column: attr.name.clone(), row: row, from: self.from, to: attr.dtype })?;
+            set_column_value(&mut out, 0, row, &value)?;
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+
+    fn text_block(values: &[Option<&str>]) -> Block<'static> {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("doc", true, Type::TEXT));
+        block.add_rows(values.len()).unwrap();
+        for (row, v) in values.iter().enumerate() {
+            set_column_value(&mut block, 0, row, v).unwrap();
+        }
+        block
+    }
+
+    fn values(block: &Block) -> Vec<Value<'static>> {
+        (0 .. block.rows()).map(|row| column_value(block.column(0).unwrap(), row).unwrap().into_owned()).collect()
+    }
+
+    #[test]
+    fn text_json() {
+        let input = text_block(&[Some("{\"a\": 1}"), None, Some("[1, 2]")]);
+
+        let to_json = CastExpr::new(Type::JSON, TestInput).bind(&allocator::GLOBAL, input.schema()).unwrap();
+        assert_eq!(to_json.schema().get(0).unwrap().dtype, Type::JSON);
+        let docs = to_json.evaluate(&input, 3).unwrap();
+        assert_eq!(values(&docs), vec![Value::JSON("{\"a\": 1}".into()), Value::NULL, Value::JSON("[1, 2]".into())]);
+
+        let to_text = CastExpr::new(Type::TEXT, TestInput).bind(&allocator::GLOBAL, docs.schema()).unwrap();
+        assert_eq!(values(&to_text.evaluate(&docs, 3).unwrap()), values(&input));
+
+        let to_str = ToStr::new(Type::TEXT, TestInput).bind(&allocator::GLOBAL, docs.schema()).unwrap();
+        assert_eq!(values(&to_str.evaluate(&docs, 3).unwrap()), values(&input));
+
+        // Only valid documents cast to JSON
+        let bad = text_block(&[Some("[1, 2]"), Some("{oops")]);
+        match to_json.evaluate(&bad, 2) {
+            Err(DBError::Cast { row: 1, from: Type::TEXT, to: Type::JSON, .. })    => (),
+            r                                                                       => panic!("{:?}", r.err()),
+        }
+
+        match CastExpr::new(Type::UINT32, TestInput).bind(&allocator::GLOBAL, input.schema()) {
+            Err(DBError::Unsupported(_))    => (),
+            _                               => panic!("Expected unsupported cast"),
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let input = text_block(&[Some("2024-02-29 13:45:00"), None, Some("2024-02-29 13:45:00.5Z")]);
+
+        // Naive TIMESTAMPs have no UTC offset
+        let to_ts = CastExpr::new(Type::TIMESTAMP, TestInput).bind(&allocator::GLOBAL, input.schema()).unwrap();
+        match to_ts.evaluate(&input, 3) {
+            Err(DBError::Cast { row: 2, from: Type::TEXT, to: Type::TIMESTAMP, .. })    => (),
+            r                                                                           => panic!("{:?}", r.err()),
+        }
+        let ts = to_ts.evaluate(&input, 2).unwrap();
+        assert_eq!(values(&ts), vec![Value::TIMESTAMP(1709214300000000), Value::NULL]);
+
+        let to_tz = CastExpr::new(Type::TIMESTAMP_TZ, TestInput).bind(&allocator::GLOBAL, input.schema()).unwrap();
+        let tz = to_tz.evaluate(&input, 3).unwrap();
+        assert_eq!(values(&tz), vec![Value::TIMESTAMP_TZ(1709214300000000), Value::NULL,
+                                     Value::TIMESTAMP_TZ(1709214300500000)]);
+
+        // TIMESTAMP <-> TIMESTAMP_TZ in UTC
+        let tz_ts = CastExpr::new(Type::TIMESTAMP, TestInput).bind(&allocator::GLOBAL, tz.schema()).unwrap();
+        assert_eq!(values(&tz_ts.evaluate(&tz, 3).unwrap()), vec![Value::TIMESTAMP(1709214300000000), Value::NULL,
+                                                                   Value::TIMESTAMP(1709214300500000)]);
+        let ts_tz = CastExpr::new(Type::TIMESTAMP_TZ, TestInput).bind(&allocator::GLOBAL, ts.schema()).unwrap();
+        assert_eq!(values(&ts_tz.evaluate(&ts, 2).unwrap()), vec![Value::TIMESTAMP_TZ(1709214300000000), Value::NULL]);
+
+        let to_text = CastExpr::new(Type::TEXT, TestInput).bind(&allocator::GLOBAL, tz.schema()).unwrap();
+        assert_eq!(values(&to_text.evaluate(&tz, 3).unwrap()),
+                   vec![Value::TEXT("2024-02-29 13:45:00+00:00".into()), Value::NULL,
+                        Value::TEXT("2024-02-29 13:45:00.5+00:00".into())]);
+        let to_str = ToStr::new(Type::TEXT, TestInput).bind(&allocator::GLOBAL, ts.schema()).unwrap();
+        assert_eq!(values(&to_str.evaluate(&ts, 2).unwrap()), vec![Value::TEXT("2024-02-29 13:45:00".into()), Value::NULL]);
+
+        // Text form of a TIMESTAMP_TZ casts back to the same value
+        let back = to_tz.evaluate(&to_text.evaluate(&tz, 3).unwrap(), 3).unwrap();
+        assert_eq!(values(&back), values(&tz));
+    }
+}
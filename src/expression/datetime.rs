@@ -0,0 +1,391 @@
+//! Date and time functions. TIMESTAMP_TZ values are handled in UTC; TEXT inputs are parsed as
+//! ISO-8601 TIMESTAMPs (without a UTC offset).
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::*;
+use ::expression::literal::Literal;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::copy_value::set_column_value;
+use ::util::datetime::{self, DatePart, Interval};
+
+/// `DATE_TRUNC(part, input)`: the timestamp truncated to the start of its year, month, day, ...
+pub struct DateTrunc<'b> {
+    pub part: DatePart,
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+/// `EXTRACT(part FROM input)` as an INT64
+pub struct Extract<'b> {
+    pub part: DatePart,
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+/// `input + interval`; subtraction is adding the negated interval
+pub struct DateAdd<'b> {
+    pub interval: Interval,
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+/// `DATEDIFF(part, start, end)`: number of `part` boundaries from start to end as an INT64. Both
+/// inputs have to be of the same type.
+pub struct DateDiff<'b> {
+    pub part: DatePart,
+    pub start: Box<Expr<'b> + 'b>,
+    pub end: Box<Expr<'b> + 'b>,
+}
+
+/// `NOW()`: TIMESTAMP_TZ of when the expression is bound, the same for every row of the query
+pub struct Now;
+
+#[derive(Clone, Copy)]
+enum DateFunc {
+    TRUNC(DatePart),
+    EXTRACT(DatePart),
+    ADD(Interval),
+    DIFF(DatePart),
+}
+
+/// Row at a time evaluation of a `DateFunc`. NULL inputs produce NULL.
+struct DateFuncBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    func: DateFunc,
+}
+
+impl<'a> DateTrunc<'a> {
+    pub fn new<T: Expr<'a> + 'a>(part: DatePart, input: T) -> DateTrunc<'a> {
+        DateTrunc { part: part, input: Box::new(input) }
+    }
+}
+
+impl<'a> Extract<'a> {
+    pub fn new<T: Expr<'a> + 'a>(part: DatePart, input: T) -> Extract<'a> {
+        Extract { part: part, input: Box::new(input) }
+    }
+}
+
+impl<'a> DateAdd<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, interval: Interval) -> DateAdd<'a> {
+        DateAdd { interval: interval, input: Box::new(input) }
+    }
+
+    /// `input - interval`
+    pub fn sub<T: Expr<'a> + 'a>(input: T, interval: Interval) -> Result<DateAdd<'a>, DBError> {
+        let negated = interval.negate().ok_or_else(|| DBError::Overflow(format!("-({})", interval)))?;
+        Ok(DateAdd::new(input, negated))
+    }
+}
+
+impl<'a> DateDiff<'a> {
+    pub fn new<S: Expr<'a> + 'a, E: Expr<'a> + 'a>(part: DatePart, start: S, end: E) -> DateDiff<'a> {
+        DateDiff { part: part, start: Box::new(start), end: Box::new(end) }
+    }
+}
+
+/// Timestamp type of a date function input; TEXT is parsed as TIMESTAMP
+fn temporal_type(attr: &Attribute) -> Result<Type, DBError> {
+    match attr.dtype {
+        Type::TIMESTAMP | Type::TIMESTAMP_TZ    => Ok(attr.dtype),
+        Type::TEXT                              => Ok(Type::TIMESTAMP),
+        dtype                                   => Err(DBError::ExpressionInputType(dtype.name().to_string())),
+    }
+}
+
+/// Single temporal input attribute
+fn temporal_input(input_schema: &Schema) -> Result<&Attribute, DBError> {
+    if input_schema.count() != 1 {
+        return Err(DBError::ExpressionInputCount(format!("{} != 1", input_schema.count())))
+    }
+
+    let attr = input_schema.get(0)?;
+    temporal_type(attr)?;
+    Ok(attr)
+}
+
+fn bound<'a>(alloc: &'a Allocator, attr: Attribute, func: DateFunc) -> Box<BoundExpr<'a> + 'a> {
+    Box::new(DateFuncBound { alloc: alloc, schema: Schema::from_attr(attr), func: func })
+}
+
+impl<'b> Expr<'b> for DateTrunc<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let attr = temporal_input(input_schema)?;
+        Ok(bound(alloc, attr.cast(temporal_type(attr)?), DateFunc::TRUNC(self.part)))
+    }
+
+    fn describe(&self) -> String {
+        format!("DATE_TRUNC('{}', {})", self.part.name(), self.input.describe())
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for Extract<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let attr = temporal_input(input_schema)?;
+        Ok(bound(alloc, attr.cast(Type::INT64), DateFunc::EXTRACT(self.part)))
+    }
+
+    fn describe(&self) -> String {
+        format!("EXTRACT({} FROM {})", self.part.name(), self.input.describe())
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for DateAdd<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let attr = temporal_input(input_schema)?;
+        Ok(bound(alloc, attr.cast(temporal_type(attr)?), DateFunc::ADD(self.interval)))
+    }
+
+    fn describe(&self) -> String {
+        format!("{} + INTERVAL '{}'", self.input.describe(), self.interval)
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for DateDiff<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        if input_schema.count() != 2 {
+            return Err(DBError::ExpressionInputCount(format!("{} != 2", input_schema.count())))
+        }
+
+        let (start, end) = (input_schema.get(0)?, input_schema.get(1)?);
+        if temporal_type(start)? != temporal_type(end)? {
+            return Err(DBError::ExpressionInputType(format!("DATEDIFF({}, {})", start.dtype.name(), end.dtype.name())))
+        }
+
+        let name = format!("DATEDIFF({}, {})", start.name, end.name);
+        let attr = Attribute::new(name, start.nullable || end.nullable, Type::INT64);
+        Ok(bound(alloc, attr, DateFunc::DIFF(self.part)))
+    }
+
+    fn describe(&self) -> String {
+        format!("DATEDIFF('{}', {}, {})", self.part.name(), self.start.describe(), self.end.describe())
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.start, &*self.end]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.start, &mut self.end]
+    }
+}
+
+impl<'b> Expr<'b> for Now {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let micros = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since)   => since.as_secs() as i64 * datetime::MICROS_PER_SECOND + since.subsec_micros() as i64,
+            Err(before) => {
+                let until = before.duration();
+                -(until.as_secs() as i64 * datetime::MICROS_PER_SECOND + until.subsec_micros() as i64)
+            }
+        };
+
+        Literal::new("now", Value::TIMESTAMP_TZ(micros)).bind(alloc, input_schema)
+    }
+
+    fn describe(&self) -> String {
+        "NOW()".to_string()
+    }
+}
+
+impl<'alloc> DateFuncBound<'alloc> {
+    /// Microseconds of the input column's row, None for NULL
+    fn input<'v>(&self, view: &'v View<'v>, pos: usize, row: RowOffset) -> Result<Option<i64>, DBError> {
+        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        match column_value(col, row)? {
+            Value::NULL             => Ok(None),
+            Value::TEXT(ref text)   => datetime::parse_timestamp(text).map(Some).ok_or_else(|| DBError::Cast {
+                column: col.attribute().name.clone(), row: row, from: Type::TEXT, to: Type::TIMESTAMP }),
+            value                   => value.as_timestamp().map(Some)
+                .ok_or_else(|| DBError::ExpressionInputType(col.attribute().dtype.name().to_string())),
+        }
+    }
+
+    fn apply(&self, args: &[i64]) -> Result<Value<'static>, DBError> {
+        let overflow = || DBError::Overflow(format!("{} out of range", self.schema[0].name));
+        let out_type = self.schema[0].dtype;
+
+        let value = match self.func {
+            DateFunc::TRUNC(part)       => datetime::trunc(args[0], part).ok_or_else(overflow)?,
+            DateFunc::EXTRACT(part)     => return Ok(Value::INT64(datetime::extract(args[0], part))),
+            DateFunc::ADD(interval)     => interval.add_to(args[0]).ok_or_else(overflow)?,
+            DateFunc::DIFF(part)        =>
+                return Ok(Value::INT64(datetime::diff(args[0], args[1], part).ok_or_else(overflow)?)),
+        };
+
+        Ok(if out_type == Type::TIMESTAMP_TZ { Value::TIMESTAMP_TZ(value) } else { Value::TIMESTAMP(value) })
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for DateFuncBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let inputs = match self.func { DateFunc::DIFF(_) => 2, _ => 1 };
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let mut args = [0i64; 2];
+        for row in 0 .. rows {
+            let mut null = false;
+            for pos in 0 .. inputs {
+                match self.input(view, pos, row)? {
+                    Some(v) => args[pos] = v,
+                    None    => null = true,
+                }
+            }
+
+            let value = if null { Value::NULL } else { self.apply(&args[.. inputs])? };
+            set_column_value(&mut out, 0, row, &value)?;
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+
+    /// Columns of `dtypes`, each row of `values` in each column
+    fn make_block(dtypes: &[Type], values: &[Option<&str>]) -> Block<'static> {
+        let attrs = dtypes.iter().enumerate().map(|(pos, t)| Attribute::new(format!("c{}", pos), true, *t)).collect();
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(values.len()).unwrap();
+
+        for (row, v) in values.iter().enumerate() {
+            for (pos, dtype) in dtypes.iter().enumerate() {
+                let value = match (*v, *dtype) {
+                    (None, _)                   => Value::NULL,
+                    (Some(v), Type::TEXT)       => Value::from(v),
+                    (Some(v), Type::TIMESTAMP)  => Value::TIMESTAMP(datetime::parse_timestamp(v).unwrap()),
+                    (Some(v), _)                => Value::TIMESTAMP_TZ(datetime::parse_timestamp_tz(v).unwrap()),
+                };
+                set_column_value(&mut block, pos, row, &value).unwrap();
+            }
+        }
+        block
+    }
+
+    fn eval<'e>(expr: &Expr<'e>, block: &Block<'static>) -> Result<Vec<String>, DBError> {
+        let bound = expr.bind(&allocator::GLOBAL, block.schema())?;
+        let out = bound.evaluate(block, block.rows())?;
+        Ok((0 .. out.rows()).map(|row| column_value(&out[0], row).unwrap().to_string()).collect())
+    }
+
+    #[test]
+    fn trunc_extract() {
+        let values = [Some("2024-02-29 13:45:07.25"), None, Some("1969-12-31 23:59:59")];
+        let (ts, tz, text) = (make_block(&[Type::TIMESTAMP], &values), make_block(&[Type::TIMESTAMP_TZ], &values),
+                              make_block(&[Type::TEXT], &values));
+
+        let trunc = DateTrunc::new(DatePart::MONTH, TestInput);
+        assert_eq!(eval(&trunc, &ts).unwrap(), vec!["2024-02-01 00:00:00", "NULL", "1969-12-01 00:00:00"]);
+        assert_eq!(eval(&trunc, &tz).unwrap()[0], "2024-02-01 00:00:00+00:00");
+        assert_eq!(eval(&trunc, &text).unwrap(), eval(&trunc, &ts).unwrap());
+        assert_eq!(trunc.describe(), "DATE_TRUNC('MONTH', <expr>)");
+
+        let hour = Extract::new(DatePart::HOUR, TestInput);
+        assert_eq!(eval(&hour, &ts).unwrap(), vec!["13", "NULL", "23"]);
+        assert_eq!(eval(&Extract::new(DatePart::YEAR, TestInput), &text).unwrap(), vec!["2024", "NULL", "1969"]);
+
+        // Unparsable TEXT, non temporal inputs
+        let bad = make_block(&[Type::TEXT], &[Some("2024-02-29"), Some("2024-02-30")]);
+        match eval(&hour, &bad) {
+            Err(DBError::Cast { row: 1, from: Type::TEXT, to: Type::TIMESTAMP, .. })    => (),
+            r                                                                           => panic!("{:?}", r),
+        }
+        assert!(hour.bind(&allocator::GLOBAL, &Schema::make_one_attr("i", false, Type::INT64)).is_err());
+        assert!(hour.bind(&allocator::GLOBAL, make_block(&[Type::TEXT, Type::TEXT], &[]).schema()).is_err());
+    }
+
+    #[test]
+    fn intervals() {
+        let values = [Some("2023-01-31 10:00"), None];
+        let (ts, tz) = (make_block(&[Type::TIMESTAMP], &values), make_block(&[Type::TIMESTAMP_TZ], &values));
+
+        let add = DateAdd::new(TestInput, "1 month 2 hours".parse().unwrap());
+        assert_eq!(eval(&add, &ts).unwrap(), vec!["2023-02-28 12:00:00", "NULL"]);
+        assert_eq!(eval(&add, &tz).unwrap()[0], "2023-02-28 12:00:00+00:00");
+
+        let sub = DateAdd::sub(TestInput, "1 day".parse().unwrap()).unwrap();
+        assert_eq!(eval(&sub, &make_block(&[Type::TEXT], &values)).unwrap(), vec!["2023-01-30 10:00:00", "NULL"]);
+
+        match eval(&DateAdd::new(TestInput, Interval::new(0, ::std::i64::MAX)), &ts) {
+            Err(DBError::Overflow(_))   => (),
+            r                           => panic!("{:?}", r),
+        }
+        assert!(DateAdd::sub(TestInput, Interval::new(::std::i64::MIN, 0)).is_err());
+    }
+
+    #[test]
+    fn diff() {
+        let mut block = make_block(&[Type::TIMESTAMP, Type::TIMESTAMP], &[Some("2024-02-29 13:45"), None]);
+        set_column_value(&mut block, 1, 0, &Value::TIMESTAMP(datetime::parse_timestamp("2024-03-01").unwrap())).unwrap();
+
+        assert_eq!(eval(&DateDiff::new(DatePart::DAY, TestInput, TestInput), &block).unwrap(), vec!["1", "NULL"]);
+        assert_eq!(eval(&DateDiff::new(DatePart::MINUTE, TestInput, TestInput), &block).unwrap()[0], "615");
+
+        let mixed = make_block(&[Type::TIMESTAMP, Type::TIMESTAMP_TZ], &[]);
+        assert!(DateDiff::new(DatePart::DAY, TestInput, TestInput).bind(&allocator::GLOBAL, mixed.schema()).is_err());
+    }
+
+    #[test]
+    fn now() {
+        let block = make_block(&[Type::TIMESTAMP], &[None, None, None]);
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 * datetime::MICROS_PER_SECOND;
+
+        let now = Now.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        assert!(now.is_constant());
+        assert_eq!(now.schema().get(0).unwrap().dtype, Type::TIMESTAMP_TZ);
+
+        let ts = now.evaluate_constant().unwrap().as_timestamp().unwrap();
+        assert!(ts >= before && ts < before + 60 * datetime::MICROS_PER_SECOND);
+
+        // Same value for every row
+        let out = now.evaluate(&block, 3).unwrap();
+        assert!((0 .. 3).all(|row| column_value(&out[0], row).unwrap() == Value::TIMESTAMP_TZ(ts)));
+    }
+}
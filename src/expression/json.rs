@@ -0,0 +1,355 @@
+
+use ::allocator::Allocator;
+use ::block::{Bitmap, Block, Column, RefColumn, View, column_row_data};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::copy_value::ValueSetter;
+use ::util::json::{self, JsonType, PathElem};
+
+/// Extract the value at a JSON path (eg. `$.a.b[0]`) as a JSON document. Produces NULL when the
+/// path does not exist.
+pub struct JsonExtract<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+    pub path: String,
+}
+
+/// Name of the JSON value's type: null, boolean, number, string, array or object.
+pub struct JsonTypeOf<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+/// Convert JSON objects into STRUCT values. Fields are looked up by name; missing fields, JSON nulls
+/// and documents that aren't objects produce NULLs.
+pub struct JsonToStruct<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+    /// STRUCT attribute describing the output fields
+    pub to: Attribute,
+}
+
+struct JsonExtractBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    path: Vec<PathElem>,
+}
+
+struct JsonTypeOfBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+}
+
+struct JsonToStructBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    /// Path of each output field
+    fields: Vec<Vec<PathElem>>,
+}
+
+impl<'a> JsonExtract<'a> {
+    pub fn new<T: Expr<'a> + 'a, S: Into<String>>(input: T, path: S) -> JsonExtract<'a> {
+        JsonExtract { input: Box::new(input), path: path.into() }
+    }
+}
+
+impl<'a> JsonTypeOf<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T) -> JsonTypeOf<'a> {
+        JsonTypeOf { input: Box::new(input) }
+    }
+}
+
+impl<'a> JsonToStruct<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, to: Attribute) -> JsonToStruct<'a> {
+        JsonToStruct { input: Box::new(input), to: to }
+    }
+}
+
+/// Single JSON (or TEXT) input attribute
+fn json_input(input_schema: &Schema) -> Result<&Attribute, DBError> {
+    if input_schema.count() != 1 {
+        return Err(DBError::ExpressionInputCount(format!("{} != 1", input_schema.count())))
+    }
+
+    let attr = input_schema.get(0)?;
+    match attr.dtype {
+        Type::JSON | Type::TEXT => Ok(attr),
+        dtype                   => Err(DBError::ExpressionInputType(dtype.name().to_string())),
+    }
+}
+
+/// JSON document (values, nulls) of the input column
+fn json_docs<'c>(col: &'c RefColumn) -> Result<(&'c [RawData], Bitmap<'c>), DBError> {
+    if col.attribute().dtype == Type::TEXT {
+        column_row_data::<Text>(col).map(|r| (r.values, r.nulls))
+    } else {
+        column_row_data::<Json>(col).map(|r| (r.values, r.nulls))
+    }
+}
+
+/// Set a column value from the raw JSON text of a scalar value.
+fn set_json_value(col: &mut Column, row: RowOffset, raw: Option<&str>) -> Result<(), DBError> {
+    let raw = match raw {
+        Some(v) if json::type_of(v)? != JsonType::NULL  => v,
+        _                                               => return NULL_VALUE.set_row(col, row),
+    };
+
+    if col.attribute().nullable {
+        col.nulls_mut()?.set(row, false);
+    }
+
+    let (dtype, name) = (col.attribute().dtype, col.attribute().name.clone());
+    let bad = || DBError::Cast { column: name.clone(), row: row, from: Type::JSON, to: dtype };
+
+    match dtype {
+        Type::UINT32    => raw.parse::<u32>().map_err(|_| bad())?.set_row(col, row),
+        Type::UINT64    => raw.parse::<u64>().map_err(|_| bad())?.set_row(col, row),
+        Type::INT32     => raw.parse::<i32>().map_err(|_| bad())?.set_row(col, row),
+        Type::INT64     => raw.parse::<i64>().map_err(|_| bad())?.set_row(col, row),
+        Type::FLOAT32   => raw.parse::<f32>().map_err(|_| bad())?.set_row(col, row),
+        Type::FLOAT64   => raw.parse::<f64>().map_err(|_| bad())?.set_row(col, row),
+        Type::BOOLEAN   => match raw {
+            "true"  => true.set_row(col, row),
+            "false" => false.set_row(col, row),
+            _       => Err(bad()),
+        },
+        // Non string values keep their JSON representation
+        Type::TEXT if json::type_of(raw)? == JsonType::STRING =>
+            json::unquote(raw)?.set_row(col, row),
+        Type::TEXT | Type::JSON =>
+            raw.set_row(col, row),
+        _ =>
+            Err(DBError::AttributeType(col.attribute().name.clone())),
+    }
+}
+
+impl<'b> Expr<'b> for JsonExtract<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let mut out_attr = json_input(input_schema)?.cast(Type::JSON);
+        out_attr.nullable = true;
+
+        let path = json::parse_path(self.path.as_str())?;
+        Ok(Box::new(JsonExtractBound { alloc: alloc, schema: Schema::from_attr(out_attr), path: path }))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for JsonTypeOf<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let out_attr = json_input(input_schema)?.cast(Type::TEXT);
+        Ok(Box::new(JsonTypeOfBound { alloc: alloc, schema: Schema::from_attr(out_attr) }))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for JsonToStruct<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let input = json_input(input_schema)?;
+        if self.to.dtype != Type::STRUCT {
+            return Err(DBError::AttributeType(self.to.name.clone()))
+        }
+
+        let fields = self.to.children.iter()
+            .map(|f| vec![PathElem::KEY(f.name.clone())])
+            .collect();
+
+        let mut out_attr = self.to.rename(input.name.clone());
+        out_attr.nullable = true;
+
+        Ok(Box::new(JsonToStructBound { alloc: alloc, schema: Schema::from_attr(out_attr), fields: fields }))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for JsonExtractBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src = view.column(0).unwrap();
+        let (docs, nulls) = json_docs(src)?;
+        let src_nullable = src.attribute().nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                let value = if src_nullable && nulls.get(row) {
+                    None
+                } else {
+                    let doc: &str = docs[row].as_ref();
+                    json::extract(doc, &self.path)?
+                };
+
+                match value {
+                    Some(v) => { col.nulls_mut()?.set(row, false); v.set_row(col, row)? }
+                    None    => NULL_VALUE.set_row(col, row)?,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for JsonTypeOfBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src = view.column(0).unwrap();
+        let (docs, nulls) = json_docs(src)?;
+        let nullable = src.attribute().nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                if nullable && nulls.get(row) {
+                    NULL_VALUE.set_row(col, row)?;
+                    continue;
+                }
+
+                if nullable {
+                    col.nulls_mut()?.set(row, false);
+                }
+
+                let doc: &str = docs[row].as_ref();
+                json::type_of(doc)?.name().set_row(col, row)?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for JsonToStructBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src = view.column(0).unwrap();
+        let (docs, nulls) = json_docs(src)?;
+        let src_nullable = src.attribute().nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                let doc: Option<&str> = if src_nullable && nulls.get(row) {
+                    None
+                } else {
+                    Some(docs[row].as_ref())
+                };
+
+                let doc = match doc {
+                    Some(d) if json::type_of(d)? == JsonType::OBJECT    => d,
+                    _                                                   => {
+                        NULL_VALUE.set_row(col, row)?;
+                        continue;
+                    }
+                };
+
+                col.nulls_mut()?.set(row, false);
+
+                for (pos, path) in self.fields.iter().enumerate() {
+                    let raw = json::extract(doc, path)?;
+                    set_json_value(col.child_mut(pos).unwrap(), row, raw)?;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::View;
+
+    const DOC: &'static str = r#"{"id":7,"n":"x\"y","t":[1,2]}"#;
+
+    fn make_block() -> Block<'static> {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("doc", false, Type::JSON));
+        block.add_row().unwrap();
+        DOC.set_row(&mut block[0], 0).unwrap();
+        block
+    }
+
+    #[test]
+    fn extract_and_typeof() {
+        let block = make_block();
+
+        let bound = JsonExtract::new(TestInput, "$.t[1]").bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, 1).unwrap();
+        let rows = column_row_data::<Json>(&out[0]).unwrap();
+        assert_eq!(rows.values[0].to_string(), "2");
+
+        let bound = JsonTypeOf::new(TestInput).bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, 1).unwrap();
+        let rows = column_row_data::<Text>(&out[0]).unwrap();
+        assert_eq!(rows.values[0].to_string(), "object");
+    }
+
+    #[test]
+    fn to_struct() {
+        let block = make_block();
+
+        let to = Attribute::structure("s", false, vec![
+            Attribute::new("id", false, Type::INT64),
+            Attribute::new("n", false, Type::TEXT),
+            Attribute::new("missing", true, Type::BOOLEAN),
+            Attribute::new("t", false, Type::JSON),
+        ]).unwrap();
+
+        let bound = JsonToStruct::new(TestInput, to).bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, 1).unwrap();
+        let col = &out[0];
+
+        assert_eq!(column_row_data::<Int64>(col.child(0).unwrap()).unwrap().values[0], 7);
+        assert_eq!(column_row_data::<Text>(col.child(1).unwrap()).unwrap().values[0].to_string(), "x\"y");
+        assert_eq!(column_row_data::<Boolean>(col.child(2).unwrap()).unwrap().nulls.get(0), true);
+        assert_eq!(column_row_data::<Json>(col.child(3).unwrap()).unwrap().values[0].to_string(), "[1,2]");
+    }
+}
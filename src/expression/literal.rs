@@ -0,0 +1,117 @@
+
+use ::allocator::Allocator;
+use ::block::{Block, ConstColumn, View};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::copy_value::ValueSetter;
+
+/// Constant value expression
+pub struct Literal<'b> {
+    pub name: String,
+    pub value: Value<'b>,
+    /// Type of the value, required for NULL literals
+    pub dtype: Option<Type>,
+}
+
+struct LiteralBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    value: Value<'static>,
+}
+
+impl<'a> Literal<'a> {
+    pub fn new<S: Into<String>>(name: S, value: Value<'a>) -> Literal<'a> {
+        Literal { name: name.into(), value: value, dtype: None }
+    }
+
+    /// Typed NULL literal
+    pub fn null<S: Into<String>>(name: S, dtype: Type) -> Literal<'a> {
+        Literal { name: name.into(), value: Value::NULL, dtype: Some(dtype) }
+    }
+
+    fn attribute(&self) -> Result<Attribute, DBError> {
+        let dtype = self.dtype.or(self.value.dtype())
+            .ok_or(DBError::ExpressionInputType("NULL".to_string()))?;
+
+        // Nested literals would need the element / field attributes
+        if dtype.is_nested() {
+            return Err(DBError::ExpressionInputType(dtype.name().to_string()))
+        }
+
+        Ok(Attribute::new(self.name.clone(), self.value.is_null(), dtype))
+    }
+
+    /// Literal value repeated for `rows` rows, without materializing each row.
+    pub fn column<'alloc>(&self, alloc: &'alloc Allocator, rows: RowOffset)
+        -> Result<ConstColumn<'alloc>, DBError>
+    {
+        ConstColumn::new(alloc, self.attribute()?, &self.value, rows)
+    }
+}
+
+impl<'b> Expr<'b> for Literal<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, _: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let schema = Schema::from_attr(self.attribute()?);
+        Ok(Box::new(LiteralBound { alloc: alloc, schema: schema, value: self.value.clone().into_owned() }))
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for LiteralBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, _: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        for row in 0 .. rows {
+            self.value.set_row(&mut out[0], row)?;
+        }
+
+        Ok(out)
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+
+    fn evaluate_constant(&self) -> Result<Value<'alloc>, DBError> {
+        Ok(self.value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+
+    #[test]
+    fn literal() {
+        let schema = Schema::make_one_attr("unused", false, Type::UINT32);
+        let input = Block::new(&allocator::GLOBAL, &schema);
+
+        let bound = Literal::new("one", Value::UINT32(1)).bind(&allocator::GLOBAL, &schema).unwrap();
+        assert!(bound.is_constant());
+        assert_eq!(bound.evaluate_constant().unwrap(), Value::UINT32(1));
+
+        let out = bound.evaluate(&input, 3).unwrap();
+        assert_eq!(&column_row_data::<UInt32>(&out[0]).unwrap().values[0 .. 3], &[1, 1, 1]);
+
+        let bound = Literal::null("none", Type::INT32).bind(&allocator::GLOBAL, &schema).unwrap();
+        assert!(bound.schema().get(0).unwrap().nullable);
+
+        let col = Literal::new("s", Value::from("abc")).column(&allocator::GLOBAL, 5000).unwrap();
+        assert_eq!(col.value().unwrap(), Value::from("abc"));
+    }
+}
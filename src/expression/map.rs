@@ -0,0 +1,291 @@
+
+use std::slice;
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_row_data};
+use ::error::DBError;
+use ::expression::*;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::*;
+use ::util::copy_value::ValueSetter;
+
+/// Lookup the value of `key` in a MAP. Rows without the key (or NULL maps) produce NULL.
+pub struct MapGet<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+    pub key: Value<'b>,
+}
+
+/// LIST of MAP keys
+pub struct MapKeys<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+/// LIST of MAP values
+pub struct MapValues<'b> {
+    pub input: Box<Expr<'b> + 'b>,
+}
+
+struct MapGetBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    /// Single row block containing the lookup key
+    key: Block<'alloc>,
+}
+
+/// Extract one of the MAP child columns into a LIST
+struct MapChildBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    /// 0 for keys, 1 for values
+    child: usize,
+}
+
+impl<'a> MapGet<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T, key: Value<'a>) -> MapGet<'a> {
+        MapGet { input: Box::new(input), key: key }
+    }
+}
+
+impl<'a> MapKeys<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T) -> MapKeys<'a> {
+        MapKeys { input: Box::new(input) }
+    }
+}
+
+impl<'a> MapValues<'a> {
+    pub fn new<T: Expr<'a> + 'a>(input: T) -> MapValues<'a> {
+        MapValues { input: Box::new(input) }
+    }
+}
+
+/// Single MAP input attribute
+fn map_input(input_schema: &Schema) -> Result<&Attribute, DBError> {
+    if input_schema.count() != 1 {
+        return Err(DBError::ExpressionInputCount(format!("{} != 1", input_schema.count())))
+    }
+
+    let attr = input_schema.get(0)?;
+    if attr.dtype != Type::MAP {
+        return Err(DBError::ExpressionInputType(attr.dtype.name().to_string()))
+    }
+
+    Ok(attr)
+}
+
+fn bind_map_child<'a>(alloc: &'a Allocator, input_schema: &Schema, child: usize)
+    -> Result<MapChildBound<'a>, DBError>
+{
+    let attr = map_input(input_schema)?;
+    let elem = attr.children[child].clone();
+    let out_attr = Attribute::list(attr.name.clone(), attr.nullable, elem);
+
+    Ok(MapChildBound { alloc: alloc, schema: Schema::from_attr(out_attr), child: child })
+}
+
+impl<'b> Expr<'b> for MapGet<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let attr = map_input(input_schema)?;
+        let (key_attr, value_attr) = attr.map_entry()?;
+
+        let mut key = Block::new(alloc, &Schema::from_attr(key_attr.clone()));
+        key.add_row()?;
+        self.key.set_row(&mut key[0], 0)?;
+
+        let mut out_attr = value_attr.rename(attr.name.clone());
+        out_attr.nullable = true;
+
+        Ok(Box::new(MapGetBound { alloc: alloc, schema: Schema::from_attr(out_attr), key: key }))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for MapKeys<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        Ok(Box::new(bind_map_child(alloc, input_schema, 0)?))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+impl<'b> Expr<'b> for MapValues<'b> {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        Ok(Box::new(bind_map_child(alloc, input_schema, 1)?))
+    }
+
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        vec![&*self.input]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        vec![&mut self.input]
+    }
+}
+
+/// Compare a key column row with the (single row) lookup key column.
+///
+/// Fixed width keys are compared bitwise.
+fn key_equals(keys: &RefColumn, row: RowOffset, key: &RefColumn) -> bool {
+    let dtype = keys.attribute().dtype;
+
+    unsafe {
+        if dtype.is_varlen() {
+            let lhs = *(keys.rows_ptr() as *const RawData).offset(row as isize);
+            let rhs = *(key.rows_ptr() as *const RawData);
+            AsRef::<[u8]>::as_ref(&lhs) == AsRef::<[u8]>::as_ref(&rhs)
+        } else {
+            let size_of = dtype.size_of();
+            let lhs = slice::from_raw_parts(keys.rows_ptr().offset((row * size_of) as isize), size_of);
+            let rhs = slice::from_raw_parts(key.rows_ptr(), size_of);
+            lhs == rhs
+        }
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for MapGetBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src = view.column(0).unwrap();
+        let entries = column_row_data::<Map>(src)?;
+        let keys = src.child(0).unwrap();
+        let values = src.child(1).unwrap();
+        let key = &self.key[0];
+        let src_nullable = src.attribute().nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                let found = if src_nullable && entries.nulls.get(row) {
+                    None
+                } else {
+                    let entry = entries.values[row];
+                    (entry.offset .. entry.offset + entry.len)
+                        .find(|idx| key_equals(keys, *idx, key))
+                };
+
+                match found {
+                    Some(idx)   => col.copy_rows(row, values, idx, 1)?,
+                    None        => NULL_VALUE.set_row(col, row)?,
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for MapChildBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+
+        let src = view.column(0).unwrap();
+        let entries = column_row_data::<Map>(src)?;
+        let child = src.child(self.child).unwrap();
+        let src_nullable = src.attribute().nullable;
+
+        {
+            let col = out.column_mut(0).unwrap();
+
+            for row in 0 .. rows {
+                if src_nullable && entries.nulls.get(row) {
+                    col.list_append(row, 0)?;
+                    NULL_VALUE.set_row(col, row)?;
+                    continue;
+                }
+
+                let entry = entries.values[row];
+                let (elems, offset) = col.list_append(row, entry.len)?;
+                elems.copy_rows(offset, child, entry.offset, entry.len)?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::View;
+
+    fn make_block() -> Block<'static> {
+        let attr = Attribute::map("map", false,
+                                  Attribute::new("key", false, Type::UINT32),
+                                  Attribute::new("value", false, Type::INT64)).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_attr(attr));
+        block.add_rows(2).unwrap();
+
+        let pairs: [&[(u32, i64)]; 2] = [&[(10, 1), (20, 2)], &[(30, 3)]];
+        for row in 0 .. 2 {
+            let (keys, values, offset) = block[0].map_append(row, pairs[row].len()).unwrap();
+            for (idx, &(k, v)) in pairs[row].iter().enumerate() {
+                k.set_row(keys, offset + idx).unwrap();
+                v.set_row(values, offset + idx).unwrap();
+            }
+        }
+
+        block
+    }
+
+    #[test]
+    fn map_get() {
+        let block = make_block();
+        let expr = MapGet::new(TestInput, Value::UINT32(20));
+
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+
+        let rows = column_row_data::<Int64>(&out[0]).unwrap();
+        assert_eq!(rows.values[0], 2);
+        assert!(!rows.nulls.get(0));
+        assert!(rows.nulls.get(1));
+    }
+
+    #[test]
+    fn map_keys() {
+        let block = make_block();
+        let expr = MapKeys::new(TestInput);
+
+        let bound = expr.bind(&allocator::GLOBAL, block.schema()).unwrap();
+        let out = bound.evaluate(&block, block.rows()).unwrap();
+
+        let entries = column_row_data::<List>(&out[0]).unwrap();
+        assert_eq!((entries.values[1].offset, entries.values[1].len), (2, 1));
+
+        let keys = column_row_data::<UInt32>(out[0].child(0).unwrap()).unwrap();
+        assert_eq!(&keys.values[0 .. 3], &[10, 20, 30]);
+    }
+}
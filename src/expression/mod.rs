@@ -11,11 +11,35 @@ pub trait Expr<'b> {
     fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
                     -> Result<Box<BoundExpr<'a> + 'b>, DBError>;
 
+    /// Text of the expression, for EXPLAIN
+    fn describe(&self) -> String {
+        "<expr>".to_string()
+    }
+
     /// Expression can be evaluated without row data and the expression produces the same value on
     /// each invocation.
     fn is_constant(&self) -> bool {
         false
     }
+
+    /// Input expressions
+    fn inputs(&self) -> Vec<&(Expr<'b> + 'b)> {
+        Vec::new()
+    }
+
+    /// Input expressions, for replacing them (`rewrite::Rewriter`)
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Expr<'b> + 'b>> {
+        Vec::new()
+    }
+
+    /// Supply the values of the `Placeholder`s in the tree; call before `bind`. Can be called
+    /// again to re-bind the same expression with different parameters.
+    fn bind_params(&mut self, params: &[Value]) -> Result<(), DBError> {
+        for input in self.inputs_mut() {
+            input.bind_params(params)?;
+        }
+        Ok(())
+    }
 }
 
 /// Materialized expression. Input and output schema of the operation are know
@@ -34,10 +58,40 @@ pub trait BoundExpr<'alloc> {
     }
 
     fn evaluate_constant(&self) -> Result<Value<'alloc>, DBError> {
-        Err(DBError::ExpressionNotCost)
+        Err(DBError::ExpressionNotConstant)
     }
 }
 
+/// Stand-in input expression for tests; expressions are currently bound against the input schema
+/// directly.
+#[cfg(test)]
+pub struct TestInput;
+
+#[cfg(test)]
+impl<'b> Expr<'b> for TestInput {
+    fn bind<'a: 'b>(&self, _: &'a Allocator, _: &Schema) -> Result<Box<BoundExpr<'a> + 'b>, DBError> {
+        unimplemented!()
+    }
+}
+
+/// Impl items that type specific impls can override (`default`) with the `nightly` feature, plain
+/// items otherwise.
+#[cfg(feature = "nightly")]
+macro_rules! specializable {
+    ($($item:tt)*) => { default $($item)* }
+}
+
+#[cfg(not(feature = "nightly"))]
+macro_rules! specializable {
+    ($($item:tt)*) => { $($item)* }
+}
+
+pub mod arithmetic;
 pub mod convert;
 pub mod comparison;
+pub mod datetime;
+pub mod map;
+pub mod json;
+pub mod literal;
+pub mod placeholder;
 // pub mod internal;
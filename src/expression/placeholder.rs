@@ -0,0 +1,98 @@
+use ::allocator::Allocator;
+use ::error::DBError;
+use ::expression::*;
+use ::expression::literal::Literal;
+use ::schema::Schema;
+use ::types::*;
+
+/// Query parameter. The value is supplied by `Expr::bind_params` before the expression is bound,
+/// so the same expression tree can be bound and executed again with different parameters.
+pub struct Placeholder {
+    pub name: String,
+    /// Position in the parameters
+    pub index: usize,
+    pub dtype: Type,
+    value: Option<Value<'static>>,
+}
+
+impl Placeholder {
+    pub fn new<S: Into<String>>(name: S, index: usize, dtype: Type) -> Placeholder {
+        Placeholder { name: name.into(), index: index, dtype: dtype, value: None }
+    }
+
+    /// Value from the last `bind_params`
+    pub fn value(&self) -> Option<&Value<'static>> {
+        self.value.as_ref()
+    }
+}
+
+impl<'b> Expr<'b> for Placeholder {
+    fn bind<'a: 'b>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'b>, DBError>
+    {
+        let value = self.value.as_ref()
+            .ok_or_else(|| DBError::ExpressionInputCount(format!("parameter {} (${}) not bound", self.name, self.index)))?;
+
+        let literal: Literal<'b> = Literal { name: self.name.clone(), value: value.clone(), dtype: Some(self.dtype) };
+        literal.bind(alloc, input_schema)
+    }
+
+    fn is_constant(&self) -> bool {
+        true
+    }
+
+    fn bind_params(&mut self, params: &[Value]) -> Result<(), DBError> {
+        let value = params.get(self.index)
+            .ok_or_else(|| DBError::ExpressionInputCount(format!("parameter ${} of {}", self.index, params.len())))?;
+
+        match value.dtype() {
+            Some(dtype) if dtype != self.dtype =>
+                return Err(DBError::ExpressionInputType(format!("parameter ${}: {} instead of {}", self.index,
+                                                                dtype.name(), self.dtype.name()))),
+            _ => (),
+        }
+
+        self.value = Some(value.clone().into_owned());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, column_row_data};
+    use ::expression::json::JsonTypeOf;
+
+    #[test]
+    fn placeholder() {
+        let schema = Schema::make_one_attr("unused", false, Type::UINT32);
+        let input = Block::new(&allocator::GLOBAL, &schema);
+
+        let mut param = Placeholder::new("limit", 1, Type::UINT32);
+        assert!(param.bind(&allocator::GLOBAL, &schema).is_err());
+
+        for v in 1 .. 3 {
+            param.bind_params(&[Value::from("unused"), Value::UINT32(v)]).unwrap();
+            let bound = param.bind(&allocator::GLOBAL, &schema).unwrap();
+            assert_eq!(bound.evaluate_constant().unwrap(), Value::UINT32(v));
+
+            let out = bound.evaluate(&input, 2).unwrap();
+            assert_eq!(&column_row_data::<UInt32>(&out[0]).unwrap().values[0 .. 2], &[v, v]);
+        }
+
+        assert!(param.bind_params(&[Value::UINT32(1)]).is_err());
+        assert!(param.bind_params(&[Value::NULL, Value::INT64(1)]).is_err());
+
+        param.bind_params(&[Value::NULL, Value::NULL]).unwrap();
+        let bound = param.bind(&allocator::GLOBAL, &schema).unwrap();
+        assert!(bound.schema().get(0).unwrap().nullable);
+
+        // Parameters are bound through the whole tree
+        let mut expr: Box<Expr> = Box::new(JsonTypeOf::new(Placeholder::new("doc", 0, Type::JSON)));
+        assert!(expr.bind_params(&[]).is_err());
+        expr.bind_params(&[Value::JSON("[]".into())]).unwrap();
+        let bound = expr.inputs()[0].bind(&allocator::GLOBAL, &schema).unwrap();
+        assert_eq!(bound.evaluate_constant().unwrap(), Value::JSON("[]".into()));
+    }
+}
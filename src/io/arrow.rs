@@ -0,0 +1,837 @@
+// vim : set ts=4 sw=4 et :
+
+//! Apache Arrow IPC stream format.
+//!
+//! A stream is a Schema message followed by RecordBatch messages and an end of stream marker. Each
+//! RecordBatch maps to one Block (or View). Only the scalar types are supported:
+//!
+//! | dbkit          | Arrow                                      |
+//! |----------------|--------------------------------------------|
+//! | UINT32, UINT64 | Int (unsigned)                             |
+//! | INT32, INT64   | Int (signed)                               |
+//! | FLOAT32/64     | FloatingPoint (SINGLE, DOUBLE)             |
+//! | BOOLEAN        | Bool                                       |
+//! | TEXT           | Utf8                                       |
+//! | JSON           | Utf8, with the `arrow.json` extension name |
+//! | BLOB           | Binary                                     |
+//!
+//! Dictionary encoded & compressed batches are not supported. Data is written in the host's byte
+//! order (which is recorded in the Schema message). Schema and attribute metadata map to the
+//! Arrow custom metadata.
+
+use std::io::{Read, Write};
+use std::mem;
+use std::slice;
+
+use ::allocator::Allocator;
+use ::block::{Block, RefColumn, View, column_nulls, column_row_data};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::schema::{Attribute, Metadata, Schema};
+use ::types::{self, RawData, Type, ValueInfo};
+use ::util::bitmap::{bytes_for, get_bit, set_bit};
+use ::util::copy_value::set_column_value;
+
+use super::flatbuf::{self, Field, Object, push_i64, push_u32};
+
+/// Marks the start of a message
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+/// MetadataVersion V5
+const METADATA_VERSION: i16 = 4;
+
+// MessageHeader union
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_DICTIONARY_BATCH: u8 = 2;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// Type union
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_TIMESTAMP: u8 = 10;
+
+// FloatingPoint precision
+const PRECISION_SINGLE: i16 = 1;
+const PRECISION_DOUBLE: i16 = 2;
+const UNIT_MICROSECOND: i16 = 2;
+
+const EXTENSION_NAME: &'static str = "ARROW:extension:name";
+const JSON_EXTENSION: &'static str = "arrow.json";
+
+/// Size of the FieldNode & Buffer structs
+const STRUCT_SIZE: usize = 16;
+
+/// Buffers are aligned (and padded) to 8 bytes
+const ALIGNMENT: usize = 8;
+
+fn error<S: Into<String>>(msg: S) -> DBError {
+    DBError::Arrow(msg.into())
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    while buf.len() % ALIGNMENT != 0 {
+        buf.push(0);
+    }
+}
+
+fn check_attribute(attr: &Attribute) -> Result<(), DBError> {
+    if attr.dtype.is_nested() {
+        return Err(DBError::AttributeType(attr.name.clone()))
+    }
+    Ok(())
+}
+
+/// Writes Views as an Arrow IPC stream. The Schema message is written with the first batch.
+pub struct ArrowWriter<W: Write> {
+    out: W,
+    schema: Schema,
+    started: bool,
+}
+
+impl<W: Write> ArrowWriter<W> {
+    pub fn new(out: W, schema: &Schema) -> Result<ArrowWriter<W>, DBError> {
+        for attr in schema.iter() {
+            check_attribute(attr)?;
+        }
+
+        Ok(ArrowWriter { out: out, schema: schema.clone(), started: false })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Write the view's rows as one RecordBatch. The view's schema must match the writer's.
+    pub fn write_view<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        {
+            let schema = view.schema();
+            if schema.count() != self.schema.count() {
+                return Err(error(format!("{} columns, expected {}", schema.count(), self.schema.count())))
+            }
+
+            for (attr, expected) in schema.iter().zip(self.schema.iter()) {
+                if attr.dtype != expected.dtype || attr.nullable && !expected.nullable {
+                    return Err(DBError::AttributeType(attr.name.clone()))
+                }
+            }
+        }
+
+        self.start()?;
+
+        let rows = view.rows();
+        let mut nodes = Vec::new();
+        let mut buffers = Vec::new();
+        let mut body = Vec::new();
+
+        for pos in 0 .. self.schema.count() {
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            let null_count = write_column(&mut body, &mut buffers, col, rows)?;
+
+            push_i64(&mut nodes, rows as i64);
+            push_i64(&mut nodes, null_count as i64);
+        }
+
+        let batch = Object::Table(vec![
+            Some(Field::I64(rows as i64)),
+            Some(Field::Object(Object::Structs(STRUCT_SIZE, nodes))),
+            Some(Field::Object(Object::Structs(STRUCT_SIZE, buffers))),
+        ]);
+
+        self.write_message(HEADER_RECORD_BATCH, batch, &body)
+    }
+
+    /// Write the end of stream marker. Streams without batches still get their Schema message.
+    pub fn finish(&mut self) -> Result<(), DBError> {
+        self.start()?;
+        self.out.write_all(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0])?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), DBError> {
+        if self.started {
+            return Ok(())
+        }
+
+        let fields = self.schema.iter().map(schema_field).collect();
+        let endianness = if cfg!(target_endian = "big") { 1 } else { 0 };
+        let schema = Object::Table(vec![
+            Some(Field::I16(endianness)),
+            Some(Field::Object(Object::Tables(fields))),
+            Some(Field::Object(Object::Tables(key_values(self.schema.metadata().iter())))),
+        ]);
+
+        self.write_message(HEADER_SCHEMA, schema, &[])?;
+        self.started = true;
+        Ok(())
+    }
+
+    fn write_message(&mut self, header_type: u8, header: Object, body: &[u8]) -> Result<(), DBError> {
+        let message = flatbuf::finish(&Object::Table(vec![
+            Some(Field::I16(METADATA_VERSION)),
+            Some(Field::U8(header_type)),
+            Some(Field::Object(header)),
+            Some(Field::I64(body.len() as i64)),
+        ]));
+
+        let mut prefix: Vec<u8> = Vec::with_capacity(8);
+        push_u32(&mut prefix, CONTINUATION);
+        push_u32(&mut prefix, message.len() as u32);
+
+        self.out.write_all(&prefix)?;
+        self.out.write_all(&message)?;
+        self.out.write_all(body)?;
+        Ok(())
+    }
+}
+
+/// KeyValue tables
+fn key_values<'m, I: Iterator<Item=(&'m String, &'m String)>>(entries: I) -> Vec<Object> {
+    entries
+        .map(|(key, value)| Object::Table(vec![
+            Some(Field::Object(Object::String(key.clone()))),
+            Some(Field::Object(Object::String(value.clone()))),
+        ]))
+        .collect()
+}
+
+fn read_key_values(tables: Option<Vec<flatbuf::Table>>) -> Metadata {
+    tables.unwrap_or_default().iter()
+        .filter_map(|kv| Some((kv.string(0)?.to_string(), kv.string(1).unwrap_or("").to_string())))
+        .collect()
+}
+
+fn schema_field(attr: &Attribute) -> Object {
+    let int = |width: i32, signed: bool| (TYPE_INT, vec![Some(Field::I32(width)), Some(Field::Bool(signed))]);
+    let float = |precision: i16| (TYPE_FLOATING_POINT, vec![Some(Field::I16(precision))]);
+
+    let (type_type, type_fields) = match attr.dtype {
+        Type::UINT32                => int(32, false),
+        Type::UINT64                => int(64, false),
+        Type::INT32                 => int(32, true),
+        Type::INT64                 => int(64, true),
+        Type::FLOAT32               => float(PRECISION_SINGLE),
+        Type::FLOAT64               => float(PRECISION_DOUBLE),
+        Type::BOOLEAN               => (TYPE_BOOL, vec![]),
+        Type::TEXT | Type::JSON     => (TYPE_UTF8, vec![]),
+        Type::BLOB                  => (TYPE_BINARY, vec![]),
+        Type::TIMESTAMP             => (TYPE_TIMESTAMP, vec![Some(Field::I16(UNIT_MICROSECOND))]),
+        Type::TIMESTAMP_TZ          => (TYPE_TIMESTAMP, vec![Some(Field::I16(UNIT_MICROSECOND)),
+                                                         Some(Field::Object(Object::String("UTC".to_string())))]),
+        // Rejected when creating the writer
+        _                           => unreachable!(),
+    };
+
+    let mut metadata = attr.metadata.clone();
+    if attr.dtype == Type::JSON {
+        metadata.insert(EXTENSION_NAME.to_string(), JSON_EXTENSION.to_string());
+    }
+
+    let metadata = if metadata.is_empty() {
+        None
+    } else {
+        Some(Field::Object(Object::Tables(key_values(metadata.iter()))))
+    };
+
+    Object::Table(vec![
+        Some(Field::Object(Object::String(attr.name.clone()))),
+        Some(Field::Bool(attr.nullable)),
+        Some(Field::U8(type_type)),
+        Some(Field::Object(Object::Table(type_fields))),
+        None,
+        Some(Field::Object(Object::Tables(vec![]))),
+        metadata,
+    ])
+}
+
+/// Append a buffer to the body (and its location to `buffers`)
+fn add_buffer(body: &mut Vec<u8>, buffers: &mut Vec<u8>, data: &[u8]) {
+    pad(body);
+    push_i64(buffers, body.len() as i64);
+    push_i64(buffers, data.len() as i64);
+    body.extend_from_slice(data);
+}
+
+/// Raw bytes of the first `rows` values of a fixed width column
+fn fixed_bytes<'c, T: ValueInfo>(col: &'c RefColumn, rows: RowOffset) -> Result<&'c [u8], DBError> {
+    let values = &column_row_data::<T>(col)?.values[.. rows];
+    Ok(unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, rows * mem::size_of::<T::Store>()) })
+}
+
+/// Write the column's buffers. Returns the NULL count.
+fn write_column<'c>(body: &mut Vec<u8>, buffers: &mut Vec<u8>, col: &'c RefColumn<'c>, rows: RowOffset)
+    -> Result<usize, DBError>
+{
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let attr = col.attribute();
+
+    // Validity bitmap; a set bit is a valid (not NULL) row. Omitted if there are no NULLs.
+    let nulls = column_nulls(col);
+    let null_count = if attr.nullable { nulls.slice(0, rows).count_ones() } else { 0 };
+
+    if null_count > 0 {
+        let mut validity = vec![0u8; bytes_for(rows)];
+        for row in 0 .. rows {
+            set_bit(&mut validity, row, !nulls.get(row));
+        }
+        add_buffer(body, buffers, &validity);
+    } else {
+        add_buffer(body, buffers, &[]);
+    }
+
+    match attr.dtype {
+        Type::UINT32    => add_buffer(body, buffers, fixed_bytes::<types::UInt32>(col, rows)?),
+        Type::UINT64    => add_buffer(body, buffers, fixed_bytes::<types::UInt64>(col, rows)?),
+        Type::INT32     => add_buffer(body, buffers, fixed_bytes::<types::Int32>(col, rows)?),
+        Type::INT64     => add_buffer(body, buffers, fixed_bytes::<types::Int64>(col, rows)?),
+        Type::FLOAT32   => add_buffer(body, buffers, fixed_bytes::<types::Float32>(col, rows)?),
+        Type::FLOAT64   => add_buffer(body, buffers, fixed_bytes::<types::Float64>(col, rows)?),
+        Type::TIMESTAMP     => add_buffer(body, buffers, fixed_bytes::<types::Timestamp>(col, rows)?),
+        Type::TIMESTAMP_TZ  => add_buffer(body, buffers, fixed_bytes::<types::TimestampTz>(col, rows)?),
+        Type::BOOLEAN   => {
+            let values = &column_row_data::<types::Boolean>(col)?.values[.. rows];
+            let mut bits = vec![0u8; bytes_for(rows)];
+            for (row, value) in values.iter().enumerate() {
+                set_bit(&mut bits, row, *value);
+            }
+            add_buffer(body, buffers, &bits);
+        }
+        Type::TEXT      => write_varlen(body, buffers, &column_row_data::<types::Text>(col)?.values[.. rows])?,
+        Type::JSON      => write_varlen(body, buffers, &column_row_data::<types::Json>(col)?.values[.. rows])?,
+        Type::BLOB      => write_varlen(body, buffers, &column_row_data::<types::Blob>(col)?.values[.. rows])?,
+        _               => return Err(DBError::AttributeType(attr.name.clone())),
+    }
+
+    Ok(null_count)
+}
+
+/// Offsets (rows + 1 i32s) and data buffers
+fn write_varlen(body: &mut Vec<u8>, buffers: &mut Vec<u8>, values: &[RawData]) -> Result<(), DBError> {
+    let mut offsets = Vec::with_capacity((values.len() + 1) * 4);
+    let mut data = Vec::new();
+    let mut end = 0usize;
+
+    push_u32(&mut offsets, 0);
+    for value in values {
+        let value: &[u8] = value.as_ref();
+        end = end.checked_add(value.len())
+            .filter(|&end| end <= i32::max_value() as usize)
+            .ok_or_else(|| error("variable length data over 2GB"))?;
+        data.extend_from_slice(value);
+        push_u32(&mut offsets, end as u32);
+    }
+
+    add_buffer(body, buffers, &offsets);
+    add_buffer(body, buffers, &data);
+    Ok(())
+}
+
+/// Reads an Arrow IPC stream into Blocks, one per RecordBatch.
+pub struct ArrowReader<R: Read> {
+    input: R,
+    schema: Schema,
+    done: bool,
+}
+
+/// Message metadata (flatbuffer) and body
+struct Message {
+    metadata: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl<R: Read> ArrowReader<R> {
+    /// Reads the stream's Schema message
+    pub fn new(input: R) -> Result<ArrowReader<R>, DBError> {
+        let mut reader = ArrowReader { input: input, schema: Schema::default(), done: false };
+
+        let message = reader.read_message()?.ok_or_else(|| error("missing schema"))?;
+        let (header_type, header) = message.header()?;
+        if header_type != HEADER_SCHEMA {
+            return Err(error(format!("expected a schema message, got {}", header_type)))
+        }
+
+        reader.schema = read_schema(header)?;
+        Ok(reader)
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Read the next RecordBatch into a new Block. None at the end of the stream.
+    pub fn read_block<'a>(&mut self, alloc: &'a Allocator) -> Result<Option<Block<'a>>, DBError> {
+        let message = match self.read_message()? {
+            Some(m) => m,
+            None    => return Ok(None),
+        };
+
+        let (header_type, header) = message.header()?;
+        match header_type {
+            HEADER_RECORD_BATCH     => read_batch(alloc, &self.schema, header, &message.body).map(Some),
+            HEADER_DICTIONARY_BATCH => Err(error("dictionary batches are not supported")),
+            _                       => Err(error(format!("unexpected message type {}", header_type))),
+        }
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>, DBError> {
+        let mut buf = vec![0u8; len];
+        self.input.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// None at the end of the stream (marker or end of input)
+    fn read_message(&mut self) -> Result<Option<Message>, DBError> {
+        if self.done {
+            return Ok(None)
+        }
+
+        let mut prefix = [0u8; 4];
+        let mut filled = 0;
+        while filled < 4 {
+            match self.input.read(&mut prefix[filled ..])? {
+                0   => break,
+                n   => filled += n,
+            }
+        }
+
+        // Streams may end without the end of stream marker
+        if filled == 0 {
+            self.done = true;
+            return Ok(None)
+        } else if filled < 4 {
+            return Err(error("truncated message"))
+        }
+
+        let mut len = flatbuf::read_u32(&prefix, 0).unwrap();
+
+        // Pre 0.15 streams don't have the continuation marker
+        if len == CONTINUATION {
+            let raw = self.read_exact(4)?;
+            len = flatbuf::read_u32(&raw, 0).unwrap();
+        }
+
+        if len == 0 {
+            self.done = true;
+            return Ok(None)
+        }
+
+        let metadata = self.read_exact(len as usize)?;
+
+        let body_len = {
+            let message = flatbuf::Table::root(&metadata).ok_or_else(|| error("invalid message"))?;
+            message.i64(3).unwrap_or(0)
+        };
+
+        if body_len < 0 {
+            return Err(error("invalid body length"))
+        }
+
+        let body = self.read_exact(body_len as usize)?;
+        Ok(Some(Message { metadata: metadata, body: body }))
+    }
+}
+
+impl Message {
+    fn header(&self) -> Result<(u8, flatbuf::Table), DBError> {
+        let message = flatbuf::Table::root(&self.metadata).ok_or_else(|| error("invalid message"))?;
+        let header_type = message.u8(1).unwrap_or(0);
+        let header = message.table(2).ok_or_else(|| error("missing message header"))?;
+        Ok((header_type, header))
+    }
+}
+
+fn read_schema(schema: flatbuf::Table) -> Result<Schema, DBError> {
+    let big_endian = schema.i16(0).unwrap_or(0) == 1;
+    if big_endian != cfg!(target_endian = "big") {
+        return Err(error("byte order differs from the host"))
+    }
+
+    let fields = schema.tables(1).ok_or_else(|| error("invalid schema fields"))?;
+    let mut attrs = Vec::with_capacity(fields.len());
+
+    for field in fields {
+        let name = field.string(0).unwrap_or("");
+
+        if field.table(4).is_some() {
+            return Err(error(format!("{}: dictionary encoding is not supported", name)))
+        }
+
+        if field.tables(5).map_or(false, |c| !c.is_empty()) {
+            return Err(DBError::UnknownType(format!("{}: nested Arrow type", name)))
+        }
+
+        let mut metadata = read_key_values(field.tables(6));
+        let json = metadata.get(EXTENSION_NAME).map_or(false, |v| v == JSON_EXTENSION);
+        if json {
+            metadata.remove(EXTENSION_NAME);
+        }
+
+        let type_type = field.u8(2).unwrap_or(0);
+        let dtype = match (type_type, field.table(3)) {
+            (TYPE_INT, Some(t)) => match (t.i32(0).unwrap_or(0), t.bool(1).unwrap_or(false)) {
+                (32, false)     => Type::UINT32,
+                (64, false)     => Type::UINT64,
+                (32, true)      => Type::INT32,
+                (64, true)      => Type::INT64,
+                (width, _)      => return Err(DBError::UnknownType(format!("{}: {} bit Arrow Int", name, width))),
+            },
+            (TYPE_FLOATING_POINT, Some(t)) => match t.i16(0).unwrap_or(0) {
+                PRECISION_SINGLE    => Type::FLOAT32,
+                PRECISION_DOUBLE    => Type::FLOAT64,
+                _                   => return Err(DBError::UnknownType(format!("{}: half float Arrow type", name))),
+            },
+            (TYPE_BOOL, _)      => Type::BOOLEAN,
+            (TYPE_UTF8, _)      => if json { Type::JSON } else { Type::TEXT },
+            (TYPE_BINARY, _)    => Type::BLOB,
+            (TYPE_TIMESTAMP, Some(t)) => match (t.i16(0).unwrap_or(0), t.string(1).is_some()) {
+                (UNIT_MICROSECOND, false)   => Type::TIMESTAMP,
+                (UNIT_MICROSECOND, true)    => Type::TIMESTAMP_TZ,
+                (unit, _)       => return Err(DBError::UnknownType(format!("{}: Arrow Timestamp unit {}", name, unit))),
+            },
+            (t, _)              => return Err(DBError::UnknownType(format!("{}: Arrow type {}", name, t))),
+        };
+
+        let mut attr = Attribute::new(name, field.bool(1).unwrap_or(false), dtype);
+        attr.metadata = metadata;
+        attrs.push(attr);
+    }
+
+    let mut out = Schema::from_vec(attrs)?;
+    for (key, value) in read_key_values(schema.tables(2)) {
+        out = out.with_metadata(key, value);
+    }
+    Ok(out)
+}
+
+/// Node (length & NULL count) or buffer (offset & length) struct
+fn read_pair(structs: &[u8], idx: usize) -> (i64, i64) {
+    let pos = idx * STRUCT_SIZE;
+    (flatbuf::read_i64(structs, pos).unwrap(), flatbuf::read_i64(structs, pos + 8).unwrap())
+}
+
+/// Sequential access to a batch's buffers
+struct Buffers<'a> {
+    structs: &'a [u8],
+    body: &'a [u8],
+    next: usize,
+}
+
+impl<'a> Buffers<'a> {
+    fn next(&mut self) -> Result<&'a [u8], DBError> {
+        if (self.next + 1) * STRUCT_SIZE > self.structs.len() {
+            return Err(error("missing buffers"))
+        }
+
+        let (offset, len) = read_pair(self.structs, self.next);
+        self.next += 1;
+
+        let end = match offset.checked_add(len) {
+            Some(end) if offset >= 0 && len >= 0 && end as u64 <= self.body.len() as u64 => end,
+            _ => return Err(error("buffer out of bounds")),
+        };
+
+        Ok(&self.body[offset as usize .. end as usize])
+    }
+
+    /// Buffer that has to hold at least `count` values of `size` bytes
+    fn next_sized(&mut self, count: usize, size: usize) -> Result<&'a [u8], DBError> {
+        let len = count.checked_mul(size).ok_or_else(|| error("buffer size overflow"))?;
+        let buf = self.next()?;
+        if buf.len() < len {
+            return Err(error("buffer too short"))
+        }
+        Ok(buf)
+    }
+}
+
+fn read_batch<'a>(alloc: &'a Allocator, schema: &Schema, batch: flatbuf::Table, body: &[u8])
+    -> Result<Block<'a>, DBError>
+{
+    if batch.table(3).is_some() {
+        return Err(error("compressed batches are not supported"))
+    }
+
+    let length = batch.i64(0).unwrap_or(0);
+    if length < 0 {
+        return Err(error("invalid batch length"))
+    }
+    let rows = length as RowOffset;
+
+    let nodes = batch.structs(1, STRUCT_SIZE).ok_or_else(|| error("missing field nodes"))?;
+    if nodes.len() != schema.count() * STRUCT_SIZE {
+        return Err(error(format!("{} field nodes, expected {}", nodes.len() / STRUCT_SIZE, schema.count())))
+    }
+
+    let mut buffers = Buffers {
+        structs: batch.structs(2, STRUCT_SIZE).ok_or_else(|| error("missing buffers"))?,
+        body: body,
+        next: 0,
+    };
+
+    let mut block = Block::new(alloc, schema);
+    if rows > 0 {
+        block.add_rows(rows)?;
+    }
+
+    for pos in 0 .. schema.count() {
+        let attr = schema.get(pos)?.clone();
+        let (node_rows, null_count) = read_pair(nodes, pos);
+
+        if node_rows != length {
+            return Err(error(format!("{}: {} rows, expected {}", attr.name, node_rows, length)))
+        }
+
+        let validity = buffers.next()?;
+        let validity = if null_count > 0 {
+            if !attr.nullable {
+                return Err(DBError::AttributeNullability(attr.name.clone()))
+            }
+            if validity.len() < bytes_for(rows) {
+                return Err(error("validity buffer too short"))
+            }
+            Some(validity)
+        } else {
+            None
+        };
+
+        read_column(&mut block, pos, &attr, rows, validity, &mut buffers)?;
+    }
+
+    Ok(block)
+}
+
+/// Copy the raw values into a fixed width column
+fn read_fixed<T: ValueInfo>(block: &mut Block, pos: usize, data: &[u8], rows: RowOffset) -> Result<(), DBError> {
+    let values = block.column_mut(pos).unwrap().rows_mut::<T>()?;
+    let len = rows * mem::size_of::<T::Store>();
+    unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, len).copy_from_slice(&data[.. len]); }
+    Ok(())
+}
+
+fn read_column(block: &mut Block, pos: usize, attr: &Attribute, rows: RowOffset, validity: Option<&[u8]>,
+               buffers: &mut Buffers) -> Result<(), DBError>
+{
+    let is_null = |row: RowOffset| validity.map_or(false, |v| !get_bit(v, row));
+    let size = attr.dtype.size_of();
+
+    match attr.dtype {
+        Type::UINT32    => read_fixed::<types::UInt32>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::UINT64    => read_fixed::<types::UInt64>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::INT32     => read_fixed::<types::Int32>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::INT64     => read_fixed::<types::Int64>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::FLOAT32   => read_fixed::<types::Float32>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::FLOAT64   => read_fixed::<types::Float64>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::TIMESTAMP     => read_fixed::<types::Timestamp>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::TIMESTAMP_TZ  => read_fixed::<types::TimestampTz>(block, pos, buffers.next_sized(rows, size)?, rows)?,
+        Type::BOOLEAN   => {
+            let bits = buffers.next_sized(bytes_for(rows), 1)?;
+            let values = block.column_mut(pos).unwrap().rows_mut::<types::Boolean>()?;
+            for row in 0 .. rows {
+                values[row] = get_bit(bits, row);
+            }
+        }
+        Type::TEXT | Type::JSON | Type::BLOB => {
+            let offsets = match rows {
+                0       => buffers.next_sized(0, 4)?,
+                rows    => buffers.next_sized(rows.checked_add(1).ok_or_else(|| error("too many rows"))?, 4)?,
+            };
+            let data = buffers.next()?;
+
+            for row in 0 .. rows {
+                if is_null(row) {
+                    continue
+                }
+
+                let start = flatbuf::read_u32(offsets, row * 4).unwrap() as i32;
+                let end = flatbuf::read_u32(offsets, row * 4 + 4).unwrap() as i32;
+                if start < 0 || end < start || end as usize > data.len() {
+                    return Err(error(format!("{}: invalid offsets", attr.name)))
+                }
+
+                let value = &data[start as usize .. end as usize];
+                if attr.dtype == Type::BLOB {
+                    set_column_value(block, pos, row, &value)?;
+                } else {
+                    let value = ::std::str::from_utf8(value)
+                        .map_err(|_| error(format!("{}: invalid UTF-8", attr.name)))?;
+                    set_column_value(block, pos, row, &value)?;
+                }
+            }
+        }
+        _               => return Err(DBError::AttributeType(attr.name.clone())),
+    }
+
+    if validity.is_some() {
+        let col = block.column_mut(pos).unwrap();
+        for row in (0 .. rows).filter(|r| is_null(*r)) {
+            col.nulls_mut()?.set(row, true);
+            col.set_default(row)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{column_value, window_alias};
+    use ::row::RowRange;
+    use ::types::Value;
+
+    fn test_block<'a>() -> Block<'a> {
+        let attrs = vec![
+            Attribute::new("u", false, Type::UINT32),
+            Attribute::new("i", true, Type::INT64),
+            Attribute::new("f", false, Type::FLOAT64),
+            Attribute::new("b", true, Type::BOOLEAN),
+            Attribute::new("t", true, Type::TEXT),
+            Attribute::new("j", false, Type::JSON),
+            Attribute::new("x", false, Type::BLOB),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(3).unwrap();
+
+        let rows: Vec<(u32, Option<i64>, f64, Option<bool>, Option<&str>, &str, &[u8])> = vec![
+            (1, Some(-5), 0.5, Some(true), Some("a"), "1", b""),
+            (2, None, -1.25, None, None, "[2]", b"\x00\x01"),
+            (3, Some(7), 3.0, Some(false), Some("xyzw"), "{\"a\":3}", b"abcd"),
+        ];
+
+        for (row, values) in rows.into_iter().enumerate() {
+            set_column_value(&mut block, 0, row, &values.0).unwrap();
+            set_column_value(&mut block, 1, row, &values.1).unwrap();
+            set_column_value(&mut block, 2, row, &values.2).unwrap();
+            set_column_value(&mut block, 3, row, &values.3).unwrap();
+            set_column_value(&mut block, 4, row, &values.4).unwrap();
+            set_column_value(&mut block, 5, row, &values.5).unwrap();
+            set_column_value(&mut block, 6, row, &values.6).unwrap();
+        }
+
+        block
+    }
+
+    fn values<'v>(view: &'v View<'v>) -> Vec<Vec<Value<'static>>> {
+        (0 .. view.rows())
+            .map(|row| (0 .. view.schema().count())
+                .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                .collect())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let block = test_block();
+
+        let mut writer = ArrowWriter::new(Vec::new(), block.schema()).unwrap();
+        writer.write_view(&block).unwrap();
+        writer.write_view(&window_alias(&block, Some(RowRange { offset: 1, rows: 2 })).unwrap()).unwrap();
+        writer.finish().unwrap();
+        let stream = writer.into_inner();
+
+        // Framing: continuation marker, 8 byte aligned metadata; end of stream marker
+        assert_eq!(&stream[.. 4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(flatbuf::read_u32(&stream, 4).unwrap() % 8, 0);
+        assert_eq!(&stream[stream.len() - 8 ..], &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+
+        let mut reader = ArrowReader::new(&stream[..]).unwrap();
+        let schema = reader.schema().clone();
+        let types: Vec<(Type, bool)> = schema.iter().map(|a| (a.dtype, a.nullable)).collect();
+        assert_eq!(types, block.schema().iter().map(|a| (a.dtype, a.nullable)).collect::<Vec<_>>());
+        assert_eq!(schema.get(5).unwrap().name, "j");
+        assert!(schema.get(5).unwrap().metadata.is_empty());
+
+        let first = reader.read_block(&allocator::GLOBAL).unwrap().unwrap();
+        assert_eq!(values(&first), values(&block));
+        assert_eq!(values(&first)[1][4], Value::NULL);
+
+        let second = reader.read_block(&allocator::GLOBAL).unwrap().unwrap();
+        assert_eq!(second.rows(), 2);
+        assert_eq!(values(&second), values(&block)[1 ..].to_vec());
+
+        assert!(reader.read_block(&allocator::GLOBAL).unwrap().is_none());
+    }
+
+    #[test]
+    fn timestamps() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("ts", true, Type::TIMESTAMP),
+            Attribute::new("tz", false, Type::TIMESTAMP_TZ),
+        ]).unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+        set_column_value(&mut block, 0, 0, &Value::TIMESTAMP(-1)).unwrap();
+        set_column_value(&mut block, 0, 1, &Value::NULL).unwrap();
+        set_column_value(&mut block, 1, 0, &Value::TIMESTAMP_TZ(1709214300000000)).unwrap();
+        set_column_value(&mut block, 1, 1, &Value::TIMESTAMP_TZ(0)).unwrap();
+
+        let mut writer = ArrowWriter::new(Vec::new(), &schema).unwrap();
+        writer.write_view(&block).unwrap();
+        let stream = writer.into_inner();
+
+        let mut reader = ArrowReader::new(&stream[..]).unwrap();
+        let types: Vec<Type> = reader.schema().iter().map(|a| a.dtype).collect();
+        assert_eq!(types, vec![Type::TIMESTAMP, Type::TIMESTAMP_TZ]);
+        assert_eq!(values(&reader.read_block(&allocator::GLOBAL).unwrap().unwrap()), values(&block));
+    }
+
+    #[test]
+    fn errors() {
+        let block = test_block();
+
+        let nested = Schema::from_attr(Attribute::list("l", false, Attribute::new("e", false, Type::UINT32)));
+        assert!(ArrowWriter::new(Vec::new(), &nested).is_err());
+
+        // Schema mismatch
+        let mut writer = ArrowWriter::new(Vec::new(), &Schema::make_one_attr("u", false, Type::UINT64)).unwrap();
+        assert!(writer.write_view(&block).is_err());
+
+        // Empty stream still has a schema
+        writer.finish().unwrap();
+        let stream = writer.into_inner();
+        let mut reader = ArrowReader::new(&stream[..]).unwrap();
+        assert_eq!(reader.schema().get(0).unwrap().dtype, Type::UINT64);
+        assert!(reader.read_block(&allocator::GLOBAL).unwrap().is_none());
+
+        // Truncated batch
+        let mut writer = ArrowWriter::new(Vec::new(), block.schema()).unwrap();
+        writer.write_view(&block).unwrap();
+        let stream = writer.into_inner();
+        let mut reader = ArrowReader::new(&stream[.. stream.len() - 8]).unwrap();
+        assert!(reader.read_block(&allocator::GLOBAL).is_err());
+
+        assert!(ArrowReader::new(&b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00"[..]).is_err());
+
+        // Buffer offset + length overflows
+        let mut structs = Vec::new();
+        push_i64(&mut structs, i64::max_value());
+        push_i64(&mut structs, 1);
+        let mut buffers = Buffers { structs: &structs, body: &[0u8; 8], next: 0 };
+        assert!(buffers.next().is_err());
+        assert!(Buffers { structs: &structs, body: &[], next: 0 }.next_sized(usize::max_value(), 2).is_err());
+    }
+
+    #[test]
+    fn metadata() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("u", false, Type::UINT32).with_metadata("unit", "ms"),
+            Attribute::new("j", false, Type::JSON).with_metadata("source", "api"),
+        ]).unwrap().with_metadata("table", "events");
+
+        let mut writer = ArrowWriter::new(Vec::new(), &schema).unwrap();
+        writer.finish().unwrap();
+        let stream = writer.into_inner();
+
+        let reader = ArrowReader::new(&stream[..]).unwrap();
+        assert!(*reader.schema() == schema);
+        assert_eq!(reader.schema().get(1).unwrap().dtype, Type::JSON);
+    }
+}
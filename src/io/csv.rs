@@ -0,0 +1,697 @@
+// vim : set ts=4 sw=4 et :
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::operation::{Cursor, CursorChunk, Operation, DEFAULT_CURSOR_FETCH};
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value, NULL_VALUE};
+use ::util::copy_value::set_column_value;
+use ::util::datetime;
+
+/// Number of records used for inferring the schema
+const INFER_RECORDS: usize = 100;
+
+/// Rows per block when bulk loading
+const LOAD_ROWS: RowOffset = 1024;
+
+/// Delimited text format options
+#[derive(Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// The first record is a header with the column names
+    pub header: bool,
+    /// Unquoted fields equal to this are NULL
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions { delimiter: b',', quote: b'"', header: true, null: String::new() }
+    }
+}
+
+/// Parsed record fields; None is NULL
+type Record = Vec<Option<String>>;
+
+/// Parses delimited text into Blocks.
+///
+/// The schema is either provided or inferred from the header (column names) and the first records
+/// (types). Inferred columns are INT64, FLOAT64, BOOLEAN or TEXT, and always nullable: a NULL can
+/// come after the records used for inference.
+pub struct CsvReader<R: Read> {
+    input: BufReader<R>,
+    options: CsvOptions,
+    schema: Schema,
+    /// Records read ahead while inferring the schema
+    pending: VecDeque<Record>,
+    /// Input line of the last record, for error messages
+    line: usize,
+}
+
+impl<R: Read> CsvReader<R> {
+    pub fn new(input: R, options: CsvOptions, schema: Option<Schema>) -> Result<CsvReader<R>, DBError> {
+        let mut reader = CsvReader {
+            input: BufReader::new(input),
+            options: options,
+            schema: Schema::default(),
+            pending: VecDeque::new(),
+            line: 0,
+        };
+
+        let names = if reader.options.header {
+            let header = reader.parse_record()?.unwrap_or_default();
+            Some(header.into_iter().map(|n| n.unwrap_or_default()).collect::<Vec<String>>())
+        } else {
+            None
+        };
+
+        reader.schema = match schema {
+            Some(s) => {
+                if let Some(ref n) = names {
+                    if n.len() != s.count() {
+                        return Err(reader.error(format!("header has {} columns, expected {}", n.len(), s.count())))
+                    }
+                }
+
+                if let Some(attr) = s.iter().find(|a| a.dtype.is_nested()) {
+                    return Err(DBError::AttributeType(attr.name.clone()))
+                }
+
+                s
+            }
+            None => reader.infer_schema(names)?,
+        };
+
+        Ok(reader)
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Read up to `rows` records into a new Block. None at the end of input.
+    pub fn read_block<'a>(&mut self, alloc: &'a Allocator, rows: RowOffset) -> Result<Option<Block<'a>>, DBError> {
+        let mut block = Block::new(alloc, &self.schema);
+
+        for _ in 0 .. rows {
+            if !self.read_row(&mut block)? {
+                break
+            }
+        }
+
+        Ok(if block.rows() > 0 { Some(block) } else { None })
+    }
+
+    /// Bulk load the rest of the input into a single Block.
+    pub fn read_all<'a>(&mut self, alloc: &'a Allocator) -> Result<Block<'a>, DBError> {
+        let mut block = Block::new(alloc, &self.schema);
+
+        while self.read_row(&mut block)? {
+            if block.rows() == block.capacity() {
+                block.set_capacity(block.capacity() + LOAD_ROWS)?;
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Append the next record to the block. False at the end of input.
+    fn read_row(&mut self, block: &mut Block) -> Result<bool, DBError> {
+        let record = match self.read_record()? {
+            Some(r) => r,
+            None    => return Ok(false),
+        };
+
+        if record.len() != self.schema.count() {
+            return Err(self.error(format!("{} fields, expected {}", record.len(), self.schema.count())))
+        }
+
+        let row = block.add_row()?;
+        for (pos, field) in record.iter().enumerate() {
+            let dtype = self.schema.get(pos)?.dtype;
+            if let Err(e) = set_field(block, pos, row, dtype, field.as_ref().map(|f| f.as_str())) {
+                block.truncate(row);
+                return Err(match e {
+                    DBError::CSV(msg)   => self.field_error(pos, msg),
+                    e                   => e,
+                })
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn error(&self, msg: String) -> DBError {
+        DBError::Parse { format: "CSV", line: self.line, column: 0, msg: msg }
+    }
+
+    /// Error in the field at `pos` of the current record
+    fn field_error(&self, pos: usize, msg: String) -> DBError {
+        DBError::Parse { format: "CSV", line: self.line, column: pos + 1, msg: msg }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, DBError> {
+        match self.pending.pop_front() {
+            Some(r) => Ok(Some(r)),
+            None    => self.parse_record(),
+        }
+    }
+
+    /// Read one record. Quoted fields can span multiple lines. Blank lines are skipped.
+    fn parse_record(&mut self) -> Result<Option<Record>, DBError> {
+        let mut buf = Vec::new();
+
+        loop {
+            if self.input.read_until(b'\n', &mut buf)? == 0 {
+                if buf.is_empty() {
+                    return Ok(None)
+                }
+                return Err(self.error("unterminated quoted field".to_string()))
+            }
+            self.line += 1;
+
+            let mut end = buf.len();
+            while end > 0 && (buf[end - 1] == b'\n' || buf[end - 1] == b'\r') {
+                end -= 1;
+            }
+
+            if end == 0 {
+                buf.clear();
+                continue
+            }
+
+            // None: the newline is inside a quoted field
+            if let Some(record) = self.split_fields(&buf[.. end])? {
+                return Ok(Some(record))
+            }
+        }
+    }
+
+    /// Fields of the record; None if the line ends inside a quoted field. Only a quote at the
+    /// start of a field begins a quoted field, other quotes are part of the value.
+    fn split_fields(&self, line: &[u8]) -> Result<Option<Record>, DBError> {
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+
+        let mut out = Vec::new();
+        let mut field = Vec::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut idx = 0;
+
+        while idx < line.len() {
+            let c = line[idx];
+
+            if in_quotes {
+                if c != quote {
+                    field.push(c);
+                } else if line.get(idx + 1) == Some(&quote) {
+                    field.push(quote);
+                    idx += 1;
+                } else {
+                    in_quotes = false;
+                }
+            } else if c == quote && field.is_empty() && !quoted {
+                in_quotes = true;
+                quoted = true;
+            } else if c == delimiter {
+                out.push(self.finish_field(field, quoted)?);
+                field = Vec::new();
+                quoted = false;
+            } else {
+                field.push(c);
+            }
+
+            idx += 1;
+        }
+
+        if in_quotes {
+            return Ok(None)
+        }
+
+        out.push(self.finish_field(field, quoted)?);
+        Ok(Some(out))
+    }
+
+    fn finish_field(&self, field: Vec<u8>, quoted: bool) -> Result<Option<String>, DBError> {
+        let value = String::from_utf8(field)
+            .map_err(|_| self.error("invalid UTF-8".to_string()))?;
+
+        Ok(if !quoted && value == self.options.null { None } else { Some(value) })
+    }
+
+    /// Read ahead records and pick the narrowest type that parses all of the column's values.
+    fn infer_schema(&mut self, names: Option<Vec<String>>) -> Result<Schema, DBError> {
+        while self.pending.len() < INFER_RECORDS {
+            match self.parse_record()? {
+                Some(r) => self.pending.push_back(r),
+                None    => break,
+            }
+        }
+
+        let count = names.as_ref().map(|n| n.len())
+            .or_else(|| self.pending.front().map(|r| r.len()))
+            .unwrap_or(0);
+
+        let mut attrs = Vec::with_capacity(count);
+        for pos in 0 .. count {
+            let name = names.as_ref().map_or_else(|| format!("c{}", pos), |n| n[pos].clone());
+            let values: Vec<Option<&str>> = self.pending.iter()
+                .map(|r| r.get(pos).and_then(|f| f.as_ref()).map(|f| f.as_str()))
+                .collect();
+
+            let present: Vec<&str> = values.into_iter().filter_map(|v| v).collect();
+
+            let dtype = if present.is_empty() {
+                Type::TEXT
+            } else if present.iter().all(|v| v.parse::<i64>().is_ok()) {
+                Type::INT64
+            } else if present.iter().all(|v| v.parse::<f64>().is_ok()) {
+                Type::FLOAT64
+            } else if present.iter().all(|v| parse_bool(v).is_some()) {
+                Type::BOOLEAN
+            } else {
+                Type::TEXT
+            };
+
+            attrs.push(Attribute::new(name, true, dtype));
+        }
+
+        Schema::from_vec(attrs)
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "TRUE" | "True" | "t" | "1"    => Some(true),
+        "false" | "FALSE" | "False" | "f" | "0" => Some(false),
+        _                                       => None,
+    }
+}
+
+fn set_field(block: &mut Block, pos: usize, row: RowOffset, dtype: Type, field: Option<&str>) -> Result<(), DBError> {
+    let value = match field {
+        Some(v) => v,
+        None    => return set_column_value(block, pos, row, &NULL_VALUE),
+    };
+
+    let bad = || DBError::CSV(format!("{} is not a {}", value, dtype.name()));
+
+    match dtype {
+        Type::UINT32    => set_column_value(block, pos, row, &value.parse::<u32>().map_err(|_| bad())?),
+        Type::UINT64    => set_column_value(block, pos, row, &value.parse::<u64>().map_err(|_| bad())?),
+        Type::INT32     => set_column_value(block, pos, row, &value.parse::<i32>().map_err(|_| bad())?),
+        Type::INT64     => set_column_value(block, pos, row, &value.parse::<i64>().map_err(|_| bad())?),
+        Type::FLOAT32   => set_column_value(block, pos, row, &value.parse::<f32>().map_err(|_| bad())?),
+        Type::FLOAT64   => set_column_value(block, pos, row, &value.parse::<f64>().map_err(|_| bad())?),
+        Type::BOOLEAN   => set_column_value(block, pos, row, &parse_bool(value).ok_or_else(bad)?),
+        Type::TEXT | Type::JSON => set_column_value(block, pos, row, &value),
+        Type::BLOB      => set_column_value(block, pos, row, &value.as_bytes()),
+        Type::TIMESTAMP     => {
+            let ts = datetime::parse_timestamp(value).ok_or_else(bad)?;
+            set_column_value(block, pos, row, &Value::TIMESTAMP(ts))
+        }
+        Type::TIMESTAMP_TZ  => {
+            let ts = datetime::parse_timestamp_tz(value).ok_or_else(bad)?;
+            set_column_value(block, pos, row, &Value::TIMESTAMP_TZ(ts))
+        }
+        _               => Err(DBError::AttributeType(block.schema().get(pos)?.name.clone())),
+    }
+}
+
+/// Operation reading CSV input as a stream of chunks. The input can only be scanned (bound) once.
+pub struct CsvScan<R: Read> {
+    reader: RefCell<Option<CsvReader<R>>>,
+    schema: Schema,
+}
+
+impl<R: Read> CsvScan<R> {
+    pub fn new(reader: CsvReader<R>) -> CsvScan<R> {
+        let schema = reader.schema().clone();
+        CsvScan { reader: RefCell::new(Some(reader)), schema: schema }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn cursor<'a>(&self, alloc: &'a Allocator, cancel: &CancelToken) -> Result<CsvCursor<'a, R>, DBError> {
+        let reader = self.reader.borrow_mut().take()
+            .ok_or(DBError::CSV("input already scanned".to_string()))?;
+
+        Ok(CsvCursor { reader: reader, alloc: alloc, block: None, cancel: cancel.clone() })
+    }
+}
+
+impl<'a, R: Read + 'a> Operation<'a> for CsvScan<R> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(self.cursor(ctx.allocator(), ctx.cancel_token())?))
+    }
+
+    fn describe(&self) -> String {
+        format!("CsvScan columns={}", self.schema.count())
+    }
+}
+
+/// Implementation of the `CsvScan` operation
+struct CsvCursor<'a, R: Read> {
+    reader: CsvReader<R>,
+    alloc: &'a Allocator,
+    /// Last chunk
+    block: Option<Block<'a>>,
+    cancel: CancelToken,
+}
+
+impl<'a, R: Read> Cursor<'a> for CsvCursor<'a, R> {
+    fn schema(&self) -> &Schema {
+        self.reader.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+        self.block = self.reader.read_block(self.alloc, rows)?;
+
+        match self.block {
+            Some(ref b) => Ok(CursorChunk::Next(window_alias(b, None)?)),
+            None        => Ok(CursorChunk::End),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+}
+
+/// Text representation of BLOB values
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlobFormat {
+    HEX,
+    BASE64,
+}
+
+/// Writes rows as delimited text. Fields are quoted when they contain the delimiter, quotes, line
+/// breaks or when they'd be read back as NULL. NULLs are written as the (unquoted) null token.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    options: CsvOptions,
+    blobs: BlobFormat,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(out: W, options: CsvOptions) -> CsvWriter<W> {
+        CsvWriter { out: out, options: options, blobs: BlobFormat::HEX }
+    }
+
+    pub fn with_blob_format(self, blobs: BlobFormat) -> CsvWriter<W> {
+        CsvWriter { blobs: blobs, ..self }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Write the column names
+    pub fn write_header(&mut self, schema: &Schema) -> Result<(), DBError> {
+        let mut line = Vec::new();
+
+        for (pos, attr) in schema.iter().enumerate() {
+            if pos > 0 {
+                line.push(self.options.delimiter);
+            }
+            self.push_field(&mut line, attr.name.as_bytes(), false);
+        }
+
+        line.push(b'\n');
+        self.out.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Write all the rows of the view
+    pub fn write_view<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        let count = view.schema().count();
+        let columns = (0 .. count)
+            .map(|pos| view.column(pos).ok_or(DBError::make_column_unknown_pos(pos)))
+            .collect::<Result<Vec<_>, DBError>>()?;
+
+        let mut line = Vec::new();
+
+        for row in 0 .. view.rows() {
+            line.clear();
+
+            for (pos, col) in columns.iter().enumerate() {
+                if pos > 0 {
+                    line.push(self.options.delimiter);
+                }
+
+                let value = column_value(*col, row)?;
+                self.push_value(&mut line, &value, &col.attribute().name)?;
+            }
+
+            line.push(b'\n');
+            self.out.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write all the remaining rows of the cursor (and the header, if enabled). Returns the number
+    /// of rows written.
+    pub fn write_cursor<'a>(&mut self, cursor: &mut Cursor<'a>) -> Result<usize, DBError> {
+        if self.options.header {
+            let schema = cursor.schema().clone();
+            self.write_header(&schema)?;
+        }
+
+        let mut rows = 0;
+        loop {
+            match cursor.next(DEFAULT_CURSOR_FETCH)? {
+                CursorChunk::Next(view) => {
+                    rows += view.rows();
+                    self.write_view(&view)?;
+                }
+                CursorChunk::End        => break,
+            }
+        }
+
+        self.out.flush()?;
+        Ok(rows)
+    }
+
+    fn push_value(&self, line: &mut Vec<u8>, value: &Value, name: &str) -> Result<(), DBError> {
+        let text = match *value {
+            Value::NULL             => {
+                line.extend_from_slice(self.options.null.as_bytes());
+                return Ok(())
+            }
+            Value::UINT32(v)        => v.to_string(),
+            Value::UINT64(v)        => v.to_string(),
+            Value::INT32(v)         => v.to_string(),
+            Value::INT64(v)         => v.to_string(),
+            Value::FLOAT32(v)       => v.to_string(),
+            Value::FLOAT64(v)       => v.to_string(),
+            Value::BOOLEAN(v)       => v.to_string(),
+            Value::TEXT(ref v)      => v.to_string(),
+            Value::JSON(ref v)      => v.to_string(),
+            Value::TIMESTAMP(_) | Value::TIMESTAMP_TZ(_) => value.to_string(),
+            Value::BLOB(ref v)      => match self.blobs {
+                BlobFormat::HEX     => v.iter().map(|b| format!("{:02x}", b)).collect(),
+                BlobFormat::BASE64  => base64_encode(v),
+            },
+            _                       => return Err(DBError::AttributeType(name.to_string())),
+        };
+
+        self.push_field(line, text.as_bytes(), true);
+        Ok(())
+    }
+
+    /// Append a (non NULL) field, quoting it if necessary
+    fn push_field(&self, line: &mut Vec<u8>, field: &[u8], value: bool) {
+        let quote = self.options.quote;
+        let needs_quotes = field.iter().any(|c| *c == quote || *c == self.options.delimiter || *c == b'\n' || *c == b'\r')
+            || (value && field == self.options.null.as_bytes());
+
+        if !needs_quotes {
+            line.extend_from_slice(field);
+            return
+        }
+
+        line.push(quote);
+        for c in field {
+            if *c == quote {
+                line.push(quote);
+            }
+            line.push(*c);
+        }
+        line.push(quote);
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for idx in 0 .. 4 {
+            if idx <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - idx * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::operation::ScanView;
+    use ::util::copy_value::ValueSetter;
+
+    #[test]
+    fn infer_and_load() {
+        let input = "id,name,score\n1,a,0.5\r\n2,\"b,\"\"c\"\"\",\n\n3,\"first\nsecond line\",1e3\n";
+        let mut reader = CsvReader::new(input.as_bytes(), CsvOptions::default(), None).unwrap();
+
+        let dtypes: Vec<(Type, bool)> = reader.schema().iter().map(|a| (a.dtype, a.nullable)).collect();
+        assert_eq!(dtypes, vec![(Type::INT64, true), (Type::TEXT, true), (Type::FLOAT64, true)]);
+        assert_eq!(reader.schema().get(1).unwrap().name, "name");
+
+        let block = reader.read_all(&allocator::GLOBAL).unwrap();
+        assert_eq!(block.rows(), 3);
+
+        let value = |col, row| column_value(block.column(col).unwrap(), row).unwrap().into_owned();
+        assert_eq!(value(1, 1), Value::TEXT("b,\"c\"".into()));
+        assert_eq!(value(1, 2), Value::TEXT("first\nsecond line".into()));
+        assert_eq!(value(2, 1), Value::NULL);
+        assert_eq!(value(2, 2), Value::FLOAT64(1000.0));
+    }
+
+    #[test]
+    fn stray_quotes() {
+        // Quotes inside an unquoted field are part of the value, not the start of a quoted field
+        let input = "id,item\n1,5\" pipe\n2,\"quoted\nfield\"\n3,a\"b\"c\n";
+        let mut reader = CsvReader::new(input.as_bytes(), CsvOptions::default(), None).unwrap();
+        let block = reader.read_all(&allocator::GLOBAL).unwrap();
+        assert_eq!(block.rows(), 3);
+
+        let value = |row| column_value(block.column(1).unwrap(), row).unwrap().into_owned();
+        assert_eq!(value(0), Value::TEXT("5\" pipe".into()));
+        assert_eq!(value(1), Value::TEXT("quoted\nfield".into()));
+        assert_eq!(value(2), Value::TEXT("a\"b\"c".into()));
+
+        // Schema inference reads the unterminated record
+        assert!(CsvReader::new("a\n\"open\n".as_bytes(), CsvOptions::default(), None).is_err());
+    }
+
+    #[test]
+    fn options_and_errors() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("a", false, Type::UINT32),
+            Attribute::new("b", true, Type::BOOLEAN),
+        ]).unwrap();
+
+        let options = CsvOptions { delimiter: b';', header: false, null: "NA".to_string(), ..Default::default() };
+
+        let input = "1;true\n2;NA\n3;maybe\n";
+        let mut reader = CsvReader::new(input.as_bytes(), options.clone(), Some(schema.clone())).unwrap();
+        let block = reader.read_block(&allocator::GLOBAL, 2).unwrap().unwrap();
+        assert_eq!(column_value(block.column(1).unwrap(), 1).unwrap(), Value::NULL);
+
+        match reader.read_block(&allocator::GLOBAL, 2) {
+            Err(DBError::Parse { line: 3, column: 2, ref msg, .. }) => assert_eq!(msg, "maybe is not a BOOLEAN"),
+            _ => panic!("Expected a CSV error"),
+        }
+
+        let input = "1;true;x\n";
+        let mut reader = CsvReader::new(input.as_bytes(), options, Some(schema)).unwrap();
+        assert!(reader.read_block(&allocator::GLOBAL, 2).is_err());
+    }
+
+    #[test]
+    fn scan_chunks() {
+        let input = "v\n1\n2\n3\n";
+        let scan = CsvScan::new(CsvReader::new(input.as_bytes(), CsvOptions::default(), None).unwrap());
+
+        let mut cursor = scan.cursor(&allocator::GLOBAL, &CancelToken::new()).unwrap();
+        assert!(scan.cursor(&allocator::GLOBAL, &CancelToken::new()).is_err(), "Input can only be scanned once");
+
+        assert_eq!(cursor.schema().get(0).unwrap().dtype, Type::INT64);
+
+        let mut chunks = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            chunks.push(view.rows());
+        }
+        assert_eq!(chunks, vec![2, 1]);
+    }
+
+    #[test]
+    fn null_after_inference() {
+        // The NULL is past the records used for inference
+        let mut input = "v\n".to_string();
+        for row in 0 .. INFER_RECORDS + 10 {
+            input += &format!("{}\n", row);
+        }
+        input += "NA\n7\n";
+
+        let options = CsvOptions { null: "NA".to_string(), ..Default::default() };
+        let scan = CsvScan::new(CsvReader::new(input.as_bytes(), options, None).unwrap());
+        assert!(scan.schema().get(0).unwrap().nullable);
+
+        let mut cursor = scan.bind(&ExecContext::default()).unwrap();
+        let mut values = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(64).unwrap() {
+            for row in 0 .. view.rows() {
+                values.push(column_value(view.column(0).unwrap(), row).unwrap().into_owned());
+            }
+        }
+
+        assert_eq!(values.len(), INFER_RECORDS + 12);
+        assert_eq!(&values[INFER_RECORDS + 10 ..], &[Value::NULL, Value::INT64(7)]);
+    }
+
+    #[test]
+    fn write_cursor() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("n", true, Type::FLOAT64),
+            Attribute::new("t", true, Type::TEXT),
+            Attribute::new("b", false, Type::BLOB),
+            Attribute::new("ok", false, Type::BOOLEAN),
+        ]).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+        set_column_value(&mut block, 0, 0, &0.5f64).unwrap();
+        set_column_value(&mut block, 0, 1, &NULL_VALUE).unwrap();
+        set_column_value(&mut block, 1, 0, &"").unwrap();
+        set_column_value(&mut block, 1, 1, &"say \"hi\", x").unwrap();
+        (&b"\x01\xff"[..]).set_row(&mut block[2], 0).unwrap();
+        (&b"dbkit"[..]).set_row(&mut block[2], 1).unwrap();
+        true.set_row(&mut block[3], 0).unwrap();
+        false.set_row(&mut block[3], 1).unwrap();
+
+        let mut writer = CsvWriter::new(Vec::new(), CsvOptions::default());
+        let mut cursor = ScanView::new(&block, None).bind(&ExecContext::default()).unwrap();
+        assert_eq!(writer.write_cursor(&mut *cursor).unwrap(), 2);
+
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(text, "n,t,b,ok\n0.5,\"\",01ff,true\n,\"say \"\"hi\"\", x\",64626b6974,false\n");
+
+        let mut writer = CsvWriter::new(Vec::new(), CsvOptions::default()).with_blob_format(BlobFormat::BASE64);
+        writer.write_view(&block).unwrap();
+        assert!(String::from_utf8(writer.into_inner()).unwrap().contains(",Af8=,"));
+        assert_eq!(base64_encode(b"dbkit"), "ZGJraXQ=");
+    }
+}
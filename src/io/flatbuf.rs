@@ -0,0 +1,338 @@
+// vim : set ts=4 sw=4 et :
+
+//! Minimal FlatBuffers encoding & decoding; just enough for the Arrow IPC metadata.
+//!
+//! Objects are written front to back: a table (or vector) is written before the objects it
+//! references, so all offsets point forward as required by the format.
+
+use std::cmp::Reverse;
+use std::mem;
+use std::ptr;
+
+/// Table field value
+pub enum Field {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    /// Reference to another object
+    Object(Object),
+}
+
+pub enum Object {
+    /// Fields by id; None for absent (default) fields
+    Table(Vec<Option<Field>>),
+    String(String),
+    /// Vector of tables
+    Tables(Vec<Object>),
+    /// Vector of inline structs: element size and the raw (little endian) elements
+    Structs(usize, Vec<u8>),
+}
+
+impl Field {
+    fn size(&self) -> usize {
+        match *self {
+            Field::Bool(_) | Field::U8(_)   => 1,
+            Field::I16(_)                   => 2,
+            Field::I32(_) | Field::Object(_)=> 4,
+            Field::I64(_)                   => 8,
+        }
+    }
+}
+
+/// Serialize the root object. The output is padded to 8 bytes.
+pub fn finish(root: &Object) -> Vec<u8> {
+    let mut buf = vec![0u8; 4];
+    let pos = write_object(&mut buf, root);
+    patch_u32(&mut buf, 0, pos as u32);
+
+    pad(&mut buf, 8);
+    buf
+}
+
+fn pad(buf: &mut Vec<u8>, align: usize) {
+    while buf.len() % align != 0 {
+        buf.push(0);
+    }
+}
+
+/// Store a (little endian) scalar at `pos`
+fn put<T: Copy>(buf: &mut [u8], pos: usize, value: T) {
+    assert!(pos + mem::size_of::<T>() <= buf.len());
+    unsafe { ptr::write_unaligned(buf.as_mut_ptr().offset(pos as isize) as *mut T, value) }
+}
+
+/// Append a (little endian) scalar
+fn push<T: Copy>(buf: &mut Vec<u8>, value: T) {
+    let pos = buf.len();
+    buf.resize(pos + mem::size_of::<T>(), 0);
+    put(buf, pos, value);
+}
+
+pub fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    push(buf, value.to_le());
+}
+
+pub fn push_i64(buf: &mut Vec<u8>, value: i64) {
+    push(buf, value.to_le());
+}
+
+fn patch_u32(buf: &mut [u8], pos: usize, value: u32) {
+    put(buf, pos, value.to_le());
+}
+
+/// Write the object at the end of the buffer. Returns the position offsets have to point to.
+fn write_object(buf: &mut Vec<u8>, obj: &Object) -> usize {
+    match *obj {
+        Object::Table(ref fields) => write_table(buf, fields),
+        Object::String(ref s) => {
+            pad(buf, 4);
+            let pos = buf.len();
+            push(buf, (s.len() as u32).to_le());
+            buf.extend_from_slice(s.as_bytes());
+            buf.push(0);
+            pos
+        }
+        Object::Tables(ref tables) => {
+            pad(buf, 4);
+            let pos = buf.len();
+            push(buf, (tables.len() as u32).to_le());
+
+            let slots = buf.len();
+            buf.resize(slots + tables.len() * 4, 0);
+
+            for (idx, table) in tables.iter().enumerate() {
+                let slot = slots + idx * 4;
+                let target = write_object(buf, table);
+                patch_u32(buf, slot, (target - slot) as u32);
+            }
+
+            pos
+        }
+        Object::Structs(size, ref data) => {
+            // Elements (up to 8 byte scalars) follow the length
+            while (buf.len() + 4) % 8 != 0 {
+                buf.push(0);
+            }
+
+            let pos = buf.len();
+            push(buf, ((data.len() / size) as u32).to_le());
+            buf.extend_from_slice(data);
+            pos
+        }
+    }
+}
+
+fn write_table(buf: &mut Vec<u8>, fields: &[Option<Field>]) -> usize {
+    // Lay out the fields after the vtable offset, largest first to keep them aligned
+    let mut order: Vec<usize> = (0 .. fields.len()).filter(|id| fields[*id].is_some()).collect();
+    order.sort_by_key(|id| Reverse(fields[*id].as_ref().unwrap().size()));
+
+    let mut layout = vec![0u16; fields.len()];
+    let mut end = 4;
+    for id in order {
+        let size = fields[id].as_ref().unwrap().size();
+        end = (end + size - 1) / size * size;
+        layout[id] = end as u16;
+        end += size;
+    }
+
+    pad(buf, 2);
+    let vtable = buf.len();
+    push(buf, ((4 + 2 * fields.len()) as u16).to_le());
+    push(buf, (end as u16).to_le());
+    for off in &layout {
+        push(buf, off.to_le());
+    }
+
+    pad(buf, 8);
+    let table = buf.len();
+    push(buf, ((table - vtable) as i32).to_le());
+    buf.resize(table + end, 0);
+
+    for (id, field) in fields.iter().enumerate() {
+        let pos = table + layout[id] as usize;
+        match *field {
+            Some(Field::Bool(v))    => buf[pos] = v as u8,
+            Some(Field::U8(v))      => buf[pos] = v,
+            Some(Field::I16(v))     => put(buf, pos, v.to_le()),
+            Some(Field::I32(v))     => put(buf, pos, v.to_le()),
+            Some(Field::I64(v))     => put(buf, pos, v.to_le()),
+            _                       => (),
+        }
+    }
+
+    for (id, field) in fields.iter().enumerate() {
+        if let Some(Field::Object(ref obj)) = *field {
+            let slot = table + layout[id] as usize;
+            let target = write_object(buf, obj);
+            patch_u32(buf, slot, (target - slot) as u32);
+        }
+    }
+
+    table
+}
+
+fn read_bytes(buf: &[u8], pos: usize, len: usize) -> Option<&[u8]> {
+    buf.get(pos .. pos.checked_add(len)?)
+}
+
+/// Load a scalar stored at `pos`, None if out of bounds
+fn get<T: Copy>(buf: &[u8], pos: usize) -> Option<T> {
+    let bytes = read_bytes(buf, pos, mem::size_of::<T>())?;
+    Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    get(buf, pos).map(u16::from_le)
+}
+
+pub fn read_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    get(buf, pos).map(u32::from_le)
+}
+
+pub fn read_i64(buf: &[u8], pos: usize) -> Option<i64> {
+    get(buf, pos).map(i64::from_le)
+}
+
+/// Read only view of a serialized table. Accessors return None for absent fields and malformed
+/// data.
+#[derive(Clone, Copy)]
+pub struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    pub fn root(buf: &'a [u8]) -> Option<Table<'a>> {
+        let pos = read_u32(buf, 0)? as usize;
+        Table::at(buf, pos)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> Option<Table<'a>> {
+        // Validate the vtable reference up front
+        let table = Table { buf: buf, pos: pos };
+        table.vtable()?;
+        Some(table)
+    }
+
+    fn vtable(&self) -> Option<usize> {
+        let soffset = read_u32(self.buf, self.pos)? as i32 as isize;
+        let vtable = self.pos as isize - soffset;
+        if vtable < 0 { None } else { Some(vtable as usize) }
+    }
+
+    /// Position of the field's data
+    fn field(&self, id: usize) -> Option<usize> {
+        let vtable = self.vtable()?;
+        let slot = 4 + 2 * id;
+
+        if slot >= read_u16(self.buf, vtable)? as usize {
+            return None
+        }
+
+        match read_u16(self.buf, vtable + slot)? {
+            0   => None,
+            off => Some(self.pos + off as usize),
+        }
+    }
+
+    /// Position of the object referenced by the field
+    fn object(&self, id: usize) -> Option<usize> {
+        let pos = self.field(id)?;
+        pos.checked_add(read_u32(self.buf, pos)? as usize)
+    }
+
+    pub fn bool(&self, id: usize) -> Option<bool> {
+        self.u8(id).map(|v| v != 0)
+    }
+
+    pub fn u8(&self, id: usize) -> Option<u8> {
+        self.field(id).and_then(|pos| self.buf.get(pos).cloned())
+    }
+
+    pub fn i16(&self, id: usize) -> Option<i16> {
+        self.field(id).and_then(|pos| read_u16(self.buf, pos)).map(|v| v as i16)
+    }
+
+    pub fn i32(&self, id: usize) -> Option<i32> {
+        self.field(id).and_then(|pos| read_u32(self.buf, pos)).map(|v| v as i32)
+    }
+
+    pub fn i64(&self, id: usize) -> Option<i64> {
+        self.field(id).and_then(|pos| read_i64(self.buf, pos))
+    }
+
+    pub fn table(&self, id: usize) -> Option<Table<'a>> {
+        Table::at(self.buf, self.object(id)?)
+    }
+
+    pub fn string(&self, id: usize) -> Option<&'a str> {
+        let pos = self.object(id)?;
+        let len = read_u32(self.buf, pos)? as usize;
+        ::std::str::from_utf8(read_bytes(self.buf, pos + 4, len)?).ok()
+    }
+
+    pub fn tables(&self, id: usize) -> Option<Vec<Table<'a>>> {
+        let pos = self.object(id)?;
+        let len = read_u32(self.buf, pos)? as usize;
+
+        (0 .. len)
+            .map(|idx| {
+                let slot = pos + 4 + idx * 4;
+                Table::at(self.buf, slot.checked_add(read_u32(self.buf, slot)? as usize)?)
+            })
+            .collect()
+    }
+
+    /// Raw elements of a vector of `size` byte structs
+    pub fn structs(&self, id: usize, size: usize) -> Option<&'a [u8]> {
+        let pos = self.object(id)?;
+        let len = read_u32(self.buf, pos)? as usize;
+        read_bytes(self.buf, pos + 4, len.checked_mul(size)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let root = Object::Table(vec![
+            Some(Field::I16(4)),
+            None,
+            Some(Field::Object(Object::String("name".to_string()))),
+            Some(Field::I64(-7)),
+            Some(Field::Object(Object::Tables(vec![
+                Object::Table(vec![Some(Field::Bool(true))]),
+                Object::Table(vec![]),
+            ]))),
+            Some(Field::Object(Object::Structs(16, (0 .. 32).collect()))),
+            Some(Field::U8(3)),
+        ]);
+
+        let buf = finish(&root);
+        assert_eq!(buf.len() % 8, 0);
+
+        let table = Table::root(&buf).unwrap();
+        assert_eq!(table.i16(0), Some(4));
+        assert_eq!(table.i32(1), None);
+        assert_eq!(table.string(2), Some("name"));
+        assert_eq!(table.i64(3), Some(-7));
+        assert_eq!(table.u8(6), Some(3));
+        assert_eq!(table.i64(9), None);
+
+        let tables = table.tables(4).unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].bool(0), Some(true));
+        assert_eq!(tables[1].bool(0), None);
+
+        let structs = table.structs(5, 16).unwrap();
+        assert_eq!(structs.len(), 32);
+        assert_eq!(read_i64(structs, 8), Some(0x0f0e0d0c0b0a0908));
+
+        assert!(Table::root(&buf[.. 2]).is_none());
+    }
+}
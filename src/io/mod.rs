@@ -0,0 +1,16 @@
+// vim : set ts=4 sw=4 et :
+
+/// Delimited text (CSV) input & output
+pub mod csv;
+
+/// Apache Arrow IPC stream format
+pub mod arrow;
+
+/// Apache Parquet file reader
+pub mod parquet;
+
+/// PostgreSQL wire protocol result encoding
+pub mod pgwire;
+
+mod flatbuf;
+mod thrift;
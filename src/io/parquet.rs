@@ -0,0 +1,1214 @@
+// vim : set ts=4 sw=4 et :
+
+//! Apache Parquet file reader.
+//!
+//! Reads flat (non nested) files one row group at a time, decoding only the requested columns.
+//! Physical types map to dbkit types:
+//!
+//! | Parquet                                   | dbkit                         |
+//! |-------------------------------------------|-------------------------------|
+//! | BOOLEAN                                   | BOOLEAN                       |
+//! | INT32, INT64                              | INT32, INT64 (UINT* if unsigned) |
+//! | FLOAT, DOUBLE                             | FLOAT32, FLOAT64              |
+//! | BYTE_ARRAY                                | TEXT (UTF8, ENUM), JSON, BLOB |
+//! | INT96, FIXED_LEN_BYTE_ARRAY               | BLOB                          |
+//!
+//! Other logical types (dates, timestamps, decimals) are read as their physical type. Supported
+//! encodings are PLAIN, dictionary and RLE (booleans); supported codecs are UNCOMPRESSED and
+//! SNAPPY.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::rc::Rc;
+use std::str;
+
+use ::allocator::Allocator;
+use ::block::{Block, ColumnStats, View, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::operation::{Cursor, CursorChunk, Operation};
+use ::operation::aggregate::{aggregates_with, merge_stats};
+use ::operation::scan_view::{ScanCounters, ScanPredicate};
+use ::plan::Aggregate;
+use ::row::{RowOffset, RowRange};
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value, NULL_VALUE};
+use ::util::bitmap::get_bit;
+use ::util::copy_value::set_column_value;
+use ::util::snappy;
+
+use super::flatbuf::{read_i64, read_u32};
+use super::thrift::{self, Struct};
+
+const MAGIC: &'static [u8] = b"PAR1";
+
+// Physical types
+const BOOLEAN: i64 = 0;
+const INT32: i64 = 1;
+const INT64: i64 = 2;
+const INT96: i64 = 3;
+const FLOAT: i64 = 4;
+const DOUBLE: i64 = 5;
+const BYTE_ARRAY: i64 = 6;
+const FIXED_LEN_BYTE_ARRAY: i64 = 7;
+
+// Converted types
+const CONVERTED_UTF8: i64 = 0;
+const CONVERTED_ENUM: i64 = 4;
+const CONVERTED_UINT_8: i64 = 11;
+const CONVERTED_UINT_64: i64 = 14;
+const CONVERTED_JSON: i64 = 19;
+
+// LogicalType union
+const LOGICAL_STRING: i16 = 1;
+const LOGICAL_ENUM: i16 = 4;
+const LOGICAL_INTEGER: i16 = 10;
+const LOGICAL_JSON: i16 = 12;
+
+// Field repetition
+const OPTIONAL: i64 = 1;
+const REPEATED: i64 = 2;
+
+// Compression codecs
+const UNCOMPRESSED: i64 = 0;
+const SNAPPY: i64 = 1;
+
+// Page types
+const DATA_PAGE: i64 = 0;
+const DICTIONARY_PAGE: i64 = 2;
+const DATA_PAGE_V2: i64 = 3;
+
+// Encodings
+const PLAIN: i64 = 0;
+const PLAIN_DICTIONARY: i64 = 2;
+const RLE: i64 = 3;
+const RLE_DICTIONARY: i64 = 8;
+
+fn error<S: Into<String>>(msg: S) -> DBError {
+    DBError::Parquet(msg.into())
+}
+
+/// Physical layout of a column
+#[derive(Clone, Copy)]
+struct ColumnDesc {
+    physical: i64,
+    /// Value size of FIXED_LEN_BYTE_ARRAY (and INT96) columns
+    width: usize,
+}
+
+/// Location & statistics of a column chunk
+struct ChunkMeta {
+    codec: i64,
+    values: usize,
+    start: u64,
+    size: usize,
+    stats: Option<ColumnStats>,
+}
+
+struct RowGroupMeta {
+    rows: RowOffset,
+    columns: Vec<ChunkMeta>,
+}
+
+/// Reads Parquet files into Blocks, one row group at a time.
+pub struct ParquetReader<R: Read + Seek> {
+    input: R,
+    schema: Schema,
+    columns: Vec<ColumnDesc>,
+    groups: Vec<RowGroupMeta>,
+}
+
+impl<R: Read + Seek> ParquetReader<R> {
+    /// Reads the file's footer (metadata)
+    pub fn new(mut input: R) -> Result<ParquetReader<R>, DBError> {
+        let file_len = input.seek(SeekFrom::End(0))?;
+        if file_len < 12 {
+            return Err(error("file too short"))
+        }
+
+        let mut tail = [0u8; 8];
+        input.seek(SeekFrom::End(-8))?;
+        input.read_exact(&mut tail)?;
+        if &tail[4 ..] != MAGIC {
+            return Err(error("missing magic number"))
+        }
+
+        let footer_len = read_u32(&tail, 0).unwrap() as u64;
+        if footer_len + 12 > file_len {
+            return Err(error("invalid footer length"))
+        }
+
+        let mut footer = vec![0u8; footer_len as usize];
+        input.seek(SeekFrom::Start(file_len - 8 - footer_len))?;
+        input.read_exact(&mut footer)?;
+
+        let meta = thrift::read_struct(&footer, &mut 0).ok_or_else(|| error("invalid file metadata"))?;
+        let (schema, columns) = read_schema(&meta)?;
+        let groups = read_row_groups(&meta, &schema, file_len)?;
+
+        Ok(ParquetReader { input: input, schema: schema, columns: columns, groups: groups })
+    }
+
+    /// Schema of all the file's columns
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn row_groups(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Total number of rows
+    pub fn rows(&self) -> RowOffset {
+        self.groups.iter().map(|g| g.rows).sum()
+    }
+
+    pub fn row_group_rows(&self, group: usize) -> Option<RowOffset> {
+        self.groups.get(group).map(|g| g.rows)
+    }
+
+    /// Column chunk statistics (from the file metadata), if the writer stored them
+    pub fn statistics(&self, group: usize, column: usize) -> Option<&ColumnStats> {
+        self.groups.get(group)?.columns.get(column)?.stats.as_ref()
+    }
+
+    /// Read a row group into a new Block. Only the `columns` (positions in the file's schema) are
+    /// decoded; they're the Block's columns in the given order.
+    pub fn read_row_group<'a>(&mut self, alloc: &'a Allocator, group: usize, columns: &[usize])
+        -> Result<Block<'a>, DBError>
+    {
+        let rows = self.row_group_rows(group).ok_or(DBError::RowOutOfBounds)?;
+
+        let mut attrs = Vec::with_capacity(columns.len());
+        for col in columns {
+            attrs.push(self.schema.get(*col)?.clone());
+        }
+
+        let mut block = Block::new(alloc, &Schema::from_vec(attrs)?);
+        if rows > 0 {
+            block.add_rows(rows)?;
+        }
+
+        for (pos, col) in columns.iter().enumerate() {
+            let (values, nulls) = self.read_chunk(group, *col)?;
+            let attr = self.schema.get(*col)?;
+
+            if nulls.len() != rows {
+                return Err(error(format!("{}: {} values, expected {}", attr.name, nulls.len(), rows)))
+            }
+
+            let mut next = 0;
+            for row in 0 .. rows {
+                if nulls[row] {
+                    set_column_value(&mut block, pos, row, &NULL_VALUE)?;
+                } else {
+                    set_column_value(&mut block, pos, row, &values.value(next, attr)?)?;
+                    next += 1;
+                }
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Decode a column chunk into its (non NULL) values and the per row NULL flags
+    fn read_chunk(&mut self, group: usize, col: usize) -> Result<(Values, Vec<bool>), DBError> {
+        let (desc, nullable) = (self.columns[col], self.schema.get(col)?.nullable);
+        let chunk = &self.groups[group].columns[col];
+
+        let mut buf = vec![0u8; chunk.size];
+        self.input.seek(SeekFrom::Start(chunk.start))?;
+        self.input.read_exact(&mut buf)?;
+
+        let mut dictionary = None;
+        let mut values = Values::new(desc.physical);
+        let mut nulls = Vec::with_capacity(chunk.values);
+        let mut pos = 0;
+
+        while nulls.len() < chunk.values {
+            let header = thrift::read_struct(&buf, &mut pos).ok_or_else(|| error("invalid page header"))?;
+            let uncompressed = bounded(header.int(2), i32::max_value() as usize, "page size")?;
+            let compressed = bounded(header.int(3), buf.len(), "compressed page size")?;
+            let remaining = chunk.values - nulls.len();
+
+            let page = buf.get(pos .. pos.saturating_add(compressed)).ok_or_else(|| error("page out of bounds"))?;
+            pos += compressed;
+
+            match header.int(1) {
+                Some(DICTIONARY_PAGE) => {
+                    let h = header.structure(7).ok_or_else(|| error("missing dictionary page header"))?;
+                    let data = decompress(chunk.codec, page, uncompressed)?;
+
+                    let mut dict = Values::new(desc.physical);
+                    // At most a value per bit (BOOLEAN)
+                    let count = bounded(h.int(1), data.len().saturating_mul(8), "dictionary size")?;
+                    dict.extend_plain(&data, count, desc.width)?;
+                    dictionary = Some(dict);
+                }
+                Some(DATA_PAGE) => {
+                    let h = header.structure(5).ok_or_else(|| error("missing data page header"))?;
+                    let count = bounded(h.int(1), remaining, "page value count")?;
+                    let data = decompress(chunk.codec, page, uncompressed)?;
+
+                    // Definition levels are prefixed with their length
+                    let mut start = 0;
+                    if nullable {
+                        let len = read_u32(&data, 0).ok_or_else(|| error("missing definition levels"))? as usize;
+                        let levels = data.get(4 .. 4 + len).ok_or_else(|| error("invalid definition levels"))?;
+                        nulls.extend(decode_levels(levels, count)?);
+                        start = 4 + len;
+                    } else {
+                        nulls.extend((0 .. count).map(|_| false));
+                    }
+
+                    let present = count - nulls[nulls.len() - count ..].iter().filter(|n| **n).count();
+                    let encoding = h.int(2).unwrap_or(PLAIN);
+                    values.extend(&data[start ..], encoding, present, desc.width, dictionary.as_ref())?;
+                }
+                Some(DATA_PAGE_V2) => {
+                    let h = header.structure(8).ok_or_else(|| error("missing data page header"))?;
+                    let count = bounded(h.int(1), remaining, "page value count")?;
+                    let null_count = bounded(h.int(2), count, "page NULL count")?;
+                    let def_len = bounded(h.int(5), page.len(), "definition levels length")?;
+                    let rep_len = bounded(h.int(6), page.len(), "repetition levels length")?;
+
+                    // Levels are never compressed
+                    let levels_end = def_len.checked_add(rep_len).filter(|l| *l <= page.len() && *l <= uncompressed)
+                        .ok_or_else(|| error("invalid level lengths"))?;
+
+                    if nullable {
+                        nulls.extend(decode_levels(&page[rep_len .. levels_end], count)?);
+                    } else if null_count > 0 {
+                        return Err(error("NULLs in a required column"))
+                    } else {
+                        nulls.extend((0 .. count).map(|_| false));
+                    }
+
+                    let data = if h.bool(7).unwrap_or(true) {
+                        decompress(chunk.codec, &page[levels_end ..], uncompressed - levels_end)?
+                    } else {
+                        Cow::Borrowed(&page[levels_end ..])
+                    };
+
+                    let present = count - nulls[nulls.len() - count ..].iter().filter(|n| **n).count();
+                    let encoding = h.int(4).unwrap_or(PLAIN);
+                    values.extend(&data, encoding, present, desc.width, dictionary.as_ref())?;
+                }
+                // Index pages
+                _ => (),
+            }
+
+            if pos >= buf.len() && nulls.len() < chunk.values {
+                return Err(error("column chunk truncated"))
+            }
+        }
+
+        Ok((values, nulls))
+    }
+}
+
+/// Schema & physical layout of the leaf columns
+fn read_schema(meta: &Struct) -> Result<(Schema, Vec<ColumnDesc>), DBError> {
+    let elements = meta.structs(2).ok_or_else(|| error("missing schema"))?;
+
+    let mut attrs = Vec::new();
+    let mut columns = Vec::new();
+
+    // The first element is the root of the (flat) schema
+    for elem in elements.iter().skip(1) {
+        let name = elem.string(4).unwrap_or("");
+
+        if elem.int(5).unwrap_or(0) > 0 || elem.int(3) == Some(REPEATED) {
+            return Err(DBError::UnknownType(format!("{}: nested Parquet column", name)))
+        }
+
+        let physical = elem.int(1).ok_or_else(|| error(format!("{}: missing type", name)))?;
+        let width = match physical {
+            INT96                   => 12,
+            FIXED_LEN_BYTE_ARRAY    => elem.int(2).unwrap_or(0) as usize,
+            _                       => 0,
+        };
+
+        let mut attr = Attribute::new(name, elem.int(3) == Some(OPTIONAL), column_type(elem, physical)?);
+        attr.field_id = elem.int(9).map(|id| id as i32);
+        attrs.push(attr);
+        columns.push(ColumnDesc { physical: physical, width: width });
+    }
+
+    // File key_value_metadata
+    let mut schema = Schema::from_vec(attrs)?;
+    for kv in meta.structs(5).unwrap_or_default() {
+        if let Some(key) = kv.string(1) {
+            schema = schema.with_metadata(key, kv.string(2).unwrap_or(""));
+        }
+    }
+
+    Ok((schema, columns))
+}
+
+fn column_type(elem: &Struct, physical: i64) -> Result<Type, DBError> {
+    let converted = elem.int(6);
+    let logical = elem.structure(10).and_then(|l| l.fields.first());
+    let logical_id = logical.map(|l| l.0);
+
+    let unsigned = converted.map_or(false, |c| c >= CONVERTED_UINT_8 && c <= CONVERTED_UINT_64)
+        || logical.map_or(false, |l| match l.1 {
+            thrift::Value::Struct(ref int) => l.0 == LOGICAL_INTEGER && int.bool(2) == Some(false),
+            _ => false,
+        });
+
+    let dtype = match physical {
+        BOOLEAN     => Type::BOOLEAN,
+        INT32       => if unsigned { Type::UINT32 } else { Type::INT32 },
+        INT64       => if unsigned { Type::UINT64 } else { Type::INT64 },
+        FLOAT       => Type::FLOAT32,
+        DOUBLE      => Type::FLOAT64,
+        BYTE_ARRAY  => {
+            if converted == Some(CONVERTED_JSON) || logical_id == Some(LOGICAL_JSON) {
+                Type::JSON
+            } else if converted == Some(CONVERTED_UTF8) || converted == Some(CONVERTED_ENUM)
+                || logical_id == Some(LOGICAL_STRING) || logical_id == Some(LOGICAL_ENUM) {
+                Type::TEXT
+            } else {
+                Type::BLOB
+            }
+        }
+        INT96 | FIXED_LEN_BYTE_ARRAY => Type::BLOB,
+        _           => return Err(DBError::UnknownType(format!("Parquet physical type {}", physical))),
+    };
+
+    Ok(dtype)
+}
+
+fn read_row_groups(meta: &Struct, schema: &Schema, file_len: u64) -> Result<Vec<RowGroupMeta>, DBError> {
+    let groups = meta.structs(4).unwrap_or_default();
+    let mut out = Vec::with_capacity(groups.len());
+
+    for group in groups {
+        let rows = bounded(group.int(3), usize::max_value(), "row group rows")?;
+        let chunks = group.structs(1).ok_or_else(|| error("missing column chunks"))?;
+
+        if chunks.len() != schema.count() {
+            return Err(error(format!("{} column chunks, expected {}", chunks.len(), schema.count())))
+        }
+
+        let mut columns = Vec::with_capacity(chunks.len());
+        for (pos, chunk) in chunks.iter().enumerate() {
+            let attr = schema.get(pos)?;
+
+            if chunk.string(1).is_some() {
+                return Err(error(format!("{}: column data in another file", attr.name)))
+            }
+
+            let meta = chunk.structure(3).ok_or_else(|| error("missing column metadata"))?;
+            let data_offset = meta.int(9).unwrap_or(0);
+            let start = match meta.int(11) {
+                // Some writers store 0 when there's no dictionary page
+                Some(dict) if dict > 0 && dict < data_offset => dict,
+                _                                           => data_offset,
+            };
+
+            let size = meta.int(7).unwrap_or(0);
+            match start.checked_add(size) {
+                Some(end) if start >= 0 && size >= 0 && end as u64 <= file_len => (),
+                _ => return Err(error(format!("{}: column chunk out of bounds", attr.name))),
+            }
+
+            // Flat columns have a value (or NULL) per row
+            let values = bounded(meta.int(5), rows, "column chunk values")?;
+
+            columns.push(ChunkMeta {
+                codec: meta.int(4).unwrap_or(UNCOMPRESSED),
+                values: values,
+                start: start as u64,
+                size: size as usize,
+                stats: meta.structure(12).map(|s| read_stats(s, attr, rows)),
+            });
+        }
+
+        out.push(RowGroupMeta { rows: rows, columns: columns });
+    }
+
+    Ok(out)
+}
+
+fn read_stats(stats: &Struct, attr: &Attribute, rows: RowOffset) -> ColumnStats {
+    // The deprecated min & max fields use signed comparison; only trust them for signed types
+    let signed = match attr.dtype {
+        Type::INT32 | Type::INT64 | Type::FLOAT32 | Type::FLOAT64 => true,
+        _ => false,
+    };
+
+    let bound = |value_id: i16, legacy_id: i16| {
+        stats.binary(value_id)
+            .or_else(|| if signed { stats.binary(legacy_id) } else { None })
+            .and_then(|raw| stat_value(attr.dtype, raw))
+    };
+
+    ColumnStats {
+        rows: rows,
+        null_count: stats.int(3).unwrap_or(0) as usize,
+        min: bound(6, 2),
+        max: bound(5, 1),
+        distinct_estimate: stats.int(4).unwrap_or(0) as u64,
+    }
+}
+
+/// Decode a PLAIN encoded statistics value
+fn stat_value(dtype: Type, raw: &[u8]) -> Option<Value<'static>> {
+    let value = match dtype {
+        Type::BOOLEAN   => Value::BOOLEAN(*raw.first()? != 0),
+        Type::UINT32    => Value::UINT32(read_u32(raw, 0)?),
+        Type::INT32     => Value::INT32(read_u32(raw, 0)? as i32),
+        Type::UINT64    => Value::UINT64(read_i64(raw, 0)? as u64),
+        Type::INT64     => Value::INT64(read_i64(raw, 0)?),
+        Type::FLOAT32   => Value::FLOAT32(f32::from_bits(read_u32(raw, 0)?)),
+        Type::FLOAT64   => Value::FLOAT64(f64::from_bits(read_i64(raw, 0)? as u64)),
+        Type::TEXT      => Value::TEXT(Cow::Owned(String::from_utf8(raw.to_vec()).ok()?)),
+        Type::BLOB      => Value::BLOB(Cow::Owned(raw.to_vec())),
+        _               => return None,
+    };
+
+    Some(value)
+}
+
+/// Non negative header field, at most `max`; 0 when missing
+fn bounded(value: Option<i64>, max: usize, what: &str) -> Result<usize, DBError> {
+    match value.unwrap_or(0) {
+        n if n >= 0 && n as u64 <= max as u64  => Ok(n as usize),
+        n                                       => Err(error(format!("invalid {} {}", what, n))),
+    }
+}
+
+fn decompress(codec: i64, data: &[u8], size: usize) -> Result<Cow<[u8]>, DBError> {
+    let out = match codec {
+        UNCOMPRESSED    => Cow::Borrowed(data),
+        SNAPPY          => Cow::Owned(snappy::decompress(data).ok_or_else(|| error("invalid snappy data"))?),
+        _               => return Err(error(format!("compression codec {} is not supported", codec))),
+    };
+
+    if out.len() != size {
+        return Err(error("unexpected page size"))
+    }
+
+    Ok(out)
+}
+
+fn varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut out = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        if shift >= 64 {
+            return None
+        }
+        out |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Some(out)
+        }
+        shift += 7;
+    }
+}
+
+/// Decode `count` values of the RLE / bit-packing hybrid encoding
+fn decode_hybrid(data: &[u8], bit_width: usize, count: usize) -> Result<Vec<u32>, DBError> {
+    let invalid = || error("invalid RLE data");
+
+    if bit_width > 32 {
+        return Err(invalid())
+    }
+
+    let mut out = Vec::with_capacity(count);
+    let mut pos = 0;
+
+    while out.len() < count {
+        let header = varint(data, &mut pos).ok_or_else(invalid)?;
+
+        if header & 1 == 0 {
+            // Run of a repeated value
+            let run = min((header >> 1) as usize, count - out.len());
+            let bytes = data.get(pos .. pos + (bit_width + 7) / 8).ok_or_else(invalid)?;
+            let value = bytes.iter().rev().fold(0u32, |acc, b| acc << 8 | *b as u32);
+            pos += bytes.len();
+
+            for _ in 0 .. run {
+                out.push(value);
+            }
+        } else {
+            // Groups of 8 bit-packed values
+            let values = ((header >> 1) as usize).checked_mul(8).ok_or_else(invalid)?;
+            let len = values.checked_mul(bit_width).ok_or_else(invalid)? / 8;
+            let bits = data.get(pos .. pos.checked_add(len).ok_or_else(invalid)?).ok_or_else(invalid)?;
+            pos += len;
+
+            for idx in 0 .. min(values, count - out.len()) {
+                let mut value = 0u32;
+                for bit in 0 .. bit_width {
+                    if get_bit(bits, idx * bit_width + bit) {
+                        value |= 1 << bit;
+                    }
+                }
+                out.push(value);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// NULL flags from the definition levels of a flat, optional column
+fn decode_levels(data: &[u8], count: usize) -> Result<Vec<bool>, DBError> {
+    Ok(decode_hybrid(data, 1, count)?.into_iter().map(|l| l == 0).collect())
+}
+
+/// Decoded column values
+enum Values {
+    BOOLEAN(Vec<bool>),
+    INT32(Vec<i32>),
+    INT64(Vec<i64>),
+    FLOAT(Vec<f32>),
+    DOUBLE(Vec<f64>),
+    BYTES(Vec<Vec<u8>>),
+}
+
+impl Values {
+    fn new(physical: i64) -> Values {
+        match physical {
+            BOOLEAN => Values::BOOLEAN(Vec::new()),
+            INT32   => Values::INT32(Vec::new()),
+            INT64   => Values::INT64(Vec::new()),
+            FLOAT   => Values::FLOAT(Vec::new()),
+            DOUBLE  => Values::DOUBLE(Vec::new()),
+            _       => Values::BYTES(Vec::new()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Values::BOOLEAN(ref v)  => v.len(),
+            Values::INT32(ref v)    => v.len(),
+            Values::INT64(ref v)    => v.len(),
+            Values::FLOAT(ref v)    => v.len(),
+            Values::DOUBLE(ref v)   => v.len(),
+            Values::BYTES(ref v)    => v.len(),
+        }
+    }
+
+    /// Append `count` values of a data page
+    fn extend(&mut self, data: &[u8], encoding: i64, count: usize, width: usize, dictionary: Option<&Values>)
+        -> Result<(), DBError>
+    {
+        match encoding {
+            PLAIN                               => self.extend_plain(data, count, width),
+            PLAIN_DICTIONARY | RLE_DICTIONARY   => {
+                let dict = dictionary.ok_or_else(|| error("missing dictionary page"))?;
+                let bit_width = *data.first().ok_or_else(|| error("missing dictionary indices"))?;
+                let indices = decode_hybrid(&data[1 ..], bit_width as usize, count)?;
+                self.extend_dictionary(dict, &indices)
+            }
+            RLE                                 => {
+                // Only used for booleans; prefixed with the length
+                let values = match *self {
+                    Values::BOOLEAN(ref mut v)  => v,
+                    _                           => return Err(error("RLE encoding of non boolean values")),
+                };
+                let len = read_u32(data, 0).ok_or_else(|| error("invalid RLE data"))? as usize;
+                let encoded = data.get(4 .. 4 + len).ok_or_else(|| error("invalid RLE data"))?;
+                values.extend(decode_hybrid(encoded, 1, count)?.into_iter().map(|v| v != 0));
+                Ok(())
+            }
+            _                                   => Err(error(format!("encoding {} is not supported", encoding))),
+        }
+    }
+
+    fn extend_plain(&mut self, data: &[u8], count: usize, width: usize) -> Result<(), DBError> {
+        let short = || error("data page too short");
+
+        let fixed = match *self {
+            Values::INT32(_) | Values::FLOAT(_)     => 4,
+            Values::INT64(_) | Values::DOUBLE(_)    => 8,
+            _                                       => 0,
+        };
+        if fixed > 0 && count.checked_mul(fixed).map_or(true, |len| len > data.len()) {
+            return Err(short())
+        }
+
+        match *self {
+            Values::BOOLEAN(ref mut v)  => {
+                if (count + 7) / 8 > data.len() {
+                    return Err(short())
+                }
+                v.extend((0 .. count).map(|idx| get_bit(data, idx)));
+            }
+            Values::INT32(ref mut v)    => v.extend((0 .. count).map(|idx| read_u32(data, idx * 4).unwrap() as i32)),
+            Values::INT64(ref mut v)    => v.extend((0 .. count).map(|idx| read_i64(data, idx * 8).unwrap())),
+            Values::FLOAT(ref mut v)    => v.extend((0 .. count).map(|idx| f32::from_bits(read_u32(data, idx * 4).unwrap()))),
+            Values::DOUBLE(ref mut v)   => v.extend((0 .. count).map(|idx| f64::from_bits(read_i64(data, idx * 8).unwrap() as u64))),
+            Values::BYTES(ref mut v)    => {
+                let mut pos = 0;
+                for _ in 0 .. count {
+                    // BYTE_ARRAY values are prefixed with their length, the rest are fixed width
+                    let len = if width > 0 {
+                        width
+                    } else {
+                        let len = read_u32(data, pos).ok_or_else(short)? as usize;
+                        pos += 4;
+                        len
+                    };
+
+                    v.push(data.get(pos .. pos.saturating_add(len)).ok_or_else(short)?.to_vec());
+                    pos += len;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn extend_dictionary(&mut self, dict: &Values, indices: &[u32]) -> Result<(), DBError> {
+        if indices.iter().any(|idx| *idx as usize >= dict.len()) {
+            return Err(error("dictionary index out of bounds"))
+        }
+
+        match (self, dict) {
+            (&mut Values::BOOLEAN(ref mut v), &Values::BOOLEAN(ref d))  => v.extend(indices.iter().map(|i| d[*i as usize])),
+            (&mut Values::INT32(ref mut v), &Values::INT32(ref d))      => v.extend(indices.iter().map(|i| d[*i as usize])),
+            (&mut Values::INT64(ref mut v), &Values::INT64(ref d))      => v.extend(indices.iter().map(|i| d[*i as usize])),
+            (&mut Values::FLOAT(ref mut v), &Values::FLOAT(ref d))      => v.extend(indices.iter().map(|i| d[*i as usize])),
+            (&mut Values::DOUBLE(ref mut v), &Values::DOUBLE(ref d))    => v.extend(indices.iter().map(|i| d[*i as usize])),
+            (&mut Values::BYTES(ref mut v), &Values::BYTES(ref d))      => v.extend(indices.iter().map(|i| d[*i as usize].clone())),
+            _                                                           => return Err(error("dictionary type mismatch")),
+        }
+
+        Ok(())
+    }
+
+    /// Value `idx` as a value of the attribute's type
+    fn value<'v>(&'v self, idx: usize, attr: &Attribute) -> Result<Value<'v>, DBError> {
+        if idx >= self.len() {
+            return Err(error(format!("{}: missing values", attr.name)))
+        }
+
+        let value = match *self {
+            Values::BOOLEAN(ref v)  => Value::BOOLEAN(v[idx]),
+            Values::INT32(ref v)    => if attr.dtype == Type::UINT32 { Value::UINT32(v[idx] as u32) } else { Value::INT32(v[idx]) },
+            Values::INT64(ref v)    => if attr.dtype == Type::UINT64 { Value::UINT64(v[idx] as u64) } else { Value::INT64(v[idx]) },
+            Values::FLOAT(ref v)    => Value::FLOAT32(v[idx]),
+            Values::DOUBLE(ref v)   => Value::FLOAT64(v[idx]),
+            Values::BYTES(ref v)    => match attr.dtype {
+                Type::TEXT | Type::JSON => {
+                    let text = str::from_utf8(&v[idx]).map_err(|_| error(format!("{}: invalid UTF-8", attr.name)))?;
+                    if attr.dtype == Type::JSON { Value::JSON(Cow::Borrowed(text)) } else { Value::TEXT(Cow::Borrowed(text)) }
+                }
+                _                       => Value::BLOB(Cow::Borrowed(&v[idx])),
+            },
+        };
+
+        Ok(value)
+    }
+}
+
+/// Operation scanning a Parquet file.
+///
+/// Only the selected columns are decoded. With a predicate, row groups whose statistics prove that
+/// no row matches are skipped without being read.
+pub struct ParquetScan<R: Read + Seek> {
+    reader: Rc<RefCell<ParquetReader<R>>>,
+    /// Selected columns (positions in the file's schema)
+    columns: Vec<usize>,
+    schema: Schema,
+    /// Predicate column is a position in the file's schema; it doesn't have to be selected
+    pub predicate: Option<ScanPredicate>,
+    counters: Rc<ScanCounters>,
+}
+
+impl<R: Read + Seek> ParquetScan<R> {
+    /// Scan all the columns of the file
+    pub fn new(reader: ParquetReader<R>) -> ParquetScan<R> {
+        let schema = reader.schema().clone();
+        let columns = (0 .. schema.count()).collect();
+
+        ParquetScan {
+            reader: Rc::new(RefCell::new(reader)),
+            columns: columns,
+            schema: schema,
+            predicate: None,
+            counters: Default::default(),
+        }
+    }
+
+    /// Only read the `columns` (positions in the file's schema)
+    pub fn with_columns(self, columns: &[usize]) -> Result<ParquetScan<R>, DBError> {
+        let schema = {
+            let file_schema = self.reader.borrow();
+            let mut attrs = Vec::with_capacity(columns.len());
+            for col in columns {
+                attrs.push(file_schema.schema().get(*col)?.clone());
+            }
+            Schema::from_vec(attrs)?
+        };
+
+        Ok(ParquetScan { columns: columns.to_vec(), schema: schema, ..self })
+    }
+
+    pub fn with_predicate(self, predicate: ScanPredicate) -> ParquetScan<R> {
+        ParquetScan { predicate: Some(predicate), ..self }
+    }
+
+    /// Output schema (selected columns)
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Counters shared with the bound cursors; skipped chunks are row groups
+    pub fn counters(&self) -> Rc<ScanCounters> {
+        self.counters.clone()
+    }
+
+    fn cursor<'a>(&self, alloc: &'a Allocator, cancel: &CancelToken) -> Result<ParquetCursor<'a, R>, DBError> {
+        let mut groups = VecDeque::new();
+
+        {
+            let reader = self.reader.borrow();
+
+            if let Some(ref p) = self.predicate {
+                if p.column >= reader.schema().count() {
+                    return Err(DBError::make_column_unknown_pos(p.column))
+                }
+            }
+
+            for group in 0 .. reader.row_groups() {
+                let stats = self.predicate.as_ref()
+                    .and_then(|p| reader.statistics(group, p.column).map(|s| (p, s)));
+
+                if let Some((p, stats)) = stats {
+                    if !p.might_match(&stats) {
+                        let counters = &self.counters;
+                        counters.skipped_chunks.set(counters.skipped_chunks.get() + 1);
+                        counters.skipped_rows.set(counters.skipped_rows.get() + stats.rows);
+                        continue
+                    }
+                }
+
+                groups.push_back(group);
+            }
+        }
+
+        Ok(ParquetCursor {
+            reader: self.reader.clone(),
+            alloc: alloc,
+            columns: self.columns.clone(),
+            schema: self.schema.clone(),
+            selected: groups.clone(),
+            groups: groups,
+            block: None,
+            offset: 0,
+            cancel: cancel.clone(),
+        })
+    }
+}
+
+impl<'a, R: Read + Seek + 'a> Operation<'a> for ParquetScan<R> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(self.cursor(ctx.allocator(), ctx.cancel_token())?))
+    }
+
+    fn describe(&self) -> String {
+        let mut out = format!("ParquetScan columns={}", self.columns.len());
+        if let Some(ref p) = self.predicate {
+            out += &format!(" predicate=#{} {:?} {}", p.column, p.op, p.value);
+        }
+        out
+    }
+
+    /// Used for skipping row groups, if the scan doesn't have a predicate yet
+    fn push_predicate(&mut self, predicate: &ScanPredicate) -> bool {
+        if self.predicate.is_some() || predicate.column >= self.columns.len() {
+            return false
+        }
+
+        // Output column to file column
+        let column = self.columns[predicate.column];
+        self.predicate = Some(ScanPredicate { column: column, .. predicate.clone() });
+        true
+    }
+}
+
+/// Implementation of the `ParquetScan` operation
+struct ParquetCursor<'a, R: Read + Seek> {
+    reader: Rc<RefCell<ParquetReader<R>>>,
+    alloc: &'a Allocator,
+    columns: Vec<usize>,
+    schema: Schema,
+    /// Row groups not skipped by the predicate
+    selected: VecDeque<usize>,
+    /// Row groups left to read
+    groups: VecDeque<usize>,
+    /// Current row group
+    block: Option<Block<'a>>,
+    /// Next row of the current row group
+    offset: RowOffset,
+    cancel: CancelToken,
+}
+
+impl<'a, R: Read + Seek> Cursor<'a> for ParquetCursor<'a, R> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        while self.block.as_ref().map_or(true, |b| self.offset >= b.rows()) {
+            let group = match self.groups.pop_front() {
+                Some(g) => g,
+                None    => return Ok(CursorChunk::End),
+            };
+
+            let block = self.reader.borrow_mut().read_row_group(self.alloc, group, &self.columns)?;
+            self.block = Some(block);
+            self.offset = 0;
+        }
+
+        let block = self.block.as_ref().unwrap();
+        let range = RowRange { offset: self.offset, rows: min(rows, block.rows() - self.offset) };
+        self.offset += range.rows;
+
+        Ok(CursorChunk::Next(window_alias(block, Some(range))?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    /// Rows of the current (or next) row group
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        match self.block {
+            Some(ref block) => Some(block.rows()),
+            None            => self.groups.front().and_then(|g| self.reader.borrow().row_group_rows(*g)),
+        }
+    }
+
+    /// Row groups are re-read from the file
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.groups = self.selected.clone();
+        self.block = None;
+        self.offset = 0;
+        Ok(())
+    }
+
+    /// From the row group statistics of the file metadata, unless row groups were skipped
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        let reader = self.reader.borrow();
+        let started = self.block.is_some() || self.groups.len() < self.selected.len();
+        if started || self.selected.len() != reader.row_groups() {
+            return None
+        }
+
+        aggregates_with(aggregates, reader.rows(), |pos| {
+            let column = *self.columns.get(pos)?;
+            let mut merged: Option<ColumnStats> = None;
+            for group in 0 .. reader.row_groups() {
+                let stats = reader.statistics(group, column)?;
+                merged = Some(match merged {
+                    Some(ref m) => merge_stats(m, stats),
+                    None        => stats.clone(),
+                });
+            }
+            merged
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor as IOCursor;
+    use ::allocator;
+    use ::block::column_value;
+    use ::expression::comparison::CompareOp;
+    use ::io::thrift::Value as T;
+    use ::io::thrift::encode::{self, make};
+
+    fn int(v: i64) -> T {
+        T::Int(v)
+    }
+
+    fn bin(v: &[u8]) -> T {
+        T::Binary(v.to_vec())
+    }
+
+    fn plain_i32(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| (0 .. 4).map(move |b| (*v >> (8 * b)) as u8)).collect()
+    }
+
+    fn plain_i64(values: &[i64]) -> Vec<u8> {
+        values.iter().flat_map(|v| (0 .. 8).map(move |b| (*v >> (8 * b)) as u8)).collect()
+    }
+
+    fn plain_bytes(values: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for v in values {
+            out.extend(plain_i32(&[v.len() as i32]));
+            out.extend_from_slice(v.as_bytes());
+        }
+        out
+    }
+
+    /// Bit-packed run of 1 bit values (up to 8)
+    fn bits(values: &[bool]) -> Vec<u8> {
+        vec![3, values.iter().enumerate().fold(0u8, |acc, (idx, v)| acc | (*v as u8) << idx)]
+    }
+
+    /// Definition levels of a v1 data page (length prefixed)
+    fn levels(present: &[bool]) -> Vec<u8> {
+        let mut out = plain_i32(&[2]);
+        out.extend(bits(present));
+        out
+    }
+
+    fn page(page_type: i64, header_id: i16, header: Vec<(i16, T)>, uncompressed: usize, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode::value(&mut out, &make(vec![
+            (1, int(page_type)),
+            (2, int(uncompressed as i64)),
+            (3, int(data.len() as i64)),
+            (header_id, make(header)),
+        ]));
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn data_page(count: usize, encoding: i64, data: &[u8]) -> Vec<u8> {
+        let header = vec![(1, int(count as i64)), (2, int(encoding)), (3, int(RLE)), (4, int(RLE))];
+        page(DATA_PAGE, 5, header, data.len(), data)
+    }
+
+    struct Chunk {
+        data: Vec<u8>,
+        values: usize,
+        codec: i64,
+        dictionary: bool,
+        stats: Option<(i64, i64, i64)>,
+    }
+
+    fn chunk(data: Vec<u8>, values: usize) -> Chunk {
+        Chunk { data: data, values: values, codec: UNCOMPRESSED, dictionary: false, stats: None }
+    }
+
+    /// id UINT32, v INT64 (nullable), name TEXT (nullable), flag BOOLEAN, junk FLOAT64
+    fn test_file() -> Vec<u8> {
+        file_with(data_page(3, PLAIN, &plain_i32(&[1, 2, 3])))
+    }
+
+    /// `test_file` with the pages of the first id chunk
+    fn file_with(ids: Vec<u8>) -> Vec<u8> {
+        let columns: Vec<(i64, i64, &str, Option<(i16, T)>)> = vec![
+            (INT32, 0, "id", Some((6, int(13)))),
+            (INT64, OPTIONAL, "v", Some((9, int(7)))),
+            (BYTE_ARRAY, OPTIONAL, "name", Some((10, make(vec![(LOGICAL_STRING, make(vec![]))])))),
+            (BOOLEAN, 0, "flag", None),
+            (DOUBLE, 0, "junk", None),
+        ];
+
+        // Row group 0: dictionary encoded names, v1 pages
+        let mut names = page(DICTIONARY_PAGE, 7, vec![(1, int(2)), (2, int(PLAIN))], 11, &plain_bytes(&["bc", "a"]));
+        let mut indices = levels(&[true, false, true]);
+        indices.extend(vec![1, 3, 0b01]);
+        names.extend(data_page(3, RLE_DICTIONARY, &indices));
+
+        let mut v = levels(&[true, false, true]);
+        v.extend(plain_i64(&[10, -5]));
+
+        let group0 = vec![
+            chunk(ids, 3),
+            Chunk { stats: Some((-5, 10, 1)), ..chunk(data_page(3, PLAIN, &v), 3) },
+            Chunk { dictionary: true, ..chunk(names, 3) },
+            chunk(data_page(3, PLAIN, &bits(&[true, false, true])[1 ..]), 3),
+            chunk(vec![0xFF; 8], 3),
+        ];
+
+        // Row group 1: snappy compressed v2 page
+        let mut compressed = vec![16, 15 << 2];
+        compressed.extend(plain_i64(&[100, 200]));
+        let mut v2 = bits(&[true, true]);
+        v2.extend(compressed);
+        let v2_header = vec![(1, int(2)), (2, int(0)), (3, int(2)), (4, int(PLAIN)), (5, int(2)), (6, int(0))];
+
+        let mut name_data = levels(&[true, true]);
+        name_data.extend(plain_bytes(&["x", "yz"]));
+
+        let group1 = vec![
+            chunk(data_page(2, PLAIN, &plain_i32(&[4, 5])), 2),
+            Chunk { codec: SNAPPY, stats: Some((100, 200, 0)), ..chunk(page(DATA_PAGE_V2, 8, v2_header, 18, &v2), 2) },
+            chunk(data_page(2, PLAIN, &name_data), 2),
+            chunk(data_page(2, PLAIN, &[0]), 2),
+            chunk(vec![0xFF; 8], 2),
+        ];
+
+        let mut file = MAGIC.to_vec();
+        let mut row_groups = Vec::new();
+
+        for (rows, group) in vec![(3, group0), (2, group1)] {
+            let mut chunks = Vec::new();
+
+            for (pos, c) in group.into_iter().enumerate() {
+                let start = file.len() as i64;
+                file.extend_from_slice(&c.data);
+
+                let mut meta = vec![
+                    (1, int(columns[pos].0)),
+                    (2, T::List(vec![int(PLAIN)])),
+                    (3, T::List(vec![bin(columns[pos].2.as_bytes())])),
+                    (4, int(c.codec)),
+                    (5, int(c.values as i64)),
+                    (6, int(c.data.len() as i64)),
+                    (7, int(c.data.len() as i64)),
+                    (9, int(start)),
+                ];
+                if c.dictionary {
+                    meta.push((11, int(start)));
+                }
+                if let Some((min, max, nulls)) = c.stats {
+                    meta.push((12, make(vec![
+                        (3, int(nulls)),
+                        (5, bin(&plain_i64(&[max]))),
+                        (6, bin(&plain_i64(&[min]))),
+                    ])));
+                }
+
+                chunks.push(make(vec![(2, int(start)), (3, make(meta))]));
+            }
+
+            row_groups.push(make(vec![(1, T::List(chunks)), (2, int(0)), (3, int(rows))]));
+        }
+
+        let mut schema = vec![make(vec![(4, bin(b"schema")), (5, int(columns.len() as i64))])];
+        for &(ref physical, ref repetition, ref name, ref extra) in &columns {
+            let mut elem = vec![(1, int(*physical)), (3, int(*repetition)), (4, bin(name.as_bytes()))];
+            elem.extend(extra.clone());
+            schema.push(make(elem));
+        }
+
+        let mut footer = Vec::new();
+        encode::value(&mut footer, &make(vec![
+            (1, int(1)),
+            (2, T::List(schema)),
+            (3, int(5)),
+            (4, T::List(row_groups)),
+            (5, T::List(vec![make(vec![(1, bin(b"writer")), (2, bin(b"test"))])])),
+        ]));
+
+        file.extend_from_slice(&footer);
+        file.extend(plain_i32(&[footer.len() as i32]));
+        file.extend_from_slice(MAGIC);
+        file
+    }
+
+    fn values<'v>(view: &'v View<'v>) -> Vec<Vec<Value<'static>>> {
+        (0 .. view.rows())
+            .map(|row| (0 .. view.schema().count())
+                .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                .collect())
+            .collect()
+    }
+
+    fn text(v: &str) -> Value<'static> {
+        Value::TEXT(Cow::Owned(v.to_string()))
+    }
+
+    #[test]
+    fn read_row_groups() {
+        let mut reader = ParquetReader::new(IOCursor::new(test_file())).unwrap();
+
+        let types: Vec<(Type, bool)> = reader.schema().iter().map(|a| (a.dtype, a.nullable)).collect();
+        assert_eq!(types, vec![
+            (Type::UINT32, false), (Type::INT64, true), (Type::TEXT, true),
+            (Type::BOOLEAN, false), (Type::FLOAT64, false),
+        ]);
+        assert_eq!(reader.schema().find_field_id(7), Some(1));
+        assert_eq!(reader.schema().metadata().get("writer").map(|v| v.as_str()), Some("test"));
+        assert_eq!(reader.row_groups(), 2);
+        assert_eq!(reader.rows(), 5);
+        assert_eq!(reader.statistics(0, 1).unwrap().min, Some(Value::INT64(-5)));
+        assert_eq!(reader.statistics(0, 1).unwrap().null_count, 1);
+
+        let block = reader.read_row_group(&allocator::GLOBAL, 0, &[2, 0, 1, 3]).unwrap();
+        assert_eq!(values(&block), vec![
+            vec![text("a"), Value::UINT32(1), Value::INT64(10), Value::BOOLEAN(true)],
+            vec![Value::NULL, Value::UINT32(2), Value::NULL, Value::BOOLEAN(false)],
+            vec![text("bc"), Value::UINT32(3), Value::INT64(-5), Value::BOOLEAN(true)],
+        ]);
+
+        let block = reader.read_row_group(&allocator::GLOBAL, 1, &[1, 2]).unwrap();
+        assert_eq!(values(&block), vec![
+            vec![Value::INT64(100), text("x")],
+            vec![Value::INT64(200), text("yz")],
+        ]);
+
+        // Only requested columns are decoded
+        assert!(reader.read_row_group(&allocator::GLOBAL, 0, &[4]).is_err());
+        assert!(reader.read_row_group(&allocator::GLOBAL, 2, &[0]).is_err());
+
+        assert!(ParquetReader::new(IOCursor::new(b"PAR1 not a file".to_vec())).is_err());
+    }
+
+    #[test]
+    fn invalid_counts() {
+        let ids = plain_i32(&[1, 2, 3]);
+        let header = |count: i64| vec![(1, int(count)), (2, int(PLAIN)), (3, int(RLE)), (4, int(RLE))];
+
+        // Page value counts past the chunk's values, or negative
+        for count in vec![4, 1 << 40, -1] {
+            let file = file_with(page(DATA_PAGE, 5, header(count), ids.len(), &ids));
+            let mut reader = ParquetReader::new(IOCursor::new(file)).unwrap();
+            assert!(reader.read_row_group(&allocator::GLOBAL, 0, &[0]).is_err());
+            assert_eq!(reader.read_row_group(&allocator::GLOBAL, 1, &[0]).unwrap().rows(), 2);
+        }
+
+        assert_eq!(bounded(Some(3), 3, "count").unwrap(), 3);
+        assert_eq!(bounded(None, 0, "count").unwrap(), 0);
+        assert!(bounded(Some(-1), 3, "count").is_err());
+        assert!(bounded(Some(4), 3, "count").is_err());
+    }
+
+    #[test]
+    fn scan_pruning() {
+        let reader = ParquetReader::new(IOCursor::new(test_file())).unwrap();
+        let scan = ParquetScan::new(reader)
+            .with_columns(&[0, 2]).unwrap()
+            .with_predicate(ScanPredicate::new(1, CompareOp::GT, 50i64));
+        let counters = scan.counters();
+
+        assert_eq!(scan.schema().get(1).unwrap().name, "name");
+
+        let mut cursor = scan.bind(&ExecContext::default()).unwrap();
+        let mut out = Vec::new();
+        loop {
+            match cursor.next(1).unwrap() {
+                CursorChunk::Next(view) => out.extend(values(&view)),
+                CursorChunk::End        => break,
+            }
+        }
+
+        assert_eq!(out, vec![vec![Value::UINT32(4), text("x")], vec![Value::UINT32(5), text("yz")]]);
+        assert_eq!(counters.skipped_chunks.get(), 1);
+        assert_eq!(counters.skipped_rows.get(), 3);
+
+        // Pushed down predicates are over the selected columns
+        let reader = ParquetReader::new(IOCursor::new(test_file())).unwrap();
+        let mut scan = ParquetScan::new(reader).with_columns(&[2, 0, 1]).unwrap();
+        assert!(scan.push_predicate(&ScanPredicate::new(2, CompareOp::GT, 50i64)));
+        assert!(!scan.push_predicate(&ScanPredicate::new(0, CompareOp::EQ, "x")));
+        assert_eq!(scan.predicate, Some(ScanPredicate::new(1, CompareOp::GT, 50i64)));
+    }
+}
@@ -0,0 +1,516 @@
+// vim : set ts=4 sw=4 et :
+
+//! PostgreSQL frontend / backend protocol result encoding.
+//!
+//! Encodes the backend messages of a query result: RowDescription, a DataRow per row and
+//! CommandComplete. Connection handling, authentication and query parsing are left to the
+//! application.
+//!
+//! Type mapping: UINT32 is sent as int8 and UINT64 as numeric since PostgreSQL has no unsigned
+//! types. TIMESTAMP_TZ is sent as timestamptz in UTC. LIST is sent as a (one dimensional) array and STRUCT as a record. MAP columns and LIST
+//! of LIST / MAP aren't supported.
+
+use std::io::Write;
+
+use ::block::{View, column_value};
+use ::error::DBError;
+use ::operation::{Cursor, CursorChunk, DEFAULT_CURSOR_FETCH};
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+use ::util::datetime;
+
+/// Result value format
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    TEXT,
+    BINARY,
+}
+
+impl Format {
+    fn code(self) -> i16 {
+        match self {
+            Format::TEXT    => 0,
+            Format::BINARY  => 1,
+        }
+    }
+}
+
+// Type OIDs
+pub const BOOL_OID: u32 = 16;
+pub const BYTEA_OID: u32 = 17;
+pub const INT8_OID: u32 = 20;
+pub const INT4_OID: u32 = 23;
+pub const TEXT_OID: u32 = 25;
+pub const JSON_OID: u32 = 114;
+pub const FLOAT4_OID: u32 = 700;
+pub const FLOAT8_OID: u32 = 701;
+pub const TIMESTAMP_OID: u32 = 1114;
+pub const TIMESTAMPTZ_OID: u32 = 1184;
+pub const NUMERIC_OID: u32 = 1700;
+pub const RECORD_OID: u32 = 2249;
+
+/// Type OID of the attribute
+pub fn type_oid(attr: &Attribute) -> Result<u32, DBError> {
+    let oid = match attr.dtype {
+        Type::BOOLEAN   => BOOL_OID,
+        Type::INT32     => INT4_OID,
+        Type::UINT32    => INT8_OID,
+        Type::INT64     => INT8_OID,
+        Type::UINT64    => NUMERIC_OID,
+        Type::FLOAT32   => FLOAT4_OID,
+        Type::FLOAT64   => FLOAT8_OID,
+        Type::TEXT      => TEXT_OID,
+        Type::BLOB      => BYTEA_OID,
+        Type::JSON      => JSON_OID,
+        Type::TIMESTAMP     => TIMESTAMP_OID,
+        Type::TIMESTAMP_TZ  => TIMESTAMPTZ_OID,
+        Type::STRUCT    => RECORD_OID,
+        Type::LIST if attr.children[0].dtype != Type::LIST && attr.children[0].dtype != Type::MAP
+                        => array_oid(type_oid(&attr.children[0])?),
+        _               => return Err(DBError::AttributeType(attr.name.clone())),
+    };
+
+    Ok(oid)
+}
+
+/// Type OID of an array of `oid`
+fn array_oid(oid: u32) -> u32 {
+    match oid {
+        BOOL_OID    => 1000,
+        BYTEA_OID   => 1001,
+        INT4_OID    => 1007,
+        TEXT_OID    => 1009,
+        INT8_OID    => 1016,
+        FLOAT4_OID  => 1021,
+        FLOAT8_OID  => 1022,
+        JSON_OID    => 199,
+        TIMESTAMP_OID   => 1115,
+        TIMESTAMPTZ_OID => 1185,
+        NUMERIC_OID => 1231,
+        _           => 2287,
+    }
+}
+
+/// Fixed size of the type's binary representation; -1 for variable length
+fn type_size(dtype: Type) -> i16 {
+    match dtype {
+        Type::BOOLEAN                   => 1,
+        Type::INT32 | Type::FLOAT32     => 4,
+        Type::UINT32 | Type::INT64 | Type::FLOAT64 => 8,
+        Type::TIMESTAMP | Type::TIMESTAMP_TZ => 8,
+        _                               => -1,
+    }
+}
+
+fn push_i16(out: &mut Vec<u8>, v: i16) {
+    out.push((v >> 8) as u8);
+    out.push(v as u8);
+}
+
+fn push_i32(out: &mut Vec<u8>, v: i32) {
+    out.extend((0 .. 4).rev().map(|idx| (v >> (8 * idx)) as u8));
+}
+
+fn push_i64(out: &mut Vec<u8>, v: i64) {
+    out.extend((0 .. 8).rev().map(|idx| (v >> (8 * idx)) as u8));
+}
+
+fn set_i32(out: &mut [u8], pos: usize, v: i32) {
+    for idx in 0 .. 4 {
+        out[pos + idx] = (v >> (8 * (3 - idx))) as u8;
+    }
+}
+
+/// Length prefixed value; -1 length for NULL
+fn push_field<F>(out: &mut Vec<u8>, value: &Value, encode: F) -> Result<(), DBError>
+    where F: FnOnce(&mut Vec<u8>) -> Result<(), DBError>
+{
+    let start = out.len();
+    push_i32(out, -1);
+
+    if *value != Value::NULL {
+        encode(out)?;
+        let len = out.len() - start - 4;
+        set_i32(out, start, len as i32);
+    }
+
+    Ok(())
+}
+
+/// Microseconds from 1970-01-01 to 2000-01-01, the PostgreSQL timestamp epoch
+const PG_EPOCH: i64 = 10957 * datetime::MICROS_PER_DAY;
+
+/// Binary format (big endian) value. Numeric is base 10000 digits.
+fn encode_binary(out: &mut Vec<u8>, attr: &Attribute, value: &Value) -> Result<(), DBError> {
+    match *value {
+        Value::BOOLEAN(v)       => out.push(v as u8),
+        Value::INT32(v)         => push_i32(out, v),
+        Value::UINT32(v)        => push_i64(out, v as i64),
+        Value::INT64(v)         => push_i64(out, v),
+        Value::UINT64(v)        => {
+            let mut digits = Vec::new();
+            let mut rest = v;
+            while rest > 0 {
+                digits.push((rest % 10000) as i16);
+                rest /= 10000;
+            }
+
+            let weight = digits.len() as i16 - 1;
+            // Trailing zero digits are implied by the weight
+            let skip = digits.iter().take_while(|d| **d == 0).count();
+
+            push_i16(out, (digits.len() - skip) as i16);
+            push_i16(out, weight.max(0));
+            push_i16(out, 0);   // sign: positive
+            push_i16(out, 0);   // display scale
+            for digit in digits[skip ..].iter().rev() {
+                push_i16(out, *digit);
+            }
+        }
+        Value::FLOAT32(v)       => push_i32(out, v.to_bits() as i32),
+        Value::FLOAT64(v)       => push_i64(out, v.to_bits() as i64),
+        Value::TIMESTAMP(v) | Value::TIMESTAMP_TZ(v) => {
+            let v = v.checked_sub(PG_EPOCH).ok_or_else(|| DBError::AttributeType(attr.name.clone()))?;
+            push_i64(out, v)
+        }
+        Value::TEXT(ref v)      => out.extend_from_slice(v.as_bytes()),
+        Value::JSON(ref v)      => out.extend_from_slice(v.as_bytes()),
+        Value::BLOB(ref v)      => out.extend_from_slice(v),
+        Value::LIST(ref elems)  => {
+            let elem = &attr.children[0];
+            push_i32(out, 1);   // dimensions
+            push_i32(out, elems.iter().any(|e| *e == Value::NULL) as i32);
+            push_i32(out, type_oid(elem)? as i32);
+            push_i32(out, elems.len() as i32);
+            push_i32(out, 1);   // lower bound
+
+            for e in elems {
+                push_field(out, e, |out| encode_binary(out, elem, e))?;
+            }
+        }
+        Value::STRUCT(ref fields) => {
+            push_i32(out, fields.len() as i32);
+            for (field, value) in attr.children.iter().zip(fields) {
+                push_i32(out, type_oid(field)? as i32);
+                push_field(out, value, |out| encode_binary(out, field, value))?;
+            }
+        }
+        _                       => return Err(DBError::AttributeType(attr.name.clone())),
+    }
+
+    Ok(())
+}
+
+fn float_text<F: ToString>(v: F, nan: bool, inf: bool, neg: bool) -> String {
+    match (nan, inf, neg) {
+        (true, _, _)        => "NaN".to_string(),
+        (_, true, false)    => "Infinity".to_string(),
+        (_, true, true)     => "-Infinity".to_string(),
+        _                   => v.to_string(),
+    }
+}
+
+/// Text format value
+fn encode_text(out: &mut Vec<u8>, attr: &Attribute, value: &Value) -> Result<(), DBError> {
+    let text = match *value {
+        Value::BOOLEAN(v)       => (if v { "t" } else { "f" }).to_string(),
+        Value::INT32(v)         => v.to_string(),
+        Value::UINT32(v)        => v.to_string(),
+        Value::INT64(v)         => v.to_string(),
+        Value::UINT64(v)        => v.to_string(),
+        Value::FLOAT32(v)       => float_text(v, v.is_nan(), v.is_infinite(), v < 0.0),
+        Value::FLOAT64(v)       => float_text(v, v.is_nan(), v.is_infinite(), v < 0.0),
+        Value::TEXT(ref v)      => v.to_string(),
+        Value::JSON(ref v)      => v.to_string(),
+        Value::TIMESTAMP(v)     => datetime::format(v),
+        Value::TIMESTAMP_TZ(v)  => datetime::format(v) + "+00",
+        Value::BLOB(ref v)      => {
+            let mut text = String::with_capacity(2 + v.len() * 2);
+            text.push_str("\\x");
+            for b in v.iter() {
+                text.push_str(&format!("{:02x}", b));
+            }
+            text
+        }
+        Value::LIST(ref elems)  => {
+            let elem = &attr.children[0];
+            out.push(b'{');
+            for (idx, e) in elems.iter().enumerate() {
+                if idx > 0 {
+                    out.push(b',');
+                }
+
+                if *e == Value::NULL {
+                    out.extend_from_slice(b"NULL");
+                    continue
+                }
+
+                let mut raw = Vec::new();
+                encode_text(&mut raw, elem, e)?;
+                let quote = raw.is_empty() || raw.eq_ignore_ascii_case(b"NULL")
+                    || raw.iter().any(|c| b"{},\"\\".contains(c) || c.is_ascii_whitespace());
+                push_quoted(out, &raw, quote, b'\\');
+            }
+            out.push(b'}');
+            return Ok(())
+        }
+        Value::STRUCT(ref fields) => {
+            out.push(b'(');
+            for (idx, (field, value)) in attr.children.iter().zip(fields).enumerate() {
+                if idx > 0 {
+                    out.push(b',');
+                }
+
+                // NULL fields are empty
+                if *value == Value::NULL {
+                    continue
+                }
+
+                let mut raw = Vec::new();
+                encode_text(&mut raw, field, value)?;
+                let quote = raw.is_empty()
+                    || raw.iter().any(|c| b"(),\"\\".contains(c) || c.is_ascii_whitespace());
+                push_quoted(out, &raw, quote, b'"');
+            }
+            out.push(b')');
+            return Ok(())
+        }
+        _                       => return Err(DBError::AttributeType(attr.name.clone())),
+    };
+
+    out.extend_from_slice(text.as_bytes());
+    Ok(())
+}
+
+/// Append an array element / record field. Quotes & backslashes are escaped with `escape`.
+fn push_quoted(out: &mut Vec<u8>, raw: &[u8], quote: bool, escape: u8) {
+    if !quote {
+        out.extend_from_slice(raw);
+        return
+    }
+
+    out.push(b'"');
+    for c in raw {
+        if *c == b'"' || *c == b'\\' {
+            out.push(if *c == b'\\' { b'\\' } else { escape });
+        }
+        out.push(*c);
+    }
+    out.push(b'"');
+}
+
+/// Writes query results as PostgreSQL protocol backend messages
+pub struct PgWireWriter<W: Write> {
+    out: W,
+    format: Format,
+}
+
+impl<W: Write> PgWireWriter<W> {
+    pub fn new(out: W, format: Format) -> PgWireWriter<W> {
+        PgWireWriter { out: out, format: format }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Message with a type byte and a length prefix
+    fn message(&mut self, kind: u8, body: &[u8]) -> Result<(), DBError> {
+        let mut header = vec![kind];
+        push_i32(&mut header, body.len() as i32 + 4);
+        self.out.write_all(&header)?;
+        self.out.write_all(body)?;
+        Ok(())
+    }
+
+    /// RowDescription message
+    pub fn write_row_description(&mut self, schema: &Schema) -> Result<(), DBError> {
+        let mut body = Vec::new();
+        push_i16(&mut body, schema.count() as i16);
+
+        for attr in schema.iter() {
+            body.extend_from_slice(attr.name.as_bytes());
+            body.push(0);
+            push_i32(&mut body, 0);     // table oid
+            push_i16(&mut body, 0);     // column number
+            push_i32(&mut body, type_oid(attr)? as i32);
+            push_i16(&mut body, type_size(attr.dtype));
+            push_i32(&mut body, -1);    // type modifier
+            push_i16(&mut body, self.format.code());
+        }
+
+        self.message(b'T', &body)
+    }
+
+    /// DataRow message for every row of the view
+    pub fn write_view<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        let count = view.schema().count();
+        let columns = (0 .. count)
+            .map(|pos| view.column(pos).ok_or(DBError::make_column_unknown_pos(pos)))
+            .collect::<Result<Vec<_>, DBError>>()?;
+
+        let mut body = Vec::new();
+
+        for row in 0 .. view.rows() {
+            body.clear();
+            push_i16(&mut body, count as i16);
+
+            for col in &columns {
+                let attr = col.attribute();
+                let value = column_value(*col, row)?;
+
+                match self.format {
+                    Format::TEXT    => push_field(&mut body, &value, |out| encode_text(out, attr, &value))?,
+                    Format::BINARY  => push_field(&mut body, &value, |out| encode_binary(out, attr, &value))?,
+                }
+            }
+
+            self.message(b'D', &body)?;
+        }
+
+        Ok(())
+    }
+
+    /// CommandComplete message for a SELECT returning `rows` rows
+    pub fn write_complete(&mut self, rows: usize) -> Result<(), DBError> {
+        let tag = format!("SELECT {}\0", rows);
+        self.message(b'C', tag.as_bytes())
+    }
+
+    /// Write the whole result of the cursor: RowDescription, DataRows and CommandComplete.
+    /// Returns the number of rows written.
+    pub fn write_cursor<'a>(&mut self, cursor: &mut Cursor<'a>) -> Result<usize, DBError> {
+        let schema = cursor.schema().clone();
+        self.write_row_description(&schema)?;
+
+        let mut rows = 0;
+        loop {
+            match cursor.next(DEFAULT_CURSOR_FETCH)? {
+                CursorChunk::Next(view) => {
+                    rows += view.rows();
+                    self.write_view(&view)?;
+                }
+                CursorChunk::End        => break,
+            }
+        }
+
+        self.write_complete(rows)?;
+        self.out.flush()?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use ::allocator;
+    use ::block::Block;
+    use ::exec::ExecContext;
+    use ::operation::{Operation, ScanView};
+    use ::util::copy_value::set_column_value;
+
+    /// Split into (type, body) messages
+    fn messages(data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = data[pos + 1 .. pos + 5].iter().fold(0usize, |acc, b| acc << 8 | *b as usize);
+            out.push((data[pos], data[pos + 5 .. pos + 1 + len].to_vec()));
+            pos += 1 + len;
+        }
+        out
+    }
+
+    fn block() -> Block<'static> {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("id", false, Type::UINT64),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::list("tags", true, Attribute::new("tag", true, Type::TEXT)),
+            Attribute::structure("pt", false, vec![
+                Attribute::new("x", false, Type::INT32),
+                Attribute::new("ok", true, Type::BOOLEAN),
+            ]).unwrap(),
+        ]).unwrap();
+
+        let text = |v: &str| Value::TEXT(Cow::Owned(v.to_string()));
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+
+        let rows = vec![
+            vec![Value::UINT64(123450000), text("a"),
+                 Value::LIST(vec![text("x"), Value::NULL, text("y z")]),
+                 Value::STRUCT(vec![Value::INT32(-1), Value::BOOLEAN(true)])],
+            vec![Value::UINT64(0), Value::NULL, Value::NULL,
+                 Value::STRUCT(vec![Value::INT32(2), Value::NULL])],
+        ];
+
+        for (row, values) in rows.iter().enumerate() {
+            for (pos, value) in values.iter().enumerate() {
+                set_column_value(&mut block, pos, row, value).unwrap();
+            }
+        }
+
+        block
+    }
+
+    #[test]
+    fn text_results() {
+        let block = block();
+        let mut cursor = ScanView::new(&block, None).bind(&ExecContext::default()).unwrap();
+
+        let mut writer = PgWireWriter::new(Vec::new(), Format::TEXT);
+        assert_eq!(writer.write_cursor(&mut *cursor).unwrap(), 2);
+
+        let msgs = messages(&writer.into_inner());
+        assert_eq!(msgs.iter().map(|m| m.0).collect::<Vec<_>>(), b"TDDC".to_vec());
+
+        // RowDescription: 4 fields, "id" numeric
+        let desc = &msgs[0].1;
+        assert_eq!(&desc[.. 5], b"\x00\x04id\x00");
+        assert_eq!(&desc[11 .. 15], &[0, 0, 0x06, 0xa4]);
+
+        let mut row = vec![0, 4];
+        for field in &[&b"123450000"[..], b"a", b"{x,NULL,\"y z\"}", b"(-1,t)"] {
+            row.extend_from_slice(&[0, 0, 0, field.len() as u8]);
+            row.extend_from_slice(field);
+        }
+        assert_eq!(msgs[1].1, row);
+
+        let mut row = vec![0, 4, 0, 0, 0, 1, b'0', 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        row.extend_from_slice(&[0, 0, 0, 4]);
+        row.extend_from_slice(b"(2,)");
+        assert_eq!(msgs[2].1, row);
+        assert_eq!(msgs[3].1, b"SELECT 2\0".to_vec());
+    }
+
+    #[test]
+    fn binary_results() {
+        let block = block();
+        let mut writer = PgWireWriter::new(Vec::new(), Format::BINARY);
+        writer.write_view(&block).unwrap();
+
+        let msgs = messages(&writer.into_inner());
+        let row = &msgs[0].1;
+
+        // numeric 1_2345_0000: 2 digits, weight 2, positive, scale 0, [1, 2345]
+        assert_eq!(&row[2 .. 18], &[0, 0, 0, 12, 0, 2, 0, 2, 0, 0, 0, 0, 0, 1, 0x09, 0x29]);
+
+        // Record: 2 fields, int4 2, NULL bool
+        let record = &msgs[1].1[msgs[1].1.len() - 28 ..];
+        assert_eq!(record, &[0, 0, 0, 24, 0, 0, 0, 2, 0, 0, 0, 23, 0, 0, 0, 4, 0, 0, 0, 2,
+                             0, 0, 0, 16, 0xff, 0xff, 0xff, 0xff][..]);
+
+        let ts = Attribute::new("ts", false, Type::TIMESTAMP_TZ);
+        let mut out = Vec::new();
+        encode_binary(&mut out, &ts, &Value::TIMESTAMP_TZ(PG_EPOCH + 1)).unwrap();
+        assert_eq!(out, vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        out.clear();
+        encode_text(&mut out, &ts, &Value::TIMESTAMP_TZ(PG_EPOCH + 1)).unwrap();
+        assert_eq!(out, b"2000-01-01 00:00:00.000001+00".to_vec());
+        assert!(encode_binary(&mut out, &ts, &Value::TIMESTAMP_TZ(i64::min_value())).is_err());
+
+        let map = Schema::make_one_attr("m", false, Type::MAP);
+        assert!(PgWireWriter::new(Vec::new(), Format::TEXT).write_row_description(&map).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+// vim : set ts=4 sw=4 et :
+
+//! Thrift compact protocol decoding (used by the Parquet metadata).
+//!
+//! Structs are decoded generically into field id, value pairs; the caller picks the fields it
+//! knows about. Decoding functions return None for malformed input.
+
+use super::flatbuf::read_i64;
+
+/// Decoded value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    /// All the integer types (byte, i16, i32, i64)
+    Int(i64),
+    Double(f64),
+    Binary(Vec<u8>),
+    /// List or set
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Struct(Struct),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Struct {
+    pub fields: Vec<(i16, Value)>,
+}
+
+// Compact protocol type ids
+const T_STOP: u8 = 0;
+const T_TRUE: u8 = 1;
+const T_FALSE: u8 = 2;
+const T_BYTE: u8 = 3;
+const T_I16: u8 = 4;
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_DOUBLE: u8 = 7;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_SET: u8 = 10;
+const T_MAP: u8 = 11;
+const T_STRUCT: u8 = 12;
+
+/// Limit on nesting, so malformed input can't blow the stack
+const MAX_DEPTH: usize = 64;
+
+impl Struct {
+    pub fn get(&self, id: i16) -> Option<&Value> {
+        self.fields.iter().find(|f| f.0 == id).map(|f| &f.1)
+    }
+
+    pub fn int(&self, id: i16) -> Option<i64> {
+        match self.get(id) {
+            Some(&Value::Int(v))    => Some(v),
+            _                       => None,
+        }
+    }
+
+    pub fn bool(&self, id: i16) -> Option<bool> {
+        match self.get(id) {
+            Some(&Value::Bool(v))   => Some(v),
+            _                       => None,
+        }
+    }
+
+    pub fn binary(&self, id: i16) -> Option<&[u8]> {
+        match self.get(id) {
+            Some(&Value::Binary(ref v)) => Some(v),
+            _                           => None,
+        }
+    }
+
+    pub fn string(&self, id: i16) -> Option<&str> {
+        self.binary(id).and_then(|v| ::std::str::from_utf8(v).ok())
+    }
+
+    pub fn list(&self, id: i16) -> Option<&[Value]> {
+        match self.get(id) {
+            Some(&Value::List(ref v))   => Some(v),
+            _                           => None,
+        }
+    }
+
+    pub fn structure(&self, id: i16) -> Option<&Struct> {
+        match self.get(id) {
+            Some(&Value::Struct(ref v)) => Some(v),
+            _                           => None,
+        }
+    }
+
+    /// List of structs
+    pub fn structs(&self, id: i16) -> Option<Vec<&Struct>> {
+        self.list(id)?.iter()
+            .map(|v| match *v { Value::Struct(ref s) => Some(s), _ => None })
+            .collect()
+    }
+}
+
+/// Read a struct starting at `*pos`. On success `*pos` is moved past the struct.
+pub fn read_struct(buf: &[u8], pos: &mut usize) -> Option<Struct> {
+    Decoder { buf: buf, pos: *pos }.read_struct(0)
+        .map(|(s, end)| {
+            *pos = end;
+            s
+        })
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut out = 0u64;
+        let mut shift = 0;
+
+        loop {
+            let b = self.byte()?;
+            if shift >= 64 {
+                return None
+            }
+            out |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Some(out)
+            }
+            shift += 7;
+        }
+    }
+
+    fn zigzag(&mut self) -> Option<i64> {
+        let v = self.varint()?;
+        Some((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let out = self.buf.get(self.pos .. self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(out)
+    }
+
+    fn read_struct(mut self, depth: usize) -> Option<(Struct, usize)> {
+        let out = self.structure(depth)?;
+        Some((out, self.pos))
+    }
+
+    fn structure(&mut self, depth: usize) -> Option<Struct> {
+        if depth > MAX_DEPTH {
+            return None
+        }
+
+        let mut out = Struct::default();
+        let mut last = 0i16;
+
+        loop {
+            let header = self.byte()?;
+            let ttype = header & 0x0f;
+            if ttype == T_STOP {
+                return Some(out)
+            }
+
+            let delta = (header >> 4) as i16;
+            let id = if delta != 0 { last.checked_add(delta)? } else { self.zigzag()? as i16 };
+            last = id;
+
+            let value = match ttype {
+                T_TRUE  => Value::Bool(true),
+                T_FALSE => Value::Bool(false),
+                _       => self.value(ttype, depth)?,
+            };
+            out.fields.push((id, value));
+        }
+    }
+
+    fn value(&mut self, ttype: u8, depth: usize) -> Option<Value> {
+        let out = match ttype {
+            // Bools outside of struct fields (list elements) are a byte
+            T_TRUE | T_FALSE    => Value::Bool(self.byte()? == T_TRUE),
+            T_BYTE              => Value::Int(self.byte()? as i8 as i64),
+            T_I16 | T_I32 | T_I64 => Value::Int(self.zigzag()?),
+            T_DOUBLE            => Value::Double(f64::from_bits(read_i64(self.bytes(8)?, 0)? as u64)),
+            T_BINARY            => {
+                let len = self.varint()? as usize;
+                Value::Binary(self.bytes(len)?.to_vec())
+            }
+            T_LIST | T_SET      => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15  => self.varint()? as usize,
+                    len => len as usize,
+                };
+
+                // Every element takes at least one byte
+                if len > self.buf.len() - self.pos {
+                    return None
+                }
+
+                let mut elems = Vec::with_capacity(len);
+                for _ in 0 .. len {
+                    elems.push(self.value(header & 0x0f, depth + 1)?);
+                }
+                Value::List(elems)
+            }
+            T_MAP               => {
+                let len = self.varint()? as usize;
+                if len > self.buf.len() - self.pos {
+                    return None
+                }
+
+                let mut pairs = Vec::with_capacity(len);
+                if len > 0 {
+                    let types = self.byte()?;
+                    for _ in 0 .. len {
+                        let key = self.value(types >> 4, depth + 1)?;
+                        let value = self.value(types & 0x0f, depth + 1)?;
+                        pairs.push((key, value));
+                    }
+                }
+                Value::Map(pairs)
+            }
+            T_STRUCT            => Value::Struct(self.structure(depth + 1)?),
+            _                   => return None,
+        };
+
+        Some(out)
+    }
+}
+
+/// Compact protocol encoding. Only used to build test data.
+#[cfg(test)]
+pub mod encode {
+    use super::*;
+    use ::io::flatbuf::push_i64;
+
+    fn varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn zigzag(out: &mut Vec<u8>, v: i64) {
+        varint(out, ((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    fn type_id(value: &Value) -> u8 {
+        match *value {
+            Value::Bool(true)   => T_TRUE,
+            Value::Bool(false)  => T_FALSE,
+            Value::Int(_)       => T_I64,
+            Value::Double(_)    => T_DOUBLE,
+            Value::Binary(_)    => T_BINARY,
+            Value::List(_)      => T_LIST,
+            Value::Map(_)       => T_MAP,
+            Value::Struct(_)    => T_STRUCT,
+        }
+    }
+
+    pub fn value(out: &mut Vec<u8>, v: &Value) {
+        match *v {
+            Value::Bool(b)          => out.push(if b { T_TRUE } else { T_FALSE }),
+            Value::Int(i)           => zigzag(out, i),
+            Value::Double(d)        => push_i64(out, d.to_bits() as i64),
+            Value::Binary(ref b)    => {
+                varint(out, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Value::List(ref elems)  => {
+                let elem_type = elems.first().map_or(T_BYTE, type_id);
+                out.push(15 << 4 | elem_type);
+                varint(out, elems.len() as u64);
+                for e in elems {
+                    value(out, e);
+                }
+            }
+            Value::Map(ref pairs)   => {
+                varint(out, pairs.len() as u64);
+                if let Some(&(ref k, ref v)) = pairs.first() {
+                    out.push(type_id(k) << 4 | type_id(v));
+                }
+                for &(ref k, ref v) in pairs {
+                    value(out, k);
+                    value(out, v);
+                }
+            }
+            Value::Struct(ref s)    => structure(out, s),
+        }
+    }
+
+    pub fn structure(out: &mut Vec<u8>, s: &Struct) {
+        for &(id, ref v) in &s.fields {
+            out.push(type_id(v));
+            zigzag(out, id as i64);
+            if let Value::Bool(_) = *v {
+                continue
+            }
+            value(out, v);
+        }
+        out.push(T_STOP);
+    }
+
+    /// Struct from (id, value) pairs
+    pub fn make(fields: Vec<(i16, Value)>) -> Value {
+        Value::Struct(Struct { fields: fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_round_trip() {
+        let inner = encode::make(vec![(1, Value::Binary(b"abc".to_vec())), (300, Value::Bool(false))]);
+        let outer = Struct { fields: vec![
+            (1, Value::Int(-3)),
+            (2, Value::Bool(true)),
+            (4, Value::Double(1.5)),
+            (5, Value::List(vec![inner.clone(), inner])),
+            (6, Value::Map(vec![(Value::Int(1), Value::Binary(vec![]))])),
+            (7, Value::List((0 .. 20).map(Value::Int).collect())),
+        ]};
+
+        let mut buf = vec![0xAA];
+        encode::structure(&mut buf, &outer);
+        buf.push(0xBB);
+
+        let mut pos = 1;
+        let decoded = read_struct(&buf, &mut pos).unwrap();
+        assert_eq!(decoded, outer);
+        assert_eq!(pos, buf.len() - 1);
+
+        assert_eq!(decoded.int(1), Some(-3));
+        assert_eq!(decoded.bool(2), Some(true));
+        assert_eq!(decoded.structs(5).unwrap()[1].string(1), Some("abc"));
+        assert_eq!(decoded.structs(5).unwrap()[1].bool(300), Some(false));
+        assert!(decoded.structs(7).is_none());
+
+        // Truncated input
+        let mut pos = 1;
+        assert!(read_struct(&buf[.. buf.len() - 3], &mut pos).is_none());
+        assert_eq!(pos, 1);
+    }
+}
@@ -1,29 +1,29 @@
-#![feature(alloc)]
-#![feature(allocator_api)]
-#![feature(associated_consts)]
-#![feature(associated_type_defaults)]
-#![feature(box_patterns)]
-#![feature(box_syntax)]
-#![feature(heap_api)]
-#![feature(inclusive_range_syntax)]
-#![feature(specialization)]
-// #![feature(nll)]
+#![cfg_attr(feature = "nightly", feature(specialization))]
 
 //! DBKit Engine -- Columnar query processing engine
 //!
 //! Part of the DBKit set of Rust libraries. DBKit isn't a standalone database, rather its a
 //! group of libraries that provided building blocks to build a database or database like data
 //! processing applications.
-
-extern crate alloc;
+//!
+//! Builds on stable Rust. The `nightly` feature enables specialization (and the benchmarks),
+//! `no-jit` restricts expression evaluation to the interpreted kernels.
 
 #[macro_use]
 extern crate log;
 
 extern crate itertools;
 
+extern crate libc;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
 extern crate num;
 
+#[cfg(test)]
+extern crate quickcheck;
+
 /// Database error type and error utilities
 pub mod error;
 
@@ -35,16 +35,33 @@ pub mod types;
 pub mod schema;
 pub mod row;
 pub mod util;
+/// Bit-packed bitmaps used for column null vectors
+pub mod bitmaps;
 
 /// Containers for columnar data.
 pub mod block;
 /// Tools for creating, writing & accessing columnar by row or element.
 pub mod table;
+/// Mapping of Rust structs to rows
+pub mod record;
+/// Reading & writing external data formats
+pub mod io;
+/// Named, shared in-memory tables
+pub mod catalog;
 
 /// Database operations
 pub mod operation;
 /// Database expressions
 pub mod expression;
+/// Parallel execution of operation trees
+pub mod exec;
+/// Visiting and rewriting operation & expression trees
+pub mod rewrite;
+/// Logical query plans, their optimization and lowering to operations
+pub mod plan;
+/// SQL (SELECT subset) front-end producing logical plans
+#[cfg(feature = "sql")]
+pub mod sql;
 
 /// Data structures for representing schema projections.
 pub mod projector;
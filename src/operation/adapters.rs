@@ -0,0 +1,252 @@
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value, window_alias};
+use ::error::DBError;
+use ::projector::{BoundExprProjector, ExprProjector};
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::util::collation::Collator;
+
+use super::{Cursor, CursorChunk, Pipelining};
+use super::scan_view::ScanPredicate;
+
+/// Chunk level combinators over cursors, for light transformations that don't need an `Operation`
+pub trait CursorExt<'a>: Cursor<'a> + Sized + 'a {
+    /// Output of the expression projection of every chunk; computed columns are allocated from `alloc`
+    fn map_exprs(self, alloc: &'a Allocator, proj: &ExprProjector<'a>) -> Result<MapCursor<'a>, DBError> {
+        let bound = proj.bind(alloc, self.schema())?;
+        Ok(MapCursor { input: Box::new(self), alloc: alloc, proj: bound, block: None })
+    }
+
+    /// Calls `f` with every chunk (eg. to log it), passing the chunks on unchanged
+    fn inspect<F: for<'v> FnMut(&'v View<'v>) + 'a>(self, f: F) -> InspectCursor<'a, F> {
+        InspectCursor { input: Box::new(self), f: f }
+    }
+
+    /// Rows up to (excluding) the first one that doesn't match the predicate
+    fn take_while(self, predicate: ScanPredicate) -> Result<TakeWhileCursor<'a>, DBError> {
+        let collator = predicate.collation.collator()?;
+        self.schema().get(predicate.column)?;
+        Ok(TakeWhileCursor { input: Box::new(self), predicate: predicate, collator: collator, done: false })
+    }
+}
+
+impl<'a, C: Cursor<'a> + 'a> CursorExt<'a> for C {}
+
+/// See `CursorExt::map_exprs`
+pub struct MapCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    proj: BoundExprProjector<'a, 'a>,
+    /// Output of the last chunk
+    block: Option<Block<'a>>,
+}
+
+impl<'a> Cursor<'a> for MapCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.proj.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let out = match self.input.next(rows)? {
+            CursorChunk::Next(view) => {
+                let computed = self.proj.evaluate(&view)?;
+                let mut out = Block::new(self.alloc, &self.proj.schema);
+                out.append_view(&self.proj.project_view(&view, &computed)?)?;
+                out
+            }
+            CursorChunk::End        => return Ok(CursorChunk::End),
+        };
+
+        self.block = Some(out);
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.block = None;
+        self.input.rewind()
+    }
+}
+
+/// See `CursorExt::inspect`
+pub struct InspectCursor<'a, F> {
+    input: Box<Cursor<'a> + 'a>,
+    f: F,
+}
+
+impl<'a, F: for<'v> FnMut(&'v View<'v>)> Cursor<'a> for InspectCursor<'a, F> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let chunk = self.input.next(rows)?;
+        if let CursorChunk::Next(ref view) = chunk {
+            (self.f)(view);
+        }
+        Ok(chunk)
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.input.rewind()
+    }
+}
+
+/// See `CursorExt::take_while`
+pub struct TakeWhileCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    predicate: ScanPredicate,
+    collator: Collator,
+    /// A row didn't match
+    done: bool,
+}
+
+impl<'a> Cursor<'a> for TakeWhileCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        if self.done {
+            return Ok(CursorChunk::End)
+        }
+
+        let view = match self.input.next(rows)? {
+            CursorChunk::Next(view) => view,
+            CursorChunk::End        => return Ok(CursorChunk::End),
+        };
+
+        let mut matching = view.rows();
+        {
+            let pos = self.predicate.column;
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            for row in 0 .. view.rows() {
+                if !self.predicate.matches_with(&self.collator, &column_value(col, row)?) {
+                    matching = row;
+                    break
+                }
+            }
+        }
+
+        if matching == view.rows() {
+            return Ok(CursorChunk::Next(view))
+        }
+
+        self.done = true;
+        if matching == 0 {
+            return Ok(CursorChunk::End)
+        }
+        Ok(CursorChunk::Next(view.truncate(matching)?))
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.done = false;
+        self.input.rewind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use ::allocator;
+    use ::exec::{ExecContext, collect_rows, count};
+    use ::expression::comparison::CompareOp;
+    use ::expression::literal::Literal;
+    use ::operation::ScanView;
+    use ::projector::project_by_name;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn adapters() {
+        let inspected_rows = Cell::new(0);
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id UINT32 NOT NULL").unwrap());
+        block.add_rows(10).unwrap();
+        for row in 0 .. 10 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let ctx = ExecContext::default();
+        let op = ScanView::new(&block, None);
+
+        let proj = ExprProjector::new()
+            .add(project_by_name("id"))
+            .add_expr(Literal::new("x", Value::INT64(7)), "seven");
+        let mut mapped = ctx.bind(&op).unwrap().map_exprs(&allocator::GLOBAL, &proj).unwrap();
+        assert_eq!(mapped.schema().count(), 2);
+        let rows = collect_rows(&mut mapped).unwrap();
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows[3], vec![Value::UINT32(3), Value::INT64(7)]);
+
+        let mut inspected = ctx.bind(&op).unwrap().inspect(|view| inspected_rows.set(inspected_rows.get() + view.rows()));
+        assert_eq!(count(&mut inspected).unwrap(), 10);
+        assert_eq!(inspected_rows.get(), 10);
+
+        // Stops in the middle of a chunk, and at the start of one
+        for &(below, rows) in &[(6u32, 6), (4, 4)] {
+            let mut cursor = ctx.bind(&op).unwrap()
+                .take_while(ScanPredicate::new(0, CompareOp::LT, below)).unwrap();
+            let mut read = 0;
+            while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+                read += view.rows();
+            }
+            assert_eq!(read, rows);
+            cursor.rewind().unwrap();
+            assert_eq!(count(&mut cursor).unwrap(), rows);
+        }
+
+        assert!(ctx.bind(&op).unwrap().take_while(ScanPredicate::new(1, CompareOp::LT, 0u32)).is_err());
+    }
+}
@@ -0,0 +1,822 @@
+use std::collections::HashMap;
+
+use ::allocator::Allocator;
+use ::block::{Block, ColumnStats, Dictionary, RefView, Runs, View, alias_column, take, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::plan::{Aggregate, AggregateFunc};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::types::{Type, Value};
+use ::util::copy_value::set_column_value;
+use ::util::collation::{Collation, Collator};
+use ::util::math::OverflowPolicy;
+use ::util::row_hash::{NullEquality, hash_rows_collated, rows_equal, rows_equal_collated};
+use ::util::simd::{Accumulator, accumulator};
+
+use super::{Operation, Cursor, CursorChunk, Pipelining};
+
+/// Groups the input rows by the `group_by` columns and computes the aggregates of every group.
+///
+/// The output has the group columns followed by the aggregates, a row per group (in no particular
+/// order). Without grouping columns there's exactly one output row, even for an empty input.
+/// Ungrouped aggregates are answered from the input's metadata when it can
+/// (`Cursor::aggregates_from_metadata`), without reading any rows. A dictionary encoded single
+/// group column is grouped by its codes, a run-length encoded one a run at a time. TEXT group columns are grouped under their collation.
+pub struct HashAggregate<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub group_by: Vec<usize>,
+    pub aggregates: Vec<Aggregate>,
+    /// Collation of every group column; BINARY for the columns past its end
+    pub collations: Vec<Collation>,
+}
+
+impl<'a> HashAggregate<'a> {
+    pub fn new<T: Operation<'a> + 'a>(group_by: Vec<usize>, aggregates: Vec<Aggregate>, src: T) -> HashAggregate<'a> {
+        HashAggregate { src: Box::new(src), group_by: group_by, aggregates: aggregates, collations: Vec::new() }
+    }
+
+    pub fn with_collations(self, collations: Vec<Collation>) -> HashAggregate<'a> {
+        HashAggregate { collations: collations, ..self }
+    }
+}
+
+impl<'a> Operation<'a> for HashAggregate<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let input = ctx.bind(&*self.src)?;
+
+        let mut attrs = Vec::with_capacity(self.group_by.len() + self.aggregates.len());
+        let mut funcs = Vec::with_capacity(self.aggregates.len());
+        {
+            let schema = input.schema();
+            for &pos in &self.group_by {
+                attrs.push(schema.get(pos)?.clone());
+            }
+
+            for agg in &self.aggregates {
+                attrs.push(agg.attribute(schema)?);
+                let dtype = match agg.column {
+                    Some(pos)   => Some(schema.get(pos)?.dtype),
+                    None        => None,
+                };
+
+                // Fails early for unsupported input types
+                if let Some(dtype) = dtype {
+                    accumulator(agg.func, dtype, ctx.config().sum_overflow)?;
+                }
+                funcs.push((agg.func, agg.column, agg.by, dtype));
+            }
+        }
+
+        let alloc: &'a Allocator = ctx.allocator();
+        let key_schema = Schema::from_slice(&attrs[.. self.group_by.len()])?;
+        let mut cursor = HashAggregateCursor {
+            fetch: ctx.fetch_rows(&*input),
+            input: input,
+            alloc: alloc,
+            schema: Schema::from_vec(attrs)?,
+            state: GroupState {
+                group_by: self.group_by.clone(),
+                collators: self.collations.iter().map(Collation::collator).collect::<Result<_, _>>()?,
+                funcs: funcs,
+                overflow: ctx.config().sum_overflow,
+                keys: Block::new(alloc, &key_schema),
+                table: HashMap::new(),
+                groups: Vec::new(),
+                dict: None,
+            },
+            output: None,
+            offset: 0,
+            cancel: ctx.cancel_token().clone(),
+        };
+
+        if self.group_by.is_empty() {
+            cursor.state.add_groups(1)?;
+
+            if let Some(values) = cursor.input.aggregates_from_metadata(&self.aggregates) {
+                ctx.metrics().add("aggregate_pushdown", 1);
+                cursor.output = Some(cursor.result(Some(&values[..]))?);
+            }
+        }
+
+        Ok(Box::new(cursor))
+    }
+
+    fn describe(&self) -> String {
+        let aggregates: Vec<_> = self.aggregates.iter().map(|a| a.to_string()).collect();
+        let mut out = format!("HashAggregate group_by={:?} [{}]", self.group_by, aggregates.join(", "));
+        if self.collations.iter().any(|c| !c.is_binary()) {
+            let collations: Vec<_> = self.collations.iter().map(|c| c.to_string()).collect();
+            out += &format!(" COLLATE [{}]", collations.join(", "));
+        }
+        out
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+/// Implementation of the `HashAggregate` operation
+struct HashAggregateCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    schema: Schema,
+    fetch: RowOffset,
+    state: GroupState<'a>,
+    /// Groups and their aggregates, once the input is consumed
+    output: Option<Block<'a>>,
+    offset: RowOffset,
+    cancel: CancelToken,
+}
+
+/// Groups seen so far and their running aggregates
+struct GroupState<'a> {
+    group_by: Vec<usize>,
+    /// Of the group columns
+    collators: Vec<Collator>,
+    /// Function, input column, `by` column and the input column's type of every aggregate
+    funcs: Vec<(AggregateFunc, Option<usize>, Option<usize>, Option<Type>)>,
+    overflow: OverflowPolicy,
+    /// Key columns, a row per group
+    keys: Block<'a>,
+    /// Groups by key hash
+    table: HashMap<u64, Vec<usize>>,
+    groups: Vec<Group>,
+    /// Groups of the codes of the last dictionary encoded key column
+    dict: Option<DictGroups<'a>>,
+}
+
+struct DictGroups<'a> {
+    /// Dictionary values (a copy), one column
+    values: Block<'a>,
+    /// Group of every code; `None` until a row with the code is seen
+    groups: Vec<Option<usize>>,
+}
+
+struct Group {
+    rows: u64,
+    /// `None` for `COUNT(*)`
+    accumulators: Vec<Option<Box<Accumulator>>>,
+}
+
+impl<'a> GroupState<'a> {
+    fn add_groups(&mut self, count: usize) -> Result<(), DBError> {
+        for _ in 0 .. count {
+            let mut accumulators = Vec::with_capacity(self.funcs.len());
+            for &(func, _, _, dtype) in &self.funcs {
+                accumulators.push(match dtype {
+                    Some(dtype) => Some(accumulator(func, dtype, self.overflow)?),
+                    None        => None,
+                });
+            }
+
+            self.groups.push(Group { rows: 0, accumulators: accumulators });
+        }
+
+        Ok(())
+    }
+
+    fn update<'v>(&mut self, view: &RefView<'v>) -> Result<(), DBError> {
+        if view.rows() == 0 {
+            return Ok(())
+        }
+
+        // Run-length encoded key: every run is a range of rows of one group
+        if self.group_by.len() == 1 {
+            let pos = self.group_by[0];
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            if let Some(runs) = col.runs() {
+                for (run, group) in self.run_groups(runs)?.into_iter().enumerate() {
+                    self.accumulate(view, group, runs.range(run))?;
+                }
+                return Ok(())
+            }
+        }
+
+        let ids = self.assign_groups(view)?;
+
+        // Rows of every group, in order
+        let mut slots: HashMap<usize, usize> = HashMap::new();
+        let mut runs: Vec<(usize, Vec<RowOffset>)> = Vec::new();
+        for (row, &group) in ids.iter().enumerate() {
+            let slot = *slots.entry(group).or_insert(runs.len());
+            if slot == runs.len() {
+                runs.push((group, Vec::new()));
+            }
+            runs[slot].1.push(row);
+        }
+
+        if runs.len() == 1 {
+            return self.accumulate(view, runs[0].0, RowRange { offset: 0, rows: view.rows() })
+        }
+
+        for &(group, ref rows) in &runs {
+            self.accumulate_selected(view, group, rows)?;
+        }
+
+        Ok(())
+    }
+
+    /// `accumulate` the `selection` rows of the view, in place
+    fn accumulate_selected<'v>(&mut self, view: &'v View<'v>, group: usize, selection: &[RowOffset]) -> Result<(), DBError> {
+        let group = &mut self.groups[group];
+        group.rows += selection.len() as u64;
+
+        for (&(_, column, by, _), acc) in self.funcs.iter().zip(group.accumulators.iter_mut()) {
+            if let (Some(pos), Some(acc)) = (column, acc.as_mut()) {
+                let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                match by {
+                    Some(by) => {
+                        let by_col = view.column(by).ok_or(DBError::make_column_unknown_pos(by))?;
+                        acc.update_selected(col, &[by_col], selection)?;
+                    }
+                    None => acc.update_selected(col, &[], selection)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn accumulate<'v>(&mut self, view: &'v View<'v>, group: usize, range: RowRange) -> Result<(), DBError> {
+        let group = &mut self.groups[group];
+        group.rows += range.rows as u64;
+
+        for (&(_, column, by, _), acc) in self.funcs.iter().zip(group.accumulators.iter_mut()) {
+            if let (Some(pos), Some(acc)) = (column, acc.as_mut()) {
+                let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                match by {
+                    Some(by) => {
+                        let by_col = view.column(by).ok_or(DBError::make_column_unknown_pos(by))?;
+                        let by_col = alias_column(by_col, Some(range))?;
+                        acc.update_with(&alias_column(col, Some(range))?, &[&by_col], range.rows)?;
+                    }
+                    None => acc.update(&alias_column(col, Some(range))?, range.rows)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Group of every row; adds the new groups
+    fn assign_groups<'v>(&mut self, view: &RefView<'v>) -> Result<Vec<usize>, DBError> {
+        if self.group_by.is_empty() {
+            return Ok(vec![0; view.rows()])
+        }
+
+        if self.group_by.len() == 1 {
+            let pos = self.group_by[0];
+            let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            if let Some(dict) = col.dictionary() {
+                return self.dict_groups(dict)
+            }
+        }
+
+        let group_by = self.group_by.clone();
+        self.hash_groups(view, &group_by)
+    }
+
+    /// Groups of dictionary encoded keys, through a dense array by code. The codes of a dictionary
+    /// other than the last one are remapped.
+    fn dict_groups<'d>(&mut self, dict: Dictionary<'d>) -> Result<Vec<usize>, DBError> {
+        // Dictionary entries in use; the values column can have extra rows
+        let size = dict.codes.iter().max().map_or(0, |c| *c as usize + 1);
+        let attr = dict.values.attribute().clone();
+        let values = RefView::new(Schema::from_attr(attr), vec![alias_column(dict.values, Some(RowRange { offset: 0, rows: size }))?], size);
+
+        let same = match self.dict {
+            Some(ref cached) if size <= cached.values.rows()    => {
+                let pairs: Vec<_> = (0 .. size).map(|code| (code, code)).collect();
+                rows_equal(&cached.values, &[0], &values, &[0], &pairs, NullEquality::MATCH)?.iter().all(|eq| *eq)
+            }
+            _                                                   => false,
+        };
+
+        let mut cached = match self.dict.take() {
+            Some(cached) if same    => cached,
+            _                       => {
+                let mut copy = Block::new(self.keys.allocator(), values.schema());
+                copy.append_view(&values)?;
+                DictGroups { values: copy, groups: vec![None; size] }
+            }
+        };
+
+        // Codes seen for the first time
+        let mut missing = Vec::new();
+        for &code in dict.codes {
+            let slot = &mut cached.groups[code as usize];
+            if slot.is_none() {
+                // Placeholder, assigned below
+                *slot = Some(!0);
+                missing.push(code as RowOffset);
+            }
+        }
+
+        if !missing.is_empty() {
+            let keys = take(self.keys.allocator(), &cached.values, &missing)?;
+            for (&code, group) in missing.iter().zip(self.hash_groups(&keys, &[0])?) {
+                cached.groups[code] = Some(group);
+            }
+        }
+
+        let ids = dict.codes.iter().map(|c| cached.groups[*c as usize].unwrap()).collect();
+        self.dict = Some(cached);
+        Ok(ids)
+    }
+
+    /// Group of every run of run-length encoded keys; the run values are hashed instead of the rows
+    fn run_groups<'r>(&mut self, runs: Runs<'r>) -> Result<Vec<usize>, DBError> {
+        let count = runs.count();
+        let attr = runs.values.attribute().clone();
+        let values = RefView::new(Schema::from_attr(attr), vec![alias_column(runs.values, Some(RowRange { offset: 0, rows: count }))?], count);
+        self.hash_groups(&values, &[0])
+    }
+
+    /// Group of every row by the hash of the `columns`
+    fn hash_groups<'v>(&mut self, view: &'v View<'v>, columns: &[usize]) -> Result<Vec<usize>, DBError> {
+        let hashes = hash_rows_collated(view, columns, &self.collators)?;
+        let key_columns: Vec<usize> = (0 .. self.group_by.len()).collect();
+        let first_new = self.groups.len();
+
+        let mut ids = Vec::with_capacity(view.rows());
+        // Rows of the chunk with a new key; their keys aren't in `keys` yet
+        let mut new_rows = Vec::new();
+        let mut pair = Vec::with_capacity(1);
+
+        for (row, &hash) in hashes.iter().enumerate() {
+            let mut found = None;
+
+            if let Some(candidates) = self.table.get(&hash) {
+                for &group in candidates {
+                    pair.clear();
+                    let equal = if group < first_new {
+                        pair.push((group, row));
+                        rows_equal_collated(&self.keys, &key_columns, view, columns, &pair, NullEquality::MATCH, &self.collators)?
+                    } else {
+                        pair.push((new_rows[group - first_new], row));
+                        rows_equal_collated(view, columns, view, columns, &pair, NullEquality::MATCH, &self.collators)?
+                    };
+
+                    if equal[0] {
+                        found = Some(group);
+                        break
+                    }
+                }
+            }
+
+            let group = match found {
+                Some(group) => group,
+                None        => {
+                    let group = first_new + new_rows.len();
+                    new_rows.push(row);
+                    self.table.entry(hash).or_insert_with(Vec::new).push(group);
+                    group
+                }
+            };
+
+            ids.push(group);
+        }
+
+        if !new_rows.is_empty() {
+            let first = self.keys.add_rows(new_rows.len())?;
+            for (key, &pos) in columns.iter().enumerate() {
+                let src = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                self.keys.column_mut(key).unwrap().take_rows(first, src, &new_rows)?;
+            }
+
+            self.add_groups(new_rows.len())?;
+        }
+
+        Ok(ids)
+    }
+}
+
+impl<'a> HashAggregateCursor<'a> {
+    /// Output block of the groups. The aggregate `values` of the single group, if they're known
+    /// (from metadata).
+    fn result(&self, values: Option<&[Value]>) -> Result<Block<'a>, DBError> {
+        let state = &self.state;
+        let keys = state.group_by.len();
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        if state.groups.is_empty() {
+            return Ok(out)
+        }
+
+        out.add_rows(state.groups.len())?;
+        for key in 0 .. keys {
+            let src = state.keys.column(key).unwrap();
+            out.column_mut(key).unwrap().copy_rows(0, src, 0, state.groups.len())?;
+        }
+
+        for (row, group) in state.groups.iter().enumerate() {
+            for (pos, acc) in group.accumulators.iter().enumerate() {
+                let value = match (values, acc.as_ref()) {
+                    (Some(values), _)   => values[pos].clone(),
+                    (None, Some(acc))   => acc.result()?,
+                    (None, None)        => Value::UINT64(group.rows),
+                };
+
+                set_column_value(&mut out, keys + pos, row, &value)?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'a> Cursor<'a> for HashAggregateCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        if self.output.is_none() {
+            while let CursorChunk::Next(view) = self.input.next(self.fetch)? {
+                self.state.update(&view)?;
+                self.cancel.check()?;
+            }
+
+            self.output = Some(self.result(None)?);
+        }
+
+        let output = self.output.as_ref().unwrap();
+        if self.offset >= output.rows() {
+            return Ok(CursorChunk::End)
+        }
+
+        let range = RowRange { offset: self.offset, rows: rows.min(output.rows() - self.offset) };
+        self.offset += range.rows;
+        Ok(CursorChunk::Next(window_alias(output, Some(range))?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        let output = self.output.as_ref().map_or(0, |b| b.memory_usage().total());
+        let dict = self.state.dict.as_ref().map_or(0, |d| d.values.memory_usage().total());
+        self.state.keys.memory_usage().total() + dict + output
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+/// Value of an ungrouped aggregate from the statistics of its column over all the rows. `None`
+/// when the statistics can't answer it: functions other than COUNT, MIN and MAX, MIN / MAX of non
+/// integer columns (float statistics don't skip NaNs like the accumulators do) or without a known
+/// min / max.
+pub fn aggregate_from_stats(agg: &Aggregate, rows: RowOffset, stats: Option<&ColumnStats>) -> Option<Value<'static>> {
+    let stats = match (agg.column, stats) {
+        (None, _)               => return if agg.func == AggregateFunc::COUNT { Some(Value::UINT64(rows as u64)) } else { None },
+        (Some(_), Some(stats))  => stats,
+        (Some(_), None)         => return None,
+    };
+
+    match agg.func {
+        AggregateFunc::COUNT                    => return Some(Value::UINT64((stats.rows - stats.null_count) as u64)),
+        AggregateFunc::MIN | AggregateFunc::MAX => (),
+        _                                       => return None,
+    }
+
+    if stats.all_null() {
+        return Some(Value::NULL)
+    }
+
+    let value = if agg.func == AggregateFunc::MIN { stats.min.as_ref() } else { stats.max.as_ref() };
+    match value.map(|v| (v.dtype(), v)) {
+        Some((Some(Type::UINT32), v)) | Some((Some(Type::UINT64), v)) |
+        Some((Some(Type::INT32), v)) | Some((Some(Type::INT64), v))     => Some(v.clone()),
+        _                                                               => None,
+    }
+}
+
+/// Ungrouped aggregates over `rows` rows, from the statistics of the columns (by position)
+pub fn aggregates_with<F>(aggregates: &[Aggregate], rows: RowOffset, mut stats: F) -> Option<Vec<Value<'static>>>
+    where F: FnMut(usize) -> Option<ColumnStats>
+{
+    let mut out = Vec::with_capacity(aggregates.len());
+
+    for agg in aggregates {
+        let stats = match agg.column {
+            Some(pos)   => Some(stats(pos)?),
+            None        => None,
+        };
+
+        out.push(aggregate_from_stats(agg, rows, stats.as_ref())?);
+    }
+
+    Some(out)
+}
+
+/// Statistics of two runs of rows together (eg. row groups of a file). The min / max are unknown
+/// if they're unknown for a run with non-NULL values.
+pub fn merge_stats(lhs: &ColumnStats, rhs: &ColumnStats) -> ColumnStats {
+    fn bound(lhs: &ColumnStats, rhs: &ColumnStats, l: &Option<Value<'static>>, r: &Option<Value<'static>>, min: bool)
+        -> Option<Value<'static>>
+    {
+        match (l, r) {
+            _ if lhs.all_null()         => r.clone(),
+            _ if rhs.all_null()         => l.clone(),
+            (&Some(ref l), &Some(ref r)) => Some(if (r < l) == min { r.clone() } else { l.clone() }),
+            _                           => None,
+        }
+    }
+
+    ColumnStats {
+        rows: lhs.rows + rhs.rows,
+        null_count: lhs.null_count + rhs.null_count,
+        min: bound(lhs, rhs, &lhs.min, &rhs.min, true),
+        max: bound(lhs, rhs, &lhs.max, &rhs.max, false),
+        distinct_estimate: lhs.distinct_estimate.max(rhs.distinct_estimate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use ::allocator;
+    use ::block::{dict_encode, rle_encode};
+    use ::exec::{Metrics, collect_rows};
+    use ::expression::comparison::CompareOp;
+    use ::operation::{Filter, ScanPredicate, ScanView};
+
+    /// Groups come out in no particular order
+    fn sorted(mut rows: Vec<Vec<Value<'static>>>) -> Vec<Vec<Value<'static>>> {
+        rows.sort_by(|l, r| l.partial_cmp(r).unwrap());
+        rows
+    }
+
+    #[test]
+    fn aggregate() {
+        let schema = Schema::parse_ddl("region TEXT, price INT64, qty UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(20).unwrap();
+        for row in 0 .. 20 {
+            let region = if row % 5 == 4 { Value::NULL } else { Value::from(["east", "west"][row % 2]) };
+            let price = if row % 3 == 0 { Value::NULL } else { Value::INT64(row as i64 - 5) };
+            set_column_value(&mut block, 0, row, &region).unwrap();
+            set_column_value(&mut block, 1, row, &price).unwrap();
+            set_column_value(&mut block, 2, row, &Value::UINT32(row as u32)).unwrap();
+        }
+        block.update_stats().unwrap();
+
+        let aggregates = vec![
+            Aggregate::count_all("n"),
+            Aggregate::new(AggregateFunc::COUNT, 1, "prices"),
+            Aggregate::new(AggregateFunc::SUM, 2, "qty"),
+            Aggregate::new(AggregateFunc::MIN, 1, "low"),
+            Aggregate::new(AggregateFunc::MAX, 1, "high"),
+        ];
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 3;
+
+        let op = HashAggregate::new(vec![0], aggregates.clone(), ScanView::new(&block, None));
+        assert_eq!(op.describe(), "HashAggregate group_by=[0] [COUNT(*) AS n, COUNT(#1) AS prices, \
+                                   SUM(#2) AS qty, MIN(#1) AS low, MAX(#1) AS high]");
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert_eq!(cursor.schema().count(), 6);
+        assert_eq!(cursor.pipelining(), Pipelining::BLOCKING);
+
+        // NULL keys are a group of their own
+        assert_eq!(sorted(collect_rows(&mut *cursor).unwrap()), vec![
+            vec![Value::NULL, Value::UINT64(4), Value::UINT64(3), Value::UINT64(4 + 9 + 14 + 19),
+                 Value::INT64(-1), Value::INT64(14)],
+            vec![Value::from("east"), Value::UINT64(8), Value::UINT64(4), Value::UINT64(0 + 2 + 6 + 8 + 10 + 12 + 16 + 18),
+                 Value::INT64(-3), Value::INT64(11)],
+            vec![Value::from("west"), Value::UINT64(8), Value::UINT64(6), Value::UINT64(1 + 3 + 5 + 7 + 11 + 13 + 15 + 17),
+                 Value::INT64(-4), Value::INT64(12)],
+        ]);
+
+        // Ungrouped; answered from statistics unless there's a SUM
+        let metrics = Arc::new(Metrics::new());
+        let ctx = ctx.with_metrics(metrics.clone());
+
+        let op = HashAggregate::new(vec![], aggregates.clone(), ScanView::new(&block, None));
+        let expected = vec![vec![Value::UINT64(20), Value::UINT64(13), Value::UINT64(190), Value::INT64(-4), Value::INT64(14)]];
+        assert_eq!(sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap()), expected);
+        assert_eq!(metrics.get("aggregate_pushdown"), 0);
+
+        let no_sum: Vec<_> = aggregates.iter().cloned().filter(|a| a.func != AggregateFunc::SUM).collect();
+        let op = HashAggregate::new(vec![], no_sum.clone(), ScanView::new(&block, None));
+        assert_eq!(sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap()),
+                   vec![vec![Value::UINT64(20), Value::UINT64(13), Value::INT64(-4), Value::INT64(14)]]);
+        assert_eq!(metrics.get("aggregate_pushdown"), 1);
+
+        // Statistics aren't computed for views without them
+        let unindexed = window_alias(&block, None).unwrap();
+        let op = HashAggregate::new(vec![], no_sum.clone(), ScanView::new(&unindexed, None));
+        assert_eq!(sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap()),
+                   vec![vec![Value::UINT64(20), Value::UINT64(13), Value::INT64(-4), Value::INT64(14)]]);
+        assert_eq!(metrics.get("aggregate_pushdown"), 1);
+
+        // Filtered input, and an empty one
+        let filter = Filter::new(ScanPredicate::new(2, CompareOp::GE, 100u32), ScanView::new(&block, None));
+        let op = HashAggregate::new(vec![], no_sum.clone(), filter);
+        assert_eq!(sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap()),
+                   vec![vec![Value::UINT64(0), Value::UINT64(0), Value::NULL, Value::NULL]]);
+        assert_eq!(metrics.get("aggregate_pushdown"), 1);
+
+        let empty = Block::new(&allocator::GLOBAL, &schema);
+        let op = HashAggregate::new(vec![0], no_sum.clone(), ScanView::new(&empty, None));
+        assert!(sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap()).is_empty());
+
+        // Only numeric SUM, MIN & MAX
+        let op = HashAggregate::new(vec![], vec![Aggregate::new(AggregateFunc::MAX, 0, "m")], ScanView::new(&block, None));
+        assert!(ctx.bind(&op).is_err());
+    }
+
+    #[test]
+    fn companion_columns() {
+        let schema = Schema::parse_ddl("region TEXT NOT NULL, price INT64, qty UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(12).unwrap();
+        for row in 0 .. 12 {
+            let price = if row % 4 == 2 { Value::NULL } else { Value::INT64((row * 5 % 7) as i64) };
+            set_column_value(&mut block, 0, row, &Value::from(["east", "west"][row % 2])).unwrap();
+            set_column_value(&mut block, 1, row, &price).unwrap();
+            set_column_value(&mut block, 2, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let aggregates = vec![
+            Aggregate::arg(AggregateFunc::ARGMAX, 2, 1, "top"),
+            Aggregate::arg(AggregateFunc::ARGMIN, 2, 1, "bottom"),
+            Aggregate::new(AggregateFunc::FIRST, 1, "first"),
+            Aggregate::new(AggregateFunc::LAST, 1, "last"),
+        ];
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 3;
+
+        let op = HashAggregate::new(vec![0], aggregates, ScanView::new(&block, None));
+        assert_eq!(op.describe(), "HashAggregate group_by=[0] [ARGMAX(#2 BY #1) AS top, ARGMIN(#2 BY #1) AS bottom, \
+                                   FIRST(#1) AS first, LAST(#1) AS last]");
+
+        // NULL prices are skipped by ARGMIN / ARGMAX, not by FIRST / LAST
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert_eq!(cursor.schema().get(1).unwrap().dtype, Type::UINT32);
+        assert_eq!(sorted(collect_rows(&mut *cursor).unwrap()), vec![
+            vec![Value::from("east"), Value::UINT32(4), Value::UINT32(0), Value::INT64(0), Value::NULL],
+            vec![Value::from("west"), Value::UINT32(11), Value::UINT32(7), Value::INT64(5), Value::INT64(6)],
+        ]);
+
+        assert!(Aggregate::new(AggregateFunc::ARGMIN, 2, "x").attribute(&schema).is_err());
+        assert!(Aggregate::arg(AggregateFunc::MAX, 2, 1, "x").attribute(&schema).is_err());
+        assert!(Aggregate::arg(AggregateFunc::ARGMIN, 2, 3, "x").attribute(&schema).is_err());
+    }
+
+    #[test]
+    fn dictionary_keys() {
+        let schema = Schema::parse_ddl("region TEXT, qty UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        let mut reversed = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(12).unwrap();
+        reversed.add_rows(12).unwrap();
+        for row in 0 .. 12 {
+            let region = if row % 4 == 3 { Value::NULL } else { Value::from(["east", "west", "north"][row % 3]) };
+            set_column_value(&mut block, 0, row, &region).unwrap();
+            set_column_value(&mut block, 1, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut reversed, 0, 11 - row, &region).unwrap();
+            set_column_value(&mut reversed, 1, 11 - row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let dict = dict_encode(&allocator::GLOBAL, &block[0], 12).unwrap();
+        let view = RefView::new(schema.clone(), vec![alias_column(&dict, None).unwrap(), alias_column(&block[1], None).unwrap()], 12);
+
+        let aggregates = vec![Aggregate::count_all("n"), Aggregate::new(AggregateFunc::SUM, 1, "qty")];
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 5;
+
+        let plain = HashAggregate::new(vec![0], aggregates.clone(), ScanView::new(&block, None));
+        let encoded = HashAggregate::new(vec![0], aggregates.clone(), ScanView::new(&view, None));
+        let expected = sorted(collect_rows(&mut *ctx.bind(&plain).unwrap()).unwrap());
+        assert_eq!(expected.len(), 4);
+        assert_eq!(sorted(collect_rows(&mut *ctx.bind(&encoded).unwrap()).unwrap()), expected);
+
+        // Chunks with different dictionaries, and a plain chunk
+        let other = dict_encode(&allocator::GLOBAL, &reversed[0], 12).unwrap();
+        assert!(other.codes() != dict.codes());
+        let other_view = RefView::new(schema.clone(), vec![alias_column(&other, None).unwrap(), alias_column(&reversed[1], None).unwrap()], 12);
+
+        let mut state = GroupState {
+            group_by: vec![0],
+            collators: Vec::new(),
+            funcs: vec![(AggregateFunc::COUNT, None, None, None)],
+            overflow: OverflowPolicy::ERROR,
+            keys: Block::new(&allocator::GLOBAL, &Schema::from_slice(&[schema.get(0).unwrap().clone()]).unwrap()),
+            table: HashMap::new(),
+            groups: Vec::new(),
+            dict: None,
+        };
+
+        state.update(&window_alias(&view, None).unwrap()).unwrap();
+        state.update(&window_alias(&other_view, None).unwrap()).unwrap();
+        state.update(&window_alias(&block, None).unwrap()).unwrap();
+        state.update(&window_alias(&view, Some(RowRange { offset: 0, rows: 3 })).unwrap()).unwrap();
+
+        assert_eq!(state.groups.len(), 4);
+        assert_eq!(state.groups.iter().map(|g| g.rows).collect::<Vec<_>>(), vec![10, 10, 10, 9]);
+        // Only the entries in use of the last dictionary
+        assert_eq!(state.dict.as_ref().unwrap().values.rows(), 3);
+    }
+
+    #[test]
+    fn collated_keys() {
+        let schema = Schema::parse_ddl("region TEXT NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(5).unwrap();
+        for (row, region) in ["East", "east", "west", "EAST", "West"].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::from(*region)).unwrap();
+        }
+
+        let dict = dict_encode(&allocator::GLOBAL, &block[0], 5).unwrap();
+        let view = RefView::new(schema.clone(), vec![alias_column(&dict, None).unwrap()], 5);
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 2;
+
+        for src in &[&block as &View, &view] {
+            let op = HashAggregate::new(vec![0], vec![Aggregate::count_all("n")], ScanView::new(*src, None))
+                .with_collations(vec![Collation::NOCASE]);
+            assert_eq!(op.describe(), "HashAggregate group_by=[0] [COUNT(*) AS n] COLLATE [NOCASE]");
+
+            // The first value of every group is kept
+            let groups = sorted(collect_rows(&mut *ctx.bind(&op).unwrap()).unwrap());
+            assert_eq!(groups, vec![vec![Value::from("East"), Value::UINT64(3)],
+                                    vec![Value::from("west"), Value::UINT64(2)]]);
+        }
+    }
+
+    #[test]
+    fn rle_keys() {
+        let schema = Schema::parse_ddl("region TEXT, qty UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(12).unwrap();
+        for row in 0 .. 12 {
+            // Runs of 4, 3, 2 and 3 rows; the last run repeats the first key
+            let region = if row < 4 || row > 8 { Value::from("east") } else if row < 7 { Value::NULL } else { Value::from("west") };
+            set_column_value(&mut block, 0, row, &region).unwrap();
+            set_column_value(&mut block, 1, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let rle = rle_encode(&allocator::GLOBAL, &block[0], 12).unwrap();
+        assert_eq!(rle.run_count(), 4);
+        let view = RefView::new(schema.clone(), vec![alias_column(&rle, None).unwrap(), alias_column(&block[1], None).unwrap()], 12);
+
+        let aggregates = vec![Aggregate::count_all("n"), Aggregate::new(AggregateFunc::SUM, 1, "qty")];
+        let mut ctx = ExecContext::default();
+        // Chunks cut the runs
+        ctx.config_mut().fetch_rows = 5;
+
+        let plain = HashAggregate::new(vec![0], aggregates.clone(), ScanView::new(&block, None));
+        let encoded = HashAggregate::new(vec![0], aggregates.clone(), ScanView::new(&view, None));
+        let expected = sorted(collect_rows(&mut *ctx.bind(&plain).unwrap()).unwrap());
+        let actual = sorted(collect_rows(&mut *ctx.bind(&encoded).unwrap()).unwrap());
+        assert_eq!(expected.len(), 3);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn stats() {
+        let stats = |rows, nulls, min: Option<i64>, max: Option<i64>| ColumnStats {
+            rows: rows,
+            null_count: nulls,
+            min: min.map(Value::INT64),
+            max: max.map(Value::INT64),
+            distinct_estimate: 0,
+        };
+
+        let merged = merge_stats(&stats(10, 2, Some(-3), Some(8)), &stats(5, 5, None, None));
+        assert_eq!(merged, stats(15, 7, Some(-3), Some(8)));
+        let merged = merge_stats(&merged, &stats(5, 0, Some(-5), Some(4)));
+        assert_eq!(merged, stats(20, 7, Some(-5), Some(8)));
+        // Unknown bounds
+        assert_eq!(merge_stats(&merged, &stats(5, 0, None, None)).min, None);
+
+        assert_eq!(aggregate_from_stats(&Aggregate::count_all("n"), 20, None), Some(Value::UINT64(20)));
+        assert_eq!(aggregate_from_stats(&Aggregate::new(AggregateFunc::COUNT, 0, "c"), 20, Some(&merged)), Some(Value::UINT64(13)));
+        assert_eq!(aggregate_from_stats(&Aggregate::new(AggregateFunc::MIN, 0, "m"), 20, Some(&merged)), Some(Value::INT64(-5)));
+        assert_eq!(aggregate_from_stats(&Aggregate::new(AggregateFunc::SUM, 0, "s"), 20, Some(&merged)), None);
+        assert_eq!(aggregate_from_stats(&Aggregate::new(AggregateFunc::MAX, 0, "m"), 5, Some(&stats(5, 5, None, None))), Some(Value::NULL));
+
+        let floats = ColumnStats { min: Some(Value::FLOAT64(1.0)), .. merged.clone() };
+        assert_eq!(aggregate_from_stats(&Aggregate::new(AggregateFunc::MIN, 0, "m"), 20, Some(&floats)), None);
+    }
+}
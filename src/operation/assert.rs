@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use ::block::{View, column_value};
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::expression::comparison::CompareOp;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Value;
+use ::util::row_hash::{NullEquality, hash_rows, rows_equal};
+
+use super::{Operation, Cursor, CursorChunk};
+
+/// Data quality checks: rows are passed through unchanged, the first row failing a check fails the
+/// query with `DBError::Assertion` (the check, the row's position in the input and its values).
+pub struct AssertOp<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub checks: Vec<Check>,
+}
+
+/// Invariant checked by `AssertOp`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    /// The column has no NULLs
+    NOTNULL(usize),
+    /// Values of the column are within `min ..= max` (either end is optional); NULLs pass
+    RANGE { column: usize, min: Option<Value<'static>>, max: Option<Value<'static>> },
+    /// No two rows of a chunk have the same values in the columns; rows with NULLs pass
+    UNIQUE(Vec<usize>),
+}
+
+/// Implementation of the `AssertOp` operation
+struct AssertCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    checks: Vec<Check>,
+    /// Input rows read so far
+    offset: RowOffset,
+}
+
+impl<'a> AssertOp<'a> {
+    pub fn new<T: Operation<'a> + 'a>(checks: Vec<Check>, src: T) -> AssertOp<'a> {
+        AssertOp { src: Box::new(src), checks: checks }
+    }
+}
+
+impl Check {
+    fn columns(&self) -> Vec<usize> {
+        match *self {
+            Check::NOTNULL(column) | Check::RANGE { column, .. }    => vec![column],
+            Check::UNIQUE(ref columns)                              => columns.clone(),
+        }
+    }
+
+    fn validate(&self, schema: &Schema) -> Result<(), DBError> {
+        for pos in self.columns() {
+            schema.get(pos)?;
+        }
+
+        match *self {
+            Check::RANGE { column, ref min, ref max } => {
+                let attr = schema.get(column)?;
+                if min.iter().chain(max).any(|v| v.dtype() != Some(attr.dtype)) {
+                    return Err(DBError::ExpressionInputType(format!("{} range of {}", attr.name, attr.dtype.name())))
+                }
+            }
+            Check::UNIQUE(ref columns) if columns.is_empty() =>
+                return Err(DBError::InvalidArgument("UNIQUE without columns".to_string())),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// First row of the view failing the check
+    fn first_failure<'v>(&self, view: &'v View<'v>) -> Result<Option<RowOffset>, DBError> {
+        let column = |pos: usize| view.column(pos).ok_or(DBError::make_column_unknown_pos(pos));
+
+        match *self {
+            Check::NOTNULL(pos) => {
+                let col = column(pos)?;
+                for row in 0 .. view.rows() {
+                    if column_value(col, row)?.is_null() {
+                        return Ok(Some(row))
+                    }
+                }
+            }
+            Check::RANGE { column: pos, ref min, ref max } => {
+                let col = column(pos)?;
+                for row in 0 .. view.rows() {
+                    let value = column_value(col, row)?;
+                    if value.is_null() {
+                        continue
+                    }
+
+                    let low = min.as_ref().map_or(false, |min| !CompareOp::GE.eval(&value, min));
+                    let high = max.as_ref().map_or(false, |max| !CompareOp::LE.eval(&value, max));
+                    if low || high {
+                        return Ok(Some(row))
+                    }
+                }
+            }
+            Check::UNIQUE(ref columns) => {
+                let mut seen: HashMap<u64, Vec<RowOffset>> = HashMap::new();
+                for (row, hash) in hash_rows(view, columns)?.into_iter().enumerate() {
+                    let pairs: Vec<(RowOffset, RowOffset)> = seen.get(&hash)
+                        .map_or_else(Vec::new, |rows| rows.iter().map(|&r| (r, row)).collect());
+                    if rows_equal(view, columns, view, columns, &pairs, NullEquality::NEVER)?.iter().any(|eq| *eq) {
+                        return Ok(Some(row))
+                    }
+                    seen.entry(hash).or_insert_with(Vec::new).push(row);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Check::NOTNULL(pos) =>
+                write!(f, "#{} NOT NULL", pos),
+            Check::RANGE { column, ref min, ref max } => {
+                write!(f, "#{} RANGE", column)?;
+                if let Some(ref min) = *min {
+                    write!(f, " >= {}", min)?;
+                }
+                if let Some(ref max) = *max {
+                    write!(f, " <= {}", max)?;
+                }
+                Ok(())
+            }
+            Check::UNIQUE(ref columns) => {
+                let columns: Vec<String> = columns.iter().map(|c| format!("#{}", c)).collect();
+                write!(f, "UNIQUE ({})", columns.join(", "))
+            }
+        }
+    }
+}
+
+impl<'a> Operation<'a> for AssertOp<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let input = ctx.bind(&*self.src)?;
+        for check in &self.checks {
+            check.validate(input.schema())?;
+        }
+
+        Ok(Box::new(AssertCursor { input: input, checks: self.checks.clone(), offset: 0 }))
+    }
+
+    fn describe(&self) -> String {
+        let checks: Vec<String> = self.checks.iter().map(|c| c.to_string()).collect();
+        format!("Assert {}", checks.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+}
+
+impl<'a> Cursor<'a> for AssertCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let view = match self.input.next(rows)? {
+            CursorChunk::Next(view) => view,
+            CursorChunk::End        => return Ok(CursorChunk::End),
+        };
+
+        for check in &self.checks {
+            if let Some(row) = check.first_failure(&view)? {
+                let values = (0 .. view.schema().count())
+                    .map(|pos| column_value(view.column(pos).unwrap(), row).map(|v| v.into_owned()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return Err(DBError::Assertion { check: check.to_string(), row: self.offset + row, values: values })
+            }
+        }
+
+        self.offset += view.rows();
+        Ok(CursorChunk::Next(view))
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.offset = 0;
+        self.input.rewind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::operation::ScanView;
+    use ::util::copy_value::set_column_value;
+
+    fn run<'a>(op: &(Operation<'a> + 'a), rows: RowOffset) -> Result<RowOffset, DBError> {
+        let mut cursor = ExecContext::default().bind(op)?;
+        let mut total = 0;
+        while let CursorChunk::Next(view) = cursor.next(rows)? {
+            total += view.rows();
+        }
+        Ok(total)
+    }
+
+    #[test]
+    fn checks() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id UINT32, v INT64").unwrap());
+        block.add_rows(6).unwrap();
+        for (row, &(id, v)) in [(1u32, Some(5i64)), (2, None), (3, Some(7)), (1, Some(9)), (5, Some(-1)), (6, Some(8))].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::UINT32(id)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from(v)).unwrap();
+        }
+
+        let failure = |checks: Vec<Check>, rows: RowOffset| {
+            match run(&AssertOp::new(checks, ScanView::new(&block, None)), rows).err().expect("failed check").root_cause() {
+                &DBError::Assertion { ref check, row, ref values } => (check.clone(), row, values.clone()),
+                e                                                   => panic!("expected an assertion: {}", e),
+            }
+        };
+
+        // Rows pass through
+        let op = AssertOp::new(vec![Check::NOTNULL(0), Check::UNIQUE(vec![0])], ScanView::new(&block, None));
+        assert_eq!(op.describe(), "Assert #0 NOT NULL, UNIQUE (#0)");
+        assert_eq!(run(&op, 3).unwrap(), 6);
+
+        assert_eq!(failure(vec![Check::NOTNULL(1)], 4), ("#1 NOT NULL".to_string(), 1, vec![Value::UINT32(2), Value::NULL]));
+
+        let range = Check::RANGE { column: 1, min: Some(Value::INT64(0)), max: Some(Value::INT64(10)) };
+        assert_eq!(failure(vec![range], 2), ("#1 RANGE >= 0 <= 10".to_string(), 4, vec![Value::UINT32(5), Value::INT64(-1)]));
+
+        // Within chunks
+        assert_eq!(failure(vec![Check::UNIQUE(vec![0])], 4).1, 3);
+
+        // Checks are validated against the input
+        let bad = Check::RANGE { column: 1, min: Some(Value::UINT32(0)), max: None };
+        assert!(run(&AssertOp::new(vec![bad], ScanView::new(&block, None)), 3).is_err());
+        assert!(run(&AssertOp::new(vec![Check::NOTNULL(2)], ScanView::new(&block, None)), 3).is_err());
+        let err = run(&AssertOp::new(vec![Check::UNIQUE(vec![])], ScanView::new(&block, None)), 3).unwrap_err();
+        match *err.root_cause() {
+            DBError::InvalidArgument(_) => (),
+            ref e                       => panic!("{}", e),
+        }
+    }
+}
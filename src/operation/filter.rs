@@ -0,0 +1,390 @@
+use ::allocator::Allocator;
+use ::block::{Bitmap, Block, MutBitmap, View, column_nulls, column_value, filter_view, take, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::expression::{BoundExpr, Expr};
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Type;
+use ::util::bitmap::bytes_for;
+use ::util::bloom::KeyFilter;
+use ::util::collation::Collator;
+use ::util::selection::{bits_to_indices, bools_to_bitmap};
+
+use super::{Operation, Cursor, CursorChunk};
+use super::scan_view::ScanPredicate;
+
+/// Relational Filter Operation; only the rows matching the predicate are passed through.
+///
+/// Input chunks without any matching rows are skipped, so chunks can be smaller than requested.
+pub struct Filter<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub predicate: ScanPredicate,
+}
+
+/// Implementation of the `Filter` operation
+struct FilterCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    predicate: ScanPredicate,
+    collator: Collator,
+    /// Extra filter pushed by the consumer (and not taken by the input)
+    key_filter: Option<KeyFilter>,
+    /// Matching rows of the last chunk
+    block: Option<Block<'a>>,
+    cancel: CancelToken,
+}
+
+/// Filter by a BOOLEAN expression of the input columns; rows where it's NULL are dropped.
+///
+/// Evaluates the expression for every row; use `Filter` for `column op value` predicates.
+pub struct ExprFilter<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub predicate: Box<Expr<'a> + 'a>,
+}
+
+/// Implementation of the `ExprFilter` operation
+struct ExprFilterCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    predicate: Box<BoundExpr<'a> + 'a>,
+    /// Matching rows of the last chunk
+    block: Option<Block<'a>>,
+    cancel: CancelToken,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new<T: Operation<'a> + 'a>(predicate: ScanPredicate, src: T) -> Filter<'a> {
+        Filter { src: Box::new(src), predicate: predicate }
+    }
+}
+
+impl<'a> Operation<'a> for Filter<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let input = ctx.bind(&*self.src)?;
+
+        {
+            let attr = input.schema().get(self.predicate.column)?;
+            if self.predicate.value.dtype() != Some(attr.dtype) {
+                return Err(DBError::ExpressionInputType(attr.dtype.name().to_string()))
+            }
+        }
+
+        Ok(Box::new(FilterCursor {
+            input: input,
+            alloc: ctx.allocator(),
+            predicate: self.predicate.clone(),
+            collator: self.predicate.collation.collator()?,
+            key_filter: None,
+            block: None,
+            cancel: ctx.cancel_token().clone(),
+        }))
+    }
+
+    fn describe(&self) -> String {
+        let p = &self.predicate;
+        let mut out = format!("Filter #{} {:?} {}", p.column, p.op, p.value);
+        if !p.collation.is_binary() {
+            out += &format!(" COLLATE {}", p.collation);
+        }
+        out
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+
+    fn predicate(&self) -> Option<&ScanPredicate> {
+        Some(&self.predicate)
+    }
+}
+
+impl<'a> Cursor<'a> for FilterCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        loop {
+            // Inputs without matching rows can be long
+            self.cancel.check()?;
+
+            let view = match self.input.next(rows)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => return Ok(CursorChunk::End),
+            };
+
+            let selected = {
+                let pos = self.predicate.column;
+                let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                let rows = view.rows();
+
+                // Candidate rows: not NULL (never matches) and passing the key filter
+                let mut bits = vec![0u8; bytes_for(rows)];
+                {
+                    let mut candidates = MutBitmap::new(&mut bits, rows);
+                    candidates.fill(0, rows, true);
+                    if col.attribute().nullable {
+                        candidates.and_not(&column_nulls(col).slice(0, rows));
+                    }
+                    if let Some(ref f) = self.key_filter {
+                        let keys = bools_to_bitmap(&f.matches(&view)?);
+                        candidates.and(&Bitmap::new(&keys, 0, rows));
+                    }
+                }
+
+                match col.runs() {
+                    // Run-length encoded: the predicate is evaluated once per run
+                    Some(runs) => {
+                        {
+                            let mut candidates = MutBitmap::new(&mut bits, rows);
+                            for run in 0 .. runs.count() {
+                                if !self.predicate.matches_with(&self.collator, &column_value(runs.values, run)?) {
+                                    let range = runs.range(run);
+                                    candidates.fill(range.offset, range.rows, false);
+                                }
+                            }
+                        }
+                        bits_to_indices(&bits, 0, rows, true)
+                    }
+                    None => {
+                        let mut selected = Vec::new();
+                        for row in bits_to_indices(&bits, 0, rows, true) {
+                            if self.predicate.matches_with(&self.collator, &column_value(col, row)?) {
+                                selected.push(row);
+                            }
+                        }
+                        selected
+                    }
+                }
+            };
+
+            if selected.is_empty() {
+                continue
+            }
+
+            self.block = Some(take(self.alloc, &view, &selected)?);
+            break
+        }
+
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.block = None;
+        self.input.rewind()
+    }
+
+    /// Passed on to the input, otherwise applied along with the predicate
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        if !self.input.push_key_filter(filter) {
+            self.key_filter = Some(filter.clone());
+        }
+        true
+    }
+}
+
+impl<'a> ExprFilter<'a> {
+    pub fn new<E: Expr<'a> + 'a, T: Operation<'a> + 'a>(predicate: E, src: T) -> ExprFilter<'a> {
+        ExprFilter { src: Box::new(src), predicate: Box::new(predicate) }
+    }
+}
+
+impl<'a> Operation<'a> for ExprFilter<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let input = ctx.bind(&*self.src)?;
+        let alloc: &'a Allocator = ctx.allocator();
+        let predicate = self.predicate.bind(alloc, input.schema())?;
+
+        {
+            let out = predicate.schema();
+            if out.count() != 1 {
+                return Err(DBError::ExpressionInputCount(format!("{} != 1", out.count())))
+            }
+            if out.get(0)?.dtype != Type::BOOLEAN {
+                return Err(DBError::ExpressionInputType(out.get(0)?.dtype.name().to_string()))
+            }
+        }
+
+        Ok(Box::new(ExprFilterCursor {
+            input: input,
+            alloc: alloc,
+            predicate: predicate,
+            block: None,
+            cancel: ctx.cancel_token().clone(),
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Filter {}", self.predicate.describe())
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+}
+
+impl<'a> Cursor<'a> for ExprFilterCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        loop {
+            self.cancel.check()?;
+
+            let view = match self.input.next(rows)? {
+                CursorChunk::Next(view) => view,
+                CursorChunk::End        => return Ok(CursorChunk::End),
+            };
+
+            let mask = self.predicate.evaluate(&view, view.rows())?;
+            let block = filter_view(self.alloc, &view, mask.column(0).unwrap())?;
+            if block.rows() == 0 {
+                continue
+            }
+
+            self.block = Some(block);
+            break
+        }
+
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.block = None;
+        self.input.rewind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{RefView, View, alias_column, column_row_data, rle_encode};
+    use ::expression::comparison::CompareOp;
+    use ::operation::ScanView;
+    use ::plan::ScalarExpr;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn filter() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, v INT64").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+        for row in 0 .. 10 {
+            let v = if row % 3 == 0 { Value::NULL } else { Value::INT64(row as i64) };
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut block, 1, row, &v).unwrap();
+        }
+
+        let op = Filter::new(ScanPredicate::new(1, CompareOp::GE, 5i64), ScanView::new(&block, None));
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+
+        // The first chunk has no matches
+        while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+            ids.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+
+        // NULLs don't match
+        assert_eq!(ids, vec![5, 7, 8]);
+
+        let bad = Filter::new(ScanPredicate::new(1, CompareOp::EQ, "x"), ScanView::new(&block, None));
+        assert!(bad.bind(&ExecContext::default()).is_err());
+    }
+
+    #[test]
+    fn expr_filter() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, v INT64").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(6).unwrap();
+        for row in 0 .. 6 {
+            let v = if row % 3 == 0 { Value::NULL } else { Value::INT64(row as i64) };
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut block, 1, row, &v).unwrap();
+        }
+
+        // NULL predicates don't match: v > 4 OR id = 0
+        let predicate = ScalarExpr::or(
+            ScalarExpr::compare(CompareOp::GT, ScalarExpr::column(1), ScalarExpr::literal(4i64)),
+            ScalarExpr::compare(CompareOp::EQ, ScalarExpr::column(0), ScalarExpr::literal(0u32)));
+        let op = ExprFilter::new(predicate, ScanView::new(&block, None));
+        assert_eq!(op.describe(), "Filter (#1 > 4 OR #0 = 0)");
+
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            ids.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        assert_eq!(ids, vec![0, 5]);
+
+        let bad = ExprFilter::new(ScalarExpr::column(1), ScanView::new(&block, None));
+        assert!(bad.bind(&ExecContext::default()).is_err());
+    }
+
+    #[test]
+    fn rle() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, v INT64").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+        for (row, &v) in [Some(1i64), Some(1), Some(1), Some(2), Some(2), None, None, Some(3), Some(3), Some(3)].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut block, 1, row, &v).unwrap();
+        }
+
+        let rle = rle_encode(&allocator::GLOBAL, &block[1], 10).unwrap();
+        let view = RefView::new(schema.clone(), vec![alias_column(&block[0], None).unwrap(), alias_column(&rle, None).unwrap()], 10);
+
+        // Chunks of 4 rows cut the runs
+        let op = Filter::new(ScanPredicate::new(1, CompareOp::GE, 2i64), ScanView::new(&view, None));
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+            ids.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+
+        assert_eq!(ids, vec![3, 4, 7, 8, 9]);
+    }
+}
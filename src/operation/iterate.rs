@@ -0,0 +1,350 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use ::allocator::Allocator;
+use ::block::{Block, SharedBlock, View, take, window_alias};
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::util::row_hash::{NullEquality, hash_rows, rows_equal};
+
+use super::{Operation, Cursor, CursorChunk};
+use super::scan_table::ScanTableCursor;
+
+/// Fixpoint iteration. The `step` operation is run over the new rows of the previous iteration
+/// (read through its `WorkingSet` input), starting with the `seed` rows, until it doesn't produce
+/// any new rows or it ran `max_iterations` times.
+///
+/// The output is the distinct rows of the seed and of every iteration (like a recursive `UNION`
+/// query), eg. the nodes of a graph reachable from the seed nodes. The step's output columns must
+/// have the same types as the seed's. The iterations run as the cursor is read; new rows are
+/// returned as soon as they're found. The number of step runs is counted in the `iterations`
+/// metric.
+///
+/// The step is shared with the bound cursors (which bind it for every iteration), so it's only
+/// exposed to rewrites (`inputs_mut`) while there are none.
+pub struct Iterate<'a> {
+    pub seed: Box<Operation<'a> + 'a>,
+    pub step: Rc<Box<Operation<'a> + 'a>>,
+    pub input: WorkingSet<'a>,
+    pub max_iterations: usize,
+}
+
+/// Input of an `Iterate` step: the rows the previous iteration added
+#[derive(Clone, Default)]
+pub struct WorkingSet<'a> {
+    rows: Rc<RefCell<Option<SharedBlock<'a>>>>,
+}
+
+impl<'a> Iterate<'a> {
+    /// The step is built over the working set
+    pub fn new<S, T, F>(seed: S, max_iterations: usize, step: F) -> Iterate<'a>
+        where S: Operation<'a> + 'a, T: Operation<'a> + 'a, F: FnOnce(WorkingSet<'a>) -> T
+    {
+        let input = WorkingSet::default();
+        let step: Box<Operation<'a> + 'a> = Box::new(step(input.clone()));
+        Iterate { seed: Box::new(seed), step: Rc::new(step), input: input, max_iterations: max_iterations }
+    }
+}
+
+/// Implementation of the `Iterate` operation
+struct IterateCursor<'a> {
+    ctx: ExecContext<'a>,
+    step_op: Rc<Box<Operation<'a> + 'a>>,
+    input: WorkingSet<'a>,
+    max_iterations: usize,
+    /// Seed cursor, then the step cursor of the current iteration; `None` once done
+    source: Option<Box<Cursor<'a> + 'a>>,
+    distinct: DistinctRows<'a>,
+    /// Rows added by the current iteration; the working set of the next one
+    delta: Block<'a>,
+    iterations: usize,
+    /// New rows of the last chunk
+    block: Option<Block<'a>>,
+}
+
+/// Distinct rows, and the hash table used to check for new ones
+struct DistinctRows<'a> {
+    alloc: &'a Allocator,
+    rows: Block<'a>,
+    columns: Vec<usize>,
+    table: HashMap<u64, Vec<RowOffset>>,
+}
+
+impl<'a> DistinctRows<'a> {
+    fn new(alloc: &'a Allocator, schema: &Schema) -> DistinctRows<'a> {
+        DistinctRows {
+            alloc: alloc,
+            rows: Block::new(alloc, schema),
+            columns: (0 .. schema.count()).collect(),
+            table: HashMap::new(),
+        }
+    }
+
+    /// Add the rows of the view that aren't in the set yet (NULLs are equal), returns them
+    fn add<'v>(&mut self, view: &'v View<'v>) -> Result<Block<'a>, DBError> {
+        let hashes = hash_rows(view, &self.columns)?;
+
+        let mut pairs = Vec::new();
+        for (row, hash) in hashes.iter().enumerate() {
+            if let Some(rows) = self.table.get(hash) {
+                pairs.extend(rows.iter().map(|&r| (r, row)));
+            }
+        }
+
+        let mut seen = vec![false; view.rows()];
+        let equal = rows_equal(&self.rows, &self.columns, view, &self.columns, &pairs, NullEquality::MATCH)?;
+        for (&(_, row), eq) in pairs.iter().zip(equal) {
+            seen[row] |= eq;
+        }
+
+        // Duplicates within the view
+        let mut added: HashMap<u64, Vec<RowOffset>> = HashMap::new();
+        let mut new = Vec::new();
+        for row in (0 .. view.rows()).filter(|row| !seen[*row]) {
+            let pairs: Vec<(RowOffset, RowOffset)> = added.get(&hashes[row])
+                .map_or_else(Vec::new, |rows| rows.iter().map(|&r| (r, row)).collect());
+            if rows_equal(view, &self.columns, view, &self.columns, &pairs, NullEquality::MATCH)?.iter().any(|eq| *eq) {
+                continue
+            }
+
+            added.entry(hashes[row]).or_insert_with(Vec::new).push(row);
+            new.push(row);
+        }
+
+        let out = take(self.alloc, view, &new)?;
+        let start = self.rows.append_view(&out)?;
+        for (pos, row) in new.into_iter().enumerate() {
+            self.table.entry(hashes[row]).or_insert_with(Vec::new).push(start + pos);
+        }
+
+        Ok(out)
+    }
+}
+
+impl<'a> Operation<'a> for Iterate<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let alloc: &'a Allocator = ctx.allocator();
+        let seed = ctx.bind(&*self.seed)?;
+        let schema = seed.schema().clone();
+
+        Ok(Box::new(IterateCursor {
+            ctx: ctx.with_allocator(alloc),
+            step_op: self.step.clone(),
+            input: self.input.clone(),
+            max_iterations: self.max_iterations,
+            source: Some(seed),
+            distinct: DistinctRows::new(alloc, &schema),
+            delta: Block::new(alloc, &schema),
+            iterations: 0,
+            block: None,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Iterate max_iterations={}", self.max_iterations)
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.seed, &**self.step]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        let mut out = vec![&mut self.seed];
+        if let Some(step) = Rc::get_mut(&mut self.step) {
+            out.push(step);
+        }
+        out
+    }
+}
+
+impl<'a> IterateCursor<'a> {
+    /// Read the sources until there are new rows (`block`). False once the fixpoint is reached.
+    fn advance(&mut self, rows: RowOffset) -> Result<bool, DBError> {
+        loop {
+            self.ctx.cancel_token().check()?;
+
+            let added = match self.source {
+                Some(ref mut source) => match source.next(rows)? {
+                    CursorChunk::Next(view)    => Some(self.distinct.add(&view)?),
+                    CursorChunk::End           => None,
+                },
+                None => return Ok(false),
+            };
+
+            match added {
+                Some(block) => {
+                    if block.rows() == 0 {
+                        continue
+                    }
+
+                    self.delta.append_view(&block)?;
+                    self.block = Some(block);
+                    return Ok(true)
+                }
+                None => self.next_iteration()?,
+            }
+        }
+    }
+
+    /// Start the next iteration over the rows the last one added, if there are any
+    fn next_iteration(&mut self) -> Result<(), DBError> {
+        self.source = None;
+
+        if self.delta.rows() == 0 || self.iterations >= self.max_iterations {
+            self.input.set(None);
+            self.ctx.metrics().add("iterations", self.iterations as u64);
+            return Ok(())
+        }
+
+        let next = Block::new(self.delta.allocator(), self.distinct.rows.schema());
+        self.input.set(Some(SharedBlock::new(mem::replace(&mut self.delta, next))));
+        self.iterations += 1;
+
+        let step = self.ctx.bind(&**self.step_op)?;
+        {
+            let (expected, actual) = (self.distinct.rows.schema(), step.schema());
+            if expected.count() != actual.count() || expected.iter().zip(actual.iter()).any(|(l, r)| l.dtype != r.dtype) {
+                return Err(DBError::SchemaMismatch(format!("iteration step output ({}) doesn't match the seed ({})",
+                                                           actual, expected)))
+            }
+        }
+
+        self.source = Some(step);
+        Ok(())
+    }
+}
+
+impl<'a> Cursor<'a> for IterateCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.distinct.rows.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        match self.advance(rows) {
+            Ok(true)    => Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?)),
+            Ok(false)   => Ok(CursorChunk::End),
+            Err(e)      => {
+                // The working set doesn't outlive a failed iteration
+                self.source = None;
+                self.input.set(None);
+                Err(e)
+            }
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.distinct.rows.memory_usage().total() + self.delta.memory_usage().total()
+            + self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+}
+
+impl<'a> WorkingSet<'a> {
+    fn set(&self, rows: Option<SharedBlock<'a>>) {
+        *self.rows.borrow_mut() = rows;
+    }
+
+    /// Rows of the current iteration; `None` outside of an iteration
+    pub fn rows(&self) -> Option<SharedBlock<'a>> {
+        self.rows.borrow().clone()
+    }
+}
+
+impl<'a> Operation<'a> for WorkingSet<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        match *self.rows.borrow() {
+            Some(ref rows)  => Ok(Box::new(ScanTableCursor::new(rows.clone(), ctx))),
+            None            => Err(DBError::Execution("working set bound outside of its Iterate".to_string())),
+        }
+    }
+
+    fn describe(&self) -> String {
+        "WorkingSet".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::{HashJoin, Project, ScanView};
+    use ::plan::JoinKind;
+    use ::projector::project_by_position;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    fn nodes<'a>(cursor: &mut (Cursor<'a> + 'a)) -> Vec<u32> {
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            out.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn reachability() {
+        let mut edges = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("src UINT32 NOT NULL, dst UINT32 NOT NULL").unwrap());
+        edges.add_rows(5).unwrap();
+        for (row, &(src, dst)) in [(1u32, 2u32), (2, 3), (3, 1), (2, 4), (5, 6)].iter().enumerate() {
+            set_column_value(&mut edges, 0, row, &Value::UINT32(src)).unwrap();
+            set_column_value(&mut edges, 1, row, &Value::UINT32(dst)).unwrap();
+        }
+
+        let mut start = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("node UINT32 NOT NULL").unwrap());
+        start.add_rows(2).unwrap();
+        set_column_value(&mut start, 0, 0, &Value::UINT32(1)).unwrap();
+        set_column_value(&mut start, 0, 1, &Value::UINT32(1)).unwrap();
+
+        let reachable = |max| Iterate::new(ScanView::new(&start, None), max, |input| {
+            let join = HashJoin::new(JoinKind::INNER, vec![(0, 0)], input, ScanView::new(&edges, None));
+            Project::new(project_by_position(2), join)
+        });
+
+        // Stops once a step doesn't find new nodes
+        let ctx = ExecContext::default();
+        let op = reachable(100);
+        assert_eq!(op.describe(), "Iterate max_iterations=100");
+        assert_eq!(nodes(&mut *ctx.bind(&op).unwrap()), vec![1, 2, 3, 4]);
+        assert_eq!(ctx.metrics().get("iterations"), 3);
+
+        let ctx = ExecContext::default();
+        assert_eq!(nodes(&mut *ctx.bind(&reachable(1)).unwrap()), vec![1, 2]);
+        assert_eq!(ctx.metrics().get("iterations"), 1);
+
+        // Nothing runs until the cursor is read
+        let ctx = ExecContext::default();
+        let op = reachable(100);
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert!(op.input.rows().is_none());
+        match cursor.next(2).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 1),
+            CursorChunk::End        => panic!("expected the seed"),
+        }
+        assert_eq!(ctx.metrics().get("iterations"), 0);
+
+        // Steps have to return the seed's column types; the working set is cleared on error
+        let op = Iterate::new(ScanView::new(&start, None), 10, |input| {
+            HashJoin::new(JoinKind::INNER, vec![(0, 0)], input, ScanView::new(&edges, None))
+        });
+        let mut cursor = ctx.bind(&op).unwrap();
+        let err = loop {
+            match cursor.next(2) {
+                Ok(CursorChunk::Next(_))    => (),
+                Ok(CursorChunk::End)        => panic!("expected a schema mismatch"),
+                Err(e)                      => break e,
+            }
+        };
+        match *err.root_cause() {
+            DBError::SchemaMismatch(_)  => (),
+            ref e                       => panic!("expected a schema mismatch: {}", e),
+        }
+        assert!(op.input.rows().is_none());
+
+        assert!(ctx.bind(&WorkingSet::default()).is_err());
+    }
+}
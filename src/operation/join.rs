@@ -0,0 +1,864 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value, take, window_alias};
+use ::block::serialize::serialize_view;
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext, Metrics};
+use ::plan::JoinKind;
+use ::row::{RowOffset, RowRange};
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+use ::util::bloom::{BloomFilter, KeyFilter};
+use ::util::copy_value::ValueSetter;
+use ::util::collation::{Collation, Collator};
+use ::util::row_hash::{NullEquality, hash_rows_collated, mix, rows_equal_collated};
+
+use super::{Operation, Cursor, CursorChunk, Pipelining};
+
+/// Equi-join on pairs of (left, right) key columns. The right input is read into a hash table,
+/// the left input is streamed through it.
+///
+/// The output has the left columns followed by the right ones; `LEFT` joins keep the unmatched
+/// left rows with NULL right columns. NULL keys don't match anything. Keys of different types are
+/// compared as their common type (`join_key_type`), the casts are added when the join is bound.
+/// Dictionary encoded keys are compared by value.
+///
+/// The join starts out with all the right rows in memory. If they go over the memory budget
+/// (`DBError::MemoryLimit` from the allocator) it switches to a grace join: both inputs are split
+/// by key hash into `GRACE_PARTITIONS` temporary files and joined one partition at a time. A
+/// partition that doesn't fit in the budget still fails. The strategy is counted in the `join_hash`
+/// or `join_grace` metric.
+///
+/// For in memory `INNER` joins a bloom filter of the build keys is pushed into the left input
+/// (`Cursor::push_key_filter`), so scans can drop the rows without a match early.
+///
+/// TEXT keys are compared under the collation of their pair.
+pub struct HashJoin<'a> {
+    pub left: Box<Operation<'a> + 'a>,
+    pub right: Box<Operation<'a> + 'a>,
+    pub kind: JoinKind,
+    pub on: Vec<(usize, usize)>,
+    /// Collation of every pair of keys; BINARY for the pairs past its end
+    pub collations: Vec<Collation>,
+}
+
+impl<'a> HashJoin<'a> {
+    pub fn new<L, R>(kind: JoinKind, on: Vec<(usize, usize)>, left: L, right: R) -> HashJoin<'a>
+        where L: Operation<'a> + 'a, R: Operation<'a> + 'a
+    {
+        HashJoin { left: Box::new(left), right: Box::new(right), kind: kind, on: on, collations: Vec::new() }
+    }
+
+    pub fn with_collations(self, collations: Vec<Collation>) -> HashJoin<'a> {
+        HashJoin { collations: collations, ..self }
+    }
+}
+
+/// Partitions of the grace join
+pub const GRACE_PARTITIONS: usize = 16;
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Type keys of type `l` and `r` are compared as; `None` if they can't be. Integers are widened
+/// (to a signed type if one is signed), 32 bit integers and floats compare as FLOAT64, JSON as TEXT.
+pub fn join_key_type(l: Type, r: Type) -> Option<Type> {
+    if l == r {
+        return Some(l)
+    }
+
+    widen(l, r).or_else(|| widen(r, l))
+}
+
+fn widen(l: Type, r: Type) -> Option<Type> {
+    match (l, r) {
+        (Type::UINT32, Type::UINT64)                        => Some(Type::UINT64),
+        (Type::UINT32, Type::INT32)                         |
+        (Type::UINT32, Type::INT64)                         |
+        (Type::INT32, Type::INT64)                          => Some(Type::INT64),
+        (Type::UINT32, Type::FLOAT32)                       |
+        (Type::UINT32, Type::FLOAT64)                       |
+        (Type::INT32, Type::FLOAT32)                        |
+        (Type::INT32, Type::FLOAT64)                        |
+        (Type::FLOAT32, Type::FLOAT64)                      => Some(Type::FLOAT64),
+        (Type::TEXT, Type::JSON)                            => Some(Type::TEXT),
+        _                                                   => None,
+    }
+}
+
+/// Key value cast to the join key type
+fn cast_key<'v>(value: Value<'v>, dtype: Type) -> Result<Value<'v>, DBError> {
+    if value.is_null() || value.dtype() == Some(dtype) {
+        return Ok(value)
+    }
+
+    Ok(match (value, dtype) {
+        (Value::UINT32(v), Type::UINT64)    => Value::UINT64(v as u64),
+        (Value::UINT32(v), Type::INT64)     => Value::INT64(v as i64),
+        (Value::INT32(v), Type::INT64)      => Value::INT64(v as i64),
+        (Value::UINT32(v), Type::FLOAT64)   => Value::FLOAT64(v as f64),
+        (Value::INT32(v), Type::FLOAT64)    => Value::FLOAT64(v as f64),
+        (Value::FLOAT32(v), Type::FLOAT64)  => Value::FLOAT64(v as f64),
+        (Value::JSON(v), Type::TEXT)        => Value::TEXT(v),
+        (v, _)                              => return Err(DBError::AttributeType(format!("{} as {}", v, dtype.name()))),
+    })
+}
+
+/// Key columns of one side of the join
+#[derive(Clone)]
+struct JoinKeys {
+    columns: Vec<usize>,
+    /// Join key type of every column that has to be cast
+    casts: Vec<Option<Attribute>>,
+    collators: Vec<Collator>,
+}
+
+impl JoinKeys {
+    /// Key columns cast to the join key types, `None` if they don't need casts
+    fn cast<'a, 'v>(&self, alloc: &'a Allocator, view: &'v View<'v>) -> Result<Option<Block<'a>>, DBError> {
+        if self.casts.iter().all(|c| c.is_none()) {
+            return Ok(None)
+        }
+
+        let attrs = self.columns.iter().zip(&self.casts)
+            .map(|(&pos, cast)| cast.clone().map_or_else(|| view.schema().get(pos).map(|a| a.clone()), Ok))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut out = Block::new(alloc, &Schema::from_vec(attrs)?);
+        if view.rows() == 0 {
+            return Ok(Some(out))
+        }
+
+        out.add_rows(view.rows())?;
+        for (key, (&pos, cast)) in self.columns.iter().zip(&self.casts).enumerate() {
+            let src = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            let dst = out.column_mut(key).unwrap();
+
+            match *cast {
+                None            => dst.copy_rows(0, src, 0, view.rows())?,
+                Some(ref attr)  => for row in 0 .. view.rows() {
+                    cast_key(column_value(src, row)?, attr.dtype)?.set_row(dst, row)?;
+                },
+            }
+        }
+
+        Ok(Some(out))
+    }
+
+    /// Positions of the keys in the output of `cast`
+    fn cast_columns(&self) -> Vec<usize> {
+        (0 .. self.columns.len()).collect()
+    }
+
+    /// Hashes of the (cast) keys of every row
+    fn hashes<'a, 'v>(&self, alloc: &'a Allocator, view: &'v View<'v>) -> Result<Vec<u64>, DBError> {
+        match self.cast(alloc, view)? {
+            Some(ref keys)  => hash_rows_collated(keys, &self.cast_columns(), &self.collators),
+            None            => hash_rows_collated(view, &self.columns, &self.collators),
+        }
+    }
+}
+
+/// Temporary file of serialized blocks, removed when dropped
+struct SpillFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// Blocks written
+    blocks: usize,
+}
+
+impl SpillFile {
+    fn new() -> Result<SpillFile, DBError> {
+        let id = SPILL_FILES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("dbkit-join-{}-{}", process::id(), id));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(SpillFile { path: path, out: BufWriter::new(file), blocks: 0 })
+    }
+
+    fn write<'v>(&mut self, view: &'v View<'v>) -> Result<(), DBError> {
+        serialize_view(view, &mut self.out)?;
+        self.blocks += 1;
+        Ok(())
+    }
+
+    /// Reader of the blocks written so far, and their count
+    fn reader(&mut self) -> Result<(BufReader<File>, usize), DBError> {
+        self.out.flush()?;
+        Ok((BufReader::new(File::open(&self.path)?), self.blocks))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Rows of one join input split by key hash into temporary files, one per partition
+struct Partitions {
+    files: Vec<SpillFile>,
+}
+
+impl Partitions {
+    fn new(count: usize) -> Result<Partitions, DBError> {
+        Ok(Partitions { files: (0 .. count).map(|_| SpillFile::new()).collect::<Result<_, _>>()? })
+    }
+
+    /// Partition of a key hash; uses other bits than the hash table does
+    fn partition(&self, hash: u64) -> usize {
+        (mix(hash) % self.files.len() as u64) as usize
+    }
+
+    /// Append the rows of the view to the partitions of their keys, at most `rows` at a time
+    fn write<'a, 'v>(&mut self, alloc: &'a Allocator, keys: &JoinKeys, view: &'v View<'v>, rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        let mut offset = 0;
+        while offset < view.rows() {
+            let range = RowRange { offset: offset, rows: min(rows.max(1), view.rows() - offset) };
+            let sub = window_alias(view, Some(range))?;
+            offset += range.rows;
+
+            let mut parts = vec![Vec::new(); self.files.len()];
+            for (row, hash) in keys.hashes(alloc, &sub)?.into_iter().enumerate() {
+                parts[self.partition(hash)].push(row);
+            }
+
+            for (file, part) in self.files.iter_mut().zip(parts) {
+                if !part.is_empty() {
+                    file.write(&take(alloc, &sub, &part)?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reader(&mut self, part: usize) -> Result<(BufReader<File>, usize), DBError> {
+        self.files[part].reader()
+    }
+}
+
+/// State of a grace join, after the right input went over the memory budget
+struct Grace {
+    right: Partitions,
+    left: Partitions,
+    /// The left input is partitioned
+    partitioned: bool,
+    /// Next partition to join
+    next: usize,
+    /// Left blocks left to probe in the current partition
+    probe: Option<(BufReader<File>, usize)>,
+}
+
+impl Grace {
+    fn new() -> Result<Grace, DBError> {
+        Ok(Grace {
+            right: Partitions::new(GRACE_PARTITIONS)?,
+            left: Partitions::new(GRACE_PARTITIONS)?,
+            partitioned: false,
+            next: 0,
+            probe: None,
+        })
+    }
+
+    /// Next left block to probe, loading the right rows of its partition into the table first;
+    /// `None` once all partitions are joined
+    fn next_block<'a, 'l>(&mut self, table: &mut JoinTable<'a>, left: &mut (Cursor<'l> + 'l), fetch: RowOffset)
+        -> Result<Option<Block<'a>>, DBError>
+    {
+        if !self.partitioned {
+            while let CursorChunk::Next(view) = left.next(fetch)? {
+                self.left.write(table.alloc, &table.left_keys, &view, fetch)?;
+                table.cancel.check()?;
+            }
+            self.partitioned = true;
+        }
+
+        loop {
+            table.cancel.check()?;
+
+            if let Some((ref mut input, ref mut blocks)) = self.probe {
+                if *blocks > 0 {
+                    *blocks -= 1;
+                    return Ok(Some(Block::deserialize(input, table.alloc)?))
+                }
+            }
+
+            if self.next == GRACE_PARTITIONS {
+                return Ok(None)
+            }
+
+            let part = self.next;
+            self.next += 1;
+
+            let (mut input, blocks) = self.right.reader(part)?;
+            if blocks == 0 && table.kind == JoinKind::INNER {
+                self.probe = None;
+                continue
+            }
+
+            table.build = Block::new(table.alloc, table.build.schema());
+            for _ in 0 .. blocks {
+                table.build.append_view(&Block::deserialize(&mut input, table.alloc)?)?;
+            }
+            table.index()?;
+            self.probe = Some(self.left.reader(part)?);
+        }
+    }
+}
+
+impl<'a> Operation<'a> for HashJoin<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let left = ctx.bind(&*self.left)?;
+        let right = ctx.bind(&*self.right)?;
+
+        let collators = self.collations.iter().map(Collation::collator).collect::<Result<Vec<_>, _>>()?;
+        let mut left_keys = JoinKeys { columns: Vec::new(), casts: Vec::new(), collators: collators };
+        let mut right_keys = left_keys.clone();
+        let mut attrs: Vec<Attribute> = left.schema().iter().cloned().collect();
+        {
+            let (lschema, rschema) = (left.schema(), right.schema());
+            for &(l, r) in &self.on {
+                let (lattr, rattr) = (lschema.get(l)?, rschema.get(r)?);
+                let dtype = join_key_type(lattr.dtype, rattr.dtype)
+                    .ok_or_else(|| DBError::AttributeType(format!("join key {} {} = {} {}", lattr.name, lattr.dtype.name(),
+                                                                  rattr.name, rattr.dtype.name())))?;
+
+                left_keys.columns.push(l);
+                left_keys.casts.push(if lattr.dtype == dtype { None } else { Some(lattr.cast(dtype)) });
+                right_keys.columns.push(r);
+                right_keys.casts.push(if rattr.dtype == dtype { None } else { Some(rattr.cast(dtype)) });
+            }
+
+            for attr in rschema.iter() {
+                let mut attr = attr.clone();
+                attr.nullable |= self.kind == JoinKind::LEFT;
+                attrs.push(attr);
+            }
+        }
+
+        let alloc: &'a Allocator = ctx.allocator();
+        let build = Block::new(alloc, right.schema());
+
+        Ok(Box::new(HashJoinCursor {
+            fetch: ctx.fetch_rows(&*right),
+            metrics: ctx.metrics().clone(),
+            left: left,
+            right: right,
+            built: false,
+            grace: None,
+            state: JoinTable {
+                alloc: alloc,
+                kind: self.kind,
+                schema: Schema::from_vec(attrs)?,
+                left_keys: left_keys,
+                right_keys: right_keys,
+                build: build,
+                build_keys: None,
+                table: None,
+                cancel: ctx.cancel_token().clone(),
+            },
+            block: None,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        let on: Vec<_> = self.on.iter().map(|&(l, r)| format!("#{} = #{}", l, r)).collect();
+        let mut out = format!("HashJoin {:?} on [{}]", self.kind, on.join(", "));
+        if self.collations.iter().any(|c| !c.is_binary()) {
+            let collations: Vec<_> = self.collations.iter().map(|c| c.to_string()).collect();
+            out += &format!(" COLLATE [{}]", collations.join(", "));
+        }
+        out
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.left, &*self.right]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.left, &mut self.right]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+/// Implementation of the `HashJoin` operation
+struct HashJoinCursor<'a> {
+    left: Box<Cursor<'a> + 'a>,
+    right: Box<Cursor<'a> + 'a>,
+    fetch: RowOffset,
+    metrics: Arc<Metrics>,
+    state: JoinTable<'a>,
+    /// The right input is read
+    built: bool,
+    /// Set when the right input didn't fit in memory
+    grace: Option<Grace>,
+    /// Output of the last left chunk
+    block: Option<Block<'a>>,
+}
+
+/// Right input rows and their hash table
+struct JoinTable<'a> {
+    alloc: &'a Allocator,
+    kind: JoinKind,
+    schema: Schema,
+    left_keys: JoinKeys,
+    right_keys: JoinKeys,
+    /// Right input rows
+    build: Block<'a>,
+    /// Cast build keys, when they need casts
+    build_keys: Option<Block<'a>>,
+    /// Build rows by key hash, once the right input is read
+    table: Option<HashMap<u64, Vec<RowOffset>>>,
+    /// Checked while reading the inputs
+    cancel: CancelToken,
+}
+
+impl<'a> JoinTable<'a> {
+    /// Read the right input. If it goes over the memory budget the rows are written out to
+    /// partitions instead, for a grace join.
+    fn build<'r>(&mut self, right: &mut (Cursor<'r> + 'r), fetch: RowOffset) -> Result<Option<Grace>, DBError> {
+        while let CursorChunk::Next(view) = right.next(fetch)? {
+            self.cancel.check()?;
+
+            match self.build.append_view(&view) {
+                Ok(_)                       => continue,
+                Err(DBError::MemoryLimit)   => (),
+                Err(e)                      => return Err(e),
+            }
+
+            // Partitioning takes memory, so the rows read so far are written out as they are first
+            let mut staged = SpillFile::new()?;
+            let mut offset = 0;
+            while offset < self.build.rows() {
+                let range = RowRange { offset: offset, rows: min(fetch.max(1), self.build.rows() - offset) };
+                staged.write(&window_alias(&self.build, Some(range))?)?;
+                offset += range.rows;
+            }
+            self.build = Block::new(self.alloc, self.build.schema());
+
+            let mut grace = Grace::new()?;
+            let (mut input, blocks) = staged.reader()?;
+            for _ in 0 .. blocks {
+                grace.right.write(self.alloc, &self.right_keys, &Block::deserialize(&mut input, self.alloc)?, fetch)?;
+            }
+
+            grace.right.write(self.alloc, &self.right_keys, &view, fetch)?;
+            while let CursorChunk::Next(view) = right.next(fetch)? {
+                self.cancel.check()?;
+                grace.right.write(self.alloc, &self.right_keys, &view, fetch)?;
+            }
+            return Ok(Some(grace))
+        }
+
+        Ok(None)
+    }
+
+    /// Hash table of the build rows. Returns a filter of the build keys to push into the left
+    /// input, if the left keys can be tested without casts and unmatched rows can be dropped.
+    fn index(&mut self) -> Result<Option<KeyFilter>, DBError> {
+        self.build_keys = self.right_keys.cast(self.alloc, &self.build)?;
+        let hashes = match self.build_keys {
+            Some(ref keys)  => hash_rows_collated(keys, &self.right_keys.cast_columns(), &self.right_keys.collators)?,
+            None            => hash_rows_collated(&self.build, &self.right_keys.columns, &self.right_keys.collators)?,
+        };
+
+        // The filter hashes the left keys as they are
+        let binary = self.left_keys.collators.iter().all(Collator::is_binary);
+        let filter = if self.kind == JoinKind::INNER && binary && self.left_keys.casts.iter().all(|c| c.is_none()) {
+            let mut bloom = BloomFilter::with_keys(hashes.len());
+            bloom.insert_all(&hashes);
+            Some(KeyFilter { columns: self.left_keys.columns.clone(), bloom: Arc::new(bloom) })
+        } else {
+            None
+        };
+
+        let mut table: HashMap<u64, Vec<RowOffset>> = HashMap::new();
+        for (row, hash) in hashes.into_iter().enumerate() {
+            table.entry(hash).or_insert_with(Vec::new).push(row);
+        }
+
+        self.table = Some(table);
+        Ok(filter)
+    }
+
+    /// Matching (build, probe) row pairs of the probe keys, by probe row
+    fn matches<'p>(&self, probe: &'p View<'p>, columns: &[usize]) -> Result<Vec<(RowOffset, RowOffset)>, DBError> {
+        let table = self.table.as_ref().unwrap();
+        let mut pairs = Vec::new();
+        for (row, hash) in hash_rows_collated(probe, columns, &self.left_keys.collators)?.into_iter().enumerate() {
+            if let Some(rows) = table.get(&hash) {
+                pairs.extend(rows.iter().map(|&b| (b, row)));
+            }
+        }
+
+        let equal = match self.build_keys {
+            Some(ref keys)  => rows_equal_collated(keys, &self.right_keys.cast_columns(), probe, columns, &pairs,
+                                                   NullEquality::NEVER, &self.right_keys.collators)?,
+            None            => rows_equal_collated(&self.build, &self.right_keys.columns, probe, columns, &pairs,
+                                                   NullEquality::NEVER, &self.right_keys.collators)?,
+        };
+
+        Ok(pairs.into_iter().zip(equal).filter(|&(_, eq)| eq).map(|(pair, _)| pair).collect())
+    }
+
+    /// Output rows of a left chunk; `None` if there are none
+    fn probe<'v>(&self, view: &'v View<'v>) -> Result<Option<Block<'a>>, DBError> {
+        let pairs = match self.left_keys.cast(self.alloc, view)? {
+            Some(ref keys)  => self.matches(keys, &self.left_keys.cast_columns())?,
+            None            => self.matches(view, &self.left_keys.columns)?,
+        };
+
+        let mut left_rows = Vec::with_capacity(pairs.len());
+        let mut right_rows = Vec::with_capacity(pairs.len());
+        let mut matched = vec![false; view.rows()];
+        for (b, p) in pairs {
+            left_rows.push(p);
+            right_rows.push(b);
+            matched[p] = true;
+        }
+
+        let found = left_rows.len();
+        if self.kind == JoinKind::LEFT {
+            left_rows.extend((0 .. view.rows()).filter(|row| !matched[*row]));
+        }
+
+        if left_rows.is_empty() {
+            return Ok(None)
+        }
+
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(left_rows.len())?;
+
+        let width = view.schema().count();
+        for pos in 0 .. width {
+            let src = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+            out.column_mut(pos).unwrap().take_rows(0, src, &left_rows)?;
+        }
+
+        for pos in 0 .. self.build.schema().count() {
+            let dst = out.column_mut(width + pos).unwrap();
+            dst.take_rows(0, self.build.column(pos).unwrap(), &right_rows)?;
+
+            if found < left_rows.len() {
+                let mut nulls = dst.nulls_mut()?;
+                for row in found .. left_rows.len() {
+                    nulls.set(row, true);
+                }
+            }
+        }
+
+        Ok(Some(out))
+    }
+}
+
+impl<'a> Cursor<'a> for HashJoinCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.state.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.state.cancel.check()?;
+
+        if !self.built {
+            self.grace = self.state.build(&mut *self.right, self.fetch)?;
+            self.built = true;
+
+            if self.grace.is_some() {
+                self.metrics.add("join_grace", 1);
+            } else {
+                self.metrics.add("join_hash", 1);
+                if let Some(filter) = self.state.index()? {
+                    if self.left.push_key_filter(&filter) {
+                        self.metrics.add("join_bloom_filter", 1);
+                    }
+                }
+            }
+        }
+
+        // Release the last output before making the next one
+        self.block = None;
+        loop {
+            self.state.cancel.check()?;
+
+            let out = match self.grace {
+                Some(ref mut grace) => match grace.next_block(&mut self.state, &mut *self.left, rows)? {
+                    Some(block) => self.state.probe(&block)?,
+                    None        => return Ok(CursorChunk::End),
+                },
+                None                => match self.left.next(rows)? {
+                    CursorChunk::Next(view) => self.state.probe(&view)?,
+                    CursorChunk::End        => return Ok(CursorChunk::End),
+                },
+            };
+
+            if out.is_some() {
+                self.block = out;
+                break
+            }
+        }
+
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        let keys = self.state.build_keys.as_ref().map_or(0, |b| b.memory_usage().total());
+        let block = self.block.as_ref().map_or(0, |b| b.memory_usage().total());
+        self.state.build.memory_usage().total() + keys + block
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.left, &*self.right]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use ::allocator::{self, TrackingAllocator};
+    use ::exec::collect_rows;
+    use ::block::{RefView, alias_column, dict_encode};
+    use ::operation::ScanView;
+    use ::util::copy_value::set_column_value;
+
+    fn rows<'a>(cursor: &mut (Cursor<'a> + 'a)) -> Vec<Vec<Value<'static>>> {
+        let mut out = collect_rows(cursor).unwrap();
+        out.sort_by(|l, r| l.partial_cmp(r).unwrap());
+        out
+    }
+
+    #[test]
+    fn key_types() {
+        assert_eq!(join_key_type(Type::INT32, Type::INT32), Some(Type::INT32));
+        assert_eq!(join_key_type(Type::INT64, Type::INT32), Some(Type::INT64));
+        assert_eq!(join_key_type(Type::UINT32, Type::INT32), Some(Type::INT64));
+        assert_eq!(join_key_type(Type::UINT64, Type::UINT32), Some(Type::UINT64));
+        assert_eq!(join_key_type(Type::FLOAT64, Type::INT32), Some(Type::FLOAT64));
+        assert_eq!(join_key_type(Type::JSON, Type::TEXT), Some(Type::TEXT));
+        assert_eq!(join_key_type(Type::UINT64, Type::INT64), None);
+        assert_eq!(join_key_type(Type::INT64, Type::FLOAT64), None);
+        assert_eq!(join_key_type(Type::TEXT, Type::BLOB), None);
+    }
+
+    #[test]
+    fn join() {
+        let mut orders = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("customer INT32, amount INT64 NOT NULL").unwrap());
+        orders.add_rows(5).unwrap();
+        for (row, &(customer, amount)) in [(Some(1), 10), (Some(2), 20), (None, 30), (Some(4), 40), (Some(1), 50)].iter().enumerate() {
+            set_column_value(&mut orders, 0, row, &Value::from(customer)).unwrap();
+            set_column_value(&mut orders, 1, row, &Value::INT64(amount)).unwrap();
+        }
+
+        let mut customers = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id INT64 NOT NULL, name TEXT NOT NULL").unwrap());
+        customers.add_rows(4).unwrap();
+        for (row, &(id, name)) in [(1, "a"), (2, "b"), (3, "c"), (2, "b2")].iter().enumerate() {
+            set_column_value(&mut customers, 0, row, &Value::INT64(id)).unwrap();
+            set_column_value(&mut customers, 1, row, &Value::from(name)).unwrap();
+        }
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 3;
+
+        // INT32 keys against INT64 ones
+        let op = HashJoin::new(JoinKind::INNER, vec![(0, 0)], ScanView::new(&orders, None), ScanView::new(&customers, None));
+        assert_eq!(op.describe(), "HashJoin INNER on [#0 = #0]");
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert_eq!(cursor.schema().count(), 4);
+
+        let row = |c: Option<i32>, amount: i64, id: Option<i64>, name: Option<&str>| {
+            vec![Value::from(c), Value::INT64(amount), Value::from(id), Value::from(name).into_owned()]
+        };
+        let inner = vec![
+            row(Some(1), 10, Some(1), Some("a")),
+            row(Some(1), 50, Some(1), Some("a")),
+            row(Some(2), 20, Some(2), Some("b")),
+            row(Some(2), 20, Some(2), Some("b2")),
+        ];
+        assert_eq!(rows(&mut *cursor), inner);
+
+        // Unmatched rows, including the NULL key
+        let op = HashJoin::new(JoinKind::LEFT, vec![(0, 0)], ScanView::new(&orders, None), ScanView::new(&customers, None));
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert!(cursor.schema().get(3).unwrap().nullable);
+
+        let mut left = inner.clone();
+        left.push(row(None, 30, None, None));
+        left.push(row(Some(4), 40, None, None));
+        left.sort_by(|l, r| l.partial_cmp(r).unwrap());
+        assert_eq!(rows(&mut *cursor), left);
+
+        // Dictionary encoded TEXT keys against plain ones
+        let names = dict_encode(&allocator::GLOBAL, &customers[1], 4).unwrap();
+        let view = RefView::new(Schema::from_attr(Attribute::new("customer", false, Type::TEXT)), vec![alias_column(&names, None).unwrap()], 4);
+        let op = HashJoin::new(JoinKind::INNER, vec![(0, 1)], ScanView::new(&view, None), ScanView::new(&customers, None));
+        assert_eq!(rows(&mut *ctx.bind(&op).unwrap()).len(), 4);
+
+        // Case insensitive TEXT keys; no bloom filter, it would hash the keys as they are
+        let mut upper = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("customer TEXT NOT NULL").unwrap());
+        upper.add_rows(3).unwrap();
+        for (row, name) in ["A", "B2", "Z"].iter().enumerate() {
+            set_column_value(&mut upper, 0, row, &Value::from(*name)).unwrap();
+        }
+        let op = HashJoin::new(JoinKind::INNER, vec![(0, 1)], ScanView::new(&upper, None), ScanView::new(&customers, None))
+            .with_collations(vec![Collation::NOCASE]);
+        assert_eq!(op.describe(), "HashJoin INNER on [#0 = #1] COLLATE [NOCASE]");
+        let ctx = ExecContext::default();
+        assert_eq!(rows(&mut *ctx.bind(&op).unwrap()), vec![
+            vec![Value::from("A"), Value::INT64(1), Value::from("a")],
+            vec![Value::from("B2"), Value::INT64(2), Value::from("b2")],
+        ]);
+        assert_eq!(ctx.metrics().get("join_bloom_filter"), 0);
+
+        // Keys that can't be compared
+        for &(l, r) in &[(1, 1), (0, 1)] {
+            let op = HashJoin::new(JoinKind::INNER, vec![(l, r)], ScanView::new(&orders, None), ScanView::new(&customers, None));
+            let err = ctx.bind(&op).err().expect("incompatible keys");
+            match *err.root_cause() {
+                DBError::AttributeType(ref msg) => assert!(msg.starts_with("join key")),
+                _                               => panic!("expected a key type error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn bloom_pushdown() {
+        let mut probe = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("key INT64 NOT NULL").unwrap());
+        probe.add_rows(100).unwrap();
+        for row in 0 .. 100 {
+            set_column_value(&mut probe, 0, row, &Value::INT64(row as i64)).unwrap();
+        }
+
+        let mut build = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id INT64 NOT NULL").unwrap());
+        build.add_rows(3).unwrap();
+        for (row, &id) in [5i64, 50, 500].iter().enumerate() {
+            set_column_value(&mut build, 0, row, &Value::INT64(id)).unwrap();
+        }
+
+        let ctx = ExecContext::default();
+        let scan = ScanView::new(&probe, None);
+        let counters = scan.counters();
+        let op = HashJoin::new(JoinKind::INNER, vec![(0, 0)], scan, ScanView::new(&build, None));
+
+        let expected: Vec<Vec<Value>> = [5i64, 50].iter().map(|&k| vec![Value::INT64(k), Value::INT64(k)]).collect();
+        assert_eq!(rows(&mut *ctx.bind(&op).unwrap()), expected);
+        assert_eq!(ctx.metrics().get("join_bloom_filter"), 1);
+        assert!(counters.filtered_rows.get() >= 90, "{} rows filtered", counters.filtered_rows.get());
+
+        // LEFT joins keep all the left rows
+        let op = HashJoin::new(JoinKind::LEFT, vec![(0, 0)], ScanView::new(&probe, None), ScanView::new(&build, None));
+        assert_eq!(rows(&mut *ctx.bind(&op).unwrap()).len(), 100);
+        assert_eq!(ctx.metrics().get("join_bloom_filter"), 1);
+    }
+
+    #[test]
+    fn grace() {
+        let mut orders = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("customer INT64, amount INT64 NOT NULL").unwrap());
+        orders.add_rows(20000).unwrap();
+        for row in 0 .. 20000 {
+            let customer = if row % 100 == 0 { Value::NULL } else { Value::INT64((row % 5000) as i64) };
+            set_column_value(&mut orders, 0, row, &customer).unwrap();
+            set_column_value(&mut orders, 1, row, &Value::INT64(row as i64)).unwrap();
+        }
+
+        let mut customers = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("id INT32 NOT NULL, v INT64 NOT NULL").unwrap());
+        customers.add_rows(32000).unwrap();
+        for row in 0 .. 32000 {
+            set_column_value(&mut customers, 0, row, &Value::INT32(row as i32 * 2)).unwrap();
+            set_column_value(&mut customers, 1, row, &Value::INT64(row as i64)).unwrap();
+        }
+
+        // The right rows don't fit
+        let tracking = TrackingAllocator::new(&allocator::GLOBAL, 160 * 1024);
+
+        for &kind in &[JoinKind::INNER, JoinKind::LEFT] {
+            let op = HashJoin::new(kind, vec![(0, 0)], ScanView::new(&orders, None), ScanView::new(&customers, None));
+
+            let ctx = ExecContext::default();
+            let expected = rows(&mut *ctx.bind(&op).unwrap());
+            assert_eq!(ctx.metrics().get("join_hash"), 1);
+
+            let ctx = ExecContext::default().with_allocator(&tracking);
+            let out = rows(&mut *ctx.bind(&op).unwrap());
+            assert_eq!(ctx.metrics().get("join_grace"), 1);
+            assert_eq!(out.len(), expected.len());
+            assert_eq!(out, expected);
+        }
+    }
+
+    /// Cancels the query after its first chunk, without checking the token itself
+    struct CancelAfterFirst<'a>(ScanView<'a>, Rc<Cell<usize>>);
+
+    struct CancelAfterFirstCursor<'a> {
+        input: Box<Cursor<'a> + 'a>,
+        token: CancelToken,
+        chunks: Rc<Cell<usize>>,
+    }
+
+    impl<'a> Operation<'a> for CancelAfterFirst<'a> {
+        fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+            let input = self.0.bind(&ExecContext::default())?;
+            Ok(Box::new(CancelAfterFirstCursor { input: input, token: ctx.cancel_token().clone(), chunks: self.1.clone() }))
+        }
+
+        fn describe(&self) -> String {
+            "CancelAfterFirst".to_string()
+        }
+    }
+
+    impl<'a> Cursor<'a> for CancelAfterFirstCursor<'a> {
+        fn schema(&self) -> &Schema {
+            self.input.schema()
+        }
+
+        fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+            self.chunks.set(self.chunks.get() + 1);
+            self.token.cancel();
+            self.input.next(rows)
+        }
+    }
+
+    #[test]
+    fn cancel_build() {
+        let numbers = |ddl| {
+            let mut block = Block::new(&allocator::GLOBAL, &Schema::parse_ddl(ddl).unwrap());
+            block.add_rows(1000).unwrap();
+            for row in 0 .. 1000 {
+                set_column_value(&mut block, 0, row, &Value::INT64(row as i64)).unwrap();
+            }
+            block
+        };
+        let (left, right) = (numbers("id INT64 NOT NULL"), numbers("key INT64 NOT NULL"));
+
+        let mut ctx = ExecContext::default();
+        ctx.config_mut().fetch_rows = 10;
+
+        // The build stops at the next chunk rather than reading all of the right input
+        let chunks = Rc::new(Cell::new(0));
+        let op = HashJoin::new(JoinKind::INNER, vec![(0, 0)], ScanView::new(&left, None),
+                               CancelAfterFirst(ScanView::new(&right, None), chunks.clone()));
+        let mut cursor = ctx.bind(&op).unwrap();
+        match cursor.next(10) {
+            Err(DBError::Cancelled) => (),
+            _                       => panic!("expected the join to be cancelled"),
+        }
+        assert_eq!(chunks.get(), 1);
+    }
+}
@@ -0,0 +1,141 @@
+use std::cmp::min;
+
+use ::block::View;
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::row::RowOffset;
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk};
+
+/// Skips the first `offset` rows of the input and passes through at most `limit` rows after them
+pub struct Limit<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub offset: RowOffset,
+    pub limit: Option<RowOffset>,
+}
+
+/// Implementation of the `Limit` operation
+struct LimitCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    offset: RowOffset,
+    limit: Option<RowOffset>,
+    /// Rows left to skip
+    skip: RowOffset,
+    /// Rows left to return
+    left: Option<RowOffset>,
+}
+
+impl<'a> Limit<'a> {
+    pub fn new<T: Operation<'a> + 'a>(offset: RowOffset, limit: Option<RowOffset>, src: T) -> Limit<'a> {
+        Limit { src: Box::new(src), offset: offset, limit: limit }
+    }
+}
+
+impl<'a> Operation<'a> for Limit<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(LimitCursor {
+            input: ctx.bind(&*self.src)?,
+            offset: self.offset,
+            limit: self.limit,
+            skip: self.offset,
+            left: self.limit,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        match self.limit {
+            Some(limit) => format!("Limit offset={} limit={}", self.offset, limit),
+            None        => format!("Limit offset={}", self.offset),
+        }
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+}
+
+impl<'a> Cursor<'a> for LimitCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    // Never asks the input for more rows than needed, so chunks don't have to be sliced
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        while self.skip > 0 {
+            let skipped = match self.input.next(min(rows, self.skip))? {
+                CursorChunk::Next(view) => view.rows(),
+                CursorChunk::End        => return Ok(CursorChunk::End),
+            };
+            self.skip -= skipped;
+        }
+
+        let rows = min(rows, self.left.unwrap_or(rows));
+        if rows == 0 {
+            return Ok(CursorChunk::End)
+        }
+
+        let chunk = self.input.next(rows)?;
+        if let CursorChunk::Next(ref view) = chunk {
+            self.left = self.left.map(|left| left - view.rows());
+        }
+
+        Ok(chunk)
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.input.rewind()?;
+        self.skip = self.offset;
+        self.left = self.limit;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, column_row_data};
+    use ::operation::ScanView;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn limit() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+        for row in 0 .. 10 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        for &(offset, limit, ref expected) in &[(3, Some(4), vec![3, 4, 5, 6]), (8, None, vec![8, 9]),
+                                                 (0, Some(0), vec![]), (20, Some(1), vec![])] {
+            let op = Limit::new(offset, limit, ScanView::new(&block, None));
+            let mut cursor = op.bind(&ExecContext::default()).unwrap();
+            let mut ids = Vec::new();
+
+            while let CursorChunk::Next(view) = cursor.next(3).unwrap() {
+                ids.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+            }
+
+            assert_eq!(&ids, expected);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::cmp::min;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk, Pipelining};
+
+/// Reads all of the input up front into blocks; the cursor can be rewound (eg. the inner side of a
+/// nested loop join).
+pub struct Materialize<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+}
+
+impl<'a> Materialize<'a> {
+    pub fn new<T: Operation<'a> + 'a>(src: T) -> Materialize<'a> {
+        Materialize { src: Box::new(src) }
+    }
+}
+
+impl<'a> Operation<'a> for Materialize<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let input = ctx.bind(&*self.src)?;
+        let fetch = ctx.fetch_rows(&*input);
+
+        let mut cursor = RewindableCursor::buffered(input, ctx.allocator())
+            .with_cancel(ctx.cancel_token().clone());
+        cursor.fill(fetch)?;
+        Ok(Box::new(cursor))
+    }
+
+    fn describe(&self) -> String {
+        "Materialize".to_string()
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+/// Adds rewind support to any cursor. Inputs that can rewind themselves are passed through,
+/// otherwise the chunks read so far are kept (copied into blocks) and replayed after a rewind.
+pub struct RewindableCursor<'a> {
+    input: Box<Cursor<'a> + 'a>,
+    alloc: &'a Allocator,
+    /// Rewind the input instead of buffering
+    passthrough: bool,
+    /// Input chunks read so far
+    blocks: Vec<Block<'a>>,
+    /// Next buffered block to replay, past the end when reading the input
+    replay: usize,
+    /// Next row of the replayed block
+    offset: RowOffset,
+    /// The input reached its end
+    done: bool,
+    /// Checked before reading every chunk; never cancelled unless set
+    cancel: CancelToken,
+}
+
+impl<'a> RewindableCursor<'a> {
+    pub fn new(input: Box<Cursor<'a> + 'a>, alloc: &'a Allocator) -> RewindableCursor<'a> {
+        let passthrough = input.can_rewind();
+        RewindableCursor { passthrough: passthrough, .. RewindableCursor::buffered(input, alloc) }
+    }
+
+    /// Buffers the input even if it can rewind
+    pub fn buffered(input: Box<Cursor<'a> + 'a>, alloc: &'a Allocator) -> RewindableCursor<'a> {
+        RewindableCursor {
+            input: input,
+            alloc: alloc,
+            passthrough: false,
+            blocks: Vec::new(),
+            replay: 0,
+            offset: 0,
+            done: false,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    pub fn with_cancel(self, cancel: CancelToken) -> RewindableCursor<'a> {
+        RewindableCursor { cancel: cancel, .. self }
+    }
+
+    /// Read the rest of the input into the buffer, `rows` at a time. The next chunk is the same as
+    /// before.
+    pub fn fill(&mut self, rows: RowOffset) -> Result<(), DBError> {
+        if self.passthrough {
+            return Ok(())
+        }
+
+        let (replay, offset) = (self.replay, self.offset);
+        while self.read_input(rows)? {}
+        self.replay = min(replay, self.blocks.len());
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// Buffer the next non empty input chunk, false at the end of the input
+    fn read_input(&mut self, rows: RowOffset) -> Result<bool, DBError> {
+        while !self.done {
+            self.cancel.check()?;
+
+            let block = match self.input.next(rows)? {
+                CursorChunk::Next(ref view) if view.rows() == 0 => continue,
+                CursorChunk::Next(view)                         => {
+                    let mut block = Block::new(self.alloc, view.schema());
+                    block.append_view(&view)?;
+                    block
+                }
+                CursorChunk::End                                => {
+                    self.done = true;
+                    break
+                }
+            };
+
+            self.blocks.push(block);
+            self.replay = self.blocks.len();
+            return Ok(true)
+        }
+
+        Ok(false)
+    }
+}
+
+impl<'a> Cursor<'a> for RewindableCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        if self.passthrough {
+            return self.input.next(rows)
+        }
+
+        if self.replay < self.blocks.len() {
+            let left = self.blocks[self.replay].rows() - self.offset;
+            let range = RowRange { offset: self.offset, rows: min(rows, left) };
+
+            self.offset += range.rows;
+            let pos = self.replay;
+            if self.offset >= self.blocks[pos].rows() {
+                self.replay += 1;
+                self.offset = 0;
+            }
+
+            return Ok(CursorChunk::Next(window_alias(&self.blocks[pos], Some(range))?))
+        }
+
+        if !self.read_input(rows)? {
+            return Ok(CursorChunk::End)
+        }
+
+        Ok(CursorChunk::Next(window_alias(self.blocks.last().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.blocks.iter().map(|b| b.memory_usage().total()).sum()
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        self.input.pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        if self.passthrough {
+            return self.input.rewind()
+        }
+
+        self.replay = 0;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::exec::{CancelToken, Cancellable};
+    use ::operation::{Filter, Limit, ScanPredicate, ScanView};
+    use ::expression::comparison::CompareOp;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    /// Hides the input's rewind support
+    struct Forward<'a>(Box<Cursor<'a> + 'a>);
+
+    impl<'a> Cursor<'a> for Forward<'a> {
+        fn schema(&self) -> &Schema {
+            self.0.schema()
+        }
+
+        fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+            self.0.next(rows)
+        }
+    }
+
+    fn drain<'a>(cursor: &mut (Cursor<'a> + 'a), rows: RowOffset) -> Vec<u32> {
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(rows).unwrap() {
+            out.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        out
+    }
+
+    #[test]
+    fn rewind() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(10).unwrap();
+        for row in 0 .. 10 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let all: Vec<u32> = (0 .. 10).collect();
+        let ctx = ExecContext::default();
+
+        // Scans rewind themselves, so do the operators over them
+        let op = Limit::new(2, Some(5), ScanView::new(&block, None));
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert!(cursor.can_rewind());
+        assert_eq!(drain(&mut *cursor, 3), vec![2, 3, 4, 5, 6]);
+        cursor.rewind().unwrap();
+        assert_eq!(drain(&mut *cursor, 4), vec![2, 3, 4, 5, 6]);
+
+        let op = Cancellable::new(ScanView::new(&block, None), CancelToken::new());
+        let filter = Filter::new(ScanPredicate::new(0, CompareOp::GE, 0u32), op);
+        assert!(ctx.bind(&filter).unwrap().can_rewind());
+        assert!(RewindableCursor::new(ctx.bind(&filter).unwrap(), &allocator::GLOBAL).passthrough);
+
+        // Buffered: partially read, rewound, read past what was buffered
+        let mut input = Box::new(Forward(ctx.bind(&filter).unwrap()));
+        assert!(!input.can_rewind());
+        assert!(input.rewind().is_err());
+
+        let mut cursor = RewindableCursor::new(input, &allocator::GLOBAL);
+        assert!(!cursor.passthrough);
+        match cursor.next(4).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(view.rows(), 4),
+            CursorChunk::End        => panic!("expected rows"),
+        }
+        cursor.rewind().unwrap();
+        assert_eq!(drain(&mut cursor, 3), all);
+        assert_eq!(cursor.blocks.len(), 3);
+        cursor.rewind().unwrap();
+        assert_eq!(drain(&mut cursor, 100), all);
+        assert!(cursor.memory_usage() > 0);
+
+        // Materialized up front
+        let op = Materialize::new(ScanView::new(&block, None));
+        let mut cursor = ctx.bind(&op).unwrap();
+        assert_eq!(op.pipelining(), Pipelining::BLOCKING);
+        assert!(cursor.can_rewind());
+        assert_eq!(drain(&mut *cursor, 7), all);
+        cursor.rewind().unwrap();
+        assert_eq!(drain(&mut *cursor, 7), all);
+    }
+}
@@ -1,12 +1,16 @@
 use super::error::DBError;
-use super::allocator::Allocator;
+use super::exec::ExecContext;
 
 use super::block::RefView;
+use super::plan::Aggregate;
+use super::types::Value;
+use super::exec::explain::CursorMetrics;
 use super::row::RowOffset;
 use super::schema::Schema;
+use super::util::bloom::KeyFilter;
 
-#[allow(dead_code)]
-const DEFAULT_CURSOR_FETCH : RowOffset = 1024;
+/// Default number of rows fetched from a `Cursor` at a time
+pub const DEFAULT_CURSOR_FETCH : RowOffset = 1024;
 
 /// Next series of `Cursor` data
 pub enum CursorChunk<'a> {
@@ -18,14 +22,120 @@ pub enum CursorChunk<'a> {
     // TODO: Next for off memory data (GPU)
 }
 
+/// How an operation passes rows on to its consumer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pipelining {
+    /// Output chunks are produced as the input is read (filters, projections, scans)
+    STREAMING,
+    /// The whole input is consumed before the first output chunk (sorts, hash aggregates, hash
+    /// join builds). Pipelines are broken up at these.
+    BLOCKING,
+}
+
 /// Materialized operation cursor stream results from previous operations.
 ///
 /// A cursor know it output and (optionally) input schema.
 pub trait Cursor<'a> {
     fn schema(&self) -> &Schema;
 
-    // Can't quite be an iterator, we can want different batch sizes in subsequent calls
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError>;
+    // Can't quite be an iterator, we can want different batch sizes in subsequent calls.
+    // The returned chunk borrows the cursor until the next call.
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError>;
+
+    /// Memory held by the cursor (buffered blocks, hash tables...), in bytes. Doesn't include the
+    /// inputs.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Input cursors, for walking the cursor tree (`exec::explain_analyze`)
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        Vec::new()
+    }
+
+    /// Name and runtime metrics of an instrumented cursor
+    fn metrics(&self) -> Option<(&str, &CursorMetrics)> {
+        None
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::STREAMING
+    }
+
+    /// Chunk size the cursor produces most efficiently (eg. the rows of the blocks it reads); a hint
+    /// for the consumer's fetch size (`ExecContext::fetch_rows`)
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        None
+    }
+
+    /// Whether `rewind` is supported. Wrap the cursor in a `RewindableCursor` if it isn't.
+    fn can_rewind(&self) -> bool {
+        false
+    }
+
+    /// Restart from the first row; the next chunks are the same rows again
+    fn rewind(&mut self) -> Result<(), DBError> {
+        Err(DBError::Unsupported("cursor rewind".to_string()))
+    }
+
+    /// Drop the output rows whose keys aren't in the filter (eg. the build keys of a hash join),
+    /// as early as possible. Returns true if the cursor (or one of its inputs) takes care of it.
+    fn push_key_filter(&mut self, _: &KeyFilter) -> bool {
+        false
+    }
+
+    /// Values of the ungrouped aggregates over all the output rows, from row counts and stored
+    /// column statistics instead of reading the rows. `None` if they can't all be answered that
+    /// way, or once rows were read. Probed by `HashAggregate` when it's bound.
+    fn aggregates_from_metadata(&self, _: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        None
+    }
+}
+
+impl<'a, C: Cursor<'a> + ?Sized> Cursor<'a> for Box<C> {
+    fn schema(&self) -> &Schema {
+        (**self).schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        (**self).next(rows)
+    }
+
+    fn memory_usage(&self) -> usize {
+        (**self).memory_usage()
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        (**self).inputs()
+    }
+
+    fn metrics(&self) -> Option<(&str, &CursorMetrics)> {
+        (**self).metrics()
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        (**self).pipelining()
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        (**self).preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        (**self).can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        (**self).rewind()
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        (**self).push_key_filter(filter)
+    }
+
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        (**self).aggregates_from_metadata(aggregates)
+    }
 }
 
 /// `Operation` is the basic building model of a query.
@@ -34,14 +144,82 @@ pub trait Cursor<'a> {
 /// one relational Operation into another.
 pub trait Operation<'a> {
 
-    /// Convert operation AST a bound Cursor
+    /// Convert operation AST a bound Cursor. The inputs are bound with the same context
+    /// (`ExecContext::bind`).
     // TODO: Tell bind if we want to shuffle GPU data or memory data
-    fn bind<'b: 'a>(&self, &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError>;
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError>;
+
+    /// Operation name and parameters, for EXPLAIN
+    fn describe(&self) -> String;
+
+    /// Input operations
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        Vec::new()
+    }
+
+    /// Input operations, for replacing them (`rewrite::Rewriter`)
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        Vec::new()
+    }
+
+    /// Predicate every output row satisfies (`Filter`); it can be pushed down into the input
+    fn predicate(&self) -> Option<&scan_view::ScanPredicate> {
+        None
+    }
+
+    /// Hint a predicate (over the output columns) the consumer filters by. Returns true if the
+    /// operation made use of it; it doesn't have to remove all the non matching rows.
+    fn push_predicate(&mut self, _: &scan_view::ScanPredicate) -> bool {
+        false
+    }
+
+    /// Whether the operation is a pipeline breaker. Wrappers report their input's.
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::STREAMING
+    }
+
+}
+
+/// Blocking operations of the tree (pipeline breakers), parents before their inputs
+pub fn pipeline_breakers<'s, 'a>(op: &'s (Operation<'a> + 'a)) -> Vec<&'s (Operation<'a> + 'a)> {
+    let mut out = Vec::new();
+    if op.pipelining() == Pipelining::BLOCKING {
+        out.push(op);
+    }
+
+    for input in op.inputs() {
+        out.extend(pipeline_breakers(input));
+    }
+    out
 }
 
+pub mod adapters;
+pub mod aggregate;
+pub mod assert;
+pub mod filter;
+pub mod iterate;
+pub mod join;
+pub mod limit;
+pub mod materialize;
 pub mod scan_view;
+pub mod scan_file;
+pub mod scan_parallel;
+pub mod scan_table;
+pub mod sort;
 pub mod project;
 
-pub use self::scan_view::ScanView;
+pub use self::adapters::CursorExt;
+pub use self::aggregate::HashAggregate;
+pub use self::assert::{AssertOp, Check};
+pub use self::filter::{ExprFilter, Filter};
+pub use self::iterate::{Iterate, WorkingSet};
+pub use self::join::HashJoin;
+pub use self::limit::Limit;
+pub use self::materialize::{Materialize, RewindableCursor};
+pub use self::scan_view::{ScanPredicate, ScanView};
+pub use self::scan_file::ScanMmap;
+pub use self::scan_parallel::{ParallelScanView, ParallelScanWorker};
+pub use self::scan_table::ScanTable;
+pub use self::sort::Sort;
 pub use self::project::Project;
 
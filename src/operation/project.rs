@@ -1,47 +1,70 @@
-use std::mem::replace;
-
 use ::allocator::Allocator;
-use ::block::RefView;
+use ::block::{Block, window_alias};
 use ::error::DBError;
+use ::exec::ExecContext;
+use ::plan::Aggregate;
 use ::row::RowOffset;
 use ::schema::Schema;
+use ::types::Value;
+use ::util::bloom::KeyFilter;
 
 use ::projector::*;
 
 use super::{Operation, Cursor, CursorChunk};
 
-/// Relational Project Operation
+/// Relational Project Operation. Pass-through columns alias the input chunks; computed columns
+/// are evaluated, and the chunk copied, into a block per chunk.
 pub struct Project<'a> {
     pub src: Box<Operation<'a> + 'a>,
-    pub proj: SingleSourceProjector,
+    pub proj: ExprProjector<'a>,
 }
 
 /// Implementation of the `Project` operation
 struct ProjectCursor<'a> {
     input: Box<Cursor<'a> + 'a>,
-    proj: BoundProjector,
-    _next: RefView<'a>,
+    alloc: &'a Allocator,
+    proj: BoundExprProjector<'a, 'a>,
+    /// Output of the last chunk, with computed columns
+    block: Option<Block<'a>>,
 }
 
 impl<'a> Project<'a> {
     pub fn new<T: Operation<'a> + 'a>(proj: SingleSourceProjector, src: T) -> Project<'a> {
-        Project { src: box src, proj: proj }
+        Project { src: Box::new(src), proj: ExprProjector::new().add(proj) }
+    }
+
+    /// With computed columns
+    pub fn computed<T: Operation<'a> + 'a>(proj: ExprProjector<'a>, src: T) -> Project<'a> {
+        Project { src: Box::new(src), proj: proj }
     }
 }
 
 impl<'a> Operation<'a> for Project<'a> {
-    fn bind<'b: 'a>(&self, alloc: &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError> {
-        let boxed = self.src.bind(alloc)?;
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let boxed = ctx.bind(&*self.src)?;
+        let alloc: &'a Allocator = ctx.allocator();
 
         let proj = {
             let cursor = &*boxed;
             let schema = cursor.schema();
-            self.proj.bind(schema)?
+            self.proj.bind(alloc, schema)?
         };
 
-        let out = Box::new(ProjectCursor {input: boxed, proj: proj, _next: Default::default()});
+        let out = Box::new(ProjectCursor {input: boxed, alloc: alloc, proj: proj, block: None});
         Ok(out)
     }
+
+    fn describe(&self) -> String {
+        format!("Project {}", self.proj)
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
 }
 
 impl<'a> Cursor<'a> for ProjectCursor<'a> {
@@ -49,17 +72,64 @@ impl<'a> Cursor<'a> for ProjectCursor<'a> {
         &self.proj.schema
     }
 
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
-        let next_chunk = self.input.as_mut()
-            .next(rows)?;
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let proj = &self.proj;
+
+        let out = match self.input.as_mut().next(rows)? {
+            CursorChunk::Next(src)  => {
+                if !proj.is_computed() {
+                    return proj.project_chunk(src).map(|v| CursorChunk::Next(v))
+                }
+
+                let computed = proj.evaluate(&src)?;
+                let mut out = Block::new(self.alloc, &proj.schema);
+                out.append_view(&proj.project_view(&src, &computed)?)?;
+                out
+            }
+            CursorChunk::End        => return Ok(CursorChunk::End),
+        };
+
+        self.block = Some(out);
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn inputs(&self) -> Vec<&Cursor<'a>> {
+        vec![&*self.input]
+    }
+
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.input.preferred_rows()
+    }
+
+    fn can_rewind(&self) -> bool {
+        self.input.can_rewind()
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.block = None;
+        self.input.rewind()
+    }
+
+    /// With the key columns mapped to the input's
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        let columns: Option<Vec<usize>> = filter.columns.iter().map(|c| self.proj.source_column(*c)).collect();
+        match columns {
+            Some(columns)   => self.input.push_key_filter(&filter.with_columns(columns)),
+            None            => false,
+        }
+    }
 
-        if let CursorChunk::Next(src) = next_chunk {
-            replace(&mut self._next, src);
-            self.proj.project_view(&self._next)
-                .map(|v| CursorChunk::Next(v))
-        } else {
-            Ok(next_chunk)
+    /// Only `COUNT(*)`, it doesn't depend on the columns
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        if aggregates.iter().any(|a| a.column.is_some()) {
+            return None
         }
+
+        self.input.aggregates_from_metadata(aggregates)
     }
 }
 
@@ -68,33 +138,34 @@ impl<'a> Cursor<'a> for ProjectCursor<'a> {
 mod tests {
     use super::*;
     use ::allocator;
+    use ::block::{Block, View, column_value};
+    use ::exec::ExecContext;
+    use ::expression::literal::Literal;
     use ::schema::{Attribute, Schema};
     use ::operation::{Operation, ScanView};
-    use ::projector::*;
     use ::table::{Table, TableAppender};
     use ::types::*;
+    use ::util::copy_value::set_column_value;
 
     #[test]
     fn reorder_columns() {
         let block = {
             let attrs = vec![
-                Attribute{name: "one".to_string(), nullable: false, dtype: Type::UINT32},
-                Attribute{name: "two".to_string(), nullable: false, dtype: Type::UINT32},
-                Attribute{name: "three".to_string(), nullable: false, dtype: Type::UINT32},
+                Attribute::new("one", false, Type::UINT32),
+                Attribute::new("two", false, Type::UINT32),
+                Attribute::new("three", false, Type::UINT32),
             ];
 
             let schema = Schema::from_vec(attrs).unwrap();
             let mut table = Table::new(&allocator::GLOBAL, &schema, None);
 
             {
-                let status = TableAppender::new(&mut table)
-                    .add_row()
-                        .set(0 as u32)
-                        .set(1 as u32)
-                        .set(13 as u32)
-                    .done();
-
-                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+                let mut appender = TableAppender::new(&mut table);
+                appender.add_row().unwrap()
+                    .set(0 as u32).unwrap()
+                    .set(1 as u32).unwrap()
+                    .set(13 as u32).unwrap();
+                appender.done().unwrap();
             }
 
             table.take()
@@ -109,7 +180,7 @@ mod tests {
             let scan_op = ScanView::new(block.as_ref().unwrap(), None);
             let proj_op = Project::new(proj, scan_op);
 
-            let cursor = proj_op.bind(&allocator::GLOBAL).unwrap();
+            let cursor = proj_op.bind(&ExecContext::default()).unwrap();
             let cursor_ref = &*cursor;
 
             // Columns correctly re-arranged
@@ -118,4 +189,89 @@ mod tests {
             assert_eq!(cursor_schema.get(1).unwrap().name, "two", "Bad cursor schema");
         }
     }
+
+    #[test]
+    fn rename_round_trip() {
+        let attrs = vec![
+            Attribute::new("one", false, Type::UINT32),
+            Attribute::new("two", true, Type::TEXT),
+            Attribute::new("three", false, Type::INT64),
+        ];
+        let schema = Schema::from_vec(attrs).unwrap();
+
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(2).unwrap();
+        for row in 0 .. 2 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from("t")).unwrap();
+            set_column_value(&mut block, 2, row, &Value::INT64(-(row as i64))).unwrap();
+        }
+
+        let build = || BuildSingleSourceProjector::new()
+            .add_as(project_by_position(2), "neg")
+            .add_prefixed(project_by_position(0), "p_")
+            .add(project_by_name("two"))
+            .done();
+
+        // View projection
+        let bound = build().bind(&schema).unwrap();
+        let view = bound.project_view(&block).unwrap();
+        assert!(*view.schema() == bound.schema);
+
+        let names: Vec<_> = view.schema().iter().map(|a| a.name.clone()).collect();
+        assert_eq!(names, vec!["neg", "p_one", "two"]);
+        for (pos, attr) in view.schema().iter().enumerate() {
+            assert_eq!(view.column(pos).unwrap().attribute().dtype, attr.dtype);
+        }
+
+        // Through the operation
+        let proj_op = Project::new(build(), ScanView::new(&block, None));
+        let mut cursor = proj_op.bind(&ExecContext::default()).unwrap();
+
+        let mut rows = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(1).unwrap() {
+            assert_eq!(view.schema().get(0).unwrap().name, "neg");
+            rows.push((0 .. 3)
+                .map(|pos| column_value(view.column(pos).unwrap(), 0).unwrap().into_owned())
+                .collect::<Vec<_>>());
+        }
+
+        assert_eq!(rows, vec![
+            vec![Value::INT64(0), Value::UINT32(0), Value::from("t")],
+            vec![Value::INT64(-1), Value::UINT32(1), Value::from("t")],
+        ]);
+    }
+
+    #[test]
+    fn computed() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, name TEXT").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(3).unwrap();
+        for row in 0 .. 3 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+
+        let proj = ExprProjector::new()
+            .add(project_by_name("id"))
+            .add_computed(Literal::new("seven", Value::INT64(7)))
+            .add_expr(Literal::new("x", Value::from("y")), "id");
+        let op = Project::computed(proj, ScanView::new(&block, None));
+        assert_eq!(op.describe(), "Project [id, <expr>, <expr> AS id]");
+
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let names: Vec<_> = cursor.schema().iter().map(|a| a.name.clone()).collect();
+        assert_eq!(names, vec!["id", "seven", "_c2"]);
+
+        let mut rows = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            for row in 0 .. view.rows() {
+                rows.push((0 .. 3)
+                    .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                    .collect::<Vec<_>>());
+            }
+        }
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2], vec![Value::UINT32(2), Value::INT64(7), Value::from("y")]);
+    }
 }
@@ -0,0 +1,274 @@
+use std::cmp::min;
+use std::path::Path;
+use std::sync::Arc;
+use std::slice;
+
+use ::block::{View, window_alias};
+use ::block::serialize::{MappedBlock, map_block};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::plan::Aggregate;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::types::Value;
+use ::util::mmap::Mmap;
+
+use super::{Operation, Cursor, CursorChunk};
+use super::aggregate::aggregates_with;
+
+/// Operation scanning a memory mapped file of blocks in the native serialization format
+/// (`Block::serialize`).
+///
+/// The file can hold a sequence of serialized blocks with the same schema. Column data is used in
+/// place; the views point into the mapped file rather than allocator memory. The map is shared by
+/// the bound cursors, the operation can be sent to other threads (`exec::Driver`).
+pub struct ScanMmap {
+    map: Arc<Mmap>,
+    schema: Schema,
+    /// Offset of every block in the file
+    blocks: Vec<usize>,
+    rows: RowOffset,
+}
+
+impl ScanMmap {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ScanMmap, DBError> {
+        ScanMmap::new(Mmap::open(path)?)
+    }
+
+    /// Validates all the blocks in the file up front
+    pub fn new(map: Mmap) -> Result<ScanMmap, DBError> {
+        let mut schema = None;
+        let mut blocks = Vec::new();
+        let mut rows = 0;
+
+        {
+            let data = map.as_slice();
+            let mut pos = 0;
+
+            while pos < data.len() {
+                let (block, len) = map_block(&data[pos ..])?;
+
+                if schema.as_ref().map_or(false, |s| *s != *block.schema()) {
+                    return Err(DBError::Serialization(format!("block at {} has a different schema", pos)))
+                }
+
+                if schema.is_none() {
+                    schema = Some(block.schema().clone());
+                }
+
+                blocks.push(pos);
+                rows += block.rows();
+                pos += len;
+            }
+        }
+
+        let schema = schema
+            .ok_or_else(|| DBError::Serialization("no blocks in file".to_string()))?;
+
+        Ok(ScanMmap { map: Arc::new(map), schema: schema, blocks: blocks, rows: rows })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Number of blocks in the file
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Total rows in the file
+    pub fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    fn cursor(&self, cancel: &CancelToken) -> ScanMmapCursor {
+        ScanMmapCursor {
+            block: None,
+            map: self.map.clone(),
+            schema: self.schema.clone(),
+            blocks: self.blocks.clone(),
+            next_block: 0,
+            offset: 0,
+            rows: self.rows,
+            preferred: match self.rows / self.blocks.len() {
+                0       => None,
+                rows    => Some(rows),
+            },
+            cancel: cancel.clone(),
+        }
+    }
+}
+
+impl<'a> Operation<'a> for ScanMmap {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(self.cursor(ctx.cancel_token())))
+    }
+
+    fn describe(&self) -> String {
+        format!("ScanMmap blocks={} rows={}", self.blocks.len(), self.rows)
+    }
+}
+
+/// Implementation of the `ScanMmap` operation
+struct ScanMmapCursor {
+    /// Current block. Points into `map`, it's declared first so it's dropped before the map.
+    block: Option<MappedBlock<'static>>,
+    map: Arc<Mmap>,
+    schema: Schema,
+    blocks: Vec<usize>,
+    next_block: usize,
+    /// Next row of the current block
+    offset: RowOffset,
+    /// Total rows in the file
+    rows: RowOffset,
+    /// Average block rows
+    preferred: Option<RowOffset>,
+    cancel: CancelToken,
+}
+
+impl<'a> Cursor<'a> for ScanMmapCursor {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        while self.block.as_ref().map_or(true, |b| self.offset >= b.rows()) {
+            let pos = match self.blocks.get(self.next_block) {
+                Some(pos)   => *pos,
+                None        => return Ok(CursorChunk::End),
+            };
+
+            // The map outlives the block (kept alive by the cursor's Arc)
+            let data = self.map.as_slice();
+            let data: &'static [u8] = unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
+
+            self.block = Some(map_block(&data[pos ..])?.0);
+            self.next_block += 1;
+            self.offset = 0;
+        }
+
+        let block = self.block.as_ref().unwrap();
+        let range = RowRange { offset: self.offset, rows: min(rows, block.rows() - self.offset) };
+        self.offset += range.rows;
+
+        Ok(CursorChunk::Next(window_alias(block, Some(range))?))
+    }
+
+    /// Chunks don't span blocks
+    fn preferred_rows(&self) -> Option<RowOffset> {
+        self.preferred
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.block = None;
+        self.next_block = 0;
+        self.offset = 0;
+        Ok(())
+    }
+
+    /// Only `COUNT(*)`; the serialized blocks don't have column statistics
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        if self.next_block > 0 {
+            return None
+        }
+
+        aggregates_with(aggregates, self.rows, |_| None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use ::allocator;
+    use ::block::{Block, column_value};
+    use ::plan::AggregateFunc;
+    use ::schema::Attribute;
+    use ::types::{Type, Value};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn scan_blocks() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::list("tags", false, Attribute::new("tag", false, Type::INT64)),
+        ]).unwrap();
+
+        let path = env::temp_dir().join(format!("dbkit-scan-mmap-{}.bin", ::std::process::id()));
+
+        {
+            let mut file = File::create(&path).unwrap();
+            for (first, count) in vec![(0, 3), (3, 0), (3, 2)] {
+                let mut block = Block::new(&allocator::GLOBAL, &schema);
+                if count > 0 {
+                    block.add_rows(count).unwrap();
+                }
+
+                for row in 0 .. count {
+                    let id = (first + row) as u32;
+                    let name = if id % 2 == 0 { Value::TEXT(Cow::Owned("x".repeat(id as usize + 1))) } else { Value::NULL };
+                    set_column_value(&mut block, 0, row, &Value::UINT32(id)).unwrap();
+                    set_column_value(&mut block, 1, row, &name).unwrap();
+                    set_column_value(&mut block, 2, row, &Value::LIST(vec![Value::INT64(id as i64); row])).unwrap();
+                }
+
+                block.serialize(&mut file).unwrap();
+            }
+            file.flush().unwrap();
+        }
+
+        let scan = ScanMmap::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(scan.blocks(), 3);
+        assert_eq!(scan.rows(), 5);
+        assert!(*scan.schema() == schema);
+
+        let mut cursor = scan.bind(&ExecContext::default()).unwrap();
+        let mut chunks = Vec::new();
+        let mut rows = Vec::new();
+
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            chunks.push(view.rows());
+            for row in 0 .. view.rows() {
+                let values: Vec<_> = (0 .. 3)
+                    .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                    .collect();
+                rows.push(values);
+            }
+        }
+
+        // Chunks don't span blocks
+        assert_eq!(chunks, vec![2, 1, 2]);
+        assert_eq!(rows[2], vec![Value::UINT32(2), Value::TEXT(Cow::Owned("xxx".to_string())),
+                                 Value::LIST(vec![Value::INT64(2), Value::INT64(2)])]);
+        assert_eq!(rows[3], vec![Value::UINT32(3), Value::NULL, Value::LIST(vec![])]);
+        assert_eq!(rows[4][0], Value::UINT32(4));
+
+        // Row counts from the block headers; columns have no stored statistics
+        let cursor = scan.bind(&ExecContext::default()).unwrap();
+        assert_eq!(cursor.aggregates_from_metadata(&[Aggregate::count_all("n")]), Some(vec![Value::UINT64(5)]));
+        assert_eq!(cursor.aggregates_from_metadata(&[Aggregate::new(AggregateFunc::MAX, 0, "max")]), None);
+
+        // The scan stops at the next chunk once cancelled
+        let ctx = ExecContext::default();
+        let mut cursor = scan.bind(&ctx).unwrap();
+        assert!(cursor.next(1).is_ok());
+        ctx.cancel_token().cancel();
+        match cursor.next(1) {
+            Err(DBError::Cancelled) => (),
+            _                       => panic!("expected the scan to be cancelled"),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::cmp::min;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ::block::{Block, SharedBlock, View, window_alias};
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+
+use super::{Operation, Cursor, CursorChunk};
+
+/// Default rows in a morsel
+pub const DEFAULT_MORSEL_ROWS: RowOffset = 16 * 1024;
+
+/// Morsel driven scan of a block shared by multiple workers (eg. run with `exec::Driver::union`).
+///
+/// The block is split into fixed size morsels. Every worker starts with an equal, contiguous share
+/// of the morsels and once it runs out steals half of the remaining morsels of the busiest
+/// worker. Every row is returned by exactly one worker.
+pub struct ParallelScanView<'a> {
+    src: SharedBlock<'a>,
+    queue: Arc<MorselQueue>,
+}
+
+/// Operation scanning the morsels of one `ParallelScanView` worker
+pub struct ParallelScanWorker<'a> {
+    src: SharedBlock<'a>,
+    queue: Arc<MorselQueue>,
+    worker: usize,
+}
+
+/// Morsels still to scan, per worker
+struct MorselQueue {
+    rows: RowOffset,
+    morsel: RowOffset,
+    /// Range of morsel indexes [next, end) of each worker
+    ranges: Vec<Mutex<(usize, usize)>>,
+    stolen: AtomicUsize,
+}
+
+impl<'a> ParallelScanView<'a> {
+    pub fn new(src: SharedBlock<'a>, workers: usize, morsel_rows: RowOffset) -> Result<ParallelScanView<'a>, DBError> {
+        if workers == 0 || morsel_rows == 0 {
+            return Err(DBError::Execution(format!("invalid parallel scan ({} workers, {} row morsels)",
+                                                  workers, morsel_rows)))
+        }
+
+        let queue = MorselQueue::new(src.rows(), morsel_rows, workers);
+        Ok(ParallelScanView { src: src, queue: Arc::new(queue) })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.queue.ranges.len()
+    }
+
+    /// Scan operation of the worker `idx`
+    pub fn worker(&self, idx: usize) -> Result<ParallelScanWorker<'a>, DBError> {
+        if idx >= self.workers() {
+            return Err(DBError::Execution(format!("no parallel scan worker {}", idx)))
+        }
+
+        Ok(ParallelScanWorker { src: self.src.clone(), queue: self.queue.clone(), worker: idx })
+    }
+
+    /// Scan operations of all the workers
+    pub fn all_workers(&self) -> Vec<ParallelScanWorker<'a>> {
+        (0 .. self.workers())
+            .map(|idx| self.worker(idx).unwrap())
+            .collect()
+    }
+
+    /// Morsels taken from other workers
+    pub fn stolen(&self) -> usize {
+        self.queue.stolen.load(Ordering::Relaxed)
+    }
+}
+
+impl MorselQueue {
+    fn new(rows: RowOffset, morsel: RowOffset, workers: usize) -> MorselQueue {
+        let morsels = (rows + morsel - 1) / morsel;
+        let ranges = (0 .. workers)
+            .map(|w| Mutex::new((morsels * w / workers, morsels * (w + 1) / workers)))
+            .collect();
+
+        MorselQueue { rows: rows, morsel: morsel, ranges: ranges, stolen: AtomicUsize::new(0) }
+    }
+
+    fn rows(&self, idx: usize) -> RowRange {
+        let offset = idx * self.morsel;
+        RowRange { offset: offset, rows: min(self.morsel, self.rows - offset) }
+    }
+
+    fn next(&self, worker: usize) -> Option<RowRange> {
+        {
+            let mut own = self.ranges[worker].lock().unwrap();
+            if own.0 < own.1 {
+                own.0 += 1;
+                return Some(self.rows(own.0 - 1))
+            }
+        }
+
+        // Only one lock held at a time; the victim might have changed by the time it's locked
+        loop {
+            let victim = (0 .. self.ranges.len())
+                .filter(|w| *w != worker)
+                .map(|w| { let r = self.ranges[w].lock().unwrap(); (r.1 - r.0, w) })
+                .max()
+                .and_then(|(left, w)| if left > 0 { Some(w) } else { None });
+
+            let victim = match victim {
+                Some(w) => w,
+                None    => return None,
+            };
+
+            let (start, end) = {
+                let mut range = self.ranges[victim].lock().unwrap();
+                let left = range.1 - range.0;
+                if left == 0 {
+                    continue
+                }
+
+                let end = range.1;
+                range.1 -= (left + 1) / 2;
+                (range.1, end)
+            };
+
+            self.stolen.fetch_add(end - start, Ordering::Relaxed);
+            *self.ranges[worker].lock().unwrap() = (start + 1, end);
+            return Some(self.rows(start))
+        }
+    }
+}
+
+impl<'a> Operation<'a> for ParallelScanWorker<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(ParallelScanCursor {
+            schema: self.src.schema().clone(),
+            src: self.src.clone(),
+            queue: self.queue.clone(),
+            worker: self.worker,
+            morsel: None,
+            cancel: ctx.cancel_token().clone(),
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("ParallelScan worker={} of {}", self.worker, self.queue.ranges.len())
+    }
+}
+
+/// Implementation of the `ParallelScanWorker` operation
+struct ParallelScanCursor<'a> {
+    schema: Schema,
+    src: SharedBlock<'a>,
+    queue: Arc<MorselQueue>,
+    worker: usize,
+    /// Rows left in the current morsel
+    morsel: Option<RowRange>,
+    cancel: CancelToken,
+}
+
+impl<'a> Cursor<'a> for ParallelScanCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        if self.morsel.map_or(true, |m| m.rows == 0) {
+            self.morsel = self.queue.next(self.worker);
+        }
+
+        let morsel = match self.morsel.as_mut() {
+            Some(m) => m,
+            None    => return Ok(CursorChunk::End),
+        };
+
+        let range = RowRange { offset: morsel.offset, rows: min(rows, morsel.rows) };
+        morsel.offset += range.rows;
+        morsel.rows -= range.rows;
+
+        let src: &Block = &self.src;
+        Ok(CursorChunk::Next(window_alias(src, Some(range))?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::exec::Driver;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    fn numbers(rows: usize) -> SharedBlock<'static> {
+        let schema = Schema::parse_ddl("v UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(rows).unwrap();
+        for row in 0 .. rows {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+        SharedBlock::new(block)
+    }
+
+    fn drain(cursor: &mut Cursor, rows: RowOffset) -> Vec<u32> {
+        let mut out = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(rows).unwrap() {
+            out.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        out
+    }
+
+    #[test]
+    fn work_stealing() {
+        let scan = ParallelScanView::new(numbers(1000), 4, 64).unwrap();
+        assert!(scan.worker(4).is_err());
+        assert!(ParallelScanView::new(numbers(10), 0, 64).is_err());
+
+        // A lone worker scans its share, then steals everything else
+        let mut cursor = scan.worker(1).unwrap().bind(&ExecContext::default()).unwrap();
+        let mut values = drain(&mut *cursor, 100);
+        assert_eq!(values.len(), 1000);
+        assert_eq!(&values[.. 3], &[256, 257, 258]);
+        assert_eq!(scan.stolen(), 16 - 4);
+
+        values.sort();
+        assert_eq!(values, (0 .. 1000).collect::<Vec<u32>>());
+
+        let mut other = scan.worker(0).unwrap().bind(&ExecContext::default()).unwrap();
+        assert!(drain(&mut *other, 100).is_empty());
+    }
+
+    #[test]
+    fn parallel_workers() {
+        let scan = ParallelScanView::new(numbers(10000), 3, 100).unwrap();
+        let driver = Driver::new(3, &allocator::GLOBAL).unwrap().with_fetch(30);
+
+        let mut cursor = driver.union(scan.all_workers()).unwrap();
+        let mut values = drain(&mut cursor, 1000);
+        values.sort();
+        assert_eq!(values, (0 .. 10000).collect::<Vec<u32>>());
+    }
+}
@@ -0,0 +1,185 @@
+use std::cmp::min;
+use std::sync::Arc;
+
+use ::allocator::Allocator;
+use ::block::{Block, SharedBlock, View, window_alias};
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::plan::Aggregate;
+use ::row::{RowOffset, RowRange};
+use ::schema::Schema;
+use ::types::Value;
+use ::util::bloom::KeyFilter;
+
+use super::{Operation, Cursor, CursorChunk};
+use super::aggregate::aggregates_with;
+
+/// Operation scanning a table of a `Catalog` by name.
+///
+/// The table is looked up when bound; the cursor keeps scanning it even if it's dropped from (or
+/// replaced in) the catalog in the meantime.
+pub struct ScanTable<'a> {
+    catalog: Arc<Catalog<'a>>,
+    name: String,
+}
+
+impl<'a> ScanTable<'a> {
+    pub fn new<S: Into<String>>(catalog: &Arc<Catalog<'a>>, name: S) -> ScanTable<'a> {
+        ScanTable { catalog: catalog.clone(), name: name.into() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<'a> Operation<'a> for ScanTable<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let table = self.catalog.lookup_ok(&self.name)?;
+        Ok(Box::new(ScanTableCursor::new(table, ctx)))
+    }
+
+    fn describe(&self) -> String {
+        format!("ScanTable {}", self.name)
+    }
+}
+
+/// Implementation of the `ScanTable` operation; scans any shared block (eg. `Iterate`'s working
+/// set).
+pub struct ScanTableCursor<'a> {
+    schema: Schema,
+    table: SharedBlock<'a>,
+    alloc: &'a Allocator,
+    offset: RowOffset,
+    cancel: CancelToken,
+    /// Pushed down by the consumer (eg. the build side keys of a hash join)
+    key_filter: Option<KeyFilter>,
+    /// Rows of the last chunk that passed the key filter
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ScanTableCursor<'a> {
+    pub fn new<'b: 'a>(table: SharedBlock<'a>, ctx: &ExecContext<'b>) -> ScanTableCursor<'a> {
+        ScanTableCursor {
+            schema: table.schema().clone(),
+            table: table,
+            alloc: ctx.allocator(),
+            offset: 0,
+            cancel: ctx.cancel_token().clone(),
+            key_filter: None,
+            block: None,
+        }
+    }
+}
+
+impl<'a> Cursor<'a> for ScanTableCursor<'a> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        let table: &Block = &self.table;
+        loop {
+            if self.offset >= table.rows() {
+                return Ok(CursorChunk::End)
+            }
+
+            let range = RowRange { offset: self.offset, rows: min(rows, table.rows() - self.offset) };
+            self.offset += range.rows;
+
+            let sub = window_alias(table, Some(range))?;
+            if let Some(ref f) = self.key_filter {
+                let block = f.apply(self.alloc, &sub)?;
+                if block.rows() == 0 {
+                    continue
+                }
+
+                self.block = Some(block);
+                return Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+            }
+
+            return Ok(CursorChunk::Next(sub))
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.offset = 0;
+        self.block = None;
+        Ok(())
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        if filter.columns.iter().any(|c| *c >= self.schema.count()) {
+            return false
+        }
+
+        self.key_filter = Some(filter.clone());
+        true
+    }
+
+    /// From the bound table's statistics (computed when it's registered, `Block::update_stats`)
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        if self.key_filter.is_some() || self.offset > 0 {
+            return None
+        }
+
+        aggregates_with(aggregates, self.table.rows(), |pos| self.table.stats(pos).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_value;
+    use ::exec::Driver;
+    use ::plan::AggregateFunc;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn scan_by_name() {
+        let catalog = Arc::new(Catalog::new());
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(5).unwrap();
+        for row in 0 .. 5 {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+        }
+        catalog.register("numbers", block).unwrap();
+
+        let scan = ScanTable::new(&catalog, "numbers");
+        assert!(ScanTable::new(&catalog, "missing").bind(&ExecContext::default()).is_err());
+
+        let mut cursor = scan.bind(&ExecContext::default()).unwrap();
+        catalog.drop_table("numbers").unwrap();
+
+        // Statistics of the bound table, computed when it was registered
+        let max = [Aggregate::new(AggregateFunc::MAX, 0, "m")];
+        assert_eq!(cursor.aggregates_from_metadata(&max), Some(vec![Value::UINT32(4)]));
+
+        let mut chunks = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(2).unwrap() {
+            chunks.push(column_value(view.column(0).unwrap(), 0).unwrap().into_owned());
+        }
+        assert_eq!(chunks, vec![Value::UINT32(0), Value::UINT32(2), Value::UINT32(4)]);
+        assert_eq!(cursor.aggregates_from_metadata(&max), None);
+
+        // Shared with other threads
+        catalog.register("numbers", Block::new(&allocator::GLOBAL, &schema)).unwrap();
+        let driver = Driver::new(1, &allocator::GLOBAL).unwrap();
+        let mut cursor = driver.spawn(ScanTable::new(&catalog, "numbers")).unwrap();
+        assert!(match cursor.next(10).unwrap() { CursorChunk::End => true, _ => false });
+    }
+}
@@ -1,38 +1,199 @@
+use std::cell::Cell;
 use std::cmp::min;
+use std::rc::Rc;
 
 use ::allocator::Allocator;
-use ::block::{RefView, View, window_alias};
+use ::block::{Block, ColumnStats, RefView, View, take, window_alias};
 use ::error::DBError;
+use ::exec::{CancelToken, ExecContext};
+use ::expression::comparison::CompareOp;
+use ::plan::Aggregate;
 use ::row::{RowRange, RowOffset};
 use ::schema::Schema;
+use ::types::{Type, Value};
+use ::util::bitmap;
+use ::util::bloom::KeyFilter;
+use ::util::collation::{Collation, Collator};
 
 use super::{Operation, Cursor, CursorChunk};
+use super::aggregate::aggregates_with;
 
-/// Operation that takes an "external" view and uses it as a source
+/// Operation that takes an "external" view and uses it as a source. Rows the view reports as
+/// deleted (`View::deleted`) are skipped.
 pub struct ScanView<'a> {
     pub src: &'a View<'a>,
     pub range: Option<RowRange>,
+    /// Chunks that can't match the predicate (based on column min/max) are skipped
+    pub predicate: Option<ScanPredicate>,
+    counters: Rc<ScanCounters>,
+}
+
+/// `column op value` predicate used for skipping chunks (zone maps).
+///
+/// The scan only skips chunks where no row can match, it does not filter individual rows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanPredicate {
+    pub column: usize,
+    pub op: CompareOp,
+    pub value: Value<'static>,
+    /// Collation of TEXT comparisons
+    pub collation: Collation,
+}
+
+/// Scan observability counters
+#[derive(Default)]
+pub struct ScanCounters {
+    pub skipped_chunks: Cell<usize>,
+    pub skipped_rows: Cell<usize>,
+    /// Rows dropped by a pushed down `KeyFilter`
+    pub filtered_rows: Cell<usize>,
 }
 
 impl<'a> ScanView<'a> {
     pub fn new(src: &'a View<'a>, range: Option<RowRange>) -> ScanView<'a> {
-        ScanView { src: src, range: range }
+        ScanView { src: src, range: range, predicate: None, counters: Default::default() }
+    }
+
+    pub fn with_predicate(self, predicate: ScanPredicate) -> ScanView<'a> {
+        ScanView { predicate: Some(predicate), ..self }
+    }
+
+    /// Counters shared with the bound cursors
+    pub fn counters(&self) -> Rc<ScanCounters> {
+        self.counters.clone()
+    }
+}
+
+impl ScanPredicate {
+    pub fn new<V: Into<Value<'static>>>(column: usize, op: CompareOp, value: V) -> ScanPredicate {
+        ScanPredicate { column: column, op: op, value: value.into(), collation: Collation::BINARY }
+    }
+
+    pub fn with_collation(self, collation: Collation) -> ScanPredicate {
+        ScanPredicate { collation: collation, ..self }
+    }
+
+    /// NULLs and values of a different type never match. Resolves the collation on each call, use
+    /// `matches_with` when matching many values.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self.collation.collator() {
+            Ok(collator)    => self.matches_with(&collator, value),
+            Err(_)          => false,
+        }
+    }
+
+    /// `matches` using the (resolved) collation of the predicate
+    pub fn matches_with(&self, collator: &Collator, value: &Value) -> bool {
+        if value.is_null() || value.dtype() != self.value.dtype() {
+            return false
+        }
+
+        self.op.eval_collated(collator, value, &self.value)
+    }
+
+    /// Min/max statistics are in binary order, so can't rule out TEXT matches with other collations
+    pub fn might_match(&self, stats: &ColumnStats) -> bool {
+        match self.value.dtype() {
+            Some(Type::TEXT) | Some(Type::JSON) if !self.collation.is_binary() => true,
+            _ => stats.might_match(self.op, &self.value),
+        }
+    }
+}
+
+impl ScanCounters {
+    fn skip(&self, rows: RowOffset) {
+        self.skipped_chunks.set(self.skipped_chunks.get() + 1);
+        self.skipped_rows.set(self.skipped_rows.get() + rows);
+    }
+
+    fn filter(&self, rows: RowOffset) {
+        self.filtered_rows.set(self.filtered_rows.get() + rows);
     }
 }
 
 impl<'a> Operation<'a> for ScanView<'a> {
-    fn bind<'b: 'a>(&self, _: &'b Allocator) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        Ok(Box::new(self.cursor(ctx.allocator(), ctx.cancel_token())?))
+    }
+
+    fn describe(&self) -> String {
+        let mut out = format!("ScanView rows={}", self.range.map_or(self.src.rows(), |r| r.rows));
+        if let Some(ref p) = self.predicate {
+            out += &format!(" predicate=#{} {:?} {}", p.column, p.op, p.value);
+        }
+        out
+    }
+
+    /// Used for skipping chunks, if the scan doesn't have a predicate yet
+    fn push_predicate(&mut self, predicate: &ScanPredicate) -> bool {
+        if self.predicate.is_some() {
+            return false
+        }
+
+        self.predicate = Some(predicate.clone());
+        true
+    }
+}
+
+impl<'a> ScanView<'a> {
+    fn cursor(&self, alloc: &'a Allocator, cancel: &CancelToken) -> Result<ScanViewCursor<'a>, DBError> {
         let sub = window_alias(self.src, self.range)?;
-        let out = Box::new(ScanViewCursor { src: sub, offset: 0 });
-        Ok(out)
+
+        if let Some(ref p) = self.predicate {
+            if p.column >= sub.schema().count() {
+                return Err(DBError::make_column_unknown_pos(p.column))
+            }
+        }
+
+        Ok(ScanViewCursor {
+            view: self.src,
+            base: self.range.map_or(0, |r| r.offset),
+            src: sub,
+            alloc: alloc,
+            offset: 0,
+            predicate: self.predicate.clone(),
+            counters: self.counters.clone(),
+            cancel: cancel.clone(),
+            key_filter: None,
+            block: None,
+        })
     }
 }
 
 /// Implementation of the `ScanView` operation
 struct ScanViewCursor<'a> {
+    /// Scanned view, for its stored statistics
+    view: &'a View<'a>,
+    /// Offset of `src` in `view`
+    base: RowOffset,
     /// This view is already sub
     src: RefView<'a>,
+    alloc: &'a Allocator,
     offset: RowOffset,
+    predicate: Option<ScanPredicate>,
+    counters: Rc<ScanCounters>,
+    cancel: CancelToken,
+    /// Pushed down by the consumer (eg. the build side keys of a hash join)
+    key_filter: Option<KeyFilter>,
+    /// Rows of the last chunk that passed the key filter or weren't deleted
+    block: Option<Block<'a>>,
+}
+
+impl<'a> ScanViewCursor<'a> {
+    /// Rows of the chunk (`range` of `src`) that aren't deleted; None if none of them are
+    fn live_rows(&self, range: RowRange) -> Option<Vec<RowOffset>> {
+        let deleted = self.view.deleted()?;
+        let is_deleted = |row: RowOffset| {
+            let row = self.base + range.offset + row;
+            row < deleted.len() * 8 && bitmap::get_bit(deleted, row)
+        };
+
+        if !(0 .. range.rows).any(|row| is_deleted(row)) {
+            return None
+        }
+
+        Some((0 .. range.rows).filter(|row| !is_deleted(*row)).collect())
+    }
 }
 
 impl<'a> Cursor<'a> for ScanViewCursor<'a> {
@@ -40,17 +201,165 @@ impl<'a> Cursor<'a> for ScanViewCursor<'a> {
         self.src.schema()
     }
 
-    fn next(&'a mut self, rows: RowOffset) -> Result<CursorChunk<'a>, DBError> {
-        let left = self.src.rows() - self.offset;
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        self.cancel.check()?;
+
+        loop {
+            let left = self.src.rows() - self.offset;
+
+            if left == 0 {
+                return Ok(CursorChunk::End)
+            }
+
+            let range = RowRange { offset: self.offset, rows: min(left, rows) };
+            self.offset += range.rows;
+
+            // Zone map: skip chunks where the min/max of the predicate column prove no row matches
+            if let Some(ref p) = self.predicate {
+                let zones = self.view.zone_map(p.column)
+                    .and_then(|z| z.zones(RowRange { offset: self.base + range.offset, rows: range.rows }));
+                if zones.map_or(false, |zs| !zs.iter().any(|z| p.might_match(z))) {
+                    self.counters.skip(range.rows);
+                    continue
+                }
+            }
+
+            let sub = window_alias(&self.src, Some(range))?;
+            let live = match self.live_rows(range) {
+                Some(ref rows) if rows.is_empty()   => continue,
+                Some(rows)                          => Some(take(self.alloc, &sub, &rows)?),
+                None                                => None,
+            };
 
-        if left == 0 {
-            return Ok(CursorChunk::End)
+            if let Some(ref f) = self.key_filter {
+                let (rows, block) = match live {
+                    Some(ref live)  => (live.rows(), f.apply(self.alloc, live)?),
+                    None            => (range.rows, f.apply(self.alloc, &sub)?),
+                };
+                self.counters.filter(rows - block.rows());
+                if block.rows() == 0 {
+                    continue
+                }
+
+                self.block = Some(block);
+                return Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+            }
+
+            if live.is_some() {
+                self.block = live;
+                return Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+            }
+
+            return Ok(CursorChunk::Next(sub))
         }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.offset = 0;
+        self.block = None;
+        Ok(())
+    }
+
+    fn push_key_filter(&mut self, filter: &KeyFilter) -> bool {
+        if filter.columns.iter().any(|c| *c >= self.src.schema().count()) {
+            return false
+        }
+
+        self.key_filter = Some(filter.clone());
+        true
+    }
+
+    /// From the view's stored statistics when scanning all of it, unless rows can be skipped
+    fn aggregates_from_metadata(&self, aggregates: &[Aggregate]) -> Option<Vec<Value<'static>>> {
+        if self.predicate.is_some() || self.key_filter.is_some() || self.offset > 0 || self.view.deleted().is_some() {
+            return None
+        }
+
+        let whole = self.src.rows() == self.view.rows();
+        aggregates_with(aggregates, self.src.rows(), |pos| match whole {
+            true    => self.view.stored_stats(pos).cloned(),
+            false   => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, column_value};
+    use ::types::Type;
+    use ::util::copy_value::ValueSetter;
+
+    #[test]
+    fn zone_map_skipping() {
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::make_one_attr("key", false, Type::UINT32));
+        block.add_rows(40).unwrap();
+        for row in 0 .. 40 {
+            (row as u32).set_row(&mut block[0], row).unwrap();
+        }
+
+        // Without zone maps nothing is skipped
+        {
+            let scan = ScanView::new(&block, None)
+                .with_predicate(ScanPredicate::new(0, CompareOp::GE, 25u32));
+            let counters = scan.counters();
+            let mut cursor = scan.cursor(&allocator::GLOBAL, &CancelToken::new()).unwrap();
+            match cursor.next(10).unwrap() {
+                CursorChunk::Next(view) => assert_eq!(column_value(view.column(0).unwrap(), 0).unwrap(), Value::UINT32(0)),
+                CursorChunk::End        => panic!("Expected a chunk"),
+            }
+            assert_eq!(counters.skipped_chunks.get(), 0);
+        }
+
+        block.update_stats_zoned(5).unwrap();
+        let scan = ScanView::new(&block, None)
+            .with_predicate(ScanPredicate::new(0, CompareOp::GE, 25u32));
+        let counters = scan.counters();
+
+        let mut cursor = scan.cursor(&allocator::GLOBAL, &CancelToken::new()).unwrap();
+        let chunk = match cursor.next(10).unwrap() {
+            CursorChunk::Next(view) => column_value(view.column(0).unwrap(), 0).unwrap().into_owned(),
+            CursorChunk::End        => panic!("Expected a chunk"),
+        };
+
+        // Chunks [0, 10) and [10, 20) can't match
+        assert_eq!(chunk, Value::UINT32(20));
+        assert_eq!(counters.skipped_chunks.get(), 2);
+        assert_eq!(counters.skipped_rows.get(), 20);
+
+        // Zones of a window are offset by the start of the window
+        let scan = ScanView::new(&block, Some(RowRange { offset: 12, rows: 20 }))
+            .with_predicate(ScanPredicate::new(0, CompareOp::LT, 20u32));
+        let counters = scan.counters();
+        let mut cursor = scan.cursor(&allocator::GLOBAL, &CancelToken::new()).unwrap();
+        while let CursorChunk::Next(_) = cursor.next(8).unwrap() { }
+        // Rows [12, 20) might match, [20, 28) and [28, 32) can't
+        assert_eq!(counters.skipped_chunks.get(), 2);
+        assert_eq!(counters.skipped_rows.get(), 12);
+    }
+
+    #[test]
+    fn collated_predicate() {
+        let p = ScanPredicate::new(0, CompareOp::EQ, "abc");
+        assert!(!p.matches(&Value::from("ABC")));
 
-        let range = RowRange { offset: self.offset, rows: min(left, rows) };
-        let sub = window_alias(&self.src, Some(range))?;
+        let p = p.with_collation(Collation::NOCASE);
+        assert!(p.matches(&Value::from("ABC")));
+        assert!(!p.matches(&Value::from("ABD")));
 
-        self.offset += range.rows;
-        Ok(CursorChunk::Next(sub))
+        // Binary min/max can't rule out case insensitive matches
+        let stats = ColumnStats { rows: 2, null_count: 0, min: Some(Value::from("b")), max: Some(Value::from("c")),
+                                  distinct_estimate: 2 };
+        assert!(p.might_match(&stats));
+        assert!(!ScanPredicate::new(0, CompareOp::EQ, "abc").might_match(&stats));
     }
 }
@@ -0,0 +1,164 @@
+use std::cmp::min;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, take, window_alias};
+use ::error::DBError;
+use ::exec::ExecContext;
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::util::sort::{SortColumn, SortKeys};
+
+use super::{Operation, Cursor, CursorChunk, Pipelining};
+
+/// Orders the input rows by the key columns (`util::sort`); rows with equal keys keep their input
+/// order.
+///
+/// The whole input is read when the cursor is bound; the output chunks are gathered from it in
+/// sorted order.
+pub struct Sort<'a> {
+    pub src: Box<Operation<'a> + 'a>,
+    pub keys: Vec<SortColumn>,
+}
+
+/// Implementation of the `Sort` operation
+struct SortCursor<'a> {
+    alloc: &'a Allocator,
+    /// All of the input rows
+    input: Block<'a>,
+    /// Input rows in sorted order
+    perm: Vec<RowOffset>,
+    /// Next position in `perm`
+    offset: RowOffset,
+    /// Rows of the last chunk
+    block: Option<Block<'a>>,
+}
+
+impl<'a> Sort<'a> {
+    pub fn new<T: Operation<'a> + 'a>(keys: Vec<SortColumn>, src: T) -> Sort<'a> {
+        Sort { src: Box::new(src), keys: keys }
+    }
+}
+
+impl<'a> Operation<'a> for Sort<'a> {
+    fn bind<'b: 'a>(&self, ctx: &ExecContext<'b>) -> Result<Box<Cursor<'a> + 'a>, DBError> {
+        let mut input = ctx.bind(&*self.src)?;
+        let fetch = ctx.fetch_rows(&*input);
+        let alloc: &'a Allocator = ctx.allocator();
+
+        let mut block = Block::new(alloc, input.schema());
+        loop {
+            ctx.cancel_token().check()?;
+            match input.next(fetch)? {
+                CursorChunk::Next(view) => block.append_view(&view)?,
+                CursorChunk::End        => break,
+            };
+        }
+
+        let perm = SortKeys::new(&block, &self.keys)?.sort();
+        Ok(Box::new(SortCursor { alloc: alloc, input: block, perm: perm, offset: 0, block: None }))
+    }
+
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter()
+            .map(|k| {
+                let mut out = format!("#{} {}", k.column, if k.ascending { "ASC" } else { "DESC" });
+                if k.nulls_first != k.ascending {
+                    out += if k.nulls_first { " NULLS FIRST" } else { " NULLS LAST" };
+                }
+                if !k.collation.is_binary() {
+                    out += &format!(" COLLATE {}", k.collation);
+                }
+                out
+            })
+            .collect();
+        format!("Sort [{}]", keys.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&(Operation<'a> + 'a)> {
+        vec![&*self.src]
+    }
+
+    fn inputs_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        vec![&mut self.src]
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+}
+
+impl<'a> Cursor<'a> for SortCursor<'a> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    fn next<'c>(&'c mut self, rows: RowOffset) -> Result<CursorChunk<'c>, DBError> {
+        let end = min(self.offset + rows, self.perm.len());
+        if end <= self.offset {
+            return Ok(CursorChunk::End)
+        }
+
+        self.block = Some(take(self.alloc, &self.input, &self.perm[self.offset .. end])?);
+        self.offset = end;
+        Ok(CursorChunk::Next(window_alias(self.block.as_ref().unwrap(), None)?))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.input.memory_usage().total() + self.block.as_ref().map_or(0, |b| b.memory_usage().total())
+    }
+
+    fn pipelining(&self) -> Pipelining {
+        Pipelining::BLOCKING
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self) -> Result<(), DBError> {
+        self.offset = 0;
+        self.block = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_row_data;
+    use ::operation::ScanView;
+    use ::types::{UInt32, Value};
+    use ::util::copy_value::set_column_value;
+
+    fn drain(op: &Sort) -> Vec<u32> {
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+            ids.extend_from_slice(&column_row_data::<UInt32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+        ids
+    }
+
+    #[test]
+    fn sort() {
+        let schema = Schema::parse_ddl("id UINT32 NOT NULL, v INT64").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(6).unwrap();
+        for (row, &v) in [Some(3i64), None, Some(1), Some(3), Some(-2), None].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut block, 1, row, &v).unwrap();
+        }
+
+        let op = Sort::new(vec![SortColumn::asc(1)], ScanView::new(&block, None));
+        assert_eq!(op.describe(), "Sort [#1 ASC]");
+        assert_eq!(op.pipelining(), Pipelining::BLOCKING);
+        assert_eq!(drain(&op), vec![1, 5, 4, 2, 0, 3]);
+
+        let op = Sort::new(vec![SortColumn::desc(1), SortColumn::desc(0)], ScanView::new(&block, None));
+        assert_eq!(drain(&op), vec![3, 0, 2, 4, 5, 1]);
+
+        let op = Sort::new(vec![SortColumn::asc(2)], ScanView::new(&block, None));
+        assert!(op.bind(&ExecContext::default()).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+// vim: set ts=4 sw=4 et :
+
+//! Row at a time evaluation of `ScalarExpr`s; they're `Expr`s, so they can be used as computed
+//! columns (`ExprProjector`) and filter predicates (`ExprFilter`).
+
+use ::allocator::Allocator;
+use ::block::{Block, View, column_value};
+use ::error::DBError;
+use ::expression::{BoundExpr, Expr};
+use ::row::RowOffset;
+use ::schema::Schema;
+use ::types::Value;
+use ::util::copy_value::set_column_value;
+
+use super::ScalarExpr;
+
+struct ScalarBound<'alloc> {
+    alloc: &'alloc Allocator,
+    schema: Schema,
+    expr: ScalarExpr,
+}
+
+impl ScalarExpr {
+    /// Value at the `row` of the view. Follows SQL NULL semantics, like `fold`.
+    pub fn eval<'v>(&self, view: &'v View<'v>, row: RowOffset) -> Result<Value<'v>, DBError> {
+        Ok(match *self {
+            ScalarExpr::COLUMN(pos) => {
+                let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+                column_value(col, row)?
+            }
+            ScalarExpr::LITERAL(ref v) => v.clone(),
+            ScalarExpr::COMPARE(op, ref l, ref r) => match (l.eval(view, row)?, r.eval(view, row)?) {
+                (Value::NULL, _) | (_, Value::NULL) => Value::NULL,
+                (l, r)                              => Value::BOOLEAN(op.eval(&l, &r)),
+            },
+            ScalarExpr::AND(ref l, ref r) => match (truth(l.eval(view, row)?)?, truth(r.eval(view, row)?)?) {
+                (Some(false), _) | (_, Some(false)) => Value::BOOLEAN(false),
+                (Some(true), Some(true))            => Value::BOOLEAN(true),
+                _                                   => Value::NULL,
+            },
+            ScalarExpr::OR(ref l, ref r) => match (truth(l.eval(view, row)?)?, truth(r.eval(view, row)?)?) {
+                (Some(true), _) | (_, Some(true))   => Value::BOOLEAN(true),
+                (Some(false), Some(false))          => Value::BOOLEAN(false),
+                _                                   => Value::NULL,
+            },
+            ScalarExpr::NOT(ref e) => match truth(e.eval(view, row)?)? {
+                Some(b) => Value::BOOLEAN(!b),
+                None    => Value::NULL,
+            },
+        })
+    }
+}
+
+/// BOOLEAN value, `None` for NULL
+fn truth(value: Value) -> Result<Option<bool>, DBError> {
+    match value {
+        Value::BOOLEAN(b)   => Ok(Some(b)),
+        Value::NULL         => Ok(None),
+        v                   => Err(DBError::ExpressionInputType(v.dtype().unwrap().name().to_string())),
+    }
+}
+
+/// Named after the expression text (`#0 > 1`)
+impl<'e> Expr<'e> for ScalarExpr {
+    fn bind<'a: 'e>(&self, alloc: &'a Allocator, input_schema: &Schema)
+        -> Result<Box<BoundExpr<'a> + 'e>, DBError>
+    {
+        let attr = self.attribute(input_schema, self.to_string())?;
+        Ok(Box::new(ScalarBound { alloc: alloc, schema: Schema::from_attr(attr), expr: self.clone() }))
+    }
+
+    fn describe(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<'alloc> BoundExpr<'alloc> for ScalarBound<'alloc> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn evaluate<'a>(&self, view: &'a View<'a>, rows: RowOffset) -> Result<Block<'alloc>, DBError> {
+        let mut out = Block::new(self.alloc, &self.schema);
+        out.add_rows(rows)?;
+        for row in 0 .. rows {
+            set_column_value(&mut out, 0, row, &self.expr.eval(view, row)?)?;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::expression::comparison::CompareOp;
+
+    #[test]
+    fn three_valued() {
+        let schema = Schema::parse_ddl("a INT64, b BOOLEAN").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(3).unwrap();
+        set_column_value(&mut block, 0, 0, &Value::INT64(5)).unwrap();
+        set_column_value(&mut block, 1, 0, &Value::BOOLEAN(false)).unwrap();
+        set_column_value(&mut block, 0, 1, &Value::INT64(1)).unwrap();
+        set_column_value(&mut block, 1, 1, &Value::NULL).unwrap();
+        set_column_value(&mut block, 0, 2, &Value::NULL).unwrap();
+        set_column_value(&mut block, 1, 2, &Value::BOOLEAN(true)).unwrap();
+
+        let gt = ScalarExpr::compare(CompareOp::GT, ScalarExpr::column(0), ScalarExpr::literal(2i64));
+        let expr = ScalarExpr::or(gt, ScalarExpr::not(ScalarExpr::column(1)));
+
+        let bound = expr.bind(&allocator::GLOBAL, &schema).unwrap();
+        assert_eq!(bound.schema().get(0).unwrap().name, "(#0 > 2 OR NOT #1)");
+
+        let out = bound.evaluate(&block, 3).unwrap();
+        let values: Vec<_> = (0 .. 3).map(|row| column_value(out.column(0).unwrap(), row).unwrap()).collect();
+        assert_eq!(values, vec![Value::BOOLEAN(true), Value::NULL, Value::NULL]);
+
+        let bad = ScalarExpr::not(ScalarExpr::column(0));
+        assert!(bad.bind(&allocator::GLOBAL, &schema).unwrap().evaluate(&block, 1).is_err());
+    }
+}
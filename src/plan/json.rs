@@ -0,0 +1,412 @@
+// vim: set ts=4 sw=4 et :
+
+//! JSON representation of logical plans and their expressions, for logging, persisting and
+//! shipping plans between processes.
+//!
+//! ```text
+//! expr:  {"expr": "COLUMN", "column": 0}
+//!        {"expr": "LITERAL", "value": {"type": "INT64", "value": "10"}}
+//!        {"expr": "COMPARE", "op": "GT", "lhs": expr, "rhs": expr}
+//!        {"expr": "AND" | "OR", "lhs": expr, "rhs": expr}
+//!        {"expr": "NOT", "input": expr}
+//! plan:  {"node": "SCAN", "table": "t", "schema": schema, "columns": [0, 1]}
+//!        {"node": "FILTER", "input": plan, "predicate": expr}
+//!        {"node": "PROJECT", "input": plan, "exprs": [{"expr": expr, "name": "a"}, ...]}
+//!        {"node": "AGGREGATE", "input": plan, "group_by": [0],
+//!         "aggregates": [{"func": "SUM", "column": 1, "name": "s"}, ...]}
+//!         (PERCENTILEAPPROX aggregates have a "fraction": 0.9, ARGMIN / ARGMAX a "by": 2 column)
+//!        {"node": "JOIN", "left": plan, "right": plan, "kind": "INNER", "on": [[0, 0], ...]}
+//!        {"node": "SORT", "input": plan, "keys": [{"column": 0, "ascending": true}, ...]}
+//!        {"node": "LIMIT", "input": plan, "offset": 0, "limit": 10}
+//! ```
+//!
+//! Schemas use the `Schema::to_json` format. Numeric literals are stored as strings, so 64 bit
+//! integers survive; BLOBs are hex strings. `COUNT(*)` has no column and an unlimited `LIMIT`
+//! no limit.
+
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::schema::Schema;
+use ::types::{Type, Value};
+use ::util::json::{self, JsonValue};
+
+use super::{Aggregate, AggregateFunc, JoinKind, LogicalPlan, ScalarExpr, SortKey};
+
+/// Limit on the document nesting, so malformed input can't blow the stack
+const MAX_DEPTH: usize = 256;
+
+fn error<S: Into<String>>(msg: S) -> DBError {
+    DBError::Serialization(msg.into())
+}
+
+impl ScalarExpr {
+    pub fn to_json(&self) -> String {
+        match *self {
+            ScalarExpr::COLUMN(pos) =>
+                format!("{{\"expr\":\"COLUMN\",\"column\":{}}}", pos),
+            ScalarExpr::LITERAL(ref v) =>
+                format!("{{\"expr\":\"LITERAL\",\"value\":{}}}", value_to_json(v)),
+            ScalarExpr::COMPARE(op, ref l, ref r) =>
+                format!("{{\"expr\":\"COMPARE\",\"op\":\"{:?}\",\"lhs\":{},\"rhs\":{}}}", op, l.to_json(), r.to_json()),
+            ScalarExpr::AND(ref l, ref r) =>
+                format!("{{\"expr\":\"AND\",\"lhs\":{},\"rhs\":{}}}", l.to_json(), r.to_json()),
+            ScalarExpr::OR(ref l, ref r) =>
+                format!("{{\"expr\":\"OR\",\"lhs\":{},\"rhs\":{}}}", l.to_json(), r.to_json()),
+            ScalarExpr::NOT(ref e) =>
+                format!("{{\"expr\":\"NOT\",\"input\":{}}}", e.to_json()),
+        }
+    }
+
+    pub fn from_json(doc: &str) -> Result<ScalarExpr, DBError> {
+        expr_from_json(&json::parse(doc)?, 0)
+    }
+}
+
+impl LogicalPlan {
+    pub fn to_json(&self) -> String {
+        match *self {
+            LogicalPlan::SCAN { ref table, ref schema, ref columns } =>
+                format!("{{\"node\":\"SCAN\",\"table\":{},\"schema\":{},\"columns\":{:?}}}",
+                        json::quote(table), schema.to_json(), columns),
+            LogicalPlan::FILTER { ref input, ref predicate } =>
+                format!("{{\"node\":\"FILTER\",\"input\":{},\"predicate\":{}}}", input.to_json(), predicate.to_json()),
+            LogicalPlan::PROJECT { ref input, ref exprs } => {
+                let exprs: Vec<_> = exprs.iter()
+                    .map(|&(ref e, ref n)| format!("{{\"expr\":{},\"name\":{}}}", e.to_json(), json::quote(n)))
+                    .collect();
+                format!("{{\"node\":\"PROJECT\",\"input\":{},\"exprs\":[{}]}}", input.to_json(), exprs.join(","))
+            }
+            LogicalPlan::AGGREGATE { ref input, ref group_by, ref aggregates } => {
+                let aggregates: Vec<_> = aggregates.iter()
+                    .map(|a| {
+                        let args = match (a.func, a.by) {
+                            (AggregateFunc::PERCENTILEAPPROX(q), _) => format!(",\"fraction\":{}", q),
+                            (_, Some(by))                           => format!(",\"by\":{}", by),
+                            _                                       => String::new(),
+                        };
+                        match a.column {
+                            Some(c) => format!("{{\"func\":\"{}\"{},\"column\":{},\"name\":{}}}",
+                                               a.func.name(), args, c, json::quote(&a.name)),
+                            None    => format!("{{\"func\":\"{}\"{},\"name\":{}}}", a.func.name(), args, json::quote(&a.name)),
+                        }
+                    })
+                    .collect();
+                format!("{{\"node\":\"AGGREGATE\",\"input\":{},\"group_by\":{:?},\"aggregates\":[{}]}}",
+                        input.to_json(), group_by, aggregates.join(","))
+            }
+            LogicalPlan::JOIN { ref left, ref right, kind, ref on } => {
+                let on: Vec<_> = on.iter().map(|&(l, r)| format!("[{},{}]", l, r)).collect();
+                format!("{{\"node\":\"JOIN\",\"left\":{},\"right\":{},\"kind\":\"{:?}\",\"on\":[{}]}}",
+                        left.to_json(), right.to_json(), kind, on.join(","))
+            }
+            LogicalPlan::SORT { ref input, ref keys } => {
+                let keys: Vec<_> = keys.iter()
+                    .map(|k| format!("{{\"column\":{},\"ascending\":{}}}", k.column, k.ascending))
+                    .collect();
+                format!("{{\"node\":\"SORT\",\"input\":{},\"keys\":[{}]}}", input.to_json(), keys.join(","))
+            }
+            LogicalPlan::LIMIT { ref input, offset, limit } => match limit {
+                Some(limit) => format!("{{\"node\":\"LIMIT\",\"input\":{},\"offset\":{},\"limit\":{}}}",
+                                       input.to_json(), offset, limit),
+                None        => format!("{{\"node\":\"LIMIT\",\"input\":{},\"offset\":{}}}", input.to_json(), offset),
+            },
+        }
+    }
+
+    /// Plan from its `to_json` representation. The plan isn't validated, see `LogicalPlan::schema`.
+    pub fn from_json(doc: &str) -> Result<LogicalPlan, DBError> {
+        plan_from_json(&json::parse(doc)?, 0)
+    }
+}
+
+fn field<'a>(doc: &'a JsonValue, key: &str) -> Result<&'a JsonValue, DBError> {
+    doc.get(key).ok_or_else(|| error(format!("missing {}", key)))
+}
+
+fn string<'a>(doc: &'a JsonValue, key: &str) -> Result<&'a str, DBError> {
+    field(doc, key)?.as_str().ok_or_else(|| error(format!("{} has to be a string", key)))
+}
+
+fn array<'a>(doc: &'a JsonValue, key: &str) -> Result<&'a [JsonValue], DBError> {
+    field(doc, key)?.as_array().ok_or_else(|| error(format!("{} has to be an array", key)))
+}
+
+fn position(value: &JsonValue) -> Result<usize, DBError> {
+    value.as_f64()
+        .filter(|v| v.fract() == 0.0 && *v >= 0.0 && *v < (1u64 << 53) as f64)
+        .map(|v| v as usize)
+        .ok_or_else(|| error("expected a non-negative integer"))
+}
+
+fn positions(doc: &JsonValue, key: &str) -> Result<Vec<usize>, DBError> {
+    array(doc, key)?.iter().map(position).collect()
+}
+
+fn compare_op(name: &str) -> Result<CompareOp, DBError> {
+    match name {
+        "EQ"    => Ok(CompareOp::EQ),
+        "NE"    => Ok(CompareOp::NE),
+        "LT"    => Ok(CompareOp::LT),
+        "LE"    => Ok(CompareOp::LE),
+        "GT"    => Ok(CompareOp::GT),
+        "GE"    => Ok(CompareOp::GE),
+        _       => Err(error(format!("unknown comparison {}", name))),
+    }
+}
+
+fn expr_from_json(doc: &JsonValue, depth: usize) -> Result<ScalarExpr, DBError> {
+    if depth > MAX_DEPTH {
+        return Err(error("expression nested too deep"))
+    }
+
+    let input = |key: &str| -> Result<Box<ScalarExpr>, DBError> {
+        Ok(Box::new(expr_from_json(field(doc, key)?, depth + 1)?))
+    };
+
+    Ok(match string(doc, "expr")? {
+        "COLUMN"    => ScalarExpr::COLUMN(position(field(doc, "column")?)?),
+        "LITERAL"   => ScalarExpr::LITERAL(value_from_json(field(doc, "value")?, depth + 1)?),
+        "COMPARE"   => ScalarExpr::COMPARE(compare_op(string(doc, "op")?)?, input("lhs")?, input("rhs")?),
+        "AND"       => ScalarExpr::AND(input("lhs")?, input("rhs")?),
+        "OR"        => ScalarExpr::OR(input("lhs")?, input("rhs")?),
+        "NOT"       => ScalarExpr::NOT(input("input")?),
+        other       => return Err(error(format!("unknown expression {}", other))),
+    })
+}
+
+fn plan_from_json(doc: &JsonValue, depth: usize) -> Result<LogicalPlan, DBError> {
+    if depth > MAX_DEPTH {
+        return Err(error("plan nested too deep"))
+    }
+
+    let input = |key: &str| -> Result<Box<LogicalPlan>, DBError> {
+        Ok(Box::new(plan_from_json(field(doc, key)?, depth + 1)?))
+    };
+
+    Ok(match string(doc, "node")? {
+        "SCAN" => LogicalPlan::SCAN {
+            table: string(doc, "table")?.to_string(),
+            schema: Schema::from_json_value(field(doc, "schema")?)?,
+            columns: positions(doc, "columns")?,
+        },
+        "FILTER" => LogicalPlan::FILTER {
+            input: input("input")?,
+            predicate: expr_from_json(field(doc, "predicate")?, depth + 1)?,
+        },
+        "PROJECT" => {
+            let exprs = array(doc, "exprs")?.iter()
+                .map(|e| Ok((expr_from_json(field(e, "expr")?, depth + 1)?, string(e, "name")?.to_string())))
+                .collect::<Result<Vec<_>, DBError>>()?;
+            LogicalPlan::PROJECT { input: input("input")?, exprs: exprs }
+        }
+        "AGGREGATE" => {
+            let aggregates = array(doc, "aggregates")?.iter()
+                .map(|a| {
+                    let func = match string(a, "func")? {
+                        "COUNT"             => AggregateFunc::COUNT,
+                        "SUM"               => AggregateFunc::SUM,
+                        "MIN"               => AggregateFunc::MIN,
+                        "MAX"               => AggregateFunc::MAX,
+                        "MEDIAN"            => AggregateFunc::MEDIAN,
+                        "ARGMIN"            => AggregateFunc::ARGMIN,
+                        "ARGMAX"            => AggregateFunc::ARGMAX,
+                        "FIRST"             => AggregateFunc::FIRST,
+                        "LAST"              => AggregateFunc::LAST,
+                        "PERCENTILEAPPROX"  => {
+                            let q = field(a, "fraction")?.as_f64().ok_or_else(|| error("fraction has to be a number".to_string()))?;
+                            AggregateFunc::PERCENTILEAPPROX(q)
+                        }
+                        other               => return Err(error(format!("unknown aggregate {}", other))),
+                    };
+                    let column = match a.get("column") {
+                        Some(c) => Some(position(c)?),
+                        None    => None,
+                    };
+                    let by = match a.get("by") {
+                        Some(c) => Some(position(c)?),
+                        None    => None,
+                    };
+                    Ok(Aggregate { func: func, column: column, by: by, name: string(a, "name")?.to_string() })
+                })
+                .collect::<Result<Vec<_>, DBError>>()?;
+            LogicalPlan::AGGREGATE { input: input("input")?, group_by: positions(doc, "group_by")?, aggregates: aggregates }
+        }
+        "JOIN" => {
+            let kind = match string(doc, "kind")? {
+                "INNER" => JoinKind::INNER,
+                "LEFT"  => JoinKind::LEFT,
+                other   => return Err(error(format!("unknown join {}", other))),
+            };
+            let on = array(doc, "on")?.iter()
+                .map(|pair| match pair.as_array() {
+                    Some(pair) if pair.len() == 2 => Ok((position(&pair[0])?, position(&pair[1])?)),
+                    _ => Err(error("join keys have to be pairs")),
+                })
+                .collect::<Result<Vec<_>, DBError>>()?;
+            LogicalPlan::JOIN { left: input("left")?, right: input("right")?, kind: kind, on: on }
+        }
+        "SORT" => {
+            let keys = array(doc, "keys")?.iter()
+                .map(|k| {
+                    let ascending = field(k, "ascending")?.as_bool()
+                        .ok_or_else(|| error("ascending has to be a boolean"))?;
+                    Ok(SortKey { column: position(field(k, "column")?)?, ascending: ascending })
+                })
+                .collect::<Result<Vec<_>, DBError>>()?;
+            LogicalPlan::SORT { input: input("input")?, keys: keys }
+        }
+        "LIMIT" => {
+            let limit = match doc.get("limit") {
+                Some(l) => Some(position(l)?),
+                None    => None,
+            };
+            LogicalPlan::LIMIT { input: input("input")?, offset: position(field(doc, "offset")?)?, limit: limit }
+        }
+        other => return Err(error(format!("unknown plan node {}", other))),
+    })
+}
+
+fn value_to_json(value: &Value) -> String {
+    fn typed<T: ::std::fmt::Debug>(dtype: Type, v: T) -> String {
+        format!("{{\"type\":\"{}\",\"value\":\"{:?}\"}}", dtype.name(), v)
+    }
+
+    fn seq(dtype: Type, values: Vec<String>) -> String {
+        format!("{{\"type\":\"{}\",\"value\":[{}]}}", dtype.name(), values.join(","))
+    }
+
+    match *value {
+        Value::NULL                 => "{\"type\":\"NULL\"}".to_string(),
+        Value::UINT32(v)            => typed(Type::UINT32, v),
+        Value::UINT64(v)            => typed(Type::UINT64, v),
+        Value::INT32(v)             => typed(Type::INT32, v),
+        Value::INT64(v)             => typed(Type::INT64, v),
+        Value::FLOAT32(v)           => typed(Type::FLOAT32, v),
+        Value::FLOAT64(v)           => typed(Type::FLOAT64, v),
+        Value::TIMESTAMP(v)         => typed(Type::TIMESTAMP, v),
+        Value::TIMESTAMP_TZ(v)      => typed(Type::TIMESTAMP_TZ, v),
+        Value::BOOLEAN(v)           => format!("{{\"type\":\"BOOLEAN\",\"value\":{}}}", v),
+        Value::TEXT(ref v)          => format!("{{\"type\":\"TEXT\",\"value\":{}}}", json::quote(v)),
+        Value::JSON(ref v)          => format!("{{\"type\":\"JSON\",\"value\":{}}}", json::quote(v)),
+        Value::BLOB(ref v)          => {
+            let hex: Vec<_> = v.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{{\"type\":\"BLOB\",\"value\":\"{}\"}}", hex.concat())
+        }
+        Value::LIST(ref v)          => seq(Type::LIST, v.iter().map(value_to_json).collect()),
+        Value::STRUCT(ref v)        => seq(Type::STRUCT, v.iter().map(value_to_json).collect()),
+        Value::MAP(ref v)           => {
+            let entries = v.iter().map(|&(ref k, ref v)| format!("[{},{}]", value_to_json(k), value_to_json(v))).collect();
+            seq(Type::MAP, entries)
+        }
+    }
+}
+
+fn value_from_json(doc: &JsonValue, depth: usize) -> Result<Value<'static>, DBError> {
+    fn number<T: ::std::str::FromStr>(doc: &JsonValue) -> Result<T, DBError> {
+        string(doc, "value")?.parse().map_err(|_| error("invalid number"))
+    }
+
+    if depth > MAX_DEPTH {
+        return Err(error("value nested too deep"))
+    }
+
+    let dtype = match string(doc, "type")? {
+        "NULL"  => return Ok(Value::NULL),
+        name    => name.parse::<Type>()?,
+    };
+
+    let values = || -> Result<Vec<Value<'static>>, DBError> {
+        array(doc, "value")?.iter().map(|v| value_from_json(v, depth + 1)).collect()
+    };
+
+    Ok(match dtype {
+        Type::UINT32    => Value::UINT32(number(doc)?),
+        Type::UINT64    => Value::UINT64(number(doc)?),
+        Type::INT32     => Value::INT32(number(doc)?),
+        Type::INT64     => Value::INT64(number(doc)?),
+        Type::FLOAT32   => Value::FLOAT32(number(doc)?),
+        Type::FLOAT64   => Value::FLOAT64(number(doc)?),
+        Type::TIMESTAMP     => Value::TIMESTAMP(number(doc)?),
+        Type::TIMESTAMP_TZ  => Value::TIMESTAMP_TZ(number(doc)?),
+        Type::BOOLEAN   => Value::BOOLEAN(field(doc, "value")?.as_bool().ok_or_else(|| error("invalid boolean"))?),
+        Type::TEXT      => Value::TEXT(string(doc, "value")?.to_string().into()),
+        Type::JSON      => Value::JSON(string(doc, "value")?.to_string().into()),
+        Type::BLOB      => {
+            let hex = string(doc, "value")?;
+            if hex.len() % 2 != 0 || !hex.is_ascii() {
+                return Err(error("invalid BLOB"))
+            }
+            let bytes = (0 .. hex.len() / 2)
+                .map(|i| u8::from_str_radix(&hex[i * 2 .. i * 2 + 2], 16).map_err(|_| error("invalid BLOB")))
+                .collect::<Result<Vec<_>, _>>()?;
+            Value::BLOB(bytes.into())
+        }
+        Type::LIST      => Value::LIST(values()?),
+        Type::STRUCT    => Value::STRUCT(values()?),
+        Type::MAP       => {
+            let entries = array(doc, "value")?.iter()
+                .map(|e| match e.as_array() {
+                    Some(kv) if kv.len() == 2 => Ok((value_from_json(&kv[0], depth + 1)?, value_from_json(&kv[1], depth + 1)?)),
+                    _ => Err(error("map entries have to be pairs")),
+                })
+                .collect::<Result<Vec<_>, DBError>>()?;
+            Value::MAP(entries)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expr_round_trip() {
+        let values = vec![
+            Value::NULL, Value::UINT64(u64::max_value()), Value::INT64(i64::min_value()), Value::INT32(-7),
+            Value::FLOAT64(0.1), Value::FLOAT32(2.5), Value::BOOLEAN(true), Value::from("it's \"quoted\""),
+            Value::JSON("{\"a\":1}".into()), Value::BLOB(vec![0u8, 0xff, 0x10].into()),
+            Value::LIST(vec![Value::INT32(1), Value::NULL]),
+            Value::MAP(vec![(Value::from("k"), Value::STRUCT(vec![Value::BOOLEAN(false)]))]),
+        ];
+
+        for v in values {
+            let expr = ScalarExpr::not(ScalarExpr::or(
+                ScalarExpr::compare(CompareOp::LE, ScalarExpr::column(3), ScalarExpr::LITERAL(v.clone())),
+                ScalarExpr::and(ScalarExpr::column(0), ScalarExpr::literal(false))));
+
+            let json = expr.to_json();
+            let back = ScalarExpr::from_json(&json).unwrap();
+            assert_eq!(back, expr);
+            assert_eq!(back.to_json(), json);
+            assert_eq!(back.to_string(), expr.to_string());
+        }
+
+        assert!(ScalarExpr::from_json("{\"expr\":\"COLUMN\",\"column\":-1}").is_err());
+        assert!(ScalarExpr::from_json("{\"expr\":\"COMPARE\",\"op\":\"LIKE\",\"lhs\":{},\"rhs\":{}}").is_err());
+        assert!(ScalarExpr::from_json("{\"expr\":\"LITERAL\",\"value\":{\"type\":\"INT32\",\"value\":\"1e3\"}}").is_err());
+    }
+
+    #[test]
+    fn plan_round_trip() {
+        let orders = LogicalPlan::scan("orders", Schema::parse_ddl("id UINT32 NOT NULL, customer TEXT, amount INT32").unwrap());
+        let customers = LogicalPlan::scan("customers", Schema::parse_ddl("name TEXT NOT NULL, tags LIST<TEXT>").unwrap());
+
+        let plan = orders
+            .filter(ScalarExpr::compare(CompareOp::GT, ScalarExpr::column(2), ScalarExpr::literal(10i32)))
+            .join(customers, JoinKind::LEFT, vec![(1, 0)])
+            .aggregate(vec![3], vec![Aggregate::new(AggregateFunc::SUM, 2, "total"), Aggregate::count_all("n")])
+            .project(vec![(ScalarExpr::column(1), "total"), (ScalarExpr::column(0), "name")])
+            .sort(vec![SortKey::desc(0), SortKey::asc(1)])
+            .limit(5, None)
+            .limit(0, Some(3));
+
+        let json = plan.to_json();
+        let back = LogicalPlan::from_json(&json).unwrap();
+        assert_eq!(back.to_json(), json);
+        assert_eq!(back.to_string(), plan.to_string());
+        assert_eq!(back.schema().unwrap().to_json(), plan.schema().unwrap().to_json());
+
+        assert!(LogicalPlan::from_json("{\"node\":\"SCAN\",\"table\":\"t\"}").is_err());
+        assert!(LogicalPlan::from_json("{\"node\":\"UNION\"}").is_err());
+    }
+}
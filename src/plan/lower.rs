@@ -0,0 +1,197 @@
+// vim: set ts=4 sw=4 et :
+
+//! Translation of logical plans into physical `Operation` trees.
+
+use std::sync::Arc;
+
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::operation::{ExprFilter, Filter, HashAggregate, HashJoin, Limit, Operation, Project, ScanPredicate, ScanTable, Sort};
+use ::projector::{BuildSingleSourceProjector, ExprProjector, project_by_position};
+use ::util::sort::SortColumn;
+
+use super::{LogicalPlan, ScalarExpr};
+
+/// Physical operation tree of the plan, scanning the `catalog` tables.
+///
+/// `column op literal` filter conjuncts become `Filter`s (that can be pushed into the scans), the
+/// other conjuncts and computed projections are evaluated a row at a time (`ExprFilter`,
+/// `ExprProjector`).
+pub fn lower<'a>(plan: &LogicalPlan, catalog: &Arc<Catalog<'a>>) -> Result<Box<Operation<'a> + 'a>, DBError> {
+    match *plan {
+        LogicalPlan::SCAN { ref table, ref schema, ref columns } => {
+            let scan = ScanTable::new(catalog, table.as_str());
+            if columns.len() == schema.count() && columns.iter().enumerate().all(|(i, &c)| i == c) {
+                return Ok(Box::new(scan))
+            }
+
+            let mut proj = BuildSingleSourceProjector::new();
+            for &pos in columns {
+                proj = proj.add_as(project_by_position(pos), schema.get(pos)?.name.as_str());
+            }
+
+            Ok(Box::new(Project::new(proj.done(), scan)))
+        }
+        LogicalPlan::FILTER { ref input, ref predicate } => {
+            let mut op = lower(input, catalog)?;
+            for c in predicate.clone().conjuncts() {
+                op = match scan_predicate(&c) {
+                    Some(predicate) => Box::new(Filter { src: op, predicate: predicate }),
+                    None            => Box::new(ExprFilter { src: op, predicate: Box::new(c) }),
+                };
+            }
+            Ok(op)
+        }
+        LogicalPlan::PROJECT { ref input, ref exprs } => {
+            let mut proj = ExprProjector::new();
+            for &(ref e, ref name) in exprs {
+                proj = match *e {
+                    ScalarExpr::COLUMN(pos) => proj.add_as(project_by_position(pos), name.as_str()),
+                    _                       => proj.add_expr(e.clone(), name.as_str()),
+                };
+            }
+
+            Ok(Box::new(Project { src: lower(input, catalog)?, proj: proj }))
+        }
+        LogicalPlan::LIMIT { ref input, offset, limit } => {
+            Ok(Box::new(Limit { src: lower(input, catalog)?, offset: offset, limit: limit }))
+        }
+        LogicalPlan::AGGREGATE { ref input, ref group_by, ref aggregates } => {
+            Ok(Box::new(HashAggregate { src: lower(input, catalog)?, group_by: group_by.clone(), aggregates: aggregates.clone(),
+                                   collations: Vec::new() }))
+        }
+        LogicalPlan::JOIN { ref left, ref right, kind, ref on } => {
+            Ok(Box::new(HashJoin { left: lower(left, catalog)?, right: lower(right, catalog)?, kind: kind, on: on.clone(),
+                              collations: Vec::new() }))
+        }
+        LogicalPlan::SORT { ref input, ref keys } => {
+            let keys = keys.iter()
+                .map(|k| if k.ascending { SortColumn::asc(k.column) } else { SortColumn::desc(k.column) })
+                .collect();
+            Ok(Box::new(Sort { src: lower(input, catalog)?, keys: keys }))
+        }
+    }
+}
+
+/// `column op literal` comparison as a scan predicate
+fn scan_predicate(expr: &ScalarExpr) -> Option<ScanPredicate> {
+    if let ScalarExpr::COMPARE(op, ref l, ref r) = *expr {
+        match (&**l, &**r) {
+            (&ScalarExpr::COLUMN(pos), &ScalarExpr::LITERAL(ref v)) if !v.is_null() =>
+                return Some(ScanPredicate::new(pos, op, v.clone())),
+            (&ScalarExpr::LITERAL(ref v), &ScalarExpr::COLUMN(pos)) if !v.is_null() =>
+                return Some(ScanPredicate::new(pos, flip(op), v.clone())),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// `a op b` as `b flip(op) a`
+fn flip(op: CompareOp) -> CompareOp {
+    match op {
+        CompareOp::LT   => CompareOp::GT,
+        CompareOp::LE   => CompareOp::GE,
+        CompareOp::GT   => CompareOp::LT,
+        CompareOp::GE   => CompareOp::LE,
+        op              => op,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View, column_row_data, column_value};
+    use ::exec::{ExecContext, Metrics};
+    use ::exec::explain::explain;
+    use ::operation::CursorChunk;
+    use ::plan::{optimize, Aggregate, SortKey};
+    use ::schema::Schema;
+    use ::types::{Int64, Value};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn lower_and_run() {
+        let schema = Schema::parse_ddl("id INT64 NOT NULL, name TEXT, score INT64 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(20).unwrap();
+        for row in 0 .. 20 {
+            set_column_value(&mut block, 0, row, &Value::INT64(row as i64)).unwrap();
+            set_column_value(&mut block, 2, row, &Value::INT64(100 - row as i64)).unwrap();
+        }
+
+        let catalog = Arc::new(Catalog::new());
+        catalog.register("t", block).unwrap();
+
+        let plan = LogicalPlan::scan_catalog(&catalog, "t").unwrap()
+            .project(vec![(ScalarExpr::column(2), "s"), (ScalarExpr::column(0), "id")])
+            .filter(ScalarExpr::and(
+                ScalarExpr::compare(CompareOp::LT, ScalarExpr::literal(85i64), ScalarExpr::column(0)),
+                ScalarExpr::compare(CompareOp::GE, ScalarExpr::column(1), ScalarExpr::literal(2i64))))
+            .limit(1, Some(5));
+
+        let op = lower(&optimize(plan).unwrap(), &catalog).unwrap();
+        assert_eq!(explain(&*op),
+                   "Limit offset=1 limit=5\n\
+                   \x20 Project [#1 AS s, #0 AS id]\n\
+                   \x20   Filter #0 GE 2\n\
+                   \x20     Filter #1 GT 85\n\
+                   \x20       Project [#0 AS id, #2 AS score]\n\
+                   \x20         ScanTable t\n");
+
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(3).unwrap() {
+            ids.extend_from_slice(&column_row_data::<Int64>(view.column(1).unwrap()).unwrap().values[.. view.rows()]);
+        }
+
+        assert_eq!(ids, vec![3, 4, 5, 6, 7]);
+
+        // COUNT(*) from the table's row count
+        let counted = LogicalPlan::scan_catalog(&catalog, "t").unwrap()
+            .project(vec![(ScalarExpr::column(1), "name")])
+            .aggregate(vec![], vec![Aggregate::count_all("n")]);
+        let op = lower(&optimize(counted).unwrap(), &catalog).unwrap();
+        let metrics = Arc::new(Metrics::new());
+        let mut cursor = op.bind(&ExecContext::default().with_metrics(metrics.clone())).unwrap();
+        match cursor.next(10).unwrap() {
+            CursorChunk::Next(view) => assert_eq!(column_value(view.column(0).unwrap(), 0).unwrap(), Value::UINT64(20)),
+            CursorChunk::End        => panic!("expected the count"),
+        }
+        assert_eq!(metrics.get("aggregate_pushdown"), 1);
+
+        // Sorted by a computed column, filtered by an OR
+        let sorted = LogicalPlan::scan_catalog(&catalog, "t").unwrap()
+            .filter(ScalarExpr::or(
+                ScalarExpr::compare(CompareOp::LT, ScalarExpr::column(0), ScalarExpr::literal(3i64)),
+                ScalarExpr::not(ScalarExpr::compare(CompareOp::LT, ScalarExpr::column(2), ScalarExpr::literal(84i64)))))
+            .project(vec![(ScalarExpr::column(0), "id"),
+                          (ScalarExpr::compare(CompareOp::GE, ScalarExpr::column(0), ScalarExpr::literal(1i64)), "big")])
+            .sort(vec![SortKey::asc(1), SortKey::desc(0)]);
+
+        let op = lower(&optimize(sorted).unwrap(), &catalog).unwrap();
+        assert_eq!(explain(&*op),
+                   "Sort [#1 ASC, #0 DESC] [blocking]\n\
+                   \x20 Project [#0 AS id, #0 >= 1 AS big]\n\
+                   \x20   Filter (#0 < 3 OR NOT #1 < 84)\n\
+                   \x20     Project [#0 AS id, #2 AS score]\n\
+                   \x20       ScanTable t\n");
+
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut rows = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(3).unwrap() {
+            for row in 0 .. view.rows() {
+                rows.push((column_value(view.column(0).unwrap(), row).unwrap().into_owned(),
+                           column_value(view.column(1).unwrap(), row).unwrap().into_owned()));
+            }
+        }
+
+        let expected: Vec<_> = vec![0, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1].into_iter()
+            .map(|id| (Value::INT64(id), Value::BOOLEAN(id >= 1)))
+            .collect();
+        assert_eq!(rows, expected);
+    }
+}
@@ -0,0 +1,664 @@
+// vim: set ts=4 sw=4 et :
+
+//! Logical query plans.
+//!
+//! A `LogicalPlan` describes what a query computes, not how: it's a tree of relational nodes
+//! referencing input columns by position. Plans are simplified by the `optimize` rules and
+//! translated into physical `Operation` trees by `lower`. Plans and their expressions are
+//! displayed as text and serialized as JSON (`to_json` / `from_json`).
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use ::block::View;
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::operation::join::join_key_type;
+use ::rewrite::Node;
+use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
+use ::types::{Type, Value};
+
+mod eval;
+mod json;
+pub mod lower;
+pub mod optimize;
+
+pub use self::lower::lower;
+pub use self::optimize::optimize;
+
+/// Scalar expression over the columns of the plan node input
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalarExpr {
+    /// Input column by position
+    COLUMN(usize),
+    LITERAL(Value<'static>),
+    COMPARE(CompareOp, Box<ScalarExpr>, Box<ScalarExpr>),
+    AND(Box<ScalarExpr>, Box<ScalarExpr>),
+    OR(Box<ScalarExpr>, Box<ScalarExpr>),
+    NOT(Box<ScalarExpr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AggregateFunc {
+    COUNT,
+    SUM,
+    MIN,
+    MAX,
+    /// Exact median, the mean of the two middle values for an even count
+    MEDIAN,
+    /// Approximate quantile (0 ..= 1), from a t-digest
+    PERCENTILEAPPROX(f64),
+    /// Value of the column at the row with the smallest `Aggregate::by` value (the first such row)
+    ARGMIN,
+    /// Value of the column at the row with the largest `Aggregate::by` value (the first such row)
+    ARGMAX,
+    /// Value of the column at the first row, in input order
+    FIRST,
+    /// Value of the column at the last row, in input order
+    LAST,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    pub func: AggregateFunc,
+    /// Input column; `None` only for `COUNT(*)`
+    pub column: Option<usize>,
+    /// Input column the row is picked by, for ARGMIN / ARGMAX
+    pub by: Option<usize>,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinKind {
+    INNER,
+    /// Unmatched left rows are kept, with NULL right columns
+    LEFT,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: usize,
+    pub ascending: bool,
+}
+
+/// Relational plan node. Columns are referenced by position in the node's input; for `JOIN`
+/// the output columns are the left columns followed by the right ones.
+#[derive(Clone)]
+pub enum LogicalPlan {
+    /// Catalog table; `columns` are the scanned positions in the table `schema`
+    SCAN { table: String, schema: Schema, columns: Vec<usize> },
+    FILTER { input: Box<LogicalPlan>, predicate: ScalarExpr },
+    PROJECT { input: Box<LogicalPlan>, exprs: Vec<(ScalarExpr, String)> },
+    /// Output is the group by columns followed by the aggregates
+    AGGREGATE { input: Box<LogicalPlan>, group_by: Vec<usize>, aggregates: Vec<Aggregate> },
+    /// Equi-join on pairs of (left, right) column positions
+    JOIN { left: Box<LogicalPlan>, right: Box<LogicalPlan>, kind: JoinKind, on: Vec<(usize, usize)> },
+    SORT { input: Box<LogicalPlan>, keys: Vec<SortKey> },
+    LIMIT { input: Box<LogicalPlan>, offset: RowOffset, limit: Option<RowOffset> },
+}
+
+impl ScalarExpr {
+    pub fn column(pos: usize) -> ScalarExpr {
+        ScalarExpr::COLUMN(pos)
+    }
+
+    pub fn literal<V: Into<Value<'static>>>(value: V) -> ScalarExpr {
+        ScalarExpr::LITERAL(value.into())
+    }
+
+    pub fn compare(op: CompareOp, lhs: ScalarExpr, rhs: ScalarExpr) -> ScalarExpr {
+        ScalarExpr::COMPARE(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn and(lhs: ScalarExpr, rhs: ScalarExpr) -> ScalarExpr {
+        ScalarExpr::AND(Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn or(lhs: ScalarExpr, rhs: ScalarExpr) -> ScalarExpr {
+        ScalarExpr::OR(Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn not(expr: ScalarExpr) -> ScalarExpr {
+        ScalarExpr::NOT(Box::new(expr))
+    }
+
+    /// Referenced input columns, sorted and without duplicates
+    pub fn columns(&self) -> Vec<usize> {
+        fn collect(expr: &ScalarExpr, out: &mut Vec<usize>) {
+            match *expr {
+                ScalarExpr::COLUMN(pos)                 => out.push(pos),
+                ScalarExpr::LITERAL(_)                  => (),
+                ScalarExpr::COMPARE(_, ref l, ref r)    |
+                ScalarExpr::AND(ref l, ref r)           |
+                ScalarExpr::OR(ref l, ref r)            => { collect(l, out); collect(r, out) }
+                ScalarExpr::NOT(ref e)                  => collect(e, out),
+            }
+        }
+
+        let mut out = Vec::new();
+        collect(self, &mut out);
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    /// Copy of the expression with the column references replaced
+    pub fn map_columns<F: Fn(usize) -> usize>(&self, f: &F) -> ScalarExpr {
+        match *self {
+            ScalarExpr::COLUMN(pos)                 => ScalarExpr::COLUMN(f(pos)),
+            ScalarExpr::LITERAL(ref v)              => ScalarExpr::LITERAL(v.clone()),
+            ScalarExpr::COMPARE(op, ref l, ref r)   => ScalarExpr::COMPARE(op, Box::new(l.map_columns(f)), Box::new(r.map_columns(f))),
+            ScalarExpr::AND(ref l, ref r)           => ScalarExpr::AND(Box::new(l.map_columns(f)), Box::new(r.map_columns(f))),
+            ScalarExpr::OR(ref l, ref r)            => ScalarExpr::OR(Box::new(l.map_columns(f)), Box::new(r.map_columns(f))),
+            ScalarExpr::NOT(ref e)                  => ScalarExpr::NOT(Box::new(e.map_columns(f))),
+        }
+    }
+
+    /// Evaluate the constant sub-expressions. Follows SQL NULL semantics: comparing with NULL
+    /// is NULL, `NULL AND FALSE` is FALSE and `NULL OR TRUE` is TRUE.
+    pub fn fold(self) -> ScalarExpr {
+        use self::ScalarExpr::*;
+
+        match self {
+            COMPARE(op, l, r) => match (l.fold(), r.fold()) {
+                (LITERAL(Value::NULL), _) | (_, LITERAL(Value::NULL)) =>
+                    LITERAL(Value::NULL),
+                (LITERAL(ref l), LITERAL(ref r)) if l.dtype() == r.dtype() =>
+                    LITERAL(Value::BOOLEAN(op.eval(l, r))),
+                (l, r) =>
+                    COMPARE(op, Box::new(l), Box::new(r)),
+            },
+            AND(l, r) => match (l.fold(), r.fold()) {
+                (LITERAL(Value::BOOLEAN(false)), _) | (_, LITERAL(Value::BOOLEAN(false))) =>
+                    LITERAL(Value::BOOLEAN(false)),
+                (LITERAL(Value::BOOLEAN(true)), e) | (e, LITERAL(Value::BOOLEAN(true))) =>
+                    e,
+                (l, r) =>
+                    AND(Box::new(l), Box::new(r)),
+            },
+            OR(l, r) => match (l.fold(), r.fold()) {
+                (LITERAL(Value::BOOLEAN(true)), _) | (_, LITERAL(Value::BOOLEAN(true))) =>
+                    LITERAL(Value::BOOLEAN(true)),
+                (LITERAL(Value::BOOLEAN(false)), e) | (e, LITERAL(Value::BOOLEAN(false))) =>
+                    e,
+                (l, r) =>
+                    OR(Box::new(l), Box::new(r)),
+            },
+            NOT(e) => match e.fold() {
+                LITERAL(Value::BOOLEAN(b))  => LITERAL(Value::BOOLEAN(!b)),
+                LITERAL(Value::NULL)        => LITERAL(Value::NULL),
+                NOT(e)                      => *e,
+                e                           => NOT(Box::new(e)),
+            },
+            e => e,
+        }
+    }
+
+    /// Split the top level ANDs
+    pub fn conjuncts(self) -> Vec<ScalarExpr> {
+        fn split(expr: ScalarExpr, out: &mut Vec<ScalarExpr>) {
+            match expr {
+                ScalarExpr::AND(l, r)   => { split(*l, out); split(*r, out) }
+                e                       => out.push(e),
+            }
+        }
+
+        let mut out = Vec::new();
+        split(self, &mut out);
+        out
+    }
+
+    /// AND the expressions together; `None` if there aren't any
+    pub fn conjunction(exprs: Vec<ScalarExpr>) -> Option<ScalarExpr> {
+        exprs.into_iter().fold(None, |acc, e| match acc {
+            Some(acc)   => Some(ScalarExpr::and(acc, e)),
+            None        => Some(e),
+        })
+    }
+
+    /// Output attribute of the expression evaluated over `input`
+    pub fn attribute<S: Into<String>>(&self, input: &Schema, name: S) -> Result<Attribute, DBError> {
+        match *self {
+            ScalarExpr::COLUMN(pos)     => Ok(input.get(pos)?.rename(name)),
+            ScalarExpr::LITERAL(ref v)  => match v.dtype() {
+                Some(dtype) => Ok(Attribute::new(name, false, dtype)),
+                None        => Ok(Attribute::new(name, true, Type::BOOLEAN)),
+            },
+            _ => {
+                for pos in self.columns() {
+                    input.get(pos)?;
+                }
+                Ok(Attribute::new(name, true, Type::BOOLEAN))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScalarExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScalarExpr::COLUMN(pos)                 => write!(f, "#{}", pos),
+            ScalarExpr::LITERAL(Value::TEXT(ref v)) => write!(f, "'{}'", v),
+            ScalarExpr::LITERAL(ref v)              => write!(f, "{}", v),
+            ScalarExpr::COMPARE(op, ref l, ref r)   => write!(f, "{} {} {}", l, op_symbol(op), r),
+            ScalarExpr::AND(ref l, ref r)           => write!(f, "({} AND {})", l, r),
+            ScalarExpr::OR(ref l, ref r)            => write!(f, "({} OR {})", l, r),
+            ScalarExpr::NOT(ref e)                  => write!(f, "NOT {}", e),
+        }
+    }
+}
+
+fn op_symbol(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::EQ => "=",
+        CompareOp::NE => "<>",
+        CompareOp::LT => "<",
+        CompareOp::LE => "<=",
+        CompareOp::GT => ">",
+        CompareOp::GE => ">=",
+    }
+}
+
+impl AggregateFunc {
+    /// Name without the arguments
+    pub fn name(self) -> &'static str {
+        match self {
+            AggregateFunc::COUNT                => "COUNT",
+            AggregateFunc::SUM                  => "SUM",
+            AggregateFunc::MIN                  => "MIN",
+            AggregateFunc::MAX                  => "MAX",
+            AggregateFunc::MEDIAN               => "MEDIAN",
+            AggregateFunc::PERCENTILEAPPROX(_)  => "PERCENTILEAPPROX",
+            AggregateFunc::ARGMIN               => "ARGMIN",
+            AggregateFunc::ARGMAX               => "ARGMAX",
+            AggregateFunc::FIRST                => "FIRST",
+            AggregateFunc::LAST                 => "LAST",
+        }
+    }
+
+    /// ARGMIN and ARGMAX, which need a `by` column
+    pub fn has_by(self) -> bool {
+        self == AggregateFunc::ARGMIN || self == AggregateFunc::ARGMAX
+    }
+}
+
+impl Aggregate {
+    pub fn new<S: Into<String>>(func: AggregateFunc, column: usize, name: S) -> Aggregate {
+        Aggregate { func: func, column: Some(column), by: None, name: name.into() }
+    }
+
+    /// ARGMIN / ARGMAX of `column` by the `by` column
+    pub fn arg<S: Into<String>>(func: AggregateFunc, column: usize, by: usize, name: S) -> Aggregate {
+        Aggregate { func: func, column: Some(column), by: Some(by), name: name.into() }
+    }
+
+    /// `COUNT(*)`
+    pub fn count_all<S: Into<String>>(name: S) -> Aggregate {
+        Aggregate { func: AggregateFunc::COUNT, column: None, by: None, name: name.into() }
+    }
+
+    /// Output attribute, over the `input` columns
+    pub fn attribute(&self, input: &Schema) -> Result<Attribute, DBError> {
+        let attr = match self.column {
+            Some(pos)   => Some(input.get(pos)?),
+            None        => None,
+        };
+
+        match (self.func.has_by(), self.by) {
+            (true, Some(pos))   => { input.get(pos)?; }
+            (false, None)       => (),
+            (true, None)        => return Err(DBError::Plan(format!("{} requires a by column", self.func.name()))),
+            (false, Some(_))    => return Err(DBError::Plan(format!("{} doesn't take a by column", self.func.name()))),
+        }
+
+        let dtype = match (self.func, attr) {
+            (AggregateFunc::COUNT, _)           => return Ok(Attribute::new(self.name.as_str(), false, Type::UINT64)),
+            (AggregateFunc::SUM, Some(attr))    => match attr.dtype {
+                Type::INT32 | Type::INT64       => Type::INT64,
+                Type::UINT32 | Type::UINT64     => Type::UINT64,
+                Type::FLOAT32 | Type::FLOAT64   => Type::FLOAT64,
+                dtype                           => return Err(DBError::ExpressionInputType(dtype.name().to_string())),
+            },
+            (AggregateFunc::PERCENTILEAPPROX(q), _) if !(q >= 0.0 && q <= 1.0) =>
+                return Err(DBError::Plan(format!("percentile {} isn't between 0 and 1", q))),
+            (AggregateFunc::MEDIAN, Some(attr)) | (AggregateFunc::PERCENTILEAPPROX(_), Some(attr)) => match attr.dtype {
+                Type::UINT32 | Type::UINT64 | Type::INT32 | Type::INT64 |
+                Type::FLOAT32 | Type::FLOAT64   => Type::FLOAT64,
+                dtype                           => return Err(DBError::ExpressionInputType(dtype.name().to_string())),
+            },
+            (_, Some(attr))                     => attr.dtype,
+            (func, None)                        => return Err(DBError::Plan(format!("{:?} requires a column", func))),
+        };
+
+        // Empty groups don't have a value
+        Ok(Attribute::new(self.name.as_str(), true, dtype))
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.column, self.by, self.func) {
+            (Some(pos), _, AggregateFunc::PERCENTILEAPPROX(q)) =>
+                write!(f, "{}(#{}, {}) AS {}", self.func.name(), pos, q, self.name),
+            (Some(pos), Some(by), _) =>
+                write!(f, "{}(#{} BY #{}) AS {}", self.func.name(), pos, by, self.name),
+            (Some(pos), None, _)    => write!(f, "{}(#{}) AS {}", self.func.name(), pos, self.name),
+            (None, _, _)            => write!(f, "{}(*) AS {}", self.func.name(), self.name),
+        }
+    }
+}
+
+impl SortKey {
+    pub fn asc(column: usize) -> SortKey {
+        SortKey { column: column, ascending: true }
+    }
+
+    pub fn desc(column: usize) -> SortKey {
+        SortKey { column: column, ascending: false }
+    }
+}
+
+impl LogicalPlan {
+    /// Scan all the table columns
+    pub fn scan<S: Into<String>>(table: S, schema: Schema) -> LogicalPlan {
+        let columns = (0 .. schema.count()).collect();
+        LogicalPlan::SCAN { table: table.into(), schema: schema, columns: columns }
+    }
+
+    /// Scan all the columns of a catalog table, using its current schema
+    pub fn scan_catalog(catalog: &Arc<Catalog>, table: &str) -> Result<LogicalPlan, DBError> {
+        let block = catalog.lookup_ok(table)?;
+        Ok(LogicalPlan::scan(table, block.schema().clone()))
+    }
+
+    pub fn filter(self, predicate: ScalarExpr) -> LogicalPlan {
+        LogicalPlan::FILTER { input: Box::new(self), predicate: predicate }
+    }
+
+    pub fn project<S: Into<String>>(self, exprs: Vec<(ScalarExpr, S)>) -> LogicalPlan {
+        let exprs = exprs.into_iter().map(|(e, n)| (e, n.into())).collect();
+        LogicalPlan::PROJECT { input: Box::new(self), exprs: exprs }
+    }
+
+    pub fn aggregate(self, group_by: Vec<usize>, aggregates: Vec<Aggregate>) -> LogicalPlan {
+        LogicalPlan::AGGREGATE { input: Box::new(self), group_by: group_by, aggregates: aggregates }
+    }
+
+    pub fn join(self, right: LogicalPlan, kind: JoinKind, on: Vec<(usize, usize)>) -> LogicalPlan {
+        LogicalPlan::JOIN { left: Box::new(self), right: Box::new(right), kind: kind, on: on }
+    }
+
+    pub fn sort(self, keys: Vec<SortKey>) -> LogicalPlan {
+        LogicalPlan::SORT { input: Box::new(self), keys: keys }
+    }
+
+    pub fn limit(self, offset: RowOffset, limit: Option<RowOffset>) -> LogicalPlan {
+        LogicalPlan::LIMIT { input: Box::new(self), offset: offset, limit: limit }
+    }
+
+    /// Output schema; also checks the column references
+    pub fn schema(&self) -> Result<Schema, DBError> {
+        match *self {
+            LogicalPlan::SCAN { ref schema, ref columns, .. } => {
+                let attrs: Result<Vec<_>, _> = columns.iter().map(|&c| schema.get(c).map(|a| a.clone())).collect();
+                Schema::from_vec(attrs?)
+            }
+            LogicalPlan::FILTER { ref input, ref predicate } => {
+                let schema = input.schema()?;
+                predicate.attribute(&schema, "")?;
+                Ok(schema)
+            }
+            LogicalPlan::PROJECT { ref input, ref exprs } => {
+                let schema = input.schema()?;
+                let attrs: Result<Vec<_>, _> = exprs.iter().map(|&(ref e, ref n)| e.attribute(&schema, n.as_str())).collect();
+                Schema::from_vec(attrs?)
+            }
+            LogicalPlan::AGGREGATE { ref input, ref group_by, ref aggregates } => {
+                let schema = input.schema()?;
+                let mut attrs = Vec::with_capacity(group_by.len() + aggregates.len());
+                for &pos in group_by {
+                    attrs.push(schema.get(pos)?.clone());
+                }
+                for agg in aggregates {
+                    attrs.push(agg.attribute(&schema)?);
+                }
+                Schema::from_vec(attrs)
+            }
+            LogicalPlan::JOIN { ref left, ref right, kind, ref on } => {
+                let (left, right) = (left.schema()?, right.schema()?);
+                for &(l, r) in on {
+                    let (l, r) = (left.get(l)?, right.get(r)?);
+                    if join_key_type(l.dtype, r.dtype).is_none() {
+                        return Err(DBError::Plan(format!("join of {} with {}", l.dtype.name(), r.dtype.name())))
+                    }
+                }
+
+                let mut attrs: Vec<Attribute> = left.iter().cloned().collect();
+                for attr in right.iter() {
+                    let mut attr = attr.clone();
+                    attr.nullable |= kind == JoinKind::LEFT;
+                    attrs.push(attr);
+                }
+                Schema::from_vec(attrs)
+            }
+            LogicalPlan::SORT { ref input, ref keys } => {
+                let schema = input.schema()?;
+                for key in keys {
+                    schema.get(key.column)?;
+                }
+                Ok(schema)
+            }
+            LogicalPlan::LIMIT { ref input, .. } => {
+                input.schema()
+            }
+        }
+    }
+
+    /// Single line description of the node (without the inputs)
+    pub fn describe(&self) -> String {
+        fn list<T: fmt::Display>(items: &[T]) -> String {
+            items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+        }
+
+        match *self {
+            LogicalPlan::SCAN { ref table, ref columns, .. } =>
+                format!("Scan {} {:?}", table, columns),
+            LogicalPlan::FILTER { ref predicate, .. } =>
+                format!("Filter {}", predicate),
+            LogicalPlan::PROJECT { ref exprs, .. } => {
+                let exprs: Vec<_> = exprs.iter().map(|&(ref e, ref n)| format!("{} AS {}", e, n)).collect();
+                format!("Project [{}]", exprs.join(", "))
+            }
+            LogicalPlan::AGGREGATE { ref group_by, ref aggregates, .. } =>
+                format!("Aggregate group_by={:?} [{}]", group_by, list(aggregates)),
+            LogicalPlan::JOIN { kind, ref on, .. } => {
+                let on: Vec<_> = on.iter().map(|&(l, r)| format!("#{} = #{}", l, r)).collect();
+                format!("Join {:?} on [{}]", kind, on.join(", "))
+            }
+            LogicalPlan::SORT { ref keys, .. } => {
+                let keys: Vec<_> = keys.iter()
+                    .map(|k| format!("#{} {}", k.column, if k.ascending { "ASC" } else { "DESC" }))
+                    .collect();
+                format!("Sort [{}]", keys.join(", "))
+            }
+            LogicalPlan::LIMIT { offset, limit: Some(limit), .. } =>
+                format!("Limit offset={} limit={}", offset, limit),
+            LogicalPlan::LIMIT { offset, limit: None, .. } =>
+                format!("Limit offset={}", offset),
+        }
+    }
+
+    /// Columns of the (single) input referenced by the node
+    fn input_columns(&self) -> Vec<usize> {
+        let mut out = match *self {
+            LogicalPlan::FILTER { ref predicate, .. } =>
+                predicate.columns(),
+            LogicalPlan::PROJECT { ref exprs, .. } =>
+                exprs.iter().flat_map(|&(ref e, _)| e.columns()).collect(),
+            LogicalPlan::AGGREGATE { ref group_by, ref aggregates, .. } =>
+                group_by.iter().cloned().chain(aggregates.iter().flat_map(|a| a.column.into_iter().chain(a.by))).collect(),
+            LogicalPlan::SORT { ref keys, .. } =>
+                keys.iter().map(|k| k.column).collect(),
+            _ =>
+                Vec::new(),
+        };
+
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    /// Replace the column references to the (single) input
+    fn map_input_columns<F: Fn(usize) -> usize>(&mut self, f: &F) {
+        match *self {
+            LogicalPlan::FILTER { ref mut predicate, .. } =>
+                *predicate = predicate.map_columns(f),
+            LogicalPlan::PROJECT { ref mut exprs, .. } =>
+                for &mut (ref mut e, _) in exprs.iter_mut() {
+                    *e = e.map_columns(f);
+                },
+            LogicalPlan::AGGREGATE { ref mut group_by, ref mut aggregates, .. } => {
+                for pos in group_by.iter_mut() {
+                    *pos = f(*pos);
+                }
+                for agg in aggregates.iter_mut() {
+                    agg.column = agg.column.map(|c| f(c));
+                    agg.by = agg.by.map(|c| f(c));
+                }
+            }
+            LogicalPlan::SORT { ref mut keys, .. } =>
+                for key in keys.iter_mut() {
+                    key.column = f(key.column);
+                },
+            _ => (),
+        }
+    }
+
+    /// Placeholder used while moving nodes around
+    fn empty() -> LogicalPlan {
+        LogicalPlan::SCAN { table: String::new(), schema: Schema::default(), columns: Vec::new() }
+    }
+
+    /// Take the node out, leaving a placeholder behind
+    fn take(&mut self) -> LogicalPlan {
+        mem::replace(self, LogicalPlan::empty())
+    }
+}
+
+impl Node for LogicalPlan {
+    fn children(&self) -> Vec<&LogicalPlan> {
+        match *self {
+            LogicalPlan::SCAN { .. }                        => Vec::new(),
+            LogicalPlan::JOIN { ref left, ref right, .. }   => vec![&**left, &**right],
+            LogicalPlan::FILTER { ref input, .. }           |
+            LogicalPlan::PROJECT { ref input, .. }          |
+            LogicalPlan::AGGREGATE { ref input, .. }        |
+            LogicalPlan::SORT { ref input, .. }             |
+            LogicalPlan::LIMIT { ref input, .. }            => vec![&**input],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<LogicalPlan>> {
+        match *self {
+            LogicalPlan::SCAN { .. }                                => Vec::new(),
+            LogicalPlan::JOIN { ref mut left, ref mut right, .. }   => vec![left, right],
+            LogicalPlan::FILTER { ref mut input, .. }               |
+            LogicalPlan::PROJECT { ref mut input, .. }              |
+            LogicalPlan::AGGREGATE { ref mut input, .. }            |
+            LogicalPlan::SORT { ref mut input, .. }                 |
+            LogicalPlan::LIMIT { ref mut input, .. }                => vec![input],
+        }
+    }
+}
+
+/// Plan tree, one node per line, inputs indented under their parent
+impl fmt::Display for LogicalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn walk(plan: &LogicalPlan, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(f, "{:indent$}{}", "", plan.describe(), indent = depth * 2)?;
+            for input in plan.children() {
+                walk(input, depth + 1, f)?;
+            }
+            Ok(())
+        }
+
+        walk(self, 0, f)
+    }
+}
+
+impl fmt::Debug for LogicalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orders() -> LogicalPlan {
+        LogicalPlan::scan("orders", Schema::parse_ddl("id UINT32 NOT NULL, customer TEXT NOT NULL, amount INT32").unwrap())
+    }
+
+    #[test]
+    fn plan_schema() {
+        let plan = orders()
+            .filter(ScalarExpr::compare(CompareOp::GT, ScalarExpr::column(2), ScalarExpr::literal(10i32)))
+            .aggregate(vec![1], vec![Aggregate::new(AggregateFunc::SUM, 2, "total"), Aggregate::count_all("n")])
+            .sort(vec![SortKey::desc(1)])
+            .limit(0, Some(10));
+
+        let schema = plan.schema().unwrap();
+        let attrs: Vec<_> = schema.iter().map(|a| (a.name.as_str(), a.dtype, a.nullable)).collect();
+        assert_eq!(attrs, vec![("customer", Type::TEXT, false), ("total", Type::INT64, true), ("n", Type::UINT64, false)]);
+
+        assert_eq!(plan.to_string(),
+                   "Limit offset=0 limit=10\n\
+                   \x20 Sort [#1 DESC]\n\
+                   \x20   Aggregate group_by=[1] [SUM(#2) AS total, COUNT(*) AS n]\n\
+                   \x20     Filter #2 > 10\n\
+                   \x20       Scan orders [0, 1, 2]\n");
+
+        let bad = orders().project(vec![(ScalarExpr::column(3), "x")]);
+        assert!(bad.schema().is_err());
+
+        let bad = orders().aggregate(vec![], vec![Aggregate::new(AggregateFunc::SUM, 1, "s")]);
+        assert!(bad.schema().is_err());
+    }
+
+    #[test]
+    fn join_schema() {
+        let customers = LogicalPlan::scan("customers", Schema::parse_ddl("name TEXT NOT NULL, city TEXT NOT NULL").unwrap());
+        let plan = orders().join(customers.clone(), JoinKind::LEFT, vec![(1, 0)]);
+
+        let schema = plan.schema().unwrap();
+        assert_eq!(schema.count(), 5);
+        assert!(!schema.get(1).unwrap().nullable);
+        assert!(schema.get(4).unwrap().nullable);
+
+        assert!(orders().join(customers, JoinKind::INNER, vec![(0, 0)]).schema().is_err());
+    }
+
+    #[test]
+    fn fold() {
+        use self::ScalarExpr as E;
+
+        let cmp = E::compare(CompareOp::LT, E::literal(1i64), E::literal(2i64));
+        assert_eq!(cmp.clone().fold(), E::literal(true));
+
+        let col = E::compare(CompareOp::EQ, E::column(0), E::literal("x"));
+        assert_eq!(E::and(cmp.clone(), col.clone()).fold(), col);
+        assert_eq!(E::or(E::not(cmp.clone()), col.clone()).fold(), col);
+        assert_eq!(E::and(E::not(cmp), col.clone()).fold(), E::literal(false));
+        assert_eq!(E::compare(CompareOp::EQ, E::literal(Value::NULL), E::literal(1i64)).fold(), E::LITERAL(Value::NULL));
+        assert_eq!(E::not(E::not(col.clone())).fold(), col);
+
+        let conj = E::and(E::and(col.clone(), E::column(1)), E::column(2));
+        assert_eq!(conj.to_string(), "((#0 = 'x' AND #1) AND #2)");
+        assert_eq!(conj.clone().conjuncts(), vec![col, E::column(1), E::column(2)]);
+        assert_eq!(E::conjunction(conj.clone().conjuncts()), Some(conj));
+    }
+}
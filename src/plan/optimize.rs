@@ -0,0 +1,324 @@
+// vim: set ts=4 sw=4 et :
+
+//! Logical plan rewrite rules.
+
+use ::error::DBError;
+use ::rewrite::{Rewriter, Rule};
+use ::types::Value;
+
+use super::{JoinKind, LogicalPlan, ScalarExpr};
+
+/// Evaluates constant expressions. Filters that always pass are removed, filters that never pass
+/// are replaced by an empty `LIMIT`.
+pub struct FoldConstants;
+
+/// Moves filters closer to the scans: below projections, sorts, aggregates (group by columns
+/// only) and into the join inputs.
+pub struct PushFilter;
+
+/// Scans only the columns used by the projection or aggregate above them
+pub struct PruneColumns;
+
+impl Rule<LogicalPlan> for FoldConstants {
+    fn name(&self) -> &str {
+        "fold_constants"
+    }
+
+    fn apply(&self, node: &mut Box<LogicalPlan>) -> Result<bool, DBError> {
+        match **node {
+            LogicalPlan::FILTER { .. } => (),
+            LogicalPlan::PROJECT { ref mut exprs, .. } => {
+                let mut changed = false;
+                for &mut (ref mut e, _) in exprs.iter_mut() {
+                    let folded = e.clone().fold();
+                    changed |= folded != *e;
+                    *e = folded;
+                }
+                return Ok(changed)
+            }
+            _ => return Ok(false),
+        }
+
+        let (input, predicate) = match node.take() {
+            LogicalPlan::FILTER { input, predicate } => (input, predicate),
+            _ => unreachable!(),
+        };
+
+        let folded = predicate.clone().fold();
+        let (plan, changed) = match folded {
+            ScalarExpr::LITERAL(Value::BOOLEAN(true)) =>
+                (*input, true),
+            ScalarExpr::LITERAL(Value::BOOLEAN(false)) | ScalarExpr::LITERAL(Value::NULL) =>
+                (LogicalPlan::LIMIT { input: input, offset: 0, limit: Some(0) }, true),
+            folded => {
+                let changed = folded != predicate;
+                (LogicalPlan::FILTER { input: input, predicate: folded }, changed)
+            }
+        };
+
+        **node = plan;
+        Ok(changed)
+    }
+}
+
+impl Rule<LogicalPlan> for PushFilter {
+    fn name(&self) -> &str {
+        "push_filter"
+    }
+
+    fn apply(&self, node: &mut Box<LogicalPlan>) -> Result<bool, DBError> {
+        let pushable = match **node {
+            LogicalPlan::FILTER { ref input, .. } => match **input {
+                LogicalPlan::PROJECT { .. } | LogicalPlan::SORT { .. } |
+                LogicalPlan::AGGREGATE { .. } | LogicalPlan::JOIN { .. } => true,
+                _ => false,
+            },
+            _ => false,
+        };
+
+        if !pushable {
+            return Ok(false)
+        }
+
+        let (mut input, predicate) = match node.take() {
+            LogicalPlan::FILTER { input, predicate } => (input, predicate),
+            _ => unreachable!(),
+        };
+
+        // Conjuncts that can't be pushed down
+        let mut kept = Vec::new();
+
+        match *input {
+            LogicalPlan::PROJECT { input: ref mut below, ref exprs } => {
+                let mut pushed = Vec::new();
+                for c in predicate.clone().conjuncts() {
+                    let plain = c.columns().iter().all(|&pos| match exprs.get(pos) {
+                        Some(&(ScalarExpr::COLUMN(_), _))   => true,
+                        _                                   => false,
+                    });
+
+                    if plain {
+                        pushed.push(c.map_columns(&|pos| match exprs[pos].0 {
+                            ScalarExpr::COLUMN(src) => src,
+                            _                       => unreachable!(),
+                        }));
+                    } else {
+                        kept.push(c);
+                    }
+                }
+                push_into(below, pushed);
+            }
+            LogicalPlan::SORT { input: ref mut below, .. } => {
+                push_into(below, predicate.clone().conjuncts());
+            }
+            LogicalPlan::AGGREGATE { input: ref mut below, ref group_by, .. } => {
+                let mut pushed = Vec::new();
+                for c in predicate.clone().conjuncts() {
+                    if c.columns().iter().all(|&pos| pos < group_by.len()) {
+                        pushed.push(c.map_columns(&|pos| group_by[pos]));
+                    } else {
+                        kept.push(c);
+                    }
+                }
+                push_into(below, pushed);
+            }
+            LogicalPlan::JOIN { ref mut left, ref mut right, kind, .. } => {
+                let width = left.schema()?.count();
+                let (mut to_left, mut to_right) = (Vec::new(), Vec::new());
+
+                for c in predicate.clone().conjuncts() {
+                    let columns = c.columns();
+                    if columns.iter().all(|&pos| pos < width) {
+                        to_left.push(c);
+                    } else if kind == JoinKind::INNER && columns.iter().all(|&pos| pos >= width) {
+                        to_right.push(c.map_columns(&|pos| pos - width));
+                    } else {
+                        // Filtering the right input of a LEFT join would turn dropped rows into
+                        // NULL padded ones
+                        kept.push(c);
+                    }
+                }
+
+                push_into(left, to_left);
+                push_into(right, to_right);
+            }
+            _ => unreachable!(),
+        }
+
+        let changed = match ScalarExpr::conjunction(kept) {
+            Some(ref p) if *p == predicate => {
+                **node = LogicalPlan::FILTER { input: input, predicate: predicate };
+                false
+            }
+            Some(p) => {
+                **node = LogicalPlan::FILTER { input: input, predicate: p };
+                true
+            }
+            None => {
+                **node = *input;
+                true
+            }
+        };
+
+        Ok(changed)
+    }
+}
+
+/// Filter the plan with the conjuncts
+fn push_into(plan: &mut Box<LogicalPlan>, conjuncts: Vec<ScalarExpr>) {
+    if let Some(predicate) = ScalarExpr::conjunction(conjuncts) {
+        let input = plan.take();
+        **plan = LogicalPlan::FILTER { input: Box::new(input), predicate: predicate };
+    }
+}
+
+impl Rule<LogicalPlan> for PruneColumns {
+    fn name(&self) -> &str {
+        "prune_columns"
+    }
+
+    fn apply(&self, node: &mut Box<LogicalPlan>) -> Result<bool, DBError> {
+        match **node {
+            LogicalPlan::PROJECT { .. } | LogicalPlan::AGGREGATE { .. } => (),
+            _ => return Ok(false),
+        }
+
+        let mut used = node.input_columns();
+        let mut width = None;
+
+        // Only single input chains ending in a scan
+        {
+            let mut plan = &**node;
+            loop {
+                plan = match *plan {
+                    LogicalPlan::PROJECT { ref input, .. } | LogicalPlan::AGGREGATE { ref input, .. } |
+                    LogicalPlan::FILTER { ref input, .. } | LogicalPlan::SORT { ref input, .. } |
+                    LogicalPlan::LIMIT { ref input, .. } => &**input,
+                    _ => break,
+                };
+
+                used.extend(plan.input_columns());
+
+                match *plan {
+                    LogicalPlan::FILTER { .. } | LogicalPlan::SORT { .. } | LogicalPlan::LIMIT { .. } => (),
+                    LogicalPlan::SCAN { ref columns, .. } => { width = Some(columns.len()); break }
+                    _ => break,
+                }
+            }
+        }
+
+        used.sort();
+        used.dedup();
+
+        match width {
+            Some(width) if used.len() < width => (),
+            _ => return Ok(false),
+        }
+
+        remap_chain(node, &used, &|pos| used.binary_search(&pos).unwrap());
+        Ok(true)
+    }
+}
+
+/// Narrow the scan at the end of the chain to the `used` columns and update the references
+fn remap_chain<F: Fn(usize) -> usize>(plan: &mut LogicalPlan, used: &[usize], remap: &F) {
+    plan.map_input_columns(remap);
+
+    match *plan {
+        LogicalPlan::SCAN { ref mut columns, .. } => {
+            let scanned = used.iter().map(|&pos| columns[pos]).collect();
+            *columns = scanned;
+        }
+        LogicalPlan::PROJECT { ref mut input, .. } | LogicalPlan::AGGREGATE { ref mut input, .. } |
+        LogicalPlan::FILTER { ref mut input, .. } | LogicalPlan::SORT { ref mut input, .. } |
+        LogicalPlan::LIMIT { ref mut input, .. } => remap_chain(input, used, remap),
+        _ => unreachable!(),
+    }
+}
+
+/// Rewriter with the logical plan rules
+pub fn plan_rules<'r>() -> Rewriter<'r, LogicalPlan> {
+    Rewriter::new()
+        .with_rule(FoldConstants)
+        .with_rule(PushFilter)
+        .with_rule(PruneColumns)
+}
+
+/// Apply the `plan_rules` to the plan
+pub fn optimize(plan: LogicalPlan) -> Result<LogicalPlan, DBError> {
+    let mut plan = Box::new(plan);
+    plan_rules().rewrite(&mut plan)?;
+    Ok(*plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::expression::comparison::CompareOp;
+    use ::plan::{Aggregate, AggregateFunc, SortKey};
+    use ::schema::Schema;
+
+    fn scan(table: &str, ddl: &str) -> LogicalPlan {
+        LogicalPlan::scan(table, Schema::parse_ddl(ddl).unwrap())
+    }
+
+    fn gt(pos: usize, v: i64) -> ScalarExpr {
+        ScalarExpr::compare(CompareOp::GT, ScalarExpr::column(pos), ScalarExpr::literal(v))
+    }
+
+    #[test]
+    fn push_and_prune() {
+        let plan = scan("t", "a INT64, b INT64, c TEXT, d TEXT")
+            .sort(vec![SortKey::asc(2)])
+            .project(vec![(ScalarExpr::column(2), "c"), (ScalarExpr::column(0), "a")])
+            .filter(ScalarExpr::and(gt(1, 5), ScalarExpr::literal(true)));
+
+        let plan = optimize(plan).unwrap();
+        assert_eq!(plan.to_string(),
+                   "Project [#1 AS c, #0 AS a]\n\
+                   \x20 Sort [#1 ASC]\n\
+                   \x20   Filter #0 > 5\n\
+                   \x20     Scan t [0, 2]\n");
+        assert_eq!(plan.schema().unwrap().iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), vec!["c", "a"]);
+    }
+
+    #[test]
+    fn push_join_aggregate() {
+        let join = scan("l", "id INT64, v INT64").join(scan("r", "id INT64, w INT64"), JoinKind::INNER, vec![(0, 0)]);
+        let plan = join.clone()
+            .filter(ScalarExpr::and(ScalarExpr::and(gt(1, 1), gt(3, 3)), ScalarExpr::compare(CompareOp::EQ, ScalarExpr::column(1), ScalarExpr::column(3))));
+
+        assert_eq!(optimize(plan).unwrap().to_string(),
+                   "Filter #1 = #3\n\
+                   \x20 Join INNER on [#0 = #0]\n\
+                   \x20   Filter #1 > 1\n\
+                   \x20     Scan l [0, 1]\n\
+                   \x20   Filter #1 > 3\n\
+                   \x20     Scan r [0, 1]\n");
+
+        // Right side of a LEFT join stays above it
+        let plan = scan("l", "id INT64, v INT64")
+            .join(scan("r", "id INT64, w INT64"), JoinKind::LEFT, vec![(0, 0)])
+            .filter(gt(3, 3));
+        assert_eq!(optimize(plan.clone()).unwrap().to_string(), plan.to_string());
+
+        let plan = scan("t", "a INT64, b INT64, c INT64")
+            .aggregate(vec![2], vec![Aggregate::new(AggregateFunc::MAX, 0, "m")])
+            .filter(ScalarExpr::and(gt(0, 7), gt(1, 1)));
+        assert_eq!(optimize(plan).unwrap().to_string(),
+                   "Filter #1 > 1\n\
+                   \x20 Aggregate group_by=[1] [MAX(#0) AS m]\n\
+                   \x20   Filter #1 > 7\n\
+                   \x20     Scan t [0, 2]\n");
+    }
+
+    #[test]
+    fn fold_filters() {
+        let never = ScalarExpr::compare(CompareOp::EQ, ScalarExpr::literal(1i64), ScalarExpr::literal(2i64));
+        let plan = scan("t", "a INT64").filter(ScalarExpr::or(never.clone(), gt(0, 1)));
+        assert_eq!(optimize(plan).unwrap().to_string(), "Filter #0 > 1\n  Scan t [0]\n");
+
+        let plan = scan("t", "a INT64").filter(never);
+        assert_eq!(optimize(plan).unwrap().to_string(), "Limit offset=0 limit=0\n  Scan t [0]\n");
+    }
+}
@@ -1,8 +1,12 @@
+use std::fmt;
+
 use itertools::Itertools;
 
+use super::allocator::Allocator;
 use super::error::DBError;
+use super::expression::{BoundExpr, Expr};
 use super::schema::{Attribute, Schema};
-use super::block::{self, RefView, View};
+use super::block::{self, Block, RefView, View};
 
 /// Typed checked and evaluated projector
 pub struct BoundProjector {
@@ -16,8 +20,13 @@ pub struct SingleSourceProjector(Vec<Projector>);
 pub struct BuildSingleSourceProjector(Vec<Projector>);
 
 /// Projection for multi-input operation
-pub struct MultiSourceProjector(Vec<MultiProjector>);
+///
+/// Attributes whose name is already taken in the output are renamed with their source's prefix
+/// (`"<source index>."` unless set with `BuildMultiSourceProjector::prefixes`).
+pub struct MultiSourceProjector(Vec<MultiProjector>, Vec<String>);
+pub struct BuildMultiSourceProjector(Vec<MultiProjector>, Vec<String>);
 
+#[derive(Clone)]
 enum Source {
     /// From source by position
     POS(usize),
@@ -28,6 +37,7 @@ enum Source {
 }
 
 /// Project attribute as
+#[derive(Clone)]
 enum As {
     /// Original name
     ORIG,
@@ -37,9 +47,34 @@ enum As {
     NEW(String),
 }
 
+#[derive(Clone)]
 struct Projector(Source, As);
 struct MultiProjector(Projector, usize);
 
+/// Projection of input columns mixed with computed (expression) columns
+pub struct ExprProjector<'e>(Vec<ExprEntry<'e>>);
+
+enum ExprEntry<'e> {
+    /// Pass-through input columns
+    COLUMNS(Projector),
+    /// Expression evaluated against the input, and its output name (the expression's own if not
+    /// set)
+    EXPR(Box<Expr<'e> + 'e>, Option<String>),
+}
+
+/// Bound `ExprProjector`.
+///
+/// Pass-through columns are aliased from the input view, expression columns are evaluated into
+/// owned blocks (`evaluate`), one per expression. Output names that are already taken are
+/// replaced with `_c<output position>`.
+pub struct BoundExprProjector<'alloc, 'e> {
+    /// Output Schema
+    pub schema: Schema,
+    /// Over the input (source 0) and the expression outputs (source 1 ...)
+    proj: BoundProjector,
+    exprs: Vec<Box<BoundExpr<'alloc> + 'e>>,
+}
+
 /// Bound attribute
 /// input index, input column index & output attribute.
 struct BoundAttribute(usize, usize, Attribute);
@@ -59,14 +94,32 @@ pub fn project_by_name<S: ToString>(name: S) ->  SingleSourceProjector {
     SingleSourceProjector(vec![Projector(Source::NAME(name.to_string()), As::ORIG)])
 }
 
-fn mk_bound_attr(input: &Schema, pos: usize, out: &As) -> Result<BoundAttribute, DBError> {
+fn mk_bound_attr(input: &Schema, src: usize, pos: usize, out: &As) -> Result<BoundAttribute, DBError> {
     input.get(pos)
         .map(|attr| match *out {
             As::ORIG                => attr.clone(),
             As::PREFIX(ref prefix)  => attr.rename(format!("{}{}", prefix, attr.name)),
             As::NEW(ref name)       => attr.rename(name.clone()),
         })
-        .map(|attr| BoundAttribute(0, pos, attr))
+        .map(|attr| BoundAttribute(src, pos, attr))
+}
+
+/// Resolve a projector against the `src` input's schema
+fn bind_projector(proj: &Projector, input: &Schema, src: usize, bound: &mut Vec<BoundAttribute>)
+    -> Result<(), DBError>
+{
+    match proj.0 {
+        Source::POS(pos) =>
+            bound.push(mk_bound_attr(input, src, pos, &proj.1)?),
+        Source::NAME(ref name) =>
+            bound.push(mk_bound_attr(input, src, input.exists_ok(name.as_str())?, &proj.1)?),
+        Source::ALL =>
+            for pos in 0..input.count() {
+                bound.push(mk_bound_attr(input, src, pos, &proj.1)?)
+            }
+    }
+
+    Ok(())
 }
 
 impl SingleSourceProjector {
@@ -74,16 +127,7 @@ impl SingleSourceProjector {
         let mut bound = Vec::new();
 
         for proj in &self.0 {
-            match proj.0 {
-                Source::POS(pos) =>
-                    bound.push(mk_bound_attr(input, pos, &proj.1)?),
-                Source::NAME(ref name) =>
-                    bound.push(mk_bound_attr(input, input.exists_ok(name.as_str())?, &proj.1)?),
-                Source::ALL =>
-                    for pos in 0..input.count() {
-                        bound.push(mk_bound_attr(input, pos, &proj.1)?)
-                    }
-            }
+            bind_projector(proj, input, 0, &mut bound)?;
         }
 
         let attrs = bound.iter().map(|e| e.2.clone()).collect();
@@ -91,6 +135,29 @@ impl SingleSourceProjector {
     }
 }
 
+impl fmt::Display for Projector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Source::POS(pos)        => write!(f, "#{}", pos)?,
+            Source::NAME(ref name)  => write!(f, "{}", name)?,
+            Source::ALL             => write!(f, "*")?,
+        }
+
+        match self.1 {
+            As::ORIG                => Ok(()),
+            As::PREFIX(ref prefix)  => write!(f, " AS {}*", prefix),
+            As::NEW(ref name)       => write!(f, " AS {}", name),
+        }
+    }
+}
+
+/// `[a, #1 AS b, * AS t.*]`; `#` is a column position
+impl fmt::Display for SingleSourceProjector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}]", self.0.iter().join(", "))
+    }
+}
+
 impl BuildSingleSourceProjector {
 
     pub fn new() -> BuildSingleSourceProjector {
@@ -125,27 +192,336 @@ impl BuildSingleSourceProjector {
     }
 }
 
+impl BuildMultiSourceProjector {
+    pub fn new() -> BuildMultiSourceProjector {
+        BuildMultiSourceProjector(Vec::new(), Vec::new())
+    }
+
+    /// Project from the `src` input
+    pub fn add(mut self, src: usize, proj: SingleSourceProjector) -> BuildMultiSourceProjector {
+        proj.0.into_iter()
+            .foreach(|p| self.0.push(MultiProjector(p, src)));
+        self
+    }
+
+    pub fn add_as<S: ToString>(mut self, src: usize, proj: SingleSourceProjector, name: S)
+        -> BuildMultiSourceProjector
+    {
+        proj.0.into_iter()
+            .map(|mut p| { p.1 = As::NEW(name.to_string()); p})
+            .foreach(|p| self.0.push(MultiProjector(p, src)));
+        self
+    }
+
+    pub fn add_prefixed<S: ToString>(mut self, src: usize, proj: SingleSourceProjector, prefix: S)
+        -> BuildMultiSourceProjector
+    {
+        proj.0.into_iter()
+            .map(|mut p| { p.1 = As::PREFIX(prefix.to_string()); p})
+            .foreach(|p| self.0.push(MultiProjector(p, src)));
+        self
+    }
+
+    /// Prefixes, by source, for renaming duplicate attribute names
+    pub fn prefixes<S: ToString>(mut self, prefixes: &[S]) -> BuildMultiSourceProjector {
+        self.1 = prefixes.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    pub fn done(self) -> MultiSourceProjector {
+        MultiSourceProjector(self.0, self.1)
+    }
+}
+
 impl MultiSourceProjector {
     pub fn bind(&self, src: &[&Schema]) -> Result<BoundProjector, DBError> {
-        Err(DBError::Unknown)
+        let mut bound = Vec::new();
+
+        for proj in &self.0 {
+            let input = src.get(proj.1)
+                .ok_or_else(|| DBError::ExpressionInputCount(format!("no input {} (of {})", proj.1, src.len())))?;
+            bind_projector(&proj.0, input, proj.1, &mut bound)?;
+        }
+
+        // Prefix names that are already taken
+        for idx in 0 .. bound.len() {
+            let taken = bound[.. idx].iter().any(|b| b.2.name == bound[idx].2.name);
+            if taken {
+                let src = bound[idx].0;
+                let prefix = self.1.get(src).cloned().unwrap_or_else(|| format!("{}.", src));
+                let name = format!("{}{}", prefix, bound[idx].2.name);
+                bound[idx].2 = bound[idx].2.rename(name);
+            }
+        }
+
+        let attrs = bound.iter().map(|e| e.2.clone()).collect();
+        Ok(BoundProjector { schema: Schema::from_vec(attrs)?, bound_attrs: bound })
+    }
+}
+
+impl<'e> ExprProjector<'e> {
+    pub fn new() -> ExprProjector<'e> {
+        ExprProjector(Vec::new())
+    }
+
+    /// Pass-through columns
+    pub fn add(mut self, proj: SingleSourceProjector) -> ExprProjector<'e> {
+        self.0.extend(proj.0.into_iter().map(ExprEntry::COLUMNS));
+        self
+    }
+
+    /// Pass-through columns renamed to `name`
+    pub fn add_as<S: ToString>(mut self, proj: SingleSourceProjector, name: S) -> ExprProjector<'e> {
+        self.0.extend(proj.0.into_iter().map(|p| ExprEntry::COLUMNS(Projector(p.0, As::NEW(name.to_string())))));
+        self
+    }
+
+    /// Computed column named `name`
+    pub fn add_expr<E: Expr<'e> + 'e, S: ToString>(mut self, expr: E, name: S) -> ExprProjector<'e> {
+        self.0.push(ExprEntry::EXPR(Box::new(expr), Some(name.to_string())));
+        self
+    }
+
+    /// Computed column named after the expression's output column
+    pub fn add_computed<E: Expr<'e> + 'e>(mut self, expr: E) -> ExprProjector<'e> {
+        self.0.push(ExprEntry::EXPR(Box::new(expr), None));
+        self
+    }
+
+    /// Any computed columns
+    pub fn is_computed(&self) -> bool {
+        self.0.iter().any(|e| match *e {
+            ExprEntry::EXPR(..) => true,
+            _                   => false,
+        })
+    }
+
+    pub fn bind<'a: 'e>(&self, alloc: &'a Allocator, input: &Schema) -> Result<BoundExprProjector<'a, 'e>, DBError> {
+        let mut bound = Vec::new();
+        let mut exprs = Vec::new();
+
+        for entry in &self.0 {
+            match *entry {
+                ExprEntry::COLUMNS(ref p) =>
+                    bind_projector(p, input, 0, &mut bound)?,
+                ExprEntry::EXPR(ref expr, ref name) => {
+                    let expr = expr.bind(alloc, input)?;
+                    if expr.schema().count() != 1 {
+                        return Err(DBError::ExpressionInputCount(format!("{} != 1", expr.schema().count())))
+                    }
+
+                    let out = As::NEW(name.clone().unwrap_or_else(|| expr.schema().get(0).unwrap().name.clone()));
+                    exprs.push(expr);
+                    bound.push(mk_bound_attr(exprs.last().unwrap().schema(), exprs.len(), 0, &out)?);
+                }
+            }
+        }
+
+        for idx in 0 .. bound.len() {
+            if bound[.. idx].iter().any(|b| b.2.name == bound[idx].2.name) {
+                bound[idx].2 = bound[idx].2.rename(format!("_c{}", idx));
+            }
+        }
+
+        let attrs = bound.iter().map(|e| e.2.clone()).collect();
+        let proj = BoundProjector { schema: Schema::from_vec(attrs)?, bound_attrs: bound };
+        Ok(BoundExprProjector { schema: proj.schema.clone(), proj: proj, exprs: exprs })
+    }
+}
+
+/// `[a, #1 AS b, <expr> AS c]`
+impl<'e> fmt::Display for ExprProjector<'e> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut entries = self.0.iter().map(|e| match *e {
+            ExprEntry::COLUMNS(ref p)                   => p.to_string(),
+            ExprEntry::EXPR(ref expr, Some(ref name))   => format!("{} AS {}", expr.describe(), name),
+            ExprEntry::EXPR(ref expr, None)             => expr.describe(),
+        });
+        write!(f, "[{}]", entries.join(", "))
+    }
+}
+
+impl<'alloc, 'e> BoundExprProjector<'alloc, 'e> {
+    /// Any computed columns; without them chunks can be projected with `project_chunk`
+    pub fn is_computed(&self) -> bool {
+        !self.exprs.is_empty()
+    }
+
+    /// Input column of a pass-through output column
+    pub fn source_column(&self, pos: usize) -> Option<usize> {
+        self.proj.bound_attrs.get(pos).filter(|b| b.0 == 0).map(|b| b.1)
+    }
+
+    /// Evaluate the expression columns for the input rows
+    pub fn evaluate<'v>(&self, src: &'v View<'v>) -> Result<Vec<Block<'alloc>>, DBError> {
+        let rows = src.rows();
+        self.exprs.iter()
+            .map(|e| e.evaluate(src, rows))
+            .collect()
+    }
+
+    /// Output view. Pass-through columns alias `src`, computed columns alias the blocks from
+    /// `evaluate(src)`.
+    pub fn project_view<'v>(&self, src: &'v View<'v>, computed: &'v [Block<'v>]) -> Result<RefView<'v>, DBError> {
+        if computed.len() != self.exprs.len() {
+            return Err(DBError::ExpressionInputCount(format!("{} != {}", computed.len(), self.exprs.len())))
+        }
+
+        let mut views = Vec::with_capacity(computed.len() + 1);
+        views.push(src);
+        views.extend(computed.iter().map(|b| b as &View));
+        self.proj.project_views(&views)
+    }
+
+    /// Project a cursor chunk, without computed columns (see `BoundProjector::project_chunk`)
+    pub fn project_chunk<'v>(&self, src: RefView<'v>) -> Result<RefView<'v>, DBError> {
+        if self.is_computed() {
+            return Err(DBError::Unsupported("project_chunk with computed columns".to_string()))
+        }
+
+        self.proj.project_chunk(src)
     }
 }
 
 impl BoundProjector {
+    /// Input column of an output column (of the first input, for multi source projections)
+    pub fn source_column(&self, pos: usize) -> Option<usize> {
+        self.bound_attrs.get(pos).map(|b| b.1)
+    }
+
     pub fn project_view<'a>(&self, src: &'a View<'a>) -> Result<RefView<'a>, DBError> {
         let mut columns = Vec::new();
-        let schema = src.schema().clone();
         let rows = src.rows();
 
         for bound_attr in &self.bound_attrs {
-            let c = src.column(bound_attr.1).unwrap();
+            let c = src.column(bound_attr.1)
+                .ok_or(DBError::make_column_unknown_pos(bound_attr.1))?;
             let nc = block::alias_column(c, None)?;
 
             columns.push(nc);
         }
 
-        let out = RefView::new(schema, columns, rows);
+        let out = RefView::new(self.schema.clone(), columns, rows);
         Ok(out)
     }
+
+    /// Project columns of multiple views (bound with `MultiSourceProjector`). The views must have
+    /// the same number of rows.
+    pub fn project_views<'a>(&self, src: &[&'a View<'a>]) -> Result<RefView<'a>, DBError> {
+        let rows = src.first().map_or(0, |v| v.rows());
+        if src.iter().any(|v| v.rows() != rows) {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let mut columns = Vec::with_capacity(self.bound_attrs.len());
+        for bound_attr in &self.bound_attrs {
+            let view = src.get(bound_attr.0)
+                .ok_or_else(|| DBError::ExpressionInputCount(format!("no input {} (of {})", bound_attr.0, src.len())))?;
+            let c = view.column(bound_attr.1)
+                .ok_or(DBError::make_column_unknown_pos(bound_attr.1))?;
+            columns.push(block::alias_column(c, None)?);
+        }
+
+        Ok(RefView::new(self.schema.clone(), columns, rows))
+    }
+
+    /// Project a cursor chunk. Consumes the chunk's view (instead of borrowing it) so the result
+    /// lives as long as the chunk data.
+    pub fn project_chunk<'a>(&self, src: RefView<'a>) -> Result<RefView<'a>, DBError> {
+        let columns: Vec<usize> = self.bound_attrs.iter().map(|b| b.1).collect();
+        src.select(&columns, self.schema.clone())
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, column_value};
+    use ::expression::literal::Literal;
+    use ::types::{Type, Value};
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn multi_source() {
+        let left = Schema::from_vec(vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+        ]).unwrap();
+        let right = Schema::from_vec(vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("score", false, Type::INT64),
+        ]).unwrap();
+
+        let proj = BuildMultiSourceProjector::new()
+            .add(0, project_all_attributes())
+            .add(1, project_all_attributes())
+            .add_as(1, project_by_name("score"), "points")
+            .done();
+
+        let bound = proj.bind(&[&left, &right]).unwrap();
+        let names: Vec<_> = bound.schema.iter().map(|a| a.name.clone()).collect();
+        assert_eq!(names, vec!["id", "name", "1.id", "score", "points"]);
+
+        let prefixed = BuildMultiSourceProjector::new()
+            .add(0, project_by_name("id"))
+            .add(1, project_by_name("id"))
+            .prefixes(&["l.", "r."])
+            .done();
+        assert_eq!(prefixed.bind(&[&left, &right]).unwrap().schema.get(1).unwrap().name, "r.id");
+
+        assert!(proj.bind(&[&left]).is_err());
+        assert!(BuildMultiSourceProjector::new().add(1, project_by_name("name")).done()
+            .bind(&[&left, &right]).is_err());
+
+        let mut lblock = Block::new(&allocator::GLOBAL, &left);
+        let mut rblock = Block::new(&allocator::GLOBAL, &right);
+        lblock.add_rows(2).unwrap();
+        rblock.add_rows(2).unwrap();
+        for row in 0 .. 2 {
+            set_column_value(&mut lblock, 0, row, &Value::UINT32(row as u32)).unwrap();
+            set_column_value(&mut rblock, 1, row, &Value::INT64(10 * row as i64)).unwrap();
+        }
+
+        let view = bound.project_views(&[&lblock, &rblock]).unwrap();
+        assert_eq!(view.rows(), 2);
+        assert!(*view.schema() == bound.schema);
+        assert_eq!(column_value(view.column(0).unwrap(), 1).unwrap(), Value::UINT32(1));
+        assert_eq!(column_value(view.column(4).unwrap(), 1).unwrap(), Value::INT64(10));
+
+        rblock.add_rows(1).unwrap();
+        assert!(bound.project_views(&[&lblock, &rblock]).is_err());
+    }
+
+    #[test]
+    fn computed_columns() {
+        let schema = Schema::from_vec(vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+        ]).unwrap();
+
+        let mut input = Block::new(&allocator::GLOBAL, &schema);
+        input.add_rows(3).unwrap();
+        set_column_value(&mut input, 1, 2, &Value::from("c")).unwrap();
+
+        let proj = ExprProjector::new()
+            .add_expr(Literal::new("x", Value::INT64(7)), "seven")
+            .add(project_by_name("name"))
+            .add_expr(Literal::new("y", Value::from("z")), "name")
+            .bind(&allocator::GLOBAL, &schema)
+            .unwrap();
+
+        let names: Vec<_> = proj.schema.iter().map(|a| a.name.clone()).collect();
+        assert_eq!(names, vec!["seven", "name", "_c2"]);
+
+        let computed = proj.evaluate(&input).unwrap();
+        let view = proj.project_view(&input, &computed).unwrap();
+        assert_eq!(view.rows(), 3);
+        assert_eq!(column_value(view.column(0).unwrap(), 2).unwrap(), Value::INT64(7));
+        assert_eq!(column_value(view.column(1).unwrap(), 2).unwrap(), Value::from("c"));
+        assert_eq!(column_value(view.column(2).unwrap(), 0).unwrap(), Value::from("z"));
+
+        assert!(proj.project_view(&input, &computed[.. 1]).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+// vim : set ts=4 sw=4 et :
+
+//! Mapping between Rust structs and rows.
+//!
+//! ```ignore
+//! struct Person { id: u32, name: String, score: Option<f64> }
+//!
+//! impl Record for Person {
+//!     fn schema() -> Schema {
+//!         Schema::from_vec(vec![
+//!             Attribute::new("id", false, Type::UINT32),
+//!             Attribute::new("name", false, Type::TEXT),
+//!             Attribute::new("score", true, Type::FLOAT64),
+//!         ]).unwrap()
+//!     }
+//!
+//!     fn write<'a>(&self, block: &mut Block<'a>, row: RowOffset) -> Result<(), DBError> {
+//!         set_column_value(block, 0, row, &self.id)?;
+//!         set_column_value(block, 1, row, &self.name)?;
+//!         set_column_value(block, 2, row, &self.score)
+//!     }
+//!
+//!     fn read<'v>(row: &RowRef<'v>) -> Result<Person, DBError> {
+//!         let (id, name, score) = row.get_row()?;
+//!         Ok(Person { id: id, name: name, score: score })
+//!     }
+//! }
+//! ```
+
+use ::block::{Block, View};
+use ::error::DBError;
+use ::row::{RowOffset, RowReader, RowRef};
+use ::schema::Schema;
+
+/// Rust type stored as a row. Fields map to columns of the `schema()`.
+pub trait Record: Sized {
+    /// Schema of the record rows
+    fn schema() -> Schema;
+
+    /// Write the record fields into a row of the block (see `util::copy_value::set_column_value`)
+    fn write<'a>(&self, block: &mut Block<'a>, row: RowOffset) -> Result<(), DBError>;
+
+    /// Read a record out of a row
+    fn read<'v>(row: &RowRef<'v>) -> Result<Self, DBError>;
+}
+
+/// Schemas have the same column count and types
+pub fn check_record_schema<R: Record>(schema: &Schema) -> Result<(), DBError> {
+    let expected = R::schema();
+
+    if expected.count() != schema.count() {
+        return Err(DBError::AttributeMissing(format!("column count {} != {}", expected.count(), schema.count())))
+    }
+
+    for (e, s) in expected.iter().zip(schema.iter()) {
+        if e.dtype != s.dtype {
+            return Err(DBError::AttributeType(s.name.clone()))
+        }
+    }
+
+    Ok(())
+}
+
+/// Read all the rows of the view as records
+pub fn read_records<'v, R: Record>(view: &'v View<'v>) -> Result<Vec<R>, DBError> {
+    check_record_schema::<R>(view.schema())?;
+
+    RowReader::new(view)
+        .map(|row| R::read(&row))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::schema::Attribute;
+    use ::table::Table;
+    use ::types::Type;
+    use ::util::copy_value::set_column_value;
+
+    #[derive(Debug, PartialEq)]
+    struct Person {
+        id: u32,
+        name: String,
+        score: Option<f64>,
+    }
+
+    impl Record for Person {
+        fn schema() -> Schema {
+            Schema::from_vec(vec![
+                Attribute::new("id", false, Type::UINT32),
+                Attribute::new("name", false, Type::TEXT),
+                Attribute::new("score", true, Type::FLOAT64),
+            ]).unwrap()
+        }
+
+        fn write<'a>(&self, block: &mut Block<'a>, row: RowOffset) -> Result<(), DBError> {
+            set_column_value(block, 0, row, &self.id)?;
+            set_column_value(block, 1, row, &self.name)?;
+            set_column_value(block, 2, row, &self.score)
+        }
+
+        fn read<'v>(row: &RowRef<'v>) -> Result<Person, DBError> {
+            let (id, name, score) = row.get_row()?;
+            Ok(Person { id: id, name: name, score: score })
+        }
+    }
+
+    #[test]
+    fn records() {
+        let people = vec![
+            Person { id: 1, name: "abc".to_string(), score: None },
+        ];
+
+        let mut table = Table::new(&allocator::GLOBAL, &Person::schema(), None);
+        assert_eq!(table.append_records(&people).unwrap(), 0);
+
+        let block = table.take().unwrap();
+        assert_eq!(block.to_records::<Person>().unwrap(), people);
+
+        let mut other = Table::new(&allocator::GLOBAL, &Schema::make_one_attr("id", false, Type::UINT32), None);
+        assert!(other.append_records(&people).is_err());
+        assert_eq!(other.rows(), 0);
+    }
+}
@@ -0,0 +1,232 @@
+// vim: set ts=4 sw=4 et :
+
+//! Visiting and rewriting `Operation` and `Expr` trees.
+//!
+//! A `Rewriter` applies its rules bottom up (inputs first) over the whole tree, and repeats until
+//! a pass doesn't change anything. Rules can modify a node in place or replace it.
+
+use ::error::DBError;
+use ::expression::Expr;
+use ::operation::Operation;
+
+/// Default limit on the `Rewriter` passes
+pub const DEFAULT_MAX_PASSES: usize = 16;
+
+/// Tree node with inputs
+pub trait Node {
+    fn children(&self) -> Vec<&Self>;
+    fn children_mut(&mut self) -> Vec<&mut Box<Self>>;
+}
+
+impl<'a> Node for Operation<'a> + 'a {
+    fn children(&self) -> Vec<&(Operation<'a> + 'a)> {
+        self.inputs()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<Operation<'a> + 'a>> {
+        self.inputs_mut()
+    }
+}
+
+impl<'a> Node for Expr<'a> + 'a {
+    fn children(&self) -> Vec<&(Expr<'a> + 'a)> {
+        self.inputs()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<Expr<'a> + 'a>> {
+        self.inputs_mut()
+    }
+}
+
+pub trait Visitor<T: ?Sized> {
+    /// Called for every node, parents before their inputs (at `depth + 1`). Return false to skip
+    /// the node's inputs.
+    fn visit(&mut self, node: &T, depth: usize) -> bool;
+}
+
+impl<T: ?Sized, F: FnMut(&T, usize) -> bool> Visitor<T> for F {
+    fn visit(&mut self, node: &T, depth: usize) -> bool {
+        self(node, depth)
+    }
+}
+
+/// Visit the tree, pre-order
+pub fn walk<T: Node + ?Sized>(node: &T, visitor: &mut Visitor<T>) {
+    fn walk_depth<T: Node + ?Sized>(node: &T, visitor: &mut Visitor<T>, depth: usize) {
+        if visitor.visit(node, depth) {
+            for child in node.children() {
+                walk_depth(child, visitor, depth + 1);
+            }
+        }
+    }
+
+    walk_depth(node, visitor, 0)
+}
+
+/// Rewrite rule
+pub trait Rule<T: ?Sized> {
+    fn name(&self) -> &str;
+
+    /// Modify or replace the node. Returns true if anything changed; a rule must eventually stop
+    /// changing the tree.
+    fn apply(&self, node: &mut Box<T>) -> Result<bool, DBError>;
+}
+
+/// Applies a set of rules until the tree doesn't change
+pub struct Rewriter<'r, T: ?Sized + 'r> {
+    rules: Vec<Box<Rule<T> + 'r>>,
+    max_passes: usize,
+}
+
+impl<'r, T: Node + ?Sized + 'r> Rewriter<'r, T> {
+    pub fn new() -> Rewriter<'r, T> {
+        Rewriter { rules: Vec::new(), max_passes: DEFAULT_MAX_PASSES }
+    }
+
+    pub fn with_rule<R: Rule<T> + 'r>(mut self, rule: R) -> Rewriter<'r, T> {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Stop after `passes` even if the rules still change the tree
+    pub fn with_max_passes(self, passes: usize) -> Rewriter<'r, T> {
+        Rewriter { max_passes: passes, .. self }
+    }
+
+    /// Returns the names of the applied rules, in order
+    pub fn rewrite(&self, node: &mut Box<T>) -> Result<Vec<String>, DBError> {
+        let mut applied = Vec::new();
+
+        for _ in 0 .. self.max_passes {
+            let before = applied.len();
+            self.pass(node, &mut applied)?;
+
+            if applied.len() == before {
+                break
+            }
+        }
+
+        Ok(applied)
+    }
+
+    fn pass(&self, node: &mut Box<T>, applied: &mut Vec<String>) -> Result<(), DBError> {
+        for child in node.children_mut() {
+            self.pass(child, applied)?;
+        }
+
+        for rule in &self.rules {
+            if rule.apply(node)? {
+                applied.push(rule.name().to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Pushes the predicate of filtering operations (`Operation::predicate`) into their inputs.
+///
+/// Scans (`ScanView`, `ParquetScan`) use it to skip chunks; the filter stays in place.
+pub struct PushPredicate;
+
+impl<'a> Rule<Operation<'a> + 'a> for PushPredicate {
+    fn name(&self) -> &str {
+        "push_predicate"
+    }
+
+    fn apply(&self, node: &mut Box<Operation<'a> + 'a>) -> Result<bool, DBError> {
+        let predicate = match node.predicate() {
+            Some(p) => p.clone(),
+            None    => return Ok(false),
+        };
+
+        let mut changed = false;
+        for input in node.inputs_mut() {
+            changed |= input.push_predicate(&predicate);
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Rewriter with the built-in operation rules
+pub fn operation_rules<'a, 'r>() -> Rewriter<'r, Operation<'a> + 'a> {
+    Rewriter::new()
+        .with_rule(PushPredicate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::exec::{CancelToken, Cancellable, ExecContext};
+    use ::exec::explain::explain;
+    use ::expression::comparison::CompareOp;
+    use ::expression::json::{JsonExtract, JsonTypeOf};
+    use ::expression::literal::Literal;
+    use ::operation::{CursorChunk, Filter, ScanPredicate, ScanView};
+    use ::schema::Schema;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+
+    /// Operation descriptions up to a depth
+    struct Describe(Vec<(String, usize)>, usize);
+
+    impl<'a> Visitor<Operation<'a> + 'a> for Describe {
+        fn visit(&mut self, op: &(Operation<'a> + 'a), depth: usize) -> bool {
+            self.0.push((op.describe(), depth));
+            depth < self.1
+        }
+    }
+
+    fn boxed<'a, T: Operation<'a> + 'a>(op: T) -> Box<Operation<'a> + 'a> {
+        Box::new(op)
+    }
+
+    #[test]
+    fn push_predicate() {
+        let schema = Schema::parse_ddl("v INT64 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(100).unwrap();
+        for row in 0 .. 100 {
+            set_column_value(&mut block, 0, row, &Value::INT64(row as i64)).unwrap();
+        }
+        block.update_stats_zoned(10).unwrap();
+
+        let scan = ScanView::new(&block, None);
+        let counters = scan.counters();
+
+        let predicate = ScanPredicate::new(0, CompareOp::GE, 90i64);
+        let mut op = boxed(Filter::new(predicate, Cancellable::new(scan, CancelToken::new())));
+
+        let rules = operation_rules();
+        assert_eq!(rules.rewrite(&mut op).unwrap(), vec!["push_predicate"]);
+        assert!(rules.rewrite(&mut op).unwrap().is_empty());
+
+        assert_eq!(explain(&*op),
+                   "Filter #0 GE 90\n  Cancellable\n    ScanView rows=100 predicate=#0 GE 90\n");
+
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut rows = 0;
+        while let CursorChunk::Next(view) = cursor.next(10).unwrap() {
+            rows += view.rows();
+        }
+
+        assert_eq!(rows, 10);
+        assert_eq!(counters.skipped_chunks.get(), 9);
+
+        let mut nodes = Describe(Vec::new(), 1);
+        walk(&*op, &mut nodes);
+        assert_eq!(nodes.0, vec![("Filter #0 GE 90".to_string(), 0), ("Cancellable".to_string(), 1)]);
+    }
+
+    #[test]
+    fn expr_walk() {
+        let expr: Box<Expr> = Box::new(JsonTypeOf::new(JsonExtract::new(Literal::new("doc", Value::from("{}")), "$.a")));
+
+        let mut constants = Vec::new();
+        walk(&*expr, &mut |e: &(Expr<'static> + 'static), depth| { constants.push((e.is_constant(), depth)); true });
+        assert_eq!(constants, vec![(false, 0), (false, 1), (true, 2)]);
+    }
+}
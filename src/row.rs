@@ -1,3 +1,6 @@
+use ::block::{RefColumn, View, column_nulls, column_row_data, column_value};
+use ::error::DBError;
+use ::types::*;
 
 /// Index into table/column row
 pub type RowOffset = usize;
@@ -10,3 +13,239 @@ pub struct RowRange {
     /// Count of rows
     pub rows: usize,
 }
+
+/// Iterate a `View` row by row.
+pub struct RowReader<'v> {
+    view: &'v View<'v>,
+    row: RowOffset,
+    rows: RowOffset,
+}
+
+/// Reference to a single row of a `View` with typed accessors. Getters return `None` for NULL
+/// values.
+#[derive(Clone, Copy)]
+pub struct RowRef<'v> {
+    view: &'v View<'v>,
+    row: RowOffset,
+}
+
+impl<'v> RowReader<'v> {
+    pub fn new(view: &'v View<'v>) -> RowReader<'v> {
+        RowReader { view: view, row: 0, rows: view.rows() }
+    }
+}
+
+impl<'v> Iterator for RowReader<'v> {
+    type Item = RowRef<'v>;
+
+    fn next(&mut self) -> Option<RowRef<'v>> {
+        if self.row >= self.rows {
+            return None
+        }
+
+        let out = RowRef { view: self.view, row: self.row };
+        self.row += 1;
+        Some(out)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.rows - self.row;
+        (left, Some(left))
+    }
+}
+
+impl<'v> RowRef<'v> {
+    pub fn new(view: &'v View<'v>, row: RowOffset) -> Result<RowRef<'v>, DBError> {
+        if row >= view.rows() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        Ok(RowRef { view: view, row: row })
+    }
+
+    /// Position of the row in the view
+    pub fn row(&self) -> RowOffset {
+        self.row
+    }
+
+    fn column(&self, col: usize) -> Result<&'v RefColumn<'v>, DBError> {
+        self.view.column(col).ok_or_else(|| DBError::make_column_unknown_pos(col))
+    }
+
+    pub fn is_null(&self, col: usize) -> Result<bool, DBError> {
+        let column = self.column(col)?;
+        Ok(column.attribute().nullable && column_nulls(column).get(self.row))
+    }
+
+    /// Typed (native) value of the column, None if NULL
+    pub fn get<T: ValueInfo>(&self, col: usize) -> Result<Option<&'v T::Store>, DBError> {
+        let column = self.column(col)?;
+        let data = column_row_data::<T>(column)?;
+
+        if column.attribute().nullable && data.nulls.get(self.row) {
+            return Ok(None)
+        }
+
+        Ok(Some(&data.values[self.row]))
+    }
+
+    pub fn get_u32(&self, col: usize) -> Result<Option<u32>, DBError> {
+        self.get::<UInt32>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_u64(&self, col: usize) -> Result<Option<u64>, DBError> {
+        self.get::<UInt64>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_i32(&self, col: usize) -> Result<Option<i32>, DBError> {
+        self.get::<Int32>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_i64(&self, col: usize) -> Result<Option<i64>, DBError> {
+        self.get::<Int64>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_f32(&self, col: usize) -> Result<Option<f32>, DBError> {
+        self.get::<Float32>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_f64(&self, col: usize) -> Result<Option<f64>, DBError> {
+        self.get::<Float64>(col).map(|v| v.cloned())
+    }
+
+    pub fn get_bool(&self, col: usize) -> Result<Option<bool>, DBError> {
+        self.get::<Boolean>(col).map(|v| v.cloned())
+    }
+
+    /// TEXT or JSON value
+    pub fn get_str(&self, col: usize) -> Result<Option<&'v str>, DBError> {
+        let out = match self.column(col)?.attribute().dtype {
+            Type::JSON  => self.get::<Json>(col)?,
+            _           => self.get::<Text>(col)?,
+        };
+
+        Ok(out.map(|v| v.as_ref()))
+    }
+
+    pub fn get_blob(&self, col: usize) -> Result<Option<&'v [u8]>, DBError> {
+        Ok(self.get::<Blob>(col)?.map(|v| v.as_ref()))
+    }
+
+    /// Read the row into a tuple
+    pub fn get_row<R: RowGetter<'v>>(&self) -> Result<R, DBError> {
+        R::get_row(self)
+    }
+
+    /// Dynamically typed value of the column
+    pub fn get_value(&self, col: usize) -> Result<Value<'v>, DBError> {
+        column_value(self.column(col)?, self.row)
+    }
+}
+
+/// Rust native types that can be read out of a row column. Reading NULL into a non `Option` type
+/// is an error.
+pub trait ValueGetter<'v>: Sized {
+    fn get_value(row: &RowRef<'v>, col: usize) -> Result<Self, DBError>;
+}
+
+/// Read a whole row into a Rust tuple, one tuple element per column.
+pub trait RowGetter<'v>: Sized {
+    fn get_row(row: &RowRef<'v>) -> Result<Self, DBError>;
+}
+
+fn not_null<'v, T>(row: &RowRef<'v>, col: usize, value: Option<T>) -> Result<T, DBError> {
+    value.ok_or_else(|| DBError::make_column_not_nullable(
+        row.view.schema().get(col).map(|a| a.name.clone()).unwrap_or_default()))
+}
+
+macro_rules! value_getter {
+    ($native:ty, $getter:ident) => {
+        impl<'v> ValueGetter<'v> for $native {
+            fn get_value(row: &RowRef<'v>, col: usize) -> Result<$native, DBError> {
+                let value = row.$getter(col)?;
+                not_null(row, col, value)
+            }
+        }
+    }
+}
+
+value_getter!(u32, get_u32);
+value_getter!(u64, get_u64);
+value_getter!(i32, get_i32);
+value_getter!(i64, get_i64);
+value_getter!(f32, get_f32);
+value_getter!(f64, get_f64);
+value_getter!(bool, get_bool);
+value_getter!(&'v str, get_str);
+value_getter!(&'v [u8], get_blob);
+
+impl<'v> ValueGetter<'v> for String {
+    fn get_value(row: &RowRef<'v>, col: usize) -> Result<String, DBError> {
+        <&str>::get_value(row, col).map(String::from)
+    }
+}
+
+impl<'v, T: ValueGetter<'v>> ValueGetter<'v> for Option<T> {
+    fn get_value(row: &RowRef<'v>, col: usize) -> Result<Option<T>, DBError> {
+        if row.is_null(col)? {
+            Ok(None)
+        } else {
+            T::get_value(row, col).map(Some)
+        }
+    }
+}
+
+macro_rules! row_getter {
+    ($($name:ident: $pos:tt),+) => {
+        impl<'v, $($name: ValueGetter<'v>),+> RowGetter<'v> for ($($name,)+) {
+            fn get_row(row: &RowRef<'v>) -> Result<($($name,)+), DBError> {
+                Ok(($($name::get_value(row, $pos)?,)+))
+            }
+        }
+    }
+}
+
+row_getter!(A: 0);
+row_getter!(A: 0, B: 1);
+row_getter!(A: 0, B: 1, C: 2);
+row_getter!(A: 0, B: 1, C: 2, D: 3);
+row_getter!(A: 0, B: 1, C: 2, D: 3, E: 4);
+row_getter!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+row_getter!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+row_getter!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::schema::{Attribute, Schema};
+    use ::util::copy_value::ValueSetter;
+
+    #[test]
+    fn row_reader() {
+        let attrs = vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+        ];
+
+        let mut block = Block::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap());
+        block.add_rows(2).unwrap();
+        1u32.set_row(&mut block[0], 0).unwrap();
+        2u32.set_row(&mut block[0], 1).unwrap();
+        block[1].nulls_mut().unwrap().set(0, false);
+        "abc".set_row(&mut block[1], 0).unwrap();
+        NULL_VALUE.set_row(&mut block[1], 1).unwrap();
+
+        let rows: Vec<(Option<u32>, Option<&str>)> = RowReader::new(&block)
+            .map(|r| (r.get_u32(0).unwrap(), r.get_str(1).unwrap()))
+            .collect();
+        assert_eq!(rows, vec![(Some(1), Some("abc")), (Some(2), None)]);
+
+        let row = RowRef::new(&block, 1).unwrap();
+        assert!(row.is_null(1).unwrap());
+        assert!(row.get_i64(0).is_err(), "Wrong type");
+        assert!(row.get_u32(2).is_err(), "Missing column");
+        assert!(RowRef::new(&block, 2).is_err());
+    }
+}
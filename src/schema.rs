@@ -2,25 +2,70 @@
 
 // libstd
 use std::iter::Iterator;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::ops::Index;
 
 // DBKit
 use super::error::DBError;
 use super::types::Type;
+use super::util::json::{self, JsonValue};
+
+/// Key-value annotations of attributes and schemas
+pub type Metadata = BTreeMap<String, String>;
 
 /// Attribute represents high level column metadata such as name, nullability and type
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Attribute {
     pub name: String,
     pub nullable: bool,
     pub dtype: Type,
+    /// Nested type children. Single element attribute for LIST, fields for STRUCT, key and value
+    /// attributes for MAP.
+    pub children: Vec<Attribute>,
+    /// Stable identifier, independent of name and position (eg. Parquet field_id). Kept when the
+    /// attribute is renamed or cast, so schema evolution can match columns.
+    pub field_id: Option<i32>,
+    /// Format or application specific annotations; carried through projections
+    pub metadata: Metadata,
 }
 
 /// Describes the attributes and organization of data
 #[derive(Clone, Default)]
 pub struct Schema {
     attrs: Vec<Attribute>,
+    metadata: Metadata,
+    /// Attribute positions by name
+    index: HashMap<String, usize>,
+}
+
+/// Name resolution rules of `Schema::lookup`
+#[derive(Clone, Debug, Default)]
+pub struct LookupOptions {
+    /// Ignore ASCII case, like unquoted SQL identifiers. A name matching exactly is preferred.
+    pub case_insensitive: bool,
+    /// Name (eg. table or alias) the attributes can be qualified with, `t` in `t.id`
+    pub qualifier: Option<String>,
+}
+
+impl LookupOptions {
+    /// Exact names only; same as `Schema::exists`
+    pub fn exact() -> LookupOptions {
+        LookupOptions::default()
+    }
+
+    /// Case insensitive lookup
+    pub fn ignore_case() -> LookupOptions {
+        LookupOptions { case_insensitive: true, qualifier: None }
+    }
+
+    pub fn qualified<S: Into<String>>(self, qualifier: S) -> LookupOptions {
+        LookupOptions { qualifier: Some(qualifier.into()), .. self }
+    }
+
+    fn eq(&self, lhs: &str, rhs: &str) -> bool {
+        if self.case_insensitive { lhs.eq_ignore_ascii_case(rhs) } else { lhs == rhs }
+    }
 }
 
 pub struct AttributeIter<'a> {
@@ -29,27 +74,128 @@ pub struct AttributeIter<'a> {
 }
 
 impl Attribute {
+    /// Create a attribute of a scalar (not nested) type
+    pub fn new<S: Into<String>>(name: S, nullable: bool, dtype: Type) -> Attribute {
+        Attribute::nested(name, nullable, dtype, Vec::new())
+    }
+
+    /// Attribute with the given children, without any validation
+    pub fn nested<S: Into<String>>(name: S, nullable: bool, dtype: Type, children: Vec<Attribute>) -> Attribute {
+        Attribute {
+            name: name.into(),
+            nullable: nullable,
+            dtype: dtype,
+            children: children,
+            field_id: None,
+            metadata: Metadata::new(),
+        }
+    }
+
+    /// Create a LIST attribute with elements described by `elem`
+    pub fn list<S: Into<String>>(name: S, nullable: bool, elem: Attribute) -> Attribute {
+        Attribute::nested(name, nullable, Type::LIST, vec!(elem))
+    }
+
+    /// Create a STRUCT attribute out of fields. Field names have to be unique.
+    pub fn structure<S: Into<String>>(name: S, nullable: bool, fields: Vec<Attribute>)
+        -> Result<Attribute, DBError>
+    {
+        index_names(fields.as_slice())?;
+        Ok(Attribute::nested(name, nullable, Type::STRUCT, fields))
+    }
+
+    /// Create a MAP attribute. Keys cannot be NULL.
+    pub fn map<S: Into<String>>(name: S, nullable: bool, key: Attribute, value: Attribute)
+        -> Result<Attribute, DBError>
+    {
+        if key.nullable {
+            return Err(DBError::AttributeNullability(key.name.clone()))
+        }
+
+        Ok(Attribute::nested(name, nullable, Type::MAP, vec!(key, value)))
+    }
+
+    pub fn with_field_id(self, id: i32) -> Attribute {
+        Attribute { field_id: Some(id), ..self }
+    }
+
+    /// Add (or replace) a metadata entry
+    pub fn with_metadata<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Attribute {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
     pub fn rename<S: Into<String>>(&self, name: S) -> Attribute {
-        Attribute { name: name.into(), nullable: self.nullable, dtype: self.dtype }
+        Attribute { name: name.into(), ..self.clone() }
     }
 
     /// Helper methods to create a the same named attribute but of different type
     pub fn cast(&self, cast: Type) -> Attribute {
-        Attribute { name: self.name.clone(), nullable: self.nullable, dtype: cast }
+        let children = if cast == self.dtype { self.children.clone() } else { Vec::new() };
+        Attribute { dtype: cast, children: children, ..self.clone() }
+    }
+
+    /// Element attribute of a LIST attribute
+    pub fn element(&self) -> Result<&Attribute, DBError> {
+        match (self.dtype, self.children.first()) {
+            (Type::LIST, Some(elem))    => Ok(elem),
+            _                           => Err(DBError::AttributeType(self.name.clone())),
+        }
+    }
+
+    /// Key and value attributes of a MAP attribute
+    pub fn map_entry(&self) -> Result<(&Attribute, &Attribute), DBError> {
+        match (self.dtype, self.children.len()) {
+            (Type::MAP, 2)  => Ok((&self.children[0], &self.children[1])),
+            _               => Err(DBError::AttributeType(self.name.clone())),
+        }
+    }
+
+    /// Find field of a STRUCT attribute by name
+    pub fn field(&self, name: &str) -> Result<&Attribute, DBError> {
+        if self.dtype != Type::STRUCT {
+            return Err(DBError::AttributeType(self.name.clone()))
+        }
+
+        self.children.iter()
+            .find(|f| f.name == name)
+            .ok_or(DBError::AttributeMissing(format!("(name: {}.{})", self.name, name)))
     }
 }
 
-impl Schema {
-    pub fn from_slice(attrs: &[Attribute]) -> Result<Schema, DBError> {
-        let mut names = HashSet::with_capacity(attrs.len());
+/// Name index of the attributes; the names have to be unique
+fn index_names(attrs: &[Attribute]) -> Result<HashMap<String, usize>, DBError> {
+    let mut index = HashMap::with_capacity(attrs.len());
 
-        for a in attrs {
-            if names.replace(a.name.clone()).is_some() {
-                return Err(DBError::AttributeDuplicate(a.name.clone()))
+    for (pos, a) in attrs.iter().enumerate() {
+        if index.insert(a.name.clone(), pos).is_some() {
+            return Err(DBError::AttributeDuplicate(a.name.clone()))
+        }
+    }
+
+    Ok(index)
+}
+
+fn check_unique_field_ids(attrs: &[Attribute]) -> Result<(), DBError> {
+    let mut ids = HashSet::with_capacity(attrs.len());
+
+    for a in attrs {
+        if let Some(id) = a.field_id {
+            if !ids.insert(id) {
+                return Err(DBError::AttributeDuplicate(format!("{} (field id: {})", a.name, id)))
             }
         }
+    }
 
-        Ok(Schema { attrs: Vec::from(attrs) })
+    Ok(())
+}
+
+impl Schema {
+    /// Attribute names and field ids (if set) have to be unique
+    pub fn from_slice(attrs: &[Attribute]) -> Result<Schema, DBError> {
+        let index = index_names(attrs)?;
+        check_unique_field_ids(attrs)?;
+        Ok(Schema { attrs: Vec::from(attrs), metadata: Metadata::new(), index: index })
     }
 
     pub fn from_vec(attrs: Vec<Attribute>) -> Result<Schema, DBError> {
@@ -58,12 +204,24 @@ impl Schema {
 
     /// Create a single Attribute schema from an external attribute
     pub fn from_attr(attr: Attribute) -> Schema {
-        Schema { attrs: vec!(attr) }
+        let mut index = HashMap::with_capacity(1);
+        index.insert(attr.name.clone(), 0);
+        Schema { attrs: vec!(attr), metadata: Metadata::new(), index: index }
+    }
+
+    /// Add (or replace) a schema level metadata entry
+    pub fn with_metadata<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Schema {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
     }
 
     /// Create a single Attribute schema
     pub fn make_one_attr<S: Into<String>>(name: S, nullable: bool, dtype: Type) -> Schema {
-        Schema::from_attr(Attribute{name: name.into(), nullable: nullable, dtype: dtype})
+        Schema::from_attr(Attribute::new(name, nullable, dtype))
     }
 
     pub fn count(&self) -> usize {
@@ -71,13 +229,7 @@ impl Schema {
     }
 
     pub fn exists(&self, name: &str) -> Option<usize> {
-        for pos in 0..self.attrs.len() {
-            if &self.attrs[pos].name == name {
-                return Some(pos)
-            }
-        }
-
-        None
+        self.index.get(name).cloned()
     }
 
     pub fn exists_ok(&self, name: &str) -> Result<usize, DBError> {
@@ -94,20 +246,456 @@ impl Schema {
     }
 
     pub fn find(&self, name: &str) -> Result<&Attribute, DBError> {
-        for attr in &self.attrs {
-            if &attr.name == name {
-                return Ok(attr)
+        self.exists_ok(name)
+            .map(|pos| &self.attrs[pos])
+    }
+
+    /// Resolve a, possibly qualified, attribute reference. In order:
+    ///
+    /// - attribute with the name, ignoring case if the options say so
+    /// - `qualifier.name`, the attribute `name` if `qualifier` is the options' qualifier
+    /// - `name`, attribute qualified with any prefix (`src.name`, eg. from
+    ///   `MultiSourceProjector::bind`)
+    ///
+    /// A reference matching more than one attribute is ambiguous, an error.
+    pub fn lookup(&self, name: &str, opts: &LookupOptions) -> Result<usize, DBError> {
+        if let Some(pos) = self.exists(name) {
+            return Ok(pos)
+        }
+
+        if let Some(pos) = self.lookup_unique(name, |a| opts.eq(a, name))? {
+            return Ok(pos)
+        }
+
+        match (name.find('.'), opts.qualifier.as_ref()) {
+            (Some(dot), Some(qualifier)) => {
+                let column = &name[dot + 1 ..];
+                if opts.eq(&name[.. dot], qualifier) {
+                    if let Some(pos) = self.lookup_unique(name, |a| opts.eq(a, column))? {
+                        return Ok(pos)
+                    }
+                }
             }
+            (None, _) => {
+                let qualified = |a: &str| a.len() > name.len() + 1
+                    && a.as_bytes()[a.len() - name.len() - 1] == b'.'
+                    && opts.eq(&a[a.len() - name.len() ..], name);
+
+                if let Some(pos) = self.lookup_unique(name, qualified)? {
+                    return Ok(pos)
+                }
+            }
+            _ => (),
         }
 
         Err(DBError::AttributeMissing(format!("(name: {})", name)))
     }
 
+    /// Position of the only attribute matching
+    fn lookup_unique<F: Fn(&str) -> bool>(&self, name: &str, matches: F) -> Result<Option<usize>, DBError> {
+        let mut found = None;
+
+        for (pos, attr) in self.attrs.iter().enumerate() {
+            if matches(&attr.name) {
+                if found.is_some() {
+                    return Err(DBError::AttributeAmbiguous(name.to_string()))
+                }
+                found = Some(pos);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Position of the attribute with the field id
+    pub fn find_field_id(&self, id: i32) -> Option<usize> {
+        self.attrs.iter().position(|a| a.field_id == Some(id))
+    }
+
     pub fn iter(&self) -> AttributeIter {
         AttributeIter { schema: self, cur: 0 }
     }
 }
 
+/// JSON representation:
+///
+/// ```text
+/// {"attributes": [{"name": "a", "type": "UINT32", "nullable": false, "field_id": 1,
+///                  "metadata": {"k": "v"}, "children": [...]}, ...],
+///  "metadata": {...}}
+/// ```
+///
+/// `field_id`, `metadata` and `children` are left out when empty.
+impl Schema {
+    pub fn to_json(&self) -> String {
+        let attrs: Vec<String> = self.attrs.iter().map(attr_to_json).collect();
+        let mut out = format!("{{\"attributes\":[{}]", attrs.join(","));
+        if !self.metadata.is_empty() {
+            out.push_str(&format!(",\"metadata\":{}", metadata_to_json(&self.metadata)));
+        }
+        out.push('}');
+        out
+    }
+
+    pub fn from_json(doc: &str) -> Result<Schema, DBError> {
+        Schema::from_json_value(&json::parse(doc)?)
+    }
+
+    /// Schema from an already parsed document, eg. embedded in a larger one
+    pub fn from_json_value(doc: &JsonValue) -> Result<Schema, DBError> {
+        let attrs = doc.get("attributes")
+            .and_then(|a| a.as_array())
+            .ok_or_else(|| DBError::Schema("missing attributes".to_string()))?;
+
+        let attrs = attrs.iter()
+            .map(attr_from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut schema = Schema::from_vec(attrs)?;
+        schema.metadata = metadata_from_json(doc.get("metadata"))?;
+        Ok(schema)
+    }
+
+    /// Parse a column list such as `a UINT32 NOT NULL, b TEXT, c LIST<INT64 NOT NULL>`.
+    ///
+    /// Columns are nullable unless `NOT NULL`. Nested types are `LIST<type>`, `MAP<key, value>`
+    /// (keys are never NULL) and `STRUCT<name type, ...>`; the children are named `element`,
+    /// `key` and `value`. Names can be double quoted. Type names and keywords are case
+    /// insensitive.
+    pub fn parse_ddl(ddl: &str) -> Result<Schema, DBError> {
+        let mut parser = DdlParser { src: ddl, pos: 0 };
+        let attrs = parser.columns()?;
+
+        parser.ws();
+        if parser.pos != ddl.len() {
+            return Err(parser.error("expected ','"))
+        }
+
+        Schema::from_vec(attrs)
+    }
+}
+
+fn metadata_to_json(metadata: &Metadata) -> String {
+    let entries: Vec<String> = metadata.iter()
+        .map(|(k, v)| format!("{}:{}", json::quote(k), json::quote(v)))
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+fn metadata_from_json(value: Option<&JsonValue>) -> Result<Metadata, DBError> {
+    let entries = match value {
+        Some(v) => v.as_object().ok_or_else(|| DBError::Schema("metadata has to be an object".to_string()))?,
+        None    => return Ok(Metadata::new()),
+    };
+
+    entries.iter()
+        .map(|&(ref k, ref v)| v.as_str()
+            .map(|v| (k.clone(), v.to_string()))
+            .ok_or_else(|| DBError::Schema(format!("metadata {} has to be a string", k))))
+        .collect()
+}
+
+fn attr_to_json(attr: &Attribute) -> String {
+    let mut out = format!("{{\"name\":{},\"type\":\"{}\",\"nullable\":{}",
+                          json::quote(&attr.name), attr.dtype.name(), attr.nullable);
+
+    if let Some(id) = attr.field_id {
+        out.push_str(&format!(",\"field_id\":{}", id));
+    }
+    if !attr.metadata.is_empty() {
+        out.push_str(&format!(",\"metadata\":{}", metadata_to_json(&attr.metadata)));
+    }
+    if !attr.children.is_empty() {
+        let children: Vec<String> = attr.children.iter().map(attr_to_json).collect();
+        out.push_str(&format!(",\"children\":[{}]", children.join(",")));
+    }
+
+    out.push('}');
+    out
+}
+
+fn attr_from_json(value: &JsonValue) -> Result<Attribute, DBError> {
+    let field = |key: &str| value.get(key)
+        .ok_or_else(|| DBError::Schema(format!("attribute missing {}", key)));
+
+    let name = field("name")?.as_str()
+        .ok_or_else(|| DBError::Schema("attribute name has to be a string".to_string()))?;
+    let dtype: Type = field("type")?.as_str()
+        .ok_or_else(|| DBError::Schema(format!("{}: type has to be a string", name)))?
+        .parse()?;
+    let nullable = value.get("nullable").map_or(Some(true), |v| v.as_bool())
+        .ok_or_else(|| DBError::Schema(format!("{}: nullable has to be a boolean", name)))?;
+
+    let children = match value.get("children") {
+        Some(c) => c.as_array()
+            .ok_or_else(|| DBError::Schema(format!("{}: children has to be an array", name)))?
+            .iter()
+            .map(attr_from_json)
+            .collect::<Result<Vec<_>, _>>()?,
+        None    => Vec::new(),
+    };
+
+    let mut attr = make_attr(name, nullable, dtype, children)?;
+
+    attr.field_id = match value.get("field_id") {
+        Some(id) => Some(id.as_f64()
+            .filter(|id| id.fract() == 0.0 && *id >= i32::min_value() as f64 && *id <= i32::max_value() as f64)
+            .ok_or_else(|| DBError::Schema(format!("{}: invalid field_id", name)))? as i32),
+        None     => None,
+    };
+    attr.metadata = metadata_from_json(value.get("metadata"))?;
+    Ok(attr)
+}
+
+/// Attribute with validated children
+fn make_attr(name: &str, nullable: bool, dtype: Type, mut children: Vec<Attribute>) -> Result<Attribute, DBError> {
+    let expected = match dtype {
+        Type::LIST      => 1,
+        Type::MAP       => 2,
+        Type::STRUCT    => children.len(),
+        _               => 0,
+    };
+
+    if children.len() != expected || (dtype == Type::STRUCT && children.is_empty()) {
+        return Err(DBError::Schema(format!("{}: {} with {} children", name, dtype.name(), children.len())))
+    }
+
+    match dtype {
+        Type::LIST      => Ok(Attribute::list(name, nullable, children.remove(0))),
+        Type::STRUCT    => Attribute::structure(name, nullable, children),
+        Type::MAP       => {
+            let value = children.remove(1);
+            Attribute::map(name, nullable, children.remove(0), value)
+        }
+        _               => Ok(Attribute::new(name, nullable, dtype)),
+    }
+}
+
+struct DdlParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> DdlParser<'a> {
+    fn error(&self, msg: &str) -> DBError {
+        DBError::Schema(format!("{} at offset {}", msg, self.pos))
+    }
+
+    fn ws(&mut self) {
+        let rest = &self.src[self.pos ..];
+        self.pos += rest.len() - rest.trim_left().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.ws();
+        self.src[self.pos ..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DBError> {
+        if !self.eat(c) {
+            return Err(self.error(&format!("expected '{}'", c)))
+        }
+        Ok(())
+    }
+
+    /// Bare word (identifier or keyword)
+    fn word(&mut self) -> Option<&'a str> {
+        self.ws();
+        let rest = &self.src[self.pos ..];
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if len == 0 {
+            return None
+        }
+
+        self.pos += len;
+        Some(&rest[.. len])
+    }
+
+    /// Is the next word the `keyword`; consumed if it is
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        match self.word() {
+            Some(w) if w.eq_ignore_ascii_case(keyword)  => true,
+            _                                           => { self.pos = start; false }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, DBError> {
+        if self.peek() != Some('"') {
+            return self.word()
+                .map(|w| w.to_string())
+                .ok_or_else(|| self.error("expected name"))
+        }
+
+        // Quoted, "" is an escaped quote
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.src[self.pos ..];
+            let end = rest.find('"').ok_or_else(|| self.error("unterminated name"))?;
+            out.push_str(&rest[.. end]);
+            self.pos += end + 1;
+
+            if !self.src[self.pos ..].starts_with('"') {
+                return Ok(out)
+            }
+            out.push('"');
+            self.pos += 1;
+        }
+    }
+
+    /// `[NOT] NULL`, or `default` if absent
+    fn nullable(&mut self, default: bool) -> Result<bool, DBError> {
+        if self.keyword("NOT") {
+            if !self.keyword("NULL") {
+                return Err(self.error("expected NULL"))
+            }
+            return Ok(false)
+        }
+
+        Ok(self.keyword("NULL") || default)
+    }
+
+    fn columns(&mut self) -> Result<Vec<Attribute>, DBError> {
+        let mut out = vec![self.column()?];
+        while self.eat(',') {
+            out.push(self.column()?);
+        }
+        Ok(out)
+    }
+
+    fn column(&mut self) -> Result<Attribute, DBError> {
+        let name = self.name()?;
+        self.attr(&name, true)
+    }
+
+    /// Type and nullability of an attribute
+    fn attr(&mut self, name: &str, default_nullable: bool) -> Result<Attribute, DBError> {
+        let start = self.pos;
+        let dtype: Type = self.word()
+            .ok_or_else(|| self.error("expected type"))?
+            .to_uppercase()
+            .parse()
+            .map_err(|_| { self.pos = start; self.error("unknown type") })?;
+
+        let children = match dtype {
+            Type::LIST      => {
+                self.expect('<')?;
+                let elem = self.attr("element", true)?;
+                self.expect('>')?;
+                vec![elem]
+            }
+            Type::MAP       => {
+                self.expect('<')?;
+                let key = self.attr("key", false)?;
+                self.expect(',')?;
+                let value = self.attr("value", true)?;
+                self.expect('>')?;
+                vec![key, value]
+            }
+            Type::STRUCT    => {
+                self.expect('<')?;
+                let fields = self.columns()?;
+                self.expect('>')?;
+                fields
+            }
+            _               => Vec::new(),
+        };
+
+        let nullable = self.nullable(default_nullable)?;
+        make_attr(name, nullable, dtype, children)
+    }
+}
+
+/// The name index is derived from the attributes
+impl PartialEq for Schema {
+    fn eq(&self, other: &Schema) -> bool {
+        self.attrs == other.attrs && self.metadata == other.metadata
+    }
+}
+
+impl fmt::Debug for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Schema")
+            .field("attrs", &self.attrs)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+/// Attribute in the `Schema::parse_ddl` format, eg. `a LIST<INT64 NOT NULL> NOT NULL`
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_name(&self.name, f)?;
+        f.write_str(" ")?;
+        fmt_type(self, f)?;
+        if !self.nullable {
+            f.write_str(" NOT NULL")?;
+        }
+        Ok(())
+    }
+}
+
+/// Column list in the `Schema::parse_ddl` format
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, attr) in self.attrs.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", attr)?;
+        }
+        Ok(())
+    }
+}
+
+/// Names that aren't plain identifiers are quoted
+fn fmt_name(name: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return f.write_str(name)
+    }
+
+    write!(f, "\"{}\"", name.replace('"', "\"\""))
+}
+
+fn fmt_type(attr: &Attribute, f: &mut fmt::Formatter) -> fmt::Result {
+    match (attr.dtype, attr.children.len()) {
+        (Type::LIST, 1)     => {
+            let elem = &attr.children[0];
+            f.write_str("LIST<")?;
+            fmt_type(elem, f)?;
+            f.write_str(if elem.nullable { ">" } else { " NOT NULL>" })
+        }
+        (Type::MAP, 2)      => {
+            let value = &attr.children[1];
+            f.write_str("MAP<")?;
+            fmt_type(&attr.children[0], f)?;
+            f.write_str(", ")?;
+            fmt_type(value, f)?;
+            f.write_str(if value.nullable { ">" } else { " NOT NULL>" })
+        }
+        (Type::STRUCT, _)   => {
+            f.write_str("STRUCT<")?;
+            for (idx, field) in attr.children.iter().enumerate() {
+                if idx > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", field)?;
+            }
+            f.write_str(">")
+        }
+        (dtype, _)          => f.write_str(dtype.name()),
+    }
+}
+
 /// Address schema attributes by their index
 impl Index<usize> for Schema {
     type Output = Attribute;
@@ -132,3 +720,105 @@ impl<'a> Iterator for AttributeIter<'a> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_and_field_ids() {
+        let id = Attribute::new("id", false, Type::UINT32)
+            .with_field_id(1)
+            .with_metadata("origin", "orders.csv");
+        let name = Attribute::new("name", true, Type::TEXT).with_field_id(2);
+
+        // Carried through renames and casts
+        let renamed = id.rename("order_id").cast(Type::UINT64);
+        assert_eq!(renamed.field_id, Some(1));
+        assert_eq!(renamed.metadata.get("origin").map(|v| v.as_str()), Some("orders.csv"));
+
+        let schema = Schema::from_vec(vec![renamed, name.clone()]).unwrap()
+            .with_metadata("writer", "dbkit");
+        assert_eq!(schema.find_field_id(2), Some(1));
+        assert_eq!(schema.find_field_id(3), None);
+        assert_eq!(schema.metadata().len(), 1);
+
+        assert!(Schema::from_vec(vec![id, name.rename("other").with_field_id(1)]).is_err());
+    }
+
+    #[test]
+    fn lookup() {
+        let schema = Schema::parse_ddl("id UINT32, Name TEXT, NAME TEXT, \"a.v\" INT32, \"b.v\" INT32, \"b.w\" INT32").unwrap();
+        let exact = LookupOptions::exact();
+        let sql = LookupOptions::ignore_case().qualified("T");
+
+        assert_eq!(schema.lookup("id", &exact).unwrap(), 0);
+        assert!(schema.lookup("ID", &exact).is_err());
+        assert_eq!(schema.lookup("ID", &sql).unwrap(), 0);
+        assert_eq!(schema.lookup("t.Id", &sql).unwrap(), 0);
+        assert!(schema.lookup("t.id", &exact.clone().qualified("T")).is_err());
+        assert!(schema.lookup("u.id", &sql).is_err());
+
+        // Exact match wins, otherwise ambiguous
+        assert_eq!(schema.lookup("NAME", &sql).unwrap(), 2);
+        match schema.lookup("name", &sql) {
+            Err(DBError::AttributeAmbiguous(_)) => (),
+            _                                   => panic!("expected ambiguous name"),
+        }
+
+        // Qualified attribute names
+        assert_eq!(schema.lookup("B.V", &sql).unwrap(), 4);
+        assert_eq!(schema.lookup("w", &exact).unwrap(), 5);
+        assert!(schema.lookup("v", &exact).is_err());
+    }
+
+    #[test]
+    fn ddl() {
+        let schema = Schema::parse_ddl(r#"a UINT32 NOT NULL, "b c" text,
+            l LIST<INT64 NOT NULL> NOT NULL, m map<TEXT, JSON>, s STRUCT<x FLOAT64, y BOOLEAN NOT NULL>"#).unwrap();
+
+        let attrs: Vec<_> = schema.iter().map(|a| (a.name.as_str(), a.dtype, a.nullable)).collect();
+        assert_eq!(attrs, vec![
+            ("a", Type::UINT32, false), ("b c", Type::TEXT, true), ("l", Type::LIST, false),
+            ("m", Type::MAP, true), ("s", Type::STRUCT, true),
+        ]);
+
+        assert!(!schema[2].element().unwrap().nullable);
+        let (key, value) = schema[3].map_entry().unwrap();
+        assert_eq!((key.name.as_str(), key.nullable, value.dtype), ("key", false, Type::JSON));
+        assert!(!schema[4].field("y").unwrap().nullable);
+
+        for bad in &["", "a", "a INT8", "a UINT32 NOT", "a UINT32 b TEXT", "a LIST<TEXT",
+                     "a MAP<TEXT NULL, TEXT>", "a TEXT, a TEXT", "\"a TEXT"] {
+            assert!(Schema::parse_ddl(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let ddl = r#"a UINT32 NOT NULL, "b ""c""" TEXT, l LIST<INT64 NOT NULL> NOT NULL, m MAP<TEXT, JSON>, s STRUCT<x FLOAT64, y BOOLEAN NOT NULL>"#;
+        let schema = Schema::parse_ddl(ddl).unwrap();
+
+        assert_eq!(schema.to_string(), ddl);
+        assert_eq!(schema[2].to_string(), "l LIST<INT64 NOT NULL> NOT NULL");
+        assert!(format!("{:?}", schema).contains("dtype: UINT32"));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let schema = Schema::parse_ddl("a UINT32 NOT NULL, \"q\"\"\" TEXT, l LIST<STRUCT<x INT32, y BLOB>>").unwrap()
+            .with_metadata("table", "t\"1");
+        let mut attrs: Vec<Attribute> = schema.iter().cloned().collect();
+        attrs[0] = attrs[0].clone().with_field_id(-4).with_metadata("k", "v");
+        let schema = Schema { metadata: schema.metadata.clone(), .. Schema::from_vec(attrs).unwrap() };
+
+        let doc = schema.to_json();
+        assert!(doc.starts_with(r#"{"attributes":[{"name":"a","type":"UINT32","nullable":false,"field_id":-4"#));
+        assert!(Schema::from_json(&doc).unwrap() == schema);
+
+        assert!(Schema::from_json(r#"{"attributes": [{"name": "a", "type": "INT32"}]}"#).unwrap()[0].nullable);
+        assert!(Schema::from_json(r#"{"attributes": [{"name": "a", "type": "LIST"}]}"#).is_err());
+        assert!(Schema::from_json(r#"{"attributes": [{"name": "a", "type": "INT8"}]}"#).is_err());
+        assert!(Schema::from_json(r#"{"attrs": []}"#).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+// vim: set ts=4 sw=4 et :
+
+//! SQL tokenizer.
+
+use ::error::DBError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// Unquoted identifier or keyword, as written
+    WORD(String),
+    /// `"quoted"` identifier
+    QUOTED(String),
+    /// `'text'` literal
+    STRING(String),
+    /// Numeric literal, as written
+    NUMBER(String),
+    /// Operator or punctuation: `, . ( ) * - = <> != < <= > >=`
+    SYMBOL(&'static str),
+}
+
+const SYMBOLS: &'static [&'static str] = &["<>", "!=", "<=", ">=", ",", ".", "(", ")", "*", "-", "=", "<", ">", ";"];
+
+/// Tokens with their offsets in the source
+pub fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, DBError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < src.len() {
+        let rest = &src[pos ..];
+        let c = rest.chars().next().unwrap();
+
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue
+        }
+
+        if rest.starts_with("--") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            continue
+        }
+
+        let start = pos;
+        let token = if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            pos += len;
+            Token::WORD(rest[.. len].to_string())
+        } else if c.is_digit(10) {
+            let mut len = rest.find(|c: char| !c.is_digit(10)).unwrap_or(rest.len());
+            if rest[len ..].starts_with('.') {
+                len += 1;
+                len += rest[len ..].find(|c: char| !c.is_digit(10)).unwrap_or(rest.len() - len);
+            }
+            pos += len;
+            Token::NUMBER(rest[.. len].to_string())
+        } else if c == '\'' || c == '"' {
+            // Doubled quote is an escaped quote
+            let mut text = String::new();
+            pos += 1;
+            loop {
+                let rest = &src[pos ..];
+                let end = rest.find(c)
+                    .ok_or_else(|| DBError::SQL(format!("unterminated quote at offset {}", start)))?;
+                text.push_str(&rest[.. end]);
+                pos += end + 1;
+
+                if !src[pos ..].starts_with(c) {
+                    break
+                }
+                text.push(c);
+                pos += 1;
+            }
+            if c == '\'' { Token::STRING(text) } else { Token::QUOTED(text) }
+        } else {
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(s) => { pos += s.len(); Token::SYMBOL(s) }
+                None    => return Err(DBError::SQL(format!("unexpected '{}' at offset {}", c, pos))),
+            }
+        };
+
+        out.push((token, start));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<_> = tokenize("SELECT a.\"b c\", 'it''s' -- comment\n FROM t WHERE x<>1.5 AND y>-2")
+            .unwrap()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+
+        assert_eq!(tokens, vec![
+            Token::WORD("SELECT".to_string()), Token::WORD("a".to_string()), Token::SYMBOL("."),
+            Token::QUOTED("b c".to_string()), Token::SYMBOL(","), Token::STRING("it's".to_string()),
+            Token::WORD("FROM".to_string()), Token::WORD("t".to_string()), Token::WORD("WHERE".to_string()),
+            Token::WORD("x".to_string()), Token::SYMBOL("<>"), Token::NUMBER("1.5".to_string()),
+            Token::WORD("AND".to_string()), Token::WORD("y".to_string()), Token::SYMBOL(">"), Token::SYMBOL("-"),
+            Token::NUMBER("2".to_string()),
+        ]);
+
+        assert!(tokenize("SELECT 'open").is_err());
+        assert!(tokenize("SELECT a ? b").is_err());
+    }
+}
@@ -0,0 +1,439 @@
+// vim: set ts=4 sw=4 et :
+
+//! SQL front-end (`sql` feature).
+//!
+//! Parses a subset of SELECT into a `LogicalPlan`:
+//!
+//! ```text
+//! SELECT * | expr [[AS] alias], ...
+//! FROM table [[AS] alias]
+//!     [[INNER | LEFT [OUTER]] JOIN table [[AS] alias] ON a = b [AND ...]] ...
+//! [WHERE expr]
+//! [GROUP BY column, ...]
+//! [ORDER BY output column | ordinal [ASC | DESC], ...]
+//! [LIMIT n] [OFFSET n]
+//! ```
+//!
+//! Expressions are column references, literals, comparisons, `AND`, `OR` and `NOT`; the
+//! aggregates are `COUNT(*)`, `COUNT`, `SUM`, `MIN` and `MAX` of a column. Unquoted names are
+//! matched ignoring case. Joined tables' columns are named `table.column` (or `alias.column`).
+
+use std::sync::Arc;
+
+use ::catalog::Catalog;
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::plan::{Aggregate, AggregateFunc, JoinKind, LogicalPlan, ScalarExpr, SortKey};
+use ::schema::{LookupOptions, Schema};
+use ::types::{Type, Value};
+
+mod lexer;
+mod parser;
+
+use self::parser::{Expr, Select, SelectItem, TableRef, parse_select};
+
+/// Logical plan of the SELECT statement over the `catalog` tables
+pub fn parse(sql: &str, catalog: &Arc<Catalog>) -> Result<LogicalPlan, DBError> {
+    let select = parse_select(sql)?;
+    plan_select(&select, catalog)
+}
+
+/// Name resolution scope: the schema of a plan node
+struct Scope {
+    schema: Schema,
+    opts: LookupOptions,
+}
+
+impl Scope {
+    fn new(plan: &LogicalPlan, opts: LookupOptions) -> Result<Scope, DBError> {
+        Ok(Scope { schema: plan.schema()?, opts: opts })
+    }
+
+    fn column(&self, qualifier: &Option<String>, name: &str) -> Result<usize, DBError> {
+        match *qualifier {
+            Some(ref q) => self.schema.lookup(&format!("{}.{}", q, name), &self.opts),
+            None        => self.schema.lookup(name, &self.opts),
+        }
+    }
+
+    /// Scalar expression; aggregates aren't allowed
+    fn bind(&self, expr: &Expr) -> Result<ScalarExpr, DBError> {
+        Ok(match *expr {
+            Expr::COLUMN(ref q, ref name)   => ScalarExpr::COLUMN(self.column(q, name)?),
+            Expr::LITERAL(ref v)            => ScalarExpr::LITERAL(v.clone()),
+            Expr::AND(ref l, ref r)         => ScalarExpr::and(self.bind(l)?, self.bind(r)?),
+            Expr::OR(ref l, ref r)          => ScalarExpr::or(self.bind(l)?, self.bind(r)?),
+            Expr::NOT(ref e)                => ScalarExpr::not(self.bind(e)?),
+            Expr::COMPARE(op, ref l, ref r) => {
+                let (l, r) = (self.bind(l)?, self.bind(r)?);
+                let (ltype, rtype) = (self.column_type(&l), self.column_type(&r));
+                ScalarExpr::compare(op, coerce(l, rtype), coerce(r, ltype))
+            }
+            Expr::CALL(ref name, _)         => return Err(DBError::SQL(format!("{} not allowed here", name))),
+        })
+    }
+
+    fn column_type(&self, expr: &ScalarExpr) -> Option<Type> {
+        match *expr {
+            ScalarExpr::COLUMN(pos) => self.schema.get(pos).map(|a| a.dtype).ok(),
+            _                       => None,
+        }
+    }
+}
+
+/// Literal compared with a column converted to the column type, if it fits; otherwise left as is
+fn coerce(expr: ScalarExpr, dtype: Option<Type>) -> ScalarExpr {
+    let (value, dtype) = match (expr, dtype) {
+        (ScalarExpr::LITERAL(v), Some(dtype))   => (v, dtype),
+        (expr, _)                               => return expr,
+    };
+
+    let coerced = match (&value, dtype) {
+        (&Value::INT64(v), Type::INT32) if v as i32 as i64 == v     => Some(Value::INT32(v as i32)),
+        (&Value::INT64(v), Type::UINT32) if v as u32 as i64 == v    => Some(Value::UINT32(v as u32)),
+        (&Value::INT64(v), Type::UINT64) if v >= 0                  => Some(Value::UINT64(v as u64)),
+        (&Value::INT64(v), Type::FLOAT32)                           => Some(Value::FLOAT32(v as f32)),
+        (&Value::INT64(v), Type::FLOAT64)                           => Some(Value::FLOAT64(v as f64)),
+        (&Value::FLOAT64(v), Type::FLOAT32)                         => Some(Value::FLOAT32(v as f32)),
+        (&Value::TEXT(ref v), Type::JSON)                           => Some(Value::JSON(v.clone())),
+        _                                                           => None,
+    };
+    ScalarExpr::LITERAL(coerced.unwrap_or(value))
+}
+
+fn plan_select(select: &Select, catalog: &Arc<Catalog>) -> Result<LogicalPlan, DBError> {
+    let (mut plan, opts) = plan_from(select, catalog)?;
+
+    if let Some(ref filter) = select.filter {
+        let predicate = Scope::new(&plan, opts.clone())?.bind(filter)?;
+        plan = plan.filter(predicate);
+    }
+
+    let aggregating = !select.group_by.is_empty() || select.items.iter().any(|i| match *i {
+        SelectItem::EXPR(Expr::CALL(..), _) => true,
+        _                                   => false,
+    });
+
+    plan = if aggregating {
+        plan_aggregate(select, plan, opts)?
+    } else {
+        plan_project(select, plan, opts)?
+    };
+
+    if !select.order_by.is_empty() {
+        let scope = Scope::new(&plan, LookupOptions::ignore_case())?;
+        let mut keys = Vec::with_capacity(select.order_by.len());
+
+        for &(ref expr, ascending) in &select.order_by {
+            let column = match *expr {
+                Expr::LITERAL(Value::INT64(n)) if n >= 1 && (n as usize) <= scope.schema.count() =>
+                    n as usize - 1,
+                Expr::COLUMN(ref q, ref name) =>
+                    scope.column(q, name)?,
+                _ =>
+                    return Err(DBError::SQL("ORDER BY has to be an output column or its ordinal".to_string())),
+            };
+            keys.push(SortKey { column: column, ascending: ascending });
+        }
+
+        plan = plan.sort(keys);
+    }
+
+    if select.limit.is_some() || select.offset.is_some() {
+        let offset = select.offset.unwrap_or(0) as usize;
+        plan = plan.limit(offset, select.limit.map(|l| l as usize));
+    }
+
+    Ok(plan)
+}
+
+/// Tables & joins. Columns of joined tables are renamed to `qualifier.name`.
+fn plan_from(select: &Select, catalog: &Arc<Catalog>) -> Result<(LogicalPlan, LookupOptions), DBError> {
+    fn qualifier(table: &TableRef) -> &str {
+        table.alias.as_ref().unwrap_or(&table.name)
+    }
+
+    fn qualified(table: &TableRef, catalog: &Arc<Catalog>) -> Result<LogicalPlan, DBError> {
+        let scan = LogicalPlan::scan_catalog(catalog, &table.name)?;
+        let exprs: Vec<_> = scan.schema()?.iter()
+            .enumerate()
+            .map(|(pos, a)| (ScalarExpr::COLUMN(pos), format!("{}.{}", qualifier(table), a.name)))
+            .collect();
+        Ok(scan.project(exprs))
+    }
+
+    if select.joins.is_empty() {
+        let plan = LogicalPlan::scan_catalog(catalog, &select.from.name)?;
+        return Ok((plan, LookupOptions::ignore_case().qualified(qualifier(&select.from))))
+    }
+
+    let mut plan = qualified(&select.from, catalog)?;
+
+    for join in &select.joins {
+        let right = qualified(&join.table, catalog)?;
+        let width = plan.schema()?.count();
+
+        let joined = plan.join(right, join.kind, Vec::new());
+        let predicate = Scope::new(&joined, LookupOptions::ignore_case())?.bind(&join.on)?;
+
+        let (left, right) = match joined {
+            LogicalPlan::JOIN { left, right, .. } => (left, right),
+            _ => unreachable!(),
+        };
+
+        // Equalities of a left and a right column are the join keys
+        let (mut on, mut rest) = (Vec::new(), Vec::new());
+        for c in predicate.conjuncts() {
+            let key = match c {
+                ScalarExpr::COMPARE(CompareOp::EQ, ref l, ref r) => match (&**l, &**r) {
+                    (&ScalarExpr::COLUMN(l), &ScalarExpr::COLUMN(r)) if l < width && r >= width => Some((l, r - width)),
+                    (&ScalarExpr::COLUMN(l), &ScalarExpr::COLUMN(r)) if r < width && l >= width => Some((r, l - width)),
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            match key {
+                Some(key)   => on.push(key),
+                None        => rest.push(c),
+            }
+        }
+
+        if on.is_empty() {
+            return Err(DBError::SQL(format!("JOIN {} needs an equality of the joined columns", join.table.name)))
+        }
+
+        plan = (*left).join(*right, join.kind, on);
+
+        if let Some(rest) = ScalarExpr::conjunction(rest) {
+            if join.kind != JoinKind::INNER {
+                return Err(DBError::SQL(format!("unsupported LEFT JOIN condition: {}", rest)))
+            }
+            plan = plan.filter(rest);
+        }
+    }
+
+    Ok((plan, LookupOptions::ignore_case()))
+}
+
+fn plan_project(select: &Select, plan: LogicalPlan, opts: LookupOptions) -> Result<LogicalPlan, DBError> {
+    let scope = Scope::new(&plan, opts)?;
+    let mut exprs = Vec::new();
+
+    for item in &select.items {
+        match *item {
+            SelectItem::ALL => {
+                for (pos, attr) in scope.schema.iter().enumerate() {
+                    exprs.push((ScalarExpr::COLUMN(pos), attr.name.clone()));
+                }
+            }
+            SelectItem::EXPR(ref expr, ref alias) => {
+                let name = output_name(expr, alias, exprs.len());
+                exprs.push((scope.bind(expr)?, name));
+            }
+        }
+    }
+
+    Ok(plan.project(exprs))
+}
+
+fn plan_aggregate(select: &Select, plan: LogicalPlan, opts: LookupOptions) -> Result<LogicalPlan, DBError> {
+    let scope = Scope::new(&plan, opts)?;
+
+    let mut group_by = Vec::with_capacity(select.group_by.len());
+    for expr in &select.group_by {
+        match *expr {
+            Expr::COLUMN(ref q, ref name)   => group_by.push(scope.column(q, name)?),
+            _                               => return Err(DBError::SQL("GROUP BY has to be a column".to_string())),
+        }
+    }
+
+    let mut aggregates = Vec::new();
+    let mut exprs = Vec::new();
+
+    for item in &select.items {
+        let (expr, alias) = match *item {
+            SelectItem::EXPR(ref expr, ref alias)   => (expr, alias),
+            SelectItem::ALL                         => return Err(DBError::SQL("* in an aggregate query".to_string())),
+        };
+
+        let name = output_name(expr, alias, exprs.len());
+
+        let column = match *expr {
+            Expr::COLUMN(ref q, ref column) => {
+                let pos = scope.column(q, column)?;
+                group_by.iter().position(|&g| g == pos)
+                    .ok_or_else(|| DBError::SQL(format!("{} has to be in GROUP BY or aggregated", column)))?
+            }
+            Expr::CALL(ref func, ref arg) => {
+                let func = match func.to_uppercase().as_str() {
+                    "COUNT"     => AggregateFunc::COUNT,
+                    "SUM"       => AggregateFunc::SUM,
+                    "MIN"       => AggregateFunc::MIN,
+                    "MAX"       => AggregateFunc::MAX,
+                    "MEDIAN"    => AggregateFunc::MEDIAN,
+                    "FIRST"     => AggregateFunc::FIRST,
+                    "LAST"      => AggregateFunc::LAST,
+                    _           => return Err(DBError::SQL(format!("unknown aggregate function {}", func))),
+                };
+
+                let column = match (arg.as_ref().map(|a| &**a), func) {
+                    (Some(&Expr::COLUMN(ref q, ref column)), _) => Some(scope.column(q, column)?),
+                    (None, AggregateFunc::COUNT)                => None,
+                    _ => return Err(DBError::SQL(format!("{} argument has to be a column", func.name()))),
+                };
+
+                aggregates.push(Aggregate { func: func, column: column, by: None, name: name.clone() });
+                group_by.len() + aggregates.len() - 1
+            }
+            _ => return Err(DBError::SQL("aggregate query can only select group by columns and aggregates".to_string())),
+        };
+
+        exprs.push((ScalarExpr::COLUMN(column), name));
+    }
+
+    Ok(plan.aggregate(group_by, aggregates).project(exprs))
+}
+
+/// Alias, column name, lowercase function name or `_c<position>`
+fn output_name(expr: &Expr, alias: &Option<String>, pos: usize) -> String {
+    match (alias, expr) {
+        (&Some(ref alias), _)               => alias.clone(),
+        (_, &Expr::COLUMN(_, ref name))     => name.clone(),
+        (_, &Expr::CALL(ref name, _))       => name.to_lowercase(),
+        _                                   => format!("_c{}", pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View, column_row_data, column_value};
+    use ::exec::ExecContext;
+    use ::operation::CursorChunk;
+    use ::plan::{lower, optimize};
+    use ::types::Int32;
+    use ::util::copy_value::set_column_value;
+
+    fn catalog() -> Arc<Catalog<'static>> {
+        let catalog = Arc::new(Catalog::new());
+
+        let schema = Schema::parse_ddl("id INT32 NOT NULL, customer TEXT NOT NULL, amount INT32").unwrap();
+        let mut orders = Block::new(&allocator::GLOBAL, &schema);
+        orders.add_rows(10).unwrap();
+        for row in 0 .. 10 {
+            set_column_value(&mut orders, 0, row, &Value::INT32(row as i32)).unwrap();
+            set_column_value(&mut orders, 1, row, &Value::from(if row % 2 == 0 { "a" } else { "b" })).unwrap();
+            set_column_value(&mut orders, 2, row, &Value::INT32(row as i32 * 10)).unwrap();
+        }
+        catalog.register("orders", orders).unwrap();
+
+        let schema = Schema::parse_ddl("name TEXT NOT NULL, city TEXT").unwrap();
+        let customers = Block::new(&allocator::GLOBAL, &schema);
+        catalog.register("customers", customers).unwrap();
+
+        catalog
+    }
+
+    #[test]
+    fn query() {
+        let catalog = catalog();
+        let plan = parse("SELECT amount AS a, ID FROM orders o WHERE o.amount >= 20 AND 70 > amount \
+                          ORDER BY 2 DESC LIMIT 3 OFFSET 1", &catalog).unwrap();
+
+        assert_eq!(plan.to_string(),
+                   "Limit offset=1 limit=3\n\
+                   \x20 Sort [#1 DESC]\n\
+                   \x20   Project [#2 AS a, #0 AS ID]\n\
+                   \x20     Filter (#2 >= 20 AND 70 > #2)\n\
+                   \x20       Scan orders [0, 1, 2]\n");
+
+        // NOT isn't a scan predicate, it's evaluated by the row
+        let plan = parse("SELECT id, amount FROM orders WHERE amount < 50 AND NOT (id = 1) LIMIT 10", &catalog).unwrap();
+        assert_eq!(run(plan, &catalog), vec![
+            vec![Value::INT32(0), Value::INT32(0)],
+            vec![Value::INT32(2), Value::INT32(20)],
+            vec![Value::INT32(3), Value::INT32(30)],
+            vec![Value::INT32(4), Value::INT32(40)],
+        ]);
+
+        let plan = parse("SELECT id, amount FROM orders WHERE amount < 50 AND id <> 1 LIMIT 10 OFFSET 1", &catalog).unwrap();
+        let op = lower(&optimize(plan).unwrap(), &catalog).unwrap();
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut ids = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(10).unwrap() {
+            ids.extend_from_slice(&column_row_data::<Int32>(view.column(0).unwrap()).unwrap().values[.. view.rows()]);
+        }
+
+        assert_eq!(ids, vec![2, 3, 4]);
+    }
+
+    fn run(plan: LogicalPlan, catalog: &Arc<Catalog<'static>>) -> Vec<Vec<Value<'static>>> {
+        let op = lower(&optimize(plan).unwrap(), catalog).unwrap();
+        let mut cursor = op.bind(&ExecContext::default()).unwrap();
+        let mut rows = Vec::new();
+        while let CursorChunk::Next(view) = cursor.next(4).unwrap() {
+            for row in 0 .. view.rows() {
+                rows.push((0 .. view.schema().count())
+                    .map(|pos| column_value(view.column(pos).unwrap(), row).unwrap().into_owned())
+                    .collect());
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn order_by_expressions() {
+        let catalog = catalog();
+        let plan = parse("SELECT id, amount >= 50 AS big FROM orders WHERE id < 2 OR NOT customer = 'a' \
+                          ORDER BY big DESC, id DESC", &catalog).unwrap();
+
+        let schema = plan.schema().unwrap();
+        assert_eq!(schema.iter().map(|a| (a.name.as_str(), a.dtype)).collect::<Vec<_>>(),
+                   vec![("id", Type::INT32), ("big", Type::BOOLEAN)]);
+
+        let rows: Vec<_> = [(9, true), (7, true), (5, true), (3, false), (1, false), (0, false)].iter()
+            .map(|&(id, big)| vec![Value::INT32(id), Value::BOOLEAN(big)])
+            .collect();
+        assert_eq!(run(plan, &catalog), rows);
+
+        // Unnamed expressions, ordinals
+        let plan = parse("SELECT NOT (id > 7), id FROM orders WHERE id > 5 ORDER BY 1, 2 DESC", &catalog).unwrap();
+        assert_eq!(plan.schema().unwrap().get(0).unwrap().name, "_c0");
+        assert_eq!(run(plan, &catalog), vec![
+            vec![Value::BOOLEAN(false), Value::INT32(9)],
+            vec![Value::BOOLEAN(false), Value::INT32(8)],
+            vec![Value::BOOLEAN(true), Value::INT32(7)],
+            vec![Value::BOOLEAN(true), Value::INT32(6)],
+        ]);
+    }
+
+    #[test]
+    fn join_aggregate() {
+        let catalog = catalog();
+        let plan = parse("SELECT city, SUM(amount) total, count(*) FROM orders o \
+                          LEFT JOIN customers c ON c.name = o.customer \
+                          WHERE amount > 0 GROUP BY c.city ORDER BY total", &catalog).unwrap();
+
+        assert_eq!(plan.to_string(),
+                   "Sort [#1 ASC]\n\
+                   \x20 Project [#0 AS city, #1 AS total, #2 AS count]\n\
+                   \x20   Aggregate group_by=[4] [SUM(#2) AS total, COUNT(*) AS count]\n\
+                   \x20     Filter #2 > 0\n\
+                   \x20       Join LEFT on [#1 = #0]\n\
+                   \x20         Project [#0 AS o.id, #1 AS o.customer, #2 AS o.amount]\n\
+                   \x20           Scan orders [0, 1, 2]\n\
+                   \x20         Project [#0 AS c.name, #1 AS c.city]\n\
+                   \x20           Scan customers [0, 1]\n");
+
+        let schema = plan.schema().unwrap();
+        assert_eq!(schema.iter().map(|a| (a.name.as_str(), a.dtype)).collect::<Vec<_>>(),
+                   vec![("city", Type::TEXT), ("total", Type::INT64), ("count", Type::UINT64)]);
+
+        assert!(parse("SELECT amount FROM orders GROUP BY customer", &catalog).is_err());
+        assert!(parse("SELECT missing FROM orders", &catalog).is_err());
+        assert!(parse("SELECT * FROM nope", &catalog).is_err());
+        assert!(parse("SELECT name FROM orders o JOIN customers c ON o.id > 1", &catalog).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+// vim: set ts=4 sw=4 et :
+
+//! SELECT statement parser.
+
+use ::error::DBError;
+use ::expression::comparison::CompareOp;
+use ::plan::JoinKind;
+use ::types::Value;
+
+use super::lexer::{Token, tokenize};
+
+/// Parsed expression; names aren't resolved yet
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// Column reference, optionally qualified (`t.a`)
+    COLUMN(Option<String>, String),
+    LITERAL(Value<'static>),
+    COMPARE(CompareOp, Box<Expr>, Box<Expr>),
+    AND(Box<Expr>, Box<Expr>),
+    OR(Box<Expr>, Box<Expr>),
+    NOT(Box<Expr>),
+    /// Aggregate function call; `None` argument is `*`
+    CALL(String, Option<Box<Expr>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectItem {
+    /// `*`
+    ALL,
+    EXPR(Expr, Option<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableRef {
+    pub name: String,
+    pub alias: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: TableRef,
+    pub on: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub items: Vec<SelectItem>,
+    pub from: TableRef,
+    pub joins: Vec<Join>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    /// Expressions and ascending flags
+    pub order_by: Vec<(Expr, bool)>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+const KEYWORDS: &'static [&'static str] = &[
+    "SELECT", "FROM", "WHERE", "GROUP", "BY", "ORDER", "LIMIT", "OFFSET", "JOIN", "INNER", "LEFT",
+    "OUTER", "ON", "AS", "AND", "OR", "NOT", "ASC", "DESC", "TRUE", "FALSE", "NULL",
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Offset reported at the end of input
+    end: usize,
+}
+
+pub fn parse_select(sql: &str) -> Result<Select, DBError> {
+    let mut parser = Parser { tokens: tokenize(sql)?, pos: 0, end: sql.len() };
+    let select = parser.select()?;
+
+    parser.symbol(";");
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.error("expected end of statement"))
+    }
+
+    Ok(select)
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> DBError {
+        let offset = self.tokens.get(self.pos).map_or(self.end, |&(_, offset)| offset);
+        DBError::SQL(format!("{} at offset {}", msg, offset))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|&(ref t, _)| t)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(&Token::WORD(ref w))   => w.eq_ignore_ascii_case(keyword),
+            _                           => false,
+        }
+    }
+
+    /// Is the next token the `keyword`; consumed if it is
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), DBError> {
+        if !self.keyword(keyword) {
+            return Err(self.error(&format!("expected {}", keyword)))
+        }
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::SYMBOL(s)) => s == symbol,
+            _                       => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), DBError> {
+        if !self.symbol(symbol) {
+            return Err(self.error(&format!("expected '{}'", symbol)))
+        }
+        Ok(())
+    }
+
+    /// Identifier; unquoted ones can't be keywords
+    fn ident(&mut self) -> Option<String> {
+        let name = match self.peek() {
+            Some(&Token::WORD(ref w)) if !KEYWORDS.iter().any(|k| w.eq_ignore_ascii_case(k)) => w.clone(),
+            Some(&Token::QUOTED(ref w)) => w.clone(),
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(name)
+    }
+
+    fn expect_ident(&mut self) -> Result<String, DBError> {
+        self.ident().ok_or_else(|| self.error("expected name"))
+    }
+
+    fn unsigned(&mut self) -> Result<u64, DBError> {
+        let value = match self.peek() {
+            Some(&Token::NUMBER(ref n)) => n.parse().ok(),
+            _                           => None,
+        };
+
+        match value {
+            Some(v) => { self.pos += 1; Ok(v) }
+            None    => Err(self.error("expected row count")),
+        }
+    }
+
+    /// `[AS] alias`
+    fn alias(&mut self) -> Result<Option<String>, DBError> {
+        if self.keyword("AS") {
+            return self.expect_ident().map(Some)
+        }
+        Ok(self.ident())
+    }
+
+    fn select(&mut self) -> Result<Select, DBError> {
+        self.expect_keyword("SELECT")?;
+
+        let mut items = Vec::new();
+        loop {
+            if self.symbol("*") {
+                items.push(SelectItem::ALL);
+            } else {
+                let expr = self.expr()?;
+                items.push(SelectItem::EXPR(expr, self.alias()?));
+            }
+
+            if !self.symbol(",") {
+                break
+            }
+        }
+
+        self.expect_keyword("FROM")?;
+        let from = self.table()?;
+
+        let mut joins = Vec::new();
+        loop {
+            let kind = if self.keyword("LEFT") {
+                self.keyword("OUTER");
+                Some(JoinKind::LEFT)
+            } else if self.keyword("INNER") {
+                Some(JoinKind::INNER)
+            } else {
+                None
+            };
+
+            if !self.keyword("JOIN") {
+                if kind.is_some() {
+                    return Err(self.error("expected JOIN"))
+                }
+                break
+            }
+
+            let table = self.table()?;
+            self.expect_keyword("ON")?;
+            joins.push(Join { kind: kind.unwrap_or(JoinKind::INNER), table: table, on: self.expr()? });
+        }
+
+        let filter = if self.keyword("WHERE") { Some(self.expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if self.keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                group_by.push(self.expr()?);
+                if !self.symbol(",") {
+                    break
+                }
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let expr = self.expr()?;
+                let ascending = !self.keyword("DESC");
+                if ascending {
+                    self.keyword("ASC");
+                }
+                order_by.push((expr, ascending));
+
+                if !self.symbol(",") {
+                    break
+                }
+            }
+        }
+
+        let limit = if self.keyword("LIMIT") { Some(self.unsigned()?) } else { None };
+        let offset = if self.keyword("OFFSET") { Some(self.unsigned()?) } else { None };
+
+        Ok(Select {
+            items: items,
+            from: from,
+            joins: joins,
+            filter: filter,
+            group_by: group_by,
+            order_by: order_by,
+            limit: limit,
+            offset: offset,
+        })
+    }
+
+    fn table(&mut self) -> Result<TableRef, DBError> {
+        let name = self.expect_ident()?;
+        Ok(TableRef { name: name, alias: self.alias()? })
+    }
+
+    fn expr(&mut self) -> Result<Expr, DBError> {
+        let mut lhs = self.and()?;
+        while self.keyword("OR") {
+            lhs = Expr::OR(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, DBError> {
+        let mut lhs = self.not()?;
+        while self.keyword("AND") {
+            lhs = Expr::AND(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr, DBError> {
+        if self.keyword("NOT") {
+            return Ok(Expr::NOT(Box::new(self.not()?)))
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, DBError> {
+        let lhs = self.primary()?;
+
+        let op = match self.peek() {
+            Some(&Token::SYMBOL("="))                           => CompareOp::EQ,
+            Some(&Token::SYMBOL("<>")) | Some(&Token::SYMBOL("!=")) => CompareOp::NE,
+            Some(&Token::SYMBOL("<"))                           => CompareOp::LT,
+            Some(&Token::SYMBOL("<="))                          => CompareOp::LE,
+            Some(&Token::SYMBOL(">"))                           => CompareOp::GT,
+            Some(&Token::SYMBOL(">="))                          => CompareOp::GE,
+            _                                                   => return Ok(lhs),
+        };
+
+        self.pos += 1;
+        Ok(Expr::COMPARE(op, Box::new(lhs), Box::new(self.primary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, DBError> {
+        if self.symbol("(") {
+            let expr = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(expr)
+        }
+
+        if self.keyword("TRUE") {
+            return Ok(Expr::LITERAL(Value::BOOLEAN(true)))
+        } else if self.keyword("FALSE") {
+            return Ok(Expr::LITERAL(Value::BOOLEAN(false)))
+        } else if self.keyword("NULL") {
+            return Ok(Expr::LITERAL(Value::NULL))
+        }
+
+        // Only numeric literals can be negated
+        if self.symbol("-") {
+            let value = match self.peek() {
+                Some(&Token::NUMBER(ref n)) => number(&format!("-{}", n)).ok_or_else(|| self.error("invalid number"))?,
+                _                           => return Err(self.error("expected a number after '-'")),
+            };
+            self.pos += 1;
+            return Ok(Expr::LITERAL(value))
+        }
+
+        let literal = match self.peek() {
+            Some(&Token::STRING(ref s)) => Some(Value::TEXT(s.clone().into())),
+            Some(&Token::NUMBER(ref n)) => Some(number(n).ok_or_else(|| self.error("invalid number"))?),
+            _                           => None,
+        };
+
+        if let Some(value) = literal {
+            self.pos += 1;
+            return Ok(Expr::LITERAL(value))
+        }
+
+        let name = self.expect_ident()?;
+
+        if self.symbol("(") {
+            let arg = if self.symbol("*") { None } else { Some(Box::new(self.expr()?)) };
+            self.expect_symbol(")")?;
+            return Ok(Expr::CALL(name, arg))
+        }
+
+        if self.symbol(".") {
+            let column = self.expect_ident()?;
+            return Ok(Expr::COLUMN(Some(name), column))
+        }
+
+        Ok(Expr::COLUMN(None, name))
+    }
+}
+
+/// Integers are INT64, numbers with a fraction FLOAT64
+fn number(text: &str) -> Option<Value<'static>> {
+    if text.contains('.') {
+        text.parse().ok().map(Value::FLOAT64)
+    } else {
+        text.parse().ok().map(Value::INT64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn col(name: &str) -> Expr {
+        Expr::COLUMN(None, name.to_string())
+    }
+
+    #[test]
+    fn select() {
+        let select = parse_select("select a, count(*) n from t as x left join u on x.id = u.id \
+                                   where not a > 1 or b = 'y' group by a order by n desc, a limit 10 offset 2;").unwrap();
+
+        assert_eq!(select.items, vec![
+            SelectItem::EXPR(col("a"), None),
+            SelectItem::EXPR(Expr::CALL("count".to_string(), None), Some("n".to_string())),
+        ]);
+        assert_eq!(select.from, TableRef { name: "t".to_string(), alias: Some("x".to_string()) });
+        assert_eq!(select.joins.len(), 1);
+        assert_eq!(select.joins[0].kind, JoinKind::LEFT);
+        assert_eq!(select.joins[0].on, Expr::COMPARE(CompareOp::EQ,
+            Box::new(Expr::COLUMN(Some("x".to_string()), "id".to_string())),
+            Box::new(Expr::COLUMN(Some("u".to_string()), "id".to_string()))));
+
+        let gt = Expr::COMPARE(CompareOp::GT, Box::new(col("a")), Box::new(Expr::LITERAL(Value::INT64(1))));
+        let eq = Expr::COMPARE(CompareOp::EQ, Box::new(col("b")), Box::new(Expr::LITERAL(Value::from("y"))));
+        let not = Expr::NOT(Box::new(gt));
+        assert_eq!(select.filter, Some(Expr::OR(Box::new(not), Box::new(eq))));
+        assert_eq!(select.group_by, vec![col("a")]);
+        assert_eq!(select.order_by, vec![(col("n"), false), (col("a"), true)]);
+        assert_eq!((select.limit, select.offset), (Some(10), Some(2)));
+
+        assert!(parse_select("SELECT * FROM t WHERE").is_err());
+        assert!(parse_select("SELECT * FROM t LEFT u ON a = b").is_err());
+        assert!(parse_select("SELECT * FROM t garbage here").is_err());
+        assert!(parse_select("SELECT a FROM").is_err());
+
+        let select = parse_select("SELECT * FROM t WHERE x > -1 AND y < -0.5").unwrap();
+        let gt = Expr::COMPARE(CompareOp::GT, Box::new(col("x")), Box::new(Expr::LITERAL(Value::INT64(-1))));
+        let lt = Expr::COMPARE(CompareOp::LT, Box::new(col("y")), Box::new(Expr::LITERAL(Value::FLOAT64(-0.5))));
+        assert_eq!(select.filter, Some(Expr::AND(Box::new(gt), Box::new(lt))));
+        assert!(parse_select("SELECT * FROM t WHERE x > -y").is_err());
+    }
+}
@@ -1,16 +1,30 @@
+use std::ptr;
+
 use super::allocator::{Allocator};
 use super::block::*;
 use super::error::DBError;
+use super::record::{Record, check_record_schema};
 use super::schema::Schema;
-use super::row::RowOffset;
-use super::util::copy_value::ValueSetter;
+use super::row::{RowOffset, RowRange};
+use super::types::ValueInfo;
+use super::util::bitmap;
+use super::util::copy_value::{RowSetter, ValueSetter, set_column_value};
 
 /// Abstraction on top of a `Block` for easy construction and modification of contained data.
 ///
 /// The container assumes that all operations on the block are safe and schema type conforming. In
 /// case of errors it simply panics.
+///
+/// Deleted rows stay in the columns until the table is compacted; scans of the table (`ScanView`)
+/// skip them, reading its columns directly doesn't.
 pub struct Table<'alloc> {
     block: Option<Block<'alloc>>,
+    /// Per column row counts while columns are bulk appended (`append_column_slice`). Empty when
+    /// all columns have the same number of rows.
+    bulk_rows: Vec<RowOffset>,
+    /// Deleted rows bitmap (set bit means deleted), until the table is compacted. Only covers rows
+    /// up to the last deleted row.
+    deleted: Vec<u8>,
 }
 
 impl<'alloc> View<'alloc> for Table<'alloc> {
@@ -34,23 +48,58 @@ impl<'alloc> View<'alloc> for Table<'alloc> {
             .unwrap()
             .rows()
     }
+
+    fn deleted(&'alloc self) -> Option<&'alloc [u8]> {
+        if self.deleted.is_empty() { None } else { Some(&self.deleted) }
+    }
 }
 
 impl<'alloc> Table<'alloc> {
+    /// New table with room for `capacity` rows. If allocating the capacity fails the table starts
+    /// out empty; use `with_capacity` to handle the error.
     pub fn new(alloc: &'alloc Allocator, schema: &Schema, capacity: Option<RowOffset>) -> Table<'alloc> {
-        let b = Some(Block::new(alloc, schema));
+        let mut table = Table {
+            block: Some(Block::new(alloc, schema)),
+            bulk_rows: Vec::new(),
+            deleted: Vec::new(),
+        };
 
-        if let (Some(c), Some(mut b)) = (capacity, b) {
-            b.set_capacity(c);
+        if let Some(rows) = capacity {
+            let _ = table.reserve(rows);
         }
 
-        Table {
-            block: Some(Block::new(alloc, schema))
+        table
+    }
+
+    /// New table with room for `rows` rows (including null vectors)
+    pub fn with_capacity(alloc: &'alloc Allocator, schema: &Schema, rows: RowOffset)
+        -> Result<Table<'alloc>, DBError>
+    {
+        let mut table = Table::new(alloc, schema, None);
+        table.reserve(rows)?;
+        Ok(table)
+    }
+
+    /// Make room for at least `rows` more rows without re-allocating column data
+    pub fn reserve(&mut self, rows: RowOffset) -> Result<(), DBError> {
+        let block = self.block.as_mut().unwrap();
+        let needed = block.rows() + rows;
+
+        if needed <= block.capacity() {
+            return Ok(())
         }
+
+        block.set_capacity(needed)
+    }
+
+    pub fn capacity(&self) -> RowOffset {
+        self.block_ref().capacity()
     }
 
     /// Add a single row.
     pub fn add_row(&mut self) -> Result<RowOffset, DBError> {
+        self.check_no_bulk()?;
+
         self.block
             .as_mut()
             .unwrap()
@@ -69,6 +118,188 @@ impl<'alloc> Table<'alloc> {
             .unwrap()
     }
 
+    /// Append a row from a Rust tuple, eg. `table.push((1u32, "abc", None::<f64>))`. The tuple
+    /// types are validated against the schema.
+    pub fn push<R: RowSetter>(&mut self, values: R) -> Result<RowOffset, DBError> {
+        R::check_schema(self.schema())?;
+
+        let row = self.add_row()?;
+        if let Err(e) = values.set_columns(self.block.as_mut().unwrap(), row) {
+            self.truncate(row);
+            return Err(e)
+        }
+
+        Ok(row)
+    }
+
+    /// Append records, one row per record. Returns the first appended row.
+    pub fn append_records<R: Record>(&mut self, records: &[R]) -> Result<RowOffset, DBError> {
+        check_record_schema::<R>(self.schema())?;
+        self.check_no_bulk()?;
+
+        let start = self.rows();
+        if records.is_empty() {
+            return Ok(start)
+        }
+
+        let block = self.block.as_mut().unwrap();
+        block.add_rows(records.len())?;
+
+        for (idx, record) in records.iter().enumerate() {
+            if let Err(e) = record.write(block, start + idx) {
+                block.truncate(start);
+                return Err(e)
+            }
+        }
+
+        Ok(start)
+    }
+
+    /// Bulk append values to a column. Values are copied directly into the column's value vector
+    /// and the column's nulls are cleared.
+    ///
+    /// Columns are appended to independently; the table grows to the longest column. Other
+    /// columns have to be appended to as well before rows can be added by other means.
+    ///
+    /// VARLEN columns are not supported since their values point into the column arena.
+    pub fn append_column_slice<T: ValueInfo>(&mut self, col: usize, values: &[T::Store])
+        -> Result<(), DBError>
+    {
+        let rows = self.rows();
+        let columns = self.schema().count();
+
+        {
+            let attr = self.schema().get(col)?;
+            if attr.dtype != T::ENUM || T::VARLEN || T::NESTED {
+                return Err(DBError::AttributeType(attr.name.clone()))
+            }
+        }
+
+        if self.bulk_rows.is_empty() {
+            self.bulk_rows = vec![rows; columns];
+        }
+
+        let start = self.bulk_rows[col];
+        let end = start + values.len();
+
+        if end > rows {
+            self.block.as_mut().unwrap().add_rows(end - rows)?;
+        }
+
+        {
+            let column = self.column_mut(col).unwrap();
+
+            if column.attribute().nullable {
+                column.nulls_mut()?.fill(start, values.len(), false);
+            }
+
+            let dst = column.rows_mut::<T>()?;
+            unsafe {
+                ptr::copy_nonoverlapping(values.as_ptr(), dst[start ..].as_mut_ptr(), values.len());
+            }
+        }
+
+        self.bulk_rows[col] = end;
+
+        let total = self.rows();
+        if self.bulk_rows.iter().all(|r| *r == total) {
+            self.bulk_rows.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Bulk append all the rows of a view with a compatible schema.
+    pub fn append_view<'v>(&mut self, src: &'v View<'v>) -> Result<RowOffset, DBError> {
+        self.check_no_bulk()?;
+
+        self.block
+            .as_mut()
+            .unwrap()
+            .append_view(src)
+    }
+
+    fn check_no_bulk(&self) -> Result<(), DBError> {
+        match self.bulk_rows.iter().position(|r| *r != self.rows()) {
+            Some(pos)   => Err(DBError::AttributeMissing(format!("(pos: {}) bulk append incomplete", pos))),
+            None        => Ok(()),
+        }
+    }
+
+    /// Update the value of an existing (not deleted) row.
+    pub fn update<T: ValueSetter>(&mut self, col: usize, row: RowOffset, value: T) -> Result<(), DBError> {
+        if row >= self.rows() || self.is_deleted(row) {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        set_column_value(self.block.as_mut().unwrap(), col, row, &value)
+    }
+
+    /// Mark rows as deleted. Scans skip the rows, but they keep their offsets (and stay in the
+    /// columns) until they're dropped by `compact()`.
+    pub fn delete_rows(&mut self, range: RowRange) -> Result<(), DBError> {
+        let end = range.offset + range.rows;
+        if end > self.rows() {
+            return Err(DBError::RowOutOfBounds)
+        }
+
+        let bytes = bitmap::bytes_for(end);
+        if self.deleted.len() < bytes {
+            self.deleted.resize(bytes, 0);
+        }
+
+        for row in range.offset .. end {
+            bitmap::set_bit(&mut self.deleted, row, true);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_deleted(&self, row: RowOffset) -> bool {
+        row < self.deleted.len() * 8 && bitmap::get_bit(&self.deleted, row)
+    }
+
+    /// Number of rows marked as deleted
+    pub fn deleted_rows(&self) -> usize {
+        bitmap::count_ones(&self.deleted, self.deleted.len() * 8)
+    }
+
+    /// Rebuild the block without the deleted rows.
+    pub fn compact(&mut self) -> Result<(), DBError> {
+        if self.deleted_rows() == 0 {
+            self.deleted.clear();
+            return Ok(())
+        }
+
+        let live: Vec<RowOffset> = (0 .. self.rows())
+            .filter(|row| !self.is_deleted(*row))
+            .collect();
+
+        let compacted = {
+            let block = self.block.as_ref().unwrap();
+            take(block.allocator(), block, &live)?
+        };
+
+        self.block = Some(compacted);
+        self.deleted.clear();
+        Ok(())
+    }
+
+    /// Drop rows past `rows`
+    pub fn truncate(&mut self, rows: RowOffset) {
+        if self.deleted.len() * 8 > rows {
+            self.deleted.truncate(bitmap::bytes_for(rows));
+            for row in rows .. self.deleted.len() * 8 {
+                bitmap::set_bit(&mut self.deleted, row, false);
+            }
+        }
+
+        self.block
+            .as_mut()
+            .unwrap()
+            .truncate(rows)
+    }
+
     /// Take ownership of the contained `Block`.
     ///
     /// This is done when the `Table` is complete and is going to be used elsewhere.
@@ -86,19 +317,25 @@ impl<'alloc> Table<'alloc> {
             .column_mut(pos)
     }
 
-    /// Set nul value for (col, row) in the currently allocated table space.
+    /// Set nul value for (col, row) in the currently allocated table space. NULL rows get the type's
+    /// default value.
     pub fn set_null(&mut self, col: usize, row: RowOffset, value: bool) -> Result<(), DBError> {
         if row >= self.rows() {
             return Err(DBError::RowOutOfBounds)
         }
 
-        self.column_mut(col)
-            .ok_or(DBError::make_column_unknown_pos(col))
-            .and_then(|c| c.nulls_mut())
-            .and_then(|nulls| { nulls[row] = value as u8; Ok(()) })
+        let column = self.column_mut(col).ok_or(DBError::make_column_unknown_pos(col))?;
+        column.nulls_mut()?.set(row, value);
+
+        if value {
+            column.set_default(row)?;
+        }
+
+        Ok(())
     }
 
-    /// Set value for (col, row) in the currently allocated table space.
+    /// Set value for (col, row) in the currently allocated table space. Clears the row's null flag
+    /// (unless setting a NULL value).
     pub fn set<T: ValueSetter>(&mut self, col: usize, row: RowOffset, value: T)
         -> Result<(), DBError>
     {
@@ -106,18 +343,19 @@ impl<'alloc> Table<'alloc> {
             return Err(DBError::RowOutOfBounds)
         }
 
-        // TODO: Clear null value
-
-        self.column_mut(col)
-            .ok_or(DBError::make_column_unknown_pos(col))
-            .and_then(|c| value.set_row(c, row))
+        set_column_value(self.block.as_mut().unwrap(), col, row, &value)
     }
 }
 
 /// `TableAppender` is a convenient way to programmatically build a `Table`/`Block`.
 ///
 /// `TableAppender` works on a row -> column basis. You first add a new row, then you fill up each
-/// of the columns in the row until you're ready for the next row (or done).
+/// of the columns in the row until you're ready for the next row (or done). Every step returns the
+/// appender, so they can be chained with `?`.
+///
+/// A row is only kept if all of its columns were set without an error: a failed step drops the
+/// current row before returning its error, `done()` completes (or drops) the trailing row and
+/// `rollback_row()` drops the current row. Appending can continue after an error.
 ///
 /// `TableAppender` assumes that the Table owns the Block. If the Table does not own the block (eg.
 /// it was been taken) then the use of `TableAppender` will result in a panic!
@@ -127,66 +365,135 @@ pub struct TableAppender<'alloc: 't, 't> {
     row: RowOffset,
     // Current column offset
     col: usize,
-    error: Option<DBError>,
+    // A row was added and hasn't been rolled back
+    row_open: bool,
+    // Columns of the current row that have been set
+    filled: Vec<bool>,
 }
 
 impl<'alloc, 't> TableAppender<'alloc, 't> {
     pub fn new(table: &'t mut Table<'alloc>) -> TableAppender<'alloc, 't> {
          TableAppender {
             row: table.rows(),
+            filled: vec![false; table.schema().count()],
             table: table,
             col: 0,
-            error: None,
+            row_open: false,
         }
     }
 
-    /// Result (error) of append operation
-    pub fn status(&self) -> Option<&DBError> {
-        self.error.as_ref()
-    }
+    /// Complete the last row. It's dropped if it has unset non-nullable columns (an error).
+    pub fn done(&mut self) -> Result<(), DBError> {
+        if self.row_open {
+            let out = self.complete_row();
+            self.step(out)?;
+            self.row_open = false;
+        }
 
-    /// Takes the result (error) of the append operation
-    pub fn done(&mut self) -> Option<DBError> {
-        self.error.take()
+        Ok(())
     }
 
-    /// Append new row
-    pub fn add_row(mut self) -> TableAppender<'alloc, 't> {
-        if self.error.is_some() {
-            return self;
+    /// Drop the row currently being appended
+    pub fn rollback_row(&mut self) -> &mut TableAppender<'alloc, 't> {
+        if self.row_open {
+            self.remove_row();
         }
 
+        self
+    }
+
+    fn remove_row(&mut self) {
+        self.table.truncate(self.row);
+        self.row_open = false;
         self.col = 0;
-        match self.table.add_row() {
-            Ok(row) => self.row = row,
-            Err(e) => self.error = Some(e),
+    }
+
+    /// Result of a step on the current row; the row is dropped if it failed
+    fn step(&mut self, out: Result<(), DBError>) -> Result<(), DBError> {
+        if out.is_err() {
+            self.rollback_row();
         }
+        out
+    }
 
-        self
+    /// Unset nullable columns of the current row are set to NULL, unset non-nullable columns are
+    /// an error.
+    fn complete_row(&mut self) -> Result<(), DBError> {
+        for pos in 0 .. self.filled.len() {
+            if self.filled[pos] {
+                continue
+            }
+
+            if !self.table.schema().get(pos)?.nullable {
+                return Err(DBError::AttributeMissing(format!("(pos: {}) not set", pos)))
+            }
+
+            self.table.set_null(pos, self.row, true)?;
+            self.filled[pos] = true;
+        }
+
+        Ok(())
     }
 
-    /// Set column value to NUL and move onto the column to the right
-    pub fn set_null(mut self, value: bool) -> TableAppender<'alloc, 't> {
-        if self.error.is_some() {
-            return self
+    /// Append new row. Fails (dropping the previous row) if the previous row is incomplete.
+    pub fn add_row(&mut self) -> Result<&mut TableAppender<'alloc, 't>, DBError> {
+        self.done()?;
+
+        self.col = 0;
+        for f in &mut self.filled {
+            *f = false;
         }
 
-        self.error = self.table.set_null(self.col, self.row, value).err();
-        self.col += 1;
+        self.row = self.table.add_row()?;
+        self.row_open = true;
+        Ok(self)
+    }
 
+    /// Leave the column unset and move onto the column to the right. Skipped nullable columns are
+    /// NULL once the row is complete.
+    pub fn skip(&mut self) -> &mut TableAppender<'alloc, 't> {
+        self.col += 1;
         self
     }
 
+    /// Set column value to NUL and move onto the column to the right
+    pub fn set_null(&mut self, value: bool) -> Result<&mut TableAppender<'alloc, 't>, DBError> {
+        let col = self.col;
+        let out = self.open_row().and_then(|_| self.table.set_null(col, self.row, value));
+        self.step(out)?;
+        self.mark_filled(col);
+        Ok(self)
+    }
+
     /// Set column value and move onto the column to the right
-    pub fn set<T: ValueSetter>(mut self, value: T) -> TableAppender<'alloc, 't> {
-        if self.error.is_some() {
-            return self
+    pub fn set<T: ValueSetter>(&mut self, value: T) -> Result<&mut TableAppender<'alloc, 't>, DBError> {
+        let col = self.col;
+        let out = self.open_row().and_then(|_| self.table.set(col, self.row, value));
+        self.step(out)?;
+        self.mark_filled(col);
+        Ok(self)
+    }
+
+    /// Set the value of a column by name. Following `set()` calls continue with the column to the
+    /// right of it.
+    pub fn set_by_name<T: ValueSetter>(&mut self, name: &str, value: T) -> Result<&mut TableAppender<'alloc, 't>, DBError> {
+        match self.table.schema().exists_ok(name) {
+            Ok(pos) => { self.col = pos; self.set(value) }
+            Err(e)  => { self.rollback_row(); Err(e) }
         }
+    }
 
-        self.error = self.table.set(self.col, self.row, value).err();
-        self.col += 1;
+    /// Fails if no row was added (or it was dropped)
+    fn open_row(&self) -> Result<(), DBError> {
+        if !self.row_open {
+            return Err(DBError::RowOutOfBounds)
+        }
+        Ok(())
+    }
 
-        self
+    fn mark_filled(&mut self, col: usize) {
+        self.filled[col] = true;
+        self.col = col + 1;
     }
 }
 
@@ -195,6 +502,8 @@ mod tests {
     use super::*;
     use allocator;
     use error::DBError;
+    use exec::ExecContext;
+    use operation::{CursorChunk, Operation, ScanView};
     use schema::*;
     use types::*;
 
@@ -205,12 +514,10 @@ mod tests {
         let mut table = Table::new(&allocator::GLOBAL, &schema, None);
 
         {
-            let status = TableAppender::new(&mut table)
-                .add_row().set_null(true)
-                .add_row().set(15 as u32)
-                .done();
-
-            assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set_null(true).unwrap();
+            appender.add_row().unwrap().set(15 as u32).unwrap();
+            appender.done().unwrap();
         }
 
         // Block exists
@@ -226,7 +533,7 @@ mod tests {
         let column = table.block_ref().column(0).unwrap();
         let rows = column_row_data::<UInt32>(column).unwrap();
 
-        assert!(rows.nulls[0] == 1 && rows.nulls[1] == 0, "Null vector incorrect");
+        assert!(rows.nulls.get(0) && !rows.nulls.get(1), "Null vector incorrect");
         assert_eq!(rows.values[1], 15);
     }
 
@@ -236,14 +543,267 @@ mod tests {
         let mut table = Table::new(&allocator::GLOBAL, &schema, None);
 
         let status = TableAppender::new(&mut table)
-            .add_row().set_null(true).set(15 as u32)
-            .done();
+            .add_row().unwrap().set_null(true).unwrap().set(15 as u32)
+            .map(|_| ());
 
         match status {
-            Some(DBError::AttributeMissing(_)) => (), // nop
-            Some(e) => assert!(false, "Unexpected error {}", e),
-            None => assert!(false, "Expected error"),
+            Err(DBError::AttributeMissing(_)) => (), // nop
+            Err(e) => assert!(false, "Unexpected error {}", e),
+            Ok(_) => assert!(false, "Expected error"),
+        }
+
+        // The failed row is dropped
+        assert_eq!(table.rows(), 0);
+    }
+
+    #[test]
+    fn appender_rollback() {
+        let attrs = vec![
+            Attribute::new("one", false, Type::UINT32),
+            Attribute::new("two", false, Type::UINT32),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        // Failed row is dropped, appending continues
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set(1 as u32).unwrap().set(2 as u32).unwrap();
+            assert!(appender.add_row().unwrap().set(3 as u32).unwrap().set("wrong type").is_err());
+            assert!(appender.set(4 as u32).is_err(), "No row to set");
+            appender.add_row().unwrap().set(5 as u32).unwrap().set(6 as u32).unwrap();
+            appender.done().unwrap();
+        }
+
+        assert_eq!(table.rows(), 2);
+        assert_eq!(column_row_data::<UInt32>(table.block_ref().column(0).unwrap()).unwrap().values[1], 5);
+
+        // Rolled back row
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set(7 as u32).unwrap();
+            appender.rollback_row().add_row().unwrap().set(8 as u32).unwrap().set(9 as u32).unwrap();
+            appender.done().unwrap();
+        }
+
+        assert_eq!(table.rows(), 3);
+        table.truncate(2);
+
+        // Incomplete trailing row is dropped
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set(7 as u32).unwrap();
+            assert!(appender.done().is_err());
+        }
+
+        assert_eq!(table.rows(), 2);
+
+        // So is a row that starts before the previous one is complete
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set(7 as u32).unwrap();
+            assert!(appender.add_row().is_err());
+            appender.done().unwrap();
         }
+
+        assert_eq!(table.rows(), 2);
+    }
+
+    #[test]
+    fn appender_by_name() {
+        let attrs = vec![
+            Attribute::new("one", false, Type::UINT32),
+            Attribute::new("two", true, Type::UINT32),
+            Attribute::new("three", false, Type::UINT32),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set_by_name("three", 3 as u32).unwrap().set_by_name("one", 1 as u32).unwrap();
+            appender.add_row().unwrap().set(4 as u32).unwrap().skip().set(6 as u32).unwrap();
+            appender.done().unwrap();
+        }
+
+        assert_eq!(table.rows(), 2);
+
+        let rows = column_row_data::<UInt32>(table.block_ref().column(2).unwrap()).unwrap();
+        assert_eq!(&rows.values[0 .. 2], &[3, 6]);
+        assert!(column_nulls(table.block_ref().column(1).unwrap()).slice(0, 2).count_ones() == 2);
+
+        // Unset non-nullable column
+        {
+            let mut appender = TableAppender::new(&mut table);
+            appender.add_row().unwrap().set(7 as u32).unwrap();
+            match appender.add_row() {
+                Err(DBError::AttributeMissing(ref msg)) => assert!(msg.contains("pos: 2"), "{}", msg),
+                _ => panic!("Expected a missing column error"),
+            }
+        }
+        assert_eq!(table.rows(), 2);
+
+        {
+            let mut appender = TableAppender::new(&mut table);
+            assert!(appender.add_row().unwrap().set_by_name("four", 1 as u32).is_err());
+            appender.done().unwrap();
+        }
+        assert_eq!(table.rows(), 2);
+    }
+
+    #[test]
+    fn capacity() {
+        let schema = Schema::make_one_attr("v", true, Type::UINT64);
+
+        let mut table = Table::new(&allocator::GLOBAL, &schema, Some(10));
+        assert_eq!(table.capacity(), 10);
+
+        table.add_row().unwrap();
+        table.reserve(100).unwrap();
+        assert_eq!(table.capacity(), 101);
+
+        let mut table = Table::with_capacity(&allocator::GLOBAL, &schema, 5).unwrap();
+        let mem = table.block_ref().memory_usage();
+        assert!(mem.nulls > 0);
+
+        for v in 0 .. 5 {
+            table.push((v as u64,)).unwrap();
+        }
+
+        assert_eq!(table.capacity(), 5);
+        assert_eq!(table.block_ref().memory_usage().total(), mem.total());
+
+        let mut block = table.take().unwrap();
+        block.truncate(0);
+        assert_eq!(block.add_rows(5).unwrap(), 0);
+        assert_eq!(block.capacity(), 5);
+    }
+
+    #[test]
+    fn bulk_append() {
+        let attrs = vec![
+            Attribute::new("one", false, Type::UINT32),
+            Attribute::new("two", true, Type::INT64),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        table.append_column_slice::<UInt32>(0, &[1, 2, 3]).unwrap();
+        assert_eq!(table.rows(), 3);
+        assert!(table.add_row().is_err(), "Column two is not complete");
+
+        assert!(table.append_column_slice::<UInt64>(1, &[4]).is_err());
+        table.append_column_slice::<Int64>(1, &[4, 5, 6]).unwrap();
+
+        let src = table.take().unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        table.add_row().unwrap();
+        table.set(0, 0, 0 as u32).unwrap();
+        table.set_null(1, 0, true).unwrap();
+
+        assert_eq!(table.append_view(&src).unwrap(), 1);
+        assert_eq!(table.rows(), 4);
+
+        let rows = column_row_data::<Int64>(table.block_ref().column(1).unwrap()).unwrap();
+        assert_eq!(&rows.values[1 .. 4], &[4, 5, 6]);
+        assert!(rows.nulls.get(0) && !rows.nulls.get(3));
+    }
+
+    #[test]
+    fn tuple_rows() {
+        let attrs = vec![
+            Attribute::new("id", false, Type::UINT32),
+            Attribute::new("name", true, Type::TEXT),
+            Attribute::new("score", true, Type::FLOAT64),
+        ];
+
+        let schema = Schema::from_vec(attrs).unwrap();
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+
+        table.push((1u32, None::<String>, None::<f64>)).unwrap();
+        table.push((2u32, "abc", Some(0.5f64))).unwrap();
+        assert!(table.push((3u32, "x")).is_err(), "Column count");
+        assert!(table.push((3u32, "x", 0.5f32)).is_err(), "Wrong type");
+        assert!(table.push((None::<u32>, "x", 0.5f64)).is_err(), "Not nullable");
+        assert_eq!(table.rows(), 2);
+
+        let block = table.take().unwrap();
+        assert_eq!(block.get_row::<(u32, Option<String>, Option<f64>)>(0).unwrap(), (1, None, None));
+        assert_eq!(block.get_row::<(u32, &str, f64)>(1).unwrap(), (2, "abc", 0.5));
+        assert!(block.get_row::<(u32, &str, f64)>(0).is_err(), "NULL into non Option");
+        assert!(block.get_row::<(u64,)>(0).is_err());
+    }
+
+    #[test]
+    fn update_and_delete() {
+        let schema = Schema::make_one_attr("v", true, Type::INT64);
+        let mut table = Table::new(&allocator::GLOBAL, &schema, None);
+        table.append_column_slice::<Int64>(0, &[0, 1, 2, 3, 4, 5]).unwrap();
+
+        table.update(0, 1, 10i64).unwrap();
+        table.update(0, 5, NULL_VALUE).unwrap();
+        table.delete_rows(RowRange { offset: 2, rows: 2 }).unwrap();
+
+        assert!(table.is_deleted(3) && !table.is_deleted(4));
+        assert_eq!(table.deleted_rows(), 2);
+        assert!(table.update(0, 2, 7i64).is_err(), "Row is deleted");
+        assert!(table.delete_rows(RowRange { offset: 5, rows: 2 }).is_err());
+
+        // Scans skip deleted rows, the columns still have them
+        assert_eq!(column_value(table.column(0).unwrap(), 2).unwrap(), Value::INT64(2));
+        let mut scanned = Vec::new();
+        {
+            let mut cursor = ScanView::new(&table, None).bind(&ExecContext::default()).unwrap();
+            while let CursorChunk::Next(view) = cursor.next(3).unwrap() {
+                scanned.push((0 .. view.rows())
+                    .map(|row| column_value(view.column(0).unwrap(), row).unwrap().into_owned())
+                    .collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(scanned, vec![vec![Value::INT64(0), Value::INT64(10)], vec![Value::INT64(4), Value::NULL]]);
+
+        table.compact().unwrap();
+        assert_eq!(table.rows(), 4);
+        assert_eq!(table.deleted_rows(), 0);
+
+        let values: Vec<Value> = (0 .. 4)
+            .map(|row| column_value(table.column(0).unwrap(), row).unwrap().into_owned())
+            .collect();
+        assert_eq!(values, vec![Value::INT64(0), Value::INT64(10), Value::INT64(4), Value::NULL]);
+    }
+
+    #[test]
+    fn nullable_round_trip() {
+        let attrs = vec![
+            Attribute::new("num", true, Type::INT32),
+            Attribute::new("text", true, Type::TEXT),
+        ];
+
+        let mut table = Table::new(&allocator::GLOBAL, &Schema::from_vec(attrs).unwrap(), None);
+        table.add_row().unwrap();
+
+        for pos in 0 .. 2 {
+            table.set_null(pos, 0, true).unwrap();
+        }
+
+        table.set(0, 0, -3i32).unwrap();
+        assert_eq!(column_value(table.column(0).unwrap(), 0).unwrap(), Value::INT32(-3));
+
+        table.set(1, 0, "abc").unwrap();
+        assert_eq!(column_value(table.column(1).unwrap(), 0).unwrap(), Value::TEXT("abc".into()));
+
+        // NULL rows hold the default value
+        table.set_null(0, 0, true).unwrap();
+        table.set(1, 0, NULL_VALUE).unwrap();
+        assert_eq!(column_value(table.column(0).unwrap(), 0).unwrap(), Value::NULL);
+        assert_eq!(column_row_data::<Int32>(table.column(0).unwrap()).unwrap().values[0], 0);
+        assert_eq!(column_row_data::<Text>(table.column(1).unwrap()).unwrap().values[0].size, 0);
+
+        table.set_null(0, 0, false).unwrap();
+        assert_eq!(column_value(table.column(0).unwrap(), 0).unwrap(), Value::INT32(0));
     }
 
     #[test]
@@ -252,25 +812,22 @@ mod tests {
 
         let table = {
             let attrs = vec![
-                Attribute{name: "one".to_string(), nullable: false, dtype: Type::BLOB},
-                Attribute{name: "two".to_string(), nullable: false, dtype: Type::TEXT},
+                Attribute::new("one", false, Type::BLOB),
+                Attribute::new("two", false, Type::TEXT),
             ];
 
             let schema = Schema::from_vec(attrs).unwrap();
             let mut table = Table::new(&allocator::GLOBAL, &schema, None);
 
             {
-
-                let status = TableAppender::new(&mut table)
-                    .add_row()
-                        .set(bytes.as_ref())
-                        .set("one")
-                    .add_row()
-                        .set(bytes.as_ref())
-                        .set("two".to_string())
-                    .done();
-
-                assert!(status.is_none(), "Error appending rows {}", status.unwrap());
+                let mut appender = TableAppender::new(&mut table);
+                appender.add_row().unwrap()
+                    .set(bytes.as_ref()).unwrap()
+                    .set("one").unwrap();
+                appender.add_row().unwrap()
+                    .set(bytes.as_ref()).unwrap()
+                    .set("two".to_string()).unwrap();
+                appender.done().unwrap();
             }
 
             table
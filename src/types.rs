@@ -1,10 +1,15 @@
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::convert::{AsRef, From};
+use std::fmt;
+use std::iter::once;
 use std::mem;
 use std::slice;
 use std::str;
 
 use super::error::DBError;
+use super::util::datetime;
 
 /// "Native" type storing `Column` data for VARLEN columns
 #[derive(Clone, Copy)]
@@ -14,8 +19,19 @@ pub struct RawData {
     pub size: usize,
 }
 
+/// "Native" type storing `Column` data for LIST and MAP columns.
+///
+/// Points at a range of rows in the column's child (element, key & value) columns.
+#[derive(Clone, Copy, Default)]
+pub struct ListEntry {
+    /// First element row in the child column
+    pub offset: usize,
+    /// Number of elements in the list
+    pub len: usize,
+}
+
 /// "Symbolic" Type of a `Column` `Attribute`
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Type {
     UINT32,
     UINT64,
@@ -26,6 +42,21 @@ pub enum Type {
     BOOLEAN,
     TEXT,
     BLOB,
+    /// JSON document. Stored the same way as TEXT.
+    JSON,
+    /// Date and time without a time zone; microseconds since 1970-01-01 00:00:00
+    TIMESTAMP,
+    /// Point in time; microseconds since 1970-01-01 00:00:00 UTC
+    #[allow(non_camel_case_types)]
+    TIMESTAMP_TZ,
+    /// Variable length list of elements. Element type is described by the single child
+    /// `Attribute`.
+    LIST,
+    /// Collection of named fields. Fields are described by the child `Attribute`s.
+    STRUCT,
+    /// Variable length collection of key, value pairs. Described by the key and value child
+    /// `Attribute`s.
+    MAP,
 }
 
 /// Trait providing higher level metadata about types
@@ -41,12 +72,19 @@ pub trait ValueInfo {
 
     const VARLEN: bool = false;
 
+    /// Values are stored in child columns
+    const NESTED: bool = false;
+
     // RUST is frustrating
     // cannot use mem::size_of::<Self::Store>()
     // because apparently size_of is not constant.
     fn size_of(&self) -> usize {
         mem::size_of::<Self::Store>()
     }
+
+    fn align_of(&self) -> usize {
+        mem::align_of::<Self::Store>()
+    }
 }
 
 pub struct UInt32;
@@ -58,6 +96,12 @@ pub struct Float64;
 pub struct Boolean;
 pub struct Text;
 pub struct Blob;
+pub struct Json;
+pub struct Timestamp;
+pub struct TimestampTz;
+pub struct List;
+pub struct Struct;
+pub struct Map;
 
 impl ValueInfo for UInt32 {
     type Store = u32;
@@ -107,6 +151,42 @@ impl ValueInfo for Blob {
     const VARLEN: bool = true;
 }
 
+impl ValueInfo for Json {
+    type Store = RawData;
+    const ENUM: Type = Type::JSON;
+    const VARLEN: bool = true;
+}
+
+impl ValueInfo for Timestamp {
+    type Store = i64;
+    const ENUM: Type = Type::TIMESTAMP;
+}
+
+impl ValueInfo for TimestampTz {
+    type Store = i64;
+    const ENUM: Type = Type::TIMESTAMP_TZ;
+}
+
+impl ValueInfo for List {
+    type Store = ListEntry;
+    const ENUM: Type = Type::LIST;
+    const NESTED: bool = true;
+}
+
+/// STRUCT values live entirely in the child columns. The parent vector keeps one (unused) byte per
+/// row so row capacity math is the same as for every other column.
+impl ValueInfo for Struct {
+    type Store = u8;
+    const ENUM: Type = Type::STRUCT;
+    const NESTED: bool = true;
+}
+
+impl ValueInfo for Map {
+    type Store = ListEntry;
+    const ENUM: Type = Type::MAP;
+    const NESTED: bool = true;
+}
+
 static UINT32: UInt32 = UInt32{};
 static UINT64: UInt64 = UInt64{};
 static INT32: Int32 = Int32{};
@@ -116,6 +196,12 @@ static FLOAT64: Float64 = Float64{};
 static BOOLEAN: Boolean = Boolean{};
 static TEXT: Text = Text{};
 static BLOB: Blob = Blob{};
+static JSON: Json = Json{};
+static TIMESTAMP: Timestamp = Timestamp{};
+static TIMESTAMP_TZ: TimestampTz = TimestampTz{};
+static LIST: List = List{};
+static STRUCT: Struct = Struct{};
+static MAP: Map = Map{};
 
 impl Type {
     pub fn name(self) -> &'static str {
@@ -129,6 +215,28 @@ impl Type {
             Type::BOOLEAN => "BOOLEAN",
             Type::TEXT    => "TEXT",
             Type::BLOB    => "BLOB",
+            Type::JSON    => "JSON",
+            Type::TIMESTAMP     => "TIMESTAMP",
+            Type::TIMESTAMP_TZ  => "TIMESTAMP_TZ",
+            Type::LIST    => "LIST",
+            Type::STRUCT  => "STRUCT",
+            Type::MAP     => "MAP",
+        }
+    }
+
+    /// Type's values are variable length and stored in the `Column` arena
+    pub fn is_varlen(self) -> bool {
+        match self {
+            Type::TEXT | Type::BLOB | Type::JSON    => true,
+            _                                       => false,
+        }
+    }
+
+    /// Type's values are stored in child columns
+    pub fn is_nested(self) -> bool {
+        match self {
+            Type::LIST | Type::STRUCT | Type::MAP   => true,
+            _                                       => false,
         }
     }
 
@@ -147,8 +255,41 @@ impl Type {
             Type::BOOLEAN   => BOOLEAN.size_of(),
             Type::TEXT      => TEXT.size_of(),
             Type::BLOB      => BLOB.size_of(),
+            Type::JSON      => JSON.size_of(),
+            Type::TIMESTAMP     => TIMESTAMP.size_of(),
+            Type::TIMESTAMP_TZ  => TIMESTAMP_TZ.size_of(),
+            Type::LIST      => LIST.size_of(),
+            Type::STRUCT    => STRUCT.size_of(),
+            Type::MAP       => MAP.size_of(),
         }
     }
+
+    /// Required alignment of the column row data
+    pub fn align_of(self) -> usize {
+        match self {
+            Type::UINT32    => UINT32.align_of(),
+            Type::UINT64    => UINT64.align_of(),
+            Type::INT32     => INT32.align_of(),
+            Type::INT64     => INT64.align_of(),
+            Type::FLOAT32   => FLOAT32.align_of(),
+            Type::FLOAT64   => FLOAT64.align_of(),
+            Type::BOOLEAN   => BOOLEAN.align_of(),
+            Type::TEXT      => TEXT.align_of(),
+            Type::BLOB      => BLOB.align_of(),
+            Type::JSON      => JSON.align_of(),
+            Type::TIMESTAMP     => TIMESTAMP.align_of(),
+            Type::TIMESTAMP_TZ  => TIMESTAMP_TZ.align_of(),
+            Type::LIST      => LIST.align_of(),
+            Type::STRUCT    => STRUCT.align_of(),
+            Type::MAP       => MAP.align_of(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 impl str::FromStr for Type {
@@ -164,6 +305,12 @@ impl str::FromStr for Type {
             "BOOLEAN" => Ok(Type::BOOLEAN),
             "TEXT"    => Ok(Type::TEXT),
             "BLOB"    => Ok(Type::BLOB),
+            "JSON"    => Ok(Type::JSON),
+            "TIMESTAMP"     => Ok(Type::TIMESTAMP),
+            "TIMESTAMP_TZ"  => Ok(Type::TIMESTAMP_TZ),
+            "LIST"    => Ok(Type::LIST),
+            "STRUCT"  => Ok(Type::STRUCT),
+            "MAP"     => Ok(Type::MAP),
             _         => Err(DBError::UnknownType(String::from(s)))
         }
     }
@@ -195,7 +342,15 @@ impl ToString for RawData {
 pub struct NullType { }
 pub const NULL_VALUE: NullType = NullType {};
 
-/// Container storing any kind of value
+/// Container storing any kind of value.
+///
+/// Variable length values either borrow the data (eg. from a `Column`) or own it; `Value<'static>`
+/// is always an owned value.
+///
+/// Comparison follows column comparison semantics: values are only comparable to values of the same
+/// type (no implicit casts), TEXT / JSON / BLOB compare bytewise and nested values compare
+/// lexicographically. NULL is equal to NULL and orders before any other value.
+#[derive(Clone, Debug)]
 pub enum Value<'a> {
     NULL,
     UINT32(u32),
@@ -205,8 +360,256 @@ pub enum Value<'a> {
     FLOAT32(f32),
     FLOAT64(f64),
     BOOLEAN(bool),
-    TEXT(&'a str),
-    BLOB(&'a [u8]),
+    TEXT(Cow<'a, str>),
+    BLOB(Cow<'a, [u8]>),
+    JSON(Cow<'a, str>),
+    /// Microseconds since 1970-01-01 00:00:00
+    TIMESTAMP(i64),
+    /// Microseconds since 1970-01-01 00:00:00 UTC
+    #[allow(non_camel_case_types)]
+    TIMESTAMP_TZ(i64),
+    LIST(Vec<Value<'a>>),
+    /// Field values in attribute order
+    STRUCT(Vec<Value<'a>>),
+    /// Key, value pairs
+    MAP(Vec<(Value<'a>, Value<'a>)>),
+}
+
+impl<'a> Value<'a> {
+    /// Symbolic type of the value. NULL values are untyped.
+    pub fn dtype(&self) -> Option<Type> {
+        match *self {
+            Value::NULL         => None,
+            Value::UINT32(_)    => Some(Type::UINT32),
+            Value::UINT64(_)    => Some(Type::UINT64),
+            Value::INT32(_)     => Some(Type::INT32),
+            Value::INT64(_)     => Some(Type::INT64),
+            Value::FLOAT32(_)   => Some(Type::FLOAT32),
+            Value::FLOAT64(_)   => Some(Type::FLOAT64),
+            Value::BOOLEAN(_)   => Some(Type::BOOLEAN),
+            Value::TEXT(_)      => Some(Type::TEXT),
+            Value::BLOB(_)      => Some(Type::BLOB),
+            Value::JSON(_)      => Some(Type::JSON),
+            Value::TIMESTAMP(_)     => Some(Type::TIMESTAMP),
+            Value::TIMESTAMP_TZ(_)  => Some(Type::TIMESTAMP_TZ),
+            Value::LIST(_)      => Some(Type::LIST),
+            Value::STRUCT(_)    => Some(Type::STRUCT),
+            Value::MAP(_)       => Some(Type::MAP),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match *self {
+            Value::NULL => true,
+            _           => false,
+        }
+    }
+
+    /// Convert into a value that doesn't borrow any data
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::NULL         => Value::NULL,
+            Value::UINT32(v)    => Value::UINT32(v),
+            Value::UINT64(v)    => Value::UINT64(v),
+            Value::INT32(v)     => Value::INT32(v),
+            Value::INT64(v)     => Value::INT64(v),
+            Value::FLOAT32(v)   => Value::FLOAT32(v),
+            Value::FLOAT64(v)   => Value::FLOAT64(v),
+            Value::BOOLEAN(v)   => Value::BOOLEAN(v),
+            Value::TEXT(v)      => Value::TEXT(Cow::Owned(v.into_owned())),
+            Value::BLOB(v)      => Value::BLOB(Cow::Owned(v.into_owned())),
+            Value::JSON(v)      => Value::JSON(Cow::Owned(v.into_owned())),
+            Value::TIMESTAMP(v)     => Value::TIMESTAMP(v),
+            Value::TIMESTAMP_TZ(v)  => Value::TIMESTAMP_TZ(v),
+            Value::LIST(v)      => Value::LIST(v.into_iter().map(|e| e.into_owned()).collect()),
+            Value::STRUCT(v)    => Value::STRUCT(v.into_iter().map(|e| e.into_owned()).collect()),
+            Value::MAP(v)       => Value::MAP(v.into_iter()
+                                                .map(|(k, e)| (k.into_owned(), e.into_owned()))
+                                                .collect()),
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self { Value::UINT32(v) => Some(v), _ => None }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self { Value::UINT64(v) => Some(v), _ => None }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self { Value::INT32(v) => Some(v), _ => None }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self { Value::INT64(v) => Some(v), _ => None }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self { Value::FLOAT32(v) => Some(v), _ => None }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self { Value::FLOAT64(v) => Some(v), _ => None }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self { Value::BOOLEAN(v) => Some(v), _ => None }
+    }
+
+    /// Microseconds of a TIMESTAMP or TIMESTAMP_TZ value
+    pub fn as_timestamp(&self) -> Option<i64> {
+        match *self {
+            Value::TIMESTAMP(v) | Value::TIMESTAMP_TZ(v)    => Some(v),
+            _                                               => None,
+        }
+    }
+
+    /// TEXT or JSON value
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::TEXT(ref v) | Value::JSON(ref v) => Some(v),
+            _                                       => None,
+        }
+    }
+
+    /// Bytes of a BLOB, TEXT or JSON value
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::BLOB(ref v)                      => Some(v),
+            Value::TEXT(ref v) | Value::JSON(ref v) => Some(v.as_bytes()),
+            _                                       => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        match *self { Value::LIST(ref v) => Some(v), _ => None }
+    }
+
+    pub fn as_struct(&self) -> Option<&[Value<'a>]> {
+        match *self { Value::STRUCT(ref v) => Some(v), _ => None }
+    }
+
+    pub fn as_map(&self) -> Option<&[(Value<'a>, Value<'a>)]> {
+        match *self { Value::MAP(ref v) => Some(v), _ => None }
+    }
+}
+
+impl<'a, 'b> PartialEq<Value<'b>> for Value<'a> {
+    fn eq(&self, other: &Value<'b>) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl<'a, 'b> PartialOrd<Value<'b>> for Value<'a> {
+    fn partial_cmp(&self, other: &Value<'b>) -> Option<Ordering> {
+        match (self, other) {
+            (&Value::NULL, &Value::NULL)                    => Some(Ordering::Equal),
+            (&Value::NULL, _)                               => Some(Ordering::Less),
+            (_, &Value::NULL)                               => Some(Ordering::Greater),
+            (&Value::UINT32(l), &Value::UINT32(r))          => l.partial_cmp(&r),
+            (&Value::UINT64(l), &Value::UINT64(r))          => l.partial_cmp(&r),
+            (&Value::INT32(l), &Value::INT32(r))            => l.partial_cmp(&r),
+            (&Value::INT64(l), &Value::INT64(r))            => l.partial_cmp(&r),
+            (&Value::FLOAT32(l), &Value::FLOAT32(r))        => l.partial_cmp(&r),
+            (&Value::FLOAT64(l), &Value::FLOAT64(r))        => l.partial_cmp(&r),
+            (&Value::BOOLEAN(l), &Value::BOOLEAN(r))        => l.partial_cmp(&r),
+            (&Value::TIMESTAMP(l), &Value::TIMESTAMP(r))    |
+            (&Value::TIMESTAMP_TZ(l), &Value::TIMESTAMP_TZ(r)) => l.partial_cmp(&r),
+            (&Value::TEXT(ref l), &Value::TEXT(ref r))      => l.as_bytes().partial_cmp(r.as_bytes()),
+            (&Value::JSON(ref l), &Value::JSON(ref r))      => l.as_bytes().partial_cmp(r.as_bytes()),
+            (&Value::BLOB(ref l), &Value::BLOB(ref r))      => (**l).partial_cmp(&**r),
+            (&Value::LIST(ref l), &Value::LIST(ref r))      => cmp_seq(l.iter(), r.iter()),
+            (&Value::STRUCT(ref l), &Value::STRUCT(ref r))  => cmp_seq(l.iter(), r.iter()),
+            // Entry by entry, the key before the value
+            (&Value::MAP(ref l), &Value::MAP(ref r))        => {
+                let lhs = l.iter().flat_map(|&(ref k, ref v)| once(k).chain(once(v)));
+                let rhs = r.iter().flat_map(|&(ref k, ref v)| once(k).chain(once(v)));
+                cmp_seq(lhs, rhs)
+            }
+            _                                               => None,
+        }
+    }
+}
+
+/// Human readable value. Strings are printed as is, unless nested, BLOBs in hex (`\x0aff`),
+/// timestamps as `2024-01-02 03:04:05.5` (TIMESTAMP_TZ in UTC, with `+00:00`), nested values as `[1, 2]` (LIST), `{1, "a"}` (STRUCT) and `{"k": 1}` (MAP).
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_value(self, f, false)
+    }
+}
+
+fn fmt_value(value: &Value, f: &mut fmt::Formatter, nested: bool) -> fmt::Result {
+    fn seq<'a, I: Iterator<Item=&'a Value<'a>>>(f: &mut fmt::Formatter, values: I) -> fmt::Result {
+        for (idx, v) in values.enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            fmt_value(v, f, true)?;
+        }
+        Ok(())
+    }
+
+    match *value {
+        Value::NULL                             => f.write_str("NULL"),
+        Value::UINT32(v)                        => write!(f, "{}", v),
+        Value::UINT64(v)                        => write!(f, "{}", v),
+        Value::INT32(v)                         => write!(f, "{}", v),
+        Value::INT64(v)                         => write!(f, "{}", v),
+        Value::FLOAT32(v)                       => write!(f, "{}", v),
+        Value::FLOAT64(v)                       => write!(f, "{}", v),
+        Value::BOOLEAN(v)                       => write!(f, "{}", v),
+        Value::TEXT(ref v) | Value::JSON(ref v) => if nested { write!(f, "{:?}", v) } else { f.write_str(v) },
+        Value::TIMESTAMP(v)                     => f.write_str(&datetime::format(v)),
+        Value::TIMESTAMP_TZ(v)                  => write!(f, "{}+00:00", datetime::format(v)),
+        Value::BLOB(ref v)                      => {
+            f.write_str("\\x")?;
+            for b in v.iter() {
+                write!(f, "{:02x}", b)?;
+            }
+            Ok(())
+        }
+        Value::LIST(ref v)                      => {
+            f.write_str("[")?;
+            seq(f, v.iter())?;
+            f.write_str("]")
+        }
+        Value::STRUCT(ref v)                    => {
+            f.write_str("{")?;
+            seq(f, v.iter())?;
+            f.write_str("}")
+        }
+        Value::MAP(ref v)                       => {
+            f.write_str("{")?;
+            for (idx, &(ref k, ref v)) in v.iter().enumerate() {
+                if idx > 0 {
+                    f.write_str(", ")?;
+                }
+                fmt_value(k, f, true)?;
+                f.write_str(": ")?;
+                fmt_value(v, f, true)?;
+            }
+            f.write_str("}")
+        }
+    }
+}
+
+/// Lexicographic comparison of value sequences
+fn cmp_seq<'a, 'b, L, R>(mut lhs: L, mut rhs: R) -> Option<Ordering>
+    where L: Iterator<Item=&'a Value<'a>>, R: Iterator<Item=&'b Value<'b>>, 'a: 'b
+{
+    loop {
+        match (lhs.next(), rhs.next()) {
+            (None, None)        => return Some(Ordering::Equal),
+            (None, Some(_))     => return Some(Ordering::Less),
+            (Some(_), None)     => return Some(Ordering::Greater),
+            (Some(l), Some(r))  => match l.partial_cmp(r) {
+                Some(Ordering::Equal)   => continue,
+                other                   => return other,
+            },
+        }
+    }
 }
 
 impl<'a> From<NullType> for Value<'a> {
@@ -251,14 +654,99 @@ impl<'a> From<f64> for Value<'a> {
     }
 }
 
+impl<'a> From<bool> for Value<'a> {
+    fn from(v: bool) -> Self {
+        Value::BOOLEAN(v)
+    }
+}
+
 impl<'a> From<&'a str> for Value<'a> {
     fn from(v: &'a str) -> Self {
-        Value::TEXT(v)
+        Value::TEXT(Cow::Borrowed(v))
+    }
+}
+
+impl<'a> From<String> for Value<'a> {
+    fn from(v: String) -> Self {
+        Value::TEXT(Cow::Owned(v))
     }
 }
 
 impl<'a> From<&'a [u8]> for Value<'a> {
     fn from(v: &'a [u8]) -> Self {
-        Value::BLOB(v)
+        Value::BLOB(Cow::Borrowed(v))
+    }
+}
+
+impl<'a> From<Vec<u8>> for Value<'a> {
+    fn from(v: Vec<u8>) -> Self {
+        Value::BLOB(Cow::Owned(v))
+    }
+}
+
+/// `None` becomes NULL
+impl<'a, T: Into<Value<'a>>> From<Option<T>> for Value<'a> {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::NULL, |v| v.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_accessors() {
+        let v = Value::from("abc");
+        assert_eq!(v.dtype(), Some(Type::TEXT));
+        assert_eq!(v.as_str(), Some("abc"));
+        assert_eq!(v.as_u32(), None);
+
+        let owned: Value<'static> = v.clone().into_owned();
+        assert_eq!(owned, v);
+
+        assert!(Value::from(None::<u32>).is_null());
+        assert_eq!(Value::from(Some(5 as u32)).as_u32(), Some(5));
+    }
+
+    #[test]
+    fn value_ordering() {
+        assert!(Value::INT32(1) < Value::INT32(2));
+        assert!(Value::NULL < Value::INT32(-10));
+        assert_eq!(Value::NULL, Value::NULL);
+        assert_eq!(Value::INT32(1).partial_cmp(&Value::INT64(1)), None);
+        assert!(Value::from("ab") < Value::from("b"));
+
+        let short = Value::LIST(vec![Value::INT32(1)]);
+        let long = Value::LIST(vec![Value::INT32(1), Value::INT32(0)]);
+        assert!(short < long);
+
+        let s = |a: i32, b: &'static str| Value::STRUCT(vec![Value::INT32(a), Value::from(b)]);
+        assert!(s(1, "b") < s(2, "a"));
+        assert!(s(1, "a") < s(1, "b"));
+        assert_eq!(s(1, "a"), s(1, "a"));
+
+        let m = |entries: &[(u32, Option<bool>)]| Value::MAP(entries.iter()
+            .map(|&(k, v)| (Value::UINT32(k), Value::from(v)))
+            .collect());
+        assert!(m(&[(1, Some(true))]) < m(&[(2, Some(false))]));
+        assert!(m(&[(1, None)]) < m(&[(1, Some(false))]));
+        assert!(m(&[(1, Some(true))]) < m(&[(1, Some(true)), (0, None)]));
+        assert_eq!(m(&[]).partial_cmp(&m(&[])), Some(Ordering::Equal));
+        assert_eq!(m(&[(1, None)]).partial_cmp(&Value::MAP(vec![(Value::INT32(1), Value::NULL)])), None);
+    }
+
+    #[test]
+    fn value_display() {
+        assert_eq!(Value::NULL.to_string(), "NULL");
+        assert_eq!(Value::from("a \"b\"").to_string(), "a \"b\"");
+        assert_eq!(Value::from(vec![0u8, 10, 255]).to_string(), "\\x000aff");
+        assert_eq!(Value::FLOAT64(1.5).to_string(), "1.5");
+
+        let nested = Value::LIST(vec![
+            Value::STRUCT(vec![Value::INT32(1), Value::from("x")]),
+            Value::MAP(vec![(Value::from("k"), Value::NULL)]),
+        ]);
+        assert_eq!(nested.to_string(), r#"[{1, "x"}, {"k": NULL}]"#);
     }
 }
@@ -0,0 +1,194 @@
+// vim : set ts=4 sw=4 et :
+
+//! Word-at-a-time kernels for bit-packed bitmaps (least significant bit first).
+//!
+//! Kernels work on whole bytes; callers are responsible for the trailing bits past the bitmap's
+//! length. The inner loops are simple enough for the compiler to vectorize.
+
+use std::ptr;
+
+/// Number of bytes required to store a bitmap of `bits`
+#[inline]
+pub fn bytes_for(bits: usize) -> usize {
+    (bits + 7) / 8
+}
+
+#[inline]
+pub fn get_bit(data: &[u8], idx: usize) -> bool {
+    data[idx >> 3] & (1 << (idx & 7)) != 0
+}
+
+#[inline]
+pub fn set_bit(data: &mut [u8], idx: usize, value: bool) {
+    let mask = 1 << (idx & 7);
+    if value {
+        data[idx >> 3] |= mask;
+    } else {
+        data[idx >> 3] &= !mask;
+    }
+}
+
+/// Read one 64bit word out of the data starting at byte `pos`
+#[inline]
+pub fn read_word(data: &[u8], pos: usize) -> u64 {
+    assert!(pos + 8 <= data.len());
+    unsafe { u64::from_le(ptr::read_unaligned(data.as_ptr().offset(pos as isize) as *const u64)) }
+}
+
+#[inline]
+pub fn write_word(data: &mut [u8], pos: usize, word: u64) {
+    assert!(pos + 8 <= data.len());
+    unsafe { ptr::write_unaligned(data.as_mut_ptr().offset(pos as isize) as *mut u64, word.to_le()) }
+}
+
+/// Apply `op` to each pair of bytes (word at a time): dst = op(dst, src)
+#[inline]
+fn binary<F: Fn(u64, u64) -> u64>(dst: &mut [u8], src: &[u8], op: F) {
+    let len = dst.len().min(src.len());
+    let words = len / 8;
+
+    for w in 0 .. words {
+        let word = op(read_word(dst, w * 8), read_word(src, w * 8));
+        write_word(dst, w * 8, word);
+    }
+
+    for pos in words * 8 .. len {
+        dst[pos] = op(dst[pos] as u64, src[pos] as u64) as u8;
+    }
+}
+
+/// dst &= src
+pub fn and(dst: &mut [u8], src: &[u8]) {
+    binary(dst, src, |l, r| l & r)
+}
+
+/// dst |= src
+pub fn or(dst: &mut [u8], src: &[u8]) {
+    binary(dst, src, |l, r| l | r)
+}
+
+/// dst &= !src
+pub fn and_not(dst: &mut [u8], src: &[u8]) {
+    binary(dst, src, |l, r| l & !r)
+}
+
+/// dst = !dst
+pub fn not(dst: &mut [u8]) {
+    let words = dst.len() / 8;
+
+    for w in 0 .. words {
+        let word = !read_word(dst, w * 8);
+        write_word(dst, w * 8, word);
+    }
+
+    for byte in &mut dst[words * 8 ..] {
+        *byte = !*byte;
+    }
+}
+
+/// Number of set bits in the first `bits` bits
+pub fn count_ones(data: &[u8], bits: usize) -> usize {
+    let words = bits / 64;
+    let mut count = 0;
+
+    for w in 0 .. words {
+        count += read_word(data, w * 8).count_ones() as usize;
+    }
+
+    for idx in words * 64 .. bits {
+        count += get_bit(data, idx) as usize;
+    }
+
+    count
+}
+
+/// Position of the first bit in [from, bits) equal to `value`
+pub fn find_first(data: &[u8], from: usize, bits: usize, value: bool) -> Option<usize> {
+    let flip = if value { 0 } else { !0 };
+    let mut idx = from;
+
+    // Unaligned head
+    while idx < bits && idx & 63 != 0 {
+        if get_bit(data, idx) == value {
+            return Some(idx)
+        }
+        idx += 1;
+    }
+
+    while idx + 64 <= bits {
+        let word = read_word(data, idx >> 3) ^ flip;
+        if word != 0 {
+            return Some(idx + word.trailing_zeros() as usize)
+        }
+        idx += 64;
+    }
+
+    while idx < bits {
+        if get_bit(data, idx) == value {
+            return Some(idx)
+        }
+        idx += 1;
+    }
+
+    None
+}
+
+/// Position of the first set bit in [from, bits)
+pub fn find_first_set(data: &[u8], from: usize, bits: usize) -> Option<usize> {
+    find_first(data, from, bits, true)
+}
+
+/// Iterator over runs of consecutive set bits, yielding (start, len)
+pub struct SetRuns<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: usize,
+}
+
+/// Runs of consecutive set bits in [from, bits)
+pub fn set_runs(data: &[u8], from: usize, bits: usize) -> SetRuns {
+    SetRuns { data: data, pos: from, bits: bits }
+}
+
+impl<'a> Iterator for SetRuns<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let start = find_first(self.data, self.pos, self.bits, true)?;
+        let end = find_first(self.data, start, self.bits, false).unwrap_or(self.bits);
+
+        self.pos = end;
+        Some((start, end - start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels() {
+        let mut data = [0u8; 20];
+        for idx in (3 .. 9).chain(70 .. 140) {
+            set_bit(&mut data, idx, true);
+        }
+
+        assert_eq!(count_ones(&data, 160), 76);
+        assert_eq!(count_ones(&data, 100), 36);
+        assert_eq!(find_first_set(&data, 9, 160), Some(70));
+        assert_eq!(find_first(&data, 70, 160, false), Some(140));
+        assert_eq!(set_runs(&data, 0, 160).collect::<Vec<_>>(), vec![(3, 6), (70, 70)]);
+        assert_eq!(set_runs(&data, 5, 100).collect::<Vec<_>>(), vec![(5, 4), (70, 30)]);
+
+        let mut other = data;
+        not(&mut other);
+        assert_eq!(count_ones(&other, 160), 160 - 76);
+
+        and(&mut other, &data);
+        assert_eq!(count_ones(&other, 160), 0);
+
+        or(&mut other, &data);
+        and_not(&mut other, &data);
+        assert_eq!(find_first_set(&other, 0, 160), None);
+    }
+}
@@ -0,0 +1,174 @@
+// vim : set ts=4 sw=4 et :
+
+//! Bloom filters of row hashes (`row_hash::hash_rows`).
+//!
+//! Built from the keys of one input (eg. the build side of a hash join) and probed with the keys of
+//! another to drop rows that can't match before they're passed on. There are no false negatives;
+//! the false positive rate depends on the bits per key (about 1% at 10 bits and 7 probes).
+
+use std::mem;
+use std::sync::Arc;
+
+use ::allocator::Allocator;
+use ::block::{Block, View, filter_view};
+use ::error::DBError;
+use ::schema::{Attribute, Schema};
+use ::types::{Boolean, Type};
+use ::util::row_hash::hash_rows;
+
+/// Bits per key of `BloomFilter::with_keys`
+pub const BITS_PER_KEY: usize = 10;
+
+/// Fixed size set of hashes
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    words: Vec<u64>,
+    /// Bit count - 1; a power of two
+    mask: u64,
+    probes: u32,
+}
+
+impl BloomFilter {
+    /// Filter of at least `bits` bits (rounded up to a power of two), setting `probes` bits per hash
+    pub fn new(bits: usize, probes: u32) -> BloomFilter {
+        let bits = bits.max(64).next_power_of_two();
+        BloomFilter { words: vec![0; bits / 64], mask: bits as u64 - 1, probes: probes.max(1) }
+    }
+
+    /// Filter sized for `keys` keys
+    pub fn with_keys(keys: usize) -> BloomFilter {
+        BloomFilter::new(keys * BITS_PER_KEY, 7)
+    }
+
+    pub fn bits(&self) -> usize {
+        self.words.len() * 64
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.words.capacity() * mem::size_of::<u64>()
+    }
+
+    /// Bit positions of the hash (double hashing)
+    #[inline]
+    fn positions(&self, hash: u64) -> (u64, u64) {
+        (hash, hash.rotate_left(32) | 1)
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        let (mut bit, step) = self.positions(hash);
+        for _ in 0 .. self.probes {
+            let pos = bit & self.mask;
+            self.words[(pos >> 6) as usize] |= 1 << (pos & 63);
+            bit = bit.wrapping_add(step);
+        }
+    }
+
+    /// False if the hash was never inserted; true if it might have been
+    pub fn contains(&self, hash: u64) -> bool {
+        let (mut bit, step) = self.positions(hash);
+        let mut found = true;
+        for _ in 0 .. self.probes {
+            let pos = bit & self.mask;
+            found &= self.words[(pos >> 6) as usize] & (1 << (pos & 63)) != 0;
+            bit = bit.wrapping_add(step);
+        }
+        found
+    }
+
+    pub fn insert_all(&mut self, hashes: &[u64]) {
+        for &hash in hashes {
+            self.insert(hash);
+        }
+    }
+}
+
+/// Bloom filter of the hashes of key columns. Rows of other views are tested by the hash of the
+/// same (typed) columns, by position.
+#[derive(Clone, Debug)]
+pub struct KeyFilter {
+    pub columns: Vec<usize>,
+    pub bloom: Arc<BloomFilter>,
+}
+
+impl KeyFilter {
+    /// Filter of the keys of all the rows of the view
+    pub fn build<'v>(view: &'v View<'v>, columns: Vec<usize>) -> Result<KeyFilter, DBError> {
+        let mut bloom = BloomFilter::with_keys(view.rows());
+        bloom.insert_all(&hash_rows(view, &columns)?);
+        Ok(KeyFilter { columns: columns, bloom: Arc::new(bloom) })
+    }
+
+    /// The same filter tested with other key columns
+    pub fn with_columns(&self, columns: Vec<usize>) -> KeyFilter {
+        KeyFilter { columns: columns, bloom: self.bloom.clone() }
+    }
+
+    /// Rows of the view that might have their key in the filter
+    pub fn matches<'v>(&self, view: &'v View<'v>) -> Result<Vec<bool>, DBError> {
+        Ok(hash_rows(view, &self.columns)?.iter().map(|h| self.bloom.contains(*h)).collect())
+    }
+
+    /// Copy of the rows of the view that might have their key in the filter
+    pub fn apply<'a, 'v>(&self, alloc: &'a Allocator, view: &'v View<'v>) -> Result<Block<'a>, DBError> {
+        let matches = self.matches(view)?;
+
+        let mut mask = Block::new(alloc, &Schema::from_attr(Attribute::new("mask", false, Type::BOOLEAN)));
+        if !matches.is_empty() {
+            mask.add_rows(matches.len())?;
+            mask[0].rows_mut::<Boolean>()?[.. matches.len()].copy_from_slice(&matches);
+        }
+
+        filter_view(alloc, view, mask.column(0).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::column_value;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+    use ::util::row_hash::mix;
+
+    #[test]
+    fn bloom() {
+        let mut bloom = BloomFilter::with_keys(1000);
+        assert_eq!(bloom.bits(), 16384);
+
+        for key in 0 .. 1000u64 {
+            bloom.insert(mix(key));
+        }
+
+        // No false negatives, few false positives
+        assert!((0 .. 1000u64).all(|key| bloom.contains(mix(key))));
+        let false_positives = (1000 .. 11000u64).filter(|key| bloom.contains(mix(*key))).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        assert!(!BloomFilter::new(0, 3).contains(mix(1)));
+    }
+
+    #[test]
+    fn key_filter() {
+        let schema = Schema::parse_ddl("id INT64, name TEXT").unwrap();
+        let mut build = Block::new(&allocator::GLOBAL, &schema);
+        build.add_rows(3).unwrap();
+        for (row, &id) in [2i64, 4, 6].iter().enumerate() {
+            set_column_value(&mut build, 0, row, &Value::INT64(id)).unwrap();
+        }
+
+        let mut probe = Block::new(&allocator::GLOBAL, &Schema::parse_ddl("v TEXT, key INT64").unwrap());
+        probe.add_rows(8).unwrap();
+        for row in 0 .. 8 {
+            set_column_value(&mut probe, 1, row, &Value::INT64(row as i64)).unwrap();
+        }
+
+        let filter = KeyFilter::build(&build, vec![0]).unwrap().with_columns(vec![1]);
+        assert_eq!(filter.matches(&probe).unwrap(),
+                   vec![false, false, true, false, true, false, true, false]);
+
+        let out = filter.apply(&allocator::GLOBAL, &probe).unwrap();
+        assert_eq!(out.rows(), 3);
+        assert_eq!(column_value(out.column(1).unwrap(), 2).unwrap(), Value::INT64(6));
+    }
+}
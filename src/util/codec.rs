@@ -0,0 +1,204 @@
+// vim : set ts=4 sw=4 et :
+
+//! Compression codecs used by the block serialization.
+//!
+//! Built-in codecs are LZ4 and Snappy, plus Zstd with the `zstd` feature. Other codecs can be
+//! added by implementing `Codec` and registering them in `Codecs` used for deserialization.
+
+use ::error::DBError;
+use ::util::{lz4, snappy};
+
+/// Compression codec
+pub trait Codec {
+    /// Identifier stored in the serialized data. 0 is reserved for uncompressed data, the built-in
+    /// codecs use 1 - 15.
+    fn id(&self) -> u8;
+    fn name(&self) -> &str;
+
+    fn compress(&self, src: &[u8]) -> Result<Vec<u8>, DBError>;
+    /// Decompress into exactly `len` bytes
+    fn decompress(&self, src: &[u8], len: usize) -> Result<Vec<u8>, DBError>;
+}
+
+pub const LZ4_ID: u8 = 1;
+pub const SNAPPY_ID: u8 = 2;
+pub const ZSTD_ID: u8 = 3;
+
+fn corrupt(name: &str) -> DBError {
+    DBError::Serialization(format!("malformed {} data", name))
+}
+
+/// LZ4 block compression; fast, moderate compression ratio
+pub struct Lz4;
+
+impl Codec for Lz4 {
+    fn id(&self) -> u8 {
+        LZ4_ID
+    }
+
+    fn name(&self) -> &str {
+        "lz4"
+    }
+
+    fn compress(&self, src: &[u8]) -> Result<Vec<u8>, DBError> {
+        Ok(lz4::compress(src))
+    }
+
+    fn decompress(&self, src: &[u8], len: usize) -> Result<Vec<u8>, DBError> {
+        lz4::decompress(src, len).ok_or_else(|| corrupt(self.name()))
+    }
+}
+
+/// Raw Snappy compression
+pub struct Snappy;
+
+impl Codec for Snappy {
+    fn id(&self) -> u8 {
+        SNAPPY_ID
+    }
+
+    fn name(&self) -> &str {
+        "snappy"
+    }
+
+    fn compress(&self, src: &[u8]) -> Result<Vec<u8>, DBError> {
+        Ok(snappy::compress(src))
+    }
+
+    fn decompress(&self, src: &[u8], len: usize) -> Result<Vec<u8>, DBError> {
+        snappy::decompress(src)
+            .filter(|out| out.len() == len)
+            .ok_or_else(|| corrupt(self.name()))
+    }
+}
+
+/// Zstd compression; slower, better compression ratio
+#[cfg(feature = "zstd")]
+pub struct Zstd {
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Zstd {
+        Zstd { level: 3 }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    fn id(&self) -> u8 {
+        ZSTD_ID
+    }
+
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    fn compress(&self, src: &[u8]) -> Result<Vec<u8>, DBError> {
+        Ok(::zstd::block::compress(src, self.level)?)
+    }
+
+    fn decompress(&self, src: &[u8], len: usize) -> Result<Vec<u8>, DBError> {
+        ::zstd::block::decompress(src, len)
+            .ok()
+            .filter(|out| out.len() == len)
+            .ok_or_else(|| corrupt(self.name()))
+    }
+}
+
+/// Codecs available for deserialization, by id
+pub struct Codecs {
+    codecs: Vec<Box<Codec>>,
+}
+
+impl Default for Codecs {
+    /// Built-in codecs
+    fn default() -> Codecs {
+        #[allow(unused_mut)]
+        let mut codecs: Vec<Box<Codec>> = vec![Box::new(Lz4), Box::new(Snappy)];
+        #[cfg(feature = "zstd")]
+        codecs.push(Box::new(Zstd::default()));
+
+        Codecs { codecs: codecs }
+    }
+}
+
+impl Codecs {
+    /// No codecs; only uncompressed data can be read
+    pub fn empty() -> Codecs {
+        Codecs { codecs: Vec::new() }
+    }
+
+    /// Add a codec. Fails if there's already a codec with the same id (or id 0).
+    pub fn register(&mut self, codec: Box<Codec>) -> Result<(), DBError> {
+        if codec.id() == 0 || self.get(codec.id()).is_some() {
+            return Err(DBError::Serialization(format!("duplicate codec id {} ({})", codec.id(), codec.name())))
+        }
+
+        self.codecs.push(codec);
+        Ok(())
+    }
+
+    pub fn get(&self, id: u8) -> Option<&Codec> {
+        self.codecs.iter()
+            .find(|c| c.id() == id)
+            .map(|c| &**c)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Codec> {
+        self.codecs.iter()
+            .find(|c| c.name() == name)
+            .map(|c| &**c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Reverse;
+
+    impl Codec for Reverse {
+        fn id(&self) -> u8 {
+            100
+        }
+
+        fn name(&self) -> &str {
+            "reverse"
+        }
+
+        fn compress(&self, src: &[u8]) -> Result<Vec<u8>, DBError> {
+            Ok(src.iter().rev().cloned().collect())
+        }
+
+        fn decompress(&self, src: &[u8], _: usize) -> Result<Vec<u8>, DBError> {
+            self.compress(src)
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let mut codecs = Codecs::default();
+        assert_eq!(codecs.get(LZ4_ID).unwrap().name(), "lz4");
+        assert!(codecs.get(100).is_none());
+
+        codecs.register(Box::new(Reverse)).unwrap();
+        assert!(codecs.register(Box::new(Reverse)).is_err());
+        assert!(codecs.register(Box::new(Lz4)).is_err());
+
+        let mut names = vec!["lz4", "snappy", "reverse"];
+        if cfg!(feature = "zstd") {
+            names.push("zstd");
+        }
+
+        let data = b"hello hello hello hello".to_vec();
+        for name in names {
+            let codec = codecs.by_name(name).unwrap();
+            let compressed = codec.compress(&data).unwrap();
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+        }
+
+        assert!(codecs.get(SNAPPY_ID).unwrap().decompress(&[5, 0], 5).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+// vim : set ts=4 sw=4 et :
+
+//! TEXT collations: how strings are ordered and which strings are equal.
+//!
+//! `Collation` is the (cloneable, comparable) description, `Collator` is the resolved comparator
+//! created once per operation. Collations other than BINARY apply to TEXT (and JSON) values only;
+//! BLOBs are always compared bytewise.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "icu")]
+use std::sync::Arc;
+
+use ::error::DBError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Collation {
+    /// Bytewise (UTF-8 code point order)
+    BINARY,
+    /// Bytewise after ASCII lower casing
+    NOCASE,
+    /// Locale aware ICU collation, eg. `de` or `sv-SE`, optionally with a strength (`de:primary`)
+    #[cfg(feature = "icu")]
+    LOCALE(String),
+}
+
+impl Default for Collation {
+    fn default() -> Collation {
+        Collation::BINARY
+    }
+}
+
+impl Collation {
+    pub fn is_binary(&self) -> bool {
+        *self == Collation::BINARY
+    }
+
+    pub fn collator(&self) -> Result<Collator, DBError> {
+        let kind = match *self {
+            Collation::BINARY           => Kind::BINARY,
+            Collation::NOCASE           => Kind::NOCASE,
+            #[cfg(feature = "icu")]
+            Collation::LOCALE(ref name) => Kind::LOCALE(Arc::new(icu::Collator::new(name)?)),
+        };
+
+        Ok(Collator { kind: kind })
+    }
+}
+
+/// `BINARY`, `NOCASE` or `ICU:<locale>`
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Collation::BINARY           => f.write_str("BINARY"),
+            Collation::NOCASE           => f.write_str("NOCASE"),
+            #[cfg(feature = "icu")]
+            Collation::LOCALE(ref name) => write!(f, "ICU:{}", name),
+        }
+    }
+}
+
+/// Case insensitive parse of the `Display` format
+impl FromStr for Collation {
+    type Err = DBError;
+
+    fn from_str(s: &str) -> Result<Collation, DBError> {
+        if s.eq_ignore_ascii_case("BINARY") {
+            return Ok(Collation::BINARY)
+        }
+        if s.eq_ignore_ascii_case("NOCASE") {
+            return Ok(Collation::NOCASE)
+        }
+
+        #[cfg(feature = "icu")]
+        {
+            if s.len() > 4 && s[.. 4].eq_ignore_ascii_case("ICU:") {
+                let collation = Collation::LOCALE(s[4 ..].to_string());
+                collation.collator()?;
+                return Ok(collation)
+            }
+        }
+
+        Err(DBError::UnknownType(format!("collation {}", s)))
+    }
+}
+
+#[derive(Clone)]
+enum Kind {
+    BINARY,
+    NOCASE,
+    #[cfg(feature = "icu")]
+    LOCALE(Arc<icu::Collator>),
+}
+
+/// Resolved collation
+#[derive(Clone)]
+pub struct Collator {
+    kind: Kind,
+}
+
+impl Collator {
+    pub fn binary() -> Collator {
+        Collator { kind: Kind::BINARY }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        match self.kind { Kind::BINARY => true, _ => false }
+    }
+
+    pub fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Ordering {
+        match self.kind {
+            Kind::BINARY            => lhs.cmp(rhs),
+            Kind::NOCASE            => lhs.iter().map(u8::to_ascii_lowercase)
+                .cmp(rhs.iter().map(u8::to_ascii_lowercase)),
+            #[cfg(feature = "icu")]
+            Kind::LOCALE(ref c)     => c.compare(lhs, rhs),
+        }
+    }
+
+    pub fn equal(&self, lhs: &[u8], rhs: &[u8]) -> bool {
+        match self.kind {
+            Kind::BINARY            => lhs == rhs,
+            Kind::NOCASE            => lhs.eq_ignore_ascii_case(rhs),
+            #[cfg(feature = "icu")]
+            Kind::LOCALE(_)         => self.compare(lhs, rhs) == Ordering::Equal,
+        }
+    }
+
+    /// Values that are equal under the collation have the same hash key (locale collations use
+    /// ICU sort keys), for hash based grouping and joins.
+    pub fn hash_key<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.kind {
+            Kind::BINARY            => Cow::Borrowed(value),
+            Kind::NOCASE            => Cow::Owned(value.to_ascii_lowercase()),
+            #[cfg(feature = "icu")]
+            Kind::LOCALE(ref c)     => Cow::Owned(c.sort_key(value)),
+        }
+    }
+}
+
+/// Collators of the system ICU, through its C API
+#[cfg(feature = "icu")]
+mod icu {
+    #![allow(non_snake_case)]
+
+    use std::cmp::Ordering;
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int};
+
+    use ::error::DBError;
+
+    pub enum UCollator {}
+    type UErrorCode = c_int;
+
+    // Declarations of ucol_open, ucol_close, ucol_setStrength, ucol_strcollUTF8 and
+    // ucol_getSortKey with the symbols of the ICU version found by build.rs
+    include!(concat!(env!("OUT_DIR"), "/icu_sys.rs"));
+
+    /// Warnings are negative
+    fn failed(status: UErrorCode) -> bool {
+        status > 0
+    }
+
+    /// UCollationStrength, by name
+    fn strength(name: &str) -> Option<c_int> {
+        match &*name.to_ascii_lowercase() {
+            "primary"       => Some(0),
+            "secondary"     => Some(1),
+            "tertiary"      => Some(2),
+            "quaternary"    => Some(3),
+            "identical"     => Some(15),
+            _               => None,
+        }
+    }
+
+    pub struct Collator(*mut UCollator);
+
+    // Collators can be used from multiple threads once opened; only the (unused) setters aren't
+    // thread safe
+    unsafe impl Send for Collator {}
+    unsafe impl Sync for Collator {}
+
+    impl Collator {
+        /// `<locale>[:<strength>]`
+        pub fn new(name: &str) -> Result<Collator, DBError> {
+            let bad = |e: String| DBError::UnknownType(format!("collation ICU:{} ({})", name, e));
+
+            let mut parts = name.splitn(2, ':');
+            let locale = parts.next().unwrap();
+            let strength = match parts.next() {
+                Some(s) => Some(strength(s).ok_or_else(|| bad(format!("unknown strength {}", s)))?),
+                None    => None,
+            };
+
+            let locale = CString::new(locale).map_err(|_| bad("NUL in locale".to_string()))?;
+            let mut status = 0;
+            let coll = unsafe { ucol_open(locale.as_ptr(), &mut status) };
+            if coll.is_null() || failed(status) {
+                return Err(bad(format!("ICU error {}", status)))
+            }
+
+            let out = Collator(coll);
+            if let Some(strength) = strength {
+                unsafe { ucol_setStrength(out.0, strength) };
+            }
+
+            Ok(out)
+        }
+
+        pub fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Ordering {
+            let mut status = 0;
+            let res = unsafe {
+                ucol_strcollUTF8(self.0, lhs.as_ptr() as *const c_char, lhs.len() as i32,
+                                 rhs.as_ptr() as *const c_char, rhs.len() as i32, &mut status)
+            };
+
+            match res {
+                _ if failed(status) => lhs.cmp(rhs),
+                r if r < 0          => Ordering::Less,
+                0                   => Ordering::Equal,
+                _                   => Ordering::Greater,
+            }
+        }
+
+        /// Bytes that compare (bytewise) like the value does under the collation
+        pub fn sort_key(&self, value: &[u8]) -> Vec<u8> {
+            let utf16: Vec<u16> = String::from_utf8_lossy(value).encode_utf16().collect();
+            let mut key = vec![0u8; 16 + utf16.len() * 4];
+
+            loop {
+                let len = unsafe {
+                    ucol_getSortKey(self.0, utf16.as_ptr(), utf16.len() as i32, key.as_mut_ptr(), key.len() as i32)
+                } as usize;
+
+                if len <= key.len() {
+                    key.truncate(len);
+                    return key
+                }
+
+                key = vec![0u8; len];
+            }
+        }
+    }
+
+    impl Drop for Collator {
+        fn drop(&mut self) {
+            unsafe { ucol_close(self.0) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collations() {
+        let binary = Collation::BINARY.collator().unwrap();
+        let nocase = "nocase".parse::<Collation>().unwrap().collator().unwrap();
+
+        assert_eq!(binary.compare(b"B", b"a"), Ordering::Less);
+        assert_eq!(nocase.compare(b"B", b"a"), Ordering::Greater);
+        assert_eq!(nocase.compare(b"ab", b"AB"), Ordering::Equal);
+        assert_eq!(nocase.compare(b"ab", b"ABC"), Ordering::Less);
+        assert!(nocase.equal(b"Hello", b"hELLO") && !binary.equal(b"Hello", b"hELLO"));
+        assert_eq!(nocase.hash_key(b"Hello"), nocase.hash_key(b"hello"));
+
+        assert_eq!(Collation::NOCASE.to_string().parse::<Collation>().unwrap(), Collation::NOCASE);
+        assert!("klingon".parse::<Collation>().is_err());
+    }
+
+    #[cfg(feature = "icu")]
+    #[test]
+    fn locale() {
+        let sv = "ICU:sv".parse::<Collation>().unwrap().collator().unwrap();
+        let en = "icu:en".parse::<Collation>().unwrap().collator().unwrap();
+
+        // Swedish sorts ä after z
+        assert_eq!(sv.compare("ä".as_bytes(), b"z"), Ordering::Greater);
+        assert_eq!(en.compare("ä".as_bytes(), b"z"), Ordering::Less);
+
+        // Sort keys order and hash like the collation compares
+        assert!(sv.hash_key("ä".as_bytes()) > sv.hash_key(b"z"));
+        assert!(en.hash_key("ä".as_bytes()) < en.hash_key(b"z"));
+
+        let primary = "ICU:en:primary".parse::<Collation>().unwrap().collator().unwrap();
+        assert_eq!(primary.compare("Ä".as_bytes(), b"a"), Ordering::Equal);
+        assert_eq!(primary.hash_key("Ä".as_bytes()), primary.hash_key(b"a"));
+        assert!(en.hash_key("Ä".as_bytes()) != en.hash_key(b"a"));
+        assert!("ICU:en:loud".parse::<Collation>().is_err());
+    }
+}
@@ -1,6 +1,7 @@
-use ::block::Column;
+use ::block::{Block, Column, RefColumn};
 use ::error::DBError;
 use ::row::RowOffset;
+use ::schema::{Attribute, Schema};
 use ::types;
 
 /// Trait for setting column row values from rust native types.
@@ -9,11 +10,19 @@ pub trait ValueSetter {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError>;
 }
 
+/// Text values can be stored in TEXT or JSON columns
+fn text_rows_mut<'c, 'a>(col: &'c mut Column<'a>) -> Result<&'c mut [types::RawData], DBError> {
+    if col.attribute().dtype == types::Type::JSON {
+        col.rows_mut::<types::Json>()
+    } else {
+        col.rows_mut::<types::Text>()
+    }
+}
+
 impl ValueSetter for types::NullType {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
-        let rows = col.nulls_mut()?;
-        rows[row] = true as u8;
-        Ok(())
+        col.nulls_mut()?.set(row, true);
+        col.set_default(row)
     }
 }
 
@@ -49,6 +58,22 @@ impl ValueSetter for i64 {
     }
 }
 
+impl ValueSetter for f32 {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        let rows = col.rows_mut::<types::Float32>()?;
+        rows[row] = *self;
+        Ok(())
+    }
+}
+
+impl ValueSetter for f64 {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        let rows = col.rows_mut::<types::Float64>()?;
+        rows[row] = *self;
+        Ok(())
+    }
+}
+
 impl ValueSetter for bool {
     fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
         let rows = col.rows_mut::<types::Boolean>()?;
@@ -65,7 +90,7 @@ impl<'b> ValueSetter for &'b str {
             arena.append(data)?.1
         };
 
-        let rows = col.rows_mut::<types::Text>()?;
+        let rows = text_rows_mut(col)?;
         rows[row] = types::RawData{data: ptr, size: data.len()};
         Ok(())
     }
@@ -79,7 +104,7 @@ impl ValueSetter for String {
             arena.append(data)?.1
         };
 
-        let rows = col.rows_mut::<types::Text>()?;
+        let rows = text_rows_mut(col)?;
         rows[row] = types::RawData{data: ptr, size: data.len()};
         Ok(())
     }
@@ -98,5 +123,176 @@ impl<'b> ValueSetter for &'b[u8] {
     }
 }
 
+impl<'b> ValueSetter for types::Value<'b> {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        if !self.is_null() && col.attribute().nullable {
+            col.nulls_mut()?.set(row, false);
+        }
+
+        match *self {
+            types::Value::NULL              => types::NULL_VALUE.set_row(col, row),
+            types::Value::UINT32(v)         => v.set_row(col, row),
+            types::Value::UINT64(v)         => v.set_row(col, row),
+            types::Value::INT32(v)          => v.set_row(col, row),
+            types::Value::INT64(v)          => v.set_row(col, row),
+            types::Value::FLOAT32(v)        => v.set_row(col, row),
+            types::Value::FLOAT64(v)        => v.set_row(col, row),
+            types::Value::BOOLEAN(v)        => v.set_row(col, row),
+            types::Value::TEXT(ref v)       => v.as_ref().set_row(col, row),
+            types::Value::JSON(ref v)       => v.as_ref().set_row(col, row),
+            types::Value::BLOB(ref v)       => v.as_ref().set_row(col, row),
+            types::Value::TIMESTAMP(v)      => { col.rows_mut::<types::Timestamp>()?[row] = v; Ok(()) }
+            types::Value::TIMESTAMP_TZ(v)   => { col.rows_mut::<types::TimestampTz>()?[row] = v; Ok(()) }
+            types::Value::LIST(ref elems)   => {
+                let (child, offset) = col.list_append(row, elems.len())?;
+                for (idx, elem) in elems.iter().enumerate() {
+                    elem.set_row(child, offset + idx)?;
+                }
+                Ok(())
+            }
+            types::Value::STRUCT(ref fields) => {
+                let attr = col.attribute();
+                if attr.dtype != types::Type::STRUCT || attr.children.len() != fields.len() {
+                    return Err(DBError::AttributeType(format!("{} ({} fields)", attr.name, fields.len())))
+                }
+
+                for (pos, field) in fields.iter().enumerate() {
+                    let child = col.child_mut(pos)
+                        .ok_or(DBError::make_column_unknown_pos(pos))?;
+                    field.set_row(child, row)?;
+                }
+                Ok(())
+            }
+            types::Value::MAP(ref pairs)    => {
+                let (keys, values, offset) = col.map_append(row, pairs.len())?;
+                for (idx, &(ref k, ref v)) in pairs.iter().enumerate() {
+                    k.set_row(keys, offset + idx)?;
+                    v.set_row(values, offset + idx)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// NULL for None
+impl<T: ValueSetter> ValueSetter for Option<T> {
+    fn set_row<'a>(&self, col: &mut Column<'a>, row: RowOffset) -> Result<(), DBError> {
+        match *self {
+            Some(ref v) => {
+                if col.attribute().nullable {
+                    col.nulls_mut()?.set(row, false);
+                }
+                v.set_row(col, row)
+            }
+            None        => types::NULL_VALUE.set_row(col, row),
+        }
+    }
+}
+
+/// Set the value of a block column's row. Unlike `ValueSetter::set_row` this also clears the null
+/// flag of non NULL values.
+pub fn set_column_value<'a, T: ValueSetter>(block: &mut Block<'a>, pos: usize, row: RowOffset, value: &T)
+    -> Result<(), DBError>
+{
+    let col = block.column_mut(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+    if col.attribute().nullable {
+        col.nulls_mut()?.set(row, false);
+    }
+
+    value.set_row(col, row)
+}
+
+/// Rust native types with a known column type, used to validate values against a schema.
+pub trait NativeValue {
+    /// Can values of this type be stored in the column
+    fn accepts(attr: &Attribute) -> bool;
+}
+
+macro_rules! native_value {
+    ($native:ty, $($dtype:ident)|+) => {
+        impl NativeValue for $native {
+            fn accepts(attr: &Attribute) -> bool {
+                match attr.dtype {
+                    $(types::Type::$dtype)|+    => true,
+                    _                           => false,
+                }
+            }
+        }
+    }
+}
+
+native_value!(u32, UINT32);
+native_value!(u64, UINT64);
+native_value!(i32, INT32);
+native_value!(i64, INT64);
+native_value!(f32, FLOAT32);
+native_value!(f64, FLOAT64);
+native_value!(bool, BOOLEAN);
+native_value!(String, TEXT | JSON);
+
+impl<'b> NativeValue for &'b str {
+    fn accepts(attr: &Attribute) -> bool {
+        String::accepts(attr)
+    }
+}
+
+impl<'b> NativeValue for &'b [u8] {
+    fn accepts(attr: &Attribute) -> bool {
+        attr.dtype == types::Type::BLOB
+    }
+}
+
+impl<T: NativeValue> NativeValue for Option<T> {
+    fn accepts(attr: &Attribute) -> bool {
+        attr.nullable && T::accepts(attr)
+    }
+}
+
+/// Set a whole row from a Rust tuple, one tuple element per column.
+pub trait RowSetter {
+    /// Validate the tuple types against the schema
+    fn check_schema(schema: &Schema) -> Result<(), DBError>;
+
+    /// Set the row values. Clears the nulls of non NULL values.
+    fn set_columns<'a>(&self, block: &mut Block<'a>, row: RowOffset) -> Result<(), DBError>;
+}
+
+macro_rules! row_setter {
+    ($count:expr, $($name:ident: $pos:tt),+) => {
+        impl<$($name: ValueSetter + NativeValue),+> RowSetter for ($($name,)+) {
+            fn check_schema(schema: &Schema) -> Result<(), DBError> {
+                if schema.count() != $count {
+                    return Err(DBError::AttributeMissing(format!("{} columns != {}", $count, schema.count())))
+                }
+
+                $(
+                    let attr = schema.get($pos)?;
+                    if !$name::accepts(attr) {
+                        return Err(DBError::AttributeType(attr.name.clone()))
+                    }
+                )+
+
+                Ok(())
+            }
+
+            fn set_columns<'a>(&self, block: &mut Block<'a>, row: RowOffset) -> Result<(), DBError> {
+                $( set_column_value(block, $pos, row, &self.$pos)?; )+
+
+                Ok(())
+            }
+        }
+    }
+}
+
+row_setter!(1, A: 0);
+row_setter!(2, A: 0, B: 1);
+row_setter!(3, A: 0, B: 1, C: 2);
+row_setter!(4, A: 0, B: 1, C: 2, D: 3);
+row_setter!(5, A: 0, B: 1, C: 2, D: 3, E: 4);
+row_setter!(6, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+row_setter!(7, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+row_setter!(8, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
 // TODO: Make a value alias... we can set a value but without copying the data in the arena.
 // Clearly unsafe, but useful for things like join with Tiny... where it's always alive.
@@ -0,0 +1,451 @@
+// vim : set ts=4 sw=4 et :
+
+//! Calendar math for TIMESTAMP (local, no time zone) and TIMESTAMP_TZ (UTC) values: microseconds
+//! since 1970-01-01 00:00:00 in the proleptic Gregorian calendar, and their ISO-8601 text form.
+
+use std::fmt;
+use std::str;
+
+use ::error::DBError;
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+pub const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+pub const MICROS_PER_DAY: i64 = 24 * MICROS_PER_HOUR;
+
+/// Largest number of years from 1970 a timestamp can be
+const MAX_YEARS: i64 = 300_000;
+
+/// Field of a timestamp, for truncating, extracting and counting differences
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DatePart {
+    YEAR,
+    MONTH,
+    DAY,
+    HOUR,
+    MINUTE,
+    SECOND,
+}
+
+/// Calendar months and a fixed number of microseconds. Months are added first, keeping the day
+/// of the month when it exists (or the last day of the month when it doesn't).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Interval {
+    pub months: i64,
+    pub micros: i64,
+}
+
+/// Broken down timestamp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// 1 - 12
+    pub month: u32,
+    /// 1 - 31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub micros: u32,
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2                   => if is_leap(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11      => 30,
+        _                   => 31,
+    }
+}
+
+/// Days since 1970-01-01 of the date
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Years start in March, so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// (year, month, day) of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl DateTime {
+    pub fn from_micros(ts: i64) -> DateTime {
+        let (days, time) = (ts.div_euclid(MICROS_PER_DAY), ts.rem_euclid(MICROS_PER_DAY));
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year: year,
+            month: month,
+            day: day,
+            hour: (time / MICROS_PER_HOUR) as u32,
+            minute: (time % MICROS_PER_HOUR / MICROS_PER_MINUTE) as u32,
+            second: (time % MICROS_PER_MINUTE / MICROS_PER_SECOND) as u32,
+            micros: (time % MICROS_PER_SECOND) as u32,
+        }
+    }
+
+    /// Microseconds since the epoch, None if the fields are out of range or it doesn't fit in i64
+    pub fn to_micros(&self) -> Option<i64> {
+        if self.month < 1 || self.month > 12 || self.day < 1 || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23 || self.minute > 59 || self.second > 59 || self.micros >= 1_000_000 {
+            return None
+        }
+
+        let time = self.hour as i64 * MICROS_PER_HOUR + self.minute as i64 * MICROS_PER_MINUTE
+            + self.second as i64 * MICROS_PER_SECOND + self.micros as i64;
+
+        days_from_civil(self.year, self.month, self.day)
+            .checked_mul(MICROS_PER_DAY)
+            .and_then(|micros| micros.checked_add(time))
+    }
+}
+
+impl DatePart {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DatePart::YEAR      => "YEAR",
+            DatePart::MONTH     => "MONTH",
+            DatePart::DAY       => "DAY",
+            DatePart::HOUR      => "HOUR",
+            DatePart::MINUTE    => "MINUTE",
+            DatePart::SECOND    => "SECOND",
+        }
+    }
+
+    /// Microseconds in the part, None for calendar parts
+    fn micros(&self) -> Option<i64> {
+        match *self {
+            DatePart::YEAR | DatePart::MONTH    => None,
+            DatePart::DAY                       => Some(MICROS_PER_DAY),
+            DatePart::HOUR                      => Some(MICROS_PER_HOUR),
+            DatePart::MINUTE                    => Some(MICROS_PER_MINUTE),
+            DatePart::SECOND                    => Some(MICROS_PER_SECOND),
+        }
+    }
+}
+
+impl str::FromStr for DatePart {
+    type Err = DBError;
+
+    /// Case insensitive part name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "YEAR"      => Ok(DatePart::YEAR),
+            "MONTH"     => Ok(DatePart::MONTH),
+            "DAY"       => Ok(DatePart::DAY),
+            "HOUR"      => Ok(DatePart::HOUR),
+            "MINUTE"    => Ok(DatePart::MINUTE),
+            "SECOND"    => Ok(DatePart::SECOND),
+            _           => Err(DBError::InvalidArgument(format!("unknown date part {}", s))),
+        }
+    }
+}
+
+/// Timestamp truncated to the start of its `part`; None if that is before the earliest timestamp
+pub fn trunc(ts: i64, part: DatePart) -> Option<i64> {
+    if let Some(unit) = part.micros() {
+        return ts.div_euclid(unit).checked_mul(unit)
+    }
+
+    let dt = DateTime::from_micros(ts);
+    let month = if part == DatePart::YEAR { 1 } else { dt.month };
+    DateTime { year: dt.year, month: month, day: 1, hour: 0, minute: 0, second: 0, micros: 0 }.to_micros()
+}
+
+/// Value of the timestamp's `part`
+pub fn extract(ts: i64, part: DatePart) -> i64 {
+    let dt = DateTime::from_micros(ts);
+    match part {
+        DatePart::YEAR      => dt.year,
+        DatePart::MONTH     => dt.month as i64,
+        DatePart::DAY       => dt.day as i64,
+        DatePart::HOUR      => dt.hour as i64,
+        DatePart::MINUTE    => dt.minute as i64,
+        DatePart::SECOND    => dt.second as i64,
+    }
+}
+
+/// Number of `part` boundaries between `from` and `to`; negative if `to` is earlier
+pub fn diff(from: i64, to: i64, part: DatePart) -> Option<i64> {
+    let (l, r) = (DateTime::from_micros(from), DateTime::from_micros(to));
+    match part {
+        DatePart::YEAR  => Some(r.year - l.year),
+        DatePart::MONTH => Some((r.year - l.year) * 12 + r.month as i64 - l.month as i64),
+        _               => {
+            let unit = part.micros()?;
+            Some(to.div_euclid(unit) - from.div_euclid(unit))
+        }
+    }
+}
+
+impl Interval {
+    pub fn new(months: i64, micros: i64) -> Interval {
+        Interval { months: months, micros: micros }
+    }
+
+    /// Interval subtracted instead of added
+    pub fn negate(&self) -> Option<Interval> {
+        Some(Interval::new(self.months.checked_neg()?, self.micros.checked_neg()?))
+    }
+
+    /// Timestamp `ts` + the interval; None if it's out of range
+    pub fn add_to(&self, ts: i64) -> Option<i64> {
+        let ts = if self.months == 0 {
+            ts
+        } else {
+            let dt = DateTime::from_micros(ts);
+            let months = (dt.year * 12 + dt.month as i64 - 1).checked_add(self.months)?;
+            let year = months.div_euclid(12);
+            if (year - 1970).abs() > MAX_YEARS {
+                return None
+            }
+
+            let month = months.rem_euclid(12) as u32 + 1;
+            let day = dt.day.min(days_in_month(year, month));
+            DateTime { year: year, month: month, day: day, ..dt }.to_micros()?
+        };
+
+        ts.checked_add(self.micros)
+    }
+}
+
+impl str::FromStr for Interval {
+    type Err = DBError;
+
+    /// `<n> <unit>` pairs (eg. `1 month -2 days`); units are year, month, week, day, hour, minute,
+    /// second and microsecond, singular or plural.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || DBError::InvalidArgument(format!("invalid interval {}", s));
+        let words: Vec<&str> = s.split_whitespace().collect();
+        if words.is_empty() || words.len() % 2 != 0 {
+            return Err(bad())
+        }
+
+        let mut out = Interval::new(0, 0);
+        for pair in words.chunks(2) {
+            let n: i64 = pair[0].parse().map_err(|_| bad())?;
+            let unit = pair[1].to_ascii_lowercase();
+            let (months, micros) = match unit.trim_end_matches('s') {
+                "year"          => (12, 0),
+                "month"         => (1, 0),
+                "week"          => (0, 7 * MICROS_PER_DAY),
+                "day"           => (0, MICROS_PER_DAY),
+                "hour"          => (0, MICROS_PER_HOUR),
+                "minute"        => (0, MICROS_PER_MINUTE),
+                "second"        => (0, MICROS_PER_SECOND),
+                "microsecond"   => (0, 1),
+                _               => return Err(bad()),
+            };
+
+            out.months = n.checked_mul(months).and_then(|m| out.months.checked_add(m)).ok_or_else(bad)?;
+            out.micros = n.checked_mul(micros).and_then(|m| out.micros.checked_add(m)).ok_or_else(bad)?;
+        }
+
+        Ok(out)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} months {} microseconds", self.months, self.micros)
+    }
+}
+
+/// Fixed width decimal number
+fn digits(text: &str, len: usize) -> Option<u32> {
+    if text.len() != len || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None
+    }
+    text.parse().ok()
+}
+
+/// Parse `YYYY-MM-DD[( |T)HH:MM[:SS[.ffffff]]][Z|(+|-)HH[:MM]]`. Returns the microseconds since the
+/// epoch of the date and time as written, and the UTC offset (in seconds) if there is one.
+pub fn parse(text: &str) -> Option<(i64, Option<i32>)> {
+    let text = text.trim();
+    if text.len() < 10 || !text.is_ascii() {
+        return None
+    }
+
+    let (date, rest) = text.split_at(10);
+    let mut dt = DateTime { year: 0, month: 0, day: 0, hour: 0, minute: 0, second: 0, micros: 0 };
+    if &date[4 .. 5] != "-" || &date[7 .. 8] != "-" {
+        return None
+    }
+    dt.year = digits(&date[.. 4], 4)? as i64;
+    dt.month = digits(&date[5 .. 7], 2)?;
+    dt.day = digits(&date[8 ..], 2)?;
+
+    // Offset suffix
+    let (time, offset) = match rest.rfind(|c: char| c == 'Z' || c == '+' || c == '-') {
+        Some(pos) if rest[pos ..].starts_with('Z') && pos + 1 == rest.len() => (&rest[.. pos], Some(0)),
+        Some(pos) if pos > 0 => {
+            let (sign, zone) = if rest[pos ..].starts_with('-') { (-1, &rest[pos + 1 ..]) } else { (1, &rest[pos + 1 ..]) };
+            let (hours, minutes) = match zone.len() {
+                2   => (digits(zone, 2)?, 0),
+                5 if &zone[2 .. 3] == ":" => (digits(&zone[.. 2], 2)?, digits(&zone[3 ..], 2)?),
+                _   => return None,
+            };
+            if hours > 23 || minutes > 59 {
+                return None
+            }
+            (&rest[.. pos], Some(sign * (hours * 3600 + minutes * 60) as i32))
+        }
+        _ => (rest, None),
+    };
+
+    if !time.is_empty() {
+        if !(time.starts_with(' ') || time.starts_with('T')) || time.len() < 6 || &time[3 .. 4] != ":" {
+            return None
+        }
+        dt.hour = digits(&time[1 .. 3], 2)?;
+        dt.minute = digits(&time[4 .. 6], 2)?;
+
+        let seconds = &time[6 ..];
+        if !seconds.is_empty() {
+            if !seconds.starts_with(':') || seconds.len() < 3 {
+                return None
+            }
+            dt.second = digits(&seconds[1 .. 3], 2)?;
+
+            let fraction = &seconds[3 ..];
+            if !fraction.is_empty() {
+                if !fraction.starts_with('.') || fraction.len() < 2 || fraction.len() > 7 {
+                    return None
+                }
+                let frac = &fraction[1 ..];
+                dt.micros = digits(frac, frac.len())? * 10u32.pow(6 - frac.len() as u32);
+            }
+        }
+    }
+
+    Some((dt.to_micros()?, offset))
+}
+
+/// TIMESTAMP value of the text. Text with a UTC offset isn't a TIMESTAMP.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    match parse(text)? {
+        (ts, None)  => Some(ts),
+        _           => None,
+    }
+}
+
+/// TIMESTAMP_TZ value of the text; times without a UTC offset are in UTC
+pub fn parse_timestamp_tz(text: &str) -> Option<i64> {
+    let (ts, offset) = parse(text)?;
+    ts.checked_sub(offset.unwrap_or(0) as i64 * MICROS_PER_SECOND)
+}
+
+/// `YYYY-MM-DD HH:MM:SS`, with the fraction of a second when there is one
+pub fn format(ts: i64) -> String {
+    let dt = DateTime::from_micros(ts);
+    let mut out = format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second);
+    if dt.micros != 0 {
+        let fraction = format!("{:06}", dt.micros);
+        out.push('.');
+        out.push_str(fraction.trim_end_matches('0'));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+
+        for days in vec![-719468, -1, 0, 59, 10957, 11016, 11017, 2932896] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn parse_format() {
+        let ts = parse("2024-02-29 13:45:07.25").unwrap();
+        assert_eq!(ts, (1709214307250000, None));
+        assert_eq!(format(ts.0), "2024-02-29 13:45:07.25");
+
+        assert_eq!(parse("2024-02-29T13:45Z"), Some((1709214300000000, Some(0))));
+        assert_eq!(parse("2024-02-29T13:45:00-05:30"), Some((1709214300000000, Some(-19800))));
+        assert_eq!(parse("2024-02-29 13:45:00+02"), Some((1709214300000000, Some(7200))));
+        assert_eq!(parse(" 1969-12-31 "), Some((-MICROS_PER_DAY, None)));
+        assert_eq!(format(-1), "1969-12-31 23:59:59.999999");
+
+        assert_eq!(parse_timestamp("2024-02-29 13:45"), Some(1709214300000000));
+        assert_eq!(parse_timestamp("2024-02-29 13:45Z"), None);
+        assert_eq!(parse_timestamp_tz("2024-02-29 13:45"), Some(1709214300000000));
+        assert_eq!(parse_timestamp_tz("2024-02-29 15:45+02:00"), Some(1709214300000000));
+
+        for bad in vec!["2023-02-29", "2024-13-01", "2024-1-01", "2024-01-01 24:00", "2024-01-01 10:00:00.1234567",
+                        "2024-01-01 10", "2024-01-01x", "2024-01-01 10:00+25", "", "é2024-01-01"] {
+            assert_eq!(parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parts() {
+        let ts = parse_timestamp("2024-02-29 13:45:07.25").unwrap();
+        assert_eq!(format(trunc(ts, DatePart::YEAR).unwrap()), "2024-01-01 00:00:00");
+        assert_eq!(format(trunc(ts, DatePart::MONTH).unwrap()), "2024-02-01 00:00:00");
+        assert_eq!(format(trunc(ts, DatePart::HOUR).unwrap()), "2024-02-29 13:00:00");
+        assert_eq!(format(trunc(-1, DatePart::DAY).unwrap()), "1969-12-31 00:00:00");
+        assert_eq!(trunc(i64::min_value(), DatePart::DAY), None);
+
+        assert_eq!(extract(ts, DatePart::YEAR), 2024);
+        assert_eq!(extract(ts, DatePart::DAY), 29);
+        assert_eq!(extract(ts, DatePart::SECOND), 7);
+
+        let later = parse_timestamp("2025-01-01 00:00").unwrap();
+        assert_eq!(diff(ts, later, DatePart::YEAR), Some(1));
+        assert_eq!(diff(ts, later, DatePart::MONTH), Some(11));
+        assert_eq!(diff(ts, later, DatePart::DAY), Some(307));
+        assert_eq!(diff(later, ts, DatePart::HOUR), Some(-7355));
+
+        assert_eq!("Day".parse::<DatePart>().unwrap(), DatePart::DAY);
+        assert!("fortnight".parse::<DatePart>().is_err());
+    }
+
+    #[test]
+    fn intervals() {
+        let jan31 = parse_timestamp("2023-01-31 10:00").unwrap();
+        let month: Interval = "1 month".parse().unwrap();
+        assert_eq!(format(month.add_to(jan31).unwrap()), "2023-02-28 10:00:00");
+        assert_eq!(format(month.negate().unwrap().add_to(jan31).unwrap()), "2022-12-31 10:00:00");
+
+        let mixed: Interval = "1 year -2 days 3 Hours".parse().unwrap();
+        assert_eq!(mixed, Interval::new(12, -2 * MICROS_PER_DAY + 3 * MICROS_PER_HOUR));
+        assert_eq!(format(mixed.add_to(jan31).unwrap()), "2024-01-29 13:00:00");
+        assert_eq!(mixed.to_string().parse::<Interval>().unwrap(), mixed);
+
+        assert_eq!(Interval::new(i64::max_value(), 0).add_to(0), None);
+        assert_eq!(Interval::new(0, 1).add_to(i64::max_value()), None);
+        for bad in vec!["", "1", "day 1", "1 fortnight", "9223372036854775807 years"] {
+            assert!(bad.parse::<Interval>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use std::char;
+use std::str;
+
+use ::error::DBError;
+
+/// Type of a JSON document value
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JsonType {
+    NULL,
+    BOOLEAN,
+    NUMBER,
+    STRING,
+    ARRAY,
+    OBJECT,
+}
+
+/// Parsed JSON document
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonValue {
+    NULL,
+    BOOLEAN(bool),
+    NUMBER(f64),
+    STRING(String),
+    ARRAY(Vec<JsonValue>),
+    /// Members in document order
+    OBJECT(Vec<(String, JsonValue)>),
+}
+
+/// Single step of a JSON path
+#[derive(Clone, PartialEq, Debug)]
+pub enum PathElem {
+    /// Object member
+    KEY(String),
+    /// Array element
+    INDEX(usize),
+}
+
+impl JsonType {
+    pub fn name(self) -> &'static str {
+        match self {
+            JsonType::NULL      => "null",
+            JsonType::BOOLEAN   => "boolean",
+            JsonType::NUMBER    => "number",
+            JsonType::STRING    => "string",
+            JsonType::ARRAY     => "array",
+            JsonType::OBJECT    => "object",
+        }
+    }
+}
+
+impl JsonValue {
+    /// Object member
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match *self {
+            JsonValue::OBJECT(ref members)  => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _                               => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            JsonValue::STRING(ref v)    => Some(v),
+            _                           => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            JsonValue::BOOLEAN(v)   => Some(v),
+            _                       => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            JsonValue::NUMBER(v)    => Some(v),
+            _                       => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match *self {
+            JsonValue::ARRAY(ref v) => Some(v),
+            _                       => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match *self {
+            JsonValue::OBJECT(ref v)    => Some(v),
+            _                           => None,
+        }
+    }
+}
+
+/// Parse a whole JSON document
+pub fn parse(doc: &str) -> Result<JsonValue, DBError> {
+    let mut scan = Scanner::new(doc);
+    scan.ws();
+    let value = scan.read_value()?;
+    scan.ws();
+
+    if scan.pos != scan.src.len() {
+        return Err(scan.error("trailing data"))
+    }
+
+    Ok(value)
+}
+
+/// Encode `value` as a JSON string (with quotes)
+pub fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"'             => out.push_str("\\\""),
+            '\\'            => out.push_str("\\\\"),
+            '\n'            => out.push_str("\\n"),
+            '\r'            => out.push_str("\\r"),
+            '\t'            => out.push_str("\\t"),
+            c if c < ' '    => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c               => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Parse a JSON path such as `$.a.b[0]` or `$["a b"][1]`.
+pub fn parse_path(path: &str) -> Result<Vec<PathElem>, DBError> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::new();
+
+    if bytes.first() != Some(&b'$') {
+        return Err(DBError::JSON(format!("path {} has to start with $", path)))
+    }
+
+    let mut pos = 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'.' => {
+                let start = pos + 1;
+                pos = start;
+                while pos < bytes.len() && bytes[pos] != b'.' && bytes[pos] != b'[' {
+                    pos += 1;
+                }
+
+                if pos == start {
+                    return Err(DBError::JSON(format!("empty key in path {}", path)))
+                }
+
+                out.push(PathElem::KEY(path[start .. pos].to_string()));
+            }
+            b'[' if bytes.get(pos + 1) == Some(&b'"') => {
+                let mut scan = Scanner::new(&path[pos + 1 ..]);
+                let key = scan.read_string()?;
+                pos += 1 + scan.pos;
+
+                if bytes.get(pos) != Some(&b']') {
+                    return Err(DBError::JSON(format!("unterminated key in path {}", path)))
+                }
+
+                out.push(PathElem::KEY(key));
+                pos += 1;
+            }
+            b'[' => {
+                let end = path[pos ..].find(']')
+                    .ok_or(DBError::JSON(format!("unterminated index in path {}", path)))?;
+
+                let index = path[pos + 1 .. pos + end].trim().parse::<usize>()
+                    .map_err(|_| DBError::JSON(format!("bad index in path {}", path)))?;
+
+                out.push(PathElem::INDEX(index));
+                pos += end + 1;
+            }
+            _ => return Err(DBError::JSON(format!("unexpected character in path {}", path))),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Find the value at `path` in the document. Returns the raw JSON text of the value, or `None` if
+/// the path does not exist in the document.
+///
+/// Only the parts of the document preceding the value are validated.
+pub fn extract<'a>(doc: &'a str, path: &[PathElem]) -> Result<Option<&'a str>, DBError> {
+    let mut scan = Scanner::new(doc);
+
+    for elem in path {
+        scan.ws();
+
+        let found = match *elem {
+            PathElem::KEY(ref key)  => scan.find_member(key)?,
+            PathElem::INDEX(index)  => scan.find_element(index)?,
+        };
+
+        if !found {
+            return Ok(None)
+        }
+    }
+
+    scan.ws();
+    let start = scan.pos;
+    scan.skip_value()?;
+    Ok(Some(&doc[start .. scan.pos]))
+}
+
+/// Type of the JSON value
+pub fn type_of(doc: &str) -> Result<JsonType, DBError> {
+    let mut scan = Scanner::new(doc);
+    scan.ws();
+
+    match scan.peek() {
+        Some(b'n')                          => Ok(JsonType::NULL),
+        Some(b't') | Some(b'f')             => Ok(JsonType::BOOLEAN),
+        Some(b'"')                          => Ok(JsonType::STRING),
+        Some(b'[')                          => Ok(JsonType::ARRAY),
+        Some(b'{')                          => Ok(JsonType::OBJECT),
+        Some(b'-') | Some(b'0' ... b'9')    => Ok(JsonType::NUMBER),
+        _                                   => Err(scan.error("expected value")),
+    }
+}
+
+/// Decode a JSON string value
+pub fn unquote(value: &str) -> Result<String, DBError> {
+    let mut scan = Scanner::new(value);
+    scan.ws();
+    scan.read_string()
+}
+
+struct Scanner<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(src: &'a str) -> Scanner<'a> {
+        Scanner { src: src.as_bytes(), pos: 0 }
+    }
+
+    fn error(&self, msg: &str) -> DBError {
+        let consumed = &self.src[.. self.pos.min(self.src.len())];
+        let line = consumed.iter().filter(|&&c| c == b'\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|&&c| c != b'\n').count() + 1;
+        DBError::Parse { format: "JSON", line: line, column: column, msg: msg.to_string() }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).cloned()
+    }
+
+    fn ws(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), DBError> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)))
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, lit: &str) -> Result<(), DBError> {
+        if !self.src[self.pos ..].starts_with(lit.as_bytes()) {
+            return Err(self.error(&format!("expected {}", lit)))
+        }
+
+        self.pos += lit.len();
+        Ok(())
+    }
+
+    /// Move past the separator following a member/element. Returns false at the end of the
+    /// object/array.
+    fn next_item(&mut self, end: u8) -> Result<bool, DBError> {
+        self.ws();
+        match self.peek() {
+            Some(b',')              => { self.pos += 1; self.ws(); Ok(true) }
+            Some(c) if c == end     => { self.pos += 1; Ok(false) }
+            _                       => Err(self.error("expected separator")),
+        }
+    }
+
+    /// Position the scanner at the value of object member `key`.
+    fn find_member(&mut self, key: &str) -> Result<bool, DBError> {
+        if self.peek() != Some(b'{') {
+            return Ok(false)
+        }
+
+        self.pos += 1;
+        self.ws();
+        if self.peek() == Some(b'}') {
+            return Ok(false)
+        }
+
+        loop {
+            let name = self.read_string()?;
+            self.ws();
+            self.expect(b':')?;
+            self.ws();
+
+            if name == key {
+                return Ok(true)
+            }
+
+            self.skip_value()?;
+            if !self.next_item(b'}')? {
+                return Ok(false)
+            }
+        }
+    }
+
+    /// Position the scanner at array element `index`.
+    fn find_element(&mut self, index: usize) -> Result<bool, DBError> {
+        if self.peek() != Some(b'[') {
+            return Ok(false)
+        }
+
+        self.pos += 1;
+        self.ws();
+        if self.peek() == Some(b']') {
+            return Ok(false)
+        }
+
+        for _ in 0 .. index {
+            self.skip_value()?;
+            if !self.next_item(b']')? {
+                return Ok(false)
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn skip_value(&mut self) -> Result<(), DBError> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                self.ws();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(())
+                }
+
+                loop {
+                    self.skip_string()?;
+                    self.ws();
+                    self.expect(b':')?;
+                    self.ws();
+                    self.skip_value()?;
+                    if !self.next_item(b'}')? {
+                        return Ok(())
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                self.ws();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(())
+                }
+
+                loop {
+                    self.skip_value()?;
+                    if !self.next_item(b']')? {
+                        return Ok(())
+                    }
+                }
+            }
+            Some(b'"')  => self.skip_string(),
+            Some(b't')  => self.literal("true"),
+            Some(b'f')  => self.literal("false"),
+            Some(b'n')  => self.literal("null"),
+            Some(b'-') | Some(b'0' ... b'9') => {
+                while let Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E') | Some(b'0' ... b'9') = self.peek() {
+                    self.pos += 1;
+                }
+                Ok(())
+            }
+            _ => Err(self.error("expected value")),
+        }
+    }
+
+    fn read_value(&mut self) -> Result<JsonValue, DBError> {
+        match self.peek() {
+            Some(b'{') => {
+                let mut members = Vec::new();
+                self.pos += 1;
+                self.ws();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::OBJECT(members))
+                }
+
+                loop {
+                    let key = self.read_string()?;
+                    self.ws();
+                    self.expect(b':')?;
+                    self.ws();
+                    members.push((key, self.read_value()?));
+                    if !self.next_item(b'}')? {
+                        return Ok(JsonValue::OBJECT(members))
+                    }
+                }
+            }
+            Some(b'[') => {
+                let mut elems = Vec::new();
+                self.pos += 1;
+                self.ws();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::ARRAY(elems))
+                }
+
+                loop {
+                    elems.push(self.read_value()?);
+                    if !self.next_item(b']')? {
+                        return Ok(JsonValue::ARRAY(elems))
+                    }
+                }
+            }
+            Some(b'"')  => self.read_string().map(JsonValue::STRING),
+            Some(b't')  => self.literal("true").map(|_| JsonValue::BOOLEAN(true)),
+            Some(b'f')  => self.literal("false").map(|_| JsonValue::BOOLEAN(false)),
+            Some(b'n')  => self.literal("null").map(|_| JsonValue::NULL),
+            _ => {
+                let start = self.pos;
+                self.skip_value()?;
+                str::from_utf8(&self.src[start .. self.pos]).ok()
+                    .and_then(|n| n.parse::<f64>().ok())
+                    .map(JsonValue::NUMBER)
+                    .ok_or(self.error("bad number"))
+            }
+        }
+    }
+
+    fn skip_string(&mut self) -> Result<(), DBError> {
+        self.expect(b'"')?;
+
+        loop {
+            match self.peek() {
+                Some(b'\\') => self.pos += 2,
+                Some(b'"')  => { self.pos += 1; return Ok(()) }
+                Some(_)     => self.pos += 1,
+                None        => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, DBError> {
+        let digits = self.src.get(self.pos .. self.pos + 4)
+            .and_then(|d| str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or(self.error("bad unicode escape"))?;
+
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn read_string(&mut self) -> Result<String, DBError> {
+        self.expect(b'"')?;
+        let mut out: Vec<u8> = Vec::new();
+
+        loop {
+            let c = self.peek().ok_or(self.error("unterminated string"))?;
+            self.pos += 1;
+
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let esc = self.peek().ok_or(self.error("unterminated string"))?;
+                    self.pos += 1;
+
+                    let decoded = match esc {
+                        b'"'    => '"',
+                        b'\\'   => '\\',
+                        b'/'    => '/',
+                        b'b'    => '\x08',
+                        b'f'    => '\x0c',
+                        b'n'    => '\n',
+                        b'r'    => '\r',
+                        b't'    => '\t',
+                        b'u'    => {
+                            let mut code = self.read_hex4()?;
+                            // Surrogate pair
+                            if code >= 0xD800 && code < 0xDC00 {
+                                self.literal("\\u")?;
+                                let low = self.read_hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).ok_or(self.error("bad unicode escape"))?
+                        }
+                        _       => return Err(self.error("bad escape")),
+                    };
+
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(c),
+            }
+        }
+
+        String::from_utf8(out)
+            .map_err(|_| self.error("invalid utf-8 in string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &'static str = r#"{"a": {"b": [1, "two", {"c": null}]}, "d e": true}"#;
+
+    #[test]
+    fn paths() {
+        let path = parse_path("$.a.b[1]").unwrap();
+        assert_eq!(path, vec![PathElem::KEY("a".to_string()), PathElem::KEY("b".to_string()),
+                              PathElem::INDEX(1)]);
+
+        assert_eq!(parse_path(r#"$["d e"]"#).unwrap(), vec![PathElem::KEY("d e".to_string())]);
+        assert!(parse_path("a.b").is_err());
+    }
+
+    #[test]
+    fn extract_values() {
+        let get = |p| extract(DOC, &parse_path(p).unwrap()).unwrap();
+
+        assert_eq!(get("$.a.b[0]"), Some("1"));
+        assert_eq!(get("$.a.b[1]"), Some(r#""two""#));
+        assert_eq!(get("$.a.b[2].c"), Some("null"));
+        assert_eq!(get(r#"$["d e"]"#), Some("true"));
+        assert_eq!(get("$.a.b[3]"), None);
+        assert_eq!(get("$.x"), None);
+
+        assert_eq!(type_of(get("$.a").unwrap()).unwrap(), JsonType::OBJECT);
+        assert_eq!(unquote(get("$.a.b[1]").unwrap()).unwrap(), "two");
+    }
+
+    #[test]
+    fn parse_documents() {
+        let doc = parse(DOC).unwrap();
+        let b = doc.get("a").and_then(|a| a.get("b")).and_then(|b| b.as_array()).unwrap();
+        assert_eq!(b, &[JsonValue::NUMBER(1.0), JsonValue::STRING("two".to_string()),
+                        JsonValue::OBJECT(vec![("c".to_string(), JsonValue::NULL)])]);
+        assert_eq!(doc.get("d e").and_then(|v| v.as_bool()), Some(true));
+
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse("-").is_err());
+        match parse("{\n  \"a\": x}") {
+            Err(DBError::Parse { line: 2, column: 8, .. })  => (),
+            other                                           => panic!("{:?}", other.err()),
+        }
+
+        let text = "a \"b\"\n\\ \u{1}";
+        assert_eq!(quote(text), r#""a \"b\"\n\\ \u0001""#);
+        assert_eq!(parse(&quote(text)).unwrap().as_str(), Some(text));
+    }
+}
@@ -0,0 +1,186 @@
+// vim : set ts=4 sw=4 et :
+
+//! LZ4 block format (no frame) compression.
+
+const MIN_MATCH: usize = 4;
+const HASH_LOG: usize = 12;
+/// The last 5 bytes are always literals
+const LAST_LITERALS: usize = 5;
+/// The last match has to start at least 12 bytes before the end
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = 65535;
+/// Most output a compressed byte can produce (a length continuation byte)
+const MAX_EXPANSION: usize = 255;
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    src[pos] as u32 | (src[pos + 1] as u32) << 8 | (src[pos + 2] as u32) << 16 | (src[pos + 3] as u32) << 24
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn push_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, len: usize) {
+    let lit = literals.len();
+    let extra = len - MIN_MATCH;
+
+    out.push((lit.min(15) << 4 | extra.min(15)) as u8);
+    if lit >= 15 {
+        push_len(out, lit - 15);
+    }
+    out.extend_from_slice(literals);
+
+    out.push(offset as u8);
+    out.push((offset >> 8) as u8);
+    if extra >= 15 {
+        push_len(out, extra - 15);
+    }
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    let lit = literals.len();
+    out.push((lit.min(15) << 4) as u8);
+    if lit >= 15 {
+        push_len(out, lit - 15);
+    }
+    out.extend_from_slice(literals);
+}
+
+/// Compress into a LZ4 block. The uncompressed length is not stored.
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() / 2 + 16);
+    let mut anchor = 0;
+
+    if src.len() > MF_LIMIT {
+        // Position + 1 of the last occurrence of a hash; 0 is empty
+        let mut table = vec![0usize; 1 << HASH_LOG];
+        let limit = src.len() - MF_LIMIT;
+        let match_end = src.len() - LAST_LITERALS;
+        let mut pos = 0;
+
+        while pos < limit {
+            let seq = read_u32(src, pos);
+            let slot = &mut table[hash(seq)];
+            let candidate = *slot;
+            *slot = pos + 1;
+
+            if candidate == 0 || pos - (candidate - 1) > MAX_OFFSET || read_u32(src, candidate - 1) != seq {
+                pos += 1;
+                continue
+            }
+
+            let candidate = candidate - 1;
+            let mut len = MIN_MATCH;
+            while pos + len < match_end && src[candidate + len] == src[pos + len] {
+                len += 1;
+            }
+
+            push_sequence(&mut out, &src[anchor .. pos], pos - candidate, len);
+            pos += len;
+            anchor = pos;
+        }
+    }
+
+    push_literals(&mut out, &src[anchor ..]);
+    out
+}
+
+/// Decompress a LZ4 block of `len` uncompressed bytes. None if the input is malformed, including a
+/// `len` the input can't expand to.
+pub fn decompress(src: &[u8], len: usize) -> Option<Vec<u8>> {
+    fn read_len(src: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len == 15 {
+            loop {
+                let b = *src.get(*pos)?;
+                *pos += 1;
+                len = len.checked_add(b as usize)?;
+                if b != 255 {
+                    break
+                }
+            }
+        }
+        Some(len)
+    }
+
+    if src.len().checked_mul(MAX_EXPANSION).map_or(false, |max| len > max) {
+        return None
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+
+    loop {
+        let token = *src.get(pos)?;
+        pos += 1;
+
+        let lit = read_len(src, &mut pos, (token >> 4) as usize)?;
+        if out.len() + lit > len {
+            return None
+        }
+        out.extend_from_slice(src.get(pos .. pos.checked_add(lit)?)?);
+        pos += lit;
+
+        // The last sequence has no match
+        if pos == src.len() {
+            break
+        }
+
+        let raw = src.get(pos .. pos + 2)?;
+        let offset = raw[0] as usize | (raw[1] as usize) << 8;
+        pos += 2;
+
+        let copy_len = read_len(src, &mut pos, (token & 15) as usize)?.checked_add(MIN_MATCH)?;
+        if offset == 0 || offset > out.len() || out.len() + copy_len > len {
+            return None
+        }
+
+        // Byte at a time; the source and destination can overlap
+        let start = out.len() - offset;
+        for idx in 0 .. copy_len {
+            let b = out[start + idx];
+            out.push(b);
+        }
+    }
+
+    if out.len() != len {
+        return None
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data: Vec<u8> = b"abcdefgh".iter().cycle().take(1000).cloned().collect();
+        data.extend((0 .. 300).map(|v| (v * 7 % 251) as u8));
+
+        for src in vec![&b""[..], &b"short"[..], &data[..]] {
+            let compressed = compress(src);
+            assert_eq!(decompress(&compressed, src.len()).unwrap(), src.to_vec());
+        }
+
+        assert!(compress(&data).len() < data.len() / 2);
+
+        // Literal "abcd", then copy of 8 bytes at offset 4, then literal "xyzzy"
+        let src = [0x44, b'a', b'b', b'c', b'd', 4, 0, 0x50, b'x', b'y', b'z', b'z', b'y'];
+        assert_eq!(decompress(&src, 17).unwrap(), b"abcdabcdabcdxyzzy".to_vec());
+        assert!(decompress(&src, 16).is_none());
+        assert!(decompress(&src[.. 6], 17).is_none());
+
+        // Lengths the input can't expand to are rejected before allocating
+        assert!(decompress(&src, src.len() * 255 + 1).is_none());
+        assert!(decompress(&src, usize::max_value()).is_none());
+    }
+}
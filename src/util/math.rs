@@ -1,7 +1,10 @@
 
 use num::{Integer, Zero, One};
+use std::fmt;
 use std::ops::{Add, Sub};
 
+use ::error::DBError;
+
 /// Round n down to nearest multiple of m
 pub fn round_down<T>(n: T, m: T) -> T
     where T: Integer + Add<T> + Sub<T> + Copy
@@ -22,4 +25,123 @@ pub fn round_up<T>(n: T, m: T) -> T
     } else {
         (n / m) * m
     }
-}
\ No newline at end of file
+}
+
+/// Binary arithmetic operators
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithOp {
+    ADD,
+    SUB,
+    MUL,
+}
+
+impl fmt::Display for ArithOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ArithOp::ADD    => "+",
+            ArithOp::SUB    => "-",
+            ArithOp::MUL    => "*",
+        })
+    }
+}
+
+/// Behavior of arithmetic when the result doesn't fit the type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    /// Fail (SQL standard behavior)
+    ERROR,
+    /// Produce NULL
+    NULL,
+    /// Two's complement wrap around; fastest. Floats overflow to infinity.
+    WRAP,
+    /// The largest (or smallest) value of the type
+    SATURATE,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> OverflowPolicy {
+        OverflowPolicy::ERROR
+    }
+}
+
+/// Arithmetic with explicit overflow behavior. Float results overflow when they become infinite
+/// from finite inputs.
+pub trait CheckedArith: Copy + fmt::Display {
+    fn checked(self, op: ArithOp, rhs: Self) -> Option<Self>;
+    fn wrapping(self, op: ArithOp, rhs: Self) -> Self;
+    fn saturating(self, op: ArithOp, rhs: Self) -> Self;
+}
+
+macro_rules! int_arith {
+    ($($t:ty)*) => ($(
+        impl CheckedArith for $t {
+            fn checked(self, op: ArithOp, rhs: $t) -> Option<$t> {
+                match op {
+                    ArithOp::ADD    => self.checked_add(rhs),
+                    ArithOp::SUB    => self.checked_sub(rhs),
+                    ArithOp::MUL    => self.checked_mul(rhs),
+                }
+            }
+
+            fn wrapping(self, op: ArithOp, rhs: $t) -> $t {
+                match op {
+                    ArithOp::ADD    => self.wrapping_add(rhs),
+                    ArithOp::SUB    => self.wrapping_sub(rhs),
+                    ArithOp::MUL    => self.wrapping_mul(rhs),
+                }
+            }
+
+            fn saturating(self, op: ArithOp, rhs: $t) -> $t {
+                match op {
+                    ArithOp::ADD    => self.saturating_add(rhs),
+                    ArithOp::SUB    => self.saturating_sub(rhs),
+                    ArithOp::MUL    => self.saturating_mul(rhs),
+                }
+            }
+        }
+    )*)
+}
+
+int_arith! { u32 u64 i32 i64 }
+
+macro_rules! float_arith {
+    ($($t:ident)*) => ($(
+        impl CheckedArith for $t {
+            fn checked(self, op: ArithOp, rhs: $t) -> Option<$t> {
+                let out = self.wrapping(op, rhs);
+                if out.is_infinite() && self.is_finite() && rhs.is_finite() { None } else { Some(out) }
+            }
+
+            fn wrapping(self, op: ArithOp, rhs: $t) -> $t {
+                match op {
+                    ArithOp::ADD    => self + rhs,
+                    ArithOp::SUB    => self - rhs,
+                    ArithOp::MUL    => self * rhs,
+                }
+            }
+
+            fn saturating(self, op: ArithOp, rhs: $t) -> $t {
+                match self.checked(op, rhs) {
+                    Some(out)   => out,
+                    None        => if self.wrapping(op, rhs) > 0.0 { ::std::$t::MAX } else { ::std::$t::MIN },
+                }
+            }
+        }
+    )*)
+}
+
+float_arith! { f32 f64 }
+
+/// `lhs op rhs` following the overflow policy. `None` is a NULL result.
+#[inline]
+pub fn apply<T: CheckedArith>(op: ArithOp, policy: OverflowPolicy, lhs: T, rhs: T) -> Result<Option<T>, DBError> {
+    match policy {
+        OverflowPolicy::WRAP        => Ok(Some(lhs.wrapping(op, rhs))),
+        OverflowPolicy::SATURATE    => Ok(Some(lhs.saturating(op, rhs))),
+        OverflowPolicy::NULL        => Ok(lhs.checked(op, rhs)),
+        OverflowPolicy::ERROR       => match lhs.checked(op, rhs) {
+            Some(out)   => Ok(Some(out)),
+            None        => Err(DBError::Overflow(format!("{} {} {}", lhs, op, rhs))),
+        },
+    }
+}
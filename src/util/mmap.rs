@@ -0,0 +1,97 @@
+// vim : set ts=4 sw=4 et :
+
+//! Read only memory mapped files.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::slice;
+
+use ::error::DBError;
+
+/// Read only, private memory map of a whole file. Unmapped on drop.
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+    /// Fallback on platforms without mmap; the file is read into memory (u64 for alignment)
+    #[allow(dead_code)]
+    buf: Vec<u64>,
+}
+
+impl Mmap {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Mmap, DBError> {
+        Mmap::map(&File::open(path)?)
+    }
+
+    /// Map the whole file. The file can be closed afterwards.
+    pub fn map(file: &File) -> Result<Mmap, DBError> {
+        let len = file.metadata()?.len();
+        if len > isize::max_value() as u64 {
+            return Err(DBError::IO(io::Error::new(io::ErrorKind::InvalidInput, "file too large to map")))
+        }
+
+        let len = len as usize;
+        if len == 0 {
+            return Ok(Mmap { ptr: b"".as_ptr(), len: 0, buf: Vec::new() })
+        }
+
+        Mmap::map_len(file, len)
+    }
+
+    #[cfg(unix)]
+    fn map_len(file: &File, len: usize) -> Result<Mmap, DBError> {
+        use std::os::unix::io::AsRawFd;
+        use libc;
+
+        let ptr = unsafe {
+            libc::mmap(::std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(DBError::IO(io::Error::last_os_error()))
+        }
+
+        Ok(Mmap { ptr: ptr as *const u8, len: len, buf: Vec::new() })
+    }
+
+    #[cfg(not(unix))]
+    fn map_len(mut file: &File, len: usize) -> Result<Mmap, DBError> {
+        use std::io::Read;
+
+        let mut buf = vec![0u64; (len + 7) / 8];
+        {
+            let bytes = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, len) };
+            file.read_exact(bytes)?;
+        }
+
+        Ok(Mmap { ptr: buf.as_ptr() as *const u8, len: len, buf: buf })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Mapped file contents; page aligned
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+/// The mapping is read only
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            if self.len > 0 {
+                ::libc::munmap(self.ptr as *mut ::libc::c_void, self.len);
+            }
+        }
+    }
+}
@@ -1,5 +1,19 @@
+pub mod bitmap;
+pub mod bloom;
+pub mod codec;
+pub mod collation;
 pub mod copy_value;
+pub mod datetime;
+pub mod json;
+pub mod lz4;
 pub mod math;
+pub mod mmap;
+pub mod row_hash;
+pub mod selection;
+pub mod simd;
+pub mod snappy;
+pub mod sort;
+pub mod tdigest;
 
 pub use self::copy_value::ValueSetter;
 
@@ -0,0 +1,361 @@
+// vim : set ts=4 sw=4 et :
+
+//! Hashing and comparing rows by a set of key columns.
+//!
+//! The routines work a column at a time: the column type is dispatched on once and the inner loop
+//! runs over the rows, so they're suitable for hash joins, hash aggregation, distinct and
+//! partitioning (exchange) of whole chunks. NESTED columns fall back to comparing `Value`s.
+//!
+//! All NULLs hash the same. Floats are compared and hashed so `-0.0` equals `0.0` and `NaN`
+//! equals `NaN`, making them usable as grouping keys. The hash function is fixed (not seeded), so
+//! hashes can be used for partitioning across processes.
+//!
+//! TEXT and JSON columns can be hashed and compared under a collation (eg. case insensitive
+//! grouping) with the `_collated` variants; the others use binary collation.
+
+use ::block::{RefColumn, View, column_nulls, column_row_data, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types::{self, Type, Value, ValueInfo};
+use ::util::collation::Collator;
+
+/// Hash of NULL values
+pub const NULL_HASH: u64 = 0x5bd1_e995_1b87_3593;
+
+/// Initial row hash, before any columns are combined into it
+pub const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// NULL semantics of row equality
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NullEquality {
+    /// NULL equals NULL (GROUP BY, DISTINCT)
+    MATCH,
+    /// NULL doesn't equal anything, not even NULL (join keys)
+    NEVER,
+}
+
+/// Hash the `columns` of the view rows, one hash per row
+pub fn hash_rows<'v>(view: &'v View<'v>, columns: &[usize]) -> Result<Vec<u64>, DBError> {
+    hash_rows_collated(view, columns, &[])
+}
+
+/// `hash_rows` with the TEXT values of every column hashed under its collator; columns past the
+/// end of `collators` use binary collation
+pub fn hash_rows_collated<'v>(view: &'v View<'v>, columns: &[usize], collators: &[Collator])
+    -> Result<Vec<u64>, DBError>
+{
+    let binary = Collator::binary();
+    let mut hashes = vec![SEED; view.rows()];
+    for (key, &pos) in columns.iter().enumerate() {
+        let col = view.column(pos).ok_or(DBError::make_column_unknown_pos(pos))?;
+        hash_column_collated(col, collators.get(key).unwrap_or(&binary), &mut hashes)?;
+    }
+    Ok(hashes)
+}
+
+/// Combine the column value hash of each row into `hashes` (one per row)
+pub fn hash_column<'c>(col: &'c RefColumn<'c>, hashes: &mut [u64]) -> Result<(), DBError> {
+    hash_column_collated(col, &Collator::binary(), hashes)
+}
+
+/// `hash_column` with TEXT values hashed under the collation, so equal values hash the same
+pub fn hash_column_collated<'c>(col: &'c RefColumn<'c>, collator: &Collator, hashes: &mut [u64])
+    -> Result<(), DBError>
+{
+    if hashes.len() > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let text = |v: &types::RawData| hash_bytes(&collator.hash_key(bytes(v)));
+
+    match col.attribute().dtype {
+        Type::UINT32    => hash_values::<types::UInt32, _>(col, hashes, |v| mix(*v as u64)),
+        Type::UINT64    => hash_values::<types::UInt64, _>(col, hashes, |v| mix(*v)),
+        Type::INT32     => hash_values::<types::Int32, _>(col, hashes, |v| mix(*v as u64)),
+        Type::INT64     => hash_values::<types::Int64, _>(col, hashes, |v| mix(*v as u64)),
+        Type::FLOAT32   => hash_values::<types::Float32, _>(col, hashes, |v| mix(f32_bits(*v) as u64)),
+        Type::FLOAT64   => hash_values::<types::Float64, _>(col, hashes, |v| mix(f64_bits(*v))),
+        Type::BOOLEAN   => hash_values::<types::Boolean, _>(col, hashes, |v| mix(*v as u64)),
+        Type::TEXT      => hash_values::<types::Text, _>(col, hashes, text),
+        Type::JSON      => hash_values::<types::Json, _>(col, hashes, text),
+        Type::BLOB      => hash_values::<types::Blob, _>(col, hashes, |v| hash_bytes(bytes(v))),
+        Type::TIMESTAMP     => hash_values::<types::Timestamp, _>(col, hashes, |v| mix(*v as u64)),
+        Type::TIMESTAMP_TZ  => hash_values::<types::TimestampTz, _>(col, hashes, |v| mix(*v as u64)),
+        Type::LIST | Type::STRUCT | Type::MAP => {
+            for (row, hash) in hashes.iter_mut().enumerate() {
+                *hash = combine(*hash, hash_value(&column_value(col, row)?));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Compare the `left_columns` of `left` rows with the `right_columns` of `right` rows, for each
+/// pair of (left row, right row). The columns have to be of the same types.
+pub fn rows_equal<'l, 'r>(left: &'l View<'l>, left_columns: &[usize], right: &'r View<'r>, right_columns: &[usize],
+                          pairs: &[(RowOffset, RowOffset)], nulls: NullEquality) -> Result<Vec<bool>, DBError>
+{
+    rows_equal_collated(left, left_columns, right, right_columns, pairs, nulls, &[])
+}
+
+/// `rows_equal` with the TEXT values of every key compared under its collator; keys past the end
+/// of `collators` use binary collation
+pub fn rows_equal_collated<'l, 'r>(left: &'l View<'l>, left_columns: &[usize], right: &'r View<'r>,
+                                   right_columns: &[usize], pairs: &[(RowOffset, RowOffset)], nulls: NullEquality,
+                                   collators: &[Collator]) -> Result<Vec<bool>, DBError>
+{
+    if left_columns.len() != right_columns.len() {
+        return Err(DBError::SchemaMismatch(format!("{} and {} key columns", left_columns.len(), right_columns.len())))
+    }
+
+    let binary = Collator::binary();
+    let mut out = vec![true; pairs.len()];
+    for (key, (&l, &r)) in left_columns.iter().zip(right_columns).enumerate() {
+        let l = left.column(l).ok_or(DBError::make_column_unknown_pos(l))?;
+        let r = right.column(r).ok_or(DBError::make_column_unknown_pos(r))?;
+        columns_equal_collated(l, r, pairs, nulls, collators.get(key).unwrap_or(&binary), &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Clear the `out` flags of the pairs of rows with different values
+pub fn columns_equal<'l, 'r>(left: &'l RefColumn<'l>, right: &'r RefColumn<'r>, pairs: &[(RowOffset, RowOffset)],
+                             nulls: NullEquality, out: &mut [bool]) -> Result<(), DBError>
+{
+    columns_equal_collated(left, right, pairs, nulls, &Collator::binary(), out)
+}
+
+/// `columns_equal` with TEXT values compared under the collation
+pub fn columns_equal_collated<'l, 'r>(left: &'l RefColumn<'l>, right: &'r RefColumn<'r>,
+                                      pairs: &[(RowOffset, RowOffset)], nulls: NullEquality, collator: &Collator,
+                                      out: &mut [bool]) -> Result<(), DBError>
+{
+    let (lattr, rattr) = (left.attribute(), right.attribute());
+    if lattr.dtype != rattr.dtype {
+        return Err(DBError::AttributeType(format!("{} ({}) compared with {} ({})", lattr.name, lattr.dtype.name(),
+                                                  rattr.name, rattr.dtype.name())))
+    }
+
+    if pairs.iter().any(|&(l, r)| l >= left.capacity() || r >= right.capacity()) {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let cmp = Compare { left: left, right: right, pairs: pairs, nulls: nulls };
+
+    match lattr.dtype {
+        Type::UINT32    => cmp.values::<types::UInt32, _>(out, |l, r| l == r),
+        Type::UINT64    => cmp.values::<types::UInt64, _>(out, |l, r| l == r),
+        Type::INT32     => cmp.values::<types::Int32, _>(out, |l, r| l == r),
+        Type::INT64     => cmp.values::<types::Int64, _>(out, |l, r| l == r),
+        Type::FLOAT32   => cmp.values::<types::Float32, _>(out, |l, r| f32_bits(*l) == f32_bits(*r)),
+        Type::FLOAT64   => cmp.values::<types::Float64, _>(out, |l, r| f64_bits(*l) == f64_bits(*r)),
+        Type::BOOLEAN   => cmp.values::<types::Boolean, _>(out, |l, r| l == r),
+        Type::TEXT      => cmp.values::<types::Text, _>(out, |l, r| collator.equal(bytes(l), bytes(r))),
+        Type::JSON      => cmp.values::<types::Json, _>(out, |l, r| collator.equal(bytes(l), bytes(r))),
+        Type::BLOB      => cmp.values::<types::Blob, _>(out, |l, r| bytes(l) == bytes(r)),
+        Type::TIMESTAMP     => cmp.values::<types::Timestamp, _>(out, |l, r| l == r),
+        Type::TIMESTAMP_TZ  => cmp.values::<types::TimestampTz, _>(out, |l, r| l == r),
+        Type::LIST | Type::STRUCT | Type::MAP => {
+            for (eq, &(l, r)) in out.iter_mut().zip(pairs) {
+                if *eq {
+                    let (l, r) = (column_value(left, l)?, column_value(right, r)?);
+                    *eq = match (l.is_null() || r.is_null(), nulls) {
+                        (true, NullEquality::MATCH) => l.is_null() && r.is_null(),
+                        (true, NullEquality::NEVER) => false,
+                        (false, _)                  => l == r,
+                    };
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn hash_values<'c, T, F>(col: &'c RefColumn<'c>, hashes: &mut [u64], hash: F) -> Result<(), DBError>
+    where T: ValueInfo, F: Fn(&T::Store) -> u64
+{
+    let data = column_row_data::<T>(col)?;
+
+    if col.attribute().nullable {
+        for (row, h) in hashes.iter_mut().enumerate() {
+            let value = if data.nulls.get(row) { NULL_HASH } else { hash(&data.values[row]) };
+            *h = combine(*h, value);
+        }
+    } else {
+        for (row, h) in hashes.iter_mut().enumerate() {
+            *h = combine(*h, hash(&data.values[row]));
+        }
+    }
+
+    Ok(())
+}
+
+/// Pairwise comparison of two columns
+struct Compare<'l, 'r, 'p> {
+    left: &'l RefColumn<'l>,
+    right: &'r RefColumn<'r>,
+    pairs: &'p [(RowOffset, RowOffset)],
+    nulls: NullEquality,
+}
+
+impl<'l, 'r, 'p> Compare<'l, 'r, 'p> {
+    fn values<T, F>(&self, out: &mut [bool], eq: F) -> Result<(), DBError>
+        where T: ValueInfo, F: Fn(&T::Store, &T::Store) -> bool
+    {
+        let (l, r) = (column_row_data::<T>(self.left)?, column_row_data::<T>(self.right)?);
+
+        if !self.left.attribute().nullable && !self.right.attribute().nullable {
+            for (out, &(lrow, rrow)) in out.iter_mut().zip(self.pairs) {
+                *out = *out && eq(&l.values[lrow], &r.values[rrow]);
+            }
+            return Ok(())
+        }
+
+        let (lnulls, rnulls) = (column_nulls(self.left), column_nulls(self.right));
+        let is_null = |nullable: bool, nulls: &::bitmaps::Bitmap, row| nullable && nulls.get(row);
+
+        for (out, &(lrow, rrow)) in out.iter_mut().zip(self.pairs) {
+            if !*out {
+                continue
+            }
+
+            let lnull = is_null(self.left.attribute().nullable, &lnulls, lrow);
+            let rnull = is_null(self.right.attribute().nullable, &rnulls, rrow);
+
+            *out = match (lnull || rnull, self.nulls) {
+                (true, NullEquality::MATCH) => lnull && rnull,
+                (true, NullEquality::NEVER) => false,
+                (false, _)                  => eq(&l.values[lrow], &r.values[rrow]),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn bytes(raw: &types::RawData) -> &[u8] {
+    raw.as_ref()
+}
+
+/// Bits of the value with `-0.0` and `NaN`s normalized
+fn f32_bits(v: f32) -> u32 {
+    if v == 0.0 { 0 } else if v.is_nan() { ::std::f32::NAN.to_bits() } else { v.to_bits() }
+}
+
+fn f64_bits(v: f64) -> u64 {
+    if v == 0.0 { 0 } else if v.is_nan() { ::std::f64::NAN.to_bits() } else { v.to_bits() }
+}
+
+/// Finalizer of MurmurHash3; every input bit affects every output bit
+#[inline]
+pub fn mix(mut v: u64) -> u64 {
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    v ^= v >> 33;
+    v = v.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    v ^ (v >> 33)
+}
+
+/// Combine the hash of the next column into the row hash. Order dependent.
+#[inline]
+pub fn combine(seed: u64, hash: u64) -> u64 {
+    mix(seed.rotate_left(23) ^ hash.wrapping_add(SEED))
+}
+
+pub fn hash_bytes(data: &[u8]) -> u64 {
+    let mut h = SEED ^ (data.len() as u64).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    for chunk in data.chunks(8) {
+        let word = chunk.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        h = (h ^ mix(word)).rotate_left(27).wrapping_mul(5).wrapping_add(0x52dc_e729);
+    }
+    mix(h)
+}
+
+/// Hash of a value; the same as the column hash of a row with the value
+pub fn hash_value(value: &Value) -> u64 {
+    match *value {
+        Value::NULL                                 => NULL_HASH,
+        Value::UINT32(v)                            => mix(v as u64),
+        Value::UINT64(v)                            => mix(v),
+        Value::INT32(v)                             => mix(v as u64),
+        Value::INT64(v)                             => mix(v as u64),
+        Value::FLOAT32(v)                           => mix(f32_bits(v) as u64),
+        Value::FLOAT64(v)                           => mix(f64_bits(v)),
+        Value::BOOLEAN(v)                           => mix(v as u64),
+        Value::TEXT(ref v) | Value::JSON(ref v)     => hash_bytes(v.as_bytes()),
+        Value::BLOB(ref v)                          => hash_bytes(v),
+        Value::TIMESTAMP(v) | Value::TIMESTAMP_TZ(v) => mix(v as u64),
+        Value::LIST(ref v) | Value::STRUCT(ref v)   => v.iter().fold(SEED, |h, v| combine(h, hash_value(v))),
+        Value::MAP(ref v)                           => v.iter()
+            .fold(SEED, |h, &(ref k, ref v)| combine(combine(h, hash_value(k)), hash_value(v))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::schema::Schema;
+    use ::util::collation::Collation;
+    use ::util::copy_value::set_column_value;
+
+    fn block(rows: &[(Option<i64>, &str, f64)]) -> Block<'static> {
+        let schema = Schema::parse_ddl("k INT64, s TEXT NOT NULL, f FLOAT64 NOT NULL, l LIST<INT32>").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(rows.len()).unwrap();
+        for (row, &(k, s, f)) in rows.iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::from(k)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from(s)).unwrap();
+            set_column_value(&mut block, 2, row, &Value::FLOAT64(f)).unwrap();
+            set_column_value(&mut block, 3, row, &Value::LIST(vec![Value::INT32(s.len() as i32)])).unwrap();
+        }
+        block
+    }
+
+    #[test]
+    fn hash_and_compare() {
+        let left = block(&[(Some(1), "a", 0.0), (None, "long text value", ::std::f64::NAN), (Some(1), "b", 1.5)]);
+        let right = block(&[(None, "long text value", ::std::f64::NAN), (Some(1), "a", -0.0)]);
+
+        let lh = hash_rows(&left, &[0, 1, 2, 3]).unwrap();
+        let rh = hash_rows(&right, &[0, 1, 2, 3]).unwrap();
+        assert_eq!(lh[1], rh[0]);
+        assert_eq!(lh[0], rh[1]);
+        assert!(lh[0] != lh[2]);
+        assert!(lh[0] != lh[1]);
+
+        // Column order matters
+        assert!(hash_rows(&left, &[1, 0]).unwrap()[0] != hash_rows(&left, &[0, 1]).unwrap()[0]);
+        assert_eq!(hash_rows(&left, &[3]).unwrap()[0], combine(SEED, hash_value(&Value::LIST(vec![Value::INT32(1)]))));
+
+        let pairs = [(0, 1), (1, 0), (2, 1), (0, 0)];
+        let cols = [0, 1, 2];
+        assert_eq!(rows_equal(&left, &cols, &right, &cols, &pairs, NullEquality::MATCH).unwrap(),
+                   vec![true, true, false, false]);
+        assert_eq!(rows_equal(&left, &cols, &right, &cols, &pairs, NullEquality::NEVER).unwrap(),
+                   vec![true, false, false, false]);
+        assert_eq!(rows_equal(&left, &[3], &right, &[3], &pairs, NullEquality::NEVER).unwrap(),
+                   vec![true, true, true, false]);
+
+        assert!(rows_equal(&left, &[0], &right, &[1], &pairs, NullEquality::MATCH).is_err());
+        assert!(rows_equal(&left, &[0], &right, &[0], &[(0, 1 << 20)], NullEquality::MATCH).is_err());
+        assert!(hash_rows(&left, &[4]).is_err());
+
+        // Case insensitive
+        let nocase = Collation::NOCASE.collator().unwrap();
+        let upper = block(&[(Some(1), "A", 0.0)]);
+        let (mut lh, mut uh) = (vec![SEED; 1], vec![SEED; 1]);
+        hash_column_collated(left.column(1).unwrap(), &nocase, &mut lh).unwrap();
+        hash_column_collated(upper.column(1).unwrap(), &nocase, &mut uh).unwrap();
+        assert_eq!(lh, uh);
+
+        let mut eq = vec![true];
+        columns_equal(left.column(1).unwrap(), upper.column(1).unwrap(), &[(0, 0)], NullEquality::MATCH, &mut eq).unwrap();
+        assert_eq!(eq, vec![false]);
+        eq[0] = true;
+        columns_equal_collated(left.column(1).unwrap(), upper.column(1).unwrap(), &[(0, 0)], NullEquality::MATCH,
+                               &nocase, &mut eq).unwrap();
+        assert_eq!(eq, vec![true]);
+    }
+}
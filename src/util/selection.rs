@@ -0,0 +1,187 @@
+// vim : set ts=4 sw=4 et :
+
+//! Selection vectors: sorted positions of the selected rows of a chunk.
+//!
+//! Conversions between BOOLEAN columns (predicate results), bit-packed bitmaps (null vectors) and
+//! selection vectors. The conversions are branch free or work a word at a time so the compiler can
+//! vectorize them.
+
+use ::block::{Bitmap, MutBitmap, RefColumn, column_nulls, column_row_data};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types;
+use ::util::bitmap::{bytes_for, get_bit, read_word, set_bit};
+
+/// Positions of the `values` that are true
+pub fn bools_to_indices(values: &[bool]) -> Vec<RowOffset> {
+    let mut out = vec![0; values.len()];
+    let mut len = 0;
+
+    // Always write, only advance on a match
+    for (row, &v) in values.iter().enumerate() {
+        out[len] = row;
+        len += v as usize;
+    }
+
+    out.truncate(len);
+    out
+}
+
+/// Positions of the bits in [from, bits) of `data` equal to `value`
+pub fn bits_to_indices(data: &[u8], from: usize, bits: usize, value: bool) -> Vec<RowOffset> {
+    let flip = if value { 0 } else { !0 };
+    let mut out = Vec::new();
+    let mut idx = from;
+
+    // Unaligned head
+    while idx < bits && idx & 7 != 0 {
+        if get_bit(data, idx) == value {
+            out.push(idx);
+        }
+        idx += 1;
+    }
+
+    while idx + 64 <= bits {
+        let mut word = read_word(data, idx >> 3) ^ flip;
+        while word != 0 {
+            out.push(idx + word.trailing_zeros() as usize);
+            word &= word - 1;
+        }
+        idx += 64;
+    }
+
+    while idx < bits {
+        if get_bit(data, idx) == value {
+            out.push(idx);
+        }
+        idx += 1;
+    }
+
+    out
+}
+
+/// Positions of the bits of the bitmap equal to `value`
+pub fn bitmap_to_indices(bitmap: &Bitmap, value: bool) -> Vec<RowOffset> {
+    let offset = bitmap.offset();
+    let mut out = bits_to_indices(bitmap.raw(), offset, offset + bitmap.len(), value);
+    if offset > 0 {
+        for row in &mut out {
+            *row -= offset;
+        }
+    }
+    out
+}
+
+/// Bitmap of `bits` bits with the `indices` set
+pub fn indices_to_bitmap(indices: &[RowOffset], bits: usize) -> Vec<u8> {
+    let mut data = vec![0u8; bytes_for(bits)];
+    for &idx in indices {
+        set_bit(&mut data, idx, true);
+    }
+    data
+}
+
+/// Bitmap of the `values`, eight at a time
+pub fn bools_to_bitmap(values: &[bool]) -> Vec<u8> {
+    let mut data = vec![0u8; bytes_for(values.len())];
+    for (byte, chunk) in data.iter_mut().zip(values.chunks(8)) {
+        *byte = chunk.iter().enumerate().fold(0, |acc, (bit, &v)| acc | ((v as u8) << bit));
+    }
+    data
+}
+
+/// Rows (of the first `rows`) of a BOOLEAN column that are true. NULLs aren't selected.
+pub fn selected_rows<'c>(col: &'c RefColumn<'c>, rows: RowOffset) -> Result<Vec<RowOffset>, DBError> {
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    let values = &column_row_data::<types::Boolean>(col)?.values[.. rows];
+    if !col.attribute().nullable {
+        return Ok(bools_to_indices(values))
+    }
+
+    // NULL rows are masked out a word at a time
+    let mut bits = bools_to_bitmap(values);
+    MutBitmap::new(&mut bits, rows).and_not(&column_nulls(col).slice(0, rows));
+    Ok(bits_to_indices(&bits, 0, rows, true))
+}
+
+/// Rows (of the first `rows`) of a column that are not NULL
+pub fn valid_rows<'c>(col: &'c RefColumn<'c>, rows: RowOffset) -> Result<Vec<RowOffset>, DBError> {
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    if !col.attribute().nullable {
+        return Ok((0 .. rows).collect())
+    }
+
+    Ok(bitmap_to_indices(&column_nulls(col).slice(0, rows), false))
+}
+
+/// Rows present in both selections
+pub fn intersect(lhs: &[RowOffset], rhs: &[RowOffset]) -> Vec<RowOffset> {
+    let mut out = Vec::with_capacity(lhs.len().min(rhs.len()));
+    let (mut l, mut r) = (0, 0);
+
+    while l < lhs.len() && r < rhs.len() {
+        if lhs[l] < rhs[r] {
+            l += 1;
+        } else if lhs[l] > rhs[r] {
+            r += 1;
+        } else {
+            out.push(lhs[l]);
+            l += 1;
+            r += 1;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::schema::Schema;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn conversions() {
+        let values: Vec<bool> = (0 .. 150).map(|v| v % 3 == 0 || (v >= 70 && v < 140)).collect();
+        let expected: Vec<RowOffset> = (0 .. 150).filter(|&v| values[v]).collect();
+
+        assert_eq!(bools_to_indices(&values), expected);
+
+        let bitmap = bools_to_bitmap(&values);
+        assert_eq!(bitmap, indices_to_bitmap(&expected, 150));
+        assert_eq!(bits_to_indices(&bitmap, 0, 150, true), expected);
+        assert_eq!(bits_to_indices(&bitmap, 0, 150, false), (0 .. 150).filter(|&v| !values[v]).collect::<Vec<_>>());
+
+        let sliced = Bitmap::new(&bitmap, 0, 150).slice(5, 140);
+        assert_eq!(bitmap_to_indices(&sliced, true),
+                   expected.iter().filter(|&&v| v >= 5 && v < 145).map(|&v| v - 5).collect::<Vec<_>>());
+
+        assert_eq!(intersect(&[1, 3, 5, 7, 9], &[0, 3, 4, 9, 11]), vec![3, 9]);
+        assert_eq!(intersect(&[], &[1]), Vec::<RowOffset>::new());
+    }
+
+    #[test]
+    fn columns() {
+        let schema = Schema::parse_ddl("b BOOLEAN").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(5).unwrap();
+        for (row, &v) in [Some(true), None, Some(false), Some(true), None].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::from(v)).unwrap();
+        }
+
+        let col = block.column(0).unwrap();
+        assert_eq!(selected_rows(col, 5).unwrap(), vec![0, 3]);
+        assert_eq!(valid_rows(col, 5).unwrap(), vec![0, 2, 3]);
+        assert_eq!(valid_rows(col, 3).unwrap(), vec![0, 2]);
+        assert!(selected_rows(col, 1 << 20).is_err());
+    }
+}
@@ -0,0 +1,975 @@
+// vim : set ts=4 sw=4 et :
+
+//! Reductions of numeric column values: SUM, MIN, MAX and COUNT of the non-NULL values, and the
+//! accumulators of the other aggregate functions.
+//!
+//! The kernels keep `LANES` independent partial results and have branch free inner loops, so the
+//! compiler vectorizes them. NULL rows are replaced with the identity of the reduction, a null
+//! bitmap word (64 rows) at a time.
+//!
+//! Integer sums are exact and fail with `DBError::Overflow` when the result doesn't fit, float
+//! sums follow IEEE semantics. MIN and MAX ignore float NaNs. Accumulators keep the partial sums
+//! of the kernels, so only the final result can overflow; what happens then is up to the
+//! `OverflowPolicy`.
+
+use std::any::Any;
+use std::marker::PhantomData;
+
+use ::block::{Bitmap, RefColumn, column_nulls, column_row_data, column_value};
+use ::error::DBError;
+use ::plan::AggregateFunc;
+use ::row::RowOffset;
+use ::types::{self, Type, Value, ValueInfo};
+use ::util::bitmap::read_word;
+use ::util::math::OverflowPolicy;
+use ::util::tdigest::TDigest;
+
+/// Independent partial results kept by the kernels
+pub const LANES: usize = 8;
+
+/// Numeric value that can be reduced
+pub trait Reduce: Copy + PartialOrd + Into<Value<'static>> + 'static {
+    /// Result of SUM
+    type Sum: Copy + Into<Value<'static>> + 'static;
+    /// Running sum of the kernels, wide enough not to overflow
+    type Partial: Copy;
+    /// Exact sum of any number of runs of values
+    type Total: Copy;
+
+    const ZERO: Self;
+    /// Identity of MIN
+    const MAX: Self;
+    /// Identity of MAX
+    const MIN: Self;
+    const PARTIAL_ZERO: Self::Partial;
+    const TOTAL_ZERO: Self::Total;
+
+    fn add(acc: Self::Partial, value: Self) -> Self::Partial;
+    fn combine(lhs: Self::Partial, rhs: Self::Partial) -> Self::Partial;
+    fn finish(acc: Self::Partial) -> Result<Self::Sum, DBError>;
+    fn total(acc: Self::Partial) -> Self::Total;
+    /// Add up totals of separate runs of values
+    fn add_totals(lhs: Self::Total, rhs: Self::Total) -> Self::Total;
+    /// The total as a `Sum`; `Err` with the closest `Sum` if it doesn't fit
+    fn narrow(total: Self::Total) -> Result<Self::Sum, Self::Sum>;
+    /// The total wrapped around into a `Sum`
+    fn wrapped(total: Self::Total) -> Self::Sum;
+    /// Nearest float, for MEDIAN and percentiles
+    fn to_f64(self) -> f64;
+
+    #[inline]
+    fn lesser(lhs: Self, rhs: Self) -> Self {
+        if rhs < lhs { rhs } else { lhs }
+    }
+
+    #[inline]
+    fn greater(lhs: Self, rhs: Self) -> Self {
+        if rhs > lhs { rhs } else { lhs }
+    }
+}
+
+/// 32bit integers are summed in 64bits, overflowing only past 2^32 rows
+macro_rules! reduce_narrow {
+    ($t:ty, $sum:ty) => {
+        impl Reduce for $t {
+            type Sum = $sum;
+            type Partial = $sum;
+            type Total = $sum;
+
+            const ZERO: $t = 0;
+            const MAX: $t = <$t>::max_value();
+            const MIN: $t = <$t>::min_value();
+            const PARTIAL_ZERO: $sum = 0;
+            const TOTAL_ZERO: $sum = 0;
+
+            #[inline]
+            fn add(acc: $sum, value: $t) -> $sum {
+                acc.wrapping_add(value as $sum)
+            }
+
+            #[inline]
+            fn combine(lhs: $sum, rhs: $sum) -> $sum {
+                lhs.wrapping_add(rhs)
+            }
+
+            fn finish(acc: $sum) -> Result<$sum, DBError> {
+                Ok(acc)
+            }
+
+            fn total(acc: $sum) -> $sum {
+                acc
+            }
+
+            fn add_totals(lhs: $sum, rhs: $sum) -> $sum {
+                lhs.wrapping_add(rhs)
+            }
+
+            fn narrow(total: $sum) -> Result<$sum, $sum> {
+                Ok(total)
+            }
+
+            fn wrapped(total: $sum) -> $sum {
+                total
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    }
+}
+
+/// 64bit integers are summed as separate high and low 32bit halves, which can't overflow
+/// (before 2^32 rows); the halves are recombined with overflow checks.
+macro_rules! reduce_wide {
+    ($t:ty, $hi:ty) => {
+        impl Reduce for $t {
+            type Sum = $t;
+            type Partial = ($hi, u64);
+            type Total = ($hi, u64);
+
+            const ZERO: $t = 0;
+            const MAX: $t = <$t>::max_value();
+            const MIN: $t = <$t>::min_value();
+            const PARTIAL_ZERO: ($hi, u64) = (0, 0);
+            const TOTAL_ZERO: ($hi, u64) = (0, 0);
+
+            #[inline]
+            fn add(acc: ($hi, u64), value: $t) -> ($hi, u64) {
+                (acc.0.wrapping_add((value >> 32) as $hi), acc.1.wrapping_add(value as u64 & 0xffff_ffff))
+            }
+
+            #[inline]
+            fn combine(lhs: ($hi, u64), rhs: ($hi, u64)) -> ($hi, u64) {
+                (lhs.0.wrapping_add(rhs.0), lhs.1.wrapping_add(rhs.1))
+            }
+
+            fn finish(acc: ($hi, u64)) -> Result<$t, DBError> {
+                Self::narrow(acc).map_err(|_| DBError::Overflow("SUM".to_string()))
+            }
+
+            fn total(acc: ($hi, u64)) -> ($hi, u64) {
+                acc
+            }
+
+            fn add_totals(lhs: ($hi, u64), rhs: ($hi, u64)) -> ($hi, u64) {
+                Self::combine(lhs, rhs)
+            }
+
+            fn narrow(total: ($hi, u64)) -> Result<$t, $t> {
+                let carry = (total.1 >> 32) as $hi;
+                total.0.checked_add(carry)
+                    .and_then(|hi| hi.checked_mul(1 << 32))
+                    .and_then(|hi| hi.checked_add((total.1 & 0xffff_ffff) as $t))
+                    .ok_or_else(|| if total.0.saturating_add(carry) > 0 { Self::MAX } else { Self::MIN })
+            }
+
+            fn wrapped(total: ($hi, u64)) -> $t {
+                total.0.wrapping_add((total.1 >> 32) as $hi)
+                    .wrapping_mul(1 << 32)
+                    .wrapping_add((total.1 & 0xffff_ffff) as $t)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    }
+}
+
+macro_rules! reduce_float {
+    ($t:ident) => {
+        impl Reduce for $t {
+            type Sum = f64;
+            type Partial = f64;
+            type Total = f64;
+
+            const ZERO: $t = 0.0;
+            const MAX: $t = ::std::$t::INFINITY;
+            const MIN: $t = ::std::$t::NEG_INFINITY;
+            const PARTIAL_ZERO: f64 = 0.0;
+            const TOTAL_ZERO: f64 = 0.0;
+
+            #[inline]
+            fn add(acc: f64, value: $t) -> f64 {
+                acc + value as f64
+            }
+
+            #[inline]
+            fn combine(lhs: f64, rhs: f64) -> f64 {
+                lhs + rhs
+            }
+
+            fn finish(acc: f64) -> Result<f64, DBError> {
+                Ok(acc)
+            }
+
+            fn total(acc: f64) -> f64 {
+                acc
+            }
+
+            fn add_totals(lhs: f64, rhs: f64) -> f64 {
+                lhs + rhs
+            }
+
+            fn narrow(total: f64) -> Result<f64, f64> {
+                Ok(total)
+            }
+
+            fn wrapped(total: f64) -> f64 {
+                total
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    }
+}
+
+reduce_narrow!(u32, u64);
+reduce_narrow!(i32, i64);
+reduce_wide!(u64, u64);
+reduce_wide!(i64, i64);
+reduce_float!(f32);
+reduce_float!(f64);
+
+/// Null flags of the 64 rows starting at `row`
+#[inline]
+fn null_word(nulls: &Bitmap, row: usize) -> u64 {
+    let bit = nulls.offset() + row;
+    let (data, byte, shift) = (nulls.raw(), bit >> 3, bit & 7);
+
+    if shift == 0 && byte + 8 <= data.len() {
+        read_word(data, byte)
+    } else if byte + 9 <= data.len() {
+        read_word(data, byte) >> shift | (data[byte + 8] as u64) << (64 - shift)
+    } else {
+        (0 .. 64).fold(0, |word, idx| word | (nulls.get(row + idx) as u64) << idx)
+    }
+}
+
+/// Fold the values into `LANES` partial results, the NULL rows (set in `nulls`) are replaced with
+/// `identity`
+#[inline]
+fn fold<T, A, F, C>(values: &[T], nulls: Option<&Bitmap>, identity: T, init: A, f: F, combine: C) -> A
+    where T: Copy, A: Copy, F: Fn(A, T) -> A, C: Fn(A, A) -> A
+{
+    let mut acc = [init; LANES];
+    let mut pos = 0;
+
+    match nulls {
+        None => {
+            while pos + LANES <= values.len() {
+                let chunk = &values[pos .. pos + LANES];
+                for lane in 0 .. LANES {
+                    acc[lane] = f(acc[lane], chunk[lane]);
+                }
+                pos += LANES;
+            }
+        }
+        Some(nulls) => {
+            while pos + 64 <= values.len() {
+                let word = null_word(nulls, pos);
+                for block in 0 .. 64 / LANES {
+                    let chunk = &values[pos + block * LANES .. pos + (block + 1) * LANES];
+                    let bits = word >> (block * LANES);
+                    for lane in 0 .. LANES {
+                        let value = if bits >> lane & 1 == 0 { chunk[lane] } else { identity };
+                        acc[lane] = f(acc[lane], value);
+                    }
+                }
+                pos += 64;
+            }
+        }
+    }
+
+    let mut out = acc[1 ..].iter().fold(acc[0], |l, &r| combine(l, r));
+    for row in pos .. values.len() {
+        let null = nulls.map_or(false, |n| n.get(row));
+        out = f(out, if null { identity } else { values[row] });
+    }
+    out
+}
+
+/// Number of the `rows` that aren't NULL
+pub fn count_valid(rows: usize, nulls: Option<&Bitmap>) -> usize {
+    match nulls {
+        Some(nulls) => rows - nulls.slice(0, rows).count_ones(),
+        None        => rows,
+    }
+}
+
+/// Sum of the values, skipping the rows set in `nulls`. 0 if there are none.
+pub fn sum<T: Reduce>(values: &[T], nulls: Option<&Bitmap>) -> Result<T::Sum, DBError> {
+    T::finish(fold(values, nulls, T::ZERO, T::PARTIAL_ZERO, T::add, T::combine))
+}
+
+/// Exact sum of the values, skipping the rows set in `nulls`
+pub fn sum_total<T: Reduce>(values: &[T], nulls: Option<&Bitmap>) -> T::Total {
+    T::total(fold(values, nulls, T::ZERO, T::PARTIAL_ZERO, T::add, T::combine))
+}
+
+/// Smallest of the values, skipping the rows set in `nulls`
+pub fn min<T: Reduce>(values: &[T], nulls: Option<&Bitmap>) -> Option<T> {
+    if count_valid(values.len(), nulls) == 0 {
+        return None
+    }
+
+    Some(fold(values, nulls, T::MAX, T::MAX, T::lesser, T::lesser))
+}
+
+/// Largest of the values, skipping the rows set in `nulls`
+pub fn max<T: Reduce>(values: &[T], nulls: Option<&Bitmap>) -> Option<T> {
+    if count_valid(values.len(), nulls) == 0 {
+        return None
+    }
+
+    Some(fold(values, nulls, T::MIN, T::MIN, T::greater, T::greater))
+}
+
+/// Running aggregate of a column, updated a chunk at a time
+pub trait Accumulator {
+    /// Aggregate the first `rows` rows of the column
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError>;
+
+    /// Aggregate the first `rows` rows of the column, with the same rows of the companion columns
+    /// the function reads (the `by` column of ARGMIN / ARGMAX)
+    fn update_with<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        self.update(col, rows)
+    }
+
+    /// Aggregate the `selection` rows (sorted positions, eg. the rows of a group) of the column
+    /// and the companion columns
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>;
+
+    /// Combine with the partial aggregate of another accumulator of the same function and type,
+    /// over rows that come after this one's (for FIRST / LAST)
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError>;
+
+    /// COUNT is `UINT64`; SUM, MIN and MAX without any non-NULL rows are NULL
+    fn result(&self) -> Result<Value<'static>, DBError>;
+
+    fn as_any(&self) -> &Any;
+}
+
+/// `$make` boxed, with `$t` the `ValueInfo` of the numeric `$dtype`
+macro_rules! numeric_accumulator {
+    ($func:expr, $dtype:expr, $t:ident => $make:expr) => {
+        match $dtype {
+            Type::UINT32    => { type $t = types::UInt32; let acc: Box<Accumulator> = Box::new($make); acc }
+            Type::UINT64    => { type $t = types::UInt64; let acc: Box<Accumulator> = Box::new($make); acc }
+            Type::INT32     => { type $t = types::Int32; let acc: Box<Accumulator> = Box::new($make); acc }
+            Type::INT64     => { type $t = types::Int64; let acc: Box<Accumulator> = Box::new($make); acc }
+            Type::FLOAT32   => { type $t = types::Float32; let acc: Box<Accumulator> = Box::new($make); acc }
+            Type::FLOAT64   => { type $t = types::Float64; let acc: Box<Accumulator> = Box::new($make); acc }
+            dtype           => return Err(DBError::ExpressionInputType(format!("{}({})", $func.name(), dtype.name()))),
+        }
+    }
+}
+
+/// Accumulator of the aggregate function over a column of `dtype`. COUNT, ARGMIN / ARGMAX, FIRST
+/// and LAST support any type, the others only numeric ones.
+pub fn accumulator(func: AggregateFunc, dtype: Type, overflow: OverflowPolicy) -> Result<Box<Accumulator>, DBError> {
+    let out = match func {
+        AggregateFunc::COUNT                => return Ok(Box::new(CountAccumulator { count: 0 })),
+        AggregateFunc::MEDIAN               => numeric_accumulator!(func, dtype, T => MedianAccumulator::<T>::new()),
+        AggregateFunc::PERCENTILEAPPROX(q)  => numeric_accumulator!(func, dtype, T => PercentileAccumulator::<T>::new(q)),
+        AggregateFunc::ARGMIN               => return Ok(Box::new(ArgAccumulator { max: false, best: None })),
+        AggregateFunc::ARGMAX               => return Ok(Box::new(ArgAccumulator { max: true, best: None })),
+        AggregateFunc::FIRST                => return Ok(Box::new(FirstLastAccumulator { last: false, value: None })),
+        AggregateFunc::LAST                 => return Ok(Box::new(FirstLastAccumulator { last: true, value: None })),
+        _                                   => numeric_accumulator!(func, dtype, T => ReduceAccumulator::<T>::new(func, overflow)),
+    };
+
+    Ok(out)
+}
+
+/// Aggregate of the first `rows` rows of a column
+pub fn reduce_column<'c>(func: AggregateFunc, col: &'c RefColumn<'c>, rows: RowOffset)
+    -> Result<Value<'static>, DBError>
+{
+    let mut acc = accumulator(func, col.attribute().dtype, OverflowPolicy::ERROR)?;
+    acc.update(col, rows)?;
+    acc.result()
+}
+
+fn check_rows<'c>(col: &'c RefColumn<'c>, rows: RowOffset) -> Result<Option<Bitmap<'c>>, DBError> {
+    if rows > col.capacity() {
+        return Err(DBError::RowOutOfBounds)
+    }
+
+    Ok(if col.attribute().nullable { Some(column_nulls(col).slice(0, rows)) } else { None })
+}
+
+/// Rows spanned by a selection
+fn selection_rows(selection: &[RowOffset]) -> RowOffset {
+    selection.last().map_or(0, |row| row + 1)
+}
+
+fn no_companions<'c>(companions: &[&'c RefColumn<'c>]) -> Result<(), DBError> {
+    if !companions.is_empty() {
+        return Err(DBError::InvalidArgument(format!("{} companion columns", companions.len())))
+    }
+    Ok(())
+}
+
+fn mismatch() -> DBError {
+    DBError::ExpressionInputType("merging different accumulators".to_string())
+}
+
+struct CountAccumulator {
+    count: usize,
+}
+
+impl Accumulator for CountAccumulator {
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError> {
+        let nulls = check_rows(col, rows)?;
+        self.count += count_valid(rows, nulls.as_ref());
+        Ok(())
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        self.count += match check_rows(col, selection_rows(selection))? {
+            Some(nulls) => selection.iter().filter(|row| !nulls.get(**row)).count(),
+            None        => selection.len(),
+        };
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<CountAccumulator>().ok_or_else(mismatch)?;
+        self.count += other.count;
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        Ok(Value::UINT64(self.count as u64))
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+struct ReduceAccumulator<T: ValueInfo> where T::Store: Reduce {
+    func: AggregateFunc,
+    overflow: OverflowPolicy,
+    /// Non-NULL rows
+    count: usize,
+    sum: <T::Store as Reduce>::Total,
+    value: T::Store,
+    pt: PhantomData<T>,
+}
+
+impl<T: ValueInfo> ReduceAccumulator<T> where T::Store: Reduce {
+    fn new(func: AggregateFunc, overflow: OverflowPolicy) -> ReduceAccumulator<T> {
+        let value = if func == AggregateFunc::MIN { T::Store::MAX } else { T::Store::MIN };
+        ReduceAccumulator {
+            func: func,
+            overflow: overflow,
+            count: 0,
+            sum: T::Store::TOTAL_ZERO,
+            value: value,
+            pt: PhantomData,
+        }
+    }
+
+    fn add(&mut self, count: usize, sum: <T::Store as Reduce>::Total, value: T::Store) {
+        match self.func {
+            AggregateFunc::SUM  => self.sum = T::Store::add_totals(self.sum, sum),
+            AggregateFunc::MIN  => self.value = T::Store::lesser(self.value, value),
+            _                   => self.value = T::Store::greater(self.value, value),
+        }
+
+        self.count += count;
+    }
+}
+
+impl<T: ValueInfo + 'static> Accumulator for ReduceAccumulator<T> where T::Store: Reduce {
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError> {
+        let nulls = check_rows(col, rows)?;
+        let values = &column_row_data::<T>(col)?.values[.. rows];
+        let count = count_valid(rows, nulls.as_ref());
+        if count == 0 {
+            return Ok(())
+        }
+
+        match self.func {
+            AggregateFunc::SUM  => {
+                let sum = sum_total(values, nulls.as_ref());
+                self.add(count, sum, T::Store::ZERO)
+            }
+            AggregateFunc::MIN  => {
+                let value = min(values, nulls.as_ref()).unwrap();
+                self.add(count, T::Store::TOTAL_ZERO, value)
+            }
+            _                   => {
+                let value = max(values, nulls.as_ref()).unwrap();
+                self.add(count, T::Store::TOTAL_ZERO, value)
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        let nulls = check_rows(col, selection_rows(selection))?;
+        let values = &column_row_data::<T>(col)?.values;
+
+        let (mut count, mut partial, mut value) = (0, T::Store::PARTIAL_ZERO, self.value);
+        for &row in selection {
+            if nulls.as_ref().map_or(false, |n| n.get(row)) {
+                continue
+            }
+
+            count += 1;
+            match self.func {
+                AggregateFunc::SUM  => partial = T::Store::add(partial, values[row]),
+                AggregateFunc::MIN  => value = T::Store::lesser(value, values[row]),
+                _                   => value = T::Store::greater(value, values[row]),
+            }
+        }
+
+        if count > 0 {
+            self.add(count, T::Store::total(partial), value);
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<ReduceAccumulator<T>>()
+            .filter(|o| o.func == self.func)
+            .ok_or_else(mismatch)?;
+
+        if other.count == 0 {
+            return Ok(())
+        }
+
+        self.add(other.count, other.sum, other.value);
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        match (self.count, self.func) {
+            (0, _)                  => return Ok(Value::NULL),
+            (_, AggregateFunc::SUM) => (),
+            _                       => return Ok(self.value.into()),
+        }
+
+        match (T::Store::narrow(self.sum), self.overflow) {
+            (Ok(sum), _)                            => Ok(sum.into()),
+            (Err(_), OverflowPolicy::ERROR)         => Err(DBError::Overflow(format!("SUM of {} rows", self.count))),
+            (Err(_), OverflowPolicy::NULL)          => Ok(Value::NULL),
+            (Err(sum), OverflowPolicy::SATURATE)    => Ok(sum.into()),
+            (Err(_), OverflowPolicy::WRAP)          => Ok(T::Store::wrapped(self.sum).into()),
+        }
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// Non-NULL values of the `rows` (below `bound`), NaNs are skipped
+fn for_each_valid<'c, T, I, F>(col: &'c RefColumn<'c>, bound: RowOffset, rows: I, mut f: F) -> Result<(), DBError>
+    where T: ValueInfo, T::Store: Reduce, I: Iterator<Item = RowOffset>, F: FnMut(T::Store)
+{
+    let nulls = check_rows(col, bound)?;
+    let values = &column_row_data::<T>(col)?.values[.. bound];
+    for row in rows {
+        let value = values[row];
+        // NaN != NaN
+        if nulls.as_ref().map_or(false, |n| n.get(row)) || value != value {
+            continue
+        }
+        f(value);
+    }
+
+    Ok(())
+}
+
+/// All the values, sorted for the result
+struct MedianAccumulator<T: ValueInfo> where T::Store: Reduce {
+    values: Vec<T::Store>,
+    pt: PhantomData<T>,
+}
+
+impl<T: ValueInfo> MedianAccumulator<T> where T::Store: Reduce {
+    fn new() -> MedianAccumulator<T> {
+        MedianAccumulator { values: Vec::new(), pt: PhantomData }
+    }
+}
+
+impl<T: ValueInfo + 'static> Accumulator for MedianAccumulator<T> where T::Store: Reduce {
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError> {
+        let values = &mut self.values;
+        for_each_valid::<T, _, _>(col, rows, 0 .. rows, |v| values.push(v))
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        let values = &mut self.values;
+        for_each_valid::<T, _, _>(col, selection_rows(selection), selection.iter().cloned(), |v| values.push(v))
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<MedianAccumulator<T>>().ok_or_else(mismatch)?;
+        self.values.extend_from_slice(&other.values);
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        if self.values.is_empty() {
+            return Ok(Value::NULL)
+        }
+
+        let mut sorted = self.values.clone();
+        sorted.sort_by(|l, r| l.partial_cmp(r).unwrap());
+        let mid = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 1 {
+            sorted[mid].to_f64()
+        } else {
+            (sorted[mid - 1].to_f64() + sorted[mid].to_f64()) / 2.0
+        };
+
+        Ok(Value::FLOAT64(median))
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+struct PercentileAccumulator<T> {
+    fraction: f64,
+    digest: TDigest,
+    pt: PhantomData<T>,
+}
+
+impl<T: ValueInfo> PercentileAccumulator<T> where T::Store: Reduce {
+    fn new(fraction: f64) -> PercentileAccumulator<T> {
+        PercentileAccumulator { fraction: fraction, digest: TDigest::default(), pt: PhantomData }
+    }
+}
+
+impl<T: ValueInfo + 'static> Accumulator for PercentileAccumulator<T> where T::Store: Reduce {
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError> {
+        let digest = &mut self.digest;
+        for_each_valid::<T, _, _>(col, rows, 0 .. rows, |v| digest.add(v.to_f64()))
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        let digest = &mut self.digest;
+        for_each_valid::<T, _, _>(col, selection_rows(selection), selection.iter().cloned(), |v| digest.add(v.to_f64()))
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<PercentileAccumulator<T>>()
+            .filter(|o| o.fraction == self.fraction)
+            .ok_or_else(mismatch)?;
+
+        self.digest.merge(&other.digest);
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        Ok(self.digest.quantile(self.fraction).map_or(Value::NULL, Value::FLOAT64))
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// ARGMIN / ARGMAX: the smallest (largest) non-NULL `by` value so far, and the column's value at
+/// its first row
+struct ArgAccumulator {
+    max: bool,
+    best: Option<(Value<'static>, Value<'static>)>,
+}
+
+impl ArgAccumulator {
+    fn offer(&mut self, by: Value<'static>, value: Value<'static>) {
+        let better = match self.best {
+            Some((ref best, _)) => if self.max { by > *best } else { by < *best },
+            None                => true,
+        };
+
+        if better {
+            self.best = Some((by, value));
+        }
+    }
+
+    fn offer_rows<'c, I>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], bound: RowOffset, rows: I)
+        -> Result<(), DBError>
+        where I: Iterator<Item = RowOffset>
+    {
+        if companions.len() != 1 {
+            return self.update(col, bound)
+        }
+
+        let by = companions[0];
+
+        check_rows(col, bound)?;
+        check_rows(by, bound)?;
+        for row in rows {
+            let key = column_value(by, row)?;
+            if !key.is_null() {
+                self.offer(key.into_owned(), column_value(col, row)?.into_owned());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Accumulator for ArgAccumulator {
+    fn update<'c>(&mut self, _: &'c RefColumn<'c>, _: RowOffset) -> Result<(), DBError> {
+        Err(DBError::InvalidArgument("ARGMIN / ARGMAX without a by column".to_string()))
+    }
+
+    fn update_with<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], rows: RowOffset)
+        -> Result<(), DBError>
+    {
+        self.offer_rows(col, companions, rows, 0 .. rows)
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        self.offer_rows(col, companions, selection_rows(selection), selection.iter().cloned())
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<ArgAccumulator>()
+            .filter(|o| o.max == self.max)
+            .ok_or_else(mismatch)?;
+
+        if let Some((ref by, ref value)) = other.best {
+            self.offer(by.clone(), value.clone());
+        }
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        Ok(self.best.as_ref().map_or(Value::NULL, |b| b.1.clone()))
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+/// FIRST / LAST: the column's value at the first (last) row so far, NULL or not
+struct FirstLastAccumulator {
+    last: bool,
+    /// `None` before any rows
+    value: Option<Value<'static>>,
+}
+
+impl Accumulator for FirstLastAccumulator {
+    fn update<'c>(&mut self, col: &'c RefColumn<'c>, rows: RowOffset) -> Result<(), DBError> {
+        check_rows(col, rows)?;
+        if rows == 0 || (!self.last && self.value.is_some()) {
+            return Ok(())
+        }
+
+        let row = if self.last { rows - 1 } else { 0 };
+        self.value = Some(column_value(col, row)?.into_owned());
+        Ok(())
+    }
+
+    fn update_selected<'c>(&mut self, col: &'c RefColumn<'c>, companions: &[&'c RefColumn<'c>], selection: &[RowOffset])
+        -> Result<(), DBError>
+    {
+        no_companions(companions)?;
+        check_rows(col, selection_rows(selection))?;
+
+        let row = if self.last { selection.last() } else { selection.first() };
+        match row {
+            Some(&row) if self.last || self.value.is_none() => self.value = Some(column_value(col, row)?.into_owned()),
+            _                                               => (),
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Accumulator) -> Result<(), DBError> {
+        let other = other.as_any().downcast_ref::<FirstLastAccumulator>()
+            .filter(|o| o.last == self.last)
+            .ok_or_else(mismatch)?;
+
+        if other.value.is_some() && (self.last || self.value.is_none()) {
+            self.value = other.value.clone();
+        }
+        Ok(())
+    }
+
+    fn result(&self) -> Result<Value<'static>, DBError> {
+        Ok(self.value.clone().unwrap_or(Value::NULL))
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::{Block, View};
+    use ::schema::Schema;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn kernels() {
+        let values: Vec<i64> = (0 .. 1000).map(|v| v * 3 - 1500).collect();
+        let null_bits: Vec<u8> = (0 .. 126).map(|v| if v % 5 == 0 { 0xff } else { 0x81 }).collect();
+        // Unaligned bitmap
+        let nulls = Bitmap::new(&null_bits, 3, 1000);
+        let valid: Vec<i64> = values.iter().enumerate().filter(|&(r, _)| !nulls.get(r)).map(|(_, &v)| v).collect();
+
+        assert_eq!(sum(&values, None).unwrap(), values.iter().sum::<i64>());
+        assert_eq!(sum(&values, Some(&nulls)).unwrap(), valid.iter().sum::<i64>());
+        assert_eq!(min(&values, Some(&nulls)), valid.iter().cloned().min());
+        assert_eq!(max(&values, Some(&nulls)), valid.iter().cloned().max());
+        assert_eq!(count_valid(1000, Some(&nulls)), valid.len());
+
+        assert_eq!(min::<u32>(&[], None), None);
+        assert_eq!(max(&[1.5f32, ::std::f32::NAN, -2.0], None), Some(1.5));
+        assert_eq!(sum(&[::std::u32::MAX; 3], None).unwrap(), 3 * ::std::u32::MAX as u64);
+
+        // Exact 64bit sums
+        assert_eq!(sum(&[::std::i64::MAX, 1, -2], None).unwrap(), ::std::i64::MAX - 1);
+        assert_eq!(sum(&[::std::i64::MIN, -1, 1], None).unwrap(), ::std::i64::MIN);
+        assert!(sum(&[::std::i64::MAX, 1], None).is_err());
+        assert!(sum(&[::std::i64::MIN, -1], None).is_err());
+        assert!(sum(&[::std::u64::MAX, 1], None).is_err());
+    }
+
+    #[test]
+    fn accumulators() {
+        let schema = Schema::parse_ddl("i INT32, f FLOAT64 NOT NULL, t TEXT").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(100).unwrap();
+        for row in 0 .. 100 {
+            let i = if row % 4 == 0 { None } else { Some(row as i32 - 50) };
+            set_column_value(&mut block, 0, row, &i).unwrap();
+            set_column_value(&mut block, 1, row, &(row as f64 / 2.0)).unwrap();
+            set_column_value(&mut block, 2, row, &Some("x")).unwrap();
+        }
+
+        let ints = block.column(0).unwrap();
+        assert_eq!(reduce_column(AggregateFunc::COUNT, ints, 100).unwrap(), Value::UINT64(75));
+        assert_eq!(reduce_column(AggregateFunc::MIN, ints, 100).unwrap(), Value::INT32(-49));
+        assert_eq!(reduce_column(AggregateFunc::MAX, ints, 100).unwrap(), Value::INT32(49));
+        assert_eq!(reduce_column(AggregateFunc::SUM, block.column(1).unwrap(), 100).unwrap(), Value::FLOAT64(2475.0));
+        assert_eq!(reduce_column(AggregateFunc::MIN, ints, 1).unwrap(), Value::NULL);
+        assert_eq!(reduce_column(AggregateFunc::COUNT, block.column(2).unwrap(), 10).unwrap(), Value::UINT64(10));
+        assert!(reduce_column(AggregateFunc::SUM, block.column(2).unwrap(), 10).is_err());
+        assert!(reduce_column(AggregateFunc::SUM, ints, 1 << 20).is_err());
+
+        // Partial aggregates
+        let mut sum = accumulator(AggregateFunc::SUM, Type::INT32, OverflowPolicy::ERROR).unwrap();
+        let mut other = accumulator(AggregateFunc::SUM, Type::INT32, OverflowPolicy::ERROR).unwrap();
+        sum.update(ints, 50).unwrap();
+        other.update(ints, 100).unwrap();
+        sum.merge(&*other).unwrap();
+        let rows_sum = |rows: i64| (0 .. rows).filter(|r| r % 4 != 0).map(|r| r - 50).sum::<i64>();
+        assert_eq!(sum.result().unwrap(), Value::INT64(rows_sum(50) + rows_sum(100)));
+        assert!(sum.merge(&*accumulator(AggregateFunc::MAX, Type::INT32, OverflowPolicy::ERROR).unwrap()).is_err());
+        assert!(sum.merge(&*accumulator(AggregateFunc::SUM, Type::INT64, OverflowPolicy::ERROR).unwrap()).is_err());
+
+        // Selected rows, in place
+        let mut max = accumulator(AggregateFunc::MAX, Type::INT32, OverflowPolicy::ERROR).unwrap();
+        max.update_selected(ints, &[], &[0, 1, 2, 40]).unwrap();
+        assert_eq!(max.result().unwrap(), Value::INT32(-48));
+        assert!(max.update_selected(ints, &[ints], &[1]).is_err());
+        assert!(max.update_selected(ints, &[], &[1 << 20]).is_err());
+    }
+
+    #[test]
+    fn quantiles() {
+        let schema = Schema::parse_ddl("i INT32, f FLOAT64 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(1001).unwrap();
+        for row in 0 .. 1001 {
+            let i = if row % 2 == 0 { None } else { Some((row * 7 % 1001) as i32) };
+            set_column_value(&mut block, 0, row, &i).unwrap();
+            set_column_value(&mut block, 1, row, &(row as f64)).unwrap();
+        }
+        set_column_value(&mut block, 1, 1000, &::std::f64::NAN).unwrap();
+
+        // Odd, even count and NaNs are skipped
+        let floats = block.column(1).unwrap();
+        assert_eq!(reduce_column(AggregateFunc::MEDIAN, floats, 5).unwrap(), Value::FLOAT64(2.0));
+        assert_eq!(reduce_column(AggregateFunc::MEDIAN, floats, 4).unwrap(), Value::FLOAT64(1.5));
+        assert_eq!(reduce_column(AggregateFunc::MEDIAN, floats, 1001).unwrap(), Value::FLOAT64(499.5));
+
+        let ints = block.column(0).unwrap();
+        let mut median = accumulator(AggregateFunc::MEDIAN, Type::INT32, OverflowPolicy::ERROR).unwrap();
+        let mut other = accumulator(AggregateFunc::MEDIAN, Type::INT32, OverflowPolicy::ERROR).unwrap();
+        median.update(ints, 1).unwrap();
+        assert_eq!(median.result().unwrap(), Value::NULL);
+        median.update(ints, 2).unwrap();
+        other.update(ints, 4).unwrap();
+        median.merge(&*other).unwrap();
+        // 7, 7 and 21
+        assert_eq!(median.result().unwrap(), Value::FLOAT64(7.0));
+
+        let p90 = reduce_column(AggregateFunc::PERCENTILEAPPROX(0.9), floats, 1000).unwrap().as_f64().unwrap();
+        assert!((p90 - 900.0).abs() < 5.0, "p90 {}", p90);
+        let mut p = accumulator(AggregateFunc::PERCENTILEAPPROX(0.5), Type::FLOAT64, OverflowPolicy::ERROR).unwrap();
+        assert_eq!(p.result().unwrap(), Value::NULL);
+        assert!(p.merge(&*accumulator(AggregateFunc::PERCENTILEAPPROX(0.9), Type::FLOAT64, OverflowPolicy::ERROR).unwrap()).is_err());
+        assert!(accumulator(AggregateFunc::MEDIAN, Type::TEXT, OverflowPolicy::ERROR).is_err());
+    }
+
+    #[test]
+    fn sum_overflow() {
+        let schema = Schema::parse_ddl("i INT64 NOT NULL, u UINT64 NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(4).unwrap();
+        for (row, &i) in [::std::i64::MAX, ::std::i64::MAX, ::std::i64::MIN, ::std::i64::MIN].iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::INT64(i)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::UINT64(::std::u64::MAX)).unwrap();
+        }
+
+        let sum = |column, rows, overflow| {
+            let mut acc = accumulator(AggregateFunc::SUM, schema.get(column).unwrap().dtype, overflow).unwrap();
+            acc.update(block.column(column).unwrap(), rows).unwrap();
+            acc.result()
+        };
+
+        // Only the result has to fit
+        assert_eq!(sum(0, 4, OverflowPolicy::ERROR).unwrap(), Value::INT64(-2));
+        assert_eq!(reduce_column(AggregateFunc::SUM, block.column(0).unwrap(), 3).unwrap(), Value::INT64(::std::i64::MAX - 1));
+
+        match sum(0, 2, OverflowPolicy::ERROR) {
+            Err(DBError::Overflow(_))   => (),
+            r                           => panic!("expected an overflow: {:?}", r),
+        }
+        assert_eq!(sum(0, 2, OverflowPolicy::NULL).unwrap(), Value::NULL);
+        assert_eq!(sum(0, 2, OverflowPolicy::SATURATE).unwrap(), Value::INT64(::std::i64::MAX));
+        assert_eq!(sum(1, 4, OverflowPolicy::SATURATE).unwrap(), Value::UINT64(::std::u64::MAX));
+        assert!(sum(1, 4, OverflowPolicy::ERROR).is_err());
+        assert_eq!(sum(0, 2, OverflowPolicy::WRAP).unwrap(), Value::INT64(-2));
+        assert_eq!(sum(1, 2, OverflowPolicy::WRAP).unwrap(), Value::UINT64(::std::u64::MAX - 1));
+    }
+}
@@ -0,0 +1,212 @@
+// vim : set ts=4 sw=4 et :
+
+//! Snappy (raw, unframed) compression.
+
+const HASH_LOG: usize = 12;
+const MAX_OFFSET: usize = 65535;
+/// Most output per input byte: a 64 byte copy from a 3 byte element
+const MAX_EXPANSION: usize = 22;
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    src[pos] as u32 | (src[pos + 1] as u32) << 8 | (src[pos + 2] as u32) << 16 | (src[pos + 3] as u32) << 24
+}
+
+fn push_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return
+    }
+
+    let len = literal.len() - 1;
+    if len < 60 {
+        out.push((len << 2) as u8);
+    } else {
+        let bytes = (0 .. 4).take_while(|idx| *idx == 0 || len >> (8 * idx) != 0).count();
+        out.push(((59 + bytes) << 2) as u8);
+        for idx in 0 .. bytes {
+            out.push((len >> (8 * idx)) as u8);
+        }
+    }
+    out.extend_from_slice(literal);
+}
+
+/// Copies with a 2 byte offset; at most 64 bytes each
+fn push_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    while len > 0 {
+        let chunk = len.min(64);
+        out.push(((chunk - 1) << 2 | 2) as u8);
+        out.push(offset as u8);
+        out.push((offset >> 8) as u8);
+        len -= chunk;
+    }
+}
+
+/// Compress into a raw Snappy buffer
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() / 2 + 16);
+
+    // Preamble: uncompressed length varint
+    let mut len = src.len();
+    while len >= 0x80 {
+        out.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+
+    // Position + 1 of the last occurrence of a hash; 0 is empty
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos + 4 <= src.len() {
+        let seq = read_u32(src, pos);
+        let slot = &mut table[(seq.wrapping_mul(0x1e35a7bd) >> (32 - HASH_LOG)) as usize];
+        let candidate = *slot;
+        *slot = pos + 1;
+
+        if candidate == 0 || pos - (candidate - 1) > MAX_OFFSET || read_u32(src, candidate - 1) != seq {
+            pos += 1;
+            continue
+        }
+
+        let candidate = candidate - 1;
+        let mut len = 4;
+        while pos + len < src.len() && src[candidate + len] == src[pos + len] {
+            len += 1;
+        }
+
+        push_literal(&mut out, &src[anchor .. pos]);
+        push_copy(&mut out, pos - candidate, len);
+        pos += len;
+        anchor = pos;
+    }
+
+    push_literal(&mut out, &src[anchor ..]);
+    out
+}
+
+/// Decompress a raw Snappy buffer. None if the input is malformed, including an uncompressed length
+/// over 4GB or one the input can't expand to.
+pub fn decompress(src: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+
+    // Preamble: uncompressed length varint
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        let b = *src.get(pos)?;
+        pos += 1;
+        if shift > 28 {
+            return None
+        }
+        len |= ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 {
+            break
+        }
+        shift += 7;
+    }
+
+    if len > u32::max_value() as usize || src.len().checked_mul(MAX_EXPANSION).map_or(false, |max| len > max) {
+        return None
+    }
+
+    let mut out = Vec::with_capacity(len);
+
+    while pos < src.len() {
+        let tag = src[pos];
+        pos += 1;
+
+        let (copy_len, offset) = match tag & 3 {
+            // Literal
+            0 => {
+                let mut lit = (tag >> 2) as usize;
+                if lit >= 60 {
+                    let bytes = lit - 59;
+                    lit = 0;
+                    for idx in 0 .. bytes {
+                        lit |= (*src.get(pos + idx)? as usize) << (8 * idx);
+                    }
+                    pos += bytes;
+                }
+
+                let lit = lit + 1;
+                if out.len() + lit > len {
+                    return None
+                }
+                out.extend_from_slice(src.get(pos .. pos.checked_add(lit)?)?);
+                pos += lit;
+                continue
+            }
+            1 => {
+                let offset = ((tag as usize >> 5) << 8) | *src.get(pos)? as usize;
+                pos += 1;
+                (4 + ((tag >> 2) & 7) as usize, offset)
+            }
+            2 => {
+                let raw = src.get(pos .. pos + 2)?;
+                pos += 2;
+                ((tag >> 2) as usize + 1, raw[0] as usize | (raw[1] as usize) << 8)
+            }
+            _ => {
+                let raw = src.get(pos .. pos + 4)?;
+                pos += 4;
+                let offset = raw.iter().rev().fold(0usize, |acc, b| acc << 8 | *b as usize);
+                ((tag >> 2) as usize + 1, offset)
+            }
+        };
+
+        if offset == 0 || offset > out.len() || out.len() + copy_len > len {
+            return None
+        }
+
+        // Byte at a time; the source and destination can overlap
+        let start = out.len() - offset;
+        for idx in 0 .. copy_len {
+            let b = out[start + idx];
+            out.push(b);
+        }
+    }
+
+    if out.len() != len {
+        return None
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_elements() {
+        // "abcd" literal, copy(len 6, offset 4), 2 byte offset copy(len 2, offset 10), "x" literal
+        let src = [13, 3 << 2, b'a', b'b', b'c', b'd', 1 | 2 << 2, 4, 2 | 1 << 2, 10, 0, 0, b'x'];
+        assert_eq!(decompress(&src).unwrap(), b"abcdabcdababx".to_vec());
+
+        // Long literal length in an extra byte
+        let mut long = vec![70, 60 << 2, 69];
+        long.extend((0 .. 70).map(|b| b as u8));
+        assert_eq!(decompress(&long).unwrap().len(), 70);
+
+        assert!(decompress(&src[.. 8]).is_none());
+        // Copy before any output
+        assert!(decompress(&[4, 1, 1]).is_none());
+
+        // Preamble lengths over 4GB, or more than the input can expand to
+        assert!(decompress(&[0xff, 0xff, 0xff, 0xff, 0x7f]).is_none());
+        assert!(decompress(&[0xff, 0xff, 0x7f, 0]).is_none());
+        // Literal past the preamble length
+        assert!(decompress(&[2, 3 << 2, b'a', b'b', b'c', b'd']).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data: Vec<u8> = b"0123456789".iter().cycle().take(500).cloned().collect();
+        data.extend((0 .. 100).map(|v| (v * 7 % 251) as u8));
+
+        for src in vec![&b""[..], &b"abc"[..], &data[..]] {
+            assert_eq!(decompress(&compress(src)).unwrap(), src.to_vec());
+        }
+        assert!(compress(&data).len() < 150);
+    }
+}
@@ -0,0 +1,416 @@
+// vim : set ts=4 sw=4 et :
+
+//! Sorting rows by key columns.
+//!
+//! Fixed width keys are sorted with a LSD radix sort on an order preserving unsigned encoding of
+//! the key. Other keys (TEXT, BLOB, nested) use a stable comparison sort. Multiple key columns are
+//! sorted least significant key first; every pass is stable.
+//!
+//! NULLs sort before other values (like `Value`), unless requested otherwise. `-0.0` equals `0.0`
+//! and `NaN` sorts after all other floats. TEXT keys are ordered by the key's collation.
+
+use std::cmp::Ordering;
+use std::mem;
+
+use ::block::{Bitmap, RefColumn, View, column_nulls, column_row_data, column_value};
+use ::error::DBError;
+use ::row::RowOffset;
+use ::types::{self, RawData, Type};
+use ::util::collation::{Collation, Collator};
+
+/// Native value with an order preserving unsigned encoding
+pub trait RadixKey: Copy {
+    /// Number of significant bytes of the encoding
+    const BYTES: usize;
+
+    /// Unsigned key; comparing keys is the same as comparing the values
+    fn radix_key(self) -> u64;
+}
+
+impl RadixKey for u32 {
+    const BYTES: usize = 4;
+    fn radix_key(self) -> u64 { self as u64 }
+}
+
+impl RadixKey for u64 {
+    const BYTES: usize = 8;
+    fn radix_key(self) -> u64 { self }
+}
+
+impl RadixKey for i32 {
+    const BYTES: usize = 4;
+    fn radix_key(self) -> u64 { (self as u32 ^ (1 << 31)) as u64 }
+}
+
+impl RadixKey for i64 {
+    const BYTES: usize = 8;
+    fn radix_key(self) -> u64 { self as u64 ^ (1 << 63) }
+}
+
+impl RadixKey for f32 {
+    const BYTES: usize = 4;
+    fn radix_key(self) -> u64 {
+        let bits = if self == 0.0 { 0 } else if self.is_nan() { ::std::f32::NAN.to_bits() } else { self.to_bits() };
+        (if bits >> 31 == 1 { !bits } else { bits | (1 << 31) }) as u64
+    }
+}
+
+impl RadixKey for f64 {
+    const BYTES: usize = 8;
+    fn radix_key(self) -> u64 {
+        let bits = if self == 0.0 { 0 } else if self.is_nan() { ::std::f64::NAN.to_bits() } else { self.to_bits() };
+        if bits >> 63 == 1 { !bits } else { bits | (1 << 63) }
+    }
+}
+
+impl RadixKey for bool {
+    const BYTES: usize = 1;
+    fn radix_key(self) -> u64 { self as u64 }
+}
+
+/// Sort order of a key column
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortColumn {
+    pub column: usize,
+    pub ascending: bool,
+    pub nulls_first: bool,
+    /// Order of TEXT and JSON values
+    pub collation: Collation,
+}
+
+impl SortColumn {
+    /// Ascending, NULLs first
+    pub fn asc(column: usize) -> SortColumn {
+        SortColumn { column: column, ascending: true, nulls_first: true, collation: Collation::BINARY }
+    }
+
+    /// Descending, NULLs last
+    pub fn desc(column: usize) -> SortColumn {
+        SortColumn { column: column, ascending: false, nulls_first: false, collation: Collation::BINARY }
+    }
+
+    pub fn with_collation(self, collation: Collation) -> SortColumn {
+        SortColumn { collation: collation, ..self }
+    }
+}
+
+/// Permutation that stably sorts `keys`
+pub fn radix_sort<K: RadixKey>(keys: &[K]) -> Vec<RowOffset> {
+    let mut perm = (0 .. keys.len()).collect();
+    radix_permute(&mut perm, K::BYTES, |row| keys[row].radix_key());
+    perm
+}
+
+/// Stably reorder the rows in `perm` by the low `bytes` bytes of their key
+fn radix_permute<F: Fn(RowOffset) -> u64>(perm: &mut Vec<RowOffset>, bytes: usize, key: F) {
+    let len = perm.len();
+    let mut keys: Vec<u64> = perm.iter().map(|&row| key(row)).collect();
+    let mut rows = mem::replace(perm, Vec::new());
+    let mut tmp_keys = vec![0u64; len];
+    let mut tmp_rows = vec![0 as RowOffset; len];
+
+    for pass in 0 .. bytes {
+        let shift = pass * 8;
+
+        let mut counts = [0usize; 256];
+        for k in &keys {
+            counts[((k >> shift) & 0xff) as usize] += 1;
+        }
+
+        // All the keys share this digit
+        if counts.iter().any(|&c| c == len) {
+            continue
+        }
+
+        let mut offset = 0;
+        for c in counts.iter_mut() {
+            let n = *c;
+            *c = offset;
+            offset += n;
+        }
+
+        for (&k, &row) in keys.iter().zip(rows.iter()) {
+            let digit = ((k >> shift) & 0xff) as usize;
+            tmp_keys[counts[digit]] = k;
+            tmp_rows[counts[digit]] = row;
+            counts[digit] += 1;
+        }
+
+        mem::swap(&mut keys, &mut tmp_keys);
+        mem::swap(&mut rows, &mut tmp_rows);
+    }
+
+    *perm = rows;
+}
+
+enum KeyData<'a> {
+    UINT32(&'a [u32]),
+    UINT64(&'a [u64]),
+    INT32(&'a [i32]),
+    INT64(&'a [i64]),
+    FLOAT32(&'a [f32]),
+    FLOAT64(&'a [f64]),
+    BOOLEAN(&'a [bool]),
+    RAW(&'a [RawData]),
+    NESTED,
+}
+
+struct KeyColumn<'a> {
+    col: &'a RefColumn<'a>,
+    data: KeyData<'a>,
+    nulls: Bitmap<'a>,
+    order: SortColumn,
+    /// Binary for non TEXT columns
+    collator: Collator,
+}
+
+impl<'a> KeyColumn<'a> {
+    fn new(col: &'a RefColumn<'a>, order: SortColumn) -> Result<KeyColumn<'a>, DBError> {
+        let data = match col.attribute().dtype {
+            Type::UINT32    => KeyData::UINT32(column_row_data::<types::UInt32>(col)?.values),
+            Type::UINT64    => KeyData::UINT64(column_row_data::<types::UInt64>(col)?.values),
+            Type::INT32     => KeyData::INT32(column_row_data::<types::Int32>(col)?.values),
+            Type::INT64     => KeyData::INT64(column_row_data::<types::Int64>(col)?.values),
+            Type::FLOAT32   => KeyData::FLOAT32(column_row_data::<types::Float32>(col)?.values),
+            Type::FLOAT64   => KeyData::FLOAT64(column_row_data::<types::Float64>(col)?.values),
+            Type::BOOLEAN   => KeyData::BOOLEAN(column_row_data::<types::Boolean>(col)?.values),
+            Type::TEXT      => KeyData::RAW(column_row_data::<types::Text>(col)?.values),
+            Type::JSON      => KeyData::RAW(column_row_data::<types::Json>(col)?.values),
+            Type::BLOB      => KeyData::RAW(column_row_data::<types::Blob>(col)?.values),
+            Type::TIMESTAMP     => KeyData::INT64(column_row_data::<types::Timestamp>(col)?.values),
+            Type::TIMESTAMP_TZ  => KeyData::INT64(column_row_data::<types::TimestampTz>(col)?.values),
+            Type::LIST | Type::STRUCT | Type::MAP => KeyData::NESTED,
+        };
+
+        let collator = match col.attribute().dtype {
+            Type::TEXT | Type::JSON => order.collation.collator()?,
+            _                       => Collator::binary(),
+        };
+
+        Ok(KeyColumn { col: col, data: data, nulls: column_nulls(col), order: order, collator: collator })
+    }
+
+    fn is_null(&self, row: RowOffset) -> bool {
+        self.col.attribute().nullable && self.nulls.get(row)
+    }
+
+    /// Bytes of the radix key; 0 if the values don't have one
+    fn radix_bytes(&self) -> usize {
+        match self.data {
+            KeyData::UINT32(_)  => u32::BYTES,
+            KeyData::UINT64(_)  => u64::BYTES,
+            KeyData::INT32(_)   => i32::BYTES,
+            KeyData::INT64(_)   => i64::BYTES,
+            KeyData::FLOAT32(_) => f32::BYTES,
+            KeyData::FLOAT64(_) => f64::BYTES,
+            KeyData::BOOLEAN(_) => bool::BYTES,
+            KeyData::RAW(_) | KeyData::NESTED => 0,
+        }
+    }
+
+    fn radix_key(&self, row: RowOffset) -> Option<u64> {
+        match self.data {
+            KeyData::UINT32(v)  => Some(v[row].radix_key()),
+            KeyData::UINT64(v)  => Some(v[row].radix_key()),
+            KeyData::INT32(v)   => Some(v[row].radix_key()),
+            KeyData::INT64(v)   => Some(v[row].radix_key()),
+            KeyData::FLOAT32(v) => Some(v[row].radix_key()),
+            KeyData::FLOAT64(v) => Some(v[row].radix_key()),
+            KeyData::BOOLEAN(v) => Some(v[row].radix_key()),
+            KeyData::RAW(_) | KeyData::NESTED => None,
+        }
+    }
+
+    /// Compare non NULL values, ascending
+    fn cmp_values(&self, lrow: RowOffset, other: &KeyColumn, rrow: RowOffset) -> Ordering {
+        match (&self.data, &other.data) {
+            (&KeyData::RAW(l), &KeyData::RAW(r))    =>
+                self.collator.compare(AsRef::<[u8]>::as_ref(&l[lrow]), AsRef::<[u8]>::as_ref(&r[rrow])),
+            (&KeyData::NESTED, &KeyData::NESTED)    => {
+                match (column_value(self.col, lrow), column_value(other.col, rrow)) {
+                    (Ok(l), Ok(r))  => l.partial_cmp(&r).unwrap_or(Ordering::Equal),
+                    _               => Ordering::Equal,
+                }
+            }
+            _ => match (self.radix_key(lrow), other.radix_key(rrow)) {
+                (Some(l), Some(r))  => l.cmp(&r),
+                _                   => Ordering::Equal,
+            },
+        }
+    }
+
+    fn cmp(&self, lrow: RowOffset, other: &KeyColumn, rrow: RowOffset) -> Ordering {
+        let nulls_first = self.order.nulls_first;
+        match (self.is_null(lrow), other.is_null(rrow)) {
+            (true, true)    => Ordering::Equal,
+            (true, false)   => if nulls_first { Ordering::Less } else { Ordering::Greater },
+            (false, true)   => if nulls_first { Ordering::Greater } else { Ordering::Less },
+            (false, false)  => {
+                let ord = self.cmp_values(lrow, other, rrow);
+                if self.order.ascending { ord } else { ord.reverse() }
+            }
+        }
+    }
+
+    /// Stable sort of the rows in `perm` by this column
+    fn sort(&self, perm: &mut Vec<RowOffset>) {
+        let bytes = self.radix_bytes();
+        if bytes == 0 {
+            return perm.sort_by(|&l, &r| self.cmp(l, self, r))
+        }
+
+        let (mut nulls, mut values): (Vec<RowOffset>, Vec<RowOffset>) = perm.iter().partition(|&&row| self.is_null(row));
+
+        let ascending = self.order.ascending;
+        radix_permute(&mut values, bytes, |row| {
+            let key = self.radix_key(row).unwrap_or(0);
+            if ascending { key } else { !key }
+        });
+
+        perm.clear();
+        if self.order.nulls_first {
+            perm.append(&mut nulls);
+            perm.append(&mut values);
+        } else {
+            perm.append(&mut values);
+            perm.append(&mut nulls);
+        }
+    }
+}
+
+/// Sort key columns of a view. Used for sorting the rows of a view and for comparing rows of
+/// different views with the same key types (eg. when merging sorted runs).
+pub struct SortKeys<'a> {
+    columns: Vec<KeyColumn<'a>>,
+    rows: RowOffset,
+}
+
+impl<'a> SortKeys<'a> {
+    pub fn new(view: &'a View<'a>, keys: &[SortColumn]) -> Result<SortKeys<'a>, DBError> {
+        let mut columns = Vec::with_capacity(keys.len());
+        for key in keys {
+            let col = view.column(key.column).ok_or(DBError::make_column_unknown_pos(key.column))?;
+            columns.push(KeyColumn::new(col, key.clone())?);
+        }
+
+        Ok(SortKeys { columns: columns, rows: view.rows() })
+    }
+
+    pub fn rows(&self) -> RowOffset {
+        self.rows
+    }
+
+    /// Check that rows of `other` can be compared with these rows
+    pub fn check_compatible(&self, other: &SortKeys) -> Result<(), DBError> {
+        if self.columns.len() != other.columns.len() {
+            return Err(DBError::SchemaMismatch(format!("{} and {} sort keys", self.columns.len(),
+                                                       other.columns.len())))
+        }
+
+        for (l, r) in self.columns.iter().zip(other.columns.iter()) {
+            let (lattr, rattr) = (l.col.attribute(), r.col.attribute());
+            if lattr.dtype != rattr.dtype {
+                return Err(DBError::AttributeType(format!("{} ({}) compared with {} ({})", lattr.name,
+                                                          lattr.dtype.name(), rattr.name, rattr.dtype.name())))
+            }
+            if l.order.collation != r.order.collation {
+                return Err(DBError::AttributeType(format!("{} ({}) compared with {} ({})", lattr.name,
+                                                          l.order.collation, rattr.name, r.order.collation)))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare a row of this view with a row of `other` view. Uses the sort order of these keys.
+    pub fn compare(&self, lrow: RowOffset, other: &SortKeys, rrow: RowOffset) -> Ordering {
+        for (l, r) in self.columns.iter().zip(other.columns.iter()) {
+            match l.cmp(lrow, r, rrow) {
+                Ordering::Equal => continue,
+                ord             => return ord,
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Permutation of the view rows that stably sorts them by the keys
+    pub fn sort(&self) -> Vec<RowOffset> {
+        let mut perm = (0 .. self.rows).collect();
+        for key in self.columns.iter().rev() {
+            key.sort(&mut perm);
+        }
+        perm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::allocator;
+    use ::block::Block;
+    use ::schema::Schema;
+    use ::types::Value;
+    use ::util::copy_value::set_column_value;
+
+    #[test]
+    fn radix() {
+        assert_eq!(radix_sort(&[3i64, -1, 1 << 40, -(1 << 40), 0, -1]), vec![3, 1, 5, 4, 0, 2]);
+        assert_eq!(radix_sort(&[7u32, 7, 1]), vec![2, 0, 1]);
+
+        let floats = [::std::f64::NAN, 1.5, -0.0, ::std::f64::NEG_INFINITY, 0.0, -2.5, ::std::f64::INFINITY];
+        assert_eq!(radix_sort(&floats), vec![3, 5, 2, 4, 1, 6, 0]);
+        assert_eq!(radix_sort(&[true, false, true]), vec![1, 0, 2]);
+    }
+
+    fn block(rows: &[(Option<i32>, &str)]) -> Block<'static> {
+        let schema = Schema::parse_ddl("n INT32, s TEXT NOT NULL").unwrap();
+        let mut block = Block::new(&allocator::GLOBAL, &schema);
+        block.add_rows(rows.len()).unwrap();
+        for (row, &(n, s)) in rows.iter().enumerate() {
+            set_column_value(&mut block, 0, row, &Value::from(n)).unwrap();
+            set_column_value(&mut block, 1, row, &Value::from(s)).unwrap();
+        }
+        block
+    }
+
+    #[test]
+    fn sort_keys() {
+        let data = block(&[(Some(1), "b"), (None, "a"), (Some(-3), "c"), (Some(1), "a"), (None, "b")]);
+
+        let keys = SortKeys::new(&data, &[SortColumn::desc(0), SortColumn::asc(1)]).unwrap();
+        assert_eq!(keys.sort(), vec![3, 0, 2, 1, 4]);
+
+        let keys = SortKeys::new(&data, &[SortColumn::asc(0), SortColumn::desc(1)]).unwrap();
+        assert_eq!(keys.sort(), vec![4, 1, 2, 0, 3]);
+
+        let other = block(&[(Some(1), "a"), (None, "z")]);
+        let other_keys = SortKeys::new(&other, &[SortColumn::asc(0), SortColumn::desc(1)]).unwrap();
+        keys.check_compatible(&other_keys).unwrap();
+        assert_eq!(keys.compare(3, &other_keys, 0), Ordering::Equal);
+        assert_eq!(keys.compare(0, &other_keys, 0), Ordering::Less);
+        assert_eq!(keys.compare(1, &other_keys, 1), Ordering::Greater);
+
+        let nocase = SortKeys::new(&other, &[SortColumn::asc(0), SortColumn::desc(1).with_collation(Collation::NOCASE)]);
+        assert!(keys.check_compatible(&nocase.unwrap()).is_err());
+
+        let swapped = SortKeys::new(&other, &[SortColumn::asc(1), SortColumn::asc(0)]).unwrap();
+        assert!(keys.check_compatible(&swapped).is_err());
+        match keys.check_compatible(&SortKeys::new(&other, &[SortColumn::asc(0)]).unwrap()) {
+            Err(DBError::SchemaMismatch(_)) => (),
+            r                               => panic!("{:?}", r.err()),
+        }
+        assert!(SortKeys::new(&data, &[SortColumn::asc(2)]).is_err());
+    }
+
+    #[test]
+    fn collated() {
+        let data = block(&[(None, "b"), (None, "B"), (None, "a"), (None, "A")]);
+
+        let keys = SortKeys::new(&data, &[SortColumn::asc(1)]).unwrap();
+        assert_eq!(keys.sort(), vec![3, 1, 2, 0]);
+
+        let keys = SortKeys::new(&data, &[SortColumn::asc(1).with_collation(Collation::NOCASE)]).unwrap();
+        assert_eq!(keys.sort(), vec![2, 3, 0, 1]);
+        assert_eq!(keys.compare(0, &keys, 1), Ordering::Equal);
+    }
+}
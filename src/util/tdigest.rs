@@ -0,0 +1,208 @@
+// vim : set ts=4 sw=4 et :
+
+//! t-digest: mergeable sketch of a distribution for approximate quantiles (Dunning & Ertl).
+//!
+//! Values are summarized by centroids (mean, weight) sorted by mean. The centroids near the
+//! ends of the distribution are kept small, so extreme quantiles are more accurate than the
+//! median. Size is bounded by the compression (about `compression` centroids) regardless of the
+//! number of values, and digests of separate runs of values can be merged.
+
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+
+/// Compression of `TDigest::default`
+pub const DEFAULT_COMPRESSION: f64 = 100.0;
+
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    /// (mean, weight), sorted by mean
+    centroids: Vec<(f64, f64)>,
+    /// Values (or centroids of merged digests) not compressed into `centroids` yet
+    buffer: Vec<(f64, f64)>,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> TDigest {
+        TDigest::new(DEFAULT_COMPRESSION)
+    }
+}
+
+fn by_mean(lhs: &(f64, f64), rhs: &(f64, f64)) -> Ordering {
+    lhs.0.partial_cmp(&rhs.0).unwrap_or(Ordering::Equal)
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> TDigest {
+        TDigest {
+            compression: compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: ::std::f64::INFINITY,
+            max: ::std::f64::NEG_INFINITY,
+        }
+    }
+
+    /// Number of values added
+    pub fn count(&self) -> f64 {
+        self.centroids.iter().chain(&self.buffer).map(|c| c.1).sum()
+    }
+
+    /// NaNs are ignored
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return
+        }
+
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push((value, 1.0));
+        self.maybe_compress();
+    }
+
+    /// Add the values of another digest
+    pub fn merge(&mut self, other: &TDigest) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend(other.centroids.iter().chain(&other.buffer));
+        self.maybe_compress();
+    }
+
+    /// Estimate of the `q` (0 ..= 1) quantile; `None` without any values
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let compressed;
+        let centroids = if self.buffer.is_empty() {
+            &self.centroids
+        } else {
+            let mut copy = self.clone();
+            copy.compress();
+            compressed = copy.centroids;
+            &compressed
+        };
+
+        let total: f64 = centroids.iter().map(|c| c.1).sum();
+        if centroids.is_empty() {
+            return None
+        }
+
+        let q = q.max(0.0).min(1.0);
+        if q == 0.0 {
+            return Some(self.min)
+        } else if q == 1.0 {
+            return Some(self.max)
+        }
+
+        // Centroids are treated as their weight spread evenly around the mean; interpolate
+        // between the centers of the neighbouring centroids (or the min / max at the ends)
+        let target = q * total;
+        let mut seen = 0.0;
+        let mut prev = (self.min, 0.0);
+        for &(mean, weight) in centroids {
+            let center = seen + weight / 2.0;
+            if target < center {
+                let (prev_mean, prev_center) = prev;
+                return Some(interpolate(prev_mean, prev_center, mean, center, target))
+            }
+
+            seen += weight;
+            prev = (mean, center);
+        }
+
+        let (prev_mean, prev_center) = prev;
+        Some(interpolate(prev_mean, prev_center, self.max, total, target))
+    }
+
+    fn maybe_compress(&mut self) {
+        if self.buffer.len() as f64 >= self.compression * 5.0 {
+            self.compress();
+        }
+    }
+
+    /// Merge the buffer into the centroids
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return
+        }
+
+        let mut all = Vec::with_capacity(self.centroids.len() + self.buffer.len());
+        all.extend(self.centroids.drain(..));
+        all.extend(self.buffer.drain(..));
+        all.sort_by(by_mean);
+
+        let total: f64 = all.iter().map(|c| c.1).sum();
+        let mut out = Vec::with_capacity(self.compression as usize);
+        let mut current = all[0];
+        let mut done = 0.0;
+        let mut limit = total * self.k_to_q(self.q_to_k(0.0) + 1.0);
+
+        for &(mean, weight) in &all[1 ..] {
+            if done + current.1 + weight <= limit {
+                let merged = current.1 + weight;
+                current = (current.0 + (mean - current.0) * weight / merged, merged);
+            } else {
+                done += current.1;
+                out.push(current);
+                limit = total * self.k_to_q(self.q_to_k(done / total) + 1.0);
+                current = (mean, weight);
+            }
+        }
+
+        out.push(current);
+        self.centroids = out;
+    }
+
+    /// Scale function: centroids span at most 1 in k
+    fn q_to_k(&self, q: f64) -> f64 {
+        self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin()
+    }
+
+    fn k_to_q(&self, k: f64) -> f64 {
+        if k >= self.compression / 4.0 {
+            return 1.0
+        }
+        ((k * 2.0 * PI / self.compression).sin() + 1.0) / 2.0
+    }
+}
+
+fn interpolate(x0: f64, at0: f64, x1: f64, at1: f64, at: f64) -> f64 {
+    if at1 <= at0 {
+        return x1
+    }
+    x0 + (x1 - x0) * (at - at0) / (at1 - at0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles() {
+        let mut digest = TDigest::default();
+        assert_eq!(digest.quantile(0.5), None);
+
+        // Values 0 .. 100000 in a scrambled order, in two digests
+        let mut other = TDigest::default();
+        for i in 0 .. 100000u64 {
+            let value = (i * 7919 % 100000) as f64;
+            if i % 2 == 0 { digest.add(value) } else { other.add(value) }
+        }
+        digest.merge(&other);
+        digest.add(::std::f64::NAN);
+
+        assert_eq!(digest.count(), 100000.0);
+        assert!(digest.centroids.len() + digest.buffer.len() < 1000);
+        assert_eq!(digest.quantile(0.0), Some(0.0));
+        assert_eq!(digest.quantile(1.0), Some(99999.0));
+        // Rank errors within 0.5% at the median, 0.1% at the tails
+        for &(q, error) in &[(0.5, 500.0), (0.9, 200.0), (0.99, 100.0), (0.001, 100.0)] {
+            let estimate = digest.quantile(q).unwrap();
+            assert!((estimate - q * 100000.0).abs() < error, "{} quantile {}", q, estimate);
+        }
+
+        let mut single = TDigest::default();
+        single.add(3.5);
+        assert_eq!(single.quantile(0.5), Some(3.5));
+    }
+}